@@ -0,0 +1,73 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+package com.lancedb;
+
+import org.apache.arrow.c.ArrowArrayStream;
+import org.apache.arrow.c.Data;
+import org.apache.arrow.memory.BufferAllocator;
+import org.apache.arrow.vector.ipc.ArrowReader;
+
+/** A connection to a LanceDB database. */
+public class Connection implements AutoCloseable {
+  static {
+    System.loadLibrary("lancedb");
+  }
+
+  private long handle;
+
+  private Connection(long handle) {
+    this.handle = handle;
+  }
+
+  /**
+   * Connect to a database.
+   *
+   * @param uri a local path or an object store URI (e.g. {@code s3://bucket/path})
+   */
+  public static Connection connect(String uri) {
+    return new Connection(nativeConnect(uri));
+  }
+
+  /** Open an existing table. */
+  public Table openTable(String name) {
+    return new Table(nativeOpenTable(handle, name));
+  }
+
+  /**
+   * Create a new table from the data in {@code data}.
+   *
+   * <p>The reader is consumed by this call.
+   */
+  public Table createTable(BufferAllocator allocator, String name, ArrowReader data) {
+    try (ArrowArrayStream stream = ArrowArrayStream.allocateNew(allocator)) {
+      Data.exportArrayStream(allocator, data, stream);
+      return new Table(nativeCreateTable(handle, name, stream.memoryAddress()));
+    }
+  }
+
+  @Override
+  public void close() {
+    nativeClose(handle);
+    handle = 0;
+  }
+
+  private static native long nativeConnect(String uri);
+
+  private static native long nativeOpenTable(long handle, String name);
+
+  private static native long nativeCreateTable(long handle, String name, long streamAddr);
+
+  private static native void nativeClose(long handle);
+}
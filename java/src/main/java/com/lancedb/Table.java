@@ -0,0 +1,70 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+package com.lancedb;
+
+import org.apache.arrow.c.ArrowArrayStream;
+import org.apache.arrow.c.Data;
+import org.apache.arrow.memory.BufferAllocator;
+import org.apache.arrow.vector.ipc.ArrowReader;
+
+/** A LanceDB table. */
+public class Table implements AutoCloseable {
+  private long handle;
+
+  Table(long handle) {
+    this.handle = handle;
+  }
+
+  /**
+   * Append the data in {@code data} to the table.
+   *
+   * <p>The reader is consumed by this call.
+   */
+  public void add(BufferAllocator allocator, ArrowReader data) {
+    try (ArrowArrayStream stream = ArrowArrayStream.allocateNew(allocator)) {
+      Data.exportArrayStream(allocator, data, stream);
+      nativeAdd(handle, stream.memoryAddress());
+    }
+  }
+
+  /**
+   * Query the table.
+   *
+   * @param filter an optional SQL filter, may be null
+   * @param vector an optional query vector, if null a plain scan is performed
+   * @param limit the maximum number of rows to return, 0 for the default
+   * @return a reader over the results, use {@link ArrowReader#getVectorSchemaRoot()} to access
+   *     each batch
+   */
+  public ArrowReader query(BufferAllocator allocator, String filter, float[] vector, int limit) {
+    try (ArrowArrayStream stream = ArrowArrayStream.allocateNew(allocator)) {
+      nativeQuery(handle, filter, vector, limit, stream.memoryAddress());
+      return Data.importArrayStream(allocator, stream);
+    }
+  }
+
+  @Override
+  public void close() {
+    nativeClose(handle);
+    handle = 0;
+  }
+
+  private static native void nativeAdd(long handle, long streamAddr);
+
+  private static native void nativeQuery(
+      long handle, String filter, float[] vector, int limit, long outAddr);
+
+  private static native void nativeClose(long handle);
+}
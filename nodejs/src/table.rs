@@ -95,6 +95,12 @@ impl Table {
             op.mode(AddDataMode::Append)
         } else if mode == "overwrite" {
             op.mode(AddDataMode::Overwrite)
+        } else if mode == "overwrite-on-conflict" {
+            op.mode(AddDataMode::OverwriteOnConflict)
+        } else if mode == "error-on-conflict" {
+            op.mode(AddDataMode::ErrorOnConflict)
+        } else if mode == "bulk-ingest" {
+            op.mode(AddDataMode::BulkIngest(Default::default()))
         } else {
             return Err(napi::Error::from_reason(format!("Invalid mode: {}", mode)));
         };
@@ -123,12 +129,16 @@ impl Table {
 
     #[napi]
     pub async fn delete(&self, predicate: String) -> napi::Result<()> {
-        self.inner_ref()?.delete(&predicate).await.map_err(|e| {
-            napi::Error::from_reason(format!(
-                "Failed to delete rows in table {}: predicate={}",
-                self.name, e
-            ))
-        })
+        self.inner_ref()?
+            .delete(&predicate)
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                napi::Error::from_reason(format!(
+                    "Failed to delete rows in table {}: predicate={}",
+                    self.name, e
+                ))
+            })
     }
 
     #[napi]
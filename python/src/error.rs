@@ -13,7 +13,9 @@
 // limitations under the License.
 
 use pyo3::{
-    exceptions::{PyIOError, PyNotImplementedError, PyOSError, PyRuntimeError, PyValueError},
+    exceptions::{
+        PyIOError, PyNotImplementedError, PyOSError, PyRuntimeError, PyTimeoutError, PyValueError,
+    },
     PyResult,
 };
 
@@ -38,14 +40,30 @@ impl<T> PythonErrorExt<T> for std::result::Result<T, LanceError> {
                 LanceError::InvalidInput { .. } => self.value_error(),
                 LanceError::InvalidTableName { .. } => self.value_error(),
                 LanceError::TableNotFound { .. } => self.value_error(),
+                LanceError::NamespaceNotFound { .. } => self.value_error(),
                 LanceError::Schema { .. } => self.value_error(),
                 LanceError::CreateDir { .. } => self.os_error(),
                 LanceError::TableAlreadyExists { .. } => self.runtime_error(),
+                LanceError::IndexNotFound { .. } => self.value_error(),
+                LanceError::InvalidFilter { .. } => self.value_error(),
+                LanceError::QuotaExceeded { .. } => self.runtime_error(),
+                LanceError::CommitConflict { .. } => self.runtime_error(),
+                LanceError::TooManyQueries { .. } => self.runtime_error(),
+                LanceError::QueryTimeout { .. } => Err(PyTimeoutError::new_err(err.to_string())),
+                LanceError::QueryCancelled => self.runtime_error(),
+                LanceError::PrimaryKeyConflict { .. } => self.value_error(),
+                LanceError::StaleVersion { .. } => self.runtime_error(),
+                LanceError::UnsupportedDataType { .. } => self.value_error(),
+                LanceError::VectorMismatch { .. } => self.value_error(),
+                LanceError::UnindexedRows { .. } => self.runtime_error(),
+                LanceError::Io { .. } => Err(PyIOError::new_err(err.to_string())),
+                LanceError::Remote { .. } => self.runtime_error(),
                 LanceError::ObjectStore { .. } => Err(PyIOError::new_err(err.to_string())),
                 LanceError::Lance { .. } => self.runtime_error(),
                 LanceError::Runtime { .. } => self.runtime_error(),
                 LanceError::Http { .. } => self.runtime_error(),
                 LanceError::Arrow { .. } => self.runtime_error(),
+                LanceError::DataFusion { .. } => self.runtime_error(),
                 LanceError::NotSupported { .. } => {
                     Err(PyNotImplementedError::new_err(err.to_string()))
                 }
@@ -70,6 +70,12 @@ impl Table {
             op = op.mode(AddDataMode::Append);
         } else if mode == "overwrite" {
             op = op.mode(AddDataMode::Overwrite);
+        } else if mode == "overwrite-on-conflict" {
+            op = op.mode(AddDataMode::OverwriteOnConflict);
+        } else if mode == "error-on-conflict" {
+            op = op.mode(AddDataMode::ErrorOnConflict);
+        } else if mode == "bulk-ingest" {
+            op = op.mode(AddDataMode::BulkIngest(Default::default()));
         } else {
             return Err(PyValueError::new_err(format!("Invalid mode: {}", mode)));
         }
@@ -83,7 +89,7 @@ impl Table {
     pub fn delete(self_: PyRef<'_, Self>, condition: String) -> PyResult<&PyAny> {
         let inner = self_.inner_ref()?.clone();
         future_into_py(self_.py(), async move {
-            inner.delete(&condition).await.infer_error()
+            inner.delete(&condition).await.map(|_| ()).infer_error()
         })
     }
 
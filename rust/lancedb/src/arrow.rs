@@ -12,13 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{pin::Pin, sync::Arc};
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
 
+use arrow::compute::take;
+use arrow::json::ReaderBuilder;
 pub use arrow_array;
+use arrow_array::{Array, RecordBatch, RecordBatchIterator, RecordBatchOptions};
+use arrow_cast::CastOptions;
 pub use arrow_schema;
-use futures::{Stream, StreamExt};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use futures::{Stream, StreamExt, TryStreamExt};
+use lazy_static::lazy_static;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::runtime::Runtime;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 
 /// An iterator of batches that also has a schema
 pub trait RecordBatchReader: Iterator<Item = Result<arrow_array::RecordBatch>> {
@@ -58,6 +66,16 @@ pub trait RecordBatchStream: Stream<Item = Result<arrow_array::RecordBatch>> {
     /// Implementation of this trait should guarantee that all `RecordBatch`'s returned by this
     /// stream should have the same schema as returned from this method.
     fn schema(&self) -> Arc<arrow_schema::Schema>;
+
+    /// The version of the table the batches are read from
+    ///
+    /// All of the batches of a query on a LanceDB OSS table are read from the
+    /// version of the table when the query was executed, even if new versions
+    /// are committed while the stream is consumed.  This is None if the batches
+    /// were not read from a single version.
+    fn version(&self) -> Option<u64> {
+        None
+    }
 }
 
 /// A boxed RecordBatchStream that is also Send
@@ -102,6 +120,240 @@ impl<S: Stream<Item = Result<arrow_array::RecordBatch>>> RecordBatchStream
     }
 }
 
+/// A RecordBatchStream whose batches are all read from one version of a table
+#[pin_project::pin_project]
+pub struct VersionedRecordBatchStream {
+    #[pin]
+    stream: SendableRecordBatchStream,
+    version: u64,
+}
+
+impl VersionedRecordBatchStream {
+    pub(crate) fn new(stream: SendableRecordBatchStream, version: u64) -> Self {
+        Self { stream, version }
+    }
+}
+
+impl Stream for VersionedRecordBatchStream {
+    type Item = Result<arrow_array::RecordBatch>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+}
+
+impl RecordBatchStream for VersionedRecordBatchStream {
+    fn schema(&self) -> Arc<arrow_schema::Schema> {
+        self.stream.schema()
+    }
+
+    fn version(&self) -> Option<u64> {
+        Some(self.version)
+    }
+}
+
+/// Helpers to consume a [`SendableRecordBatchStream`], e.g. the results of a query
+///
+/// ```no_run
+/// # use lancedb::arrow::RecordBatchStreamExt;
+/// # use lancedb::query::{ExecutableQuery, QueryBase};
+/// # async fn example(table: &lancedb::Table) -> lancedb::Result<()> {
+/// let batch = table.query().limit(10).execute().await?.collect_concat().await?;
+/// assert!(batch.num_rows() <= 10);
+/// # Ok(())
+/// # }
+/// ```
+pub trait RecordBatchStreamExt {
+    /// Collect all of the batches of the stream
+    fn collect_batches(self) -> impl Future<Output = Result<Vec<RecordBatch>>> + Send;
+
+    /// Collect all of the batches of the stream into a single batch
+    ///
+    /// The batch has the schema of the stream, and no rows if the stream has
+    /// no batches.
+    fn collect_concat(self) -> impl Future<Output = Result<RecordBatch>> + Send;
+
+    /// Transform each batch of the stream with `f`
+    ///
+    /// The schema of the new stream is that of `f` applied to an empty batch
+    /// with the schema of the stream, so `f` must return batches with the
+    /// same schema whatever its input.  The batches are transformed as they
+    /// are read, and the version of the stream is kept.
+    fn map_batches<F>(self, f: F) -> Result<SendableRecordBatchStream>
+    where
+        F: FnMut(RecordBatch) -> Result<RecordBatch> + Send + 'static;
+
+    /// Only keep the columns `columns` of the batches, in this order
+    fn select_columns(self, columns: &[&str]) -> Result<SendableRecordBatchStream>;
+
+    /// Call `f` with each batch of the stream, stopping at the first error
+    fn try_for_each_batch<F>(self, f: F) -> impl Future<Output = Result<()>> + Send
+    where
+        F: FnMut(RecordBatch) -> Result<()> + Send;
+}
+
+impl RecordBatchStreamExt for SendableRecordBatchStream {
+    fn collect_batches(self) -> impl Future<Output = Result<Vec<RecordBatch>>> + Send {
+        self.try_collect()
+    }
+
+    async fn collect_concat(self) -> Result<RecordBatch> {
+        let schema = self.schema();
+        let batches = self.collect_batches().await?;
+        Ok(arrow::compute::concat_batches(&schema, &batches)?)
+    }
+
+    fn map_batches<F>(self, mut f: F) -> Result<SendableRecordBatchStream>
+    where
+        F: FnMut(RecordBatch) -> Result<RecordBatch> + Send + 'static,
+    {
+        let schema = f(RecordBatch::new_empty(self.schema()))?.schema();
+        let version = self.version();
+        let stream: SendableRecordBatchStream = Box::pin(SimpleRecordBatchStream {
+            schema,
+            stream: self.map(move |batch| batch.and_then(&mut f)),
+        });
+        Ok(match version {
+            Some(version) => Box::pin(VersionedRecordBatchStream::new(stream, version)),
+            None => stream,
+        })
+    }
+
+    fn select_columns(self, columns: &[&str]) -> Result<SendableRecordBatchStream> {
+        let schema = self.schema();
+        let indices = columns
+            .iter()
+            .map(|column| schema.index_of(column))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        self.map_batches(move |batch| Ok(batch.project(&indices)?))
+    }
+
+    async fn try_for_each_batch<F>(mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(RecordBatch) -> Result<()> + Send,
+    {
+        while let Some(batch) = self.try_next().await? {
+            f(batch)?;
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    /// The runtime that drives queries consumed through blocking APIs
+    pub(crate) static ref RUNTIME: Runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to create tokio runtime");
+}
+
+/// The rows of `batch` at `indices`
+///
+/// This is `arrow::compute::take_record_batch`, which is not available in the
+/// version of arrow used by Lance.
+pub(crate) fn take_record_batch(
+    batch: &RecordBatch,
+    indices: &dyn Array,
+) -> std::result::Result<RecordBatch, ArrowError> {
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| take(column, indices, None))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    RecordBatch::try_new_with_options(
+        batch.schema(),
+        columns,
+        &RecordBatchOptions::new().with_row_count(Some(indices.len())),
+    )
+}
+
+/// Run `future` on [`RUNTIME`], blocking the calling thread until it completes
+///
+/// If the calling thread is a worker of a multi-threaded tokio runtime its
+/// tasks are moved to other workers first.  This panics on a current thread
+/// runtime, which would not be able to make progress while blocked.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    if tokio::runtime::Handle::try_current().is_ok() {
+        tokio::task::block_in_place(|| RUNTIME.block_on(future))
+    } else {
+        RUNTIME.block_on(future)
+    }
+}
+
+/// A synchronous [`arrow_array::RecordBatchReader`] over a [`SendableRecordBatchStream`]
+///
+/// Each call to `next` blocks until the stream yields its next batch, see
+/// [`crate::query::ExecutableQuery::execute_blocking_reader`].
+pub struct BlockingRecordBatchReader {
+    schema: SchemaRef,
+    stream: SendableRecordBatchStream,
+}
+
+impl BlockingRecordBatchReader {
+    /// Wrap `stream`, which should have been created on [`RUNTIME`] as the
+    /// tasks it spawned must keep running while the reader is not polled
+    pub(crate) fn new(stream: SendableRecordBatchStream) -> Self {
+        Self {
+            schema: stream.schema(),
+            stream,
+        }
+    }
+}
+
+impl Iterator for BlockingRecordBatchReader {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        block_on(self.stream.next())
+            .map(|batch| batch.map_err(|e| ArrowError::ExternalError(Box::new(e))))
+    }
+}
+
+impl arrow_array::RecordBatchReader for BlockingRecordBatchReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// A trait for exporting streams of batches through the [Arrow C stream
+/// interface]
+///
+/// This hands the results of a query to DuckDB, Python or any other consumer
+/// of the Arrow C data interface, without copying their buffers.
+///
+/// ```no_run
+/// # use lancedb::arrow::IntoFfiStream;
+/// # use lancedb::query::ExecutableQuery;
+/// # async fn export(table: &lancedb::Table) -> lancedb::Result<()> {
+/// let stream = table.query().execute().await?.into_ffi_stream();
+/// // Hand a pointer to `stream` to the consumer, which takes ownership of it
+/// # Ok(())
+/// # }
+/// ```
+///
+/// This requires the `ffi` feature.
+///
+/// [Arrow C stream interface]: https://arrow.apache.org/docs/format/CStreamInterface.html
+#[cfg(feature = "ffi")]
+pub trait IntoFfiStream {
+    /// Export the batches as an Arrow C stream
+    ///
+    /// Each call of `get_next` by the consumer blocks its thread until the
+    /// next batch is read, see [`BlockingRecordBatchReader`].  The errors of
+    /// the stream are reported through `get_last_error`.
+    fn into_ffi_stream(self) -> arrow::ffi_stream::FFI_ArrowArrayStream;
+}
+
+#[cfg(feature = "ffi")]
+impl IntoFfiStream for SendableRecordBatchStream {
+    fn into_ffi_stream(self) -> arrow::ffi_stream::FFI_ArrowArrayStream {
+        arrow::ffi_stream::FFI_ArrowArrayStream::new(Box::new(BlockingRecordBatchReader::new(self)))
+    }
+}
+
 /// A trait for converting incoming data to Arrow
 ///
 /// Integrations should implement this trait to allow data to be
@@ -119,3 +371,1116 @@ impl<T: arrow_array::RecordBatchReader + Send + 'static> IntoArrow for T {
         Ok(Box::new(self))
     }
 }
+
+/// The number of batches of a stream read ahead of the write which consumes
+/// them, see the [`IntoArrow`] impl of [`SendableRecordBatchStream`]
+pub const STREAM_READAHEAD: usize = 2;
+
+/// An async stream of batches, e.g. fed by a network source, as the data of
+/// [`crate::table::Table::add`] or [`crate::connection::Connection::create_table`]
+///
+/// The stream is consumed as the data is written, at most
+/// [`STREAM_READAHEAD`] batches ahead of the write, so the data is never
+/// materialized in memory and a slow write slows the reads of the source
+/// down.  The first error of the stream fails the write.
+///
+/// The write waits for the batches of the stream by blocking its thread, as a
+/// [`BlockingRecordBatchReader`] does, so it must be executed on a
+/// multi-threaded runtime.
+impl IntoArrow for SendableRecordBatchStream {
+    fn into_arrow(self) -> Result<Box<dyn arrow_array::RecordBatchReader + Send>> {
+        let schema = self.schema();
+        let (sender, receiver) = tokio::sync::mpsc::channel(STREAM_READAHEAD);
+        let mut stream = self;
+        let read = async move {
+            while let Some(batch) = stream.next().await {
+                let failed = batch.is_err();
+                // The receiver is dropped if the write stops early
+                if sender.send(batch).await.is_err() || failed {
+                    break;
+                }
+            }
+        };
+        // The stream keeps being polled on the runtime it was created on, if
+        // any, while the write blocks
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle.spawn(read),
+            Err(_) => RUNTIME.spawn(read),
+        };
+        let batches = futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|batch| (batch, receiver))
+        });
+        Ok(Box::new(BlockingRecordBatchReader::new(Box::pin(
+            SimpleRecordBatchStream {
+                schema,
+                stream: batches,
+            },
+        ))))
+    }
+}
+
+/// A collection of rows, described by a serde [`Serialize`] impl, that can be
+/// converted to Arrow
+///
+/// This allows plain Rust structs to be used with methods like
+/// [`crate::connection::Connection::create_table`] or [`crate::table::Table::add`]
+/// without writing Arrow builders by hand.  Each row must serialize as a map (e.g.
+/// a struct with `#[derive(Serialize)]`) whose keys are the columns of the row.
+///
+/// As for [`JsonRows`], the schema is inferred from the serialized rows unless it
+/// is given with [`SerdeRecords::schema`].  Sequences of numbers are inferred as
+/// lists of `f64`, so vector columns (fixed size lists), which can be provided as
+/// `Vec<f32>` (or any other sequence of numbers), are declared with
+/// [`SerdeRecords::vector_column`].  The length of each vector must match the list
+/// size.
+///
+/// ```
+/// use lancedb::arrow::SerdeRecords;
+///
+/// #[derive(serde::Serialize)]
+/// struct Item {
+///     id: i32,
+///     vector: Vec<f32>,
+/// }
+///
+/// let records = SerdeRecords::new(vec![
+///     Item { id: 1, vector: vec![0.1, 0.2] },
+///     Item { id: 2, vector: vec![0.3, 0.4] },
+/// ])
+/// .vector_column("vector", 2);
+/// ```
+pub struct SerdeRecords<T: Serialize> {
+    rows: Vec<T>,
+    schema: Option<SchemaRef>,
+    column_types: HashMap<String, DataType>,
+    json_columns: Vec<String>,
+    batch_size: usize,
+}
+
+impl<T: Serialize> SerdeRecords<T> {
+    /// Create a new collection of records, whose schema is inferred from the rows
+    pub fn new(rows: Vec<T>) -> Self {
+        Self {
+            rows,
+            schema: None,
+            column_types: HashMap::new(),
+            json_columns: Vec::new(),
+            batch_size: 1024,
+        }
+    }
+
+    /// The maximum number of rows in each converted batch
+    ///
+    /// The default is 1024
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// The schema of the rows, instead of inferring it
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Convert the column `column` to `data_type`, instead of the inferred type
+    pub fn column_type(mut self, column: impl Into<String>, data_type: DataType) -> Self {
+        self.column_types.insert(column.into(), data_type);
+        self
+    }
+
+    /// Convert the sequences of the column `column` to vectors of `dim` `f32` values
+    pub fn vector_column(self, column: impl Into<String>, dim: i32) -> Self {
+        let item = Arc::new(Field::new("item", DataType::Float32, true));
+        self.column_type(column, DataType::FixedSizeList(item, dim))
+    }
+
+    /// Store the values of the column `column` as JSON documents
+    ///
+    /// See [`JSON_EXTENSION_NAME`].
+    pub fn json_column(mut self, column: impl Into<String>) -> Self {
+        self.json_columns.push(column.into());
+        self
+    }
+}
+
+/// The type `data_type` is decoded from, or encoded to, JSON as
+///
+/// The JSON decoder and encoder do not understand fixed size lists, at any
+/// depth, so they are variable size lists which are cast afterwards.
+fn json_data_type(data_type: &DataType) -> DataType {
+    let field = |field: &Field| {
+        field
+            .clone()
+            .with_data_type(json_data_type(field.data_type()))
+    };
+    match data_type {
+        DataType::FixedSizeList(item, _) | DataType::List(item) => {
+            DataType::List(Arc::new(field(item)))
+        }
+        DataType::LargeList(item) => DataType::LargeList(Arc::new(field(item))),
+        DataType::Struct(fields) => {
+            DataType::Struct(fields.iter().map(|f| field(f)).collect::<Vec<_>>().into())
+        }
+        _ => data_type.clone(),
+    }
+}
+
+fn decode_field(field: &Field) -> Field {
+    field
+        .clone()
+        .with_data_type(json_data_type(field.data_type()))
+}
+
+/// Convert rows, which serialize as JSON objects, to batches of `schema`
+fn decode_rows<T: Serialize>(
+    schema: SchemaRef,
+    rows: &[T],
+    batch_size: usize,
+) -> Result<Box<dyn arrow_array::RecordBatchReader + Send>> {
+    let decode_schema = Arc::new(Schema::new(
+        schema
+            .fields()
+            .iter()
+            .map(|f| decode_field(f))
+            .collect::<Vec<_>>(),
+    ));
+    let mut decoder = ReaderBuilder::new(decode_schema)
+        .with_batch_size(batch_size)
+        .build_decoder()?;
+
+    // Don't silently replace vectors of the wrong length with nulls
+    let cast_options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+    let mut batches = Vec::with_capacity(rows.len().div_ceil(batch_size));
+    for chunk in rows.chunks(batch_size) {
+        decoder.serialize(chunk)?;
+        if let Some(decoded) = decoder.flush()? {
+            let columns = decoded
+                .columns()
+                .iter()
+                .zip(schema.fields())
+                .map(|(col, field)| {
+                    arrow_cast::cast_with_options(col, field.data_type(), &cast_options)
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            batches.push(Ok(RecordBatch::try_new(schema.clone(), columns)?));
+        }
+    }
+    Ok(Box::new(RecordBatchIterator::new(batches, schema)))
+}
+
+impl<T: Serialize> IntoArrow for SerdeRecords<T> {
+    fn into_arrow(self) -> Result<Box<dyn arrow_array::RecordBatchReader + Send>> {
+        if let Some(schema) = &self.schema {
+            if self.column_types.is_empty()
+                && self.json_columns.is_empty()
+                && json_columns(schema).is_empty()
+            {
+                return decode_rows(schema.clone(), &self.rows, self.batch_size);
+            }
+        }
+        // The schema is inferred, and the documents of JSON columns are stored
+        // as text, from the rows as JSON values
+        let rows = self
+            .rows
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::InvalidInput {
+                message: format!("a row could not be serialized: {}", e),
+            })?;
+        JsonRows {
+            rows,
+            schema: self.schema,
+            column_types: self.column_types,
+            json_columns: self.json_columns,
+            batch_size: self.batch_size,
+        }
+        .into_arrow()
+    }
+}
+
+/// The values of a row of [`TableRows`], by column name
+///
+/// Values are JSON values, so anything with a `serde_json::Value` conversion
+/// can be set: numbers, strings, booleans, `None` for a null, and `Vec<f32>`
+/// for a vector column.  A row can also be collected from `(name, value)`
+/// pairs.  Columns without a value are null.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RowBuilder {
+    values: serde_json::Map<String, serde_json::Value>,
+}
+
+impl RowBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the value of the column `name`
+    pub fn set(mut self, name: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.values.insert(name.into(), value.into());
+        self
+    }
+}
+
+impl<K: Into<String>, V: Into<serde_json::Value>> FromIterator<(K, V)> for RowBuilder {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self {
+            values: iter
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        }
+    }
+}
+
+/// Rows built at runtime, converted to Arrow with a schema known at runtime
+///
+/// This is [`SerdeRecords`] for data without a Rust struct, e.g. in pipelines
+/// whose columns come from a configuration.  The rows are pushed as
+/// [`RowBuilder`]s or as JSON objects, and are converted to batches of
+/// `schema` with the same rules: vectors are arrays of numbers of the list
+/// size of their column.  A value whose column is not in the schema is
+/// rejected when the row is pushed, a value of the wrong type when the rows
+/// are converted.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use arrow_schema::{DataType, Field, Schema};
+/// use lancedb::arrow::{RowBuilder, TableRows};
+///
+/// let schema = Arc::new(Schema::new(vec![
+///     Field::new("id", DataType::Int32, false),
+///     Field::new(
+///         "vector",
+///         DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+///         true,
+///     ),
+/// ]));
+/// let mut rows = TableRows::new(schema);
+/// rows.push(RowBuilder::new().set("id", 1).set("vector", vec![0.1, 0.2]))?;
+/// rows.push_json(serde_json::json!({"id": 2, "vector": [0.3, 0.4]}))?;
+/// # Ok::<(), lancedb::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TableRows {
+    schema: SchemaRef,
+    rows: Vec<serde_json::Value>,
+    batch_size: usize,
+}
+
+impl TableRows {
+    /// Create an empty collection of rows which will be converted using `schema`
+    pub fn new(schema: SchemaRef) -> Self {
+        Self {
+            schema,
+            rows: Vec::new(),
+            batch_size: 1024,
+        }
+    }
+
+    /// The maximum number of rows in each converted batch
+    ///
+    /// The default is 1024
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Add a row
+    pub fn push(&mut self, row: RowBuilder) -> Result<()> {
+        if let Some(name) = row
+            .values
+            .keys()
+            .find(|name| self.schema.field_with_name(name).is_err())
+        {
+            return Err(Error::InvalidInput {
+                message: format!("the column '{}' is not in the schema", name),
+            });
+        }
+        self.rows.push(serde_json::Value::Object(row.values));
+        Ok(())
+    }
+
+    /// Add a row given as a JSON object
+    pub fn push_json(&mut self, row: serde_json::Value) -> Result<()> {
+        match row {
+            serde_json::Value::Object(values) => self.push(RowBuilder { values }),
+            _ => Err(Error::InvalidInput {
+                message: format!("a row must be a JSON object, not {}", row),
+            }),
+        }
+    }
+
+    /// The number of rows
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+impl IntoArrow for TableRows {
+    fn into_arrow(self) -> Result<Box<dyn arrow_array::RecordBatchReader + Send>> {
+        let mut rows = self.rows;
+        json_documents_as_text(&mut rows, &json_columns(&self.schema));
+        decode_rows(self.schema, &rows, self.batch_size)
+    }
+}
+
+/// The name of the Arrow extension type of JSON columns
+///
+/// A JSON column stores a JSON document per row as a string (or large string
+/// or large binary) and is marked with this name in the
+/// `ARROW:extension:name` metadata of its field.  Its documents can be
+/// filtered with `json_extract`, see [`crate::query::filter`].
+pub const JSON_EXTENSION_NAME: &str = "arrow.json";
+
+const EXTENSION_NAME_KEY: &str = "ARROW:extension:name";
+
+/// A column of JSON documents, see [`JSON_EXTENSION_NAME`]
+pub fn json_field(name: impl Into<String>, nullable: bool) -> Field {
+    Field::new(name, DataType::Utf8, nullable).with_metadata(
+        [(
+            EXTENSION_NAME_KEY.to_string(),
+            JSON_EXTENSION_NAME.to_string(),
+        )]
+        .into(),
+    )
+}
+
+/// Whether `field` is a column of JSON documents
+pub fn is_json_field(field: &Field) -> bool {
+    matches!(
+        field.data_type(),
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::LargeBinary
+    ) && field.metadata().get(EXTENSION_NAME_KEY).map(String::as_str) == Some(JSON_EXTENSION_NAME)
+}
+
+/// The names of the JSON columns of `schema`
+fn json_columns(schema: &Schema) -> Vec<&str> {
+    schema
+        .fields()
+        .iter()
+        .filter(|field| is_json_field(field))
+        .map(|field| field.name().as_str())
+        .collect()
+}
+
+/// Replace the values of the columns `columns` of `rows` by their JSON text
+///
+/// Any JSON value is a document, a JSON `null` or a missing value is a null.
+fn json_documents_as_text(rows: &mut [serde_json::Value], columns: &[&str]) {
+    if columns.is_empty() {
+        return;
+    }
+    for row in rows.iter_mut() {
+        let serde_json::Value::Object(values) = row else {
+            continue;
+        };
+        for column in columns {
+            if let Some(value) = values.get_mut(*column) {
+                if !value.is_null() {
+                    *value = serde_json::Value::String(value.to_string());
+                }
+            }
+        }
+    }
+}
+
+/// Loosely-typed JSON rows, converted to Arrow with an inferred schema
+///
+/// Each row is a JSON object whose keys are the columns of the row.  Unlike
+/// [`TableRows`], the schema does not need to be known: it is inferred from
+/// the rows, as for a newline-delimited JSON file.  Arrays of numbers are
+/// inferred as lists of `f64`, so vector columns must be declared with
+/// [`JsonRows::vector_column`] (or [`JsonRows::column_type`]) to be
+/// searchable.  Columns whose values are arbitrary documents (e.g. metadata
+/// whose keys differ from a row to the next) are declared with
+/// [`JsonRows::json_column`].
+///
+/// `IntoArrow` cannot be implemented for `Vec<serde_json::Value>` itself, it
+/// is converted with `JsonRows::from`.
+///
+/// ```
+/// use lancedb::arrow::JsonRows;
+///
+/// let rows = JsonRows::from(vec![
+///     serde_json::json!({"id": 1, "vector": [0.1, 0.2], "meta": {"tags": ["a"]}}),
+///     serde_json::json!({"id": 2, "vector": [0.3, 0.4], "meta": {"source": "web"}}),
+/// ])
+/// .vector_column("vector", 2)
+/// .json_column("meta");
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonRows {
+    rows: Vec<serde_json::Value>,
+    schema: Option<SchemaRef>,
+    column_types: HashMap<String, DataType>,
+    json_columns: Vec<String>,
+    batch_size: usize,
+}
+
+impl JsonRows {
+    /// The rows `rows`, each a JSON object
+    pub fn new(rows: Vec<serde_json::Value>) -> Self {
+        Self {
+            rows,
+            schema: None,
+            column_types: HashMap::new(),
+            json_columns: Vec::new(),
+            batch_size: 1024,
+        }
+    }
+
+    /// The maximum number of rows in each converted batch
+    ///
+    /// The default is 1024
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// The schema of the rows, instead of inferring it
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Convert the column `column` to `data_type`, instead of the inferred type
+    pub fn column_type(mut self, column: impl Into<String>, data_type: DataType) -> Self {
+        self.column_types.insert(column.into(), data_type);
+        self
+    }
+
+    /// Convert the arrays of the column `column` to vectors of `dim` `f32` values
+    pub fn vector_column(self, column: impl Into<String>, dim: i32) -> Self {
+        let item = Arc::new(Field::new("item", DataType::Float32, true));
+        self.column_type(column, DataType::FixedSizeList(item, dim))
+    }
+
+    /// Store the values of the column `column` as JSON documents
+    ///
+    /// See [`JSON_EXTENSION_NAME`].
+    pub fn json_column(mut self, column: impl Into<String>) -> Self {
+        self.json_columns.push(column.into());
+        self
+    }
+
+    /// The schema of the rows, whose JSON columns are text
+    fn table_schema(&self, rows: &[serde_json::Value]) -> Result<Schema> {
+        if let Some(row) = rows.iter().find(|row| !row.is_object()) {
+            return Err(Error::InvalidInput {
+                message: format!("a row must be a JSON object, not {}", row),
+            });
+        }
+        let schema = match &self.schema {
+            Some(schema) => schema.as_ref().clone(),
+            None => arrow::json::reader::infer_json_schema_from_iterator(rows.iter().map(Ok))?,
+        };
+        let columns = self
+            .column_types
+            .keys()
+            .chain(&self.json_columns)
+            .collect::<Vec<_>>();
+        if let Some(column) = columns
+            .iter()
+            .find(|column| schema.field_with_name(column).is_err())
+        {
+            return Err(Error::InvalidInput {
+                message: format!("the column {} is not in the rows", column),
+            });
+        }
+        let fields = schema
+            .fields()
+            .iter()
+            .map(|field| {
+                if self.json_columns.contains(field.name()) {
+                    json_field(field.name(), true)
+                } else if let Some(data_type) = self.column_types.get(field.name()) {
+                    Field::new(field.name(), data_type.clone(), field.is_nullable())
+                } else {
+                    field.as_ref().clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(Schema::new_with_metadata(fields, schema.metadata().clone()))
+    }
+}
+
+impl From<Vec<serde_json::Value>> for JsonRows {
+    fn from(rows: Vec<serde_json::Value>) -> Self {
+        Self::new(rows)
+    }
+}
+
+impl IntoArrow for JsonRows {
+    fn into_arrow(mut self) -> Result<Box<dyn arrow_array::RecordBatchReader + Send>> {
+        // The documents are converted to text first, so that the type of a
+        // JSON column is inferred as a string whatever its documents are
+        let mut rows = std::mem::take(&mut self.rows);
+        let mut columns = self
+            .json_columns
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if let Some(schema) = &self.schema {
+            columns.extend(json_columns(schema));
+        }
+        json_documents_as_text(&mut rows, &columns);
+        let schema = Arc::new(self.table_schema(&rows)?);
+        decode_rows(schema, &rows, self.batch_size)
+    }
+}
+
+/// An Arrow C stream, to read as the data of a table
+///
+/// This is the stream of the [Arrow C stream interface], produced by DuckDB,
+/// ADBC drivers, Polars, pyarrow or any other implementation of the Arrow C
+/// data interface.  The batches are imported without copying their buffers.
+///
+/// ```no_run
+/// # use arrow::ffi_stream::FFI_ArrowArrayStream;
+/// # use lancedb::arrow::ArrowCStream;
+/// # async fn insert(table: &lancedb::Table, stream: FFI_ArrowArrayStream) -> lancedb::Result<()> {
+/// table.add(ArrowCStream::new(stream)).execute().await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Columns of types that tables cannot store fail with
+/// [`Error::UnsupportedDataType`], unless [`ArrowCStream::lossy`] is set.
+///
+/// This requires the `ffi` feature.
+///
+/// [Arrow C stream interface]: https://arrow.apache.org/docs/format/CStreamInterface.html
+#[cfg(feature = "ffi")]
+pub struct ArrowCStream {
+    stream: arrow::ffi_stream::FFI_ArrowArrayStream,
+    lossy: bool,
+}
+
+#[cfg(feature = "ffi")]
+impl ArrowCStream {
+    /// Take ownership of `stream`, which is released once read or dropped
+    pub fn new(stream: arrow::ffi_stream::FFI_ArrowArrayStream) -> Self {
+        Self {
+            stream,
+            lossy: false,
+        }
+    }
+
+    /// Take ownership of the stream at `stream`, as given by a C producer
+    ///
+    /// The stream is moved out of `stream`, which is left released: the
+    /// producer must not release it again.
+    ///
+    /// # Safety
+    ///
+    /// `stream` must be null or point to a valid Arrow C stream.
+    pub unsafe fn from_raw(stream: *mut arrow::ffi_stream::FFI_ArrowArrayStream) -> Result<Self> {
+        if stream.is_null() {
+            return Err(Error::InvalidInput {
+                message: "stream must not be null".to_string(),
+            });
+        }
+        Ok(Self::new(
+            arrow::ffi_stream::FFI_ArrowArrayStream::from_raw(stream),
+        ))
+    }
+
+    /// Store the columns of unsupported types as strings, instead of failing
+    ///
+    /// The values of these columns are replaced by their string
+    /// representation, which cannot be converted back.  Columns of types
+    /// without one still fail.  This is off by default.
+    pub fn lossy(mut self, lossy: bool) -> Self {
+        self.lossy = lossy;
+        self
+    }
+}
+
+#[cfg(feature = "ffi")]
+impl From<arrow::ffi_stream::FFI_ArrowArrayStream> for ArrowCStream {
+    fn from(stream: arrow::ffi_stream::FFI_ArrowArrayStream) -> Self {
+        Self::new(stream)
+    }
+}
+
+/// The field `field`, converted to a type tables can store if needed and
+/// `lossy` is set
+#[cfg(feature = "ffi")]
+fn supported_field(field: &FieldRef, lossy: bool) -> Result<FieldRef> {
+    if lance::datatypes::Field::try_from(field.as_ref()).is_ok() {
+        return Ok(field.clone());
+    }
+    let unsupported = || Error::UnsupportedDataType {
+        column: field.name().clone(),
+        dtype: field.data_type().to_string(),
+    };
+    if !lossy || !arrow_cast::can_cast_types(field.data_type(), &DataType::Utf8) {
+        return Err(unsupported());
+    }
+    log::warn!(
+        "Storing the column {} of type {} as strings",
+        field.name(),
+        field.data_type()
+    );
+    Ok(Arc::new(
+        field.as_ref().clone().with_data_type(DataType::Utf8),
+    ))
+}
+
+#[cfg(feature = "ffi")]
+impl IntoArrow for ArrowCStream {
+    /// Import the schema of the stream, failing if the producer reports an
+    /// error or if a column cannot be stored
+    fn into_arrow(self) -> Result<Box<dyn arrow_array::RecordBatchReader + Send>> {
+        use arrow_array::RecordBatchReader;
+
+        let reader = arrow::ffi_stream::ArrowArrayStreamReader::try_new(self.stream)?;
+        let schema = reader.schema();
+        let fields = schema
+            .fields()
+            .iter()
+            .map(|field| supported_field(field, self.lossy))
+            .collect::<Result<Vec<_>>>()?;
+        let supported = Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()));
+        crate::data::sanitize::coerce_schema(reader, supported)
+    }
+}
+
+/// Convert the rows of a batch into values of `T`
+///
+/// This is the inverse of [`SerdeRecords`].  Each row is deserialized from a map of
+/// column name to value so `T` is typically a struct with `#[derive(Deserialize)]`.
+/// Columns that are not present in `T` are ignored and null values are omitted, so
+/// nullable columns should be deserialized into `Option` fields.  Vector columns
+/// (fixed size lists) can be deserialized into `Vec<f32>` fields.
+pub fn deserialize_batch<T: DeserializeOwned>(batch: &RecordBatch) -> Result<Vec<T>> {
+    let schema = Arc::new(Schema::new(
+        batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| decode_field(f))
+            .collect::<Vec<_>>(),
+    ));
+    let columns = batch
+        .columns()
+        .iter()
+        .zip(schema.fields())
+        .map(|(column, field)| arrow_cast::cast(column, field.data_type()))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let batch = RecordBatch::try_new(schema, columns)?;
+    let rows = arrow::json::writer::record_batches_to_json_rows(&[&batch])?;
+    rows.into_iter()
+        .map(|row| {
+            serde_json::from_value(serde_json::Value::Object(row)).map_err(|e| Error::Other {
+                message: format!("Failed to deserialize row: {}", e),
+                source: Some(Box::new(e)),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{
+        cast::AsArray, types::Float32Type, types::Int32Type, Array, RecordBatchReader,
+    };
+
+    use super::*;
+
+    #[derive(Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Item {
+        id: i32,
+        name: Option<String>,
+        vector: Vec<f32>,
+    }
+
+    fn items(num_rows: i32) -> Vec<Item> {
+        (0..num_rows)
+            .map(|id| Item {
+                id,
+                name: if id % 2 == 0 {
+                    Some(format!("item-{}", id))
+                } else {
+                    None
+                },
+                vector: vec![id as f32; 4],
+            })
+            .collect()
+    }
+
+    fn item_schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 4),
+                true,
+            ),
+        ]))
+    }
+
+    #[test]
+    fn test_serde_records() {
+        let reader = SerdeRecords::new(items(10))
+            .schema(item_schema())
+            .batch_size(4)
+            .into_arrow()
+            .unwrap();
+        assert_eq!(reader.schema(), item_schema());
+
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        assert_eq!(
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![4, 4, 2]
+        );
+
+        let batch = &batches[2];
+        let ids = batch.column(0).as_primitive::<Int32Type>();
+        assert_eq!(ids.values(), &[8, 9]);
+        let names = batch.column(1).as_string::<i32>();
+        assert_eq!(names.value(0), "item-8");
+        assert!(names.is_null(1));
+        let vectors = batch.column(2).as_fixed_size_list();
+        let vector = vectors.value(1);
+        assert_eq!(vector.as_primitive::<Float32Type>().values(), &[9.0; 4]);
+    }
+
+    #[test]
+    fn test_serde_records_wrong_dimension() {
+        let mut rows = items(2);
+        rows[1].vector.push(1.0);
+        let res = SerdeRecords::new(rows).schema(item_schema()).into_arrow();
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_deserialize_batch() {
+        let reader = SerdeRecords::new(items(3))
+            .schema(item_schema())
+            .into_arrow()
+            .unwrap();
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        let rows = deserialize_batch::<Item>(&batches[0]).unwrap();
+        assert_eq!(rows, items(3));
+
+        #[derive(serde::Deserialize)]
+        struct WrongType {
+            #[allow(dead_code)]
+            name: i32,
+        }
+        assert!(deserialize_batch::<WrongType>(&batches[0]).is_err());
+    }
+
+    #[test]
+    fn test_serde_records_inferred_schema() {
+        let reader = SerdeRecords::new(items(3))
+            .vector_column("vector", 4)
+            .into_arrow()
+            .unwrap();
+        let schema = reader.schema();
+        assert_eq!(
+            schema.field_with_name("id").unwrap().data_type(),
+            &DataType::Int64
+        );
+        assert_eq!(
+            schema.field_with_name("name").unwrap().data_type(),
+            &DataType::Utf8
+        );
+        assert_eq!(
+            schema.field_with_name("vector").unwrap().data_type(),
+            item_schema().field(2).data_type()
+        );
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        assert_eq!(deserialize_batch::<Item>(&batches[0]).unwrap(), items(3));
+    }
+
+    #[test]
+    fn test_nested_fixed_size_lists() {
+        #[derive(Serialize, serde::Deserialize, Debug, PartialEq)]
+        struct Point {
+            position: Vec<f32>,
+        }
+        #[derive(Serialize, serde::Deserialize, Debug, PartialEq)]
+        struct Shape {
+            vertices: Vec<Vec<f32>>,
+            center: Point,
+        }
+        let position = Arc::new(Field::new(
+            "item",
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+            true,
+        ));
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("vertices", DataType::List(position.clone()), true),
+            Field::new(
+                "center",
+                DataType::Struct(vec![position.as_ref().clone().with_name("position")].into()),
+                true,
+            ),
+        ]));
+        let shapes = vec![
+            Shape {
+                vertices: vec![vec![0.0, 0.0], vec![1.0, 0.5]],
+                center: Point {
+                    position: vec![0.5, 0.25],
+                },
+            },
+            Shape {
+                vertices: vec![],
+                center: Point {
+                    position: vec![-1.0, 2.0],
+                },
+            },
+        ];
+        let batches = SerdeRecords::new(shapes)
+            .schema(schema.clone())
+            .into_arrow()
+            .unwrap()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches[0].schema(), schema);
+        let vertices = batches[0].column(0).as_list::<i32>().value(0);
+        assert_eq!(
+            vertices
+                .as_fixed_size_list()
+                .value(1)
+                .as_primitive::<Float32Type>()
+                .values(),
+            &[1.0, 0.5]
+        );
+        let rows = deserialize_batch::<Shape>(&batches[0]).unwrap();
+        assert_eq!(rows[0].vertices, vec![vec![0.0, 0.0], vec![1.0, 0.5]]);
+        assert_eq!(rows[1].center.position, vec![-1.0, 2.0]);
+
+        let mut shapes = rows;
+        shapes[1].center.position.push(3.0);
+        assert!(SerdeRecords::new(shapes)
+            .schema(schema)
+            .into_arrow()
+            .is_err());
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn test_arrow_c_stream() {
+        use arrow::ffi_stream::FFI_ArrowArrayStream;
+
+        let reader = SerdeRecords::new(items(5))
+            .schema(item_schema())
+            .into_arrow()
+            .unwrap();
+        let mut stream = FFI_ArrowArrayStream::new(reader);
+        let source = unsafe { ArrowCStream::from_raw(&mut stream) }.unwrap();
+        let reader = source.into_arrow().unwrap();
+        assert_eq!(reader.schema(), item_schema());
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        assert_eq!(deserialize_batch::<Item>(&batches[0]).unwrap(), items(5));
+
+        assert!(unsafe { ArrowCStream::from_raw(std::ptr::null_mut()) }.is_err());
+
+        // Intervals cannot be stored, but can be stored as strings
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "period",
+            DataType::Interval(arrow_schema::IntervalUnit::YearMonth),
+            true,
+        )]));
+        let periods = arrow_array::IntervalYearMonthArray::from(vec![14]);
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(periods)]).unwrap();
+        let stream = || {
+            let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], schema.clone());
+            ArrowCStream::new(FFI_ArrowArrayStream::new(Box::new(reader)))
+        };
+        assert!(matches!(
+            stream().into_arrow(),
+            Err(Error::UnsupportedDataType { column, .. }) if column == "period"
+        ));
+        let batches = stream()
+            .lossy(true)
+            .into_arrow()
+            .unwrap()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches[0].column(0).data_type(), &DataType::Utf8);
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn test_into_ffi_stream() {
+        use arrow::ffi_stream::ArrowArrayStreamReader;
+
+        use crate::query::{ExecutableQuery, QueryBase};
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap().to_string();
+        // The stream is created on the runtime that drives it when exported
+        let stream = RUNTIME.block_on(async move {
+            let db = crate::connect(&uri).execute().await.unwrap();
+            let data = SerdeRecords::new(items(10)).schema(item_schema());
+            let table = db.create_table("items", data).execute().await.unwrap();
+            table.query().only_if("id < 5").execute().await.unwrap()
+        });
+        let reader = ArrowArrayStreamReader::try_new(stream.into_ffi_stream()).unwrap();
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        let rows = batches
+            .iter()
+            .flat_map(|batch| deserialize_batch::<Item>(batch).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(rows, items(5));
+    }
+
+    fn item_stream(version: Option<u64>) -> SendableRecordBatchStream {
+        let data = SerdeRecords::new(items(10))
+            .schema(item_schema())
+            .into_arrow()
+            .unwrap();
+        let batches = data
+            .map(|batch| batch.map_err(Error::from))
+            .collect::<Vec<_>>();
+        let stream: SendableRecordBatchStream = Box::pin(SimpleRecordBatchStream {
+            schema: item_schema(),
+            stream: futures::stream::iter(batches),
+        });
+        match version {
+            Some(version) => Box::pin(VersionedRecordBatchStream::new(stream, version)),
+            None => stream,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_batch_stream_ext() {
+        let batch = item_stream(None).collect_concat().await.unwrap();
+        assert_eq!(deserialize_batch::<Item>(&batch).unwrap(), items(10));
+        let empty: SendableRecordBatchStream = Box::pin(SimpleRecordBatchStream {
+            schema: item_schema(),
+            stream: futures::stream::empty(),
+        });
+        let batch = empty.collect_concat().await.unwrap();
+        assert_eq!(batch.num_rows(), 0);
+        assert_eq!(batch.schema(), item_schema());
+
+        let stream = item_stream(Some(3)).select_columns(&["id"]).unwrap();
+        assert_eq!(stream.version(), Some(3));
+        assert_eq!(stream.schema().fields().len(), 1);
+        let batches = stream.collect_batches().await.unwrap();
+        assert!(batches.iter().all(|batch| batch.num_columns() == 1));
+        assert!(item_stream(None).select_columns(&["missing"]).is_err());
+
+        // The schema of the mapped stream is known before it is read
+        let stream = item_stream(None)
+            .map_batches(|batch| {
+                let ids = arrow::compute::kernels::numeric::add(
+                    batch.column(0),
+                    &arrow_array::Int32Array::new_scalar(100),
+                )?;
+                Ok(RecordBatch::try_new(
+                    Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)])),
+                    vec![ids],
+                )?)
+            })
+            .unwrap();
+        assert_eq!(stream.schema().field(0).name(), "id");
+        let mut ids = Vec::<i32>::new();
+        stream
+            .try_for_each_batch(|batch| {
+                ids.extend(batch.column(0).as_primitive::<Int32Type>().values().iter());
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(ids, (100..110).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_add_stream() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = crate::connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db
+            .create_table("items", item_stream(None))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+
+        // The batches are produced while the write consumes them
+        let batches = item_stream(None).collect_batches().await.unwrap();
+        let stream: SendableRecordBatchStream = Box::pin(SimpleRecordBatchStream {
+            schema: item_schema(),
+            stream: futures::stream::iter(batches).then(|batch| async move {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                Ok(batch)
+            }),
+        });
+        table.add(stream).execute().await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 20);
+
+        // An error of the stream fails the write
+        let stream: SendableRecordBatchStream = Box::pin(SimpleRecordBatchStream {
+            schema: item_schema(),
+            stream: item_stream(None).chain(futures::stream::once(async {
+                Err(Error::Runtime {
+                    message: "connection reset".to_string(),
+                })
+            })),
+        });
+        assert!(table.add(stream).execute().await.is_err());
+        assert_eq!(table.count_rows(None).await.unwrap(), 20);
+    }
+
+    #[test]
+    fn test_table_rows() {
+        let mut rows = TableRows::new(item_schema()).batch_size(2);
+        rows.push(
+            RowBuilder::new()
+                .set("id", 0)
+                .set("name", "item-0")
+                .set("vector", vec![0.0; 4]),
+        )
+        .unwrap();
+        rows.push(
+            [
+                ("id", serde_json::json!(1)),
+                ("vector", serde_json::json!([1.0, 1.0, 1.0, 1.0])),
+            ]
+            .into_iter()
+            .collect(),
+        )
+        .unwrap();
+        rows.push_json(serde_json::json!({"id": 2, "name": "item-2", "vector": [2, 2, 2, 2]}))
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert!(matches!(
+            rows.push(RowBuilder::new().set("missing", 1)),
+            Err(Error::InvalidInput { .. })
+        ));
+        assert!(rows.push_json(serde_json::json!([1, 2])).is_err());
+
+        let reader = rows.clone().into_arrow().unwrap();
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.len(), 2);
+        let rows = batches
+            .iter()
+            .flat_map(|batch| deserialize_batch::<Item>(batch).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(rows, items(3));
+
+        // The vectors must have the list size of their column
+        let mut rows = TableRows::new(item_schema());
+        rows.push(RowBuilder::new().set("id", 0).set("vector", vec![0.0; 3]))
+            .unwrap();
+        assert!(rows.into_arrow().is_err());
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A command line tool to administer LanceDB databases
+//!
+//! This binary is only built with the `cli` feature:
+//!
+//! ```ignore
+//! cargo install lancedb --features cli
+//! lancedb s3://bucket/db ls
+//! lancedb s3://bucket/db query my_table --filter "id > 10" --limit 5
+//! ```
+
+use std::process::ExitCode;
+
+use arrow_cast::pretty::pretty_format_batches;
+use clap::{Parser, Subcommand, ValueEnum};
+
+use lancedb::blocking::{self, Connection, Table};
+use lancedb::index::scalar::BTreeIndexBuilder;
+use lancedb::index::vector::{IvfHnswPqIndexBuilder, IvfHnswSqIndexBuilder, IvfPqIndexBuilder};
+use lancedb::index::Index;
+use lancedb::query::{ExecutableQuery, QueryBase, Select};
+use lancedb::table::OptimizeAction;
+use lancedb::Result;
+
+#[derive(Debug, Parser)]
+#[command(name = "lancedb", version, about = "Administer LanceDB databases")]
+struct Cli {
+    /// The URI of the database, a directory or e.g. s3://bucket/path
+    uri: String,
+
+    /// An option of the object store, as KEY=VALUE, may be repeated
+    #[arg(long = "storage-option", value_parser = parse_key_value, global = true)]
+    storage_options: Vec<(String, String)>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List the tables of the database
+    Ls,
+    /// Print the schema of a table
+    Schema { table: String },
+    /// Print the rows of a table matching a filter or nearest to a vector
+    Query {
+        table: String,
+        /// An SQL filter on the rows
+        #[arg(long)]
+        filter: Option<String>,
+        /// The columns to print, separated by commas
+        #[arg(long, value_delimiter = ',')]
+        select: Vec<String>,
+        /// The maximum number of rows to print
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        /// A query vector, separated by commas, to run a vector search
+        #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+        vector: Vec<f32>,
+        /// The vector column to search, only needed if the table has several
+        #[arg(long)]
+        column: Option<String>,
+    },
+    /// Create an index on a column of a table
+    CreateIndex {
+        table: String,
+        column: String,
+        /// The type of the index, chosen from the type of the column by default
+        #[arg(long = "type", value_enum, default_value_t = IndexType::Auto)]
+        index_type: IndexType,
+    },
+    /// Compact the files of a table, prune its old versions and optimize its indices
+    Optimize { table: String },
+    /// List the versions of a table
+    Versions { table: String },
+    /// Restore a table to a previous version, as a new version
+    Restore { table: String, version: u64 },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum IndexType {
+    Auto,
+    Btree,
+    IvfPq,
+    IvfHnswPq,
+    IvfHnswSq,
+}
+
+impl From<IndexType> for Index {
+    fn from(index_type: IndexType) -> Self {
+        match index_type {
+            IndexType::Auto => Self::Auto,
+            IndexType::Btree => Self::BTree(BTreeIndexBuilder::default()),
+            IndexType::IvfPq => Self::IvfPq(IvfPqIndexBuilder::default()),
+            IndexType::IvfHnswPq => Self::IvfHnswPq(IvfHnswPqIndexBuilder::default()),
+            IndexType::IvfHnswSq => Self::IvfHnswSq(IvfHnswSqIndexBuilder::default()),
+        }
+    }
+}
+
+fn parse_key_value(option: &str) -> std::result::Result<(String, String), String> {
+    option
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got {}", option))
+}
+
+fn query(
+    table: &Table,
+    filter: Option<String>,
+    select: Vec<String>,
+    limit: usize,
+    vector: Vec<f32>,
+    column: Option<String>,
+) -> Result<()> {
+    let mut query = table.query().limit(limit);
+    if let Some(filter) = filter {
+        query = query.only_if(filter);
+    }
+    if !select.is_empty() {
+        query = query.select(Select::Columns(select));
+    }
+    let reader = if vector.is_empty() {
+        query.execute_blocking_reader()?
+    } else {
+        let mut query = query.nearest_to(vector)?;
+        if let Some(column) = column {
+            query = query.column(&column);
+        }
+        query.execute_blocking_reader()?
+    };
+    let batches = reader.collect::<std::result::Result<Vec<_>, _>>()?;
+    println!("{}", pretty_format_batches(&batches)?);
+    Ok(())
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let db = Connection::open(lancedb::connect(&cli.uri).storage_options(cli.storage_options))?;
+    match cli.command {
+        Command::Ls => {
+            for name in db.table_names()? {
+                println!("{}", name);
+            }
+        }
+        Command::Schema { table } => {
+            let schema = db.open_table(table)?.schema()?;
+            for field in schema.fields() {
+                let nullable = if field.is_nullable() { "" } else { " not null" };
+                println!("{}: {}{}", field.name(), field.data_type(), nullable);
+            }
+        }
+        Command::Query {
+            table,
+            filter,
+            select,
+            limit,
+            vector,
+            column,
+        } => query(
+            &db.open_table(table)?,
+            filter,
+            select,
+            limit,
+            vector,
+            column,
+        )?,
+        Command::CreateIndex {
+            table,
+            column,
+            index_type,
+        } => {
+            db.open_table(table)?
+                .create_index(&[column], index_type.into())?;
+        }
+        Command::Optimize { table } => {
+            let stats = db.open_table(table)?.optimize(OptimizeAction::All)?;
+            if let Some(compaction) = stats.compaction {
+                println!(
+                    "compaction: {} fragments removed, {} fragments added",
+                    compaction.fragments_removed, compaction.fragments_added
+                );
+            }
+            if let Some(prune) = stats.prune {
+                println!(
+                    "prune: {} old versions removed, {} bytes removed",
+                    prune.old_versions, prune.bytes_removed
+                );
+            }
+            if let Some(fragment_stats) = stats.fragment_stats {
+                println!(
+                    "fragment statistics: {} fragments scanned",
+                    fragment_stats.fragments_scanned
+                );
+            }
+        }
+        Command::Versions { table } => {
+            let table = db.open_table(table)?;
+            for version in blocking::block_on(table.as_async().list_versions())? {
+                println!("{}\t{}", version.version, version.timestamp.to_rfc3339());
+            }
+        }
+        Command::Restore { table, version } => {
+            let table = db.open_table(table)?;
+            blocking::block_on(table.as_async().restore_version(version))?;
+            println!(
+                "restored version {} as version {}",
+                version,
+                table.version()?
+            );
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "lancedb",
+            "s3://bucket/db",
+            "query",
+            "items",
+            "--vector=-1.5,2",
+            "--select",
+            "id,vector",
+            "--storage-option",
+            "region=us-east-1",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.storage_options,
+            vec![("region".to_string(), "us-east-1".to_string())]
+        );
+        match cli.command {
+            Command::Query {
+                vector,
+                select,
+                limit,
+                ..
+            } => {
+                assert_eq!(vector, vec![-1.5, 2.0]);
+                assert_eq!(select, vec!["id", "vector"]);
+                assert_eq!(limit, 10);
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
+}
@@ -0,0 +1,324 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A blocking API for code that is not async
+//!
+//! The [`Connection`] and [`Table`] of this module wrap the async
+//! [`crate::Connection`] and [`crate::Table`], running each operation on a
+//! runtime owned by LanceDB and blocking the calling thread until it completes.
+//! This is meant for command line tools and other applications that do not
+//! otherwise use tokio.
+//!
+//! ```no_run
+//! use lancedb::blocking;
+//! use lancedb::query::{ExecutableQuery, QueryBase};
+//!
+//! let db = blocking::connect("data/sample-lancedb").unwrap();
+//! let table = db.open_table("my_table").unwrap();
+//! let reader = table
+//!     .query()
+//!     .only_if("id > 10")
+//!     .limit(5)
+//!     .execute_blocking_reader()
+//!     .unwrap();
+//! for batch in reader {
+//!     println!("{} rows", batch.unwrap().num_rows());
+//! }
+//! ```
+//!
+//! Queries are built as usual and run with
+//! [`crate::query::ExecutableQuery::execute_blocking_reader`], which returns a
+//! standard [`arrow_array::RecordBatchReader`].  The operations that take
+//! options are wrapped with their defaults.  To set the options, build the
+//! operation on the async connection or table, see [`Connection::as_async`] and
+//! [`Table::as_async`], and run it with [`block_on`]:
+//!
+//! ```no_run
+//! # use lancedb::blocking;
+//! # let table: blocking::Table = todo!();
+//! blocking::block_on(
+//!     table
+//!         .as_async()
+//!         .update()
+//!         .only_if("id = 1")
+//!         .column("name", "'one'")
+//!         .execute(),
+//! )
+//! .unwrap();
+//! ```
+//!
+//! The functions of this module may be called from a thread of a
+//! multi-threaded tokio runtime, but they block that thread, and they panic on
+//! a current thread runtime.  In async code prefer the async API.
+
+use std::future::Future;
+
+use arrow_schema::SchemaRef;
+
+use crate::arrow::IntoArrow;
+use crate::connection::ConnectBuilder;
+use crate::error::Result;
+use crate::index::{Index, IndexConfig};
+use crate::query::{IntoQueryVector, Query, VectorQuery};
+use crate::table::{DeleteResult, OptimizeAction, OptimizeStats};
+
+/// Run `future` on the runtime of LanceDB, blocking until it completes
+///
+/// This is the escape hatch to run the operations of the async API which are
+/// not wrapped by this module.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    crate::arrow::block_on(future)
+}
+
+/// Connect to the database at `uri` with the default options
+///
+/// See [`crate::connect`] for the supported URIs.  Use [`Connection::open`] to
+/// connect with other options.
+pub fn connect(uri: &str) -> Result<Connection> {
+    Connection::open(crate::connect(uri))
+}
+
+/// A blocking connection to a LanceDB database
+///
+/// See the [module level documentation](self) for more details.
+#[derive(Clone)]
+pub struct Connection {
+    inner: crate::Connection,
+}
+
+impl std::fmt::Display for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl From<crate::Connection> for Connection {
+    fn from(inner: crate::Connection) -> Self {
+        Self { inner }
+    }
+}
+
+impl Connection {
+    /// Connect with the options of `builder`
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// let db = lancedb::blocking::Connection::open(
+    ///     lancedb::connect("data/sample-lancedb")
+    ///         .read_consistency_interval(Duration::from_secs(5)),
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn open(builder: ConnectBuilder) -> Result<Self> {
+        block_on(builder.execute()).map(Self::from)
+    }
+
+    /// The async connection wrapped by this connection
+    pub fn as_async(&self) -> &crate::Connection {
+        &self.inner
+    }
+
+    /// Get the URI of the connection
+    pub fn uri(&self) -> &str {
+        self.inner.uri()
+    }
+
+    /// The names of all of the tables in the database
+    pub fn table_names(&self) -> Result<Vec<String>> {
+        block_on(self.inner.table_names().execute())
+    }
+
+    /// Open an existing table in the database
+    pub fn open_table(&self, name: impl Into<String>) -> Result<Table> {
+        block_on(self.inner.open_table(name).execute()).map(Table::from)
+    }
+
+    /// Create a new table from `initial_data`
+    ///
+    /// Fails if the table already exists.
+    pub fn create_table<T: IntoArrow>(
+        &self,
+        name: impl Into<String>,
+        initial_data: T,
+    ) -> Result<Table> {
+        block_on(self.inner.create_table(name, initial_data).execute()).map(Table::from)
+    }
+
+    /// Create an empty table with `schema`
+    ///
+    /// Fails if the table already exists.
+    pub fn create_empty_table(&self, name: impl Into<String>, schema: SchemaRef) -> Result<Table> {
+        block_on(self.inner.create_empty_table(name, schema).execute()).map(Table::from)
+    }
+
+    /// Drop a table in the database
+    pub fn drop_table(&self, name: impl AsRef<str>) -> Result<()> {
+        block_on(self.inner.drop_table(name))
+    }
+
+    /// Drop the database, deleting all of its tables
+    pub fn drop_db(&self) -> Result<()> {
+        block_on(self.inner.drop_db())
+    }
+}
+
+/// A blocking handle to a LanceDB table
+///
+/// See the [module level documentation](self) for more details.
+#[derive(Clone)]
+pub struct Table {
+    inner: crate::Table,
+}
+
+impl std::fmt::Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl From<crate::Table> for Table {
+    fn from(inner: crate::Table) -> Self {
+        Self { inner }
+    }
+}
+
+impl Table {
+    /// The async table wrapped by this table
+    pub fn as_async(&self) -> &crate::Table {
+        &self.inner
+    }
+
+    /// Get the name of the table
+    pub fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    /// Get the arrow [Schema] of the table
+    ///
+    /// [Schema]: arrow_schema::Schema
+    pub fn schema(&self) -> Result<SchemaRef> {
+        block_on(self.inner.schema())
+    }
+
+    /// Count the number of rows in the table, or of the rows matching `filter`
+    pub fn count_rows(&self, filter: Option<String>) -> Result<usize> {
+        block_on(self.inner.count_rows(filter))
+    }
+
+    /// Append `batches` to the table
+    pub fn add<T: IntoArrow>(&self, batches: T) -> Result<()> {
+        block_on(self.inner.add(batches).execute())
+    }
+
+    /// Delete the rows matching `predicate`
+    pub fn delete(&self, predicate: &str) -> Result<DeleteResult> {
+        block_on(self.inner.delete(predicate))
+    }
+
+    /// Create an index on `columns`
+    ///
+    /// See [`crate::Table::create_index`] for more details.
+    pub fn create_index(&self, columns: &[impl AsRef<str>], index: Index) -> Result<()> {
+        block_on(self.inner.create_index(columns, index).execute())
+    }
+
+    /// List the indices of the table
+    pub fn list_indices(&self) -> Result<Vec<IndexConfig>> {
+        block_on(self.inner.list_indices())
+    }
+
+    /// Create a [`Query`] of the table
+    ///
+    /// Building the query does not block.  Run it with
+    /// [`crate::query::ExecutableQuery::execute_blocking_reader`].
+    pub fn query(&self) -> Query {
+        self.inner.query()
+    }
+
+    /// Create a [`VectorQuery`] searching for the nearest neighbors of `query`
+    ///
+    /// See [`crate::Table::vector_search`] for more details.
+    pub fn vector_search(&self, query: impl IntoQueryVector) -> Result<VectorQuery> {
+        self.inner.vector_search(query)
+    }
+
+    /// Optimize the on-disk data and indices of the table
+    ///
+    /// See [`crate::Table::optimize`] for more details.
+    pub fn optimize(&self, action: OptimizeAction) -> Result<OptimizeStats> {
+        block_on(self.inner.optimize(action))
+    }
+
+    /// The version of the table
+    pub fn version(&self) -> Result<u64> {
+        block_on(self.inner.version())
+    }
+
+    /// Check out a version of the table, see [`crate::Table::checkout`]
+    pub fn checkout(&self, version: u64) -> Result<()> {
+        block_on(self.inner.checkout(version))
+    }
+
+    /// Check out the latest version of the table
+    pub fn checkout_latest(&self) -> Result<()> {
+        block_on(self.inner.checkout_latest())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::query::{ExecutableQuery, QueryBase};
+
+    fn batches(range: std::ops::Range<i32>) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(range))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[test]
+    fn test_blocking() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap()).unwrap();
+        let table = db.create_table("t", batches(0..10)).unwrap();
+        assert_eq!(db.table_names().unwrap(), vec!["t"]);
+
+        table.add(batches(10..20)).unwrap();
+        assert_eq!(table.count_rows(None).unwrap(), 20);
+        assert_eq!(table.delete("id >= 15").unwrap().num_deleted_rows, 5);
+
+        let table = db.open_table("t").unwrap();
+        let rows = table
+            .query()
+            .only_if("id < 12")
+            .execute_blocking_reader()
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .sum::<usize>();
+        assert_eq!(rows, 12);
+
+        db.drop_table("t").unwrap();
+        assert!(db.table_names().unwrap().is_empty());
+    }
+}
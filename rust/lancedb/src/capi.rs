@@ -0,0 +1,570 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A stable C API for embedding LanceDB in other languages
+//!
+//! This module is only available with the `capi` feature.  When the feature is
+//! enabled the build script uses [cbindgen](https://github.com/mozilla/cbindgen)
+//! to write a C header to `include/lancedb.h`.  A shared or static library can
+//! be built with:
+//!
+//! ```ignore
+//! cargo rustc -p lancedb --features capi --release --crate-type cdylib
+//! ```
+//!
+//! Data is exchanged using the [Arrow C stream interface](https://arrow.apache.org/docs/format/CStreamInterface.html)
+//! so any language with an Arrow implementation (Go, C++, Swift, ...) can
+//! produce input and consume query results without copies.
+//!
+//! Connections and tables are opaque handles which must be released with
+//! [`lancedb_connection_free`] and [`lancedb_table_free`], and the lists of
+//! table names with [`lancedb_string_array_free`].  Functions that can fail
+//! return a null pointer or a non-zero status code; the error message can then be
+//! retrieved with [`lancedb_last_error`].
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+
+use arrow::ffi::FFI_ArrowSchema;
+use arrow::ffi_stream::FFI_ArrowArrayStream;
+
+use crate::arrow::{ArrowCStream, IntoFfiStream, RUNTIME};
+use crate::error::{Error, Result};
+use crate::query::{ExecutableQuery, QueryBase};
+use crate::{Connection, Table};
+
+/// Status code returned by functions that succeeded
+pub const LANCEDB_OK: c_int = 0;
+/// Status code returned by functions that failed, see [`lancedb_last_error`]
+pub const LANCEDB_ERROR: c_int = -1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An open connection to a LanceDB database
+pub struct LanceDBConnection {
+    inner: Connection,
+}
+
+/// An open LanceDB table
+pub struct LanceDBTable {
+    inner: Table,
+}
+
+fn set_last_error(err: Error) {
+    // Interior nul bytes would truncate the message, replace them instead
+    let message = err.to_string().replace('\0', " ");
+    let message = CString::new(message).expect("nul bytes were removed");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+/// Run `f`, recording any error so it can be retrieved with [`lancedb_last_error`]
+fn capture<T>(f: impl FnOnce() -> Result<T>) -> Option<T> {
+    clear_last_error();
+    match f() {
+        Ok(value) => Some(value),
+        Err(err) => {
+            set_last_error(err);
+            None
+        }
+    }
+}
+
+fn capture_status(f: impl FnOnce() -> Result<()>) -> c_int {
+    match capture(f) {
+        Some(()) => LANCEDB_OK,
+        None => LANCEDB_ERROR,
+    }
+}
+
+unsafe fn str_arg<'a>(name: &str, value: *const c_char) -> Result<&'a str> {
+    if value.is_null() {
+        return Err(Error::InvalidInput {
+            message: format!("{} must not be null", name),
+        });
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map_err(|e| Error::InvalidInput {
+            message: format!("{} is not valid UTF-8: {}", name, e),
+        })
+}
+
+unsafe fn handle_arg<'a, T>(name: &str, value: *const T) -> Result<&'a T> {
+    value.as_ref().ok_or_else(|| Error::InvalidInput {
+        message: format!("{} must not be null", name),
+    })
+}
+
+unsafe fn out_arg<'a, T>(name: &str, value: *mut T) -> Result<&'a mut T> {
+    value.as_mut().ok_or_else(|| Error::InvalidInput {
+        message: format!("{} must not be null", name),
+    })
+}
+
+unsafe fn optional_str_arg<'a>(name: &str, value: *const c_char) -> Result<Option<&'a str>> {
+    if value.is_null() {
+        Ok(None)
+    } else {
+        str_arg(name, value).map(Some)
+    }
+}
+
+/// Take ownership of an Arrow C stream provided by the caller
+pub(crate) unsafe fn import_stream(stream: *mut FFI_ArrowArrayStream) -> Result<ArrowCStream> {
+    ArrowCStream::from_raw(stream)
+}
+
+/// Run a plain or vector query and export the results as an Arrow C stream
+pub(crate) fn export_query(
+    table: &Table,
+    filter: Option<&str>,
+    vector: Option<&[f32]>,
+    limit: usize,
+) -> Result<FFI_ArrowArrayStream> {
+    let mut query = table.query();
+    if let Some(filter) = filter {
+        query = query.only_if(filter);
+    }
+    if limit > 0 {
+        query = query.limit(limit);
+    }
+    let stream = match vector {
+        Some(vector) => RUNTIME.block_on(query.nearest_to(vector)?.execute())?,
+        None => RUNTIME.block_on(query.execute())?,
+    };
+    Ok(stream.into_ffi_stream())
+}
+
+/// Returns the message of the last error raised on the calling thread
+///
+/// Returns null if the last call succeeded.  The returned string is owned by
+/// LanceDB and is only valid until the next LanceDB call on the same thread.
+#[no_mangle]
+pub extern "C" fn lancedb_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map(|message| message.as_ptr())
+            .unwrap_or(std::ptr::null())
+    })
+}
+
+/// Connect to the database at `uri`
+///
+/// Returns null on failure.  The returned connection must be released with
+/// [`lancedb_connection_free`].
+///
+/// # Safety
+///
+/// `uri` must be a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_connect(uri: *const c_char) -> *mut LanceDBConnection {
+    capture(|| {
+        let uri = str_arg("uri", uri)?;
+        let inner = RUNTIME.block_on(crate::connect(uri).execute())?;
+        Ok(Box::into_raw(Box::new(LanceDBConnection { inner })))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Release a connection returned by [`lancedb_connect`]
+///
+/// Tables opened from the connection remain valid.
+///
+/// # Safety
+///
+/// `conn` must be null or a pointer returned by [`lancedb_connect`] that has
+/// not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_connection_free(conn: *mut LanceDBConnection) {
+    if !conn.is_null() {
+        drop(Box::from_raw(conn));
+    }
+}
+
+/// Write the names of the tables of `conn` to `out_names` and `out_len`
+///
+/// On success `*out_names` points to an array of `*out_len` nul-terminated
+/// strings, which must be released with [`lancedb_string_array_free`].
+/// Returns [`LANCEDB_OK`] on success.
+///
+/// # Safety
+///
+/// `conn` must be a valid connection and `out_names` and `out_len` must point
+/// to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_table_names(
+    conn: *const LanceDBConnection,
+    out_names: *mut *mut *mut c_char,
+    out_len: *mut usize,
+) -> c_int {
+    capture_status(|| {
+        let conn = handle_arg("conn", conn)?;
+        let out_names = out_arg("out_names", out_names)?;
+        let out_len = out_arg("out_len", out_len)?;
+        let names = RUNTIME.block_on(conn.inner.table_names().execute())?;
+        let names = names
+            .into_iter()
+            .map(|name| {
+                CString::new(name)
+                    .map(CString::into_raw)
+                    .map_err(|e| Error::Runtime {
+                        message: format!("table name is not a valid C string: {}", e),
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let names = names.into_boxed_slice();
+        *out_len = names.len();
+        *out_names = Box::into_raw(names) as *mut *mut c_char;
+        Ok(())
+    })
+}
+
+/// Release an array of strings returned by [`lancedb_table_names`]
+///
+/// # Safety
+///
+/// `names` must be null or an array returned by [`lancedb_table_names`] with
+/// its length `len`, that has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_string_array_free(names: *mut *mut c_char, len: usize) {
+    if names.is_null() {
+        return;
+    }
+    let names = Box::from_raw(std::ptr::slice_from_raw_parts_mut(names, len));
+    for name in names.iter() {
+        drop(CString::from_raw(*name));
+    }
+}
+
+/// Drop the table named `name`
+///
+/// Tables opened before the table was dropped must still be released.
+/// Returns [`LANCEDB_OK`] on success.
+///
+/// # Safety
+///
+/// `conn` must be a valid connection and `name` a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_drop_table(
+    conn: *const LanceDBConnection,
+    name: *const c_char,
+) -> c_int {
+    capture_status(|| {
+        let conn = handle_arg("conn", conn)?;
+        let name = str_arg("name", name)?;
+        RUNTIME.block_on(conn.inner.drop_table(name))
+    })
+}
+
+/// Open the table named `name`
+///
+/// Returns null on failure.  The returned table must be released with
+/// [`lancedb_table_free`].
+///
+/// # Safety
+///
+/// `conn` must be a valid connection and `name` a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_open_table(
+    conn: *const LanceDBConnection,
+    name: *const c_char,
+) -> *mut LanceDBTable {
+    capture(|| {
+        let conn = handle_arg("conn", conn)?;
+        let name = str_arg("name", name)?;
+        let inner = RUNTIME.block_on(conn.inner.open_table(name).execute())?;
+        Ok(Box::into_raw(Box::new(LanceDBTable { inner })))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Create a table named `name` from the data in `stream`
+///
+/// The stream is consumed (and released) by this call, even if it fails.
+/// Returns null on failure.  The returned table must be released with
+/// [`lancedb_table_free`].
+///
+/// # Safety
+///
+/// `conn` must be a valid connection, `name` a valid nul-terminated string and
+/// `stream` a valid Arrow C stream.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_create_table(
+    conn: *const LanceDBConnection,
+    name: *const c_char,
+    stream: *mut FFI_ArrowArrayStream,
+) -> *mut LanceDBTable {
+    capture(|| {
+        let data = import_stream(stream)?;
+        let conn = handle_arg("conn", conn)?;
+        let name = str_arg("name", name)?;
+        let inner = RUNTIME.block_on(conn.inner.create_table(name, data).execute())?;
+        Ok(Box::into_raw(Box::new(LanceDBTable { inner })))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Release a table returned by [`lancedb_open_table`] or [`lancedb_create_table`]
+///
+/// # Safety
+///
+/// `table` must be null or a valid table that has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_table_free(table: *mut LanceDBTable) {
+    if !table.is_null() {
+        drop(Box::from_raw(table));
+    }
+}
+
+/// Append the data in `stream` to `table`
+///
+/// The stream is consumed (and released) by this call, even if it fails.
+/// Returns [`LANCEDB_OK`] on success.
+///
+/// # Safety
+///
+/// `table` must be a valid table and `stream` a valid Arrow C stream.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_table_add(
+    table: *const LanceDBTable,
+    stream: *mut FFI_ArrowArrayStream,
+) -> c_int {
+    capture_status(|| {
+        let data = import_stream(stream)?;
+        let table = handle_arg("table", table)?;
+        RUNTIME.block_on(table.inner.add(data).execute())
+    })
+}
+
+/// Write the schema of `table` to `out` as an Arrow C schema
+///
+/// On success `out` is initialized and ownership passes to the caller, who must
+/// call its `release` callback.  Returns [`LANCEDB_OK`] on success.
+///
+/// # Safety
+///
+/// `table` must be a valid table and `out` must point to writable
+/// (uninitialized) memory for an `ArrowSchema`.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_table_schema(
+    table: *const LanceDBTable,
+    out: *mut FFI_ArrowSchema,
+) -> c_int {
+    capture_status(|| {
+        let table = handle_arg("table", table)?;
+        if out.is_null() {
+            return Err(Error::InvalidInput {
+                message: "out must not be null".to_string(),
+            });
+        }
+        let schema = RUNTIME.block_on(table.inner.schema())?;
+        let schema = FFI_ArrowSchema::try_from(schema.as_ref())?;
+        std::ptr::write(out, schema);
+        Ok(())
+    })
+}
+
+/// Write the number of rows of `table` to `out`
+///
+/// If `filter` is not null only the rows matching the SQL filter are counted.
+/// Returns [`LANCEDB_OK`] on success.
+///
+/// # Safety
+///
+/// `table` must be a valid table, `filter` null or a valid nul-terminated string
+/// and `out` must point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_table_count_rows(
+    table: *const LanceDBTable,
+    filter: *const c_char,
+    out: *mut u64,
+) -> c_int {
+    capture_status(|| {
+        let table = handle_arg("table", table)?;
+        let filter = optional_str_arg("filter", filter)?;
+        let out = out_arg("out", out)?;
+        let count = RUNTIME.block_on(table.inner.count_rows(filter.map(str::to_string)))?;
+        *out = count as u64;
+        Ok(())
+    })
+}
+
+/// Delete the rows of `table` matching the SQL `predicate`
+///
+/// Returns [`LANCEDB_OK`] on success.
+///
+/// # Safety
+///
+/// `table` must be a valid table and `predicate` a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_table_delete(
+    table: *const LanceDBTable,
+    predicate: *const c_char,
+) -> c_int {
+    capture_status(|| {
+        let table = handle_arg("table", table)?;
+        let predicate = str_arg("predicate", predicate)?;
+        RUNTIME.block_on(table.inner.delete(predicate))?;
+        Ok(())
+    })
+}
+
+/// Query `table` and write the results to `out` as an Arrow C stream
+///
+/// * `filter` - an optional SQL filter, may be null
+/// * `vector` / `dim` - an optional query vector; if `vector` is null a plain
+///   scan is performed instead of a vector search
+/// * `limit` - the maximum number of rows to return, 0 means the default
+///   (unlimited for scans, 10 for vector searches)
+///
+/// On success `out` is initialized and ownership passes to the caller, who must
+/// call its `release` callback.  Returns [`LANCEDB_OK`] on success.
+///
+/// # Safety
+///
+/// `table` must be a valid table, `filter` null or a valid nul-terminated string,
+/// `vector` null or valid for `dim` reads and `out` must point to writable
+/// (uninitialized) memory for an `ArrowArrayStream`.
+#[no_mangle]
+pub unsafe extern "C" fn lancedb_table_query(
+    table: *const LanceDBTable,
+    filter: *const c_char,
+    vector: *const f32,
+    dim: usize,
+    limit: usize,
+    out: *mut FFI_ArrowArrayStream,
+) -> c_int {
+    capture_status(|| {
+        let table = handle_arg("table", table)?;
+        if out.is_null() {
+            return Err(Error::InvalidInput {
+                message: "out must not be null".to_string(),
+            });
+        }
+        let filter = optional_str_arg("filter", filter)?;
+        let vector = if vector.is_null() {
+            None
+        } else {
+            Some(std::slice::from_raw_parts(vector, dim))
+        };
+        let stream = export_query(&table.inner, filter, vector, limit)?;
+        std::ptr::write(out, stream);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::ffi_stream::ArrowArrayStreamReader;
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator};
+    use arrow_schema::{DataType, Field, Schema};
+
+    use super::*;
+
+    fn make_stream(start: i32) -> FFI_ArrowArrayStream {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(start..start + 10))],
+        )
+        .unwrap();
+        FFI_ArrowArrayStream::new(Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)))
+    }
+
+    #[test]
+    fn test_capi_roundtrip() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let uri = CString::new(tmp_dir.path().to_str().unwrap()).unwrap();
+        let name = CString::new("test").unwrap();
+
+        unsafe {
+            let conn = lancedb_connect(uri.as_ptr());
+            assert!(!conn.is_null());
+
+            let mut stream = make_stream(0);
+            let table = lancedb_create_table(conn, name.as_ptr(), &mut stream);
+            assert!(!table.is_null());
+            lancedb_table_free(table);
+
+            let table = lancedb_open_table(conn, name.as_ptr());
+            assert!(!table.is_null());
+            let mut stream = make_stream(10);
+            assert_eq!(lancedb_table_add(table, &mut stream), LANCEDB_OK);
+
+            let filter = CString::new("i >= 5").unwrap();
+            let mut out = FFI_ArrowArrayStream::empty();
+            assert_eq!(
+                lancedb_table_query(table, filter.as_ptr(), std::ptr::null(), 0, 0, &mut out),
+                LANCEDB_OK
+            );
+            let reader = ArrowArrayStreamReader::try_new(out).unwrap();
+            let num_rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+            assert_eq!(num_rows, 15);
+            assert!(lancedb_last_error().is_null());
+
+            let mut schema = FFI_ArrowSchema::empty();
+            assert_eq!(lancedb_table_schema(table, &mut schema), LANCEDB_OK);
+            let schema = Schema::try_from(&schema).unwrap();
+            assert_eq!(schema.field(0).name(), "i");
+
+            let predicate = CString::new("i < 5").unwrap();
+            assert_eq!(lancedb_table_delete(table, predicate.as_ptr()), LANCEDB_OK);
+            let mut count = 0;
+            assert_eq!(
+                lancedb_table_count_rows(table, std::ptr::null(), &mut count),
+                LANCEDB_OK
+            );
+            assert_eq!(count, 15);
+
+            lancedb_table_free(table);
+
+            let mut names = std::ptr::null_mut();
+            let mut len = 0;
+            assert_eq!(lancedb_table_names(conn, &mut names, &mut len), LANCEDB_OK);
+            assert_eq!(len, 1);
+            assert_eq!(CStr::from_ptr(*names).to_str().unwrap(), "test");
+            lancedb_string_array_free(names, len);
+
+            assert_eq!(lancedb_drop_table(conn, name.as_ptr()), LANCEDB_OK);
+            assert!(lancedb_open_table(conn, name.as_ptr()).is_null());
+            lancedb_connection_free(conn);
+        }
+    }
+
+    #[test]
+    fn test_capi_error() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let uri = CString::new(tmp_dir.path().to_str().unwrap()).unwrap();
+        let name = CString::new("missing").unwrap();
+
+        unsafe {
+            let conn = lancedb_connect(uri.as_ptr());
+            assert!(!conn.is_null());
+            let table = lancedb_open_table(conn, name.as_ptr());
+            assert!(table.is_null());
+            let message = CStr::from_ptr(lancedb_last_error()).to_str().unwrap();
+            assert!(message.contains("missing"), "{}", message);
+            lancedb_connection_free(conn);
+        }
+    }
+}
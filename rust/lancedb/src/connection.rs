@@ -14,27 +14,66 @@
 
 //! LanceDB Database
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::create_dir_all;
-use std::path::Path;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 use arrow_array::{RecordBatchIterator, RecordBatchReader};
 use arrow_schema::SchemaRef;
-use lance::dataset::{ReadParams, WriteMode};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use lance::dataset::{ReadParams, WriteMode, WriteParams};
 use lance::io::{ObjectStore, ObjectStoreParams, WrappingObjectStore};
-use object_store::{aws::AwsCredential, local::LocalFileSystem};
+use lance_table::io::commit::CommitHandler;
+use object_store::aws::{AwsCredential, AwsCredentialProvider};
+use object_store::local::LocalFileSystem;
 use snafu::prelude::*;
 
 use crate::arrow::IntoArrow;
+use crate::data::parquet::ParquetFiles;
+use crate::embeddings::{
+    self, EmbeddingDefinition, EmbeddingRegistry, MemoryRegistry, WithEmbeddings,
+};
 use crate::error::{CreateDirSnafu, Error, InvalidTableNameSnafu, Result};
+use crate::io::cache::{CacheBackend, CachingObjectStoreWrapper, ReadCacheOptions, TieredCache};
+use crate::io::checksum::{ChecksumMode, ChecksumObjectStoreWrapper};
+use crate::io::commit_lock::{self, CommitLock};
+use crate::io::encryption::{validate_provider, EncryptingObjectStoreWrapper, KeyProvider};
+use crate::io::limit::LimitedObjectStoreWrapper;
+use crate::io::memory::{InMemoryObjectStoreWrapper, MEMORY_SCHEME};
 use crate::io::object_store::MirroringObjectStoreWrapper;
-use crate::table::{NativeTable, WriteOptions};
-use crate::utils::validate_table_name;
+use crate::metrics::{self, ConnectionMetrics, MetricsRegistry};
+use crate::query::admission::{QueryAdmission, QueryMetrics};
+use crate::query::budget::{MemoryBudget, ScanLimits};
+use crate::query::cache::{QueryCache, QueryCacheOptions};
+use crate::quota::{Quota, Quotas};
+#[cfg(feature = "remote")]
+pub use crate::remote::client::{ClientConfig, Middleware, RetryConfig};
+use crate::table::compression::{self, Compression, CompressionLevel};
+use crate::table::hooks::{CommitHook, CommitSummary, OnCommit};
+use crate::table::pack::{self, PackageInfo, LATEST_MANIFEST, VERSIONS_DIR};
+use crate::table::partition;
+use crate::table::primary_key;
+use crate::table::snapshot::{self, SnapshotInfo};
+use crate::table::transaction::{self, Transaction};
+use crate::table::ttl::{self, Ttl};
+use crate::table::view::{Materialized, ViewDefinition, ViewTable};
+use crate::table::{blob, dictionary};
+use crate::table::{
+    trash, AutoCompaction, AutoIndex, CommitBackoff, NativeTable, TableInternal, WriteOptions,
+    DEFAULT_COMMIT_RETRIES,
+};
+use crate::telemetry::OperationSpan;
+use crate::utils::{glob_match, validate_namespace_name, validate_table_name};
 use crate::Table;
 
 pub const LANCE_FILE_EXTENSION: &str = "lance";
 
+/// The number of files copied at once when renaming or cloning a table
+const COPY_CONCURRENCY: usize = 16;
+
 pub type TableBuilderCallback = Box<dyn FnOnce(OpenTableBuilder) -> OpenTableBuilder + Send>;
 
 /// Describes what happens when creating a table and a table with
@@ -84,6 +123,8 @@ pub struct TableNamesBuilder {
     parent: Arc<dyn ConnectionInternal>,
     pub(crate) start_after: Option<String>,
     pub(crate) limit: Option<u32>,
+    pub(crate) prefix: Option<String>,
+    pub(crate) pattern: Option<String>,
 }
 
 impl TableNamesBuilder {
@@ -92,6 +133,8 @@ impl TableNamesBuilder {
             parent,
             start_after: None,
             limit: None,
+            prefix: None,
+            pattern: None,
         }
     }
 
@@ -111,10 +154,84 @@ impl TableNamesBuilder {
         self
     }
 
+    /// Only return the names that start with `prefix`
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Only return the names that match the glob `pattern`
+    ///
+    /// `*` matches any number of characters and `?` matches a single character,
+    /// for example `tenant_*_docs`.
+    pub fn pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.pattern = Some(pattern.into());
+        self
+    }
+
+    /// Whether the builder has a prefix or a pattern
+    pub(crate) fn has_filter(&self) -> bool {
+        self.prefix.is_some() || self.pattern.is_some()
+    }
+
+    /// Whether `name` passes the prefix and the pattern
+    pub(crate) fn matches(&self, name: &str) -> bool {
+        self.prefix
+            .as_ref()
+            .map_or(true, |prefix| name.starts_with(prefix.as_str()))
+            && self
+                .pattern
+                .as_ref()
+                .map_or(true, |pattern| glob_match(pattern, name))
+    }
+
     /// Execute the table names operation
     pub async fn execute(self) -> Result<Vec<String>> {
         self.parent.clone().table_names(self).await
     }
+
+    /// Execute the table names operation, returning the names as they are needed
+    ///
+    /// The names are requested in pages of `page_size` names, each page starting
+    /// after the last name of the previous one, so that only one page is held in
+    /// memory at a time.  This is meant for databases with a very large number
+    /// of tables.  The limit, if any, applies to the whole stream.
+    pub fn execute_stream(self, page_size: u32) -> BoxStream<'static, Result<String>> {
+        let page_size = page_size.max(1);
+        let state = (self, false);
+        futures::stream::try_unfold(state, move |(options, done)| async move {
+            if done || options.limit == Some(0) {
+                return Ok(None);
+            }
+            let requested = options
+                .limit
+                .map_or(page_size, |limit| limit.min(page_size));
+            let page = Self {
+                parent: options.parent.clone(),
+                start_after: options.start_after.clone(),
+                limit: Some(requested),
+                prefix: options.prefix.clone(),
+                pattern: options.pattern.clone(),
+            }
+            .execute()
+            .await?;
+            let Some(last) = page.last().cloned() else {
+                return Ok(None);
+            };
+            let done = page.len() < requested as usize;
+            let next = Self {
+                start_after: Some(last),
+                limit: options.limit.map(|limit| limit - page.len() as u32),
+                ..options
+            };
+            Ok::<_, Error>(Some((
+                futures::stream::iter(page.into_iter().map(Ok::<String, Error>)),
+                (next, done),
+            )))
+        })
+        .try_flatten()
+        .boxed()
+    }
 }
 
 pub struct NoData {}
@@ -133,6 +250,16 @@ pub struct CreateTableBuilder<const HAS_DATA: bool, T: IntoArrow> {
     pub(crate) schema: Option<SchemaRef>,
     pub(crate) mode: CreateTableMode,
     pub(crate) write_options: WriteOptions,
+    pub(crate) temporary: bool,
+    pub(crate) embeddings: Vec<EmbeddingDefinition>,
+    pub(crate) soft_delete: bool,
+    pub(crate) compression: HashMap<String, Compression>,
+    pub(crate) dictionary: HashSet<String>,
+    pub(crate) auto_dictionary: Option<f64>,
+    pub(crate) blobs: HashSet<String>,
+    pub(crate) primary_key: Option<String>,
+    pub(crate) ttl: Option<Ttl>,
+    pub(crate) partition_by: Option<String>,
 }
 
 // Builder methods that only apply when we have initial data
@@ -145,6 +272,16 @@ impl<T: IntoArrow> CreateTableBuilder<true, T> {
             schema: None,
             mode: CreateTableMode::default(),
             write_options: WriteOptions::default(),
+            temporary: false,
+            embeddings: Vec::new(),
+            soft_delete: false,
+            compression: HashMap::new(),
+            dictionary: HashSet::new(),
+            auto_dictionary: None,
+            blobs: HashSet::new(),
+            primary_key: None,
+            ttl: None,
+            partition_by: None,
         }
     }
 
@@ -156,9 +293,13 @@ impl<T: IntoArrow> CreateTableBuilder<true, T> {
 
     /// Execute the create table operation
     pub async fn execute(self) -> Result<Table> {
-        let parent = self.parent.clone();
-        let (data, builder) = self.extract_data()?;
-        parent.do_create_table(builder, data).await
+        let span = OperationSpan::create_table(&self.name);
+        span.run(async move {
+            let parent = self.parent.clone();
+            let (data, builder) = self.extract_data()?;
+            parent.do_create_table(builder, data).await
+        })
+        .await
     }
 
     fn extract_data(
@@ -175,6 +316,16 @@ impl<T: IntoArrow> CreateTableBuilder<true, T> {
             schema: self.schema,
             mode: self.mode,
             write_options: self.write_options,
+            temporary: self.temporary,
+            embeddings: self.embeddings,
+            soft_delete: self.soft_delete,
+            compression: self.compression,
+            dictionary: self.dictionary,
+            auto_dictionary: self.auto_dictionary,
+            blobs: self.blobs,
+            primary_key: self.primary_key,
+            ttl: self.ttl,
+            partition_by: self.partition_by,
         };
         Ok((data, builder))
     }
@@ -190,16 +341,142 @@ impl CreateTableBuilder<false, NoData> {
             schema: Some(schema),
             mode: CreateTableMode::default(),
             write_options: WriteOptions::default(),
+            temporary: false,
+            embeddings: Vec::new(),
+            soft_delete: false,
+            compression: HashMap::new(),
+            dictionary: HashSet::new(),
+            auto_dictionary: None,
+            blobs: HashSet::new(),
+            primary_key: None,
+            ttl: None,
+            partition_by: None,
         }
     }
 
     /// Execute the create table operation
     pub async fn execute(self) -> Result<Table> {
-        self.parent.clone().do_create_empty_table(self).await
+        OperationSpan::create_table(&self.name)
+            .run(self.parent.clone().do_create_empty_table(self))
+            .await
     }
 }
 
 impl<const HAS_DATA: bool, T: IntoArrow> CreateTableBuilder<HAS_DATA, T> {
+    /// Compute a vector column with an embedding function
+    ///
+    /// The function must be registered with the connection's
+    /// [`EmbeddingRegistry`].  The vector column is computed from the source
+    /// column for the initial data and for any data added later which does not
+    /// already contain it.  Queries on the vector column may use the source
+    /// data type (e.g. a string) as the query vector.
+    ///
+    /// When creating an empty table the schema should only contain the source
+    /// column, the vector column will be added.
+    pub fn add_embedding(mut self, definition: EmbeddingDefinition) -> Self {
+        self.embeddings.push(definition);
+        self
+    }
+
+    /// Keep deleted rows in a trash from which they can be restored
+    ///
+    /// Rows removed by [`Table::delete`] can be searched with
+    /// [`crate::query::QueryBase::only_deleted`] and restored with
+    /// [`Table::undelete`] until the table is compacted.  The default is false.
+    pub fn soft_delete(mut self, enabled: bool) -> Self {
+        self.soft_delete = enabled;
+        self
+    }
+
+    /// Make `column`, an integer or a string column, the primary key of the table
+    ///
+    /// The keys of the initial data must be unique and not null.  Adds can then
+    /// replace or reject the rows whose key is already in the table, see
+    /// [`crate::table::AddDataMode::OverwriteOnConflict`] and
+    /// [`crate::table::AddDataMode::ErrorOnConflict`], and [`Table::get`] looks
+    /// up a row by its key.  By default a table has no primary key.
+    pub fn primary_key(mut self, column: impl Into<String>) -> Self {
+        self.primary_key = Some(column.into());
+        self
+    }
+
+    /// Expire the rows `retention` after the time in `column`
+    ///
+    /// `column` must be a timestamp or a date column.  Expired rows are
+    /// deleted by [`crate::table::OptimizeAction::Expire`], see
+    /// [`crate::table::ttl`].  By default rows never expire.
+    pub fn ttl(mut self, column: impl Into<String>, retention: std::time::Duration) -> Self {
+        self.ttl = Some(Ttl::new(column, retention));
+        self
+    }
+
+    /// Group the rows written to the table into fragments by their value in `column`
+    ///
+    /// `column` must be an integer, a string or a date column, e.g. a tenant
+    /// id or a day.  Each fragment then holds the rows of one key, so filters
+    /// on the key read fewer fragments and deleting all of the rows of a key
+    /// removes its fragments, see [`crate::table::partition`].  By default
+    /// the rows are written in the order they are given.
+    pub fn partition_by(mut self, column: impl Into<String>) -> Self {
+        self.partition_by = Some(column.into());
+        self
+    }
+
+    /// Set the compression codec of a column
+    ///
+    /// The codec is stored in the schema of the table, see
+    /// [`crate::table::compression`] for the file formats which apply it.
+    /// Columns without a codec use the default of the file format.  The
+    /// column can be a vector column computed by an embedding function.
+    pub fn column_compression(mut self, column: impl Into<String>, codec: Compression) -> Self {
+        self.compression.insert(column.into(), codec);
+        self
+    }
+
+    /// Set the compression of a column by its level rather than its codec
+    ///
+    /// This is [`Self::column_compression`] with the codec of `level`, see
+    /// [`CompressionLevel`].
+    pub fn column_compression_level(
+        mut self,
+        column: impl Into<String>,
+        level: CompressionLevel,
+    ) -> Self {
+        self.compression.insert(column.into(), level.into());
+        self
+    }
+
+    /// Dictionary encode a string column
+    ///
+    /// The encoding is stored in the schema of the table, see
+    /// [`crate::table::dictionary`] for the file formats which apply it.
+    /// Dictionary encoding suits the columns with few distinct values, such as
+    /// tags or categories.
+    pub fn dictionary_encode(mut self, column: impl Into<String>) -> Self {
+        self.dictionary.insert(column.into());
+        self
+    }
+
+    /// Dictionary encode the string columns of low cardinality
+    ///
+    /// The string columns of the first batch of the initial data whose number
+    /// of distinct values is at most `max_ratio` times their number of rows
+    /// are dictionary encoded, as with [`Self::dictionary_encode`].  This has no
+    /// effect when creating an empty table.
+    pub fn auto_dictionary_encode(mut self, max_ratio: f64) -> Self {
+        self.auto_dictionary = Some(max_ratio);
+        self
+    }
+
+    /// Make the binary column `column` a blob column
+    ///
+    /// Blob columns hold large values, such as documents or images, which
+    /// queries can leave out and read on demand, see [`crate::table::blob`].
+    pub fn blob_column(mut self, column: impl Into<String>) -> Self {
+        self.blobs.insert(column.into());
+        self
+    }
+
     /// Set the mode for creating the table
     ///
     /// This controls what happens if a table with the given name already exists
@@ -256,9 +533,10 @@ impl<const HAS_DATA: bool, T: IntoArrow> CreateTableBuilder<HAS_DATA, T> {
 #[derive(Clone, Debug)]
 pub struct OpenTableBuilder {
     parent: Arc<dyn ConnectionInternal>,
-    name: String,
+    pub(crate) name: String,
     index_cache_size: u32,
     lance_read_params: Option<ReadParams>,
+    pub(crate) version: Option<u64>,
 }
 
 impl OpenTableBuilder {
@@ -268,9 +546,19 @@ impl OpenTableBuilder {
             name,
             index_cache_size: 256,
             lance_read_params: None,
+            version: None,
         }
     }
 
+    /// Open the table checked out at `version`
+    ///
+    /// The table is opened read-only, as if [`Table::checkout`] had been called on
+    /// it.  Use [`Table::checkout_latest`] to return it to the latest version.
+    pub fn version(mut self, version: u64) -> Self {
+        self.version = Some(version);
+        self
+    }
+
     /// Set the size of the index cache, specified as a number of entries
     ///
     /// The default value is 256
@@ -339,7 +627,61 @@ impl OpenTableBuilder {
 
     /// Open the table
     pub async fn execute(self) -> Result<Table> {
-        self.parent.clone().do_open_table(self).await
+        OperationSpan::open_table(&self.name)
+            .run(self.parent.clone().do_open_table(self))
+            .await
+    }
+}
+
+/// A builder for configuring a [`Connection::create_view`] operation
+pub struct CreateViewBuilder {
+    parent: Arc<dyn ConnectionInternal>,
+    pub(crate) name: String,
+    pub(crate) base_table: String,
+    pub(crate) filter: Option<String>,
+    pub(crate) columns: Option<Vec<String>>,
+    pub(crate) materialized: bool,
+}
+
+impl CreateViewBuilder {
+    fn new(parent: Arc<dyn ConnectionInternal>, name: String, base_table: String) -> Self {
+        Self {
+            parent,
+            name,
+            base_table,
+            filter: None,
+            columns: None,
+            materialized: false,
+        }
+    }
+
+    /// Only include the rows of the base table matching the filter
+    ///
+    /// The filter is combined with the filter of every query against the view.
+    pub fn only_if(mut self, filter: impl AsRef<str>) -> Self {
+        self.filter = Some(filter.as_ref().to_string());
+        self
+    }
+
+    /// Only include the given columns of the base table
+    pub fn select(mut self, columns: &[impl AsRef<str>]) -> Self {
+        self.columns = Some(columns.iter().map(|c| c.as_ref().to_string()).collect());
+        self
+    }
+
+    /// Store the rows of the view instead of reading them from the base table
+    ///
+    /// A materialized view is faster to query when the filter is selective but
+    /// it takes up space and its rows are rewritten the next time the view is
+    /// read after each commit to the base table.  The default is false.
+    pub fn materialized(mut self, materialized: bool) -> Self {
+        self.materialized = materialized;
+        self
+    }
+
+    /// Create the view
+    pub async fn execute(self) -> Result<Table> {
+        self.parent.clone().do_create_view(self).await
     }
 }
 
@@ -355,7 +697,42 @@ pub(crate) trait ConnectionInternal:
     ) -> Result<Table>;
     async fn do_open_table(&self, options: OpenTableBuilder) -> Result<Table>;
     async fn drop_table(&self, name: &str) -> Result<()>;
+    async fn rename_table(&self, old_name: &str, new_name: &str) -> Result<()>;
+    /// Copy the files of the table `source` to the table `target`
+    async fn clone_table(&self, source: &str, target: &str) -> Result<()>;
+    /// Write the files of the package at `path` to the table `name`
+    async fn unpack(&self, path: &Path, name: &str) -> Result<PackageInfo>;
+    async fn import_snapshot(&self, source_uri: &str, name: &str) -> Result<SnapshotInfo>;
+    /// Copy the files of all of the tables to the database at `uri`
+    async fn persist_to(&self, uri: &str) -> Result<()>;
     async fn drop_db(&self) -> Result<()>;
+    /// The database of the tables of the namespace `name`
+    fn namespace(&self, name: &str) -> Result<Arc<dyn ConnectionInternal>>;
+    async fn list_namespaces(&self) -> Result<Vec<String>>;
+    async fn create_namespace(&self, name: &str) -> Result<()>;
+    async fn drop_namespace(&self, name: &str) -> Result<()>;
+    fn embedding_registry(&self) -> &dyn EmbeddingRegistry;
+    async fn do_create_view(&self, options: CreateViewBuilder) -> Result<Table>;
+    async fn open_view(&self, name: &str) -> Result<Table>;
+    async fn drop_view(&self, name: &str) -> Result<()>;
+
+    fn query_metrics(&self) -> Option<QueryMetrics> {
+        None
+    }
+
+    fn metrics(&self) -> Option<ConnectionMetrics> {
+        None
+    }
+
+    /// The store and the directory of the records of the transactions being
+    /// committed, see [`crate::table::transaction`]
+    fn transaction_log(
+        &self,
+    ) -> Result<(Arc<dyn object_store::ObjectStore>, object_store::path::Path)> {
+        Err(Error::NotSupported {
+            message: "transactions are not supported by this connection".to_string(),
+        })
+    }
 
     async fn do_create_empty_table(
         &self,
@@ -392,7 +769,9 @@ impl Connection {
     ///
     /// The names will be returned in lexicographical order (ascending)
     ///
-    /// The parameters `page_token` and `limit` can be used to paginate the results
+    /// The parameters `start_after` and `limit` can be used to paginate the results,
+    /// and `prefix` or `pattern` to filter them.  See
+    /// [`TableNamesBuilder::execute_stream`] to page through a large number of tables.
     pub fn table_names(&self) -> TableNamesBuilder {
         TableNamesBuilder::new(self.internal.clone())
     }
@@ -411,6 +790,91 @@ impl Connection {
         CreateTableBuilder::<true, T>::new(self.internal.clone(), name.into(), initial_data)
     }
 
+    /// Create a new table from the Parquet files matching a glob
+    ///
+    /// `uri_glob` is a path or the URI of an object store, such as
+    /// `s3://bucket/data/*.parquet`.  The files are streamed into the table, so
+    /// they do not need to fit in memory.  See [`ParquetFiles`] to set the
+    /// options of the object store the files are read from.
+    pub fn create_table_from_parquet(
+        &self,
+        name: impl Into<String>,
+        uri_glob: impl Into<String>,
+    ) -> CreateTableBuilder<true, ParquetFiles> {
+        self.create_table(name, ParquetFiles::new(uri_glob))
+    }
+
+    /// The registry of embedding functions used by tables in this connection
+    ///
+    /// See [`crate::embeddings`] for more details.
+    pub fn embedding_registry(&self) -> &dyn EmbeddingRegistry {
+        self.internal.embedding_registry()
+    }
+
+    /// The number of queries running and waiting to run on this connection
+    ///
+    /// Returns None unless the queries are limited with
+    /// [`ConnectBuilder::max_concurrent_queries`].
+    pub fn query_metrics(&self) -> Option<QueryMetrics> {
+        self.internal.query_metrics()
+    }
+
+    /// The metrics of the operations of this connection
+    ///
+    /// Returns None for connections to LanceDB Cloud.  See [`crate::metrics`]
+    /// for what is measured and how to export it.
+    pub fn metrics(&self) -> Option<ConnectionMetrics> {
+        self.internal.metrics()
+    }
+
+    /// Create a temporary table from data
+    ///
+    /// The table is stored in a temporary location on the local filesystem,
+    /// regardless of the database location, and only exists for the lifetime of
+    /// this connection.  It is dropped automatically once the connection (and
+    /// all of its clones) are dropped.  This is useful for staging intermediate
+    /// results and for tests.
+    ///
+    /// Temporary tables can be opened with [`Self::open_table`] and dropped with
+    /// [`Self::drop_table`] like any other table.  They take precedence over a
+    /// persisted table with the same name but are not returned by
+    /// [`Self::table_names`].
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - The name of the table
+    /// * `initial_data` - The initial data to write to the table
+    pub fn create_temp_table<T: IntoArrow>(
+        &self,
+        name: impl Into<String>,
+        initial_data: T,
+    ) -> CreateTableBuilder<true, T> {
+        let mut builder =
+            CreateTableBuilder::<true, T>::new(self.internal.clone(), name.into(), initial_data);
+        builder.temporary = true;
+        builder
+    }
+
+    /// Create an empty temporary table with a given schema
+    ///
+    /// See [`Self::create_temp_table`].  This is useful to stage the results of
+    /// several steps into one table, adding the data of each step.
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - The name of the table
+    /// * `schema` - The schema of the table
+    pub fn create_empty_temp_table(
+        &self,
+        name: impl Into<String>,
+        schema: SchemaRef,
+    ) -> CreateTableBuilder<false, NoData> {
+        let mut builder =
+            CreateTableBuilder::<false, NoData>::new(self.internal.clone(), name.into(), schema);
+        builder.temporary = true;
+        builder
+    }
+
     /// Create an empty table with a given schema
     ///
     /// # Parameters
@@ -436,6 +900,19 @@ impl Connection {
         OpenTableBuilder::new(self.internal.clone(), name.into())
     }
 
+    /// Open an existing table at a specific version
+    ///
+    /// This is a shortcut for [`Self::open_table`] with [`OpenTableBuilder::version`],
+    /// useful to pin the reads of a pipeline to a snapshot of the table.  See
+    /// [`Table::list_versions`] for the versions of a table.
+    pub fn open_table_with_version(
+        &self,
+        name: impl Into<String>,
+        version: u64,
+    ) -> OpenTableBuilder {
+        self.open_table(name).version(version)
+    }
+
     /// Drop a table in the database.
     ///
     /// # Arguments
@@ -444,12 +921,249 @@ impl Connection {
         self.internal.drop_table(name.as_ref()).await
     }
 
+    /// Rename a table
+    ///
+    /// On a local filesystem the rename is atomic.  On an object store, such as
+    /// S3, the files of the table are copied and then deleted, so the table is
+    /// briefly visible under both names and the rename must not run concurrently
+    /// with writes to the table.  Open handles to the table, and views of it,
+    /// stop working after the rename.
+    ///
+    /// Fails with [`Error::TableNotFound`] if `old_name` does not exist and with
+    /// [`Error::TableAlreadyExists`] if `new_name` does.
+    pub async fn rename_table(
+        &self,
+        old_name: impl AsRef<str>,
+        new_name: impl AsRef<str>,
+    ) -> Result<()> {
+        self.internal
+            .rename_table(old_name.as_ref(), new_name.as_ref())
+            .await
+    }
+
+    /// Create the table `target` as a copy of the table `source`
+    ///
+    /// The copy is cheap: on a local filesystem the files are hard links to the
+    /// files of `source`, and on an object store they are copied by the store
+    /// without being downloaded.  The two tables are independent afterwards.
+    ///
+    /// If `version` is given the latest version of the copy has the contents of
+    /// that version of `source`, otherwise of its latest version.  The older
+    /// versions of `source` are part of the history of the copy.
+    pub async fn clone_table(
+        &self,
+        source: impl AsRef<str>,
+        target: impl AsRef<str>,
+        version: Option<u64>,
+    ) -> Result<Table> {
+        let target = target.as_ref();
+        self.internal.clone_table(source.as_ref(), target).await?;
+        let cloned = async {
+            let table = self.open_table(target).execute().await?;
+            if let Some(version) = version {
+                table.restore_version(version).await?;
+            }
+            if let Some(native) = table.as_native() {
+                native.update_quota_usage().await;
+            }
+            Ok(table)
+        }
+        .await;
+        if cloned.is_err() {
+            // Do not leave a partial copy behind
+            if let Err(e) = self.internal.drop_table(target).await {
+                log::warn!("Failed to remove the partial copy {}: {}", target, e);
+            }
+        }
+        cloned
+    }
+
+    /// Create the table `name` from a package written by [`Table::pack`]
+    ///
+    /// The table has the version that was packed, without its history.  Fails
+    /// with [`Error::TableAlreadyExists`] if `name` exists and with
+    /// [`Error::InvalidInput`] if the file is not a valid package.
+    pub async fn unpack(&self, path: impl AsRef<Path>, name: impl AsRef<str>) -> Result<Table> {
+        let name = name.as_ref();
+        self.internal.unpack(path.as_ref(), name).await?;
+        let opened = async {
+            let table = self.open_table(name).execute().await?;
+            if let Some(native) = table.as_native() {
+                native.update_quota_usage().await;
+            }
+            Ok(table)
+        }
+        .await;
+        if opened.is_err() {
+            // Do not leave an unusable table behind
+            if let Err(e) = self.internal.drop_table(name).await {
+                log::warn!("Failed to remove the unpacked table {}: {}", name, e);
+            }
+        }
+        opened
+    }
+
+    /// Start a transaction, whose adds and deletes to several tables of the
+    /// database are committed together
+    ///
+    /// See [`crate::table::transaction`].
+    pub fn transaction(&self) -> Transaction {
+        Transaction::new(self.clone())
+    }
+
+    /// Restore the tables written by the transactions whose commit was
+    /// interrupted, e.g. by a crash, to their version before the transaction
+    ///
+    /// The records of committed transactions are removed without rolling
+    /// them back, and a transaction whose record was updated less than
+    /// [`crate::table::transaction::TRANSACTION_LEASE`] ago is taken for a
+    /// commit still in progress and left alone.  Returns the number of
+    /// transactions rolled back.
+    pub async fn recover_transactions(&self) -> Result<usize> {
+        transaction::recover(self).await
+    }
+
+    pub(crate) fn transaction_log(
+        &self,
+    ) -> Result<(Arc<dyn object_store::ObjectStore>, object_store::path::Path)> {
+        self.internal.transaction_log()
+    }
+
+    /// Copy the snapshot of a table at `source_uri` into the table `name`
+    ///
+    /// The snapshot is one written by [`Table::export_snapshot`], or any
+    /// table, whose latest version is copied.  It is read with the storage
+    /// options of the connection.  An interrupted import is resumed by importing
+    /// the same snapshot again, the files already copied are skipped.  Fails with
+    /// [`Error::TableAlreadyExists`] if the table exists.
+    pub async fn import_snapshot(
+        &self,
+        source_uri: impl AsRef<str>,
+        name: impl AsRef<str>,
+    ) -> Result<Table> {
+        let name = name.as_ref();
+        self.internal
+            .import_snapshot(source_uri.as_ref(), name)
+            .await?;
+        let table = self.open_table(name).execute().await?;
+        if let Some(native) = table.as_native() {
+            native.update_quota_usage().await;
+        }
+        Ok(table)
+    }
+
+    /// Copy all of the tables of the database to the database at `uri`
+    ///
+    /// This is the escape hatch of an in-memory database, connected with
+    /// `memory://`, whose tables are lost when the connection is dropped: the
+    /// tables, with their history, indices and views, are written to a
+    /// directory or an object store, which can then be connected to.  The
+    /// tables should not be written while they are copied.  Fails with
+    /// [`Error::TableAlreadyExists`] if a table exists in both databases.
+    pub async fn persist_to(&self, uri: impl AsRef<str>) -> Result<()> {
+        self.internal.persist_to(uri.as_ref()).await
+    }
+
     /// Drop the database
     ///
     /// This is the same as dropping all of the tables
     pub async fn drop_db(&self) -> Result<()> {
         self.internal.drop_db().await
     }
+
+    /// A connection to the tables of the namespace `name`
+    ///
+    /// Namespaces separate the tables of the tenants of a service, without
+    /// mangling the names of the tables by hand.  The tables of a namespace are
+    /// stored in the directory `name` of the database, and the connection of
+    /// the namespace lists, creates and drops the tables of that directory only.
+    /// It shares the options, caches and limits of this connection, but the
+    /// quotas of this connection do not apply to the tables of the namespace.
+    /// Namespaces can be nested.
+    ///
+    /// The namespace does not need to be created first, its tables can be
+    /// created right away.  This is not supported by LanceDB Cloud.
+    ///
+    /// ```no_run
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let db = lancedb::connect("data/sample-lancedb").execute().await.unwrap();
+    /// let tenant = db.namespace("tenant_a").unwrap();
+    /// let table = tenant.open_table("items").execute().await.unwrap();
+    /// # });
+    /// ```
+    pub fn namespace(&self, name: impl AsRef<str>) -> Result<Self> {
+        let name = name.as_ref();
+        Ok(Self {
+            uri: format!("{}/{}", self.uri.trim_end_matches('/'), name),
+            internal: self.internal.namespace(name)?,
+        })
+    }
+
+    /// The names of the namespaces of the database, see [`Self::namespace`]
+    ///
+    /// A namespace is listed once it was created with [`Self::create_namespace`]
+    /// or one of its tables was created.  The names are sorted.
+    pub async fn list_namespaces(&self) -> Result<Vec<String>> {
+        self.internal.list_namespaces().await
+    }
+
+    /// Create the namespace `name`, see [`Self::namespace`]
+    ///
+    /// This does nothing if the namespace already exists.
+    pub async fn create_namespace(&self, name: impl AsRef<str>) -> Result<()> {
+        self.internal.create_namespace(name.as_ref()).await
+    }
+
+    /// Drop the namespace `name`, with all of its tables
+    ///
+    /// Fails with [`Error::NamespaceNotFound`] if the namespace does not exist.
+    pub async fn drop_namespace(&self, name: impl AsRef<str>) -> Result<()> {
+        self.internal.drop_namespace(name.as_ref()).await
+    }
+
+    /// Create a view over a table
+    ///
+    /// A view is a filter and/or a projection over a base table that can be
+    /// queried like a table.  The view is read only.  Queries against the view
+    /// only see the rows matching the filter of the view and only the columns
+    /// selected by the view.  Indices on the base table are used by these queries.
+    ///
+    /// See [`CreateViewBuilder::materialized`] to store the rows of the view.
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - The name of the view
+    /// * `base_table` - The name of the table the view reads from
+    pub fn create_view(
+        &self,
+        name: impl Into<String>,
+        base_table: impl Into<String>,
+    ) -> CreateViewBuilder {
+        CreateViewBuilder::new(self.internal.clone(), name.into(), base_table.into())
+    }
+
+    /// Open an existing view
+    ///
+    /// Returns [`Error::TableNotFound`] if the view does not exist.
+    pub async fn open_view(&self, name: impl AsRef<str>) -> Result<Table> {
+        self.internal.open_view(name.as_ref()).await
+    }
+
+    /// Drop a view, the base table is not affected
+    pub async fn drop_view(&self, name: impl AsRef<str>) -> Result<()> {
+        self.internal.drop_view(name.as_ref()).await
+    }
+
+    /// Run a SQL query against the tables and views of the database
+    ///
+    /// The query is executed by DataFusion.  Projections, limits, and simple
+    /// filters are pushed down into the scans of the tables, see [`crate::sql`].
+    /// Only queries are supported, the tables cannot be created or modified
+    /// through SQL.
+    #[cfg(feature = "datafusion")]
+    pub async fn sql(&self, sql: &str) -> Result<crate::arrow::SendableRecordBatchStream> {
+        crate::sql::execute(self, sql).await
+    }
 }
 
 #[derive(Debug)]
@@ -460,6 +1174,7 @@ pub struct ConnectBuilder {
     ///
     /// - `/path/to/database` - local database on file system.
     /// - `s3://bucket/path/to/database` or `gs://bucket/path/to/database` - database on cloud object store
+    /// - `memory://` - in-memory database, dropped with the connection
     /// - `db://dbname` - LanceDB Cloud
     uri: String,
 
@@ -469,9 +1184,18 @@ pub struct ConnectBuilder {
     region: Option<String>,
     /// LanceDB Cloud host override, only required if using an on-premises Lance Cloud instance
     host_override: Option<String>,
+    /// The configuration of the HTTP client, only used by LanceDB Cloud
+    #[cfg(feature = "remote")]
+    client_config: ClientConfig,
 
     storage_options: HashMap<String, String>,
 
+    /// Provides the credentials used to access S3, refreshed as they expire
+    aws_credentials: Option<AwsCredentialProvider>,
+
+    /// The DynamoDB table used to make concurrent commits to S3 safe
+    dynamodb_commit_table: Option<String>,
+
     /// The interval at which to check for updates from other processes.
     ///
     /// If None, then consistency is not checked. For performance
@@ -483,6 +1207,47 @@ pub struct ConnectBuilder {
     /// consistency only applies to read operations. Write operations are
     /// always consistent.
     read_consistency_interval: Option<std::time::Duration>,
+
+    embedding_registry: Option<Arc<dyn EmbeddingRegistry>>,
+
+    /// The quotas of the tables, by table name prefix
+    quotas: Vec<(String, Quota)>,
+
+    checksums: ChecksumMode,
+
+    commit_retries: u32,
+
+    commit_backoff: CommitBackoff,
+
+    auto_compaction: Option<AutoCompaction>,
+
+    auto_index: Option<AutoIndex>,
+
+    retry_stale_queries: bool,
+
+    commit_hooks: Vec<Arc<dyn CommitHook>>,
+
+    max_concurrent_queries: Option<usize>,
+
+    max_queued_queries: Option<usize>,
+
+    query_cache: Option<QueryCacheOptions>,
+
+    commit_lock: Option<Arc<dyn CommitLock>>,
+
+    cache_backend: Option<Arc<dyn CacheBackend>>,
+
+    read_cache: Option<ReadCacheOptions>,
+
+    encryption: Option<Arc<dyn KeyProvider>>,
+
+    max_concurrent_requests: Option<usize>,
+
+    batch_readahead: Option<usize>,
+
+    fragment_readahead: Option<usize>,
+
+    memory_budget: Option<usize>,
 }
 
 impl ConnectBuilder {
@@ -493,8 +1258,32 @@ impl ConnectBuilder {
             api_key: None,
             region: None,
             host_override: None,
+            #[cfg(feature = "remote")]
+            client_config: ClientConfig::default(),
             read_consistency_interval: None,
             storage_options: HashMap::new(),
+            aws_credentials: None,
+            dynamodb_commit_table: None,
+            embedding_registry: None,
+            quotas: Vec::new(),
+            checksums: ChecksumMode::default(),
+            commit_retries: DEFAULT_COMMIT_RETRIES,
+            commit_backoff: CommitBackoff::default(),
+            auto_compaction: None,
+            auto_index: None,
+            retry_stale_queries: false,
+            commit_hooks: Vec::new(),
+            max_concurrent_queries: None,
+            max_queued_queries: None,
+            query_cache: None,
+            commit_lock: None,
+            cache_backend: None,
+            read_cache: None,
+            encryption: None,
+            max_concurrent_requests: None,
+            batch_readahead: None,
+            fragment_readahead: None,
+            memory_budget: None,
         }
     }
 
@@ -513,6 +1302,38 @@ impl ConnectBuilder {
         self
     }
 
+    /// Configure the HTTP client used to connect to LanceDB Cloud
+    ///
+    /// The [`ClientConfig`] sets extra headers, a proxy, the timeout and the
+    /// retry policy of the requests.  It also accepts [`Middleware`] which is
+    /// called on each request, for example to add auth tokens or to sign the
+    /// requests.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use lancedb::connection::ClientConfig;
+    /// # async fn doctest_helper() -> lancedb::Result<()> {
+    /// let db = lancedb::connect("db://my-database")
+    ///     .api_key("sk_...")
+    ///     .region("us-east-1")
+    ///     .client_config(
+    ///         ClientConfig::default()
+    ///             .header("x-trace-id", "1234")
+    ///             .timeout(Duration::from_secs(60)),
+    ///     )
+    ///     .execute()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// This only affects LanceDB Cloud.
+    #[cfg(feature = "remote")]
+    pub fn client_config(mut self, client_config: ClientConfig) -> Self {
+        self.client_config = client_config;
+        self
+    }
+
     /// [`AwsCredential`] to use when connecting to S3.
     #[deprecated(note = "Pass through storage_options instead")]
     pub fn aws_creds(mut self, aws_creds: AwsCredential) -> Self {
@@ -548,8 +1369,73 @@ impl ConnectBuilder {
         self
     }
 
-    /// The interval at which to check for updates from other processes. This
-    /// only affects LanceDB OSS.
+    /// The AWS region of the S3 bucket
+    ///
+    /// This is the same as the storage option `aws_region`.
+    pub fn aws_region(self, region: impl Into<String>) -> Self {
+        self.storage_option("aws_region", region)
+    }
+
+    /// The endpoint of the S3 API, for S3 compatible stores such as MinIO
+    ///
+    /// This is the same as the storage option `aws_endpoint`.
+    pub fn aws_endpoint(self, endpoint: impl Into<String>) -> Self {
+        self.storage_option("aws_endpoint", endpoint)
+    }
+
+    /// Access a public S3 bucket without credentials
+    ///
+    /// The requests are not signed.  This is the same as the storage option
+    /// `aws_skip_signature`.
+    pub fn aws_anonymous(self) -> Self {
+        self.storage_option("aws_skip_signature", "true")
+    }
+
+    /// Get the credentials used to access S3 from `provider`
+    ///
+    /// The provider is asked for new credentials as they expire, so this can be
+    /// used with short-lived credentials, for example one set per tenant.  The
+    /// credentials of the provider take precedence over the credentials set in
+    /// the storage options.
+    pub fn aws_credentials_provider(mut self, provider: AwsCredentialProvider) -> Self {
+        self.aws_credentials = Some(provider);
+        self
+    }
+
+    /// Use the DynamoDB table `table_name` to make concurrent writes to S3 safe
+    ///
+    /// S3 does not support the atomic operations needed to commit a new version
+    /// of a table, so without this concurrent writers can overwrite each other's
+    /// commits.  The DynamoDB table must have a string hash key named `base_uri`
+    /// and a number range key named `version`.  This only applies to `s3://`
+    /// databases.
+    pub fn dynamodb_commit_table(mut self, table_name: impl Into<String>) -> Self {
+        self.dynamodb_commit_table = Some(table_name.into());
+        self
+    }
+
+    /// The path of the service account file used to access Google Cloud Storage
+    ///
+    /// This is the same as the storage option `google_service_account`.
+    pub fn google_service_account(self, path: impl Into<String>) -> Self {
+        self.storage_option("google_service_account", path)
+    }
+
+    /// The name and access key of the Azure storage account
+    ///
+    /// These are the same as the storage options `azure_storage_account_name`
+    /// and `azure_storage_account_key`.
+    pub fn azure_storage_account(
+        self,
+        account_name: impl Into<String>,
+        access_key: impl Into<String>,
+    ) -> Self {
+        self.storage_option("azure_storage_account_name", account_name)
+            .storage_option("azure_storage_account_key", access_key)
+    }
+
+    /// The interval at which to check for updates from other processes. This
+    /// only affects LanceDB OSS.
     ///
     /// If left unset, consistency is not checked. For maximum read
     /// performance, this is the default. For strong consistency, set this to
@@ -571,8 +1457,373 @@ impl ConnectBuilder {
         self
     }
 
+    /// Provide a custom [`EmbeddingRegistry`] for the connection
+    ///
+    /// By default each connection has its own, empty, [`MemoryRegistry`].
+    pub fn embedding_registry(mut self, registry: Arc<dyn EmbeddingRegistry>) -> Self {
+        self.embedding_registry = Some(registry);
+        self
+    }
+
+    /// Enforce `quota` on the tables whose name starts with `prefix`
+    ///
+    /// The limits of the quota apply to all of these tables together.  Use an
+    /// empty prefix to limit the whole database.  Several quotas can be added
+    /// and a table can be subject to more than one of them.  Writes exceeding a
+    /// quota fail with [`Error::QuotaExceeded`].  See [`crate::quota`] for how
+    /// the usage is measured.
+    ///
+    /// This only affects LanceDB OSS.
+    pub fn quota(mut self, prefix: impl Into<String>, quota: Quota) -> Self {
+        self.quotas.push((prefix.into(), quota));
+        self
+    }
+
+    /// Write checksums of the files of the tables and, optionally, verify them
+    ///
+    /// Checksums detect files that were silently corrupted by the storage, which
+    /// could otherwise produce wrong results.  See [`crate::io::checksum`] for
+    /// which files are checksummed.  Use [`Table::verify_checksums`] to check a
+    /// whole table.
+    ///
+    /// By default checksums are disabled.  This only affects LanceDB OSS.
+    pub fn checksums(mut self, mode: ChecksumMode) -> Self {
+        self.checksums = mode;
+        self
+    }
+
+    /// The number of times a write that conflicts with a concurrent writer is retried
+    ///
+    /// Concurrent writes that are compatible, such as appends, are always
+    /// committed.  Writes that are not, for example deletes or updates of the same
+    /// rows, are run again on the latest version of the table, up to this number
+    /// of times.  Each conflict is logged as a warning.  When the retries are
+    /// exhausted the write fails with [`Error::CommitConflict`].  Adds and merge
+    /// inserts consume their data and so are not retried, unless an add sets
+    /// [`crate::table::WriteOptions::max_commit_retries`].
+    ///
+    /// The default is 5.  This only affects LanceDB OSS.
+    pub fn commit_retries(mut self, commit_retries: u32) -> Self {
+        self.commit_retries = commit_retries;
+        self
+    }
+
+    /// How long to wait before retrying a conflicting commit
+    ///
+    /// The default waits 10ms before the first retry, doubling the wait after
+    /// each retry up to 1s, with random jitter.  Use [`CommitBackoff::none`] to
+    /// retry right away.
+    ///
+    /// This only affects LanceDB OSS.
+    pub fn commit_backoff(mut self, commit_backoff: CommitBackoff) -> Self {
+        self.commit_backoff = commit_backoff;
+        self
+    }
+
+    /// Compact the small fragments of a table when an add leaves it with too many
+    ///
+    /// Each add creates new fragments, so applications making frequent small
+    /// adds should either call [`Table::optimize`] regularly or enable this.
+    /// The compaction runs before the add returns and its work is bounded, see
+    /// [`crate::table::AutoCompaction`].  The default is to never compact
+    /// automatically.
+    ///
+    /// This only affects LanceDB OSS.
+    pub fn auto_compaction(mut self, auto_compaction: AutoCompaction) -> Self {
+        self.auto_compaction = Some(auto_compaction);
+        self
+    }
+
+    /// Index the rows added to a table when an add leaves an index with too
+    /// many unindexed rows
+    ///
+    /// Searches scan the rows which are not covered by an index, so their
+    /// latency grows as rows are added until [`Table::optimize_indices`] is
+    /// called.  With this the indices are optimized before the add returns, see
+    /// [`crate::table::AutoIndex`].  The default is to never optimize the indices
+    /// automatically.
+    ///
+    /// This only affects LanceDB OSS.
+    pub fn auto_index(mut self, auto_index: AutoIndex) -> Self {
+        self.auto_index = Some(auto_index);
+        self
+    }
+
+    /// Retry the queries failing because the version read is no longer available
+    ///
+    /// A table reads the version it last loaded until it is refreshed, so once
+    /// the files of that version are removed by [`crate::table::OptimizeAction::Prune`]
+    /// its queries fail with [`Error::StaleVersion`].  If this is enabled such a
+    /// query refreshes the table to the latest version and runs again, once.
+    /// The default is to return the error.
+    ///
+    /// This only affects LanceDB OSS.
+    pub fn retry_stale_queries(mut self, retry_stale_queries: bool) -> Self {
+        self.retry_stale_queries = retry_stale_queries;
+        self
+    }
+
+    /// Call `hook` before and after each commit to the tables of the connection
+    ///
+    /// This can be called several times, the hooks are called in the order they
+    /// were added.  See [`crate::table::hooks`] for when the hooks are called.
+    ///
+    /// This only affects LanceDB OSS.
+    pub fn commit_hook(mut self, hook: Arc<dyn CommitHook>) -> Self {
+        self.commit_hooks.push(hook);
+        self
+    }
+
+    /// Call `callback` after each commit to the tables of the connection
+    ///
+    /// The callback gets the table, the operation, the new version and the
+    /// number of rows of the table, see [`crate::table::hooks::CommitSummary`].
+    /// The rows are counted before and after each write for the callback.
+    /// It is called like the hooks added with [`Self::commit_hook`], in the
+    /// order they were added, and should return quickly since the write waits
+    /// for it.
+    ///
+    /// This only affects LanceDB OSS.
+    pub fn on_commit(self, callback: impl Fn(&CommitSummary) + Send + Sync + 'static) -> Self {
+        self.commit_hook(Arc::new(OnCommit::new(callback)))
+    }
+
+    /// Hold `lock` while committing each version of the tables of the connection
+    ///
+    /// This coordinates several processes writing to the same tables, see
+    /// [`crate::io::commit_lock`].  [`crate::io::commit_lock::FileCommitLock`]
+    /// can be used for local tables.  This cannot be combined with
+    /// [`Self::dynamodb_commit_table`].
+    ///
+    /// This only affects LanceDB OSS.
+    pub fn commit_lock(mut self, lock: Arc<dyn CommitLock>) -> Self {
+        self.commit_lock = Some(lock);
+        self
+    }
+
+    /// Read the index files, and the metadata of the data files, through `backend`
+    ///
+    /// A backend shared by several processes, such as one backed by Redis, lets
+    /// them share a warm cache of the indices instead of each reading them from
+    /// the object store.  See [`crate::io::cache`] for more details.
+    ///
+    /// This only affects LanceDB OSS.
+    pub fn cache_backend(mut self, backend: Arc<dyn CacheBackend>) -> Self {
+        self.cache_backend = Some(backend);
+        self
+    }
+
+    /// Cache the reads of the index files, and of the metadata of the data files,
+    /// in memory and optionally on a local disk
+    ///
+    /// This is a [`Self::cache_backend`] private to the process, see
+    /// [`crate::io::cache::TieredCache`].  The disk tier is kept when the process
+    /// restarts, which avoids reading the indices from the object store again
+    /// after a cold start.  This cannot be combined with [`Self::cache_backend`].
+    ///
+    /// This only affects LanceDB OSS, and tables in object stores such as S3.
+    pub fn read_cache(mut self, options: ReadCacheOptions) -> Self {
+        self.read_cache = Some(options);
+        self
+    }
+
+    /// Encrypt the data and index files of the tables with the keys of `provider`
+    ///
+    /// The files are encrypted with AES-256-GCM as they are written and
+    /// decrypted as they are read, so the vectors and the values of the rows are
+    /// never stored in plaintext.  See [`crate::io::encryption`] for which files
+    /// are encrypted and how the keys are rotated.  A database must always be
+    /// opened with the same keys: its files cannot be read without them.
+    ///
+    /// This only affects LanceDB OSS.
+    pub fn encryption(mut self, provider: Arc<dyn KeyProvider>) -> Self {
+        self.encryption = Some(provider);
+        self
+    }
+
+    /// The backend the reads of the tables are cached in, if any
+    fn read_cache_backend(&self) -> Result<Option<Arc<dyn CacheBackend>>> {
+        match (&self.cache_backend, &self.read_cache) {
+            (Some(_), Some(_)) => Err(Error::InvalidInput {
+                message: "a read cache cannot be combined with a cache backend".to_string(),
+            }),
+            (Some(backend), None) => Ok(Some(backend.clone())),
+            (None, Some(options)) => Ok(Some(Arc::new(TieredCache::new(options)?))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// The maximum number of queries running at once on the tables of the connection
+    ///
+    /// A query runs from the moment it is executed until its results are consumed
+    /// or dropped.  The queries beyond the limit wait for a running query to
+    /// finish.  See [`crate::query::admission`] for more details.
+    ///
+    /// By default the number of queries is not limited.  This only affects
+    /// LanceDB OSS.
+    pub fn max_concurrent_queries(mut self, max_concurrent_queries: usize) -> Self {
+        self.max_concurrent_queries = Some(max_concurrent_queries);
+        self
+    }
+
+    /// The maximum number of queries waiting to run
+    ///
+    /// Queries beyond this limit fail with [`Error::TooManyQueries`] instead of
+    /// waiting.  This only applies with [`Self::max_concurrent_queries`].  By
+    /// default the queue is not limited.
+    pub fn max_queued_queries(mut self, max_queued_queries: usize) -> Self {
+        self.max_queued_queries = Some(max_queued_queries);
+        self
+    }
+
+    /// The maximum number of requests to the object store running at once
+    ///
+    /// The limit is shared by all of the tables of the connection, so a service
+    /// cannot saturate its network however many queries it runs.  The requests
+    /// beyond the limit wait for a running request to complete.  See
+    /// [`crate::io::limit`] for more details.
+    ///
+    /// By default the requests are not limited.  This only affects LanceDB OSS,
+    /// and only the writes of local tables.
+    pub fn max_concurrent_requests(mut self, max_concurrent_requests: usize) -> Self {
+        self.max_concurrent_requests = Some(max_concurrent_requests);
+        self
+    }
+
+    /// The default number of batches a query reads ahead of its consumer
+    ///
+    /// This applies to the queries that do not set
+    /// [`QueryExecutionOptions::batch_readahead`](crate::query::QueryExecutionOptions::batch_readahead).
+    /// By default this is chosen by Lance based on the number of CPUs.  This
+    /// only affects LanceDB OSS.
+    pub fn batch_readahead(mut self, batch_readahead: usize) -> Self {
+        self.batch_readahead = Some(batch_readahead);
+        self
+    }
+
+    /// The default number of fragments a query reads at the same time
+    ///
+    /// This applies to the queries that do not set
+    /// [`QueryExecutionOptions::fragment_readahead`](crate::query::QueryExecutionOptions::fragment_readahead).
+    /// By default this is chosen by Lance based on the number of CPUs.  This
+    /// only affects LanceDB OSS.
+    pub fn fragment_readahead(mut self, fragment_readahead: usize) -> Self {
+        self.fragment_readahead = Some(fragment_readahead);
+        self
+    }
+
+    /// A soft limit on the bytes read ahead by all of the queries of the connection
+    ///
+    /// Each query reserves the memory of the batches it reads ahead, and its
+    /// read-ahead is reduced to what is left of the budget, so that the queries
+    /// of a service stay within the memory of its container.  A query always
+    /// reads at least one batch ahead, so the budget can be exceeded.  See
+    /// [`crate::query::budget`] for more details.
+    ///
+    /// By default the memory is not limited.  This only affects LanceDB OSS.
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// The limits of the scans of the queries of the connection
+    fn scan_limits(&self) -> ScanLimits {
+        ScanLimits {
+            batch_readahead: self.batch_readahead,
+            fragment_readahead: self.fragment_readahead,
+            memory_budget: self
+                .memory_budget
+                .map(|bytes| Arc::new(MemoryBudget::new(bytes))),
+        }
+    }
+
+    /// Cache the results of the queries of the tables in memory
+    ///
+    /// A query identical to a previous query on the same version of the table
+    /// returns the cached results instead of reading the table.  The cache is
+    /// shared by the tables of the connection, see [`crate::query::cache`] for
+    /// more details.  The default is to never cache the results.
+    ///
+    /// This only affects LanceDB OSS.
+    pub fn query_cache(mut self, options: QueryCacheOptions) -> Self {
+        self.query_cache = Some(options);
+        self
+    }
+
+    /// The admission control of the queries, if their number is limited
+    fn query_admission(&self) -> Option<Arc<QueryAdmission>> {
+        self.max_concurrent_queries
+            .map(|max| Arc::new(QueryAdmission::new(max, self.max_queued_queries)))
+    }
+
     #[cfg(feature = "remote")]
     fn execute_remote(self) -> Result<Connection> {
+        if !self.quotas.is_empty() {
+            return Err(Error::NotSupported {
+                message: "quotas are not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if self.checksums != ChecksumMode::Disabled {
+            return Err(Error::NotSupported {
+                message: "checksums are not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if !self.commit_hooks.is_empty() {
+            return Err(Error::NotSupported {
+                message: "commit hooks are not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if self.auto_compaction.is_some() {
+            return Err(Error::NotSupported {
+                message: "automatic compaction is not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if self.auto_index.is_some() {
+            return Err(Error::NotSupported {
+                message: "automatic indexing is not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if self.retry_stale_queries {
+            return Err(Error::NotSupported {
+                message: "retrying stale queries is not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if self.commit_lock.is_some() {
+            return Err(Error::NotSupported {
+                message: "commit locks are not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if self.cache_backend.is_some() || self.read_cache.is_some() {
+            return Err(Error::NotSupported {
+                message: "cache backends are not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if self.encryption.is_some() {
+            return Err(Error::NotSupported {
+                message: "client-side encryption is not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if self.max_concurrent_requests.is_some()
+            || self.batch_readahead.is_some()
+            || self.fragment_readahead.is_some()
+            || self.memory_budget.is_some()
+        {
+            return Err(Error::NotSupported {
+                message: "resource limits are not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if self.max_concurrent_queries.is_some() {
+            return Err(Error::NotSupported {
+                message: "limiting the concurrent queries is not supported by LanceDB Cloud"
+                    .to_string(),
+            });
+        }
+        if self.query_cache.is_some() {
+            return Err(Error::NotSupported {
+                message: "caching query results is not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        let embedding_registry = self.default_embedding_registry();
         let region = self.region.ok_or_else(|| Error::InvalidInput {
             message: "A region is required when connecting to LanceDb Cloud".to_string(),
         })?;
@@ -584,6 +1835,8 @@ impl ConnectBuilder {
             &api_key,
             &region,
             self.host_override,
+            self.client_config,
+            embedding_registry,
         )?);
         Ok(Connection {
             internal,
@@ -599,17 +1852,37 @@ impl ConnectBuilder {
         })
     }
 
+    fn default_embedding_registry(&self) -> Arc<dyn EmbeddingRegistry> {
+        self.embedding_registry
+            .clone()
+            .unwrap_or_else(|| Arc::new(MemoryRegistry::new()))
+    }
+
     /// Establishes a connection to the database
     pub async fn execute(self) -> Result<Connection> {
-        if self.uri.starts_with("db") {
-            self.execute_remote()
-        } else {
-            let internal = Arc::new(Database::connect_with_options(&self).await?);
-            Ok(Connection {
-                internal,
-                uri: self.uri,
-            })
-        }
+        let span = OperationSpan::connect(&self.uri);
+        span.run(async move {
+            if self.uri.starts_with("db") {
+                self.execute_remote()
+            } else {
+                let cache_backend = self.read_cache_backend()?;
+                let internal = Arc::new(
+                    Database::connect_with_options(&self)
+                        .await?
+                        .with_request_limit(self.max_concurrent_requests)
+                        .with_checksums(self.checksums)
+                        .with_cache_backend(cache_backend)
+                        .with_encryption(self.encryption.clone())?
+                        .with_quotas(self.quotas)
+                        .await?,
+                );
+                Ok(Connection {
+                    internal,
+                    uri: self.uri,
+                })
+            }
+        })
+        .await
     }
 }
 
@@ -638,6 +1911,99 @@ struct Database {
 
     // Storage options to be inherited by tables created from this connection
     storage_options: HashMap<String, String>,
+
+    // The S3 credentials provider inherited by the tables of this connection
+    aws_credentials: Option<AwsCredentialProvider>,
+
+    // Temporary tables, which are removed when the connection is dropped
+    temp_tables: TempTables,
+
+    embedding_registry: Arc<dyn EmbeddingRegistry>,
+
+    quotas: Option<Arc<Quotas>>,
+
+    commit_retries: u32,
+
+    commit_backoff: CommitBackoff,
+
+    auto_compaction: Option<AutoCompaction>,
+
+    auto_index: Option<AutoIndex>,
+
+    retry_stale_queries: bool,
+
+    commit_hooks: Vec<Arc<dyn CommitHook>>,
+
+    query_admission: Option<Arc<QueryAdmission>>,
+
+    scan_limits: ScanLimits,
+
+    query_cache: Option<Arc<QueryCache>>,
+
+    metrics: Arc<MetricsRegistry>,
+
+    // The lock held while committing to the tables
+    commit_lock: Option<Arc<dyn CommitLock>>,
+}
+
+/// The temporary tables created by a connection
+///
+/// These live in a directory under the system temp dir, which is created on
+/// first use and removed when the connection is dropped.
+#[derive(Debug, Default)]
+struct TempTables {
+    dir: Mutex<Option<PathBuf>>,
+    names: Mutex<HashSet<String>>,
+}
+
+impl TempTables {
+    fn dir(&self) -> Result<PathBuf> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let mut dir = self.dir.lock()?;
+        if let Some(dir) = dir.as_ref() {
+            return Ok(dir.clone());
+        }
+        let path = std::env::temp_dir().join(format!(
+            "lancedb-temp-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        create_dir_all(&path).context(CreateDirSnafu {
+            path: path.to_string_lossy(),
+        })?;
+        *dir = Some(path.clone());
+        Ok(path)
+    }
+
+    fn contains(&self, name: &str) -> Result<bool> {
+        Ok(self.names.lock()?.contains(name))
+    }
+
+    fn table_uri(&self, name: &str) -> Result<String> {
+        validate_table_name(name)?;
+        let path = self
+            .dir()?
+            .join(format!("{}.{}", name, LANCE_FILE_EXTENSION));
+        Ok(path
+            .to_str()
+            .context(InvalidTableNameSnafu {
+                name,
+                reason: "Name is not valid URL",
+            })?
+            .to_string())
+    }
+}
+
+impl Drop for TempTables {
+    fn drop(&mut self) {
+        if let Ok(dir) = self.dir.get_mut() {
+            if let Some(dir) = dir.take() {
+                if let Err(err) = std::fs::remove_dir_all(&dir) {
+                    log::warn!("Failed to remove temporary tables in {:?}: {}", dir, err);
+                }
+            }
+        }
+    }
 }
 
 impl std::fmt::Display for Database {
@@ -659,7 +2025,15 @@ impl std::fmt::Display for Database {
 }
 
 const LANCE_EXTENSION: &str = "lance";
+/// The directory holding the definitions (and materialized rows) of views
+const VIEWS_DIR: &str = "_views";
+
+/// The empty file created in the directory of a namespace
+const NAMESPACE_MARKER: &str = ".namespace";
 const ENGINE: &str = "engine";
+/// The engine, and the query parameter naming its table, of the commits through DynamoDB
+const DYNAMODB_ENGINE: &str = "ddb";
+const DYNAMODB_TABLE: &str = "ddbTableName";
 const MIRRORED_STORE: &str = "mirroredStore";
 
 /// A connection to LanceDB
@@ -671,8 +2045,9 @@ impl Database {
         // TODO: pass params regardless of OS
         match parse_res {
             Ok(url) if url.scheme().len() == 1 && cfg!(windows) => {
-                Self::open_path(uri, options.read_consistency_interval).await
+                Self::open_path(uri, options).await
             }
+            Ok(url) if url.scheme() == MEMORY_SCHEME => Self::open_memory(options).await,
             Ok(mut url) => {
                 // iter thru the query params and extract the commit store param
                 let mut engine = None;
@@ -681,6 +2056,25 @@ impl Database {
 
                 // WARNING: specifying engine is NOT a publicly supported feature in lancedb yet
                 // THE API WILL CHANGE
+                if let Some(table_name) = &options.dynamodb_commit_table {
+                    if options.commit_lock.is_some() {
+                        return Err(Error::InvalidInput {
+                            message:
+                                "a commit lock cannot be combined with a DynamoDB commit table"
+                                    .to_string(),
+                        });
+                    }
+                    if url.scheme() != "s3" {
+                        return Err(Error::InvalidInput {
+                            message: format!(
+                                "a DynamoDB commit table can only be used with an s3:// database, not '{}'",
+                                uri
+                            ),
+                        });
+                    }
+                    engine = Some(DYNAMODB_ENGINE.to_string());
+                    filtered_querys.push((DYNAMODB_TABLE.to_string(), table_name.clone()));
+                }
                 for (key, value) in url.query_pairs() {
                     if key == ENGINE {
                         engine = Some(value.to_string());
@@ -724,6 +2118,7 @@ impl Database {
                 let storage_options = options.storage_options.clone();
                 let os_params = ObjectStoreParams {
                     storage_options: Some(storage_options.clone()),
+                    aws_credentials: options.aws_credentials.clone(),
                     ..Default::default()
                 };
                 let (object_store, base_path) =
@@ -749,16 +2144,30 @@ impl Database {
                     store_wrapper: write_store_wrapper,
                     read_consistency_interval: options.read_consistency_interval,
                     storage_options,
+                    aws_credentials: options.aws_credentials.clone(),
+                    temp_tables: TempTables::default(),
+                    embedding_registry: options.default_embedding_registry(),
+                    quotas: None,
+                    commit_retries: options.commit_retries,
+                    commit_backoff: options.commit_backoff,
+                    auto_compaction: options.auto_compaction,
+                    auto_index: options.auto_index,
+                    retry_stale_queries: options.retry_stale_queries,
+                    commit_hooks: options.commit_hooks.clone(),
+                    query_admission: options.query_admission(),
+                    scan_limits: options.scan_limits(),
+                    query_cache: options
+                        .query_cache
+                        .map(|cache| Arc::new(QueryCache::new(cache))),
+                    metrics: Arc::default(),
+                    commit_lock: options.commit_lock.clone(),
                 })
             }
-            Err(_) => Self::open_path(uri, options.read_consistency_interval).await,
+            Err(_) => Self::open_path(uri, options).await,
         }
     }
 
-    async fn open_path(
-        path: &str,
-        read_consistency_interval: Option<std::time::Duration>,
-    ) -> Result<Self> {
+    async fn open_path(path: &str, options: &ConnectBuilder) -> Result<Self> {
         let (object_store, base_path) = ObjectStore::from_uri(path).await?;
         if object_store.is_local() {
             Self::try_create_dir(path).context(CreateDirSnafu { path })?;
@@ -769,11 +2178,39 @@ impl Database {
             base_path,
             object_store,
             store_wrapper: None,
-            read_consistency_interval,
+            read_consistency_interval: options.read_consistency_interval,
             storage_options: HashMap::new(),
+            aws_credentials: None,
+            temp_tables: TempTables::default(),
+            embedding_registry: options.default_embedding_registry(),
+            quotas: None,
+            commit_retries: options.commit_retries,
+            commit_backoff: options.commit_backoff,
+            auto_compaction: options.auto_compaction,
+            auto_index: options.auto_index,
+            retry_stale_queries: options.retry_stale_queries,
+            commit_hooks: options.commit_hooks.clone(),
+            query_admission: options.query_admission(),
+            scan_limits: options.scan_limits(),
+            query_cache: options
+                .query_cache
+                .map(|cache| Arc::new(QueryCache::new(cache))),
+            metrics: Arc::default(),
+            commit_lock: options.commit_lock.clone(),
         })
     }
 
+    /// Open a new, empty, in-memory database
+    ///
+    /// Each connection has its own database, see [`crate::io::memory`].
+    async fn open_memory(options: &ConnectBuilder) -> Result<Self> {
+        let uri = format!("{}:///", MEMORY_SCHEME);
+        let mut database = Self::open_path(&uri, options).await?;
+        let store = database.object_store.inner.clone();
+        database.store_wrapper = Some(Arc::new(InMemoryObjectStoreWrapper::new(store)));
+        Ok(database)
+    }
+
     /// Try to create a local directory to store the lancedb dataset
     fn try_create_dir(path: &str) -> core::result::Result<(), std::io::Error> {
         let path = Path::new(path);
@@ -783,12 +2220,117 @@ impl Database {
         Ok(())
     }
 
+    /// The handler committing the versions of the table `name` under the commit
+    /// lock of the connection, if it has one
+    fn commit_handler(&self, name: &str, table_uri: &str) -> Option<Arc<dyn CommitHandler>> {
+        let lock = self.commit_lock.clone()?;
+        let table_dir = self
+            .base_path
+            .child(format!("{}.{}", name, LANCE_FILE_EXTENSION));
+        Some(commit_lock::commit_handler(
+            lock,
+            table_uri.to_string(),
+            self.object_store.inner.clone(),
+            &table_dir,
+        ))
+    }
+
+    /// The directory of a table in the object store
+    fn table_dir(&self, name: &str) -> Result<object_store::path::Path> {
+        validate_table_name(name)?;
+        if self.temp_tables.contains(name)? {
+            return Err(Error::NotSupported {
+                message: format!("temporary table {} cannot be renamed or copied", name),
+            });
+        }
+        Ok(self
+            .base_path
+            .child(format!("{}.{}", name, LANCE_FILE_EXTENSION)))
+    }
+
+    /// The files of the table in `dir`, empty if the table does not exist
+    async fn table_files(
+        &self,
+        dir: &object_store::path::Path,
+    ) -> Result<Vec<object_store::path::Path>> {
+        Ok(self
+            .object_store
+            .inner
+            .list(Some(dir))
+            .map_ok(|meta| meta.location)
+            .try_collect()
+            .await?)
+    }
+
+    /// The directories of the tables `source` and `target`, and the files of
+    /// `source`, checking that `source` exists and `target` does not
+    async fn prepare_copy(
+        &self,
+        source: &str,
+        target: &str,
+    ) -> Result<(
+        object_store::path::Path,
+        object_store::path::Path,
+        Vec<object_store::path::Path>,
+    )> {
+        let source_dir = self.table_dir(source)?;
+        let target_dir = self.table_dir(target)?;
+        let files = self.table_files(&source_dir).await?;
+        if files.is_empty() {
+            return Err(Error::TableNotFound {
+                name: source.to_string(),
+            });
+        }
+        if !self.table_files(&target_dir).await?.is_empty() {
+            return Err(Error::TableAlreadyExists {
+                name: target.to_string(),
+            });
+        }
+        Ok((source_dir, target_dir, files))
+    }
+
+    /// Copy `files` from `source_dir` to `target_dir`
+    ///
+    /// The local object store copies files by hard linking them.
+    async fn copy_files(
+        &self,
+        source_dir: &object_store::path::Path,
+        target_dir: &object_store::path::Path,
+        files: Vec<object_store::path::Path>,
+    ) -> Result<()> {
+        futures::stream::iter(files.into_iter().map(Ok))
+            .try_for_each_concurrent(COPY_CONCURRENCY, |file| async move {
+                let relative = file
+                    .prefix_match(source_dir)
+                    .expect("listed files are under the table directory");
+                let target = target_dir.parts().chain(relative).collect();
+                self.object_store.inner.copy(&file, &target).await?;
+                Result::Ok(())
+            })
+            .await
+    }
+
     /// Get the URI of a table in the database.
     fn table_uri(&self, name: &str) -> Result<String> {
+        self.dataset_uri(Path::new(&self.uri), name)
+    }
+
+    /// Get the URI of the rows of a materialized view in the database.
+    fn view_uri(&self, name: &str) -> Result<String> {
+        self.dataset_uri(&Path::new(&self.uri).join(VIEWS_DIR), name)
+    }
+
+    /// The location of the definition of a view
+    fn view_definition_path(&self, name: &str) -> object_store::path::Path {
+        self.base_path
+            .child(VIEWS_DIR)
+            .child(format!("{}.json", name))
+    }
+
+    fn dataset_uri(&self, dir: &Path, name: &str) -> Result<String> {
         validate_table_name(name)?;
 
-        let path = Path::new(&self.uri);
-        let table_uri = path.join(format!("{}.{}", name, LANCE_FILE_EXTENSION));
+        let table_uri = dir.join(format!("{}.{}", name, LANCE_FILE_EXTENSION));
 
         let mut uri = table_uri
             .as_path()
@@ -807,36 +2349,206 @@ impl Database {
 
         Ok(uri)
     }
-}
 
-#[async_trait::async_trait]
-impl ConnectionInternal for Database {
-    async fn table_names(&self, options: TableNamesBuilder) -> Result<Vec<String>> {
-        let mut f = self
-            .object_store
-            .read_dir(self.base_path.clone())
-            .await?
-            .iter()
-            .map(Path::new)
-            .filter(|path| {
-                let is_lance = path
-                    .extension()
-                    .and_then(|e| e.to_str())
-                    .map(|e| e == LANCE_EXTENSION);
-                is_lance.unwrap_or(false)
-            })
-            .filter_map(|p| p.file_stem().and_then(|s| s.to_str().map(String::from)))
-            .collect::<Vec<String>>();
-        f.sort();
-        if let Some(start_after) = options.start_after {
-            let index = f
-                .iter()
-                .position(|name| name.as_str() > start_after.as_str())
-                .unwrap_or(f.len());
-            f.drain(0..index);
-        }
-        if let Some(limit) = options.limit {
-            f.truncate(limit as usize);
+    fn read_params(&self) -> ReadParams {
+        ReadParams {
+            store_options: Some(ObjectStoreParams {
+                storage_options: Some(self.storage_options.clone()),
+                aws_credentials: self.aws_credentials.clone(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Run at most `max_requests` requests to the object store at once
+    fn with_request_limit(mut self, max_requests: Option<usize>) -> Self {
+        if let Some(max_requests) = max_requests {
+            let wrapper = LimitedObjectStoreWrapper::new(max_requests, self.store_wrapper.take());
+            self.store_wrapper = Some(Arc::new(wrapper));
+        }
+        self
+    }
+
+    /// Write, and optionally verify, checksums when accessing the tables
+    fn with_checksums(mut self, mode: ChecksumMode) -> Self {
+        if mode != ChecksumMode::Disabled {
+            let wrapper = ChecksumObjectStoreWrapper::new(
+                mode == ChecksumMode::Verify,
+                self.store_wrapper.take(),
+            );
+            self.store_wrapper = Some(Arc::new(wrapper));
+        }
+        self
+    }
+
+    /// Read the immutable files of the tables through `backend`
+    fn with_cache_backend(mut self, backend: Option<Arc<dyn CacheBackend>>) -> Self {
+        if let Some(backend) = backend {
+            let wrapper = CachingObjectStoreWrapper::new(
+                backend,
+                self.store_wrapper.take(),
+                Some(self.metrics.clone()),
+            );
+            self.store_wrapper = Some(Arc::new(wrapper));
+        }
+        self
+    }
+
+    /// Encrypt the files of the tables with the keys of `provider`
+    ///
+    /// The encryption is applied last, so that the caches and the checksums
+    /// only see encrypted bytes.
+    fn with_encryption(mut self, provider: Option<Arc<dyn KeyProvider>>) -> Result<Self> {
+        let Some(provider) = provider else {
+            return Ok(self);
+        };
+        validate_provider(provider.as_ref())?;
+        let wrapper = EncryptingObjectStoreWrapper::new(provider, self.store_wrapper.take());
+        self.store_wrapper = Some(Arc::new(wrapper));
+        Ok(self)
+    }
+
+    /// The database of the namespace `name`, stored in its directory
+    fn open_namespace(&self, name: &str) -> Result<Self> {
+        validate_namespace_name(name)?;
+        let uri = Path::new(&self.uri).join(name);
+        let uri = uri.to_str().ok_or_else(|| Error::InvalidInput {
+            message: format!("namespace {} is not a valid URL", name),
+        })?;
+        Ok(Self {
+            object_store: self.object_store.clone(),
+            query_string: self.query_string.clone(),
+            uri: uri.to_string(),
+            base_path: self.base_path.child(name),
+            store_wrapper: self.store_wrapper.clone(),
+            read_consistency_interval: self.read_consistency_interval,
+            storage_options: self.storage_options.clone(),
+            aws_credentials: self.aws_credentials.clone(),
+            temp_tables: TempTables::default(),
+            embedding_registry: self.embedding_registry.clone(),
+            // The quotas are measured on the tables at the root of the database
+            quotas: None,
+            commit_retries: self.commit_retries,
+            commit_backoff: self.commit_backoff,
+            auto_compaction: self.auto_compaction,
+            auto_index: self.auto_index,
+            retry_stale_queries: self.retry_stale_queries,
+            commit_hooks: self.commit_hooks.clone(),
+            query_admission: self.query_admission.clone(),
+            scan_limits: self.scan_limits.clone(),
+            query_cache: self.query_cache.clone(),
+            metrics: self.metrics.clone(),
+            commit_lock: self.commit_lock.clone(),
+        })
+    }
+
+    /// The names of the tables in the database, in no particular order
+    async fn list_tables(&self) -> Result<Vec<String>> {
+        Ok(self
+            .object_store
+            .read_dir(self.base_path.clone())
+            .await?
+            .iter()
+            .map(Path::new)
+            .filter(|path| {
+                let is_lance = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(|e| e == LANCE_EXTENSION);
+                is_lance.unwrap_or(false)
+            })
+            .filter_map(|p| p.file_stem().and_then(|s| s.to_str().map(String::from)))
+            .collect())
+    }
+
+    /// Enforce `quotas`, measuring the current usage of the tables they apply to
+    async fn with_quotas(mut self, quotas: Vec<(String, Quota)>) -> Result<Self> {
+        if quotas.is_empty() {
+            return Ok(self);
+        }
+        let quotas = Quotas::new(
+            quotas,
+            self.object_store.inner.clone(),
+            self.base_path.clone(),
+        );
+        for name in self.list_tables().await? {
+            if !quotas.applies_to(&name) {
+                continue;
+            }
+            let table = NativeTable::open_with_params(
+                &self.table_uri(&name)?,
+                &name,
+                self.store_wrapper.clone(),
+                Some(self.read_params()),
+                None,
+            )
+            .await?;
+            let usage = quotas.measure(&name, &*table.dataset.get().await?).await?;
+            quotas.set_usage(&name, usage)?;
+        }
+        self.quotas = Some(Arc::new(quotas));
+        Ok(self)
+    }
+
+    /// Open the table a view reads from
+    async fn open_base_table(&self, name: &str) -> Result<Arc<dyn TableInternal>> {
+        let table_uri = if self.temp_tables.contains(name)? {
+            self.temp_tables.table_uri(name)?
+        } else {
+            self.table_uri(name)?
+        };
+        let table = NativeTable::open_with_params(
+            &table_uri,
+            name,
+            self.store_wrapper.clone(),
+            Some(self.read_params()),
+            self.read_consistency_interval,
+        )
+        .await?
+        .with_embedding_registry(self.embedding_registry.clone())
+        .with_commit_retries(self.commit_retries)
+        .with_commit_backoff(self.commit_backoff)
+        .with_auto_compaction(self.auto_compaction)
+        .with_auto_index(self.auto_index)
+        .with_retry_stale_queries(self.retry_stale_queries)
+        .with_commit_hooks(self.commit_hooks.clone())
+        .with_query_admission(self.query_admission.clone())
+        .with_scan_limits(self.scan_limits.clone())
+        .with_query_cache(self.query_cache.clone())
+        .with_metrics(Some(self.metrics.clone()));
+        Ok(Arc::new(table))
+    }
+
+    fn materialized_view(
+        &self,
+        table: NativeTable,
+        name: &str,
+        definition: &ViewDefinition,
+    ) -> Materialized {
+        Materialized {
+            table: Arc::new(table),
+            store: self.object_store.inner.clone(),
+            definition_path: self.view_definition_path(name),
+            definition: tokio::sync::Mutex::new(definition.clone()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ConnectionInternal for Database {
+    async fn table_names(&self, options: TableNamesBuilder) -> Result<Vec<String>> {
+        let mut f = self.list_tables().await?;
+        f.retain(|name| {
+            options.matches(name)
+                && options
+                    .start_after
+                    .as_ref()
+                    .map_or(true, |start_after| name > start_after)
+        });
+        f.sort();
+        if let Some(limit) = options.limit {
+            f.truncate(limit as usize);
         }
         Ok(f)
     }
@@ -846,15 +2558,23 @@ impl ConnectionInternal for Database {
         mut options: CreateTableBuilder<false, NoData>,
         data: Box<dyn RecordBatchReader + Send>,
     ) -> Result<Table> {
-        let table_uri = self.table_uri(&options.name)?;
+        let table_uri = if options.temporary {
+            self.temp_tables.table_uri(&options.name)?
+        } else {
+            self.table_uri(&options.name)?
+        };
 
         // Inherit storage options from the connection
-        let storage_options = options
+        let store_params = options
             .write_options
             .lance_write_params
             .get_or_insert_with(Default::default)
             .store_params
-            .get_or_insert_with(Default::default)
+            .get_or_insert_with(Default::default);
+        if store_params.aws_credentials.is_none() {
+            store_params.aws_credentials = self.aws_credentials.clone();
+        }
+        let storage_options = store_params
             .storage_options
             .get_or_insert_with(Default::default);
         for (key, value) in self.storage_options.iter() {
@@ -867,18 +2587,103 @@ impl ConnectionInternal for Database {
         if matches!(&options.mode, CreateTableMode::Overwrite) {
             write_params.mode = WriteMode::Overwrite;
         }
+        if write_params.commit_handler.is_none() && !options.temporary {
+            write_params.commit_handler = self.commit_handler(&options.name, &table_uri);
+        }
+
+        let data: Box<dyn RecordBatchReader + Send> = if options.embeddings.is_empty() {
+            data
+        } else {
+            let embeddings =
+                embeddings::resolve(self.embedding_registry.as_ref(), options.embeddings.clone())?;
+            Box::new(WithEmbeddings::try_new(data, embeddings)?)
+        };
+        let data = compression::with_compression(data, &options.compression)?;
+        let data =
+            dictionary::with_dictionaries(data, &options.dictionary, options.auto_dictionary)?;
+        let data = blob::with_blobs(data, &options.blobs)?;
+        let data = match &options.primary_key {
+            Some(column) => primary_key::with_primary_key(data, column)?,
+            None => data,
+        };
+        let data = if options.soft_delete {
+            trash::with_soft_delete(data)
+        } else {
+            data
+        };
+        let data = match &options.ttl {
+            Some(ttl) => ttl::with_ttl(data, ttl)?,
+            None => data,
+        };
+        // The initial data of a partitioned table is added once it is created
+        let (data, partitioned) = match &options.partition_by {
+            Some(column) => {
+                let data = partition::with_partition_by(data, column)?;
+                let empty: Box<dyn RecordBatchReader + Send> =
+                    Box::new(RecordBatchIterator::new(vec![], data.schema()));
+                (empty, Some(data))
+            }
+            None => (data, None),
+        };
+        // Temporary tables are not subject to quotas
+        let quotas = self.quotas.clone().filter(|_| !options.temporary);
+        let (data, quota_write) = match &quotas {
+            Some(quotas) => quotas.start_write(&options.name, data, true)?,
+            None => (data, None),
+        };
 
-        match NativeTable::create(
+        let (data, rows_written) = metrics::count_rows(data);
+        let result = NativeTable::create(
             &table_uri,
             &options.name,
             data,
             self.store_wrapper.clone(),
-            Some(write_params),
+            Some(write_params.clone()),
             self.read_consistency_interval,
         )
-        .await
-        {
-            Ok(table) => Ok(Table::new(Arc::new(table))),
+        .await;
+        let result = match (&quotas, quota_write) {
+            (Some(quotas), Some(write)) => quotas.finish_write(write, result),
+            _ => result,
+        };
+        match result {
+            Ok(table) => {
+                if options.temporary {
+                    self.temp_tables.names.lock()?.insert(options.name);
+                }
+                let table = table
+                    .with_embedding_registry(self.embedding_registry.clone())
+                    .with_quotas(quotas)
+                    .with_commit_retries(self.commit_retries)
+                    .with_commit_backoff(self.commit_backoff)
+                    .with_auto_compaction(self.auto_compaction)
+                    .with_auto_index(self.auto_index)
+                    .with_retry_stale_queries(self.retry_stale_queries)
+                    .with_commit_hooks(self.commit_hooks.clone())
+                    .with_query_admission(self.query_admission.clone())
+                    .with_scan_limits(self.scan_limits.clone())
+                    .with_query_cache(self.query_cache.clone())
+                    .with_metrics(Some(self.metrics.clone()));
+                self.metrics
+                    .add_rows_written(rows_written.load(Ordering::Relaxed));
+                table.report_fragments().await;
+                table.update_quota_usage().await;
+                let table = Table::new(Arc::new(table));
+                if let Some(data) = partitioned {
+                    table
+                        .add(data)
+                        .write_options(WriteOptions {
+                            lance_write_params: Some(WriteParams {
+                                mode: WriteMode::Append,
+                                ..write_params
+                            }),
+                            ..Default::default()
+                        })
+                        .execute()
+                        .await?;
+                }
+                Ok(table)
+            }
             Err(Error::TableAlreadyExists { name }) => match options.mode {
                 CreateTableMode::Create => Err(Error::TableAlreadyExists { name }),
                 CreateTableMode::ExistOk(callback) => {
@@ -893,14 +2698,23 @@ impl ConnectionInternal for Database {
     }
 
     async fn do_open_table(&self, mut options: OpenTableBuilder) -> Result<Table> {
-        let table_uri = self.table_uri(&options.name)?;
+        let is_temporary = self.temp_tables.contains(&options.name)?;
+        let table_uri = if is_temporary {
+            self.temp_tables.table_uri(&options.name)?
+        } else {
+            self.table_uri(&options.name)?
+        };
 
         // Inherit storage options from the connection
-        let storage_options = options
+        let store_options = options
             .lance_read_params
             .get_or_insert_with(Default::default)
             .store_options
-            .get_or_insert_with(Default::default)
+            .get_or_insert_with(Default::default);
+        if store_options.aws_credentials.is_none() {
+            store_options.aws_credentials = self.aws_credentials.clone();
+        }
+        let storage_options = store_options
             .storage_options
             .get_or_insert_with(Default::default);
         for (key, value) in self.storage_options.iter() {
@@ -908,6 +2722,12 @@ impl ConnectionInternal for Database {
                 storage_options.insert(key.clone(), value.clone());
             }
         }
+        let read_params = options
+            .lance_read_params
+            .get_or_insert_with(Default::default);
+        if read_params.commit_handler.is_none() && !is_temporary {
+            read_params.commit_handler = self.commit_handler(&options.name, &table_uri);
+        }
 
         let native_table = Arc::new(
             NativeTable::open_with_params(
@@ -917,12 +2737,36 @@ impl ConnectionInternal for Database {
                 options.lance_read_params,
                 self.read_consistency_interval,
             )
-            .await?,
+            .await?
+            .with_embedding_registry(self.embedding_registry.clone())
+            .with_quotas(self.quotas.clone().filter(|_| !is_temporary))
+            .with_commit_retries(self.commit_retries)
+            .with_commit_backoff(self.commit_backoff)
+            .with_auto_compaction(self.auto_compaction)
+            .with_auto_index(self.auto_index)
+            .with_retry_stale_queries(self.retry_stale_queries)
+            .with_commit_hooks(self.commit_hooks.clone())
+            .with_query_admission(self.query_admission.clone())
+            .with_scan_limits(self.scan_limits.clone())
+            .with_query_cache(self.query_cache.clone())
+            .with_metrics(Some(self.metrics.clone())),
         );
+        if let Some(version) = options.version {
+            native_table.checkout(version).await?;
+        }
+        native_table.report_fragments().await;
         Ok(Table::new(native_table))
     }
 
     async fn drop_table(&self, name: &str) -> Result<()> {
+        if self.temp_tables.contains(name)? {
+            let table_uri = self.temp_tables.table_uri(name)?;
+            std::fs::remove_dir_all(table_uri).map_err(|e| Error::Runtime {
+                message: format!("Failed to drop temporary table {}: {}", name, e),
+            })?;
+            self.temp_tables.names.lock()?.remove(name);
+            return Ok(());
+        }
         let dir_name = format!("{}.{}", name, LANCE_EXTENSION);
         let full_path = self.base_path.child(dir_name.clone());
         self.object_store
@@ -936,6 +2780,126 @@ impl ConnectionInternal for Database {
                 },
                 _ => Error::from(err),
             })?;
+        if let Some(quotas) = &self.quotas {
+            quotas.remove_usage(name)?;
+        }
+        self.metrics.remove_table(name);
+        Ok(())
+    }
+
+    async fn rename_table(&self, old_name: &str, new_name: &str) -> Result<()> {
+        let (source_dir, target_dir, files) = self.prepare_copy(old_name, new_name).await?;
+        if self.object_store.is_local() {
+            // The object store path of a local file is its absolute path
+            let root = std::path::Path::new("/");
+            std::fs::rename(
+                root.join(source_dir.as_ref()),
+                root.join(target_dir.as_ref()),
+            )
+            .map_err(|e| Error::Runtime {
+                message: format!("Failed to rename table {}: {}", old_name, e),
+            })?;
+        } else {
+            self.copy_files(&source_dir, &target_dir, files).await?;
+            self.object_store.remove_dir_all(source_dir).await?;
+        }
+        if let Some(quotas) = &self.quotas {
+            quotas.rename_usage(old_name, new_name)?;
+        }
+        Ok(())
+    }
+
+    async fn clone_table(&self, source: &str, target: &str) -> Result<()> {
+        let (source_dir, target_dir, files) = self.prepare_copy(source, target).await?;
+        self.copy_files(&source_dir, &target_dir, files).await
+    }
+
+    async fn unpack(&self, path: &Path, name: &str) -> Result<PackageInfo> {
+        let dir = self.table_dir(name)?;
+        if !self.table_files(&dir).await?.is_empty() {
+            return Err(Error::TableAlreadyExists {
+                name: name.to_string(),
+            });
+        }
+        let unpacked = pack::unpack(path, |file, data| {
+            let location = dir
+                .parts()
+                .chain(object_store::path::Path::from(file.as_str()).parts())
+                .collect();
+            async move {
+                self.object_store.inner.put(&location, data.into()).await?;
+                Ok(())
+            }
+        })
+        .await;
+        if unpacked.is_err() {
+            // Do not leave the files of a partial table behind
+            if let Err(e) = self.object_store.remove_dir_all(dir).await {
+                log::warn!(
+                    "Failed to remove the partially unpacked table {}: {}",
+                    name,
+                    e
+                );
+            }
+        }
+        unpacked
+    }
+
+    async fn import_snapshot(&self, source_uri: &str, name: &str) -> Result<SnapshotInfo> {
+        let dir = self.table_dir(name)?;
+        snapshot::import(
+            name,
+            source_uri,
+            &self.storage_options,
+            &self.object_store,
+            &dir,
+        )
+        .await
+    }
+
+    async fn persist_to(&self, uri: &str) -> Result<()> {
+        let params = ObjectStoreParams {
+            storage_options: Some(self.storage_options.clone()),
+            aws_credentials: self.aws_credentials.clone(),
+            ..Default::default()
+        };
+        let (target, target_dir) = ObjectStore::from_uri_and_params(uri, &params).await?;
+        if target.is_local() {
+            Self::try_create_dir(uri).context(CreateDirSnafu { path: uri })?;
+        }
+        for name in self.list_tables().await? {
+            let dir = target_dir.child(format!("{}.{}", name, LANCE_FILE_EXTENSION));
+            if let Some(Ok(_)) = target.inner.list(Some(&dir)).next().await {
+                return Err(Error::TableAlreadyExists { name });
+            }
+        }
+
+        // The manifests are copied last, so that a table is only visible in the
+        // target once all of its files are there
+        let (manifests, files): (Vec<_>, Vec<_>) = self
+            .table_files(&self.base_path)
+            .await?
+            .into_iter()
+            .partition(|file| {
+                file.parts()
+                    .any(|part| part.as_ref() == VERSIONS_DIR || part.as_ref() == LATEST_MANIFEST)
+            });
+        for files in [files, manifests] {
+            futures::stream::iter(files.into_iter().map(Ok))
+                .try_for_each_concurrent(COPY_CONCURRENCY, |file| {
+                    let (target, target_dir) = (&target, &target_dir);
+                    async move {
+                        let relative = file
+                            .prefix_match(&self.base_path)
+                            .expect("listed files are under the database directory");
+                        let location = target_dir.parts().chain(relative).collect();
+                        let data = self.object_store.inner.get(&file).await?.bytes().await?;
+                        target.inner.put(&location, data).await?;
+                        Result::Ok(())
+                    }
+                })
+                .await?;
+        }
         Ok(())
     }
 
@@ -943,12 +2907,207 @@ impl ConnectionInternal for Database {
         self.object_store
             .remove_dir_all(self.base_path.clone())
             .await?;
+        if let Some(quotas) = &self.quotas {
+            quotas.clear_usage()?;
+        }
+        Ok(())
+    }
+
+    fn namespace(&self, name: &str) -> Result<Arc<dyn ConnectionInternal>> {
+        Ok(Arc::new(self.open_namespace(name)?))
+    }
+
+    async fn list_namespaces(&self) -> Result<Vec<String>> {
+        // The namespaces are the directories which are not tables or metadata
+        let listing = self
+            .object_store
+            .inner
+            .list_with_delimiter(Some(&self.base_path))
+            .await?;
+        let mut names = listing
+            .common_prefixes
+            .iter()
+            .filter_map(|prefix| prefix.filename())
+            .filter(|name| validate_namespace_name(name).is_ok())
+            .map(String::from)
+            .collect::<Vec<_>>();
+        names.sort();
+        Ok(names)
+    }
+
+    async fn create_namespace(&self, name: &str) -> Result<()> {
+        validate_namespace_name(name)?;
+        // Object stores have no empty directories, the marker creates the directory
+        let marker = self.base_path.child(name).child(NAMESPACE_MARKER);
+        self.object_store
+            .inner
+            .put(&marker, bytes::Bytes::new())
+            .await?;
         Ok(())
     }
+
+    async fn drop_namespace(&self, name: &str) -> Result<()> {
+        validate_namespace_name(name)?;
+        if !self.list_namespaces().await?.iter().any(|n| n == name) {
+            return Err(Error::NamespaceNotFound {
+                name: name.to_string(),
+            });
+        }
+        self.object_store
+            .remove_dir_all(self.base_path.child(name))
+            .await?;
+        Ok(())
+    }
+
+    fn embedding_registry(&self) -> &dyn EmbeddingRegistry {
+        self.embedding_registry.as_ref()
+    }
+
+    fn query_metrics(&self) -> Option<QueryMetrics> {
+        self.query_admission
+            .as_ref()
+            .map(|admission| admission.metrics())
+    }
+
+    fn metrics(&self) -> Option<ConnectionMetrics> {
+        Some(self.metrics.snapshot())
+    }
+
+    fn transaction_log(
+        &self,
+    ) -> Result<(Arc<dyn object_store::ObjectStore>, object_store::path::Path)> {
+        Ok((
+            self.object_store.inner.clone(),
+            self.base_path.child(transaction::TRANSACTIONS_DIR),
+        ))
+    }
+
+    async fn do_create_view(&self, options: CreateViewBuilder) -> Result<Table> {
+        validate_table_name(&options.name)?;
+        let definition_path = self.view_definition_path(&options.name);
+        match self.object_store.inner.head(&definition_path).await {
+            Ok(_) => return Err(Error::TableAlreadyExists { name: options.name }),
+            Err(object_store::Error::NotFound { .. }) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        // Check the view is valid before it is stored
+        let base = self.open_base_table(&options.base_table).await?;
+        if let Some(columns) = &options.columns {
+            let schema = base.schema().await?;
+            if let Some(column) = columns.iter().find(|c| schema.index_of(c).is_err()) {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "the column '{}' does not exist in the table {}",
+                        column, options.base_table
+                    ),
+                });
+            }
+        }
+        base.count_rows(options.filter.clone()).await?;
+
+        let mut definition = ViewDefinition {
+            base_table: options.base_table,
+            filter: options.filter,
+            columns: options.columns,
+            materialized: options.materialized,
+            base_version: None,
+        };
+        let materialized = if options.materialized {
+            definition.base_version = Some(base.version().await?);
+            let data = ViewTable::read_base(&definition, &base).await?;
+            let write_params = WriteParams {
+                store_params: Some(ObjectStoreParams {
+                    storage_options: Some(self.storage_options.clone()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let table = NativeTable::create(
+                &self.view_uri(&options.name)?,
+                &options.name,
+                data,
+                self.store_wrapper.clone(),
+                Some(write_params),
+                self.read_consistency_interval,
+            )
+            .await?;
+            Some(self.materialized_view(table, &options.name, &definition))
+        } else {
+            None
+        };
+        definition
+            .write(self.object_store.inner.as_ref(), &definition_path)
+            .await?;
+
+        Ok(Table::new(Arc::new(ViewTable::new(
+            options.name,
+            definition,
+            base,
+            materialized,
+        ))))
+    }
+
+    async fn open_view(&self, name: &str) -> Result<Table> {
+        validate_table_name(name)?;
+        let definition = ViewDefinition::read(
+            self.object_store.inner.as_ref(),
+            &self.view_definition_path(name),
+            name,
+        )
+        .await?;
+        let base = self.open_base_table(&definition.base_table).await?;
+        let materialized = if definition.materialized {
+            let table = NativeTable::open_with_params(
+                &self.view_uri(name)?,
+                name,
+                self.store_wrapper.clone(),
+                Some(self.read_params()),
+                self.read_consistency_interval,
+            )
+            .await?;
+            Some(self.materialized_view(table, name, &definition))
+        } else {
+            None
+        };
+        Ok(Table::new(Arc::new(ViewTable::new(
+            name.to_string(),
+            definition,
+            base,
+            materialized,
+        ))))
+    }
+
+    async fn drop_view(&self, name: &str) -> Result<()> {
+        validate_table_name(name)?;
+        match self
+            .object_store
+            .inner
+            .delete(&self.view_definition_path(name))
+            .await
+        {
+            Ok(()) => {}
+            Err(object_store::Error::NotFound { .. }) => {
+                return Err(Error::TableNotFound {
+                    name: name.to_string(),
+                })
+            }
+            Err(err) => return Err(err.into()),
+        }
+        let rows = self
+            .base_path
+            .child(VIEWS_DIR)
+            .child(format!("{}.{}", name, LANCE_EXTENSION));
+        match self.object_store.remove_dir_all(rows).await {
+            Ok(()) | Err(lance::Error::NotFound { .. }) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, StringArray};
     use arrow_schema::{DataType, Field, Schema};
     use tempfile::tempdir;
 
@@ -1024,6 +3183,48 @@ mod tests {
         let tables = db.table_names().limit(7).execute().await.unwrap();
 
         assert_eq!(tables, names[..7]);
+
+        let tables = db
+            .table_names()
+            .execute_stream(9)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(tables, names);
+
+        let tables = db
+            .table_names()
+            .start_after(&names[30])
+            .limit(25)
+            .execute_stream(9)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(tables, names[31..56]);
+
+        let prefix = &names[42][..2];
+        let expected = names
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect::<Vec<_>>();
+        let tables = db.table_names().prefix(prefix).execute().await.unwrap();
+        assert_eq!(tables, expected);
+        let tables = db
+            .table_names()
+            .pattern(format!("{}*", prefix))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(tables, expected);
+
+        let tables = db
+            .table_names()
+            .pattern(format!("*{}", &names[42][30..]))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(tables, vec![names[42].clone()]);
     }
 
     #[tokio::test]
@@ -1031,6 +3232,34 @@ mod tests {
         // let db = Database::connect("s3://bucket/path/to/database").await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_storage_option_helpers() {
+        let builder = connect("s3://bucket/db")
+            .aws_region("us-west-2")
+            .aws_endpoint("http://localhost:9000")
+            .aws_anonymous()
+            .azure_storage_account("account", "key");
+        assert_eq!(builder.storage_options["aws_region"], "us-west-2");
+        assert_eq!(
+            builder.storage_options["aws_endpoint"],
+            "http://localhost:9000"
+        );
+        assert_eq!(builder.storage_options["aws_skip_signature"], "true");
+        assert_eq!(
+            builder.storage_options["azure_storage_account_name"],
+            "account"
+        );
+        assert_eq!(builder.storage_options["azure_storage_account_key"], "key");
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = format!("file://{}", tmp_dir.path().to_str().unwrap());
+        let result = connect(&uri)
+            .dynamodb_commit_table("commits")
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+    }
+
     #[tokio::test]
     #[ignore = "this can't pass due to https://github.com/lancedb/lancedb/issues/1019, enable it after the bug fixed"]
     async fn test_open_table() {
@@ -1077,6 +3306,143 @@ mod tests {
         assert_eq!(tables.len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_metrics() {
+        use crate::query::{ExecutableQuery, QueryBase};
+        use futures::TryStreamExt;
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, false)]));
+        let batch = || {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from_iter_values(0..10))],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema.clone())
+        };
+        let table = db.create_table("test", batch()).execute().await.unwrap();
+        table.add(batch()).execute().await.unwrap();
+        table
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert!(table.query().only_if("y > 1").execute().await.is_err());
+
+        let metrics = db.metrics().unwrap();
+        assert_eq!(metrics.rows_written, 20);
+        assert_eq!(metrics.writes, 1);
+        assert_eq!(metrics.queries, 2);
+        assert_eq!(metrics.query_errors, 1);
+        assert_eq!(metrics.query_latency.count, 2);
+        assert_eq!(metrics.fragments.get("test"), Some(&2));
+        assert_eq!(metrics.cache_hit_rate(), None);
+
+        db.drop_table("test").await.unwrap();
+        assert!(db.metrics().unwrap().fragments.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_column_compression() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("text", DataType::Utf8, false),
+            Field::new("x", DataType::Int32, false),
+        ]));
+        let table = db
+            .create_empty_table("test", schema.clone())
+            .column_compression("text", Compression::Zstd(Some(9)))
+            .column_compression_level("x", CompressionLevel::None)
+            .execute()
+            .await
+            .unwrap();
+        let table_schema = table.schema().await.unwrap();
+        assert_eq!(
+            Compression::from_field(table_schema.field_with_name("text").unwrap()),
+            Some(Compression::Zstd(Some(9)))
+        );
+        assert_eq!(
+            Compression::from_field(table_schema.field_with_name("x").unwrap()),
+            Some(Compression::None)
+        );
+
+        let result = db
+            .create_empty_table("other", schema)
+            .column_compression("missing", Compression::Lz4)
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_dictionary_encode() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("category", DataType::Utf8, false),
+            Field::new("text", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from_iter_values((0..100).map(|i| {
+                    if i % 2 == 0 {
+                        "a"
+                    } else {
+                        "b"
+                    }
+                }))),
+                Arc::new(StringArray::from_iter_values(
+                    (0..100).map(|i| format!("text {}", i)),
+                )),
+            ],
+        )
+        .unwrap();
+        let table = db
+            .create_table(
+                "auto",
+                RecordBatchIterator::new(vec![Ok(batch.clone())], schema.clone()),
+            )
+            .auto_dictionary_encode(0.1)
+            .execute()
+            .await
+            .unwrap();
+        let table_schema = table.schema().await.unwrap();
+        assert!(dictionary::is_dictionary_encoded(
+            table_schema.field_with_name("category").unwrap()
+        ));
+        assert!(!dictionary::is_dictionary_encoded(
+            table_schema.field_with_name("text").unwrap()
+        ));
+        // Later data doesn't need the encoding in its schema
+        table
+            .add(RecordBatchIterator::new(vec![Ok(batch)], schema.clone()))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 200);
+
+        let table = db
+            .create_empty_table("explicit", schema)
+            .dictionary_encode("text")
+            .execute()
+            .await
+            .unwrap();
+        let table_schema = table.schema().await.unwrap();
+        assert!(dictionary::is_dictionary_encoded(
+            table_schema.field_with_name("text").unwrap()
+        ));
+    }
+
     #[tokio::test]
     async fn test_create_table_already_exists() {
         let tmp_dir = tempdir().unwrap();
@@ -1110,4 +3476,423 @@ mod tests {
             .unwrap();
         assert_eq!(other_schema, overwritten.schema().await.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_rename_and_clone_table() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..10))],
+        )
+        .unwrap();
+        let table = db
+            .create_table(
+                "blue",
+                RecordBatchIterator::new(vec![Ok(batch.clone())], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+        let version = table.version().await.unwrap();
+        table
+            .add(RecordBatchIterator::new(vec![Ok(batch)], schema.clone()))
+            .execute()
+            .await
+            .unwrap();
+
+        let green = db
+            .clone_table("blue", "green", Some(version))
+            .await
+            .unwrap();
+        assert_eq!(green.count_rows(None).await.unwrap(), 10);
+        let latest = db.clone_table("blue", "latest", None).await.unwrap();
+        assert_eq!(latest.count_rows(None).await.unwrap(), 20);
+        // The copies are independent of the source
+        green.delete("x < 5").await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 20);
+
+        db.rename_table("green", "live").await.unwrap();
+        assert_eq!(
+            db.table_names().execute().await.unwrap(),
+            vec!["blue", "latest", "live"]
+        );
+        let live = db.open_table("live").execute().await.unwrap();
+        assert_eq!(live.count_rows(None).await.unwrap(), 5);
+
+        assert!(matches!(
+            db.rename_table("green", "other").await,
+            Err(Error::TableNotFound { .. })
+        ));
+        assert!(matches!(
+            db.rename_table("blue", "live").await,
+            Err(Error::TableAlreadyExists { .. })
+        ));
+        assert!(matches!(
+            db.clone_table("blue", "latest", None).await,
+            Err(Error::TableAlreadyExists { .. })
+        ));
+        assert!(matches!(
+            db.clone_table("blue", "bad name!", None).await,
+            Err(Error::InvalidTableName { .. })
+        ));
+        // A clone at a version that does not exist is removed
+        assert!(db.clone_table("blue", "old", Some(100)).await.is_err());
+        assert!(!db
+            .table_names()
+            .execute()
+            .await
+            .unwrap()
+            .contains(&"old".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_namespaces() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, false)]));
+        db.create_empty_table("items", schema.clone())
+            .execute()
+            .await
+            .unwrap();
+
+        let tenant_a = db.namespace("tenant_a").unwrap();
+        let tenant_b = db.namespace("tenant_b").unwrap();
+        assert!(tenant_a.table_names().execute().await.unwrap().is_empty());
+        tenant_a
+            .create_empty_table("items", schema.clone())
+            .execute()
+            .await
+            .unwrap();
+        tenant_a
+            .create_empty_table("orders", schema.clone())
+            .execute()
+            .await
+            .unwrap();
+        db.create_namespace("tenant_b").await.unwrap();
+        db.create_namespace("tenant_b").await.unwrap();
+
+        assert_eq!(db.table_names().execute().await.unwrap(), vec!["items"]);
+        assert_eq!(
+            tenant_a.table_names().execute().await.unwrap(),
+            vec!["items", "orders"]
+        );
+        assert!(tenant_b.table_names().execute().await.unwrap().is_empty());
+        assert_eq!(
+            db.list_namespaces().await.unwrap(),
+            vec!["tenant_a", "tenant_b"]
+        );
+        assert!(matches!(
+            tenant_b.open_table("items").execute().await,
+            Err(Error::TableNotFound { .. })
+        ));
+        assert!(matches!(
+            db.namespace("_views"),
+            Err(Error::InvalidInput { .. })
+        ));
+
+        db.drop_namespace("tenant_a").await.unwrap();
+        assert_eq!(db.list_namespaces().await.unwrap(), vec!["tenant_b"]);
+        assert!(tenant_a.table_names().execute().await.unwrap().is_empty());
+        assert!(matches!(
+            db.drop_namespace("tenant_a").await,
+            Err(Error::NamespaceNotFound { .. })
+        ));
+        assert_eq!(db.table_names().execute().await.unwrap(), vec!["items"]);
+    }
+
+    #[tokio::test]
+    async fn test_memory() {
+        let db = connect("memory://").execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..10))],
+        )
+        .unwrap();
+        let table = db
+            .create_table(
+                "t",
+                RecordBatchIterator::new(vec![Ok(batch.clone())], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+        table
+            .add(RecordBatchIterator::new(vec![Ok(batch)], schema.clone()))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(db.table_names().execute().await.unwrap(), vec!["t"]);
+        let table = db.open_table("t").execute().await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 20);
+        assert_eq!(table.count_rows(Some("x < 5".into())).await.unwrap(), 10);
+
+        // Each connection has its own database
+        let other = connect("memory://").execute().await.unwrap();
+        assert!(other.table_names().execute().await.unwrap().is_empty());
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        db.persist_to(uri).await.unwrap();
+        let persisted = connect(uri).execute().await.unwrap();
+        let table = persisted.open_table("t").execute().await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 20);
+        assert_eq!(table.list_versions().await.unwrap().len(), 2);
+        assert!(matches!(
+            db.persist_to(uri).await,
+            Err(Error::TableAlreadyExists { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_views() {
+        use futures::TryStreamExt;
+
+        use crate::query::{ExecutableQuery, QueryBase, Select};
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("value", DataType::Int32, false),
+        ]));
+        let make_batch = |ids: Vec<i32>| {
+            let values = Int32Array::from_iter_values(ids.iter().map(|id| id * 10));
+            RecordBatchIterator::new(
+                vec![RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from(ids)), Arc::new(values)],
+                )],
+                schema.clone(),
+            )
+        };
+        let table = db
+            .create_table("base", make_batch((0..10).collect()))
+            .execute()
+            .await
+            .unwrap();
+
+        let view = db
+            .create_view("big", "base")
+            .only_if("id >= 5")
+            .select(&["id"])
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(view.count_rows(None).await.unwrap(), 5);
+        assert_eq!(
+            view.count_rows(Some("id < 7".to_string())).await.unwrap(),
+            2
+        );
+        assert_eq!(view.schema().await.unwrap().fields().len(), 1);
+        let batches = view
+            .query()
+            .only_if("id < 7")
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+        assert_eq!(batches[0].num_columns(), 1);
+        // Columns outside of the view cannot be selected and views are read only
+        assert!(view
+            .query()
+            .select(Select::columns(&["value"]))
+            .execute()
+            .await
+            .is_err());
+        assert!(view.delete("true").await.is_err());
+
+        let materialized = db
+            .create_view("small", "base")
+            .only_if("id < 3")
+            .materialized(true)
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(materialized.count_rows(None).await.unwrap(), 3);
+        // Refreshed after a commit to the base table
+        table.add(make_batch(vec![1, 20])).execute().await.unwrap();
+        assert_eq!(materialized.count_rows(None).await.unwrap(), 4);
+        let reopened = db.open_view("small").await.unwrap();
+        assert_eq!(reopened.count_rows(None).await.unwrap(), 4);
+
+        // Views are not tables
+        assert_eq!(db.table_names().execute().await.unwrap(), vec!["base"]);
+        assert!(matches!(
+            db.create_view("big", "base").execute().await,
+            Err(crate::Error::TableAlreadyExists { .. })
+        ));
+        db.drop_view("big").await.unwrap();
+        db.drop_view("small").await.unwrap();
+        assert!(matches!(
+            db.open_view("small").await,
+            Err(crate::Error::TableNotFound { .. })
+        ));
+        assert_eq!(table.count_rows(None).await.unwrap(), 12);
+    }
+
+    #[tokio::test]
+    async fn test_create_temp_table() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..10))],
+        )
+        .unwrap();
+        let data = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        db.create_temp_table("temp", data).execute().await.unwrap();
+
+        // Temporary tables are not stored in the database
+        assert!(db.table_names().execute().await.unwrap().is_empty());
+        assert_eq!(std::fs::read_dir(tmp_dir.path()).unwrap().count(), 0);
+
+        let table = db.open_table("temp").execute().await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+
+        db.drop_table("temp").await.unwrap();
+        assert!(db.open_table("temp").execute().await.is_err());
+
+        // An empty temporary table, filled and indexed like any other table
+        let staging = db
+            .create_empty_temp_table("staging", schema.clone())
+            .execute()
+            .await
+            .unwrap();
+        for start in [0, 10] {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from_iter_values(start..start + 10))],
+            )
+            .unwrap();
+            staging
+                .add(RecordBatchIterator::new(vec![Ok(batch)], schema.clone()))
+                .execute()
+                .await
+                .unwrap();
+        }
+        staging
+            .create_index(&["x"], crate::index::Index::Auto)
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(
+            staging
+                .count_rows(Some("x >= 5".to_string()))
+                .await
+                .unwrap(),
+            15
+        );
+        assert!(db.table_names().execute().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_temp_tables_removed_on_drop() {
+        let temp_tables = TempTables::default();
+        let table_uri = temp_tables.table_uri("test").unwrap();
+        let dir = Path::new(&table_uri).parent().unwrap().to_path_buf();
+        assert!(dir.exists());
+        drop(temp_tables);
+        assert!(!dir.exists());
+    }
+
+    #[tokio::test]
+    async fn test_quotas() {
+        use crate::quota::{Quota, QuotaLimit};
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, false)]));
+        let make_batch = |n: i32| {
+            RecordBatchIterator::new(
+                vec![RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from_iter_values(0..n))],
+                )],
+                schema.clone(),
+            )
+        };
+        let connect_with_quotas = || {
+            connect(uri)
+                .quota("tenant_a_", Quota::default().max_rows(5))
+                .quota("tenant_b_", Quota::default().max_write_rate(1))
+                .execute()
+        };
+
+        let db = connect_with_quotas().await.unwrap();
+        let table = db
+            .create_table("tenant_a_t", make_batch(3))
+            .execute()
+            .await
+            .unwrap();
+        let err = table.add(make_batch(3)).execute().await.unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::QuotaExceeded {
+                limit: QuotaLimit::Rows,
+                usage: 6,
+                max: 5,
+                ..
+            }
+        ));
+        assert_eq!(table.count_rows(None).await.unwrap(), 3);
+        table.add(make_batch(2)).execute().await.unwrap();
+        // The quota applies to all of the tables with the prefix
+        assert!(matches!(
+            db.create_table("tenant_a_u", make_batch(1)).execute().await,
+            Err(crate::Error::QuotaExceeded { .. })
+        ));
+        // Overwriting replaces the rows of the table
+        table
+            .add(make_batch(4))
+            .mode(crate::table::AddDataMode::Overwrite)
+            .execute()
+            .await
+            .unwrap();
+        db.create_table("tenant_a_u", make_batch(1))
+            .execute()
+            .await
+            .unwrap();
+
+        // The usage is measured when connecting
+        let db = connect_with_quotas().await.unwrap();
+        let table = db.open_table("tenant_a_t").execute().await.unwrap();
+        assert!(matches!(
+            table.add(make_batch(1)).execute().await,
+            Err(crate::Error::QuotaExceeded { .. })
+        ));
+
+        // A write is allowed while there is rate left
+        let table = db
+            .create_table("tenant_b_t", make_batch(3))
+            .execute()
+            .await
+            .unwrap();
+        assert!(matches!(
+            table.add(make_batch(1)).execute().await,
+            Err(crate::Error::QuotaExceeded {
+                limit: QuotaLimit::WriteRate,
+                ..
+            })
+        ));
+
+        // Other tables are not limited
+        db.create_table("other", make_batch(10))
+            .execute()
+            .await
+            .unwrap();
+    }
 }
@@ -14,5 +14,12 @@
 
 //! Data types, schema coercion, and data cleaning and etc.
 
+#[cfg(feature = "csv")]
+pub mod csv;
+#[cfg(feature = "huggingface")]
+pub mod huggingface;
 pub mod inspect;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod parquet;
 pub mod sanitize;
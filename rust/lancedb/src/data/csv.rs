@@ -0,0 +1,205 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read CSV files as the data of a table
+//!
+//! ```no_run
+//! # use lancedb::data::csv::CsvSource;
+//! # async fn load(db: &lancedb::Connection) -> lancedb::Result<()> {
+//! let table = db
+//!     .create_table("products", CsvSource::new("products.csv"))
+//!     .execute()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The schema is inferred from the first rows of the file, unless it is given
+//! with [`CsvSource::schema`].  The type of some columns can be overridden
+//! with [`CsvSource::column_type`], for example to keep zip codes as strings.
+//! The file is read a batch at a time.
+//!
+//! This requires the `csv` feature.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow_array::RecordBatchReader;
+use arrow_csv::reader::Format;
+use arrow_csv::ReaderBuilder;
+use arrow_schema::{DataType, SchemaRef};
+
+use super::sanitize::with_column_types;
+use crate::arrow::IntoArrow;
+use crate::error::{Error, Result};
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::io(format!("Failed to read {}", path.display()), e)
+}
+
+/// A CSV file, to read as the data of a table
+///
+/// See the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct CsvSource {
+    path: PathBuf,
+    has_header: bool,
+    delimiter: u8,
+    batch_size: usize,
+    infer_schema_rows: Option<usize>,
+    schema: Option<SchemaRef>,
+    column_types: HashMap<String, DataType>,
+}
+
+impl CsvSource {
+    /// The CSV file at `path`
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            has_header: true,
+            delimiter: b',',
+            batch_size: 8192,
+            infer_schema_rows: Some(1000),
+            schema: None,
+            column_types: HashMap::new(),
+        }
+    }
+
+    /// Whether the first line holds the names of the columns, true by default
+    ///
+    /// Without a header the columns are named `column_1`, `column_2`, ...
+    pub fn has_header(mut self, has_header: bool) -> Self {
+        self.has_header = has_header;
+        self
+    }
+
+    /// The character separating the values, `,` by default
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// The number of rows of the batches read, 8192 by default
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// The number of rows read to infer the schema, 1000 by default
+    ///
+    /// `None` reads the whole file, once to infer the schema and once to load
+    /// it.
+    pub fn infer_schema_rows(mut self, rows: Option<usize>) -> Self {
+        self.infer_schema_rows = rows;
+        self
+    }
+
+    /// The schema of the file, instead of inferring it
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Read the column `column` as `data_type`, instead of the inferred type
+    pub fn column_type(mut self, column: impl Into<String>, data_type: DataType) -> Self {
+        self.column_types.insert(column.into(), data_type);
+        self
+    }
+
+    fn format(&self) -> Format {
+        Format::default()
+            .with_header(self.has_header)
+            .with_delimiter(self.delimiter)
+    }
+
+    /// The schema of the file, with the overridden column types
+    fn read_schema(&self, file: &mut File) -> Result<SchemaRef> {
+        let schema = match &self.schema {
+            Some(schema) => schema.as_ref().clone(),
+            None => {
+                let (schema, _) = self
+                    .format()
+                    .infer_schema(&mut *file, self.infer_schema_rows)?;
+                file.seek(SeekFrom::Start(0))
+                    .map_err(|e| io_error(&self.path, e))?;
+                schema
+            }
+        };
+        with_column_types(&schema, &self.column_types).map(Arc::new)
+    }
+}
+
+impl IntoArrow for CsvSource {
+    fn into_arrow(self) -> Result<Box<dyn RecordBatchReader + Send>> {
+        let mut file = File::open(&self.path).map_err(|e| io_error(&self.path, e))?;
+        let schema = self.read_schema(&mut file)?;
+        let reader = ReaderBuilder::new(schema)
+            .with_format(self.format())
+            .with_batch_size(self.batch_size)
+            .build(file)?;
+        Ok(Box::new(reader))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::ExecutableQuery;
+    use futures::TryStreamExt;
+
+    #[tokio::test]
+    async fn test_csv_source() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("products.csv");
+        std::fs::write(
+            &path,
+            "id,name,price,zip\n1,apple,0.5,01234\n2,pear,0.75,98765\n",
+        )
+        .unwrap();
+
+        let source = CsvSource::new(&path).column_type("zip", DataType::Utf8);
+        let mut file = File::open(&path).unwrap();
+        let schema = source.read_schema(&mut file).unwrap();
+        assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+        assert_eq!(schema.field(2).data_type(), &DataType::Float64);
+        assert_eq!(schema.field(3).data_type(), &DataType::Utf8);
+
+        let uri = tmp_dir.path().join("db");
+        let db = connect(uri.to_str().unwrap()).execute().await.unwrap();
+        let table = db.create_table("products", source).execute().await.unwrap();
+        let batches = table
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let zips = batches[0].column_by_name("zip").unwrap().as_string::<i32>();
+        assert_eq!(zips.value(0), "01234");
+
+        let missing = CsvSource::new(&path).column_type("missing", DataType::Utf8);
+        assert!(matches!(
+            missing.into_arrow(),
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+}
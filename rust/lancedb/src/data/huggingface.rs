@@ -0,0 +1,324 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Load datasets of the Hugging Face Hub into tables
+//!
+//! The Hub converts the datasets it hosts to parquet shards.
+//! [`HuggingFaceDataset::create_table`] downloads the shards of one split of a
+//! dataset, one at a time, and writes them to a new table: the first shard
+//! creates the table and each of the others is added to it.  Only one shard is
+//! held in memory at a time.
+//!
+//! ```no_run
+//! # use lancedb::data::huggingface::HuggingFaceDataset;
+//! # async fn load(db: &lancedb::Connection) -> lancedb::Result<()> {
+//! let table = HuggingFaceDataset::new("stanfordnlp/imdb")
+//!     .split("test")
+//!     .select(&["text", "label"])
+//!     .limit(10_000)
+//!     .create_table(db, "imdb")
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Vector columns can be computed while loading by an embedding function of
+//! the connection, see [`HuggingFaceDataset::add_embedding`].
+//!
+//! This requires the `huggingface` feature.
+
+use std::sync::Arc;
+
+use arrow_array::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_schema::{ArrowError, SchemaRef};
+use bytes::Bytes;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ProjectionMask;
+use reqwest::header::AUTHORIZATION;
+
+use crate::connection::Connection;
+use crate::embeddings::EmbeddingDefinition;
+use crate::error::{Error, Result};
+use crate::Table;
+
+/// The Hugging Face Hub
+pub const DEFAULT_ENDPOINT: &str = "https://huggingface.co";
+
+/// A split of a dataset of the Hugging Face Hub, to load into a table
+#[derive(Debug, Clone)]
+pub struct HuggingFaceDataset {
+    dataset: String,
+    config: String,
+    split: String,
+    columns: Option<Vec<String>>,
+    limit: Option<usize>,
+    token: Option<String>,
+    endpoint: String,
+    embeddings: Vec<EmbeddingDefinition>,
+}
+
+impl HuggingFaceDataset {
+    /// The `train` split of the `default` configuration of `dataset`, such as
+    /// `"stanfordnlp/imdb"`
+    pub fn new(dataset: &str) -> Self {
+        Self {
+            dataset: dataset.to_string(),
+            config: "default".to_string(),
+            split: "train".to_string(),
+            columns: None,
+            limit: None,
+            token: std::env::var("HF_TOKEN").ok(),
+            endpoint: DEFAULT_ENDPOINT.to_string(),
+            embeddings: Vec::new(),
+        }
+    }
+
+    /// The configuration, or subset, of the dataset to load
+    ///
+    /// The default is `default`.
+    pub fn config(mut self, config: &str) -> Self {
+        self.config = config.to_string();
+        self
+    }
+
+    /// The split of the dataset to load
+    ///
+    /// The default is `train`.
+    pub fn split(mut self, split: &str) -> Self {
+        self.split = split.to_string();
+        self
+    }
+
+    /// Only load these columns, in this order
+    ///
+    /// The other columns are not decoded.  By default all of the columns are
+    /// loaded.
+    pub fn select(mut self, columns: &[impl AsRef<str>]) -> Self {
+        self.columns = Some(columns.iter().map(|c| c.as_ref().to_string()).collect());
+        self
+    }
+
+    /// Only load the first `limit` rows of the split
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// The access token used to load private and gated datasets
+    ///
+    /// By default the token is read from the `HF_TOKEN` environment variable.
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// The URL of the Hub, by default [`DEFAULT_ENDPOINT`]
+    pub fn endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Compute a vector column with an embedding function while loading
+    ///
+    /// See [`crate::connection::CreateTableBuilder::add_embedding`].  The source
+    /// column must be loaded.
+    pub fn add_embedding(mut self, definition: EmbeddingDefinition) -> Self {
+        self.embeddings.push(definition);
+        self
+    }
+
+    /// The URL listing the parquet shards of the split
+    fn shards_url(&self) -> String {
+        format!(
+            "{}/api/datasets/{}/parquet/{}/{}",
+            self.endpoint, self.dataset, self.config, self.split
+        )
+    }
+
+    async fn get(&self, client: &reqwest::Client, url: &str) -> Result<reqwest::Response> {
+        let mut request = client.get(url);
+        if let Some(token) = &self.token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        Ok(request.send().await?.error_for_status()?)
+    }
+
+    /// The URLs of the parquet shards of the split
+    pub async fn shards(&self) -> Result<Vec<String>> {
+        self.list_shards(&reqwest::Client::new()).await
+    }
+
+    async fn list_shards(&self, client: &reqwest::Client) -> Result<Vec<String>> {
+        let shards: Vec<String> = self.get(client, &self.shards_url()).await?.json().await?;
+        if shards.is_empty() {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "the split '{}' of the configuration '{}' of the dataset '{}' \
+                     has no parquet shards",
+                    self.split, self.config, self.dataset
+                ),
+            });
+        }
+        Ok(shards)
+    }
+
+    /// Decode the rows of a shard, at most `limit` of them
+    fn read_shard(
+        &self,
+        shard: Bytes,
+        limit: Option<usize>,
+    ) -> Result<(SchemaRef, Vec<RecordBatch>)> {
+        let mut builder = ParquetRecordBatchReaderBuilder::try_new(shard)?;
+        let mut order = None;
+        if let Some(columns) = &self.columns {
+            let schema = builder.schema().clone();
+            let mut indices = columns
+                .iter()
+                .map(|column| {
+                    schema.index_of(column).map_err(|_| Error::InvalidInput {
+                        message: format!(
+                            "column '{}' is not in the dataset '{}'",
+                            column, self.dataset
+                        ),
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let mask = ProjectionMask::roots(builder.parquet_schema(), indices.clone());
+            builder = builder.with_projection(mask);
+            // The projection keeps the columns in the order of the file
+            let projected = indices.clone();
+            indices.sort_unstable();
+            indices.dedup();
+            order = Some(
+                projected
+                    .iter()
+                    .map(|index| indices.binary_search(index).unwrap())
+                    .collect::<Vec<_>>(),
+            );
+        }
+        if let Some(limit) = limit {
+            builder = builder.with_limit(limit);
+        }
+        let reader = builder.build()?;
+        let mut schema = reader.schema();
+        let mut batches = reader.collect::<std::result::Result<Vec<_>, _>>()?;
+        if let Some(order) = order {
+            schema = Arc::new(schema.project(&order)?);
+            batches = batches
+                .iter()
+                .map(|batch| batch.project(&order))
+                .collect::<std::result::Result<_, ArrowError>>()?;
+        }
+        Ok((schema, batches))
+    }
+
+    /// Create the table `name` in `db` with the rows of the split
+    ///
+    /// The table must not exist.  If loading a shard fails the rows of the
+    /// shards already loaded are kept in the table.
+    pub async fn create_table(&self, db: &Connection, name: &str) -> Result<Table> {
+        let client = reqwest::Client::new();
+        let mut remaining = self.limit;
+        let mut table: Option<Table> = None;
+        for url in self.list_shards(&client).await? {
+            if remaining == Some(0) && table.is_some() {
+                break;
+            }
+            let shard = self.get(&client, &url).await?.bytes().await?;
+            let (schema, batches) = self.read_shard(shard, remaining)?;
+            let rows = batches.iter().map(|batch| batch.num_rows()).sum::<usize>();
+            remaining = remaining.map(|remaining| remaining.saturating_sub(rows));
+            let data = RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+            match &table {
+                Some(table) => {
+                    table.add(data).execute().await?;
+                }
+                None => {
+                    let mut builder = db.create_table(name, data);
+                    for definition in &self.embeddings {
+                        builder = builder.add_embedding(definition.clone());
+                    }
+                    table = Some(builder.execute().await?);
+                }
+            }
+        }
+        // The shards were listed, so there is at least one
+        Ok(table.unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::ArrowWriter;
+
+    use super::*;
+
+    fn shard() -> Bytes {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("text", DataType::Utf8, false),
+            Field::new("label", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..10)),
+                Arc::new(StringArray::from_iter_values(
+                    (0..10).map(|i| i.to_string()),
+                )),
+                Arc::new(Int32Array::from_iter_values((0..10).map(|i| i % 2))),
+            ],
+        )
+        .unwrap();
+        let mut buffer = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut buffer, schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        Bytes::from(buffer)
+    }
+
+    #[test]
+    fn test_shards_url() {
+        let dataset = HuggingFaceDataset::new("stanfordnlp/imdb")
+            .split("test")
+            .endpoint("http://localhost:8080/");
+        assert_eq!(
+            dataset.shards_url(),
+            "http://localhost:8080/api/datasets/stanfordnlp/imdb/parquet/default/test"
+        );
+    }
+
+    #[test]
+    fn test_read_shard() {
+        let dataset = HuggingFaceDataset::new("test");
+        let (schema, batches) = dataset.read_shard(shard(), None).unwrap();
+        assert_eq!(schema.fields().len(), 3);
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 10);
+
+        let dataset = dataset.select(&["label", "id"]);
+        let (schema, batches) = dataset.read_shard(shard(), Some(4)).unwrap();
+        assert_eq!(schema.field(0).name(), "label");
+        assert_eq!(schema.field(1).name(), "id");
+        assert_eq!(batches[0].schema(), schema);
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 4);
+
+        let dataset = HuggingFaceDataset::new("test").select(&["missing"]);
+        assert!(matches!(
+            dataset.read_shard(shard(), None),
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+}
@@ -46,6 +46,10 @@ where
     }
 }
 
+fn is_float_vector(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::FixedSizeList(item, _) if item.data_type().is_floating())
+}
+
 /// Infer the vector columns from a dataset.
 ///
 /// Parameters
@@ -53,6 +57,10 @@ where
 /// - reader: RecordBatchReader
 /// - strict: if set true, only `fixed_size_list<float>` is considered as vector column. If set to false,
 ///           a `list<float>` column with same length is also considered as vector column.
+///
+/// A `fixed_size_list<uint8>` column is a binary vector column (see [`crate::DistanceType::Hamming`])
+/// and is always considered as a vector column.  So is a `list<fixed_size_list<float>>` column,
+/// which holds several vectors per row (see [`crate::DistanceType::MaxSim`]).
 pub fn infer_vector_columns(
     reader: impl RecordBatchReader + Send,
     strict: bool,
@@ -62,7 +70,13 @@ pub fn infer_vector_columns(
     let mut columns_to_infer: HashMap<String, Option<i64>> = HashMap::new();
     for field in reader.schema().fields() {
         match field.data_type() {
-            DataType::FixedSizeList(sub_field, _) if sub_field.data_type().is_floating() => {
+            DataType::FixedSizeList(sub_field, _)
+                if sub_field.data_type().is_floating()
+                    || sub_field.data_type() == &DataType::UInt8 =>
+            {
+                columns.push(field.name().to_string());
+            }
+            DataType::List(sub_field) if is_float_vector(sub_field.data_type()) => {
                 columns.push(field.name().to_string());
             }
             DataType::List(sub_field) if sub_field.data_type().is_floating() && !strict => {
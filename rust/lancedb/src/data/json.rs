@@ -0,0 +1,212 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read newline-delimited JSON files as the data of a table
+//!
+//! Each line of the file is an object, whose keys are the columns of the row.
+//!
+//! ```no_run
+//! # use lancedb::data::json::JsonSource;
+//! # async fn load(db: &lancedb::Connection) -> lancedb::Result<()> {
+//! let table = db
+//!     .create_table("docs", JsonSource::new("docs.jsonl").vector_column("vector", 384))
+//!     .execute()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The schema is inferred from the first rows of the file, unless it is given
+//! with [`JsonSource::schema`].  Arrays of numbers are inferred as lists of
+//! `f64`, so vector columns must be declared with [`JsonSource::vector_column`]
+//! (or [`JsonSource::column_type`]) to be searchable.  The file is read a batch
+//! at a time.
+//!
+//! This requires the `json` feature.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow_array::RecordBatchReader;
+use arrow_json::reader::infer_json_schema_from_seekable;
+use arrow_json::ReaderBuilder;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+use super::sanitize::{coerce_schema, with_column_types};
+use crate::arrow::IntoArrow;
+use crate::error::{Error, Result};
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::io(format!("Failed to read {}", path.display()), e)
+}
+
+/// A newline-delimited JSON file, to read as the data of a table
+///
+/// See the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct JsonSource {
+    path: PathBuf,
+    batch_size: usize,
+    infer_schema_rows: Option<usize>,
+    schema: Option<SchemaRef>,
+    column_types: HashMap<String, DataType>,
+}
+
+impl JsonSource {
+    /// The newline-delimited JSON file at `path`
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            batch_size: 8192,
+            infer_schema_rows: Some(1000),
+            schema: None,
+            column_types: HashMap::new(),
+        }
+    }
+
+    /// The number of rows of the batches read, 8192 by default
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// The number of rows read to infer the schema, 1000 by default
+    ///
+    /// `None` reads the whole file, once to infer the schema and once to load
+    /// it.  Keys missing from the rows read are not columns of the table.
+    pub fn infer_schema_rows(mut self, rows: Option<usize>) -> Self {
+        self.infer_schema_rows = rows;
+        self
+    }
+
+    /// The schema of the file, instead of inferring it
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Read the column `column` as `data_type`, instead of the inferred type
+    pub fn column_type(mut self, column: impl Into<String>, data_type: DataType) -> Self {
+        self.column_types.insert(column.into(), data_type);
+        self
+    }
+
+    /// Read the arrays of the column `column` as vectors of `dim` `f32` values
+    pub fn vector_column(self, column: impl Into<String>, dim: i32) -> Self {
+        let item = Arc::new(Field::new("item", DataType::Float32, true));
+        self.column_type(column, DataType::FixedSizeList(item, dim))
+    }
+
+    /// The schema of the table, with the overridden column types
+    fn table_schema(&self, reader: &mut BufReader<File>) -> Result<Schema> {
+        let schema = match &self.schema {
+            Some(schema) => schema.as_ref().clone(),
+            None => {
+                let (schema, _) =
+                    infer_json_schema_from_seekable(&mut *reader, self.infer_schema_rows)?;
+                reader
+                    .seek(SeekFrom::Start(0))
+                    .map_err(|e| io_error(&self.path, e))?;
+                schema
+            }
+        };
+        with_column_types(&schema, &self.column_types)
+    }
+}
+
+/// The schema the file is decoded with: the JSON decoder does not build fixed
+/// size lists, so vectors are decoded as lists and then converted
+fn decoded_schema(schema: &Schema) -> Schema {
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| match field.data_type() {
+            DataType::FixedSizeList(item, _) => Field::new(
+                field.name(),
+                DataType::List(item.clone()),
+                field.is_nullable(),
+            ),
+            _ => field.as_ref().clone(),
+        })
+        .collect::<Vec<_>>();
+    Schema::new_with_metadata(fields, schema.metadata().clone())
+}
+
+impl IntoArrow for JsonSource {
+    fn into_arrow(self) -> Result<Box<dyn RecordBatchReader + Send>> {
+        let file = File::open(&self.path).map_err(|e| io_error(&self.path, e))?;
+        let mut file = BufReader::new(file);
+        let schema = Arc::new(self.table_schema(&mut file)?);
+        let reader = ReaderBuilder::new(Arc::new(decoded_schema(&schema)))
+            .with_batch_size(self.batch_size)
+            .build(file)?;
+        coerce_schema(reader, schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+    use futures::TryStreamExt;
+
+    #[tokio::test]
+    async fn test_json_source() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("docs.jsonl");
+        std::fs::write(
+            &path,
+            "{\"id\": 1, \"text\": \"hello\", \"vector\": [0.1, 0.2]}\n\
+             {\"id\": 2, \"text\": \"world\", \"vector\": [0.3, 0.4]}\n",
+        )
+        .unwrap();
+
+        let source = JsonSource::new(&path).vector_column("vector", 2);
+        let uri = tmp_dir.path().join("db");
+        let db = connect(uri.to_str().unwrap()).execute().await.unwrap();
+        let table = db.create_table("docs", source).execute().await.unwrap();
+        let schema = table.schema().await.unwrap();
+        assert_eq!(
+            schema.field_with_name("id").unwrap().data_type(),
+            &DataType::Int64
+        );
+        assert!(matches!(
+            schema.field_with_name("vector").unwrap().data_type(),
+            DataType::FixedSizeList(_, 2)
+        ));
+
+        let batches = table
+            .vector_search(vec![0.3_f32, 0.4])
+            .unwrap()
+            .limit(1)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let text = batches[0]
+            .column_by_name("text")
+            .unwrap()
+            .as_string::<i32>();
+        assert_eq!(text.value(0), "world");
+    }
+}
@@ -0,0 +1,509 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Import and export of Parquet files
+//!
+//! [`ParquetFiles`] reads the Parquet files matching a glob, on the local
+//! filesystem or in an object store, as data for
+//! [`crate::connection::Connection::create_table`] or [`crate::Table::add`].
+//! [`crate::connection::Connection::create_table_from_parquet`] is a shortcut
+//! for the former.  [`crate::Table::export_parquet`] writes the rows of a table
+//! to a Parquet file.
+//!
+//! Both directions stream the data: the files are read a batch at a time, and
+//! the export is uploaded a row group at a time, so neither the files nor the
+//! table need to fit in memory.
+//!
+//! ```no_run
+//! # async fn lake(db: &lancedb::Connection) -> lancedb::Result<()> {
+//! let table = db
+//!     .create_table_from_parquet("events", "s3://bucket/events/**/*.parquet")
+//!     .execute()
+//!     .await?;
+//! table
+//!     .export_parquet("s3://bucket/exports/events.parquet")
+//!     .only_if("kind = 'click'")
+//!     .execute()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::{Arc, Mutex};
+
+use arrow_array::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_schema::{ArrowError, SchemaRef};
+use futures::{StreamExt, TryStreamExt};
+use lance::io::{ObjectStore, ObjectStoreParams};
+use object_store::ObjectMeta;
+use parquet::arrow::async_reader::{ParquetObjectReader, ParquetRecordBatchStreamBuilder};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use tokio::io::AsyncWriteExt;
+
+use crate::arrow::IntoArrow;
+use crate::error::{Error, Result};
+use crate::query::{ExecutableQuery, Query, QueryBase, Select};
+use crate::utils::glob_match;
+
+/// The number of batches read ahead of the writer of the table
+const READ_AHEAD: usize = 4;
+
+/// Whether the path `path` matches the glob `glob`
+///
+/// The segments of the path are matched with [`glob_match`], except that a
+/// `**` segment matches any number of directories.
+fn path_match(glob: &[&str], path: &[&str]) -> bool {
+    match glob.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| path_match(rest, &path[skip..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((name, path)) => glob_match(segment, name) && path_match(rest, path),
+            None => false,
+        },
+    }
+}
+
+/// Split `uri` into the URI of its directory and the rest of the path
+///
+/// The directory is the last one before the first wildcard, if any.
+/// [`ObjectStore::from_uri`] is given the directory, as it creates the local
+/// directories it opens.
+fn split_uri(uri: &str) -> (&str, &str) {
+    let wildcard = uri.find(['*', '?']).unwrap_or(uri.len());
+    match uri[..wildcard].rfind('/') {
+        Some(0) => ("/", &uri[1..]),
+        Some(slash) => (&uri[..slash], &uri[slash + 1..]),
+        None => (".", uri),
+    }
+}
+
+/// The Parquet files matching a glob, to read as the data of a table
+///
+/// The files must all have the same schema.  They are read in the order of
+/// their paths.  See the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct ParquetFiles {
+    uri_glob: String,
+    storage_options: HashMap<String, String>,
+    batch_size: usize,
+}
+
+impl ParquetFiles {
+    /// The files matching `uri_glob`
+    ///
+    /// The glob is a path or the URI of an object store, such as
+    /// `s3://bucket/data/*.parquet`.  `*` and `?` match any part of a file or
+    /// directory name, and `**/` any number of directories.  Without wildcards
+    /// this is a single file.
+    pub fn new(uri_glob: impl Into<String>) -> Self {
+        Self {
+            uri_glob: uri_glob.into(),
+            storage_options: HashMap::new(),
+            batch_size: 8192,
+        }
+    }
+
+    /// Set an option of the object store the files are read from
+    ///
+    /// See [`crate::connection::ConnectBuilder::storage_option`].
+    pub fn storage_option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.storage_options.insert(key.into(), value.into());
+        self
+    }
+
+    /// The number of rows of the batches read, 8192 by default
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// The store holding the files and the files matching the glob
+    pub async fn list(&self) -> Result<(Arc<dyn object_store::ObjectStore>, Vec<ObjectMeta>)> {
+        let params = ObjectStoreParams {
+            storage_options: Some(self.storage_options.clone()),
+            ..Default::default()
+        };
+        let (dir, glob) = split_uri(&self.uri_glob);
+        let (store, dir) = ObjectStore::from_uri_and_params(dir, &params).await?;
+        let files = if glob.contains(['*', '?']) {
+            let glob = glob.split('/').collect::<Vec<_>>();
+            let prefix = format!("{}/", dir.as_ref());
+            let mut files = store
+                .inner
+                .list(Some(&dir))
+                .try_filter(|meta| {
+                    let path = meta.location.as_ref();
+                    let relative = path.strip_prefix(&prefix).unwrap_or(path);
+                    let relative = relative.split('/').collect::<Vec<_>>();
+                    futures::future::ready(path_match(&glob, &relative))
+                })
+                .try_collect::<Vec<_>>()
+                .await?;
+            files.sort_by(|a, b| a.location.cmp(&b.location));
+            files
+        } else {
+            vec![store.inner.head(&dir.child(glob)).await?]
+        };
+        if files.is_empty() {
+            return Err(Error::InvalidInput {
+                message: format!("no Parquet files match {}", self.uri_glob),
+            });
+        }
+        Ok((store.inner.clone(), files))
+    }
+
+    async fn open(
+        &self,
+        store: &Arc<dyn object_store::ObjectStore>,
+        file: ObjectMeta,
+    ) -> Result<ParquetRecordBatchStreamBuilder<ParquetObjectReader>> {
+        let reader = ParquetObjectReader::new(store.clone(), file);
+        Ok(ParquetRecordBatchStreamBuilder::new(reader)
+            .await?
+            .with_batch_size(self.batch_size))
+    }
+
+    /// Read the files, sending their schema and then their batches
+    ///
+    /// Stops once the receiver of the batches is dropped.
+    async fn read(
+        self,
+        schema_sender: SyncSender<Result<SchemaRef>>,
+        sender: SyncSender<std::result::Result<RecordBatch, ArrowError>>,
+    ) {
+        let opened = async {
+            let (store, files) = self.list().await?;
+            let first = self.open(&store, files[0].clone()).await?;
+            Ok::<_, Error>((store, files, first))
+        }
+        .await;
+        let (store, files, first) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                let _ = schema_sender.send(Err(e));
+                return;
+            }
+        };
+        let schema = first.schema().clone();
+        if schema_sender.send(Ok(schema.clone())).is_err() {
+            return;
+        }
+
+        let mut next = Some(first);
+        for (i, file) in files.iter().enumerate() {
+            let location = file.location.clone();
+            let result = async {
+                let builder = match next.take() {
+                    Some(builder) => builder,
+                    None => self.open(&store, file.clone()).await?,
+                };
+                if builder.schema().fields() != schema.fields() {
+                    return Err(Error::InvalidInput {
+                        message: format!(
+                            "the schema of {} is not the schema of the first file: {:?} != {:?}",
+                            location,
+                            builder.schema(),
+                            schema
+                        ),
+                    });
+                }
+                let mut stream = builder.build()?;
+                while let Some(batch) = stream.next().await {
+                    let batch = RecordBatch::try_new(schema.clone(), batch?.columns().to_vec())?;
+                    if sender.send(Ok(batch)).is_err() {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            .await;
+            match result {
+                Ok(true) => log::debug!("Read {} ({}/{})", location, i + 1, files.len()),
+                Ok(false) => return,
+                Err(e) => {
+                    let _ = sender.send(Err(ArrowError::ExternalError(Box::new(e))));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Run `read` in a thread with its own runtime, returning the batches it sends
+///
+/// `read` sends the schema of the batches, or the error preventing to read
+/// them, and then the batches.  It should stop once the receiver of the
+/// batches is dropped.  The thread lets the batches be consumed synchronously,
+/// as the writers of tables do.
+pub(crate) fn read_in_background<F, Fut>(read: F) -> Result<Box<dyn RecordBatchReader + Send>>
+where
+    F: FnOnce(
+            SyncSender<Result<SchemaRef>>,
+            SyncSender<std::result::Result<RecordBatch, ArrowError>>,
+        ) -> Fut
+        + Send
+        + 'static,
+    Fut: Future<Output = ()>,
+{
+    let (schema_sender, schema_receiver) = sync_channel(1);
+    let (sender, receiver) = sync_channel(READ_AHEAD);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| Error::Runtime {
+            message: format!("failed to start reading the files: {}", e),
+        })?;
+    std::thread::spawn(move || runtime.block_on(read(schema_sender, sender)));
+    let schema = schema_receiver.recv().map_err(|_| Error::Runtime {
+        message: "the reader of the files stopped".to_string(),
+    })??;
+    Ok(Box::new(RecordBatchIterator::new(receiver, schema)))
+}
+
+impl IntoArrow for ParquetFiles {
+    /// Start reading the files in the background
+    ///
+    /// The files are read by a thread with its own runtime, so that the reader
+    /// can be consumed synchronously, as the writers of tables do.
+    fn into_arrow(self) -> Result<Box<dyn RecordBatchReader + Send>> {
+        read_in_background(move |schema_sender, sender| self.read(schema_sender, sender))
+    }
+}
+
+/// The buffer the Parquet writer writes to, from which the encoded row groups
+/// are taken as they are completed
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// The bytes written since the last call
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A builder used to export the rows of a table to a Parquet file
+///
+/// See [`crate::Table::export_parquet`].
+pub struct ExportParquetBuilder {
+    query: Query,
+    uri: String,
+    storage_options: HashMap<String, String>,
+    compression: Compression,
+    max_row_group_size: usize,
+}
+
+impl ExportParquetBuilder {
+    pub(crate) fn new(query: Query, uri: String) -> Self {
+        Self {
+            query,
+            uri,
+            storage_options: HashMap::new(),
+            compression: Compression::SNAPPY,
+            max_row_group_size: 1024 * 1024,
+        }
+    }
+
+    /// Only export the rows matching the filter
+    pub fn only_if(mut self, filter: impl AsRef<str>) -> Self {
+        self.query = self.query.only_if(filter);
+        self
+    }
+
+    /// Only export these columns, see [`QueryBase::select`]
+    pub fn select(mut self, select: Select) -> Self {
+        self.query = self.query.select(select);
+        self
+    }
+
+    /// Set an option of the object store the file is written to
+    ///
+    /// See [`crate::connection::ConnectBuilder::storage_option`].
+    pub fn storage_option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.storage_options.insert(key.into(), value.into());
+        self
+    }
+
+    /// The compression of the pages of the file, Snappy by default
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// The largest number of rows of a row group, 1024 * 1024 by default
+    ///
+    /// A row group is buffered in memory until it is complete.
+    pub fn max_row_group_size(mut self, max_row_group_size: usize) -> Self {
+        self.max_row_group_size = max_row_group_size.max(1);
+        self
+    }
+
+    /// Write the rows to the file, replacing it if it exists
+    ///
+    /// Returns the number of rows written.  The file only appears once it is
+    /// complete.
+    pub async fn execute(self) -> Result<usize> {
+        let params = ObjectStoreParams {
+            storage_options: Some(self.storage_options.clone()),
+            ..Default::default()
+        };
+        let (dir, name) = split_uri(&self.uri);
+        let (store, dir) = ObjectStore::from_uri_and_params(dir, &params).await?;
+        let path = dir.child(name);
+        let mut stream = self.query.execute().await?;
+        let properties = WriterProperties::builder()
+            .set_compression(self.compression)
+            .set_max_row_group_size(self.max_row_group_size)
+            .build();
+        let buffer = SharedBuffer::default();
+        let mut writer = ArrowWriter::try_new(buffer.clone(), stream.schema(), Some(properties))?;
+
+        let (id, mut upload) = store.inner.put_multipart(&path).await?;
+        let upload_error =
+            |e: std::io::Error| Error::io(format!("Failed to write {}", self.uri), e);
+        let result = async {
+            let mut rows = 0;
+            while let Some(batch) = stream.try_next().await? {
+                rows += batch.num_rows();
+                writer.write(&batch)?;
+                // Upload the row groups as they are completed
+                let encoded = buffer.take();
+                if !encoded.is_empty() {
+                    upload.write_all(&encoded).await.map_err(upload_error)?;
+                }
+            }
+            writer.close()?;
+            upload
+                .write_all(&buffer.take())
+                .await
+                .map_err(upload_error)?;
+            upload.shutdown().await.map_err(upload_error)?;
+            Ok::<_, Error>(rows)
+        }
+        .await;
+        if result.is_err() {
+            if let Err(e) = store.inner.abort_multipart(&path, &id).await {
+                log::warn!("Failed to abort the upload of {}: {}", self.uri, e);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int32Type;
+    use arrow_array::Int32Array;
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    #[test]
+    fn test_glob() {
+        assert_eq!(
+            split_uri("s3://bucket/data/*/a.parquet"),
+            ("s3://bucket/data", "*/a.parquet")
+        );
+        assert_eq!(split_uri("/data/a.parquet"), ("/data", "a.parquet"));
+        assert_eq!(split_uri("a.parquet"), (".", "a.parquet"));
+
+        let glob = ["**", "part-?.parquet"];
+        assert!(path_match(&glob, &["part-1.parquet"]));
+        assert!(path_match(&glob, &["2024", "01", "part-1.parquet"]));
+        assert!(!path_match(&glob, &["part-10.parquet"]));
+        let glob = ["*.parquet"];
+        assert!(path_match(&glob, &["a.parquet"]));
+        assert!(!path_match(&glob, &["dir", "a.parquet"]));
+        assert!(!path_match(&glob, &["aparquet"]));
+    }
+
+    #[tokio::test]
+    async fn test_parquet_round_trip() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().join("db");
+        let uri = uri.to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..100))],
+        )
+        .unwrap();
+        let table = db
+            .create_table(
+                "source",
+                RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        let exports = tmp_dir.path().join("exports");
+        for (name, filter) in [("low.parquet", "i < 30"), ("high.parquet", "i >= 30")] {
+            let path = exports.join("parts").join(name);
+            let rows = table
+                .export_parquet(path.to_str().unwrap())
+                .only_if(filter)
+                .max_row_group_size(16)
+                .execute()
+                .await
+                .unwrap();
+            assert_eq!(rows, if name == "low.parquet" { 30 } else { 70 });
+        }
+
+        let glob = format!("{}/**/*.parquet", exports.to_str().unwrap());
+        let imported = db
+            .create_table_from_parquet("imported", glob)
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(imported.count_rows(None).await.unwrap(), 100);
+        let batches = imported
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        // high.parquet sorts before low.parquet
+        let first = batches[0].column(0).as_primitive::<Int32Type>().value(0);
+        assert_eq!(first, 30);
+
+        let missing = format!("{}/*.csv", exports.to_str().unwrap());
+        assert!(matches!(
+            ParquetFiles::new(missing).into_arrow(),
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+}
@@ -16,6 +16,7 @@ use std::{iter::repeat_with, sync::Arc};
 
 use arrow_array::{
     cast::AsArray,
+    new_null_array,
     types::{Float16Type, Float32Type, Float64Type, Int32Type, Int64Type},
     Array, ArrowNumericType, FixedSizeListArray, PrimitiveArray, RecordBatch, RecordBatchIterator,
     RecordBatchReader,
@@ -28,7 +29,10 @@ use log::warn;
 use num_traits::cast::AsPrimitive;
 
 use super::inspect::infer_dimension;
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::table::{ExtraColumns, MissingColumns, SchemaMode, SchemaPolicy};
+#[cfg(any(feature = "csv", feature = "json"))]
+use std::collections::HashMap;
 
 fn cast_array<I: ArrowNumericType, O: ArrowNumericType>(
     arr: &PrimitiveArray<I>,
@@ -161,13 +165,13 @@ fn coerce_schema_batch(
     let columns = schema
         .fields()
         .iter()
-        .map(|field| {
-            batch
-                .column_by_name(field.name())
-                .ok_or_else(|| {
-                    ArrowError::SchemaError(format!("Column {} not found", field.name()))
-                })
-                .and_then(|c| coerce_array(c, field))
+        .map(|field| match batch.column_by_name(field.name()) {
+            Some(c) => coerce_array(c, field),
+            None if field.is_nullable() => Ok(new_null_array(field.data_type(), batch.num_rows())),
+            None => Err(ArrowError::SchemaError(format!(
+                "Column {} not found",
+                field.name()
+            ))),
         })
         .collect::<std::result::Result<Vec<_>, ArrowError>>()?;
     RecordBatch::try_new(schema, columns)
@@ -188,6 +192,98 @@ pub fn coerce_schema(
     Ok(Box::new(RecordBatchIterator::new(batches, schema)))
 }
 
+/// Fit the data added to a table to the `schema` of the table with `policy`
+///
+/// The data is read with the columns of the table, in the order of the table.
+/// The columns which are not in the table, the missing columns and the types
+/// which can't be cast are rejected, as allowed by `policy`, before anything is
+/// read.
+pub(crate) fn fit_to_table(
+    reader: Box<dyn RecordBatchReader + Send>,
+    schema: &Schema,
+    policy: SchemaPolicy,
+) -> Result<Box<dyn RecordBatchReader + Send>> {
+    let data_schema = reader.schema();
+    if policy.on_extra == ExtraColumns::Error {
+        if let Some(field) = data_schema
+            .fields()
+            .iter()
+            .find(|field| schema.field_with_name(field.name()).is_err())
+        {
+            return Err(Error::Schema {
+                message: format!("the column '{}' is not in the table", field.name()),
+            });
+        }
+    }
+    let coerce = policy.mode == SchemaMode::Coerce;
+    let mut fields = Vec::with_capacity(schema.fields().len());
+    for field in schema.fields() {
+        match data_schema.field_with_name(field.name()) {
+            Ok(data_field) if !coerce => fields.push(data_field.clone()),
+            Ok(data_field) => {
+                let castable = can_cast_types(data_field.data_type(), field.data_type())
+                    || matches!(field.data_type(), DataType::FixedSizeList(..));
+                if !castable {
+                    return Err(Error::Schema {
+                        message: format!(
+                            "the column '{}' of type {} can't be cast to the type {} of the table",
+                            field.name(),
+                            data_field.data_type(),
+                            field.data_type()
+                        ),
+                    });
+                }
+                fields.push(field.as_ref().clone());
+            }
+            Err(_) if !field.is_nullable() => {
+                return Err(Error::Schema {
+                    message: format!(
+                        "the column '{}' is missing and is not nullable",
+                        field.name()
+                    ),
+                });
+            }
+            Err(_) if coerce || policy.on_missing == MissingColumns::FillNull => {
+                fields.push(field.as_ref().clone())
+            }
+            Err(_) => {
+                return Err(Error::Schema {
+                    message: format!("the column '{}' is missing", field.name()),
+                });
+            }
+        }
+    }
+    let fitted = Schema::new_with_metadata(fields, data_schema.metadata().clone());
+    coerce_schema(reader, Arc::new(fitted))
+}
+
+/// `schema`, with the types of some columns replaced
+///
+/// Used to override the types inferred from text files.
+#[cfg(any(feature = "csv", feature = "json"))]
+pub(crate) fn with_column_types(
+    schema: &Schema,
+    column_types: &HashMap<String, DataType>,
+) -> Result<Schema> {
+    if let Some(column) = column_types
+        .keys()
+        .find(|column| schema.field_with_name(column).is_err())
+    {
+        return Err(Error::InvalidInput {
+            message: format!("the column {} is not in the file", column),
+        });
+    }
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| match column_types.get(field.name()) {
+            Some(data_type) => Field::new(field.name(), data_type.clone(), field.is_nullable()),
+            None => field.as_ref().clone(),
+        })
+        .collect::<Vec<_>>();
+    Ok(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,527 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Embedding functions compute vectors from other data, such as text.
+//!
+//! An [`EmbeddingFunction`] is registered with the connection's
+//! [`EmbeddingRegistry`] under a name.  A table can then be created with one or
+//! more [`EmbeddingDefinition`]s (see
+//! [`crate::connection::CreateTableBuilder::add_embedding`]), each of which maps
+//! a source column to a vector column using a registered function.  The
+//! definitions are stored in the table's schema metadata so that:
+//!
+//! * [`crate::Table::add`] computes the vector column for new rows which only
+//!   contain the source column.
+//! * [`crate::query::Query::nearest_to`] accepts a string, which is embedded
+//!   with the same function before the vector search.
+//!
+//! The embedding functions themselves are not persisted, so they must be
+//! registered again on each new connection before the table is used.
+//!
+//! Two embedding functions are provided behind cargo features:
+//!
+//! * `openai` - [`openai::OpenAIEmbeddingFunction`] calls the OpenAI embeddings
+//!   API, or any compatible endpoint.
+//! * `sentence-transformers` - [`sentence_transformers::SentenceTransformersEmbeddings`]
+//!   runs a sentence transformer model locally.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use arrow_array::{Array, RecordBatch, RecordBatchReader};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+#[cfg(feature = "openai")]
+pub mod openai;
+#[cfg(feature = "sentence-transformers")]
+pub mod sentence_transformers;
+
+/// The schema metadata key under which the embedding definitions are stored
+pub const EMBEDDING_METADATA_KEY: &str = "lancedb::embedding_functions";
+
+/// A function which computes embeddings
+pub trait EmbeddingFunction: std::fmt::Debug + Send + Sync {
+    /// The name of the function, used in error messages
+    fn name(&self) -> &str;
+    /// The data type of the source column, e.g. [`DataType::Utf8`]
+    fn source_type(&self) -> DataType;
+    /// The data type of the vector column, normally a fixed size list of floats
+    fn dest_type(&self) -> DataType;
+    /// Compute the embeddings for the values of a source column
+    ///
+    /// The result must have the same length as `source` and the type returned
+    /// by [`Self::dest_type`].
+    fn compute_source_embeddings(&self, source: Arc<dyn Array>) -> Result<Arc<dyn Array>>;
+    /// Compute the embedding of a query
+    ///
+    /// `input` contains a single value.  The result is either a single row of
+    /// [`Self::dest_type`] or the values of the query vector.
+    ///
+    /// By default this is the same as [`Self::compute_source_embeddings`].
+    /// Some models embed queries differently from documents.
+    fn compute_query_embeddings(&self, input: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        self.compute_source_embeddings(input)
+    }
+}
+
+/// Maps a source column to the vector column computed from it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingDefinition {
+    /// The column which contains the data to embed
+    pub source_column: String,
+    /// The column which contains the embeddings, by default `{source_column}_embedding`
+    pub dest_column: Option<String>,
+    /// The name of the function in the [`EmbeddingRegistry`]
+    pub embedding_name: String,
+}
+
+impl EmbeddingDefinition {
+    pub fn new(
+        source_column: impl Into<String>,
+        embedding_name: impl Into<String>,
+        dest_column: Option<String>,
+    ) -> Self {
+        Self {
+            source_column: source_column.into(),
+            dest_column,
+            embedding_name: embedding_name.into(),
+        }
+    }
+
+    /// The name of the vector column
+    pub fn dest_column(&self) -> String {
+        self.dest_column
+            .clone()
+            .unwrap_or_else(|| format!("{}_embedding", self.source_column))
+    }
+}
+
+/// A registry of embedding functions, shared by all tables in a connection
+pub trait EmbeddingRegistry: std::fmt::Debug + Send + Sync {
+    /// The names of the registered functions
+    fn functions(&self) -> Vec<String>;
+    /// Register `function` under `name`, replacing any existing function
+    fn register(&self, name: &str, function: Arc<dyn EmbeddingFunction>) -> Result<()>;
+    /// Get the function registered under `name`
+    fn get(&self, name: &str) -> Option<Arc<dyn EmbeddingFunction>>;
+}
+
+/// An [`EmbeddingRegistry`] which keeps the functions in memory
+///
+/// This is the default registry of a connection.
+#[derive(Debug, Default)]
+pub struct MemoryRegistry {
+    functions: RwLock<HashMap<String, Arc<dyn EmbeddingFunction>>>,
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl EmbeddingRegistry for MemoryRegistry {
+    fn functions(&self) -> Vec<String> {
+        let mut names = self
+            .functions
+            .read()
+            .map(|functions| functions.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+
+    fn register(&self, name: &str, function: Arc<dyn EmbeddingFunction>) -> Result<()> {
+        self.functions.write()?.insert(name.to_string(), function);
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> Option<Arc<dyn EmbeddingFunction>> {
+        self.functions.read().ok()?.get(name).cloned()
+    }
+}
+
+/// Read the embedding definitions stored in the metadata of a table schema
+pub(crate) fn definitions_from_metadata(
+    metadata: &HashMap<String, String>,
+) -> Result<Vec<EmbeddingDefinition>> {
+    match metadata.get(EMBEDDING_METADATA_KEY) {
+        Some(json) => serde_json::from_str(json).map_err(|e| Error::Schema {
+            message: format!("Invalid embedding functions in the table metadata: {}", e),
+        }),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Look up the functions of `definitions` in `registry`
+pub(crate) fn resolve(
+    registry: &dyn EmbeddingRegistry,
+    definitions: Vec<EmbeddingDefinition>,
+) -> Result<Vec<(EmbeddingDefinition, Arc<dyn EmbeddingFunction>)>> {
+    definitions
+        .into_iter()
+        .map(|definition| {
+            let function =
+                registry
+                    .get(&definition.embedding_name)
+                    .ok_or_else(|| Error::InvalidInput {
+                        message: format!(
+                            "The embedding function '{}' is not registered with this connection",
+                            definition.embedding_name
+                        ),
+                    })?;
+            Ok((definition, function))
+        })
+        .collect()
+}
+
+/// Compute the embeddings of a query for the vector column `column`
+///
+/// If `column` is None then the table must have exactly one embedding definition.
+/// Returns the name of the vector column and the query vector.
+pub(crate) fn embed_query(
+    registry: &dyn EmbeddingRegistry,
+    metadata: &HashMap<String, String>,
+    column: Option<&str>,
+    input: Arc<dyn Array>,
+) -> Result<(String, Arc<dyn Array>)> {
+    let definitions = definitions_from_metadata(metadata)?;
+    let definition = match column {
+        Some(column) => definitions
+            .into_iter()
+            .find(|definition| definition.dest_column() == column)
+            .ok_or_else(|| Error::InvalidInput {
+                message: format!(
+                    "The column '{}' does not have an embedding function",
+                    column
+                ),
+            })?,
+        None => {
+            if definitions.len() != 1 {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "The table has {} embedding functions, \
+                         please specify which column to search",
+                        definitions.len()
+                    ),
+                });
+            }
+            definitions.into_iter().next().unwrap()
+        }
+    };
+    let (definition, function) = resolve(registry, vec![definition])?.pop().unwrap();
+    let embedding = function.compute_query_embeddings(input)?;
+    let embedding = match embedding.data_type() {
+        DataType::FixedSizeList(_, _) => {
+            let list = embedding
+                .as_any()
+                .downcast_ref::<arrow_array::FixedSizeListArray>()
+                .unwrap();
+            if list.len() != 1 {
+                return Err(Error::Runtime {
+                    message: format!(
+                        "The embedding function '{}' returned {} query vectors",
+                        function.name(),
+                        list.len()
+                    ),
+                });
+            }
+            list.value(0)
+        }
+        _ => embedding,
+    };
+    let embedding = arrow_cast::cast(&embedding, &DataType::Float32)?;
+    Ok((definition.dest_column(), embedding))
+}
+
+/// Build a vector column from the embeddings of the non-null values in `texts`
+#[cfg(any(feature = "openai", feature = "sentence-transformers"))]
+pub(crate) fn text_embeddings_array(
+    texts: &arrow_array::StringArray,
+    embeddings: Vec<Vec<f32>>,
+    dim: usize,
+) -> Result<Arc<dyn Array>> {
+    if let Some(embedding) = embeddings.iter().find(|e| e.len() != dim) {
+        return Err(Error::Runtime {
+            message: format!(
+                "Expected embeddings with {} dimensions but got {}",
+                dim,
+                embedding.len()
+            ),
+        });
+    }
+    let mut embeddings = embeddings.into_iter();
+    let vectors = texts
+        .iter()
+        .map(|text| {
+            text.and_then(|_| embeddings.next())
+                .map(|embedding| embedding.into_iter().map(Some).collect::<Vec<_>>())
+        })
+        .collect::<Vec<_>>();
+    Ok(Arc::new(
+        arrow_array::FixedSizeListArray::from_iter_primitive::<arrow_array::types::Float32Type, _, _>(
+            vectors, dim as i32,
+        ),
+    ))
+}
+
+/// A reader which adds the embedding columns to the batches of another reader
+///
+/// If a batch already contains an embedding column then it is left unchanged.
+pub(crate) struct WithEmbeddings {
+    inner: Box<dyn RecordBatchReader + Send>,
+    embeddings: Vec<(EmbeddingDefinition, Arc<dyn EmbeddingFunction>)>,
+    schema: SchemaRef,
+}
+
+impl WithEmbeddings {
+    pub(crate) fn try_new(
+        inner: Box<dyn RecordBatchReader + Send>,
+        embeddings: Vec<(EmbeddingDefinition, Arc<dyn EmbeddingFunction>)>,
+    ) -> Result<Self> {
+        let input_schema = inner.schema();
+        let mut fields = input_schema.fields().to_vec();
+        for (definition, function) in &embeddings {
+            let source = input_schema
+                .field_with_name(&definition.source_column)
+                .map_err(|_| Error::InvalidInput {
+                    message: format!(
+                        "The source column '{}' of the embedding function '{}' is missing",
+                        definition.source_column, definition.embedding_name
+                    ),
+                })?;
+            if source.data_type() != &function.source_type() {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "The embedding function '{}' expects {} but the column '{}' is {}",
+                        definition.embedding_name,
+                        function.source_type(),
+                        definition.source_column,
+                        source.data_type()
+                    ),
+                });
+            }
+            let dest_column = definition.dest_column();
+            if input_schema.field_with_name(&dest_column).is_err() {
+                fields.push(Arc::new(Field::new(
+                    dest_column,
+                    function.dest_type(),
+                    source.is_nullable(),
+                )));
+            }
+        }
+
+        let definitions = embeddings
+            .iter()
+            .map(|(definition, _)| definition.clone())
+            .collect::<Vec<_>>();
+        let mut metadata = input_schema.metadata().clone();
+        metadata.insert(
+            EMBEDDING_METADATA_KEY.to_string(),
+            serde_json::to_string(&definitions).map_err(|e| Error::Other {
+                message: "Failed to serialize the embedding functions".to_string(),
+                source: Some(Box::new(e)),
+            })?,
+        );
+        let schema = Arc::new(Schema::new_with_metadata(fields, metadata));
+
+        Ok(Self {
+            inner,
+            embeddings,
+            schema,
+        })
+    }
+
+    fn embed(&self, batch: RecordBatch) -> Result<RecordBatch> {
+        let mut columns = batch.columns().to_vec();
+        for (definition, function) in &self.embeddings {
+            if batch.column_by_name(&definition.dest_column()).is_some() {
+                continue;
+            }
+            let source = batch[definition.source_column.as_str()].clone();
+            let embedding = function.compute_source_embeddings(source)?;
+            if embedding.len() != batch.num_rows() {
+                return Err(Error::Runtime {
+                    message: format!(
+                        "The embedding function '{}' returned {} embeddings for {} rows",
+                        function.name(),
+                        embedding.len(),
+                        batch.num_rows()
+                    ),
+                });
+            }
+            columns.push(embedding);
+        }
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+impl Iterator for WithEmbeddings {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = self.inner.next()?;
+        Some(batch.and_then(|batch| {
+            self.embed(batch)
+                .map_err(|e| ArrowError::ExternalError(Box::new(e)))
+        }))
+    }
+}
+
+impl RecordBatchReader for WithEmbeddings {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use arrow_array::{
+        cast::AsArray, types::Float32Type, FixedSizeListArray, RecordBatchIterator, StringArray,
+    };
+
+    use super::*;
+
+    /// An embedding function which maps each string to [length, number of spaces]
+    #[derive(Debug)]
+    pub struct MockEmbedding;
+
+    impl EmbeddingFunction for MockEmbedding {
+        fn name(&self) -> &str {
+            "mock"
+        }
+
+        fn source_type(&self) -> DataType {
+            DataType::Utf8
+        }
+
+        fn dest_type(&self) -> DataType {
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2)
+        }
+
+        fn compute_source_embeddings(&self, source: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+            let vectors = source.as_string::<i32>().iter().map(|text| {
+                text.map(|text| {
+                    vec![
+                        Some(text.len() as f32),
+                        Some(text.matches(' ').count() as f32),
+                    ]
+                })
+            });
+            Ok(Arc::new(FixedSizeListArray::from_iter_primitive::<
+                Float32Type,
+                _,
+                _,
+            >(vectors, 2)))
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = MemoryRegistry::new();
+        assert!(registry.get("mock").is_none());
+        registry.register("mock", Arc::new(MockEmbedding)).unwrap();
+        assert_eq!(registry.functions(), vec!["mock".to_string()]);
+        assert_eq!(registry.get("mock").unwrap().name(), "mock");
+    }
+
+    #[test]
+    fn test_with_embeddings() {
+        let schema = Arc::new(Schema::new(vec![Field::new("text", DataType::Utf8, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(StringArray::from(vec!["a b", "abcd"]))],
+        )
+        .unwrap();
+        let reader = Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema));
+        let definition = EmbeddingDefinition::new("text", "mock", None);
+        let mut reader =
+            WithEmbeddings::try_new(reader, vec![(definition.clone(), Arc::new(MockEmbedding))])
+                .unwrap();
+
+        let schema = reader.schema();
+        assert_eq!(
+            definitions_from_metadata(schema.metadata()).unwrap(),
+            vec![definition]
+        );
+        let batch = reader.next().unwrap().unwrap();
+        let vectors = batch["text_embedding"].as_fixed_size_list();
+        assert_eq!(
+            vectors.values().as_primitive::<Float32Type>().values(),
+            &[3.0, 1.0, 4.0, 0.0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_on_ingest_and_query() {
+        use futures::TryStreamExt;
+
+        use crate::connect;
+        use crate::query::{ExecutableQuery, QueryBase};
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        db.embedding_registry()
+            .register("mock", Arc::new(MockEmbedding))
+            .unwrap();
+
+        let schema = Arc::new(Schema::new(vec![Field::new("text", DataType::Utf8, false)]));
+        let make_data = |texts: Vec<&str>| {
+            let batch =
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(StringArray::from(texts))])
+                    .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema.clone())
+        };
+        let table = db
+            .create_table("test", make_data(vec!["a", "a b c"]))
+            .add_embedding(EmbeddingDefinition::new("text", "mock", None))
+            .execute()
+            .await
+            .unwrap();
+        table
+            .add(make_data(vec!["abcdefgh"]))
+            .execute()
+            .await
+            .unwrap();
+        let table_schema = table.schema().await.unwrap();
+        assert!(table_schema.field_with_name("text_embedding").is_ok());
+
+        let batches = table
+            .query()
+            .nearest_to("xy z")
+            .unwrap()
+            .limit(1)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batches[0]["text"].as_string::<i32>().value(0), "a b c");
+
+        // The function must be registered to add data to the table
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.open_table("test").execute().await.unwrap();
+        assert!(table.add(make_data(vec!["a"])).execute().await.is_err());
+    }
+}
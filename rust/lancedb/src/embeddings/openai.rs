@@ -0,0 +1,204 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Embeddings computed by the OpenAI embeddings API
+//!
+//! Any service which implements the same `/embeddings` endpoint (for example a
+//! local inference server) can be used by changing the base url.
+
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, Array};
+use arrow_schema::{DataType, Field};
+use serde::{Deserialize, Serialize};
+
+use super::{text_embeddings_array, EmbeddingFunction};
+use crate::error::{Error, Result};
+
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_MODEL: &str = "text-embedding-3-small";
+/// The maximum number of inputs sent in a single request
+const MAX_BATCH_SIZE: usize = 2048;
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [&'a str],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// An [`EmbeddingFunction`] which embeds text with an OpenAI compatible API
+///
+/// This is only available with the `openai` feature.
+///
+/// ```ignore
+/// let openai = OpenAIEmbeddingFunction::new(std::env::var("OPENAI_API_KEY")?);
+/// db.embedding_registry().register("openai", Arc::new(openai))?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct OpenAIEmbeddingFunction {
+    api_key: String,
+    model: String,
+    base_url: String,
+    dimensions: Option<usize>,
+}
+
+impl OpenAIEmbeddingFunction {
+    /// Create a function using the `text-embedding-3-small` model
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            model: DEFAULT_MODEL.to_string(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            dimensions: None,
+        }
+    }
+
+    /// The model to use, the default is `text-embedding-3-small`
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// The url of the API, the default is `https://api.openai.com/v1`
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// The number of dimensions in each embedding
+    ///
+    /// This must be set for models other than the OpenAI embedding models.  The
+    /// `text-embedding-3` models support shortening their embeddings to fewer
+    /// dimensions.
+    pub fn dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    fn dim(&self) -> Result<usize> {
+        if let Some(dimensions) = self.dimensions {
+            return Ok(dimensions);
+        }
+        match self.model.as_str() {
+            "text-embedding-3-small" | "text-embedding-ada-002" => Ok(1536),
+            "text-embedding-3-large" => Ok(3072),
+            model => Err(Error::InvalidInput {
+                message: format!(
+                    "the number of dimensions of the model '{}' is unknown, set it with dimensions()",
+                    model
+                ),
+            }),
+        }
+    }
+
+    fn request(&self, input: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let request = EmbeddingRequest {
+            model: &self.model,
+            input,
+            // Only send the dimensions if they were explicitly requested
+            dimensions: self.dimensions,
+        };
+        let response: EmbeddingResponse = ureq::post(&format!("{}/embeddings", self.base_url))
+            .set("Authorization", &format!("Bearer {}", self.api_key))
+            .send_json(&request)
+            .map_err(|e| Error::Http {
+                message: format!("OpenAI embeddings request failed: {}", e),
+            })?
+            .into_json()
+            .map_err(|e| Error::Http {
+                message: format!("Invalid OpenAI embeddings response: {}", e),
+            })?;
+
+        let mut data = response.data;
+        if data.len() != input.len() {
+            return Err(Error::Http {
+                message: format!(
+                    "OpenAI returned {} embeddings for {} inputs",
+                    data.len(),
+                    input.len()
+                ),
+            });
+        }
+        data.sort_by_key(|embedding| embedding.index);
+        Ok(data.into_iter().map(|data| data.embedding).collect())
+    }
+}
+
+impl EmbeddingFunction for OpenAIEmbeddingFunction {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn source_type(&self) -> DataType {
+        DataType::Utf8
+    }
+
+    fn dest_type(&self) -> DataType {
+        DataType::FixedSizeList(
+            Arc::new(Field::new("item", DataType::Float32, true)),
+            self.dim().unwrap_or_default() as i32,
+        )
+    }
+
+    fn compute_source_embeddings(&self, source: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        let dim = self.dim()?;
+        let texts = source
+            .as_string_opt::<i32>()
+            .ok_or_else(|| Error::InvalidInput {
+                message: format!(
+                    "OpenAI embeddings expect strings but the input was {}",
+                    source.data_type()
+                ),
+            })?;
+        let inputs = texts.iter().flatten().collect::<Vec<_>>();
+        let mut embeddings = Vec::with_capacity(inputs.len());
+        for chunk in inputs.chunks(MAX_BATCH_SIZE) {
+            embeddings.extend(self.request(chunk)?);
+        }
+        text_embeddings_array(texts, embeddings, dim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dimensions() {
+        let openai = OpenAIEmbeddingFunction::new("key");
+        assert_eq!(openai.dim().unwrap(), 1536);
+        assert_eq!(openai.clone().dimensions(256).dim().unwrap(), 256);
+        assert!(openai.clone().model("custom").dim().is_err());
+        assert_eq!(
+            openai
+                .model("text-embedding-3-large")
+                .base_url("http://localhost:8080/v1/")
+                .base_url,
+            "http://localhost:8080/v1"
+        );
+    }
+}
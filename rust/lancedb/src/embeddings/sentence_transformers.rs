@@ -0,0 +1,223 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Embeddings computed locally by a sentence transformer model
+//!
+//! The model is run with [candle](https://github.com/huggingface/candle) and its
+//! weights are downloaded from the Hugging Face hub the first time it is used.
+//! Only BERT based models (which includes most sentence transformers) are supported.
+
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, Array};
+use arrow_schema::{DataType, Field};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config};
+use hf_hub::{api::sync::Api, Repo, RepoType};
+use tokenizers::{PaddingParams, Tokenizer};
+
+use super::{text_embeddings_array, EmbeddingFunction};
+use crate::error::{Error, Result};
+
+const DEFAULT_MODEL: &str = "sentence-transformers/all-MiniLM-L6-v2";
+/// The number of texts run through the model at once
+const BATCH_SIZE: usize = 32;
+
+impl From<candle_core::Error> for Error {
+    fn from(source: candle_core::Error) -> Self {
+        Self::Other {
+            message: format!("Sentence transformer error: {}", source),
+            source: Some(Box::new(source)),
+        }
+    }
+}
+
+fn model_error(message: &str, e: impl std::fmt::Display) -> Error {
+    Error::Runtime {
+        message: format!("{}: {}", message, e),
+    }
+}
+
+/// Builder for a [`SentenceTransformersEmbeddings`]
+#[derive(Debug, Clone)]
+pub struct SentenceTransformersEmbeddingsBuilder {
+    model: String,
+    revision: String,
+    device: Device,
+}
+
+impl Default for SentenceTransformersEmbeddingsBuilder {
+    fn default() -> Self {
+        Self {
+            model: DEFAULT_MODEL.to_string(),
+            revision: "main".to_string(),
+            device: Device::Cpu,
+        }
+    }
+}
+
+impl SentenceTransformersEmbeddingsBuilder {
+    /// The Hugging Face model id, the default is `sentence-transformers/all-MiniLM-L6-v2`
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// The revision (branch, tag, or commit) of the model, the default is `main`
+    pub fn revision(mut self, revision: impl Into<String>) -> Self {
+        self.revision = revision.into();
+        self
+    }
+
+    /// The device to run the model on, the default is the CPU
+    pub fn device(mut self, device: Device) -> Self {
+        self.device = device;
+        self
+    }
+
+    /// Download (if needed) and load the model
+    pub fn build(self) -> Result<SentenceTransformersEmbeddings> {
+        let api = Api::new().map_err(|e| model_error("Failed to access the model hub", e))?;
+        let repo = api.repo(Repo::with_revision(
+            self.model.clone(),
+            RepoType::Model,
+            self.revision,
+        ));
+        let download = |file: &str| {
+            repo.get(file)
+                .map_err(|e| model_error(&format!("Failed to download {}", file), e))
+        };
+        let config = download("config.json")?;
+        let tokenizer = download("tokenizer.json")?;
+        let weights = download("model.safetensors")?;
+
+        let config = std::fs::read_to_string(config)
+            .map_err(|e| model_error("Failed to read the model config", e))?;
+        let config: Config =
+            serde_json::from_str(&config).map_err(|e| model_error("Invalid model config", e))?;
+        let mut tokenizer = Tokenizer::from_file(tokenizer)
+            .map_err(|e| model_error("Failed to load the tokenizer", e))?;
+        tokenizer.with_padding(Some(PaddingParams::default()));
+
+        // Safety: the weights file is not modified while it is mapped
+        let vb =
+            unsafe { VarBuilder::from_mmaped_safetensors(&[weights], DType::F32, &self.device)? };
+        let model = BertModel::load(vb, &config)?;
+
+        Ok(SentenceTransformersEmbeddings {
+            name: self.model,
+            model: Arc::new(model),
+            tokenizer: Arc::new(tokenizer),
+            device: self.device,
+            dim: config.hidden_size,
+        })
+    }
+}
+
+/// An [`EmbeddingFunction`] which embeds text with a local sentence transformer model
+///
+/// This is only available with the `sentence-transformers` feature.  Embeddings
+/// are the mean of the token embeddings, normalized to unit length.
+///
+/// ```ignore
+/// let model = SentenceTransformersEmbeddings::builder().build()?;
+/// db.embedding_registry().register("minilm", Arc::new(model))?;
+/// ```
+#[derive(Clone)]
+pub struct SentenceTransformersEmbeddings {
+    name: String,
+    model: Arc<BertModel>,
+    tokenizer: Arc<Tokenizer>,
+    device: Device,
+    dim: usize,
+}
+
+impl std::fmt::Debug for SentenceTransformersEmbeddings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SentenceTransformersEmbeddings")
+            .field("name", &self.name)
+            .field("device", &self.device)
+            .field("dim", &self.dim)
+            .finish()
+    }
+}
+
+impl SentenceTransformersEmbeddings {
+    pub fn builder() -> SentenceTransformersEmbeddingsBuilder {
+        SentenceTransformersEmbeddingsBuilder::default()
+    }
+
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts.to_vec(), true)
+            .map_err(|e| model_error("Failed to tokenize the input", e))?;
+        let token_ids = encodings
+            .iter()
+            .map(|encoding| Tensor::new(encoding.get_ids(), &self.device))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        let attention_mask = encodings
+            .iter()
+            .map(|encoding| Tensor::new(encoding.get_attention_mask(), &self.device))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        let token_ids = Tensor::stack(&token_ids, 0)?;
+        let attention_mask = Tensor::stack(&attention_mask, 0)?
+            .to_dtype(DType::F32)?
+            .unsqueeze(2)?;
+        let token_type_ids = token_ids.zeros_like()?;
+
+        // (batch, tokens, hidden) -> (batch, hidden), ignoring the padding tokens
+        let hidden = self.model.forward(&token_ids, &token_type_ids)?;
+        let summed = hidden.broadcast_mul(&attention_mask)?.sum(1)?;
+        let counts = attention_mask.sum(1)?;
+        let pooled = summed.broadcast_div(&counts)?;
+        let normalized = pooled.broadcast_div(&pooled.sqr()?.sum_keepdim(1)?.sqrt()?)?;
+        Ok(normalized.to_vec2::<f32>()?)
+    }
+}
+
+impl EmbeddingFunction for SentenceTransformersEmbeddings {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source_type(&self) -> DataType {
+        DataType::Utf8
+    }
+
+    fn dest_type(&self) -> DataType {
+        DataType::FixedSizeList(
+            Arc::new(Field::new("item", DataType::Float32, true)),
+            self.dim as i32,
+        )
+    }
+
+    fn compute_source_embeddings(&self, source: Arc<dyn Array>) -> Result<Arc<dyn Array>> {
+        let texts = source
+            .as_string_opt::<i32>()
+            .ok_or_else(|| Error::InvalidInput {
+                message: format!(
+                    "Sentence transformers expect strings but the input was {}",
+                    source.data_type()
+                ),
+            })?;
+        let inputs = texts.iter().flatten().collect::<Vec<_>>();
+        let mut embeddings = Vec::with_capacity(inputs.len());
+        for chunk in inputs.chunks(BATCH_SIZE) {
+            embeddings.extend(self.embed(chunk)?);
+        }
+        text_embeddings_array(texts, embeddings, self.dim)
+    }
+}
@@ -17,6 +17,8 @@ use std::sync::PoisonError;
 use arrow_schema::ArrowError;
 use snafu::Snafu;
 
+use crate::quota::QuotaLimit;
+
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
 pub enum Error {
@@ -28,6 +30,10 @@ pub enum Error {
     TableNotFound { name: String },
     #[snafu(display("Table '{name}' already exists"))]
     TableAlreadyExists { name: String },
+    #[snafu(display("Index '{name}' was not found"))]
+    IndexNotFound { name: String },
+    #[snafu(display("Namespace '{name}' was not found"))]
+    NamespaceNotFound { name: String },
     #[snafu(display("Unable to created lance dataset at {path}: {source}"))]
     CreateDir {
         path: String,
@@ -37,6 +43,97 @@ pub enum Error {
     Schema { message: String },
     #[snafu(display("Runtime error: {message}"))]
     Runtime { message: String },
+    #[snafu(display("Invalid filter \"{filter}\": {message} at column {column}"))]
+    InvalidFilter {
+        filter: String,
+        column: usize,
+        message: String,
+    },
+
+    #[snafu(display(
+        "Quota exceeded writing to table '{table}': {limit} would reach {usage} \
+         but the quota of the tables prefixed with \"{prefix}\" is {max}"
+    ))]
+    QuotaExceeded {
+        table: String,
+        prefix: String,
+        limit: QuotaLimit,
+        usage: u64,
+        max: u64,
+    },
+
+    #[snafu(display(
+        "Commit conflict on table '{table}': the {operation} conflicted with the concurrent \
+         commit of version {version} and failed after {attempts} attempt(s): {message}"
+    ))]
+    CommitConflict {
+        table: String,
+        operation: String,
+        version: u64,
+        attempts: u32,
+        message: String,
+    },
+
+    #[snafu(display(
+        "Too many queries: {running} queries are running and {queued} are waiting to run"
+    ))]
+    TooManyQueries { running: usize, queued: usize },
+
+    #[snafu(display("Query timed out after {timeout:?}"))]
+    QueryTimeout { timeout: std::time::Duration },
+
+    #[snafu(display("Query was cancelled"))]
+    QueryCancelled,
+
+    #[snafu(display(
+        "Primary key conflict on table '{table}': a row with the {column} {key} already exists"
+    ))]
+    PrimaryKeyConflict {
+        table: String,
+        column: String,
+        key: String,
+    },
+
+    #[snafu(display(
+        "Version {version} of table '{table}' is no longer available, it may have been \
+         cleaned up: {message}"
+    ))]
+    StaleVersion {
+        table: String,
+        version: u64,
+        message: String,
+    },
+
+    #[snafu(display("Column '{column}' has the unsupported data type {dtype}"))]
+    UnsupportedDataType { column: String, dtype: String },
+
+    #[snafu(display(
+        "The vectors of column '{column}' in batch {batch} do not match the table: \
+         expected dimension {expected_dim} of {expected_type}, got dimension {actual_dim} of \
+         {actual_type}"
+    ))]
+    VectorMismatch {
+        column: String,
+        batch: usize,
+        expected_dim: i32,
+        actual_dim: i32,
+        expected_type: String,
+        actual_type: String,
+    },
+
+    #[snafu(display("{num_rows} rows of column '{column}' are not covered by its vector index"))]
+    UnindexedRows { column: String, num_rows: u64 },
+
+    #[snafu(display("IO error: {message}"))]
+    Io {
+        message: String,
+        /// Whether the failure is transient, see [`Error::is_retryable`]
+        retryable: bool,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("LanceDB Cloud returned the status {status}: {message}"))]
+    Remote { status: u16, message: String },
 
     // 3rd party / external errors
     #[snafu(display("object_store error: {source}"))]
@@ -47,6 +144,8 @@ pub enum Error {
     Http { message: String },
     #[snafu(display("Arrow error: {source}"))]
     Arrow { source: ArrowError },
+    #[snafu(display("DataFusion error: {message}"))]
+    DataFusion { message: String },
     #[snafu(display("LanceDBError: not supported: {message}"))]
     NotSupported { message: String },
     #[snafu(whatever, display("{message}"))]
@@ -59,6 +158,138 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The class of an [`Error`], see [`Error::kind`]
+///
+/// Services use it to decide how to report an error, e.g. which status to
+/// return, instead of matching the variants or the messages of the errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A table, index, namespace or file does not exist
+    NotFound,
+    /// A table or file already exists
+    AlreadyExists,
+    /// The operation conflicted with a concurrent write, or with the rows of
+    /// the table
+    Conflict,
+    /// The arguments of the operation are not valid
+    InvalidInput,
+    /// The operation is not supported by the table or the connection
+    NotSupported,
+    /// A quota or a limit on concurrent queries was reached
+    ResourceExhausted,
+    /// The operation took too long
+    Timeout,
+    /// The operation was cancelled
+    Cancelled,
+    /// Reading or writing storage failed
+    Io,
+    /// LanceDB Cloud returned an error
+    Remote,
+    /// Any other error
+    Internal,
+}
+
+/// Whether an HTTP `status` is a transient failure of the server
+pub(crate) fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 429 | 502 | 503 | 504)
+}
+
+/// Whether an IO error of `kind` is transient
+fn is_retryable_io(kind: std::io::ErrorKind) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        kind,
+        Interrupted | TimedOut | WouldBlock | ConnectionReset | ConnectionAborted | BrokenPipe
+    )
+}
+
+impl Error {
+    /// An [`Error::Io`] for `source`, described by `message`
+    pub(crate) fn io(message: impl Into<String>, source: std::io::Error) -> Self {
+        Self::Io {
+            message: format!("{}: {}", message.into(), source),
+            retryable: is_retryable_io(source.kind()),
+            source: Box::new(source),
+        }
+    }
+
+    /// The class of the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::TableNotFound { .. }
+            | Self::IndexNotFound { .. }
+            | Self::NamespaceNotFound { .. }
+            | Self::StaleVersion { .. } => ErrorKind::NotFound,
+            Self::TableAlreadyExists { .. } => ErrorKind::AlreadyExists,
+            Self::CommitConflict { .. } | Self::PrimaryKeyConflict { .. } => ErrorKind::Conflict,
+            Self::InvalidTableName { .. }
+            | Self::InvalidInput { .. }
+            | Self::InvalidFilter { .. }
+            | Self::Schema { .. }
+            | Self::UnsupportedDataType { .. }
+            | Self::VectorMismatch { .. } => ErrorKind::InvalidInput,
+            Self::NotSupported { .. } => ErrorKind::NotSupported,
+            Self::QuotaExceeded { .. } | Self::TooManyQueries { .. } => {
+                ErrorKind::ResourceExhausted
+            }
+            Self::QueryTimeout { .. } => ErrorKind::Timeout,
+            Self::QueryCancelled => ErrorKind::Cancelled,
+            Self::CreateDir { .. } | Self::Io { .. } => ErrorKind::Io,
+            Self::ObjectStore { source } => match source {
+                object_store::Error::NotFound { .. } => ErrorKind::NotFound,
+                object_store::Error::AlreadyExists { .. } => ErrorKind::AlreadyExists,
+                object_store::Error::Precondition { .. } => ErrorKind::Conflict,
+                _ => ErrorKind::Io,
+            },
+            Self::Lance { source } => match source {
+                lance::Error::DatasetNotFound { .. } | lance::Error::NotFound { .. } => {
+                    ErrorKind::NotFound
+                }
+                lance::Error::DatasetAlreadyExists { .. } => ErrorKind::AlreadyExists,
+                lance::Error::CommitConflict { .. } => ErrorKind::Conflict,
+                lance::Error::IO { .. } => ErrorKind::Io,
+                _ => ErrorKind::Internal,
+            },
+            Self::Remote { status, .. } => match status {
+                400 | 422 => ErrorKind::InvalidInput,
+                404 => ErrorKind::NotFound,
+                409 => ErrorKind::Conflict,
+                429 => ErrorKind::ResourceExhausted,
+                501 => ErrorKind::NotSupported,
+                408 | 504 => ErrorKind::Timeout,
+                _ => ErrorKind::Remote,
+            },
+            Self::UnindexedRows { .. }
+            | Self::Runtime { .. }
+            | Self::Http { .. }
+            | Self::Arrow { .. }
+            | Self::DataFusion { .. }
+            | Self::Other { .. } => ErrorKind::Internal,
+        }
+    }
+
+    /// Whether the operation may succeed if it is run again
+    ///
+    /// This is true for conflicts with concurrent writes, which are already
+    /// retried a few times (see [`crate::table::WriteOptions::max_commit_retries`]),
+    /// for queries rejected because too many are running, and for transient
+    /// failures of storage or of LanceDB Cloud.  Errors of the object store
+    /// are not retryable, as the object store retries transient failures
+    /// itself.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::CommitConflict { .. } | Self::TooManyQueries { .. } => true,
+            Self::Lance {
+                source: lance::Error::CommitConflict { .. },
+            } => true,
+            Self::Io { retryable, .. } => *retryable,
+            Self::Remote { status, .. } => is_retryable_status(*status),
+            _ => false,
+        }
+    }
+}
+
 impl From<ArrowError> for Error {
     fn from(source: ArrowError) -> Self {
         Self::Arrow { source }
@@ -95,11 +326,33 @@ impl<T> From<PoisonError<T>> for Error {
     }
 }
 
-#[cfg(feature = "remote")]
+#[cfg(feature = "datafusion")]
+impl From<datafusion::error::DataFusionError> for Error {
+    fn from(e: datafusion::error::DataFusionError) -> Self {
+        Self::DataFusion {
+            message: e.to_string(),
+        }
+    }
+}
+
+#[cfg(any(feature = "remote", feature = "huggingface"))]
 impl From<reqwest::Error> for Error {
     fn from(e: reqwest::Error) -> Self {
-        Self::Http {
-            message: e.to_string(),
+        if let Some(status) = e.status() {
+            Self::Remote {
+                status: status.as_u16(),
+                message: e.to_string(),
+            }
+        } else if e.is_connect() || e.is_timeout() {
+            Self::Io {
+                message: e.to_string(),
+                retryable: true,
+                source: Box::new(e),
+            }
+        } else {
+            Self::Http {
+                message: e.to_string(),
+            }
         }
     }
 }
@@ -112,3 +365,50 @@ impl From<url::ParseError> for Error {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use super::*;
+
+    #[test]
+    fn test_kind() {
+        let err = Error::io(
+            "Failed to read data.json",
+            std::io::ErrorKind::TimedOut.into(),
+        );
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert!(err.is_retryable());
+        assert!(err.source().is_some());
+        let err = Error::io(
+            "Failed to read data.json",
+            std::io::ErrorKind::PermissionDenied.into(),
+        );
+        assert!(!err.is_retryable());
+
+        let remote = |status| Error::Remote {
+            status,
+            message: "error".to_string(),
+        };
+        assert_eq!(remote(404).kind(), ErrorKind::NotFound);
+        assert!(!remote(404).is_retryable());
+        assert_eq!(remote(503).kind(), ErrorKind::Remote);
+        assert!(remote(503).is_retryable());
+
+        let err = Error::CommitConflict {
+            table: "t".to_string(),
+            operation: "update".to_string(),
+            version: 2,
+            attempts: 1,
+            message: "conflict".to_string(),
+        };
+        assert_eq!(err.kind(), ErrorKind::Conflict);
+        assert!(err.is_retryable());
+        let err = Error::TableNotFound {
+            name: "t".to_string(),
+        };
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(!err.is_retryable());
+    }
+}
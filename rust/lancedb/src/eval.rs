@@ -0,0 +1,553 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Evaluation of the quality of vector indices
+//!
+//! [`RecallEvaluation`] compares the approximate searches made with the vector
+//! index of a column with exact searches.  A random sample of the vectors of the
+//! table are used as query vectors.  Each is searched without the index, and
+//! with the index once for each set of [`SearchParams`], and the results are
+//! compared by row id:
+//!
+//! * recall@k is the fraction of the exact `k` nearest neighbors found by the
+//!   approximate search
+//! * MRR, the mean reciprocal rank, is the mean of `1 / rank` of the exact
+//!   nearest neighbor in the results of the approximate search, 0 if it is
+//!   missing
+//!
+//! The query vectors are rows of the table, so each is normally its own
+//! nearest neighbor.  The query vectors can also be given, with
+//! [`RecallEvaluation::queries`], along with their known nearest neighbors if
+//! the exact searches are too slow to run.  [`evaluate_recall`] runs an
+//! evaluation of given queries in one call.  The report can be checked in CI
+//! before deploying a new index:
+//!
+//! ```no_run
+//! # use lancedb::eval::{RecallEvaluation, SearchParams};
+//! # async fn check(table: &lancedb::Table) -> lancedb::Result<()> {
+//! let report = RecallEvaluation::new(table)
+//!     .k(10)
+//!     .sample_size(200)
+//!     .search_params(SearchParams::grid(&[10, 20, 50], &[1, 5]))
+//!     .execute()
+//!     .await?;
+//! let best = report.fastest_with_recall(0.95).expect("the index is not accurate enough");
+//! println!("use {:?}", best.params);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::UInt64Type;
+use arrow_array::{Array, ArrayRef, RecordBatch};
+use futures::TryStreamExt;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::error::{Error, Result};
+use crate::query::{ExecutableQuery, QueryBase, Select, VectorQuery};
+use crate::utils::default_vector_column;
+use crate::{DistanceType, Table};
+
+const ROW_ID_COLUMN: &str = "_rowid";
+
+/// The parameters of an approximate search, see [`VectorQuery`]
+///
+/// Parameters left unset use the defaults of [`VectorQuery`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchParams {
+    /// See [`VectorQuery::nprobes`]
+    pub nprobes: Option<usize>,
+    /// See [`VectorQuery::refine_factor`]
+    pub refine_factor: Option<u32>,
+    /// See [`VectorQuery::ef`]
+    pub ef: Option<usize>,
+}
+
+impl SearchParams {
+    /// Every combination of the given numbers of partitions to probe and
+    /// refine factors
+    ///
+    /// If `refine_factors` is empty the results are not refined.
+    pub fn grid(nprobes: &[usize], refine_factors: &[u32]) -> Vec<Self> {
+        let refine_factors = if refine_factors.is_empty() {
+            vec![None]
+        } else {
+            refine_factors.iter().copied().map(Some).collect()
+        };
+        nprobes
+            .iter()
+            .flat_map(|nprobes| {
+                refine_factors.iter().map(move |refine_factor| Self {
+                    nprobes: Some(*nprobes),
+                    refine_factor: *refine_factor,
+                    ef: None,
+                })
+            })
+            .collect()
+    }
+
+    fn apply(&self, mut query: VectorQuery) -> VectorQuery {
+        if let Some(nprobes) = self.nprobes {
+            query = query.nprobes(nprobes);
+        }
+        if let Some(refine_factor) = self.refine_factor {
+            query = query.refine_factor(refine_factor);
+        }
+        if let Some(ef) = self.ef {
+            query = query.ef(ef);
+        }
+        query
+    }
+}
+
+/// A query vector of a [`RecallEvaluation`], with its exact nearest neighbors
+#[derive(Debug, Clone)]
+pub struct GroundTruthQuery {
+    /// The query vector
+    pub vector: ArrayRef,
+    /// The row ids of the exact nearest neighbors of the query vector, nearest
+    /// first
+    ///
+    /// When this is None they are found with an exact search.  Only the first
+    /// `k` are used.
+    pub neighbors: Option<Vec<u64>>,
+}
+
+impl GroundTruthQuery {
+    /// A query vector whose nearest neighbors are found with an exact search
+    pub fn new(vector: ArrayRef) -> Self {
+        Self {
+            vector,
+            neighbors: None,
+        }
+    }
+
+    /// A query vector whose nearest neighbors are known
+    pub fn with_neighbors(vector: ArrayRef, neighbors: Vec<u64>) -> Self {
+        Self {
+            vector,
+            neighbors: Some(neighbors),
+        }
+    }
+}
+
+/// The quality of the approximate searches made with one set of parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamsReport {
+    /// The parameters of the searches
+    pub params: SearchParams,
+    /// The mean recall@k of the searches, between 0 and 1
+    pub recall: f64,
+    /// The mean reciprocal rank of the exact nearest neighbor, between 0 and 1
+    pub mrr: f64,
+    /// The mean time taken by a search
+    pub mean_latency: Duration,
+    /// The median time taken by a search
+    pub p50_latency: Duration,
+    /// The 95th percentile of the time taken by a search
+    pub p95_latency: Duration,
+    /// The 99th percentile of the time taken by a search
+    pub p99_latency: Duration,
+}
+
+/// The result of a [`RecallEvaluation`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecallReport {
+    /// The vector column searched
+    pub column: String,
+    /// The number of results of each search
+    pub k: usize,
+    /// The number of query vectors
+    pub num_queries: usize,
+    /// The quality of the searches, for each set of parameters in the order they
+    /// were given
+    pub results: Vec<ParamsReport>,
+}
+
+impl RecallReport {
+    /// The parameters with the lowest mean latency among those reaching a recall
+    /// of at least `min_recall`
+    pub fn fastest_with_recall(&self, min_recall: f64) -> Option<&ParamsReport> {
+        self.results
+            .iter()
+            .filter(|result| result.recall >= min_recall)
+            .min_by_key(|result| result.mean_latency)
+    }
+}
+
+/// A builder used to evaluate the vector index of a column
+///
+/// See the [module docs](self).
+#[derive(Clone)]
+pub struct RecallEvaluation {
+    table: Table,
+    column: Option<String>,
+    k: usize,
+    sample_size: usize,
+    seed: u64,
+    distance_type: Option<DistanceType>,
+    params: Vec<SearchParams>,
+    queries: Vec<GroundTruthQuery>,
+}
+
+impl RecallEvaluation {
+    /// Evaluate the vector index of `table`
+    pub fn new(table: &Table) -> Self {
+        Self {
+            table: table.clone(),
+            column: None,
+            k: 10,
+            sample_size: 100,
+            seed: 0,
+            distance_type: None,
+            params: Vec::new(),
+            queries: Vec::new(),
+        }
+    }
+
+    /// The vector column to evaluate
+    ///
+    /// By default this is the only vector column of the table.
+    pub fn column(mut self, column: impl Into<String>) -> Self {
+        self.column = Some(column.into());
+        self
+    }
+
+    /// The number of results of each search, 10 by default
+    pub fn k(mut self, k: usize) -> Self {
+        self.k = k;
+        self
+    }
+
+    /// The number of query vectors sampled from the table, 100 by default
+    ///
+    /// This is not used if the query vectors are given with [`Self::queries`].
+    pub fn sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = sample_size;
+        self
+    }
+
+    /// The seed of the sampling of the query vectors, 0 by default
+    ///
+    /// The same seed samples the same query vectors from the same data.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// The distance type of the searches
+    ///
+    /// This should be the distance type the index was trained with.  See
+    /// [`VectorQuery::distance_type`] for the default.
+    pub fn distance_type(mut self, distance_type: DistanceType) -> Self {
+        self.distance_type = Some(distance_type);
+        self
+    }
+
+    /// Evaluate the approximate searches made with each of `params`
+    ///
+    /// This can be called several times.  By default only the default
+    /// parameters are evaluated.
+    pub fn search_params(mut self, params: impl IntoIterator<Item = SearchParams>) -> Self {
+        self.params.extend(params);
+        self
+    }
+
+    /// Search `queries` instead of vectors sampled from the table
+    ///
+    /// This can be called several times.
+    pub fn queries(mut self, queries: impl IntoIterator<Item = GroundTruthQuery>) -> Self {
+        self.queries.extend(queries);
+        self
+    }
+
+    /// Sample `sample_size` vectors of `column`, skipping the null vectors
+    async fn sample_vectors(&self, column: &str) -> Result<Vec<ArrayRef>> {
+        let mut rng = SmallRng::seed_from_u64(self.seed);
+        let mut stream = self
+            .table
+            .query()
+            .select(Select::columns(&[column]))
+            .execute()
+            .await?;
+        // Reservoir sampling, so that the table is read once
+        let mut sample = Vec::with_capacity(self.sample_size);
+        let mut seen = 0_usize;
+        while let Some(batch) = stream.try_next().await? {
+            let vectors =
+                batch
+                    .column(0)
+                    .as_fixed_size_list_opt()
+                    .ok_or_else(|| Error::InvalidInput {
+                        message: format!("the column {} is not a vector column", column),
+                    })?;
+            for row in 0..vectors.len() {
+                if vectors.is_null(row) {
+                    continue;
+                }
+                seen += 1;
+                if sample.len() < self.sample_size {
+                    sample.push(vectors.value(row));
+                } else {
+                    let slot = rng.gen_range(0..seen);
+                    if slot < self.sample_size {
+                        sample[slot] = vectors.value(row);
+                    }
+                }
+            }
+        }
+        Ok(sample)
+    }
+
+    /// The row ids of the results of `query`, and the time taken by the search
+    async fn search(&self, query: VectorQuery) -> Result<(Vec<u64>, Duration)> {
+        let mut query = query.limit(self.k);
+        if let Some(distance_type) = self.distance_type {
+            query = query.distance_type(distance_type);
+        }
+        query.base.with_row_id = true;
+        let start = Instant::now();
+        let batches = query.execute().await?.try_collect::<Vec<_>>().await?;
+        let latency = start.elapsed();
+        Ok((row_ids(&batches)?, latency))
+    }
+
+    /// Run the searches and compare their results
+    pub async fn execute(self) -> Result<RecallReport> {
+        if self.k == 0 || self.sample_size == 0 {
+            return Err(Error::InvalidInput {
+                message: "k and the sample size must be greater than 0".to_string(),
+            });
+        }
+        let column = match &self.column {
+            Some(column) => column.clone(),
+            None => {
+                let schema = self.table.schema().await?;
+                default_vector_column(&schema, None)?
+            }
+        };
+        let queries = if self.queries.is_empty() {
+            self.sample_vectors(&column)
+                .await?
+                .into_iter()
+                .map(GroundTruthQuery::new)
+                .collect()
+        } else {
+            self.queries.clone()
+        };
+        if queries.is_empty() {
+            return Err(Error::InvalidInput {
+                message: format!("the column {} has no vectors to search", column),
+            });
+        }
+        let params = if self.params.is_empty() {
+            vec![SearchParams::default()]
+        } else {
+            self.params.clone()
+        };
+
+        let mut exact = Vec::with_capacity(queries.len());
+        for query in &queries {
+            let neighbors = match &query.neighbors {
+                Some(neighbors) => neighbors.iter().take(self.k).copied().collect(),
+                None => {
+                    let search = self
+                        .table
+                        .vector_search(query.vector.clone())?
+                        .column(&column)
+                        .bypass_vector_index();
+                    self.search(search).await?.0
+                }
+            };
+            exact.push(neighbors);
+        }
+
+        let mut results = Vec::with_capacity(params.len());
+        for params in params {
+            let mut recall = 0.0;
+            let mut mrr = 0.0;
+            let mut latencies = Vec::with_capacity(queries.len());
+            for (query, exact) in queries.iter().zip(&exact) {
+                let query = self
+                    .table
+                    .vector_search(query.vector.clone())?
+                    .column(&column);
+                let (approximate, latency) = self.search(params.apply(query)).await?;
+                latencies.push(latency);
+                recall += recall_at_k(exact, &approximate);
+                mrr += reciprocal_rank(exact, &approximate);
+            }
+            latencies.sort_unstable();
+            let num_queries = latencies.len();
+            results.push(ParamsReport {
+                params,
+                recall: recall / num_queries as f64,
+                mrr: mrr / num_queries as f64,
+                mean_latency: latencies.iter().sum::<Duration>() / num_queries as u32,
+                p50_latency: percentile(&latencies, 50),
+                p95_latency: percentile(&latencies, 95),
+                p99_latency: percentile(&latencies, 99),
+            });
+        }
+        Ok(RecallReport {
+            column,
+            k: self.k,
+            num_queries: queries.len(),
+            results,
+        })
+    }
+}
+
+/// Evaluate the vector index of the only vector column of `table` with `queries`
+///
+/// This is a [`RecallEvaluation`] of the given queries, which returns `k`
+/// results with each set of parameters of `params_grid`, see
+/// [`SearchParams::grid`].
+pub async fn evaluate_recall(
+    table: &Table,
+    queries: impl IntoIterator<Item = GroundTruthQuery>,
+    k: usize,
+    params_grid: impl IntoIterator<Item = SearchParams>,
+) -> Result<RecallReport> {
+    RecallEvaluation::new(table)
+        .queries(queries)
+        .k(k)
+        .search_params(params_grid)
+        .execute()
+        .await
+}
+
+/// The `p`th percentile of the sorted, non empty, `latencies`
+fn percentile(latencies: &[Duration], p: usize) -> Duration {
+    latencies[(latencies.len() * p).div_ceil(100).max(1) - 1]
+}
+
+fn row_ids(batches: &[RecordBatch]) -> Result<Vec<u64>> {
+    let mut row_ids = Vec::new();
+    for batch in batches {
+        let column = batch
+            .column_by_name(ROW_ID_COLUMN)
+            .ok_or_else(|| Error::Runtime {
+                message: "the results of the search have no row ids".to_string(),
+            })?;
+        row_ids.extend(column.as_primitive::<UInt64Type>().values().iter());
+    }
+    Ok(row_ids)
+}
+
+/// The fraction of the `exact` results found in the `approximate` results
+fn recall_at_k(exact: &[u64], approximate: &[u64]) -> f64 {
+    if exact.is_empty() {
+        return 1.0;
+    }
+    let approximate = approximate.iter().collect::<HashSet<_>>();
+    let found = exact.iter().filter(|id| approximate.contains(id)).count();
+    found as f64 / exact.len() as f64
+}
+
+/// `1 / rank` of the exact nearest neighbor in the `approximate` results
+fn reciprocal_rank(exact: &[u64], approximate: &[u64]) -> f64 {
+    let Some(nearest) = exact.first() else {
+        return 1.0;
+    };
+    approximate
+        .iter()
+        .position(|id| id == nearest)
+        .map_or(0.0, |rank| 1.0 / (rank + 1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{FixedSizeListArray, Float32Array, RecordBatchIterator};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    #[test]
+    fn test_metrics() {
+        assert_eq!(recall_at_k(&[1, 2, 3, 4], &[4, 1, 7, 8]), 0.5);
+        assert_eq!(reciprocal_rank(&[1, 2], &[3, 1]), 0.5);
+        assert_eq!(reciprocal_rank(&[1, 2], &[3, 4]), 0.0);
+        assert_eq!(SearchParams::grid(&[1, 2], &[1, 10]).len(), 4);
+        assert_eq!(SearchParams::grid(&[1, 2], &[])[1].refine_factor, None);
+        let latencies = (1..=200).map(Duration::from_millis).collect::<Vec<_>>();
+        assert_eq!(percentile(&latencies, 50), Duration::from_millis(100));
+        assert_eq!(percentile(&latencies, 99), Duration::from_millis(198));
+        assert_eq!(percentile(&latencies[..1], 99), Duration::from_millis(1));
+    }
+
+    #[tokio::test]
+    async fn test_recall_without_index() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let field = Arc::new(Field::new("item", DataType::Float32, true));
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "vector",
+            DataType::FixedSizeList(field.clone(), 4),
+            true,
+        )]));
+        let values = Float32Array::from_iter_values((0..400).map(|i| i as f32));
+        let vectors = FixedSizeListArray::try_new(field, 4, Arc::new(values), None).unwrap();
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(vectors)]).unwrap();
+        let table = db
+            .create_table("test", RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await
+            .unwrap();
+
+        // Without an index the searches are exact
+        let report = RecallEvaluation::new(&table)
+            .k(5)
+            .sample_size(10)
+            .search_params(SearchParams::grid(&[1, 2], &[]))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(report.column, "vector");
+        assert_eq!(report.num_queries, 10);
+        assert_eq!(report.results.len(), 2);
+        for result in &report.results {
+            assert_eq!(result.recall, 1.0);
+            assert_eq!(result.mrr, 1.0);
+        }
+        assert!(report.fastest_with_recall(0.99).is_some());
+        assert!(report.results[0].p50_latency <= report.results[0].p99_latency);
+
+        // Given queries, one with wrong neighbors
+        let query = |i: i32| -> ArrayRef {
+            Arc::new(Float32Array::from_iter_values(
+                (i * 4..i * 4 + 4).map(|v| v as f32),
+            ))
+        };
+        let queries = vec![
+            GroundTruthQuery::new(query(3)),
+            GroundTruthQuery::with_neighbors(query(7), vec![1000, 1001]),
+        ];
+        let report = evaluate_recall(&table, queries, 2, SearchParams::grid(&[1], &[]))
+            .await
+            .unwrap();
+        assert_eq!(report.num_queries, 2);
+        assert_eq!(report.results[0].recall, 0.5);
+        assert_eq!(report.results[0].mrr, 0.5);
+    }
+}
@@ -12,19 +12,63 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use crate::{table::TableInternal, Result};
+use arrow_schema::DataType;
+use tokio::task::JoinHandle;
 
-use self::{scalar::BTreeIndexBuilder, vector::IvfPqIndexBuilder};
+use crate::{
+    error::{Error, Result},
+    table::TableInternal,
+};
 
+#[cfg(feature = "fts")]
+use self::fts::FtsIndexBuilder;
+use self::{
+    scalar::BTreeIndexBuilder,
+    vector::{
+        suggested_num_partitions, IndexFootprint, IvfHnswPqIndexBuilder, IvfHnswSqIndexBuilder,
+        IvfPqIndexBuilder,
+    },
+};
+
+#[cfg(feature = "fts")]
+pub mod fts;
+#[cfg(any(feature = "cuda", test))]
+pub(crate) mod kmeans;
 pub mod scalar;
 pub mod vector;
 
+#[derive(Debug, Clone)]
 pub enum Index {
     Auto,
+    /// A scalar index used by filters on the column, see [`BTreeIndexBuilder`]
     BTree(BTreeIndexBuilder),
     IvfPq(IvfPqIndexBuilder),
+    /// An IVF index with an HNSW graph in each partition, see [`IvfHnswPqIndexBuilder`]
+    IvfHnswPq(IvfHnswPqIndexBuilder),
+    /// An IVF index with an HNSW graph in each partition, see [`IvfHnswSqIndexBuilder`]
+    IvfHnswSq(IvfHnswSqIndexBuilder),
+    /// A full text search index, see [`FtsIndexBuilder`]
+    #[cfg(feature = "fts")]
+    Fts(FtsIndexBuilder),
+}
+
+impl Index {
+    /// Estimate the memory used by this index on `num_rows` vectors of dimension `dim`
+    ///
+    /// This compares the size of the index types, and of their parameters, to
+    /// choose an index which fits in memory.  Returns None for the indices
+    /// which are not vector indices, and for [`Index::Auto`] whose type depends
+    /// on the column, see [`IndexBuilder::memory_footprint`].
+    pub fn memory_footprint(&self, num_rows: usize, dim: u32) -> Option<IndexFootprint> {
+        match self {
+            Self::IvfPq(ivf_pq) => Some(ivf_pq.footprint(num_rows, dim)),
+            Self::IvfHnswPq(ivf_hnsw_pq) => Some(ivf_hnsw_pq.footprint(num_rows, dim)),
+            Self::IvfHnswSq(ivf_hnsw_sq) => Some(ivf_hnsw_sq.footprint(num_rows, dim)),
+            _ => None,
+        }
+    }
 }
 
 /// Builder for the create_index operation
@@ -57,19 +101,200 @@ impl IndexBuilder {
         self
     }
 
+    /// Estimate the memory used by the index, without building it
+    ///
+    /// The estimate is that of [`Index::memory_footprint`] for the current
+    /// number of rows of the table and the dimension of the column.  Returns
+    /// an error if the index is not a vector index.
+    pub async fn memory_footprint(&self) -> Result<IndexFootprint> {
+        let [column] = self.columns.as_slice() else {
+            return Err(Error::InvalidInput {
+                message: "the footprint of an index is estimated for a single column".to_string(),
+            });
+        };
+        let schema = self.parent.schema().await?;
+        let DataType::FixedSizeList(_, dim) = schema.field_with_name(column)?.data_type() else {
+            return Err(Error::InvalidInput {
+                message: format!("the column '{}' is not a vector column", column),
+            });
+        };
+        let num_rows = self.parent.count_rows(None).await?;
+        let index = match &self.index {
+            Index::Auto => Index::IvfPq(IvfPqIndexBuilder::default()),
+            index => index.clone(),
+        };
+        index
+            .memory_footprint(num_rows, *dim as u32)
+            .ok_or_else(|| Error::InvalidInput {
+                message: format!("the index on the column '{}' is not a vector index", column),
+            })
+    }
+
     pub async fn execute(self) -> Result<()> {
         self.parent.clone().create_index(self).await
     }
+
+    /// Build the index in the background
+    ///
+    /// The returned [`IndexJob`] can be used to follow the progress of the
+    /// build, wait for it to finish, or cancel it.  This must be called from
+    /// within a tokio runtime.
+    pub fn spawn(self) -> IndexJob {
+        let progress = Arc::new(Mutex::new(IndexProgress::default()));
+        let task_progress = progress.clone();
+        let handle = tokio::spawn(async move {
+            let result = self.execute_with_progress(&task_progress).await;
+            update_progress(&task_progress, |progress| match &result {
+                Ok(()) => {
+                    progress.stage = IndexBuildStage::Done;
+                    progress.rows_processed = progress.total_rows;
+                    progress.partitions_trained = progress.num_partitions.unwrap_or_default();
+                }
+                Err(_) => progress.stage = IndexBuildStage::Failed,
+            });
+            result
+        });
+        IndexJob { progress, handle }
+    }
+
+    async fn execute_with_progress(self, progress: &Mutex<IndexProgress>) -> Result<()> {
+        let total_rows = self.parent.count_rows(None).await?;
+        let num_partitions = match &self.index {
+            Index::IvfPq(ivf_pq) => Some(ivf_pq.num_partitions),
+            Index::IvfHnswPq(ivf_hnsw_pq) => Some(ivf_hnsw_pq.num_partitions),
+            Index::IvfHnswSq(ivf_hnsw_sq) => Some(ivf_hnsw_sq.num_partitions),
+            Index::Auto => {
+                let schema = self.parent.schema().await?;
+                let is_vector = self
+                    .columns
+                    .first()
+                    .and_then(|column| schema.field_with_name(column).ok())
+                    .is_some_and(|field| {
+                        matches!(field.data_type(), DataType::FixedSizeList(_, _))
+                    });
+                is_vector.then_some(None)
+            }
+            _ => None,
+        }
+        .map(|num_partitions| {
+            num_partitions.unwrap_or_else(|| suggested_num_partitions(total_rows)) as usize
+        });
+        update_progress(progress, |progress| {
+            progress.stage = IndexBuildStage::Building;
+            progress.total_rows = total_rows;
+            progress.num_partitions = num_partitions;
+        });
+        self.execute().await
+    }
+}
+
+fn update_progress(progress: &Mutex<IndexProgress>, update: impl FnOnce(&mut IndexProgress)) {
+    update(&mut progress.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// The stage of an index build, see [`IndexProgress`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IndexBuildStage {
+    /// The build has not started yet
+    #[default]
+    Pending,
+    /// The index is being trained and the rows are being indexed
+    Building,
+    /// The index was created
+    Done,
+    /// The build failed, the error is returned by [`IndexJob::await_done`]
+    Failed,
+    /// The build was cancelled with [`IndexJob::cancel`]
+    Cancelled,
+}
+
+/// The progress of an index being built in the background
+///
+/// The counters are updated as each stage of the build completes, they do not
+/// advance while the index is being trained.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexProgress {
+    /// The current stage of the build
+    pub stage: IndexBuildStage,
+    /// The number of rows in the table when the build started
+    pub total_rows: usize,
+    /// The number of rows which have been indexed
+    pub rows_processed: usize,
+    /// The number of IVF partitions, None if the index is not partitioned
+    pub num_partitions: Option<usize>,
+    /// The number of IVF partitions which have been trained
+    pub partitions_trained: usize,
+}
+
+/// A handle to an index being built in the background
+///
+/// Created by [`IndexBuilder::spawn`] or [`crate::Table::create_index_async`].
+/// Dropping the handle does not stop the build, use [`Self::cancel`] for that.
+pub struct IndexJob {
+    progress: Arc<Mutex<IndexProgress>>,
+    handle: JoinHandle<Result<()>>,
+}
+
+impl IndexJob {
+    /// The current progress of the build
+    pub fn progress(&self) -> IndexProgress {
+        self.progress
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Returns true if the build has finished, failed, or been cancelled
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stop the build
+    ///
+    /// An index is only added to the table when the build completes so a
+    /// cancelled build leaves the table unchanged.
+    pub fn cancel(&self) {
+        self.handle.abort();
+        update_progress(&self.progress, |progress| {
+            if matches!(
+                progress.stage,
+                IndexBuildStage::Pending | IndexBuildStage::Building
+            ) {
+                progress.stage = IndexBuildStage::Cancelled;
+            }
+        });
+    }
+
+    /// Wait for the build to finish
+    ///
+    /// Returns an error if the build failed or was cancelled.
+    pub async fn await_done(self) -> Result<()> {
+        match self.handle.await {
+            Ok(result) => result,
+            Err(err) if err.is_cancelled() => Err(Error::Runtime {
+                message: "the index build was cancelled".to_string(),
+            }),
+            Err(err) => Err(Error::Runtime {
+                message: format!("the index build failed: {}", err),
+            }),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum IndexType {
     IvfPq,
     BTree,
+    #[cfg(feature = "fts")]
+    Fts,
 }
 
 /// A description of an index currently configured on a column
+#[derive(Debug, Clone, PartialEq)]
 pub struct IndexConfig {
+    /// The name of the index, used by [`crate::Table::index_stats`] and
+    /// [`crate::Table::drop_index`]
+    pub name: String,
     /// The type of the index
     pub index_type: IndexType,
     /// The columns in the index
@@ -78,3 +303,17 @@ pub struct IndexConfig {
     /// be more columns to represent composite indices.
     pub columns: Vec<String>,
 }
+
+/// Statistics about an index, see [`crate::Table::index_stats`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexStatistics {
+    /// The type of the index
+    pub index_type: IndexType,
+    /// The number of rows covered by the index
+    pub num_indexed_rows: usize,
+    /// The number of rows which are not covered by the index
+    ///
+    /// These rows were added after the index was created or last optimized.
+    /// Searches still find them but have to scan them without the index.
+    pub num_unindexed_rows: usize,
+}
@@ -0,0 +1,382 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Full text search indices are inverted indices over string columns that are
+//! used to find rows containing the terms of a text query.  Matching rows are
+//! ranked with BM25.
+//!
+//! The index is built with [tantivy](https://github.com/quickwit-oss/tantivy) and
+//! is stored alongside the table in `_indices/fts`.  It is currently only supported
+//! for tables on the local filesystem.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::AsArray, types::UInt64Type, Array, Float32Array, RecordBatch, UInt64Array,
+};
+use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema};
+use futures::TryStreamExt;
+use lance::Dataset;
+use snafu::ResultExt;
+use tantivy::collector::TopDocs;
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, Schema, INDEXED, STORED, TEXT};
+use tantivy::Document;
+
+use crate::arrow::{SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{CreateDirSnafu, Error, Result};
+use crate::query::{Query, QueryExecutionOptions, Select, DEFAULT_TOP_K};
+
+/// The directory, relative to the table, where the index is stored
+const FTS_INDEX_DIR: &str = "_indices/fts";
+/// The name of the index, as reported by [`crate::Table::list_indices`]
+pub(crate) const FTS_INDEX_NAME: &str = "fts";
+/// The field in the index which stores the row id of each document
+const ROW_ID_FIELD: &str = "row_id";
+/// The name of the lance row id column
+pub(crate) const ROW_ID_COLUMN: &str = "_rowid";
+/// The name of the column containing the BM25 score in query results
+pub const SCORE_COLUMN: &str = "_score";
+
+/// Builder for a full text search index.
+///
+/// The index tokenizes the text in one or more string columns and stores a list
+/// of the rows containing each term.  If multiple columns are given then a single
+/// index is created and queries will search all of them.
+///
+/// The index is not updated as data is added to the table.  Rows added after the
+/// index is created will not be found by full text search until the index is
+/// recreated.
+#[derive(Debug, Clone)]
+pub struct FtsIndexBuilder {
+    pub(crate) writer_heap_size: usize,
+}
+
+impl Default for FtsIndexBuilder {
+    fn default() -> Self {
+        Self {
+            writer_heap_size: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl FtsIndexBuilder {
+    /// The amount of memory, in bytes, used to buffer documents while building the index.
+    ///
+    /// The default is 1GiB.  Larger values create fewer, larger, segments which are
+    /// faster to search.
+    pub fn writer_heap_size(mut self, writer_heap_size: usize) -> Self {
+        self.writer_heap_size = writer_heap_size;
+        self
+    }
+}
+
+impl From<tantivy::TantivyError> for Error {
+    fn from(source: tantivy::TantivyError) -> Self {
+        Self::Other {
+            message: format!("Full text search index error: {}", source),
+            source: Some(Box::new(source)),
+        }
+    }
+}
+
+fn index_path(table_uri: &str) -> Result<PathBuf> {
+    let table_path = match url::Url::parse(table_uri) {
+        // Single letter schemes are windows drive letters
+        Ok(url) if url.scheme().len() > 1 => {
+            if url.scheme() != "file" {
+                return Err(Error::NotSupported {
+                    message: format!(
+                        "full text search is only supported for local tables, not {}",
+                        table_uri
+                    ),
+                });
+            }
+            url.to_file_path().map_err(|_| Error::InvalidInput {
+                message: format!("Invalid table uri {}", table_uri),
+            })?
+        }
+        _ => PathBuf::from(table_uri),
+    };
+    Ok(table_path.join(FTS_INDEX_DIR))
+}
+
+/// A full text search index that has been opened for searching
+pub(crate) struct FtsIndex {
+    index: tantivy::Index,
+    row_id: Field,
+}
+
+impl FtsIndex {
+    /// Returns true if the table has a full text search index
+    pub(crate) fn exists(table_uri: &str) -> bool {
+        index_path(table_uri)
+            .map(|path| path.exists())
+            .unwrap_or(false)
+    }
+
+    /// Build a new index from `batches` which must contain the `columns` to
+    /// index and the lance row id column
+    pub(crate) async fn create(
+        table_uri: &str,
+        columns: &[String],
+        builder: &FtsIndexBuilder,
+        replace: bool,
+        mut batches: SendableRecordBatchStream,
+    ) -> Result<()> {
+        let path = index_path(table_uri)?;
+        if path.exists() {
+            if !replace {
+                return Err(Error::InvalidInput {
+                    message: "A full text search index already exists on this table".to_string(),
+                });
+            }
+            std::fs::remove_dir_all(&path).map_err(|e| Error::Runtime {
+                message: format!("Failed to remove the existing index: {}", e),
+            })?;
+        }
+        std::fs::create_dir_all(&path).context(CreateDirSnafu {
+            path: path.to_string_lossy(),
+        })?;
+
+        let mut schema = Schema::builder();
+        let row_id = schema.add_u64_field(ROW_ID_FIELD, INDEXED | STORED);
+        let fields = columns
+            .iter()
+            .map(|column| schema.add_text_field(column, TEXT))
+            .collect::<Vec<_>>();
+        let index = tantivy::Index::create_in_dir(&path, schema.build())?;
+        let mut writer = index.writer(builder.writer_heap_size)?;
+
+        while let Some(batch) = batches.try_next().await? {
+            let row_ids = batch
+                .column_by_name(ROW_ID_COLUMN)
+                .ok_or_else(|| Error::Runtime {
+                    message: "The row id column was not returned by the scan".to_string(),
+                })?
+                .as_primitive::<UInt64Type>()
+                .clone();
+            let texts = columns
+                .iter()
+                .map(|column| arrow_cast::cast(&batch[column.as_str()], &DataType::LargeUtf8))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            for row in 0..batch.num_rows() {
+                let mut doc = Document::default();
+                for (field, text) in fields.iter().zip(texts.iter()) {
+                    if text.is_valid(row) {
+                        doc.add_text(*field, text.as_string::<i64>().value(row));
+                    }
+                }
+                if !doc.is_empty() {
+                    doc.add_u64(row_id, row_ids.value(row));
+                    writer.add_document(doc)?;
+                }
+            }
+        }
+        writer.commit()?;
+        Ok(())
+    }
+
+    pub(crate) fn open(table_uri: &str) -> Result<Self> {
+        let path = index_path(table_uri)?;
+        if !path.exists() {
+            return Err(Error::InvalidInput {
+                message:
+                    "There is no full text search index on this table, create one with Index::Fts"
+                        .to_string(),
+            });
+        }
+        let index = tantivy::Index::open_in_dir(path)?;
+        let row_id = index.schema().get_field(ROW_ID_FIELD)?;
+        Ok(Self { index, row_id })
+    }
+
+    fn text_fields(&self) -> Vec<Field> {
+        self.index
+            .schema()
+            .fields()
+            .map(|(field, _)| field)
+            .filter(|field| *field != self.row_id)
+            .collect()
+    }
+
+    /// The names of the columns covered by the index
+    pub(crate) fn columns(&self) -> Vec<String> {
+        let schema = self.index.schema();
+        self.text_fields()
+            .into_iter()
+            .map(|field| schema.get_field_name(field).to_string())
+            .collect()
+    }
+
+    /// The number of rows in the index
+    pub(crate) fn num_rows(&self) -> Result<usize> {
+        Ok(self.index.reader()?.searcher().num_docs() as usize)
+    }
+
+    /// Delete the index of the table at `table_uri`
+    pub(crate) fn drop(table_uri: &str) -> Result<()> {
+        let path = index_path(table_uri)?;
+        std::fs::remove_dir_all(&path).map_err(|e| Error::Runtime {
+            message: format!(
+                "Failed to remove the full text search index at {}: {}",
+                path.display(),
+                e
+            ),
+        })
+    }
+
+    /// Search the index for `text`
+    ///
+    /// Returns the row id and BM25 score of the matching rows, ordered from most
+    /// to least relevant.  If `limit` is None then all matching rows are returned.
+    pub(crate) fn search(&self, text: &str, limit: Option<usize>) -> Result<Vec<(u64, f32)>> {
+        let reader = self.index.reader()?;
+        let searcher = reader.searcher();
+        let limit = limit.unwrap_or(searcher.num_docs() as usize);
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        let parser = QueryParser::for_index(&self.index, self.text_fields());
+        let query = parser.parse_query(text).map_err(|e| Error::InvalidInput {
+            message: format!("Invalid full text search query '{}': {}", text, e),
+        })?;
+        searcher
+            .search(&query, &TopDocs::with_limit(limit))?
+            .into_iter()
+            .map(|(score, address)| {
+                let doc = searcher.doc(address)?;
+                let row_id = doc
+                    .get_first(self.row_id)
+                    .and_then(|value| value.as_u64())
+                    .ok_or_else(|| Error::Runtime {
+                        message: "The full text search index is missing a row id".to_string(),
+                    })?;
+                Ok((row_id, score))
+            })
+            .collect()
+    }
+}
+
+/// Run a full text search `query` against `dataset`
+///
+/// The results contain the selected columns of the matching rows, ordered by
+/// relevance, with the BM25 score in an additional [`SCORE_COLUMN`] column.
+pub(crate) async fn execute_query(
+    table_uri: &str,
+    dataset: &Dataset,
+    query: &Query,
+    text: &str,
+    options: QueryExecutionOptions,
+) -> Result<SendableRecordBatchStream> {
+    let index = FtsIndex::open(table_uri)?;
+    let limit = query.limit.unwrap_or(DEFAULT_TOP_K);
+
+    let filter = query.resolved_filter(&ArrowSchema::from(dataset.schema()))?;
+    let matches = if let Some(filter) = &filter {
+        // Find the rows matching the filter and then keep the most relevant of those
+        let mut scanner = dataset.scan();
+        scanner.filter(filter)?;
+        scanner.project(&index.columns()[..1])?;
+        scanner.with_row_id();
+        let allowed = scanner
+            .try_into_stream()
+            .await?
+            .map_ok(|batch| {
+                batch[ROW_ID_COLUMN]
+                    .as_primitive::<UInt64Type>()
+                    .values()
+                    .to_vec()
+            })
+            .try_concat()
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let mut matches = index.search(text, None)?;
+        matches.retain(|(row_id, _)| allowed.contains(row_id));
+        matches.truncate(limit);
+        matches
+    } else {
+        index.search(text, Some(limit))?
+    };
+
+    let projection = match &query.select {
+        Select::All => dataset.schema().clone(),
+        Select::Columns(columns) => dataset.schema().project(columns)?,
+        Select::Dynamic(_) => {
+            return Err(Error::NotSupported {
+                message: "dynamic projections are not supported with full text search".to_string(),
+            })
+        }
+    };
+    let row_ids = matches
+        .iter()
+        .map(|(row_id, _)| *row_id)
+        .collect::<Vec<_>>();
+    let batch = dataset.take_rows(&row_ids, &projection).await?;
+
+    let mut fields = batch.schema().fields().to_vec();
+    let mut columns = batch.columns().to_vec();
+    if query.with_row_id {
+        fields.push(Arc::new(ArrowField::new(
+            ROW_ID_COLUMN,
+            DataType::UInt64,
+            false,
+        )));
+        columns.push(Arc::new(UInt64Array::from(row_ids)));
+    }
+    fields.push(Arc::new(ArrowField::new(
+        SCORE_COLUMN,
+        DataType::Float32,
+        false,
+    )));
+    let schema = Arc::new(ArrowSchema::new(fields));
+    columns.push(Arc::new(Float32Array::from_iter_values(
+        matches.iter().map(|(_, score)| *score),
+    )));
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+    let batch_size = (options.max_batch_length as usize).max(1);
+    let batches = (0..batch.num_rows())
+        .step_by(batch_size)
+        .map(|offset| Ok(batch.slice(offset, batch_size.min(batch.num_rows() - offset))))
+        .collect::<Vec<_>>();
+    Ok(Box::pin(SimpleRecordBatchStream {
+        schema,
+        stream: futures::stream::iter(batches),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_path() {
+        assert_eq!(
+            index_path("/tmp/db/t.lance").unwrap(),
+            PathBuf::from("/tmp/db/t.lance/_indices/fts")
+        );
+        assert_eq!(
+            index_path("file:///tmp/db/t.lance").unwrap(),
+            PathBuf::from("/tmp/db/t.lance/_indices/fts")
+        );
+        assert!(matches!(
+            index_path("s3://bucket/db/t.lance"),
+            Err(Error::NotSupported { .. })
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! GPU kmeans used to train the IVF partitions of a vector index
+//!
+//! Only the centroids are computed here.  Lance is given the centroids and does
+//! the rest of the index build (assigning vectors to partitions and PQ) itself.
+//! The same kernel is used by [`crate::Table::cluster`].
+//!
+//! Only `train_centroids`, which runs on a CUDA device, requires the `cuda`
+//! feature.  The kmeans itself runs on any device and is tested on the CPU.
+
+#[cfg(feature = "cuda")]
+use std::sync::Arc;
+
+#[cfg(feature = "cuda")]
+use arrow_array::{FixedSizeListArray, Float32Array};
+#[cfg(feature = "cuda")]
+use arrow_schema::{DataType, Field};
+use candle_core::{DType, Device, Tensor};
+
+#[cfg(feature = "cuda")]
+use crate::error::{Error, Result};
+use crate::DistanceType;
+
+/// The number of training vectors assigned to partitions at once
+///
+/// This bounds the size of the (vectors x partitions) distance matrix on the GPU.
+const CHUNK_SIZE: usize = 8192;
+/// Training stops once the centroids move less than this (relative to their norm)
+const TOLERANCE: f32 = 1e-4;
+
+#[cfg(feature = "cuda")]
+fn gpu_error(e: candle_core::Error) -> Error {
+    Error::Runtime {
+        message: format!("GPU kmeans failed: {}", e),
+    }
+}
+
+/// Train `k` centroids for the (f32) vectors in `values` on a CUDA device
+#[cfg(feature = "cuda")]
+pub(crate) async fn train_centroids(
+    values: Float32Array,
+    dim: usize,
+    k: usize,
+    max_iterations: u32,
+    distance_type: DistanceType,
+    device: usize,
+) -> Result<FixedSizeListArray> {
+    let values = values.values().to_vec();
+    let centroids = tokio::task::spawn_blocking(move || {
+        let device = Device::new_cuda(device)?;
+        kmeans(
+            &device,
+            values,
+            dim,
+            k,
+            max_iterations as usize,
+            distance_type,
+        )
+    })
+    .await
+    .map_err(|e| Error::Runtime {
+        message: format!("GPU kmeans task failed: {}", e),
+    })?
+    .map_err(gpu_error)?;
+
+    Ok(FixedSizeListArray::try_new(
+        Arc::new(Field::new("item", DataType::Float32, true)),
+        dim as i32,
+        Arc::new(Float32Array::from(centroids)),
+        None,
+    )?)
+}
+
+/// Lloyd's kmeans, returns the `k` centroids flattened into one vector
+fn kmeans(
+    device: &Device,
+    values: Vec<f32>,
+    dim: usize,
+    k: usize,
+    max_iterations: usize,
+    distance_type: DistanceType,
+) -> candle_core::Result<Vec<f32>> {
+    let n = values.len() / dim;
+    let normalize = |t: Tensor| t.broadcast_div(&t.sqr()?.sum_keepdim(1)?.sqrt()?);
+    let mut data = Tensor::from_vec(values, (n, dim), device)?;
+    if distance_type == DistanceType::Cosine {
+        data = normalize(data)?;
+    }
+
+    // The training vectors are a random sample so evenly spaced rows are a
+    // random initialization
+    let initial = (0..k).map(|i| (i * n / k) as u32).collect::<Vec<_>>();
+    let mut centroids = data.index_select(&Tensor::new(initial.as_slice(), device)?, 0)?;
+    let partition_ids = Tensor::arange(0u32, k as u32, device)?.unsqueeze(0)?;
+
+    for _ in 0..max_iterations {
+        let mut sums = Tensor::zeros((k, dim), DType::F32, device)?;
+        let mut counts = Tensor::zeros(k, DType::F32, device)?;
+        let centroid_norms = centroids.sqr()?.sum(1)?.unsqueeze(0)?;
+        for start in (0..n).step_by(CHUNK_SIZE) {
+            let chunk = data.narrow(0, start, CHUNK_SIZE.min(n - start))?;
+            let scores = chunk.matmul(&centroids.t()?)?;
+            // |x|^2 is the same for every centroid so it is left out of the L2 distance
+            let distances = match distance_type {
+                DistanceType::Dot => scores.neg()?,
+                _ => centroid_norms.broadcast_sub(&scores.affine(2.0, 0.0)?)?,
+            };
+            let assignments = distances.argmin(1)?.unsqueeze(1)?;
+            let one_hot = assignments
+                .broadcast_eq(&partition_ids)?
+                .to_dtype(DType::F32)?;
+            sums = (sums + one_hot.t()?.matmul(&chunk)?)?;
+            counts = (counts + one_hot.sum(0)?)?;
+        }
+
+        // Empty partitions keep their previous centroid
+        let empty = counts.eq(0f32)?.unsqueeze(1)?.broadcast_as((k, dim))?;
+        let means = sums.broadcast_div(&counts.clamp(1f32, f32::MAX)?.unsqueeze(1)?)?;
+        let mut updated = empty.where_cond(&centroids, &means)?;
+        if distance_type == DistanceType::Cosine {
+            updated = normalize(updated)?;
+        }
+
+        let shift = (&updated - &centroids)?
+            .sqr()?
+            .sum_all()?
+            .to_scalar::<f32>()?;
+        let norm = centroids.sqr()?.sum_all()?.to_scalar::<f32>()?;
+        centroids = updated;
+        if shift <= TOLERANCE * norm {
+            break;
+        }
+    }
+    centroids.flatten_all()?.to_vec1::<f32>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kmeans() {
+        // Two well separated clusters, trained on the CPU device
+        let mut values = Vec::new();
+        for i in 0..100 {
+            let offset = if i < 50 { 0.0 } else { 10.0 };
+            values.extend([offset + (i % 7) as f32 * 0.01, offset]);
+        }
+        let centroids = kmeans(&Device::Cpu, values, 2, 2, 50, DistanceType::L2).unwrap();
+        let mut xs = [centroids[0], centroids[2]];
+        xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!(xs[0] < 1.0);
+        assert!(xs[1] > 9.0);
+    }
+}
@@ -6,6 +6,11 @@
 //! A scalar index will help with queries with filters like `x > 10`, `x < 10`, `x = 10`,
 //! etc.  Scalar indices can also speed up prefiltering for vector searches.  A single
 //! vector search with prefiltering can use both a scalar index and a vector index.
+//!
+//! The only scalar index is the btree, see [`BTreeIndexBuilder`].  There is no bitmap
+//! index, which would suit columns with few distinct values better, because the
+//! version of lance used by LanceDB only implements the btree.  It will be added once
+//! lance provides one.
 
 /// Builder for a btree index
 ///
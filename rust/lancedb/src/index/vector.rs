@@ -19,10 +19,16 @@
 //! values
 use std::cmp::max;
 
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, Array, FixedSizeListArray, Float32Array};
+use arrow_schema::{DataType, Field};
 use serde::Deserialize;
 
 use lance::table::format::{Index, Manifest};
+use lance::Dataset;
 
+use crate::error::{Error, Result};
 use crate::DistanceType;
 
 pub struct VectorIndex {
@@ -52,6 +58,34 @@ pub struct VectorIndexStatistics {
     pub num_unindexed_rows: usize,
 }
 
+/// The statistics of an IVF index, with those of its segments
+#[derive(Debug, Deserialize)]
+pub(crate) struct IvfIndexStatistics {
+    pub num_indexed_rows: u64,
+    pub num_unindexed_rows: u64,
+    #[serde(default)]
+    pub indices: Vec<IvfSegmentStatistics>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct IvfSegmentStatistics {
+    pub num_partitions: Option<u64>,
+    #[serde(default)]
+    pub centroids: Vec<Vec<f32>>,
+}
+
+/// The device used to train the IVF partitions of a vector index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Accelerator {
+    /// Train the partitions on the CPU
+    #[default]
+    Cpu,
+    /// Train the partitions on the CUDA device with the given ordinal
+    ///
+    /// This requires the `cuda` feature.
+    Cuda(usize),
+}
+
 /// Builder for an IVF PQ index.
 ///
 /// This index stores a compressed (quantized) copy of every vector.  These vectors
@@ -79,6 +113,8 @@ pub struct IvfPqIndexBuilder {
     pub(crate) num_sub_vectors: Option<u32>,
     pub(crate) sample_rate: u32,
     pub(crate) max_iterations: u32,
+    pub(crate) accelerator: Accelerator,
+    pub(crate) centroids: Option<FixedSizeListArray>,
 }
 
 impl Default for IvfPqIndexBuilder {
@@ -89,6 +125,8 @@ impl Default for IvfPqIndexBuilder {
             num_sub_vectors: None,
             sample_rate: 256,
             max_iterations: 50,
+            accelerator: Accelerator::Cpu,
+            centroids: None,
         }
     }
 }
@@ -172,6 +210,378 @@ impl IvfPqIndexBuilder {
         self.max_iterations = max_iterations;
         self
     }
+
+    /// The device used to run kmeans when training the IVF partitions.
+    ///
+    /// Training the partitions is the slowest part of building an index on a large
+    /// dataset.  With [`Accelerator::Cuda`] the kmeans runs on a GPU instead and the
+    /// resulting centroids are handed to lance, which then assigns the vectors to
+    /// partitions and trains PQ as usual.  This requires the `cuda` feature.
+    ///
+    /// The default value is [`Accelerator::Cpu`].
+    pub fn accelerator(mut self, accelerator: Accelerator) -> Self {
+        self.accelerator = accelerator;
+        self
+    }
+
+    /// Use the given IVF centroids instead of training them
+    ///
+    /// Each centroid is the center of a partition, there must be one per
+    /// partition (if [`Self::num_partitions`] is set) and they must have the
+    /// dimension of the indexed vectors.  Skipping kmeans makes building the
+    /// index much faster, which helps when many tables hold vectors of the same
+    /// embedding model, e.g. a table per tenant: the centroids can be trained
+    /// once, or taken from the index of another table with
+    /// [`crate::Table::ivf_centroids`], and reused for all of them.  The
+    /// centroids should be representative of the vectors of the table,
+    /// otherwise the partitions are unbalanced and the recall drops.
+    ///
+    /// The accelerator is not used when the centroids are given.
+    pub fn centroids(mut self, centroids: FixedSizeListArray) -> Self {
+        self.centroids = Some(centroids);
+        self
+    }
+}
+
+/// Setters shared by the IVF HNSW index builders
+macro_rules! impl_ivf_hnsw_setters {
+    ($builder:ty) => {
+        impl $builder {
+            /// [DistanceType] to use to build the index, see [`IvfPqIndexBuilder::distance_type`]
+            ///
+            /// Default value is [DistanceType::L2].
+            pub fn distance_type(mut self, distance_type: DistanceType) -> Self {
+                self.distance_type = distance_type;
+                self
+            }
+
+            /// The number of IVF partitions, see [`IvfPqIndexBuilder::num_partitions`]
+            ///
+            /// Each partition has its own graph.  Fewer, larger partitions make better
+            /// use of the graph, by default the number of partitions is the square
+            /// root of the number of rows.
+            pub fn num_partitions(mut self, num_partitions: u32) -> Self {
+                self.num_partitions = Some(num_partitions);
+                self
+            }
+
+            /// The rate used to calculate the number of training vectors for kmeans,
+            /// see [`IvfPqIndexBuilder::sample_rate`]
+            ///
+            /// The default value is 256.
+            pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+                self.sample_rate = sample_rate;
+                self
+            }
+
+            /// Max iterations to train kmeans, see [`IvfPqIndexBuilder::max_iterations`]
+            ///
+            /// The default value is 50.
+            pub fn max_iterations(mut self, max_iterations: u32) -> Self {
+                self.max_iterations = max_iterations;
+                self
+            }
+
+            /// Use the given IVF centroids instead of training them, see
+            /// [`IvfPqIndexBuilder::centroids`]
+            pub fn centroids(mut self, centroids: FixedSizeListArray) -> Self {
+                self.centroids = Some(centroids);
+                self
+            }
+
+            /// The number of edges of each node in the graph (often called `m`).
+            ///
+            /// More edges improve the recall of the index at the cost of a larger
+            /// index and slower builds.
+            ///
+            /// The default value is 20.
+            pub fn num_edges(mut self, num_edges: u32) -> Self {
+                self.num_edges = num_edges;
+                self
+            }
+
+            /// The number of candidates considered when inserting a node into the graph.
+            ///
+            /// Larger values build a better graph (improving recall) but make the
+            /// build slower.  This is the build time equivalent of
+            /// [`crate::query::VectorQuery::ef`].
+            ///
+            /// The default value is 300.
+            pub fn ef_construction(mut self, ef_construction: u32) -> Self {
+                self.ef_construction = ef_construction;
+                self
+            }
+        }
+    };
+}
+
+/// Builder for an IVF HNSW PQ index.
+///
+/// The vectors are grouped into IVF partitions, as with [`IvfPqIndexBuilder`], and
+/// the vectors in each partition are connected in an HNSW graph.  A search walks the
+/// graph of the closest partitions instead of scanning all of their vectors, which
+/// gives lower latency than IVF PQ at the same recall.  The vectors in the graph are
+/// compressed with product quantization.
+///
+/// Building the graph is slower than building an IVF PQ index.
+#[derive(Debug, Clone)]
+pub struct IvfHnswPqIndexBuilder {
+    pub(crate) distance_type: DistanceType,
+    pub(crate) num_partitions: Option<u32>,
+    pub(crate) num_sub_vectors: Option<u32>,
+    pub(crate) sample_rate: u32,
+    pub(crate) max_iterations: u32,
+    pub(crate) num_edges: u32,
+    pub(crate) ef_construction: u32,
+    pub(crate) centroids: Option<FixedSizeListArray>,
+}
+
+impl Default for IvfHnswPqIndexBuilder {
+    fn default() -> Self {
+        Self {
+            distance_type: DistanceType::L2,
+            num_partitions: None,
+            num_sub_vectors: None,
+            sample_rate: 256,
+            max_iterations: 50,
+            num_edges: 20,
+            ef_construction: 300,
+            centroids: None,
+        }
+    }
+}
+
+impl_ivf_hnsw_setters!(IvfHnswPqIndexBuilder);
+
+impl IvfHnswPqIndexBuilder {
+    /// Number of sub-vectors of PQ, see [`IvfPqIndexBuilder::num_sub_vectors`]
+    pub fn num_sub_vectors(mut self, num_sub_vectors: u32) -> Self {
+        self.num_sub_vectors = Some(num_sub_vectors);
+        self
+    }
+}
+
+/// Builder for an IVF HNSW SQ index.
+///
+/// This is the same as [`IvfHnswPqIndexBuilder`] except that the vectors in the
+/// graph are compressed with scalar quantization, which stores each value in 8 bits.
+/// The index is larger than an IVF HNSW PQ index but is usually more accurate.
+#[derive(Debug, Clone)]
+pub struct IvfHnswSqIndexBuilder {
+    pub(crate) distance_type: DistanceType,
+    pub(crate) num_partitions: Option<u32>,
+    pub(crate) sample_rate: u32,
+    pub(crate) max_iterations: u32,
+    pub(crate) num_edges: u32,
+    pub(crate) ef_construction: u32,
+    pub(crate) centroids: Option<FixedSizeListArray>,
+}
+
+impl Default for IvfHnswSqIndexBuilder {
+    fn default() -> Self {
+        Self {
+            distance_type: DistanceType::L2,
+            num_partitions: None,
+            sample_rate: 256,
+            max_iterations: 50,
+            num_edges: 20,
+            ef_construction: 300,
+            centroids: None,
+        }
+    }
+}
+
+impl_ivf_hnsw_setters!(IvfHnswSqIndexBuilder);
+
+/// The size of a 32 bit float
+const F32_BYTES: u64 = 4;
+/// The size of the row id stored with each quantized vector
+const ROW_ID_BYTES: u64 = 8;
+/// The size of the id of a neighbor in an HNSW graph
+const NEIGHBOR_BYTES: u64 = 4;
+/// The number of centroids of each PQ sub-vector, with 8 bit codes
+const PQ_CENTROIDS: u64 = 256;
+
+/// The estimated size of a vector index, see [`crate::index::Index::memory_footprint`]
+///
+/// The estimate is what the index holds in memory once it is loaded to serve
+/// searches.  It compares the compression schemes of the indices: the smaller
+/// indices fit in memory more easily but their searches are less accurate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexFootprint {
+    /// The size of the indexed vectors, uncompressed as 32 bit floats
+    pub vector_bytes: u64,
+    /// The size of the quantized vectors and of their row ids
+    pub code_bytes: u64,
+    /// The size of the IVF centroids and of the quantizer, the PQ codebook or
+    /// the SQ bounds
+    pub model_bytes: u64,
+    /// The size of the HNSW graphs, zero for the indices without a graph
+    pub graph_bytes: u64,
+}
+
+impl IndexFootprint {
+    /// The total size of the index
+    pub fn index_bytes(&self) -> u64 {
+        self.code_bytes + self.model_bytes + self.graph_bytes
+    }
+
+    /// The size of the uncompressed vectors divided by the size of the index
+    pub fn compression_ratio(&self) -> f64 {
+        self.vector_bytes as f64 / self.index_bytes().max(1) as f64
+    }
+}
+
+/// How the vectors of an index are quantized
+enum Quantizer {
+    /// Product quantization with the given number of sub-vectors of 8 bit codes
+    Pq(Option<u32>),
+    /// Scalar quantization of each value into 8 bits
+    Sq,
+}
+
+fn footprint(
+    num_rows: usize,
+    dim: u32,
+    num_partitions: Option<u32>,
+    quantizer: Quantizer,
+    num_edges: Option<u32>,
+) -> IndexFootprint {
+    let rows = num_rows as u64;
+    let dim = dim as u64;
+    let num_partitions =
+        num_partitions.unwrap_or_else(|| suggested_num_partitions(num_rows)) as u64;
+    let (code_bytes, quantizer_bytes) = match quantizer {
+        Quantizer::Pq(num_sub_vectors) => {
+            let num_sub_vectors =
+                num_sub_vectors.unwrap_or_else(|| suggested_num_sub_vectors(dim as u32)) as u64;
+            // Each sub-vector has its own centroids, of dim / num_sub_vectors values
+            (rows * num_sub_vectors, PQ_CENTROIDS * dim * F32_BYTES)
+        }
+        Quantizer::Sq => (rows * dim, 2 * F32_BYTES),
+    };
+    IndexFootprint {
+        vector_bytes: rows * dim * F32_BYTES,
+        code_bytes: code_bytes + rows * ROW_ID_BYTES,
+        model_bytes: num_partitions * dim * F32_BYTES + quantizer_bytes,
+        graph_bytes: num_edges.map_or(0, |num_edges| rows * num_edges as u64 * NEIGHBOR_BYTES),
+    }
+}
+
+impl IvfPqIndexBuilder {
+    pub(crate) fn footprint(&self, num_rows: usize, dim: u32) -> IndexFootprint {
+        footprint(
+            num_rows,
+            dim,
+            self.num_partitions,
+            Quantizer::Pq(self.num_sub_vectors),
+            None,
+        )
+    }
+}
+
+impl IvfHnswPqIndexBuilder {
+    pub(crate) fn footprint(&self, num_rows: usize, dim: u32) -> IndexFootprint {
+        footprint(
+            num_rows,
+            dim,
+            self.num_partitions,
+            Quantizer::Pq(self.num_sub_vectors),
+            Some(self.num_edges),
+        )
+    }
+}
+
+impl IvfHnswSqIndexBuilder {
+    pub(crate) fn footprint(&self, num_rows: usize, dim: u32) -> IndexFootprint {
+        footprint(
+            num_rows,
+            dim,
+            self.num_partitions,
+            Quantizer::Sq,
+            Some(self.num_edges),
+        )
+    }
+}
+
+/// Sample the vectors in `column` used to train `k` kmeans centroids
+///
+/// Returns the values of the vectors, cast to f32, and their dimension.
+pub(crate) async fn sample_training_vectors(
+    dataset: &Dataset,
+    column: &str,
+    k: usize,
+    sample_rate: u32,
+) -> Result<(Float32Array, usize)> {
+    let num_rows = dataset.count_rows(None).await?;
+    if num_rows < k {
+        return Err(Error::InvalidInput {
+            message: format!("cannot train {} centroids with only {} rows", k, num_rows),
+        });
+    }
+    let sample_size = (sample_rate as usize * k).min(num_rows);
+    let projection = dataset.schema().project(&[column])?;
+    let row_ids = rand::seq::index::sample(&mut rand::thread_rng(), num_rows, sample_size)
+        .into_iter()
+        .map(|row| row as u64)
+        .collect::<Vec<_>>();
+    let sample = dataset.take(&row_ids, &projection).await?;
+    let vectors = sample[column]
+        .as_fixed_size_list_opt()
+        .ok_or_else(|| Error::InvalidInput {
+            message: format!("the column '{}' is not a vector column", column),
+        })?;
+    let values = arrow_cast::cast(vectors.values(), &DataType::Float32)?;
+    Ok((
+        values.as_primitive().clone(),
+        vectors.value_length() as usize,
+    ))
+}
+
+/// Check that `centroids` can be the IVF centroids of an index on `field`
+///
+/// Returns the centroids with f32 values, as lance expects them.
+pub(crate) fn check_centroids(
+    centroids: &FixedSizeListArray,
+    field: &Field,
+    num_partitions: Option<u32>,
+) -> Result<Arc<FixedSizeListArray>> {
+    let DataType::FixedSizeList(_, dim) = field.data_type() else {
+        return Err(Error::Schema {
+            message: format!("Column '{}' is not a FixedSizeList", field.name()),
+        });
+    };
+    if centroids.value_length() != *dim {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "the centroids have {} dimensions but the vectors of '{}' have {}",
+                centroids.value_length(),
+                field.name(),
+                dim
+            ),
+        });
+    }
+    if centroids.is_empty() || centroids.null_count() > 0 {
+        return Err(Error::InvalidInput {
+            message: "the centroids must not be empty or contain nulls".to_string(),
+        });
+    }
+    if let Some(num_partitions) = num_partitions.filter(|n| *n as usize != centroids.len()) {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "{} centroids were given for {} partitions",
+                centroids.len(),
+                num_partitions
+            ),
+        });
+    }
+    let data_type =
+        DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), *dim);
+    Ok(Arc::new(
+        arrow_cast::cast(centroids, &data_type)?
+            .as_fixed_size_list()
+            .clone(),
+    ))
 }
 
 pub(crate) fn suggested_num_partitions(rows: usize) -> u32 {
@@ -0,0 +1,376 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Import of Delta Lake and Apache Iceberg tables
+//!
+//! [`delta::DeltaSource`] (with the `delta` feature) and
+//! [`iceberg::IcebergSource`] (with the `iceberg` feature) load a snapshot of a
+//! lakehouse table: its schema, its partitioning and the list of its data
+//! files, read from the log or the metadata of the table.  The
+//! [`LakehouseSnapshot`] is then the data of a new table, or of rows added to
+//! an existing table.  The Parquet data files are streamed, a batch at a time.
+//!
+//! ```no_run
+//! # #[cfg(feature = "delta")]
+//! # async fn migrate(db: &lancedb::Connection) -> lancedb::Result<()> {
+//! use lancedb::interop::delta::DeltaSource;
+//!
+//! let snapshot = DeltaSource::new("s3://bucket/lake/events")
+//!     .version(42)
+//!     .load()
+//!     .await?;
+//! let table = db.create_table("events", snapshot).execute().await?;
+//!
+//! // Later, append the rows of a newer snapshot
+//! let snapshot = DeltaSource::new("s3://bucket/lake/new_events").load().await?;
+//! table.add(snapshot).execute().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The columns keep the types of the lakehouse table.  The partitioning of the
+//! table is recorded as JSON in the schema metadata, under
+//! [`PARTITIONING_METADATA_KEY`], and the table and snapshot the rows come
+//! from under [`SOURCE_METADATA_KEY`], so that a table created from a snapshot
+//! keeps them.  The partition columns of Delta tables, which are not stored in
+//! the data files, are filled from the partition values of each file.
+//!
+//! Columns are matched by name, and columns missing from older data files are
+//! read as nulls.  Only Parquet data files are supported, and tables with
+//! deleted rows (Delta deletion vectors, Iceberg delete files) are rejected.
+
+#[cfg(feature = "delta")]
+pub mod delta;
+#[cfg(feature = "iceberg")]
+pub mod iceberg;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{new_null_array, ArrayRef, RecordBatch, RecordBatchReader, StringArray};
+use arrow_cast::cast;
+use arrow_schema::{ArrowError, DataType, Field, SchemaRef};
+use futures::StreamExt;
+use lance::io::{ObjectStore, ObjectStoreParams};
+use object_store::path::Path;
+use parquet::arrow::async_reader::{ParquetObjectReader, ParquetRecordBatchStreamBuilder};
+use serde::Serialize;
+
+use crate::arrow::IntoArrow;
+use crate::data::parquet::read_in_background;
+use crate::error::{Error, Result};
+
+/// The schema metadata key of the partitioning of the lakehouse table
+///
+/// The value is a JSON list of [`PartitionField`].
+pub const PARTITIONING_METADATA_KEY: &str = "lancedb::partitioning";
+
+/// The schema metadata key of the lakehouse table the rows were read from
+///
+/// The value is a JSON object with the `format`, the `uri` and the `version`
+/// of the snapshot.
+pub const SOURCE_METADATA_KEY: &str = "lancedb::source";
+
+/// A field of the partitioning of a lakehouse table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PartitionField {
+    /// The column whose values are partitioned
+    pub column: String,
+    /// The transform of the values, such as `identity`, `day` or `bucket[16]`
+    ///
+    /// The partitions of Delta tables are always `identity`.
+    pub transform: String,
+    /// The name of the partition field
+    pub name: String,
+}
+
+/// A data file of a snapshot
+#[derive(Debug, Clone)]
+pub(crate) struct DataFile {
+    pub(crate) path: Path,
+    /// The values of the partition columns missing from the file, as strings
+    pub(crate) partition_values: HashMap<String, Option<String>>,
+}
+
+/// A snapshot of a lakehouse table, to read as the data of a table
+///
+/// See the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct LakehouseSnapshot {
+    version: i64,
+    schema: SchemaRef,
+    partitioning: Vec<PartitionField>,
+    store: Arc<dyn object_store::ObjectStore>,
+    files: Vec<DataFile>,
+    batch_size: usize,
+}
+
+impl LakehouseSnapshot {
+    pub(crate) fn new(
+        format: &str,
+        uri: &str,
+        version: i64,
+        schema: arrow_schema::Schema,
+        partitioning: Vec<PartitionField>,
+        store: Arc<dyn object_store::ObjectStore>,
+        files: Vec<DataFile>,
+    ) -> Result<Self> {
+        let mut metadata = schema.metadata().clone();
+        let partitioning_json =
+            serde_json::to_string(&partitioning).map_err(|e| Error::Runtime {
+                message: format!("failed to encode the partitioning: {}", e),
+            })?;
+        metadata.insert(PARTITIONING_METADATA_KEY.to_string(), partitioning_json);
+        metadata.insert(
+            SOURCE_METADATA_KEY.to_string(),
+            serde_json::json!({ "format": format, "uri": uri, "version": version }).to_string(),
+        );
+        Ok(Self {
+            version,
+            schema: Arc::new(schema.with_metadata(metadata)),
+            partitioning,
+            store,
+            files,
+            batch_size: 8192,
+        })
+    }
+
+    /// The number of rows of the batches read, 8192 by default
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// The version of the Delta table, or the id of the Iceberg snapshot
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    /// The schema of the rows read, with the partitioning in its metadata
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// The partitioning of the table
+    pub fn partitioning(&self) -> &[PartitionField] {
+        &self.partitioning
+    }
+
+    /// The number of data files of the snapshot
+    pub fn num_files(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Read the data file `file`, sending its batches with the schema of the
+    /// snapshot
+    ///
+    /// Returns false once the receiver of the batches is dropped.
+    async fn read_file(
+        &self,
+        file: &DataFile,
+        sender: &std::sync::mpsc::SyncSender<std::result::Result<RecordBatch, ArrowError>>,
+    ) -> Result<bool> {
+        let meta = self.store.head(&file.path).await?;
+        let reader = ParquetObjectReader::new(self.store.clone(), meta);
+        let mut stream = ParquetRecordBatchStreamBuilder::new(reader)
+            .await?
+            .with_batch_size(self.batch_size)
+            .build()?;
+        while let Some(batch) = stream.next().await {
+            let batch = conform(&batch?, &self.schema, &file.partition_values)?;
+            if sender.send(Ok(batch)).is_err() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// The columns of `batch` in the order and with the types of `schema`
+///
+/// Columns missing from the batch are filled with the values of
+/// `partition_values`, or with nulls.
+fn conform(
+    batch: &RecordBatch,
+    schema: &SchemaRef,
+    partition_values: &HashMap<String, Option<String>>,
+) -> Result<RecordBatch> {
+    let rows = batch.num_rows();
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let column = match batch.column_by_name(field.name()) {
+                Some(column) if column.data_type() == field.data_type() => column.clone(),
+                Some(column) => cast(column, field.data_type())?,
+                None => match partition_values.get(field.name()) {
+                    Some(Some(value)) => cast(
+                        &StringArray::from(vec![value.as_str(); rows]),
+                        field.data_type(),
+                    )?,
+                    _ => new_null_array(field.data_type(), rows),
+                },
+            };
+            Ok(column)
+        })
+        .collect::<Result<Vec<ArrayRef>>>()?;
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+impl IntoArrow for LakehouseSnapshot {
+    /// Start reading the data files in the background
+    fn into_arrow(self) -> Result<Box<dyn RecordBatchReader + Send>> {
+        read_in_background(move |schema_sender, sender| async move {
+            if schema_sender.send(Ok(self.schema.clone())).is_err() {
+                return;
+            }
+            for (i, file) in self.files.iter().enumerate() {
+                match self.read_file(file, &sender).await {
+                    Ok(true) => log::debug!("Read {} ({}/{})", file.path, i + 1, self.files.len()),
+                    Ok(false) => return,
+                    Err(e) => {
+                        let _ = sender.send(Err(ArrowError::ExternalError(Box::new(e))));
+                        return;
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// The store holding the table at `uri`, and the path of the table
+pub(crate) async fn open_store(
+    uri: &str,
+    storage_options: &HashMap<String, String>,
+) -> Result<(Arc<dyn object_store::ObjectStore>, Path)> {
+    let params = ObjectStoreParams {
+        storage_options: Some(storage_options.clone()),
+        ..Default::default()
+    };
+    let (store, path) =
+        ObjectStore::from_uri_and_params(uri.trim_end_matches('/'), &params).await?;
+    Ok((store.inner.clone(), path))
+}
+
+/// The path of the file `file`, relative to the table at `table_uri` or
+/// absolute, under the path `root` of the table
+pub(crate) fn file_path(root: &Path, table_uri: &str, file: &str) -> Result<Path> {
+    fn without_scheme(uri: &str) -> &str {
+        let uri = uri.strip_prefix("file://").unwrap_or(uri);
+        uri.strip_prefix("file:").unwrap_or(uri)
+    }
+    let relative = if file.contains(':') || file.starts_with('/') {
+        let table = format!("{}/", without_scheme(table_uri).trim_end_matches('/'));
+        without_scheme(file)
+            .strip_prefix(&table)
+            .ok_or_else(|| Error::InvalidInput {
+                message: format!(
+                    "the data file {} is outside of the table {}",
+                    file, table_uri
+                ),
+            })?
+    } else {
+        file
+    };
+    let relative = Path::from_url_path(relative)?;
+    Ok(root.parts().chain(relative.parts()).collect())
+}
+
+/// The arrow type of the decimal type `name`, such as `decimal(10,2)`
+pub(crate) fn decimal_type(name: &str) -> Option<DataType> {
+    let (precision, scale) = name
+        .strip_prefix("decimal(")?
+        .strip_suffix(')')?
+        .split_once(',')?;
+    Some(DataType::Decimal128(
+        precision.trim().parse().ok()?,
+        scale.trim().parse().ok()?,
+    ))
+}
+
+/// The arrow type of a map from `key` to `value`
+pub(crate) fn map_type(key: DataType, value: Field) -> DataType {
+    let entries = Field::new(
+        "key_value",
+        DataType::Struct(vec![Field::new("key", key, false), value.with_name("value")].into()),
+        false,
+    );
+    DataType::Map(Arc::new(entries), false)
+}
+
+pub(crate) fn invalid_table(uri: &str, message: impl std::fmt::Display) -> Error {
+    Error::InvalidInput {
+        message: format!("cannot read the table at {}: {}", uri, message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int32Type;
+    use arrow_array::Int32Array;
+    use arrow_schema::Schema;
+
+    use super::*;
+
+    #[test]
+    fn test_conform() {
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("b", DataType::Int32, true)])),
+            vec![Arc::new(Int32Array::from(vec![1, 2]))],
+        )
+        .unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int64, true),
+            Field::new("c", DataType::Utf8, true),
+        ]));
+        let values = HashMap::from([("a".to_string(), Some("7".to_string()))]);
+        let batch = conform(&batch, &schema, &values).unwrap();
+        assert_eq!(
+            batch.column(0).as_primitive::<Int32Type>().values(),
+            &[7, 7]
+        );
+        assert_eq!(batch.column(1).data_type(), &DataType::Int64);
+        assert_eq!(batch.column(2).null_count(), 2);
+    }
+
+    #[test]
+    fn test_file_path() {
+        let root = Path::from("lake/events");
+        assert_eq!(
+            file_path(
+                &root,
+                "s3://b/lake/events",
+                "date=2024-01-01/part%201.parquet"
+            )
+            .unwrap(),
+            Path::from("lake/events/date=2024-01-01/part 1.parquet")
+        );
+        assert_eq!(
+            file_path(
+                &root,
+                "s3://b/lake/events/",
+                "s3://b/lake/events/data/a.parquet"
+            )
+            .unwrap(),
+            Path::from("lake/events/data/a.parquet")
+        );
+        assert!(file_path(&root, "s3://b/lake/events", "s3://b/other/a.parquet").is_err());
+        assert_eq!(
+            decimal_type("decimal(10, 2)"),
+            Some(DataType::Decimal128(10, 2))
+        );
+    }
+}
@@ -0,0 +1,536 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read snapshots of Delta Lake tables
+//!
+//! The state of the table at a version is replayed from the transaction log,
+//! `_delta_log`: from the last checkpoint at or before the version, if any,
+//! and then from the JSON commits up to the version.  Tables using column
+//! mapping or deletion vectors are not supported.
+//!
+//! This requires the `delta` feature.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::{Array, MapArray, RecordBatch, StructArray};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use futures::TryStreamExt;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use parquet::arrow::async_reader::{ParquetObjectReader, ParquetRecordBatchStreamBuilder};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{
+    decimal_type, file_path, invalid_table, map_type, open_store, DataFile, LakehouseSnapshot,
+    PartitionField,
+};
+use crate::error::Result;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Add {
+    path: String,
+    #[serde(default)]
+    partition_values: HashMap<String, Option<String>>,
+    #[serde(default)]
+    deletion_vector: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Remove {
+    path: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Metadata {
+    schema_string: String,
+    #[serde(default)]
+    partition_columns: Vec<String>,
+    #[serde(default)]
+    configuration: HashMap<String, Option<String>>,
+}
+
+/// An action of a commit, of which only the actions changing the data files
+/// or the schema are read
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Action {
+    add: Option<Add>,
+    remove: Option<Remove>,
+    meta_data: Option<Metadata>,
+}
+
+/// The state of the table, as the log is replayed
+#[derive(Debug, Default)]
+struct State {
+    metadata: Option<Metadata>,
+    /// The data files, by their path in the log
+    files: BTreeMap<String, Add>,
+}
+
+impl State {
+    fn apply(&mut self, action: Action) {
+        if let Some(add) = action.add {
+            self.files.insert(add.path.clone(), add);
+        }
+        if let Some(remove) = action.remove {
+            self.files.remove(&remove.path);
+        }
+        if let Some(metadata) = action.meta_data {
+            self.metadata = Some(metadata);
+        }
+    }
+}
+
+/// A Delta Lake table, to read a snapshot of
+///
+/// See the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct DeltaSource {
+    uri: String,
+    version: Option<i64>,
+    storage_options: HashMap<String, String>,
+}
+
+impl DeltaSource {
+    /// The Delta table at `uri`, the directory holding `_delta_log`
+    pub fn new(uri: impl Into<String>) -> Self {
+        Self {
+            uri: uri.into(),
+            version: None,
+            storage_options: HashMap::new(),
+        }
+    }
+
+    /// Read the table as of the version `version`, instead of the latest one
+    pub fn version(mut self, version: i64) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Set an option of the object store the table is read from
+    ///
+    /// See [`crate::connection::ConnectBuilder::storage_option`].
+    pub fn storage_option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.storage_options.insert(key.into(), value.into());
+        self
+    }
+
+    /// Replay the log of the table to find the schema and the data files of
+    /// the version
+    pub async fn load(&self) -> Result<LakehouseSnapshot> {
+        let (store, root) = open_store(&self.uri, &self.storage_options).await?;
+        let log = root.child("_delta_log");
+        let (commits, checkpoints) = list_log(&store, &log).await?;
+        let version = match self.version {
+            Some(version) => version,
+            None => *commits
+                .keys()
+                .chain(checkpoints.keys())
+                .max()
+                .ok_or_else(|| invalid_table(&self.uri, "it has no transaction log"))?,
+        };
+
+        let mut state = State::default();
+        let checkpoint = checkpoints.range(..=version).next_back();
+        let first_commit = match checkpoint {
+            Some((checkpoint, parts)) => {
+                for part in parts {
+                    read_checkpoint(&store, part, &mut state).await?;
+                }
+                checkpoint + 1
+            }
+            None => 0,
+        };
+        for commit in first_commit..=version {
+            let path = commits.get(&commit).ok_or_else(|| {
+                invalid_table(
+                    &self.uri,
+                    format!("the log of version {} is missing", commit),
+                )
+            })?;
+            let bytes = store.get(path).await?.bytes().await?;
+            for line in bytes.split(|b| *b == b'\n') {
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let action = serde_json::from_slice::<Action>(line).map_err(|e| {
+                    invalid_table(&self.uri, format!("invalid commit {}: {}", commit, e))
+                })?;
+                state.apply(action);
+            }
+        }
+
+        let metadata = state
+            .metadata
+            .ok_or_else(|| invalid_table(&self.uri, "it has no metadata"))?;
+        if let Some(Some(mode)) = metadata.configuration.get("delta.columnMapping.mode") {
+            if mode != "none" {
+                return Err(invalid_table(&self.uri, "column mapping is not supported"));
+            }
+        }
+        let schema_json = serde_json::from_str::<Value>(&metadata.schema_string)
+            .map_err(|e| invalid_table(&self.uri, format!("invalid schema: {}", e)))?;
+        let schema = Schema::new(struct_fields(&schema_json).ok_or_else(|| {
+            invalid_table(&self.uri, format!("unsupported schema {}", schema_json))
+        })?);
+        let partitioning = metadata
+            .partition_columns
+            .iter()
+            .map(|column| PartitionField {
+                column: column.clone(),
+                transform: "identity".to_string(),
+                name: column.clone(),
+            })
+            .collect();
+        let files = state
+            .files
+            .into_values()
+            .map(|add| {
+                if add.deletion_vector.is_some_and(|dv| !dv.is_null()) {
+                    return Err(invalid_table(
+                        &self.uri,
+                        "deletion vectors are not supported",
+                    ));
+                }
+                Ok(DataFile {
+                    path: file_path(&root, &self.uri, &add.path)?,
+                    partition_values: add.partition_values,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        LakehouseSnapshot::new(
+            "delta",
+            &self.uri,
+            version,
+            schema,
+            partitioning,
+            store,
+            files,
+        )
+    }
+}
+
+/// The commits and the complete checkpoints of the log, by version
+async fn list_log(
+    store: &Arc<dyn ObjectStore>,
+    log: &Path,
+) -> Result<(BTreeMap<i64, Path>, BTreeMap<i64, Vec<Path>>)> {
+    let mut commits = BTreeMap::new();
+    // The parts of each checkpoint and their expected number
+    let mut checkpoints = BTreeMap::<i64, (Vec<Path>, usize)>::new();
+    let mut files = store.list(Some(log));
+    while let Some(file) = files.try_next().await? {
+        let Some(name) = file.location.filename() else {
+            continue;
+        };
+        let mut parts = name.split('.');
+        let Some(Ok(version)) = parts.next().map(str::parse::<i64>) else {
+            continue;
+        };
+        match parts.collect::<Vec<_>>().as_slice() {
+            ["json"] => {
+                commits.insert(version, file.location);
+            }
+            ["checkpoint", "parquet"] => {
+                checkpoints.insert(version, (vec![file.location], 1));
+            }
+            ["checkpoint", _, count, "parquet"] => {
+                let Ok(count) = count.parse() else {
+                    continue;
+                };
+                let entry = checkpoints.entry(version).or_default();
+                entry.0.push(file.location);
+                entry.1 = count;
+            }
+            _ => {}
+        }
+    }
+    let checkpoints = checkpoints
+        .into_iter()
+        .filter(|(_, (parts, count))| parts.len() == *count)
+        .map(|(version, (mut parts, _))| {
+            parts.sort();
+            (version, parts)
+        })
+        .collect();
+    Ok((commits, checkpoints))
+}
+
+/// Apply the actions of the checkpoint file `path`
+async fn read_checkpoint(
+    store: &Arc<dyn ObjectStore>,
+    path: &Path,
+    state: &mut State,
+) -> Result<()> {
+    let meta = store.head(path).await?;
+    let reader = ParquetObjectReader::new(store.clone(), meta);
+    let batches = ParquetRecordBatchStreamBuilder::new(reader)
+        .await?
+        .build()?
+        .try_collect::<Vec<_>>()
+        .await?;
+    for batch in batches {
+        for row in 0..batch.num_rows() {
+            state.apply(checkpoint_action(&batch, row));
+        }
+    }
+    Ok(())
+}
+
+fn struct_column<'a>(batch: &'a RecordBatch, name: &str) -> Option<&'a StructArray> {
+    batch.column_by_name(name)?.as_struct_opt()
+}
+
+fn string_value(array: &StructArray, name: &str, row: usize) -> Option<String> {
+    let strings = array.column_by_name(name)?.as_string_opt::<i32>()?;
+    strings
+        .is_valid(row)
+        .then(|| strings.value(row).to_string())
+}
+
+fn map_value(array: &StructArray, name: &str, row: usize) -> HashMap<String, Option<String>> {
+    let Some(map) = array
+        .column_by_name(name)
+        .and_then(|column| column.as_map_opt())
+        .filter(|map: &&MapArray| map.is_valid(row))
+    else {
+        return HashMap::new();
+    };
+    let entries = map.value(row);
+    let (Some(keys), Some(values)) = (
+        entries.column(0).as_string_opt::<i32>(),
+        entries.column(1).as_string_opt::<i32>(),
+    ) else {
+        return HashMap::new();
+    };
+    (0..entries.len())
+        .map(|i| {
+            let value = values.is_valid(i).then(|| values.value(i).to_string());
+            (keys.value(i).to_string(), value)
+        })
+        .collect()
+}
+
+/// The action of the row `row` of a checkpoint
+fn checkpoint_action(batch: &RecordBatch, row: usize) -> Action {
+    let mut action = Action::default();
+    if let Some(add) = struct_column(batch, "add").filter(|add| add.is_valid(row)) {
+        if let Some(path) = string_value(add, "path", row) {
+            let deletion_vector = add
+                .column_by_name("deletionVector")
+                .filter(|dv| dv.is_valid(row))
+                .map(|_| Value::Bool(true));
+            action.add = Some(Add {
+                path,
+                partition_values: map_value(add, "partitionValues", row),
+                deletion_vector,
+            });
+        }
+    }
+    if let Some(metadata) = struct_column(batch, "metaData").filter(|m| m.is_valid(row)) {
+        if let Some(schema_string) = string_value(metadata, "schemaString", row) {
+            let partition_columns = metadata
+                .column_by_name("partitionColumns")
+                .and_then(|column| column.as_list_opt::<i32>())
+                .filter(|list| list.is_valid(row))
+                .map(|list| {
+                    let columns = list.value(row);
+                    let columns = columns.as_string::<i32>();
+                    columns.iter().flatten().map(str::to_string).collect()
+                })
+                .unwrap_or_default();
+            action.meta_data = Some(Metadata {
+                schema_string,
+                partition_columns,
+                configuration: map_value(metadata, "configuration", row),
+            });
+        }
+    }
+    action
+}
+
+/// The fields of the Delta struct type `value`
+fn struct_fields(value: &Value) -> Option<Vec<Field>> {
+    value
+        .get("fields")?
+        .as_array()?
+        .iter()
+        .map(|field| {
+            let name = field.get("name")?.as_str()?;
+            let nullable = field.get("nullable")?.as_bool()?;
+            Some(Field::new(name, data_type(field.get("type")?)?, nullable))
+        })
+        .collect()
+}
+
+/// The arrow type of the Delta type `value`
+fn data_type(value: &Value) -> Option<DataType> {
+    let data_type = match value {
+        Value::String(name) => match name.as_str() {
+            "string" => DataType::Utf8,
+            "long" => DataType::Int64,
+            "integer" => DataType::Int32,
+            "short" => DataType::Int16,
+            "byte" => DataType::Int8,
+            "float" => DataType::Float32,
+            "double" => DataType::Float64,
+            "boolean" => DataType::Boolean,
+            "binary" => DataType::Binary,
+            "date" => DataType::Date32,
+            "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            "timestamp_ntz" => DataType::Timestamp(TimeUnit::Microsecond, None),
+            name => decimal_type(name)?,
+        },
+        Value::Object(object) => match object.get("type")?.as_str()? {
+            "struct" => DataType::Struct(struct_fields(value)?.into()),
+            "array" => DataType::List(Arc::new(Field::new(
+                "element",
+                data_type(object.get("elementType")?)?,
+                object.get("containsNull")?.as_bool()?,
+            ))),
+            "map" => map_type(
+                data_type(object.get("keyType")?)?,
+                Field::new(
+                    "value",
+                    data_type(object.get("valueType")?)?,
+                    object.get("valueContainsNull")?.as_bool()?,
+                ),
+            ),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(data_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Int32Type, Int64Type};
+    use arrow_array::Int64Array;
+    use parquet::arrow::ArrowWriter;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::interop::PARTITIONING_METADATA_KEY;
+    use crate::query::ExecutableQuery;
+
+    fn write_file(path: &std::path::Path, values: Vec<i64>) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, true)]));
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(values))]).unwrap();
+        let mut writer =
+            ArrowWriter::try_new(std::fs::File::create(path).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_delta_source() {
+        let tmp_dir = tempdir().unwrap();
+        let table = tmp_dir.path().join("events");
+        write_file(&table.join("day=1/a.parquet"), vec![1, 2]);
+        write_file(&table.join("day=2/b.parquet"), vec![3]);
+        write_file(&table.join("day=2/c.parquet"), vec![4, 5, 6]);
+        let log = table.join("_delta_log");
+        std::fs::create_dir_all(&log).unwrap();
+        let schema = r#"{"type":"struct","fields":[{"name":"id","type":"long","nullable":true,"metadata":{}},{"name":"day","type":"integer","nullable":true,"metadata":{}}]}"#;
+        let metadata = serde_json::json!({"metaData": {
+            "id": "1",
+            "format": {"provider": "parquet", "options": {}},
+            "schemaString": schema,
+            "partitionColumns": ["day"],
+            "configuration": {},
+        }});
+        let add = |path: &str, day: &str| serde_json::json!({"add": {"path": path, "partitionValues": {"day": day}, "size": 1}});
+        std::fs::write(
+            log.join("00000000000000000000.json"),
+            format!(
+                "{}\n{}\n{}\n",
+                serde_json::json!({"protocol": {"minReaderVersion": 1, "minWriterVersion": 2}}),
+                metadata,
+                add("day=1/a.parquet", "1"),
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            log.join("00000000000000000001.json"),
+            format!(
+                "{}\n{}\n",
+                add("day%3D2/b.parquet", "2"),
+                add("day=2/c.parquet", "2"),
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            log.join("00000000000000000002.json"),
+            format!(
+                "{}\n",
+                serde_json::json!({"remove": {"path": "day=2/c.parquet"}})
+            ),
+        )
+        .unwrap();
+
+        let uri = table.to_str().unwrap();
+        let snapshot = DeltaSource::new(uri).version(1).load().await.unwrap();
+        assert_eq!(snapshot.num_files(), 3);
+        let snapshot = DeltaSource::new(uri).load().await.unwrap();
+        assert_eq!(snapshot.version(), 2);
+        assert_eq!(snapshot.num_files(), 2);
+        assert_eq!(snapshot.partitioning()[0].column, "day");
+
+        let db = connect(tmp_dir.path().join("db").to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("events", snapshot).execute().await.unwrap();
+        let schema = table.schema().await.unwrap();
+        assert!(schema.metadata().contains_key(PARTITIONING_METADATA_KEY));
+        let batches = table
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let mut rows = batches
+            .iter()
+            .flat_map(|batch| {
+                let ids = batch
+                    .column_by_name("id")
+                    .unwrap()
+                    .as_primitive::<Int64Type>();
+                let days = batch
+                    .column_by_name("day")
+                    .unwrap()
+                    .as_primitive::<Int32Type>();
+                ids.values()
+                    .iter()
+                    .copied()
+                    .zip(days.values().iter().copied())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        rows.sort();
+        assert_eq!(rows, vec![(1, 1), (2, 1), (3, 2)]);
+    }
+}
@@ -0,0 +1,482 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read snapshots of Apache Iceberg tables
+//!
+//! The table is read from its metadata files, without a catalog: the metadata
+//! file is the one named by `metadata/version-hint.text`, as written by the
+//! Hadoop catalog, or else the one with the highest version in `metadata/`.
+//! Another one can be chosen with [`IcebergSource::metadata_file`].  The data
+//! files of the snapshot are listed from its manifests, which are Avro files.
+//!
+//! Tables of the format versions 1 and 2 are supported, except for snapshots
+//! with delete files.  The partition columns of Iceberg tables are stored in
+//! the data files, so the partitioning is only kept as metadata.
+//!
+//! This requires the `iceberg` feature.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use apache_avro::types::Value as AvroValue;
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use futures::TryStreamExt;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use serde_json::Value;
+
+use super::{
+    decimal_type, file_path, invalid_table, map_type, open_store, DataFile, LakehouseSnapshot,
+    PartitionField,
+};
+use crate::error::{Error, Result};
+
+/// An Apache Iceberg table, to read a snapshot of
+///
+/// See the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct IcebergSource {
+    uri: String,
+    snapshot_id: Option<i64>,
+    metadata_file: Option<String>,
+    storage_options: HashMap<String, String>,
+}
+
+impl IcebergSource {
+    /// The Iceberg table at `uri`, the directory holding `metadata`
+    pub fn new(uri: impl Into<String>) -> Self {
+        Self {
+            uri: uri.into(),
+            snapshot_id: None,
+            metadata_file: None,
+            storage_options: HashMap::new(),
+        }
+    }
+
+    /// Read the snapshot `snapshot_id`, instead of the current one
+    pub fn snapshot_id(mut self, snapshot_id: i64) -> Self {
+        self.snapshot_id = Some(snapshot_id);
+        self
+    }
+
+    /// Read the metadata file `metadata_file`, instead of the latest one
+    ///
+    /// This is the name of a file of the `metadata` directory, or the URI of
+    /// a metadata file of the table, as returned by a catalog.
+    pub fn metadata_file(mut self, metadata_file: impl Into<String>) -> Self {
+        self.metadata_file = Some(metadata_file.into());
+        self
+    }
+
+    /// Set an option of the object store the table is read from
+    ///
+    /// See [`crate::connection::ConnectBuilder::storage_option`].
+    pub fn storage_option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.storage_options.insert(key.into(), value.into());
+        self
+    }
+
+    /// The path of the metadata file to read
+    async fn metadata_path(&self, store: &Arc<dyn ObjectStore>, root: &Path) -> Result<Path> {
+        let dir = root.child("metadata");
+        if let Some(file) = &self.metadata_file {
+            return if file.contains('/') {
+                file_path(root, &self.uri, file)
+            } else {
+                Ok(dir.child(file.as_str()))
+            };
+        }
+        match store.get(&dir.child("version-hint.text")).await {
+            Ok(hint) => {
+                let hint = hint.bytes().await?;
+                let version = String::from_utf8_lossy(&hint).trim().to_string();
+                return Ok(dir.child(format!("v{}.metadata.json", version)));
+            }
+            Err(object_store::Error::NotFound { .. }) => {}
+            Err(e) => return Err(e.into()),
+        }
+        // Named v<version>.metadata.json or <version>-<uuid>.metadata.json
+        let version = |path: &Path| -> Option<u64> {
+            let name = path.filename()?.strip_suffix(".metadata.json")?;
+            let name = name.strip_prefix('v').unwrap_or(name);
+            let end = name
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(name.len());
+            name[..end].parse().ok()
+        };
+        store
+            .list(Some(&dir))
+            .try_filter_map(|file| {
+                futures::future::ready(Ok(version(&file.location).map(|v| (v, file.location))))
+            })
+            .try_fold(None, |latest: Option<(u64, Path)>, file| {
+                futures::future::ready(Ok(match latest {
+                    Some(latest) if latest.0 >= file.0 => Some(latest),
+                    _ => Some(file),
+                }))
+            })
+            .await?
+            .map(|(_, path)| path)
+            .ok_or_else(|| invalid_table(&self.uri, "it has no metadata file"))
+    }
+
+    /// The records of the Avro file `path`
+    async fn read_avro(
+        &self,
+        store: &Arc<dyn ObjectStore>,
+        root: &Path,
+        location: &str,
+        path: &str,
+    ) -> Result<Vec<AvroValue>> {
+        let bytes = store
+            .get(&file_path(root, location, path)?)
+            .await?
+            .bytes()
+            .await?;
+        let avro_error = |e: apache_avro::Error| {
+            invalid_table(&self.uri, format!("invalid file {}: {}", path, e))
+        };
+        apache_avro::Reader::new(&bytes[..])
+            .map_err(avro_error)?
+            .map(|record| record.map_err(avro_error))
+            .collect()
+    }
+
+    /// Read the metadata of the table to find the schema and the data files of
+    /// the snapshot
+    pub async fn load(&self) -> Result<LakehouseSnapshot> {
+        let (store, root) = open_store(&self.uri, &self.storage_options).await?;
+        let metadata_path = self.metadata_path(&store, &root).await?;
+        let metadata = store.get(&metadata_path).await?.bytes().await?;
+        let metadata = serde_json::from_slice::<Value>(&metadata).map_err(|e| {
+            invalid_table(
+                &self.uri,
+                format!("invalid metadata {}: {}", metadata_path, e),
+            )
+        })?;
+        let invalid = |what: &str| invalid_table(&self.uri, format!("invalid metadata: {}", what));
+        // The data files are named by absolute URIs, under the location
+        let location = metadata
+            .get("location")
+            .and_then(Value::as_str)
+            .unwrap_or(&self.uri);
+
+        let snapshot_id = self.snapshot_id.or_else(|| {
+            metadata
+                .get("current-snapshot-id")
+                .and_then(Value::as_i64)
+                .filter(|id| *id != -1)
+        });
+        let snapshot = match snapshot_id {
+            Some(id) => Some(
+                metadata
+                    .get("snapshots")
+                    .and_then(Value::as_array)
+                    .and_then(|snapshots| {
+                        snapshots
+                            .iter()
+                            .find(|s| s.get("snapshot-id").and_then(Value::as_i64) == Some(id))
+                    })
+                    .ok_or_else(|| {
+                        invalid_table(&self.uri, format!("it has no snapshot {}", id))
+                    })?,
+            ),
+            None => None,
+        };
+
+        let schema_id = snapshot
+            .and_then(|s| s.get("schema-id"))
+            .or_else(|| metadata.get("current-schema-id"))
+            .and_then(Value::as_i64);
+        let schema = match schema_id {
+            Some(id) => metadata
+                .get("schemas")
+                .and_then(Value::as_array)
+                .and_then(|schemas| {
+                    schemas
+                        .iter()
+                        .find(|s| s.get("schema-id").and_then(Value::as_i64) == Some(id))
+                }),
+            None => metadata.get("schema"),
+        }
+        .ok_or_else(|| invalid("no schema"))?;
+        let fields = struct_fields(schema).ok_or_else(|| invalid("unsupported schema"))?;
+
+        let spec = match metadata.get("default-spec-id").and_then(Value::as_i64) {
+            Some(id) => metadata
+                .get("partition-specs")
+                .and_then(Value::as_array)
+                .and_then(|specs| {
+                    specs
+                        .iter()
+                        .find(|s| s.get("spec-id").and_then(Value::as_i64) == Some(id))
+                })
+                .and_then(|spec| spec.get("fields")),
+            None => metadata.get("partition-spec"),
+        };
+        let partitioning = spec
+            .and_then(Value::as_array)
+            .map(|fields| {
+                fields
+                    .iter()
+                    .map(|field| {
+                        let source_id = field.get("source-id").and_then(Value::as_i64)?;
+                        Some(PartitionField {
+                            column: column_name(schema, source_id)?,
+                            transform: field.get("transform")?.as_str()?.to_string(),
+                            name: field.get("name")?.as_str()?.to_string(),
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+            .unwrap_or(Some(Vec::new()))
+            .ok_or_else(|| invalid("unsupported partition spec"))?;
+
+        let mut files = Vec::new();
+        if let Some(snapshot) = snapshot {
+            let manifests = match snapshot.get("manifest-list").and_then(Value::as_str) {
+                Some(list) => self
+                    .read_avro(&store, &root, location, list)
+                    .await?
+                    .iter()
+                    .map(|manifest| {
+                        avro_field(manifest, "manifest_path")
+                            .and_then(avro_string)
+                            .map(str::to_string)
+                    })
+                    .collect::<Option<Vec<_>>>(),
+                None => snapshot
+                    .get("manifests")
+                    .and_then(Value::as_array)
+                    .and_then(|manifests| {
+                        manifests
+                            .iter()
+                            .map(|m| m.as_str().map(str::to_string))
+                            .collect()
+                    }),
+            }
+            .ok_or_else(|| invalid("the snapshot has no manifests"))?;
+            for manifest in manifests {
+                for entry in self.read_avro(&store, &root, location, &manifest).await? {
+                    // Entries of deleted files
+                    if avro_field(&entry, "status").and_then(avro_long) == Some(2) {
+                        continue;
+                    }
+                    let file = avro_field(&entry, "data_file")
+                        .ok_or_else(|| invalid("a manifest entry has no data file"))?;
+                    if avro_field(file, "content").and_then(avro_long).unwrap_or(0) != 0 {
+                        return Err(invalid_table(&self.uri, "delete files are not supported"));
+                    }
+                    let path = avro_field(file, "file_path")
+                        .and_then(avro_string)
+                        .ok_or_else(|| invalid("a data file has no path"))?;
+                    let format = avro_field(file, "file_format").and_then(avro_string);
+                    if !format.is_some_and(|format| format.eq_ignore_ascii_case("parquet")) {
+                        return Err(Error::InvalidInput {
+                            message: format!(
+                                "only Parquet data files are supported, {} is {:?}",
+                                path, format
+                            ),
+                        });
+                    }
+                    files.push(DataFile {
+                        path: file_path(&root, location, path)?,
+                        partition_values: HashMap::new(),
+                    });
+                }
+            }
+        }
+
+        LakehouseSnapshot::new(
+            "iceberg",
+            &self.uri,
+            snapshot_id.unwrap_or(-1),
+            Schema::new(fields),
+            partitioning,
+            store,
+            files,
+        )
+    }
+}
+
+/// The value of the field `name` of the Avro record `record`
+fn avro_field<'a>(record: &'a AvroValue, name: &str) -> Option<&'a AvroValue> {
+    let AvroValue::Record(fields) = record else {
+        return None;
+    };
+    match fields.iter().find(|(field, _)| field == name)? {
+        (_, AvroValue::Union(_, value)) => Some(value.as_ref()),
+        (_, value) => Some(value),
+    }
+}
+
+fn avro_string(value: &AvroValue) -> Option<&str> {
+    match value {
+        AvroValue::String(value) => Some(value),
+        AvroValue::Enum(_, value) => Some(value),
+        _ => None,
+    }
+}
+
+fn avro_long(value: &AvroValue) -> Option<i64> {
+    match value {
+        AvroValue::Int(value) => Some(*value as i64),
+        AvroValue::Long(value) => Some(*value),
+        _ => None,
+    }
+}
+
+/// The name of the column with the id `id` in the struct type `value`, with
+/// the names of its parents for nested columns
+fn column_name(value: &Value, id: i64) -> Option<String> {
+    value.get("fields")?.as_array()?.iter().find_map(|field| {
+        let name = field.get("name")?.as_str()?;
+        if field.get("id")?.as_i64()? == id {
+            return Some(name.to_string());
+        }
+        let child = column_name(field.get("type")?, id)?;
+        Some(format!("{}.{}", name, child))
+    })
+}
+
+/// The fields of the Iceberg struct type `value`
+fn struct_fields(value: &Value) -> Option<Vec<Field>> {
+    value
+        .get("fields")?
+        .as_array()?
+        .iter()
+        .map(|field| {
+            let name = field.get("name")?.as_str()?;
+            let required = field.get("required")?.as_bool()?;
+            Some(Field::new(name, data_type(field.get("type")?)?, !required))
+        })
+        .collect()
+}
+
+/// The arrow type of the Iceberg type `value`
+fn data_type(value: &Value) -> Option<DataType> {
+    let data_type = match value {
+        Value::String(name) => match name.as_str() {
+            "boolean" => DataType::Boolean,
+            "int" => DataType::Int32,
+            "long" => DataType::Int64,
+            "float" => DataType::Float32,
+            "double" => DataType::Float64,
+            "date" => DataType::Date32,
+            "time" => DataType::Time64(TimeUnit::Microsecond),
+            "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, None),
+            "timestamptz" => DataType::Timestamp(TimeUnit::Microsecond, Some("+00:00".into())),
+            "string" => DataType::Utf8,
+            "uuid" => DataType::FixedSizeBinary(16),
+            "binary" => DataType::Binary,
+            name => match name.strip_prefix("fixed[") {
+                Some(length) => DataType::FixedSizeBinary(length.strip_suffix(']')?.parse().ok()?),
+                None => decimal_type(&name.replace(' ', ""))?,
+            },
+        },
+        Value::Object(object) => match object.get("type")?.as_str()? {
+            "struct" => DataType::Struct(struct_fields(value)?.into()),
+            "list" => DataType::List(Arc::new(Field::new(
+                "element",
+                data_type(object.get("element")?)?,
+                !object.get("element-required")?.as_bool()?,
+            ))),
+            "map" => map_type(
+                data_type(object.get("key")?)?,
+                Field::new(
+                    "value",
+                    data_type(object.get("value")?)?,
+                    !object.get("value-required")?.as_bool()?,
+                ),
+            ),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(data_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_iceberg_metadata() {
+        let tmp_dir = tempdir().unwrap();
+        let table = tmp_dir.path().join("events");
+        let metadata = table.join("metadata");
+        std::fs::create_dir_all(&metadata).unwrap();
+        let schema = serde_json::json!({
+            "type": "struct",
+            "schema-id": 0,
+            "fields": [
+                {"id": 1, "name": "id", "required": true, "type": "long"},
+                {"id": 2, "name": "ts", "required": false, "type": "timestamptz"},
+                {"id": 3, "name": "price", "required": false, "type": "decimal(10, 2)"},
+                {"id": 4, "name": "tags", "required": false, "type": {
+                    "type": "list", "element-id": 5, "element": "string", "element-required": false
+                }},
+            ],
+        });
+        let write = |name: &str, current_schema_id: i64| {
+            let json = serde_json::json!({
+                "format-version": 2,
+                "location": table.to_str().unwrap(),
+                "current-schema-id": current_schema_id,
+                "schemas": [schema],
+                "default-spec-id": 0,
+                "partition-specs": [{"spec-id": 0, "fields": [
+                    {"source-id": 2, "field-id": 1000, "name": "ts_day", "transform": "day"}
+                ]}],
+                "current-snapshot-id": -1,
+                "snapshots": [],
+            });
+            std::fs::write(metadata.join(name), json.to_string()).unwrap();
+        };
+        write("00001-a.metadata.json", 1);
+        write("00002-b.metadata.json", 0);
+
+        // The latest metadata file is read
+        let snapshot = IcebergSource::new(table.to_str().unwrap())
+            .load()
+            .await
+            .unwrap();
+        assert_eq!(snapshot.num_files(), 0);
+        assert_eq!(
+            snapshot.partitioning(),
+            &[PartitionField {
+                column: "ts".to_string(),
+                transform: "day".to_string(),
+                name: "ts_day".to_string(),
+            }]
+        );
+        let schema = snapshot.schema();
+        assert!(!schema.field(0).is_nullable());
+        assert_eq!(
+            schema.field(1).data_type(),
+            &DataType::Timestamp(TimeUnit::Microsecond, Some("+00:00".into()))
+        );
+        assert_eq!(schema.field(2).data_type(), &DataType::Decimal128(10, 2));
+        assert!(matches!(schema.field(3).data_type(), DataType::List(_)));
+
+        // The first one has no schema 1
+        let result = IcebergSource::new(table.to_str().unwrap())
+            .metadata_file("00001-a.metadata.json")
+            .load()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+    }
+}
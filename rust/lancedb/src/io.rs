@@ -1 +1,7 @@
+pub mod cache;
+pub mod checksum;
+pub mod commit_lock;
+pub mod encryption;
+pub mod limit;
+pub mod memory;
 pub mod object_store;
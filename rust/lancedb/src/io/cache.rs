@@ -0,0 +1,628 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Caches of the files of the tables, which can be shared by processes
+//!
+//! Lance caches the indices and the metadata it has read in memory, in caches
+//! private to the process (see
+//! [`crate::connection::OpenTableBuilder::index_cache_size`]).  A process that
+//! just started, such as a new query pod, reads all of them from the object
+//! store again.  A [`CacheBackend`], set with
+//! [`crate::connection::ConnectBuilder::cache_backend`], sits in front of the
+//! object store: the reads of index files, and the small reads of data files
+//! such as their metadata, are looked up in the backend first and the backend
+//! is filled with the bytes read from the object store.  A backend shared by
+//! several processes, for example one backed by Redis or by shared memory, lets
+//! them share a warm cache.  [`InMemoryCache`] is a backend private to the
+//! process.  [`ReadCacheOptions`], set with
+//! [`crate::connection::ConnectBuilder::read_cache`], configures a backend
+//! keeping the most recently used bytes in memory and, optionally, more of them
+//! in files on a local disk.  The disk tier survives restarts of the process, so
+//! a restarted process starts with a warm cache.
+//!
+//! The cached files are never modified once written, and their names are
+//! unique, so the cached bytes cannot go stale.  The cache is best effort: if
+//! the backend fails, the failure is logged and the object store is read.
+//!
+//! Lance reads the files of local tables directly, without going through the
+//! object store, so the backend only applies to tables in object stores such
+//! as S3.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use lance::io::WrappingObjectStore;
+use object_store::{
+    path::Path, GetOptions, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore,
+    PutOptions, PutResult,
+};
+use tokio::io::AsyncWrite;
+
+use crate::error::{Error, Result};
+use crate::io::checksum::is_immutable;
+use crate::metrics::MetricsRegistry;
+
+/// The largest read that is cached, larger reads are scans of the data
+const MAX_CACHED_READ: usize = 4 * 1024 * 1024;
+
+/// A cache of the bytes read from the files of the tables
+///
+/// The keys identify a range of a file and are at most a few hundred bytes.
+#[async_trait]
+pub trait CacheBackend: std::fmt::Debug + Send + Sync {
+    /// The bytes cached for `key`, if any
+    async fn get(&self, key: &str) -> Result<Option<Bytes>>;
+
+    /// Cache `value` for `key`
+    ///
+    /// The backend can evict the value at any time.
+    async fn put(&self, key: &str, value: Bytes) -> Result<()>;
+}
+
+#[derive(Debug, Default)]
+struct LruState {
+    /// The cached values and the tick of their last use
+    entries: HashMap<String, (Bytes, u64)>,
+    /// The keys by the tick of their last use
+    by_use: BTreeMap<u64, String>,
+    size: usize,
+    tick: u64,
+}
+
+/// A [`CacheBackend`] keeping the least recently used values in memory
+///
+/// Like the caches of Lance, this cache is private to the process.  It can be
+/// larger than them, since it holds the bytes of the files instead of the
+/// decoded indices.
+#[derive(Debug)]
+pub struct InMemoryCache {
+    capacity: usize,
+    state: Mutex<LruState>,
+}
+
+impl InMemoryCache {
+    /// A cache holding up to `capacity` bytes
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(LruState::default()),
+        }
+    }
+
+    /// The number of bytes cached
+    pub fn size(&self) -> usize {
+        self.state.lock().unwrap().size
+    }
+}
+
+#[async_trait]
+impl CacheBackend for InMemoryCache {
+    async fn get(&self, key: &str) -> Result<Option<Bytes>> {
+        let mut state = self.state.lock()?;
+        state.tick += 1;
+        let tick = state.tick;
+        let Some((value, last_use)) = state.entries.get_mut(key) else {
+            return Ok(None);
+        };
+        let previous = std::mem::replace(last_use, tick);
+        let value = value.clone();
+        state.by_use.remove(&previous);
+        state.by_use.insert(tick, key.to_string());
+        Ok(Some(value))
+    }
+
+    async fn put(&self, key: &str, value: Bytes) -> Result<()> {
+        if value.len() > self.capacity {
+            return Ok(());
+        }
+        let mut state = self.state.lock()?;
+        state.tick += 1;
+        let tick = state.tick;
+        state.size += value.len();
+        if let Some((previous, last_use)) = state.entries.insert(key.to_string(), (value, tick)) {
+            state.size -= previous.len();
+            state.by_use.remove(&last_use);
+        }
+        state.by_use.insert(tick, key.to_string());
+        while state.size > self.capacity {
+            let Some((_, evicted)) = state.by_use.pop_first() else {
+                break;
+            };
+            if let Some((value, _)) = state.entries.remove(&evicted) {
+                state.size -= value.len();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The size of the read cache of a connection, see [`TieredCache`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadCacheOptions {
+    /// The number of bytes cached in memory, the default is 256 MiB
+    pub memory_size: usize,
+    /// The directory of the files of the disk tier, None to only cache in memory
+    ///
+    /// The directory should be on a local disk and only used by one process.
+    /// The bytes cached in it are used again when the process restarts.  By
+    /// default there is no disk tier.
+    pub disk_dir: Option<PathBuf>,
+    /// The number of bytes cached on disk, the default is 10 GiB
+    pub disk_size: u64,
+}
+
+impl Default for ReadCacheOptions {
+    fn default() -> Self {
+        Self {
+            memory_size: 256 * 1024 * 1024,
+            disk_dir: None,
+            disk_size: 10 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// A stable 64 bit FNV-1a hash, naming the files of the disk tier
+fn file_name(key: &str) -> String {
+    let mut hash = 0xcbf29ce484222325_u64;
+    for byte in key.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[derive(Debug, Default)]
+struct DiskState {
+    /// The size of the cached files and the tick of their last use, by name
+    files: HashMap<String, (u64, u64)>,
+    /// The names of the files by the tick of their last use
+    by_use: BTreeMap<u64, String>,
+    size: u64,
+    tick: u64,
+}
+
+impl DiskState {
+    fn touch(&mut self, name: &str, size: u64) {
+        self.tick += 1;
+        let tick = self.tick;
+        self.size += size;
+        if let Some((previous, last_use)) = self.files.insert(name.to_string(), (size, tick)) {
+            self.size -= previous;
+            self.by_use.remove(&last_use);
+        }
+        self.by_use.insert(tick, name.to_string());
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some((size, last_use)) = self.files.remove(name) {
+            self.size -= size;
+            self.by_use.remove(&last_use);
+        }
+    }
+}
+
+/// A [`CacheBackend`] keeping the least recently used values in the files of a
+/// local directory
+///
+/// Each value is stored in a file, after the key it is cached for.  The files
+/// found in the directory when the cache is created are used, the least
+/// recently modified being evicted first.
+#[derive(Debug)]
+pub struct DiskCache {
+    dir: PathBuf,
+    capacity: u64,
+    state: Mutex<DiskState>,
+}
+
+impl DiskCache {
+    /// A cache holding up to `capacity` bytes in files of `dir`, which is
+    /// created if needed
+    pub fn new(dir: impl Into<PathBuf>, capacity: u64) -> Result<Self> {
+        let dir = dir.into();
+        let io_error = |e: std::io::Error| Error::Runtime {
+            message: format!(
+                "failed to open the cache directory {}: {}",
+                dir.display(),
+                e
+            ),
+        };
+        std::fs::create_dir_all(&dir).map_err(io_error)?;
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&dir).map_err(io_error)? {
+            let entry = entry.map_err(io_error)?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.ends_with(".tmp") {
+                // Left by a write which did not finish
+                let _ = std::fs::remove_file(entry.path());
+                continue;
+            }
+            let metadata = entry.metadata().map_err(io_error)?;
+            if metadata.is_file() {
+                files.push((metadata.modified().ok(), name, metadata.len()));
+            }
+        }
+        files.sort();
+        let mut state = DiskState::default();
+        for (_, name, size) in files {
+            state.touch(&name, size);
+        }
+        let cache = Self {
+            dir,
+            capacity,
+            state: Mutex::new(state),
+        };
+        {
+            let mut state = cache.state.lock().map_err(Error::from)?;
+            cache.evict(&mut state);
+        }
+        Ok(cache)
+    }
+
+    /// The number of bytes cached
+    pub fn size(&self) -> u64 {
+        self.state.lock().unwrap().size
+    }
+
+    /// Remove the least recently used files until the cache fits its capacity
+    fn evict(&self, state: &mut DiskState) {
+        while state.size > self.capacity {
+            let Some((_, evicted)) = state.by_use.pop_first() else {
+                break;
+            };
+            if let Some((size, _)) = state.files.remove(&evicted) {
+                state.size -= size;
+            }
+            if let Err(e) = std::fs::remove_file(self.dir.join(&evicted)) {
+                log::warn!("Failed to evict {} from the disk cache: {}", evicted, e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl CacheBackend for DiskCache {
+    async fn get(&self, key: &str) -> Result<Option<Bytes>> {
+        let name = file_name(key);
+        if !self.state.lock()?.files.contains_key(&name) {
+            return Ok(None);
+        }
+        let contents = match tokio::fs::read(self.dir.join(&name)).await {
+            Ok(contents) => Bytes::from(contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.state.lock()?.remove(&name);
+                return Ok(None);
+            }
+            Err(e) => {
+                return Err(Error::Runtime {
+                    message: format!("failed to read {} from the disk cache: {}", name, e),
+                })
+            }
+        };
+        // The file starts with the length of the key and the key, since
+        // several keys can have the same file name
+        let key_len = contents
+            .get(..4)
+            .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize);
+        let Some(value_start) = key_len.map(|len| 4 + len) else {
+            return Ok(None);
+        };
+        if contents.get(4..value_start) != Some(key.as_bytes()) {
+            return Ok(None);
+        }
+        self.state.lock()?.touch(&name, contents.len() as u64);
+        Ok(Some(contents.slice(value_start..)))
+    }
+
+    async fn put(&self, key: &str, value: Bytes) -> Result<()> {
+        let size = (4 + key.len() + value.len()) as u64;
+        if size > self.capacity {
+            return Ok(());
+        }
+        let name = file_name(key);
+        let mut contents = Vec::with_capacity(size as usize);
+        contents.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        contents.extend_from_slice(key.as_bytes());
+        contents.extend_from_slice(&value);
+        // Written to a temporary file first, so that a partial file is never read
+        let path = self.dir.join(&name);
+        let tmp_path = self.dir.join(format!("{}.tmp", name));
+        let written = async {
+            tokio::fs::write(&tmp_path, &contents).await?;
+            tokio::fs::rename(&tmp_path, &path).await
+        };
+        if let Err(e) = written.await {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return Err(Error::Runtime {
+                message: format!("failed to write {} to the disk cache: {}", name, e),
+            });
+        }
+        let mut state = self.state.lock()?;
+        state.touch(&name, size);
+        self.evict(&mut state);
+        Ok(())
+    }
+}
+
+/// A [`CacheBackend`] with a memory tier in front of an optional disk tier
+///
+/// The values are written to both tiers.  A value read from the disk tier is
+/// copied to the memory tier.
+#[derive(Debug)]
+pub struct TieredCache {
+    memory: InMemoryCache,
+    disk: Option<DiskCache>,
+}
+
+impl TieredCache {
+    pub fn new(options: &ReadCacheOptions) -> Result<Self> {
+        let disk = options
+            .disk_dir
+            .as_ref()
+            .map(|dir| DiskCache::new(dir, options.disk_size))
+            .transpose()?;
+        Ok(Self {
+            memory: InMemoryCache::new(options.memory_size),
+            disk,
+        })
+    }
+}
+
+#[async_trait]
+impl CacheBackend for TieredCache {
+    async fn get(&self, key: &str) -> Result<Option<Bytes>> {
+        if let Some(value) = self.memory.get(key).await? {
+            return Ok(Some(value));
+        }
+        let Some(disk) = &self.disk else {
+            return Ok(None);
+        };
+        let value = disk.get(key).await?;
+        if let Some(value) = &value {
+            self.memory.put(key, value.clone()).await?;
+        }
+        Ok(value)
+    }
+
+    async fn put(&self, key: &str, value: Bytes) -> Result<()> {
+        self.memory.put(key, value.clone()).await?;
+        if let Some(disk) = &self.disk {
+            disk.put(key, value).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Wraps the object stores of the tables to read through a [`CacheBackend`]
+#[derive(Debug)]
+pub(crate) struct CachingObjectStoreWrapper {
+    backend: Arc<dyn CacheBackend>,
+    /// Applied to the object store before this wrapper
+    inner: Option<Arc<dyn WrappingObjectStore>>,
+    /// Counts the hits and misses of the cache
+    metrics: Option<Arc<MetricsRegistry>>,
+}
+
+impl CachingObjectStoreWrapper {
+    pub(crate) fn new(
+        backend: Arc<dyn CacheBackend>,
+        inner: Option<Arc<dyn WrappingObjectStore>>,
+        metrics: Option<Arc<MetricsRegistry>>,
+    ) -> Self {
+        Self {
+            backend,
+            inner,
+            metrics,
+        }
+    }
+}
+
+impl WrappingObjectStore for CachingObjectStoreWrapper {
+    fn wrap(&self, original: Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
+        let inner = match &self.inner {
+            Some(wrapper) => wrapper.wrap(original),
+            None => original,
+        };
+        Arc::new(CachingObjectStore {
+            inner,
+            backend: self.backend.clone(),
+            metrics: self.metrics.clone(),
+        })
+    }
+}
+
+/// An object store reading the immutable files through a [`CacheBackend`]
+#[derive(Debug)]
+struct CachingObjectStore {
+    inner: Arc<dyn ObjectStore>,
+    backend: Arc<dyn CacheBackend>,
+    metrics: Option<Arc<MetricsRegistry>>,
+}
+
+impl std::fmt::Display for CachingObjectStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CachingObjectStore({})", self.inner)
+    }
+}
+
+/// The key of the bytes in `range` of the file at `location`
+fn cache_key(location: &Path, range: &Range<usize>) -> String {
+    format!("{}#{}-{}", location, range.start, range.end)
+}
+
+#[async_trait]
+impl ObjectStore for CachingObjectStore {
+    async fn put(&self, location: &Path, bytes: Bytes) -> object_store::Result<PutResult> {
+        self.inner.put(location, bytes).await
+    }
+
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        self.inner.put_opts(location, bytes, options).await
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> object_store::Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        self.inner.put_multipart(location).await
+    }
+
+    async fn abort_multipart(
+        &self,
+        location: &Path,
+        multipart_id: &MultipartId,
+    ) -> object_store::Result<()> {
+        self.inner.abort_multipart(location, multipart_id).await
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        self.inner.get_opts(location, options).await
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> object_store::Result<Bytes> {
+        if range.len() > MAX_CACHED_READ || !is_immutable(location) {
+            return self.inner.get_range(location, range).await;
+        }
+        let key = cache_key(location, &range);
+        let cached = match self.backend.get(&key).await {
+            Ok(cached) => cached,
+            Err(e) => {
+                log::warn!("Failed to read {} from the cache: {}", key, e);
+                None
+            }
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.observe_cache_read(cached.is_some());
+        }
+        if let Some(bytes) = cached {
+            return Ok(bytes);
+        }
+        let bytes = self.inner.get_range(location, range).await?;
+        if let Err(e) = self.backend.put(&key, bytes.clone()).await {
+            log::warn!("Failed to write {} to the cache: {}", key, e);
+        }
+        Ok(bytes)
+    }
+
+    async fn head(&self, location: &Path) -> object_store::Result<ObjectMeta> {
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> object_store::Result<()> {
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_memory_cache() {
+        let cache = InMemoryCache::new(10);
+        cache.put("a", Bytes::from("aaaa")).await.unwrap();
+        cache.put("b", Bytes::from("bbbb")).await.unwrap();
+        // Reading a makes b the least recently used
+        assert_eq!(cache.get("a").await.unwrap(), Some(Bytes::from("aaaa")));
+        cache.put("c", Bytes::from("cccc")).await.unwrap();
+        assert_eq!(cache.get("b").await.unwrap(), None);
+        assert!(cache.get("a").await.unwrap().is_some());
+        assert!(cache.get("c").await.unwrap().is_some());
+        assert_eq!(cache.size(), 8);
+
+        cache.put("a", Bytes::from("a")).await.unwrap();
+        assert_eq!(cache.size(), 5);
+        cache.put("big", Bytes::from("x".repeat(11))).await.unwrap();
+        assert_eq!(cache.get("big").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_tiered_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = ReadCacheOptions {
+            memory_size: 4,
+            disk_dir: Some(dir.path().to_path_buf()),
+            disk_size: 64,
+        };
+        let cache = TieredCache::new(&options).unwrap();
+        cache.put("a", Bytes::from("aaaa")).await.unwrap();
+        cache.put("b", Bytes::from("bbbb")).await.unwrap();
+        // a was evicted from memory but is still on disk
+        assert_eq!(cache.get("a").await.unwrap(), Some(Bytes::from("aaaa")));
+        assert_eq!(cache.get("c").await.unwrap(), None);
+
+        // The disk tier is used again by a new cache
+        let cache = TieredCache::new(&options).unwrap();
+        assert_eq!(cache.get("b").await.unwrap(), Some(Bytes::from("bbbb")));
+        let disk = cache.disk.as_ref().unwrap();
+        assert_eq!(disk.size(), 2 * (4 + 1 + 4));
+        // a and then b are evicted to make room for big
+        cache.put("big", Bytes::from("x".repeat(50))).await.unwrap();
+        assert_eq!(disk.size(), 4 + 3 + 50);
+        assert_eq!(cache.get("a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_caching_object_store() {
+        let memory = Arc::new(InMemory::new());
+        let cache = Arc::new(InMemoryCache::new(1024));
+        let store = CachingObjectStoreWrapper::new(cache.clone(), None, None).wrap(memory.clone());
+
+        let index = Path::from("db/t.lance/_indices/uuid/index.idx");
+        let manifest = Path::from("db/t.lance/_versions/1.manifest");
+        store.put(&index, Bytes::from("index")).await.unwrap();
+        store.put(&manifest, Bytes::from("manifest")).await.unwrap();
+
+        assert_eq!(store.get_range(&index, 0..3).await.unwrap(), "ind");
+        assert_eq!(store.get_range(&manifest, 0..3).await.unwrap(), "man");
+        // Only the immutable index file is cached
+        assert_eq!(cache.size(), 3);
+
+        // Cached reads do not reach the object store
+        memory.delete(&index).await.unwrap();
+        assert_eq!(store.get_range(&index, 0..3).await.unwrap(), "ind");
+        assert!(store.get_range(&index, 0..4).await.is_err());
+    }
+}
@@ -0,0 +1,475 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checksums of the files of a table
+//!
+//! When enabled with [`crate::connection::ConnectBuilder::checksums`], a CRC32
+//! checksum is stored for each data and index file as it is written.  The
+//! checksums live in a `_checksums` directory in the table's directory, which
+//! mirrors the layout of the table (e.g. `_checksums/data/<file>.crc32`).
+//!
+//! In [`ChecksumMode::Verify`] each file is checked against its checksum the
+//! first time it is read by the connection, and the read fails if the file is
+//! corrupted.  [`crate::Table::verify_checksums`] checks all of the files of a
+//! table at once.
+//!
+//! The manifests of the table are rewritten in place and are not checksummed.
+//! Files written without checksums enabled have no checksum and are not
+//! verified on read.
+
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{FutureExt, TryStreamExt};
+use lance::io::WrappingObjectStore;
+use object_store::{
+    path::Path, Error, GetOptions, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore,
+    PutOptions, PutResult, Result,
+};
+use tokio::io::AsyncWrite;
+
+const CHECKSUM_DIR: &str = "_checksums";
+const CHECKSUM_EXTENSION: &str = "crc32";
+/// The directories of a table whose files are immutable once written
+const CHECKSUMMED_DIRS: [&str; 2] = ["data", "_indices"];
+
+/// Whether checksums are written and verified
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumMode {
+    /// Checksums are neither written nor verified
+    #[default]
+    Disabled,
+    /// Checksums are written with the files
+    Write,
+    /// Checksums are written with the files and files are verified the first
+    /// time they are read
+    Verify,
+}
+
+/// The result of [`crate::Table::verify_checksums`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChecksumReport {
+    /// The number of files that match their checksum
+    pub verified: usize,
+    /// The files without a checksum, e.g. written before checksums were enabled
+    pub missing: Vec<String>,
+    /// The files that do not match their checksum
+    pub corrupted: Vec<String>,
+}
+
+impl ChecksumReport {
+    /// Whether no corrupted file was found
+    pub fn is_ok(&self) -> bool {
+        self.corrupted.is_empty()
+    }
+}
+
+/// The result of checking a file against its checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChecksumStatus {
+    Valid,
+    Missing,
+    Corrupted { expected: u32, actual: u32 },
+}
+
+/// Where the checksum of the file at `location` is stored
+///
+/// Returns None for the files that are not checksummed.  The table's directory
+/// is the one that contains the `data` or `_indices` directory.
+pub(crate) fn checksum_path(location: &Path) -> Option<Path> {
+    let parts = location.parts().collect::<Vec<_>>();
+    let dir = (1..parts.len()).rev().find(|&i| {
+        CHECKSUMMED_DIRS.contains(&parts[i].as_ref()) && parts[i - 1].as_ref().ends_with(".lance")
+    })?;
+    if dir + 1 >= parts.len() {
+        return None;
+    }
+    let mut path = parts[..dir]
+        .iter()
+        .cloned()
+        .chain(std::iter::once(CHECKSUM_DIR.into()))
+        .chain(parts[dir..parts.len() - 1].iter().cloned())
+        .collect::<Path>();
+    let file_name = format!("{}.{}", parts[parts.len() - 1].as_ref(), CHECKSUM_EXTENSION);
+    path = path.child(file_name);
+    Some(path)
+}
+
+/// Whether the file at `location` is never modified once written
+///
+/// These are the files of a table that are checksummed.
+pub(crate) fn is_immutable(location: &Path) -> bool {
+    checksum_path(location).is_some()
+}
+
+/// Check the file at `location` against its checksum
+pub(crate) async fn verify_object(
+    store: &dyn ObjectStore,
+    location: &Path,
+) -> Result<ChecksumStatus> {
+    let Some(checksum_path) = checksum_path(location) else {
+        return Ok(ChecksumStatus::Missing);
+    };
+    let expected = match store.get(&checksum_path).await {
+        Ok(result) => parse_checksum(&checksum_path, &result.bytes().await?)?,
+        Err(Error::NotFound { .. }) => return Ok(ChecksumStatus::Missing),
+        Err(e) => return Err(e),
+    };
+    let mut hasher = crc32fast::Hasher::new();
+    let mut stream = store.get(location).await?.into_stream();
+    while let Some(bytes) = stream.try_next().await? {
+        hasher.update(&bytes);
+    }
+    let actual = hasher.finalize();
+    if actual == expected {
+        Ok(ChecksumStatus::Valid)
+    } else {
+        Ok(ChecksumStatus::Corrupted { expected, actual })
+    }
+}
+
+/// Check all of the checksummed files under `dir` against their checksums
+pub(crate) async fn verify_dir(store: &dyn ObjectStore, dir: &Path) -> Result<ChecksumReport> {
+    let files = store.list(Some(dir)).try_collect::<Vec<_>>().await?;
+    let mut report = ChecksumReport::default();
+    for file in files {
+        let location = file.location;
+        if checksum_path(&location).is_none() {
+            continue;
+        }
+        match verify_object(store, &location).await? {
+            ChecksumStatus::Valid => report.verified += 1,
+            ChecksumStatus::Missing => report.missing.push(location.to_string()),
+            ChecksumStatus::Corrupted { .. } => report.corrupted.push(location.to_string()),
+        }
+    }
+    Ok(report)
+}
+
+fn parse_checksum(path: &Path, bytes: &[u8]) -> Result<u32> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|checksum| u32::from_str_radix(checksum.trim(), 16).ok())
+        .ok_or_else(|| Error::Generic {
+            store: "checksum",
+            source: format!("invalid checksum file {}", path).into(),
+        })
+}
+
+fn format_checksum(checksum: u32) -> Bytes {
+    Bytes::from(format!("{:08x}", checksum))
+}
+
+/// An object store that writes a checksum with each data and index file and
+/// optionally verifies the files when they are read
+#[derive(Debug)]
+struct ChecksumObjectStore {
+    inner: Arc<dyn ObjectStore>,
+    verify: bool,
+    /// The files verified so far, which are not verified again
+    verified: Arc<Mutex<HashSet<Path>>>,
+}
+
+impl std::fmt::Display for ChecksumObjectStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ChecksumObjectStore({})", self.inner)
+    }
+}
+
+impl ChecksumObjectStore {
+    async fn verify_once(&self, location: &Path) -> Result<()> {
+        if checksum_path(location).is_none() || self.verified.lock().unwrap().contains(location) {
+            return Ok(());
+        }
+        match verify_object(self.inner.as_ref(), location).await? {
+            ChecksumStatus::Corrupted { expected, actual } => Err(Error::Generic {
+                store: "checksum",
+                source: format!(
+                    "the file {} is corrupted, its checksum is {:08x} but {:08x} was expected",
+                    location, actual, expected
+                )
+                .into(),
+            }),
+            ChecksumStatus::Valid | ChecksumStatus::Missing => {
+                self.verified.lock().unwrap().insert(location.clone());
+                Ok(())
+            }
+        }
+    }
+
+    /// Copy the checksum of `from`, if it has one, to be the checksum of `to`
+    async fn copy_checksum(&self, from: &Path, to: &Path) -> Result<()> {
+        let (Some(from), Some(to)) = (checksum_path(from), checksum_path(to)) else {
+            return Ok(());
+        };
+        match self.inner.copy(&from, &to).await {
+            Err(Error::NotFound { .. }) | Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[async_trait]
+impl ObjectStore for ChecksumObjectStore {
+    async fn put(&self, location: &Path, bytes: Bytes) -> Result<PutResult> {
+        self.put_opts(location, bytes, PutOptions::default()).await
+    }
+
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> Result<PutResult> {
+        let checksum = crc32fast::hash(&bytes);
+        let result = self.inner.put_opts(location, bytes, options).await?;
+        if let Some(checksum_path) = checksum_path(location) {
+            self.inner
+                .put(&checksum_path, format_checksum(checksum))
+                .await?;
+        }
+        Ok(result)
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        let (id, upload) = self.inner.put_multipart(location).await?;
+        let Some(checksum_path) = checksum_path(location) else {
+            return Ok((id, upload));
+        };
+        let upload = ChecksumUpload {
+            upload,
+            hasher: crc32fast::Hasher::new(),
+            store: self.inner.clone(),
+            checksum_path,
+            state: ChecksumUploadState::Writing,
+        };
+        Ok((id, Box::new(upload)))
+    }
+
+    async fn abort_multipart(&self, location: &Path, multipart_id: &MultipartId) -> Result<()> {
+        self.inner.abort_multipart(location, multipart_id).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        if self.verify {
+            self.verify_once(location).await?;
+        }
+        self.inner.get_opts(location, options).await
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.inner.delete(location).await?;
+        if let Some(checksum_path) = checksum_path(location) {
+            match self.inner.delete(&checksum_path).await {
+                Err(Error::NotFound { .. }) | Ok(_) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.copy(from, to).await?;
+        self.copy_checksum(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.copy_if_not_exists(from, to).await?;
+        self.copy_checksum(from, to).await
+    }
+}
+
+enum ChecksumUploadState {
+    Writing,
+    StoringChecksum(BoxFuture<'static, Result<PutResult>>),
+    Completed,
+}
+
+/// A multipart upload that stores the checksum of the uploaded bytes once the
+/// upload is complete
+struct ChecksumUpload {
+    upload: Box<dyn AsyncWrite + Unpin + Send>,
+    hasher: crc32fast::Hasher,
+    store: Arc<dyn ObjectStore>,
+    checksum_path: Path,
+    state: ChecksumUploadState,
+}
+
+impl AsyncWrite for ChecksumUpload {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        if !matches!(this.state, ChecksumUploadState::Writing) {
+            return Poll::Ready(Err(std::io::Error::other("already shutdown")));
+        }
+        let written = Pin::new(&mut this.upload).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = &written {
+            this.hasher.update(&buf[..*n]);
+        }
+        written
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().upload).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            match &mut this.state {
+                ChecksumUploadState::Writing => {
+                    match Pin::new(&mut this.upload).poll_shutdown(cx) {
+                        Poll::Ready(Ok(())) => {
+                            // The checksum is only stored once the file is complete
+                            let checksum = this.hasher.clone().finalize();
+                            let store = this.store.clone();
+                            let path = this.checksum_path.clone();
+                            this.state = ChecksumUploadState::StoringChecksum(
+                                async move { store.put(&path, format_checksum(checksum)).await }
+                                    .boxed(),
+                            );
+                        }
+                        other => return other,
+                    }
+                }
+                ChecksumUploadState::StoringChecksum(future) => {
+                    let result = futures::ready!(future.poll_unpin(cx));
+                    this.state = ChecksumUploadState::Completed;
+                    return Poll::Ready(result.map(|_| ()).map_err(std::io::Error::from));
+                }
+                ChecksumUploadState::Completed => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+/// A [`WrappingObjectStore`] that writes, and optionally verifies, checksums
+///
+/// See the [module level documentation](self) for more details.
+#[derive(Debug)]
+pub struct ChecksumObjectStoreWrapper {
+    verify: bool,
+    /// Applied to the object store before this wrapper
+    inner: Option<Arc<dyn WrappingObjectStore>>,
+    verified: Arc<Mutex<HashSet<Path>>>,
+}
+
+impl ChecksumObjectStoreWrapper {
+    /// Create a wrapper, verifying the files on read if `verify` is true
+    ///
+    /// The object store is first wrapped by `inner`, if provided.
+    pub fn new(verify: bool, inner: Option<Arc<dyn WrappingObjectStore>>) -> Self {
+        Self {
+            verify,
+            inner,
+            verified: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+}
+
+impl WrappingObjectStore for ChecksumObjectStoreWrapper {
+    fn wrap(&self, original: Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
+        let inner = match &self.inner {
+            Some(wrapper) => wrapper.wrap(original),
+            None => original,
+        };
+        Arc::new(ChecksumObjectStore {
+            inner,
+            verify: self.verify,
+            verified: self.verified.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    #[test]
+    fn test_checksum_path() {
+        let path = Path::from("db/t.lance/data/abc.lance");
+        assert_eq!(
+            checksum_path(&path),
+            Some(Path::from("db/t.lance/_checksums/data/abc.lance.crc32"))
+        );
+        let path = Path::from("data/t.lance/_indices/uuid/index.idx");
+        assert_eq!(
+            checksum_path(&path),
+            Some(Path::from(
+                "data/t.lance/_checksums/_indices/uuid/index.idx.crc32"
+            ))
+        );
+        assert_eq!(
+            checksum_path(&Path::from("db/t.lance/_versions/1.manifest")),
+            None
+        );
+        assert_eq!(checksum_path(&Path::from("db/data/abc.lance")), None);
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let memory = Arc::new(InMemory::new());
+        let store = ChecksumObjectStoreWrapper::new(true, None).wrap(memory.clone());
+
+        let path = Path::from("t.lance/data/a.lance");
+        store.put(&path, Bytes::from("hello")).await.unwrap();
+        let multipart_path = Path::from("t.lance/data/b.lance");
+        let (_, mut upload) = store.put_multipart(&multipart_path).await.unwrap();
+        upload.write_all(b"hello ").await.unwrap();
+        upload.write_all(b"world").await.unwrap();
+        upload.shutdown().await.unwrap();
+        for path in [&path, &multipart_path] {
+            assert_eq!(
+                verify_object(memory.as_ref(), path).await.unwrap(),
+                ChecksumStatus::Valid
+            );
+        }
+
+        // Corrupt a file behind the store's back
+        memory.put(&path, Bytes::from("jello")).await.unwrap();
+        assert!(matches!(
+            verify_object(memory.as_ref(), &path).await.unwrap(),
+            ChecksumStatus::Corrupted { .. }
+        ));
+        assert!(store.get(&path).await.is_err());
+        assert!(store.get(&multipart_path).await.is_ok());
+    }
+}
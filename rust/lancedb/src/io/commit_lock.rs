@@ -0,0 +1,351 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Locks coordinating the commits of several processes writing to a table
+//!
+//! A commit writes the manifest of the next version of a table.  Object stores
+//! such as S3 cannot do this atomically, so two processes committing the same
+//! version at once can overwrite each other's commit.  A [`CommitLock`], set
+//! with [`crate::connection::ConnectBuilder::commit_lock`], is held while each
+//! version is committed, for example a DynamoDB lock, a Postgres advisory lock
+//! or the [`FileCommitLock`] provided for local tables.  Once the lock is held
+//! the commit fails if the version was committed meanwhile, and the operation
+//! is retried on the new version (see
+//! [`crate::connection::ConnectBuilder::commit_retries`]).
+
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use async_trait::async_trait;
+use lance_table::io::commit::{
+    CommitError, CommitHandler, CommitLease as LanceCommitLease, CommitLock as LanceCommitLock,
+};
+use object_store::path::Path;
+
+use crate::error::{Error, Result};
+
+/// The directory of the manifests of a table
+const VERSIONS_DIR: &str = "_versions";
+/// The name of the lock file of a [`FileCommitLock`]
+const LOCK_FILE: &str = "_commit.lock";
+
+/// A lock held while a version of a table is committed
+#[async_trait]
+pub trait CommitLock: std::fmt::Debug + Send + Sync {
+    /// Wait until no other process holds the lock of the table at `table_uri`
+    /// and take it to commit `version`
+    ///
+    /// The lock must be held until [`CommitLease::release`] is called on the
+    /// returned lease.  `version` can be used as a fencing token.
+    async fn lock(&self, table_uri: &str, version: u64) -> Result<Box<dyn CommitLease>>;
+}
+
+/// A held [`CommitLock`]
+#[async_trait]
+pub trait CommitLease: Send + Sync {
+    /// Release the lock, `success` tells whether the version was committed
+    async fn release(&self, success: bool) -> Result<()>;
+}
+
+/// A [`CommitLock`] for local tables, held by creating a lock file
+///
+/// The lock is the file `_commit.lock` in the directory of the table, created
+/// atomically by the process taking the lock and removed when it is released.
+/// Only processes sharing the filesystem are coordinated, so this must not be
+/// used with tables on network filesystems that do not support exclusive
+/// creates.  A lock file older than [`Self::stale_after`], left by a process
+/// that stopped while committing, is removed.
+#[derive(Debug, Clone)]
+pub struct FileCommitLock {
+    timeout: Duration,
+    stale_after: Duration,
+    poll_interval: Duration,
+}
+
+impl Default for FileCommitLock {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            stale_after: Duration::from_secs(60),
+            poll_interval: Duration::from_millis(10),
+        }
+    }
+}
+
+impl FileCommitLock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long to wait for the lock before the commit fails, the default is 30 seconds
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The age after which a lock file is considered abandoned, the default is 60 seconds
+    ///
+    /// This must be longer than the longest commit.
+    pub fn stale_after(mut self, stale_after: Duration) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    /// Whether the lock file at `path` was abandoned
+    fn is_stale(&self, path: &std::path::Path) -> bool {
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > self.stale_after)
+    }
+}
+
+/// The local path of the table at `table_uri`
+fn local_path(table_uri: &str) -> Result<PathBuf> {
+    match url::Url::parse(table_uri) {
+        Ok(url) if url.scheme() == "file" => url.to_file_path().map_err(|_| Error::InvalidInput {
+            message: format!("{} is not a valid file URI", table_uri),
+        }),
+        // A single letter is the drive of a windows path
+        Ok(url) if url.scheme().len() > 1 => Err(Error::InvalidInput {
+            message: format!(
+                "the file commit lock only supports local tables, not {}",
+                table_uri
+            ),
+        }),
+        _ => Ok(PathBuf::from(table_uri)),
+    }
+}
+
+#[async_trait]
+impl CommitLock for FileCommitLock {
+    async fn lock(&self, table_uri: &str, version: u64) -> Result<Box<dyn CommitLease>> {
+        let dir = local_path(table_uri)?;
+        std::fs::create_dir_all(&dir).map_err(|e| Error::Runtime {
+            message: format!("Failed to create the directory of {}: {}", table_uri, e),
+        })?;
+        let path = dir.join(LOCK_FILE);
+        let start = Instant::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    // The owner of the lock, to help with debugging
+                    let _ = writeln!(file, "pid={} version={}", std::process::id(), version);
+                    return Ok(Box::new(FileLease { path }));
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    if self.is_stale(&path) {
+                        log::warn!("Removing the abandoned commit lock {}", path.display());
+                        let _ = std::fs::remove_file(&path);
+                        continue;
+                    }
+                    if start.elapsed() > self.timeout {
+                        return Err(Error::Runtime {
+                            message: format!(
+                                "Timed out after {:?} waiting for the commit lock {}",
+                                self.timeout,
+                                path.display()
+                            ),
+                        });
+                    }
+                    tokio::time::sleep(self.poll_interval).await;
+                }
+                Err(e) => {
+                    return Err(Error::Runtime {
+                        message: format!(
+                            "Failed to create the commit lock {}: {}",
+                            path.display(),
+                            e
+                        ),
+                    })
+                }
+            }
+        }
+    }
+}
+
+struct FileLease {
+    path: PathBuf,
+}
+
+#[async_trait]
+impl CommitLease for FileLease {
+    async fn release(&self, _success: bool) -> Result<()> {
+        std::fs::remove_file(&self.path).map_err(|e| Error::Runtime {
+            message: format!(
+                "Failed to remove the commit lock {}: {}",
+                self.path.display(),
+                e
+            ),
+        })
+    }
+}
+
+fn commit_error(err: Error) -> CommitError {
+    CommitError::OtherError(std::io::Error::other(err.to_string()).into())
+}
+
+/// Adapts a [`CommitLock`] to the commits of one table
+#[derive(Debug)]
+struct TableCommitLock {
+    lock: Arc<dyn CommitLock>,
+    table_uri: String,
+    store: Arc<dyn object_store::ObjectStore>,
+    versions_dir: Path,
+}
+
+struct TableCommitLease(Box<dyn CommitLease>);
+
+#[async_trait]
+impl LanceCommitLease for TableCommitLease {
+    async fn release(&self, success: bool) -> std::result::Result<(), CommitError> {
+        self.0.release(success).await.map_err(commit_error)
+    }
+}
+
+#[async_trait]
+impl LanceCommitLock for TableCommitLock {
+    type Lease = TableCommitLease;
+
+    async fn lock(&self, version: u64) -> std::result::Result<Self::Lease, CommitError> {
+        let lease = self
+            .lock
+            .lock(&self.table_uri, version)
+            .await
+            .map_err(commit_error)?;
+        // Another process may have committed the version while we waited
+        let manifest = self
+            .versions_dir
+            .child(format!("{}.manifest", version).as_str());
+        match self.store.head(&manifest).await {
+            Ok(_) => {
+                lease.release(false).await.map_err(commit_error)?;
+                Err(CommitError::CommitConflict)
+            }
+            Err(object_store::Error::NotFound { .. }) => Ok(TableCommitLease(lease)),
+            Err(e) => {
+                lease.release(false).await.map_err(commit_error)?;
+                Err(commit_error(e.into()))
+            }
+        }
+    }
+}
+
+/// The commit handler of the table at `table_uri`, stored in `table_dir` of `store`
+pub(crate) fn commit_handler(
+    lock: Arc<dyn CommitLock>,
+    table_uri: String,
+    store: Arc<dyn object_store::ObjectStore>,
+    table_dir: &Path,
+) -> Arc<dyn CommitHandler> {
+    Arc::new(TableCommitLock {
+        lock,
+        table_uri,
+        store,
+        versions_dir: table_dir.child(VERSIONS_DIR),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::table::WriteOptions;
+
+    fn batches() -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..10))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_file_commit_lock() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let lock = FileCommitLock::new().timeout(Duration::from_millis(50));
+
+        let lease = lock.lock(uri, 1).await.unwrap();
+        assert!(tmp_dir.path().join(LOCK_FILE).exists());
+        let result = lock.lock(uri, 1).await;
+        assert!(matches!(result, Err(Error::Runtime { .. })));
+        lease.release(true).await.unwrap();
+        assert!(!tmp_dir.path().join(LOCK_FILE).exists());
+        lock.lock(uri, 2).await.unwrap();
+
+        // An abandoned lock is taken over
+        let lock = lock.stale_after(Duration::ZERO);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        lock.lock(uri, 3).await.unwrap();
+
+        let result = lock.lock("s3://bucket/table", 1).await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_writers() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let writers = futures::future::try_join_all((0..4).map(|_| async {
+            connect(uri)
+                .commit_lock(Arc::new(FileCommitLock::new()))
+                .execute()
+                .await
+        }))
+        .await
+        .unwrap();
+        writers[0]
+            .create_table("test", batches())
+            .execute()
+            .await
+            .unwrap();
+
+        futures::future::try_join_all(writers.iter().map(|db| async move {
+            let table = db.open_table("test").execute().await?;
+            for _ in 0..3 {
+                // Lance retries an append 5 times, which four writers
+                // committing back to back can exhaust
+                table
+                    .add(batches())
+                    .write_options(WriteOptions {
+                        max_commit_retries: Some(10),
+                        ..Default::default()
+                    })
+                    .execute()
+                    .await?;
+            }
+            table.delete("i = 0").await?;
+            Result::Ok(())
+        }))
+        .await
+        .unwrap();
+
+        let table = writers[0].open_table("test").execute().await.unwrap();
+        // The rows added after a delete are not deleted
+        let rows = table.count_rows(Some("i > 0".to_string())).await.unwrap();
+        assert_eq!(rows, 13 * 9);
+        assert!(!tmp_dir.path().join("test.lance").join(LOCK_FILE).exists());
+    }
+}
@@ -0,0 +1,976 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Encryption at rest of the files of the tables
+//!
+//! When enabled with [`crate::connection::ConnectBuilder::encryption`], the
+//! data and index files of the tables are encrypted with AES-256-GCM as they
+//! are written and decrypted as they are read.  The keys come from a
+//! [`KeyProvider`]: [`StaticKeyProvider`] holds the keys in memory,
+//! [`EnvKeyProvider`] reads a key from an environment variable and, with the
+//! `kms` feature, `KmsKeyProvider` decrypts a data key with AWS KMS.
+//!
+//! A file is encrypted in chunks of 64KiB, each with its own nonce and
+//! authentication tag, so that a range of the file can be read without
+//! decrypting the whole file.  The header of the file holds the id of the key
+//! that encrypted it, so the files written with an older key can still be read
+//! after the key is rotated, as long as the provider still returns the old key.
+//!
+//! The manifests and deletion files of the tables, which hold the schema and
+//! the row ids but none of the values of the rows, are not encrypted.  Files
+//! written without encryption cannot be read by an encrypted connection.  The
+//! tables of a local database are read through the object store, instead of
+//! directly from the files, so that their reads are decrypted.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use lance::io::WrappingObjectStore;
+use object_store::{
+    path::Path, GetOptions, GetRange, GetResult, GetResultPayload, ListResult, MultipartId,
+    ObjectMeta, ObjectStore, PutOptions, PutResult,
+};
+use tokio::io::AsyncWrite;
+
+use crate::error::{Error, Result};
+use crate::io::checksum::is_immutable;
+
+const MAGIC: &[u8; 6] = b"LDBENC";
+const FORMAT_VERSION: u8 = 1;
+/// The longest id of a key
+pub const MAX_KEY_ID_LEN: usize = 40;
+const NONCE_PREFIX_LEN: usize = 8;
+/// The magic, the format version, the length of the key id, the key id padded
+/// to its maximum length and the prefix of the nonces
+const HEADER_LEN: usize = MAGIC.len() + 2 + MAX_KEY_ID_LEN + NONCE_PREFIX_LEN;
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const ENCRYPTED_CHUNK_SIZE: usize = CHUNK_SIZE + TAG_LEN;
+
+/// A 256 bit AES key
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// A new random key
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    /// Parse a key from its 64 hexadecimal digits
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let hex = hex.trim();
+        let invalid = || Error::InvalidInput {
+            message: "an encryption key must be 64 hexadecimal digits".to_string(),
+        };
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+
+    /// The key as 64 hexadecimal digits
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(&self.0.into())
+    }
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the key
+        write!(f, "EncryptionKey(..)")
+    }
+}
+
+/// The keys that encrypt the files of the tables
+#[async_trait]
+pub trait KeyProvider: std::fmt::Debug + Send + Sync {
+    /// The id of the key that encrypts the new files
+    ///
+    /// The id is stored in the files and is at most [`MAX_KEY_ID_LEN`] bytes.
+    fn key_id(&self) -> &str;
+
+    /// The key with the id `key_id`
+    async fn key(&self, key_id: &str) -> Result<EncryptionKey>;
+}
+
+/// A [`KeyProvider`] with keys held in memory
+#[derive(Debug, Clone)]
+pub struct StaticKeyProvider {
+    key_id: String,
+    keys: HashMap<String, EncryptionKey>,
+}
+
+impl StaticKeyProvider {
+    /// Encrypt the files with `key`, whose id is `key_id`
+    pub fn new(key_id: impl Into<String>, key: EncryptionKey) -> Self {
+        let key_id = key_id.into();
+        Self {
+            keys: HashMap::from([(key_id.clone(), key)]),
+            key_id,
+        }
+    }
+
+    /// Also decrypt the files encrypted with `key`, e.g. a key that was rotated
+    pub fn with_old_key(mut self, key_id: impl Into<String>, key: EncryptionKey) -> Self {
+        self.keys.insert(key_id.into(), key);
+        self
+    }
+}
+
+#[async_trait]
+impl KeyProvider for StaticKeyProvider {
+    fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    async fn key(&self, key_id: &str) -> Result<EncryptionKey> {
+        self.keys
+            .get(key_id)
+            .cloned()
+            .ok_or_else(|| Error::InvalidInput {
+                message: format!("the encryption key {} is unknown", key_id),
+            })
+    }
+}
+
+/// A [`KeyProvider`] reading the key, as 64 hexadecimal digits, from an
+/// environment variable
+///
+/// The id of the key is the name of the variable.
+#[derive(Debug, Clone)]
+pub struct EnvKeyProvider {
+    var: String,
+}
+
+impl EnvKeyProvider {
+    /// The default variable holding the key
+    pub const DEFAULT_VAR: &'static str = "LANCEDB_ENCRYPTION_KEY";
+
+    /// Read the key from the variable `var`
+    ///
+    /// Fails if the variable is not set or is not a valid key.
+    pub fn new(var: impl Into<String>) -> Result<Self> {
+        let provider = Self { var: var.into() };
+        provider.read()?;
+        Ok(provider)
+    }
+
+    fn read(&self) -> Result<EncryptionKey> {
+        let value = std::env::var(&self.var).map_err(|_| Error::InvalidInput {
+            message: format!("the environment variable {} is not set", self.var),
+        })?;
+        EncryptionKey::from_hex(&value)
+    }
+}
+
+#[async_trait]
+impl KeyProvider for EnvKeyProvider {
+    fn key_id(&self) -> &str {
+        &self.var
+    }
+
+    async fn key(&self, key_id: &str) -> Result<EncryptionKey> {
+        if key_id != self.var {
+            return Err(Error::InvalidInput {
+                message: format!("the encryption key {} is unknown", key_id),
+            });
+        }
+        self.read()
+    }
+}
+
+/// A [`KeyProvider`] whose key is a data key encrypted by AWS KMS
+///
+/// The encrypted data key, created with [`KmsKeyProvider::generate_data_key`],
+/// is stored by the application and is decrypted by KMS the first time it is
+/// used.
+#[cfg(feature = "kms")]
+#[derive(Debug)]
+pub struct KmsKeyProvider {
+    client: aws_sdk_kms::Client,
+    key_id: String,
+    encrypted_key: Vec<u8>,
+    key: tokio::sync::OnceCell<EncryptionKey>,
+}
+
+#[cfg(feature = "kms")]
+impl KmsKeyProvider {
+    /// Encrypt the files with the data key `encrypted_key`, whose id is `key_id`
+    pub fn new(
+        client: aws_sdk_kms::Client,
+        key_id: impl Into<String>,
+        encrypted_key: Vec<u8>,
+    ) -> Self {
+        Self {
+            client,
+            key_id: key_id.into(),
+            encrypted_key,
+            key: tokio::sync::OnceCell::new(),
+        }
+    }
+
+    /// Create a data key encrypted by the KMS key `kms_key_id`
+    pub async fn generate_data_key(
+        client: &aws_sdk_kms::Client,
+        kms_key_id: &str,
+    ) -> Result<Vec<u8>> {
+        let output = client
+            .generate_data_key()
+            .key_id(kms_key_id)
+            .key_spec(aws_sdk_kms::types::DataKeySpec::Aes256)
+            .send()
+            .await
+            .map_err(|e| Error::Runtime {
+                message: format!("failed to generate a data key: {}", e),
+            })?;
+        output
+            .ciphertext_blob()
+            .map(|blob| blob.as_ref().to_vec())
+            .ok_or_else(|| Error::Runtime {
+                message: "KMS did not return the encrypted data key".to_string(),
+            })
+    }
+
+    async fn decrypt(&self) -> Result<EncryptionKey> {
+        let output = self
+            .client
+            .decrypt()
+            .ciphertext_blob(aws_sdk_kms::primitives::Blob::new(
+                self.encrypted_key.clone(),
+            ))
+            .send()
+            .await
+            .map_err(|e| Error::Runtime {
+                message: format!("failed to decrypt the data key: {}", e),
+            })?;
+        let key: [u8; 32] = output
+            .plaintext()
+            .and_then(|blob| blob.as_ref().try_into().ok())
+            .ok_or_else(|| Error::Runtime {
+                message: "KMS did not return a 256 bit data key".to_string(),
+            })?;
+        Ok(EncryptionKey::new(key))
+    }
+}
+
+#[cfg(feature = "kms")]
+#[async_trait]
+impl KeyProvider for KmsKeyProvider {
+    fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    async fn key(&self, key_id: &str) -> Result<EncryptionKey> {
+        if key_id != self.key_id {
+            return Err(Error::InvalidInput {
+                message: format!("the encryption key {} is unknown", key_id),
+            });
+        }
+        self.key.get_or_try_init(|| self.decrypt()).await.cloned()
+    }
+}
+
+/// Check that the files can be encrypted with the keys of `provider`
+pub(crate) fn validate_provider(provider: &dyn KeyProvider) -> Result<()> {
+    let key_id = provider.key_id();
+    if key_id.is_empty() || key_id.len() > MAX_KEY_ID_LEN {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "the id of an encryption key must be 1 to {} bytes, not '{}'",
+                MAX_KEY_ID_LEN, key_id
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Whether the file at `location` is encrypted
+fn is_encrypted(location: &Path) -> bool {
+    is_immutable(location)
+}
+
+/// The size of the plaintext of an encrypted file of `size` bytes
+fn plaintext_size(size: usize) -> usize {
+    let body = size.saturating_sub(HEADER_LEN);
+    let num_chunks = body.div_ceil(ENCRYPTED_CHUNK_SIZE);
+    body.saturating_sub(num_chunks * TAG_LEN)
+}
+
+/// The number of chunks of a plaintext of `size` bytes
+///
+/// The last chunk holds at least one byte, unless the file is empty, so that
+/// the size of the plaintext can be found from the size of the file.
+fn num_chunks(size: usize) -> usize {
+    size.div_ceil(CHUNK_SIZE).max(1)
+}
+
+fn store_error(message: impl Into<String>) -> object_store::Error {
+    object_store::Error::Generic {
+        store: "encryption",
+        source: message.into().into(),
+    }
+}
+
+/// The bytes of a file of `size` bytes selected by `range`, as the object
+/// stores interpret it
+fn resolve_range(range: Option<GetRange>, size: usize) -> object_store::Result<Range<usize>> {
+    let range = match range {
+        None => 0..size,
+        Some(GetRange::Bounded(range)) if range.end <= range.start => {
+            return Err(store_error(format!(
+                "range started at {} and ended at {}",
+                range.start, range.end
+            )));
+        }
+        Some(GetRange::Bounded(range)) => range.start..range.end.min(size),
+        Some(GetRange::Offset(offset)) => offset..size,
+        Some(GetRange::Suffix(length)) => size.saturating_sub(length)..size,
+    };
+    if range.start >= size && size > 0 {
+        return Err(store_error(format!(
+            "wanted range starting at {}, but the file is only {} bytes long",
+            range.start, size
+        )));
+    }
+    Ok(range)
+}
+
+fn provider_error(e: Error) -> object_store::Error {
+    object_store::Error::Generic {
+        store: "encryption",
+        source: Box::new(e),
+    }
+}
+
+/// The header of an encrypted file
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Header {
+    key_id: String,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+}
+
+impl Header {
+    fn new(key_id: &str) -> Self {
+        Self {
+            key_id: key_id.to_string(),
+            nonce_prefix: rand::random(),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.push(FORMAT_VERSION);
+        header.push(self.key_id.len() as u8);
+        header.extend_from_slice(self.key_id.as_bytes());
+        header.resize(MAGIC.len() + 2 + MAX_KEY_ID_LEN, 0);
+        header.extend_from_slice(&self.nonce_prefix);
+        header
+    }
+
+    fn decode(location: &Path, bytes: &[u8]) -> object_store::Result<Self> {
+        let invalid = || store_error(format!("{} is not an encrypted file", location));
+        if bytes.len() != HEADER_LEN || !bytes.starts_with(MAGIC) {
+            return Err(invalid());
+        }
+        if bytes[MAGIC.len()] != FORMAT_VERSION {
+            return Err(store_error(format!(
+                "{} is encrypted with the unsupported format version {}",
+                location,
+                bytes[MAGIC.len()]
+            )));
+        }
+        let key_id_len = bytes[MAGIC.len() + 1] as usize;
+        let key_id_start = MAGIC.len() + 2;
+        if key_id_len > MAX_KEY_ID_LEN {
+            return Err(invalid());
+        }
+        let key_id = std::str::from_utf8(&bytes[key_id_start..key_id_start + key_id_len])
+            .map_err(|_| invalid())?;
+        let mut nonce_prefix = [0; NONCE_PREFIX_LEN];
+        nonce_prefix.copy_from_slice(&bytes[HEADER_LEN - NONCE_PREFIX_LEN..]);
+        Ok(Self {
+            key_id: key_id.to_string(),
+            nonce_prefix,
+        })
+    }
+
+    fn nonce(&self, chunk: usize) -> [u8; 12] {
+        let mut nonce = [0; 12];
+        nonce[..NONCE_PREFIX_LEN].copy_from_slice(&self.nonce_prefix);
+        nonce[NONCE_PREFIX_LEN..].copy_from_slice(&(chunk as u32).to_be_bytes());
+        nonce
+    }
+}
+
+/// Encrypt the chunk number `chunk` of a file
+///
+/// Whether the chunk is the last one is authenticated, so that a truncated file
+/// is detected.
+fn encrypt_chunk(
+    cipher: &Aes256Gcm,
+    header: &Header,
+    chunk: usize,
+    last: bool,
+    plaintext: &[u8],
+) -> std::io::Result<Vec<u8>> {
+    let payload = Payload {
+        msg: plaintext,
+        aad: &[last as u8],
+    };
+    cipher
+        .encrypt(Nonce::from_slice(&header.nonce(chunk)), payload)
+        .map_err(|_| std::io::Error::other("failed to encrypt a chunk"))
+}
+
+fn decrypt_chunk(
+    cipher: &Aes256Gcm,
+    header: &Header,
+    chunk: usize,
+    last: bool,
+    ciphertext: &[u8],
+) -> Option<Vec<u8>> {
+    let payload = Payload {
+        msg: ciphertext,
+        aad: &[last as u8],
+    };
+    cipher
+        .decrypt(Nonce::from_slice(&header.nonce(chunk)), payload)
+        .ok()
+}
+
+/// The header and the encrypted chunks of `plaintext`
+fn encrypt(cipher: &Aes256Gcm, header: &Header, plaintext: &[u8]) -> std::io::Result<Vec<u8>> {
+    let num_chunks = num_chunks(plaintext.len());
+    let mut encrypted = header.encode();
+    encrypted.reserve(plaintext.len() + num_chunks * TAG_LEN);
+    for chunk in 0..num_chunks {
+        let start = (chunk * CHUNK_SIZE).min(plaintext.len());
+        let end = ((chunk + 1) * CHUNK_SIZE).min(plaintext.len());
+        let last = chunk + 1 == num_chunks;
+        encrypted.extend(encrypt_chunk(
+            cipher,
+            header,
+            chunk,
+            last,
+            &plaintext[start..end],
+        )?);
+    }
+    Ok(encrypted)
+}
+
+/// What is known of an encrypted file, which is never modified once written
+#[derive(Debug, Clone)]
+struct EncryptedFile {
+    header: Header,
+    /// The size of the encrypted file
+    size: usize,
+}
+
+/// An object store that encrypts the data and index files as they are written
+/// and decrypts them as they are read
+#[derive(Debug)]
+struct EncryptingObjectStore {
+    inner: Arc<dyn ObjectStore>,
+    provider: Arc<dyn KeyProvider>,
+    files: Arc<Mutex<HashMap<Path, EncryptedFile>>>,
+}
+
+impl std::fmt::Display for EncryptingObjectStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EncryptingObjectStore({})", self.inner)
+    }
+}
+
+impl EncryptingObjectStore {
+    async fn cipher(&self, key_id: &str) -> object_store::Result<Aes256Gcm> {
+        let key = self.provider.key(key_id).await.map_err(provider_error)?;
+        Ok(key.cipher())
+    }
+
+    async fn file(&self, location: &Path) -> object_store::Result<EncryptedFile> {
+        if let Some(file) = self.files.lock().unwrap().get(location) {
+            return Ok(file.clone());
+        }
+        let size = self.inner.head(location).await?.size;
+        if size < HEADER_LEN {
+            return Err(store_error(format!(
+                "{} is not an encrypted file",
+                location
+            )));
+        }
+        let bytes = self.inner.get_range(location, 0..HEADER_LEN).await?;
+        let header = Header::decode(location, &bytes)?;
+        let file = EncryptedFile { header, size };
+        self.files
+            .lock()
+            .unwrap()
+            .insert(location.clone(), file.clone());
+        Ok(file)
+    }
+
+    /// Read and decrypt the bytes in `range` of the plaintext of the file
+    async fn read(
+        &self,
+        location: &Path,
+        file: &EncryptedFile,
+        range: Range<usize>,
+    ) -> object_store::Result<Bytes> {
+        let size = plaintext_size(file.size);
+        if range.start > range.end || range.end > size {
+            return Err(store_error(format!(
+                "the range {:?} is out of the bounds of {} of {} bytes",
+                range, location, size
+            )));
+        }
+        if range.is_empty() {
+            return Ok(Bytes::new());
+        }
+        let first = range.start / CHUNK_SIZE;
+        let last = (range.end - 1) / CHUNK_SIZE;
+        let encrypted_range = HEADER_LEN + first * ENCRYPTED_CHUNK_SIZE
+            ..(HEADER_LEN + (last + 1) * ENCRYPTED_CHUNK_SIZE).min(file.size);
+        let encrypted = self.inner.get_range(location, encrypted_range).await?;
+
+        let cipher = self.cipher(&file.header.key_id).await?;
+        let num_chunks = num_chunks(size);
+        let mut plaintext = Vec::with_capacity((last + 1 - first) * CHUNK_SIZE);
+        for (i, ciphertext) in encrypted.chunks(ENCRYPTED_CHUNK_SIZE).enumerate() {
+            let chunk = first + i;
+            let decrypted = decrypt_chunk(
+                &cipher,
+                &file.header,
+                chunk,
+                chunk + 1 == num_chunks,
+                ciphertext,
+            )
+            .ok_or_else(|| {
+                store_error(format!(
+                    "failed to decrypt {}, the file is corrupted or the key is wrong",
+                    location
+                ))
+            })?;
+            plaintext.extend(decrypted);
+        }
+        let offset = first * CHUNK_SIZE;
+        Ok(Bytes::from(plaintext).slice(range.start - offset..range.end - offset))
+    }
+}
+
+#[async_trait]
+impl ObjectStore for EncryptingObjectStore {
+    async fn put(&self, location: &Path, bytes: Bytes) -> object_store::Result<PutResult> {
+        self.put_opts(location, bytes, PutOptions::default()).await
+    }
+
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        if !is_encrypted(location) {
+            return self.inner.put_opts(location, bytes, options).await;
+        }
+        let header = Header::new(self.provider.key_id());
+        let cipher = self.cipher(&header.key_id).await?;
+        let encrypted =
+            encrypt(&cipher, &header, &bytes).map_err(|e| store_error(e.to_string()))?;
+        self.files.lock().unwrap().remove(location);
+        self.inner
+            .put_opts(location, Bytes::from(encrypted), options)
+            .await
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> object_store::Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        if !is_encrypted(location) {
+            return self.inner.put_multipart(location).await;
+        }
+        let header = Header::new(self.provider.key_id());
+        let cipher = self.cipher(&header.key_id).await?;
+        let (id, upload) = self.inner.put_multipart(location).await?;
+        self.files.lock().unwrap().remove(location);
+        let upload = EncryptingUpload {
+            upload,
+            cipher,
+            pending: header.encode(),
+            header,
+            written: 0,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+            chunk: 0,
+            finished: false,
+        };
+        Ok((id, Box::new(upload)))
+    }
+
+    async fn abort_multipart(
+        &self,
+        location: &Path,
+        multipart_id: &MultipartId,
+    ) -> object_store::Result<()> {
+        self.inner.abort_multipart(location, multipart_id).await
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        if !is_encrypted(location) {
+            return self.inner.get_opts(location, options).await;
+        }
+        let file = self.file(location).await?;
+        let mut meta = self.inner.head(location).await?;
+        meta.size = plaintext_size(meta.size);
+        let range = resolve_range(options.range.clone(), meta.size)?;
+        let bytes = if options.head {
+            Bytes::new()
+        } else {
+            self.read(location, &file, range.clone()).await?
+        };
+        Ok(GetResult {
+            payload: GetResultPayload::Stream(
+                futures::stream::once(async move { Ok(bytes) }).boxed(),
+            ),
+            meta,
+            range,
+        })
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> object_store::Result<Bytes> {
+        if !is_encrypted(location) {
+            return self.inner.get_range(location, range).await;
+        }
+        let file = self.file(location).await?;
+        self.read(location, &file, range).await
+    }
+
+    async fn head(&self, location: &Path) -> object_store::Result<ObjectMeta> {
+        let mut meta = self.inner.head(location).await?;
+        if is_encrypted(location) {
+            meta.size = plaintext_size(meta.size);
+        }
+        Ok(meta)
+    }
+
+    async fn delete(&self, location: &Path) -> object_store::Result<()> {
+        self.files.lock().unwrap().remove(location);
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        self.inner
+            .list(prefix)
+            .map_ok(|mut meta| {
+                if is_encrypted(&meta.location) {
+                    meta.size = plaintext_size(meta.size);
+                }
+                meta
+            })
+            .boxed()
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        let mut result = self.inner.list_with_delimiter(prefix).await?;
+        for meta in result.objects.iter_mut() {
+            if is_encrypted(&meta.location) {
+                meta.size = plaintext_size(meta.size);
+            }
+        }
+        Ok(result)
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        // The header holds everything needed to decrypt the copy
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+/// A multipart upload that encrypts the bytes written to it
+///
+/// The bytes are buffered until a chunk is full.  The last chunk is only
+/// encrypted when the upload is shut down, since it is authenticated as the
+/// last one.
+struct EncryptingUpload {
+    upload: Box<dyn AsyncWrite + Unpin + Send>,
+    cipher: Aes256Gcm,
+    header: Header,
+    /// The encrypted bytes not yet written to the upload
+    pending: Vec<u8>,
+    /// The number of the pending bytes already written
+    written: usize,
+    /// The plaintext not yet encrypted
+    buffer: Vec<u8>,
+    /// The number of the next chunk
+    chunk: usize,
+    finished: bool,
+}
+
+impl EncryptingUpload {
+    /// Write the pending bytes to the upload
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        while self.written < self.pending.len() {
+            match Pin::new(&mut self.upload).poll_write(cx, &self.pending[self.written..]) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+                }
+                Poll::Ready(Ok(n)) => self.written += n,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        self.pending.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    fn encrypt_chunk(&mut self, len: usize, last: bool) -> std::io::Result<()> {
+        let encrypted = encrypt_chunk(
+            &self.cipher,
+            &self.header,
+            self.chunk,
+            last,
+            &self.buffer[..len],
+        )?;
+        self.pending.extend(encrypted);
+        self.buffer.drain(..len);
+        self.chunk += 1;
+        Ok(())
+    }
+}
+
+impl AsyncWrite for EncryptingUpload {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        if this.finished {
+            return Poll::Ready(Err(std::io::Error::other("already shutdown")));
+        }
+        futures::ready!(this.poll_pending(cx))?;
+        this.buffer.extend_from_slice(buf);
+        // A full chunk is kept until more bytes are written, it may be the last
+        while this.buffer.len() > CHUNK_SIZE {
+            this.encrypt_chunk(CHUNK_SIZE, false)?;
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_pending(cx))?;
+        Pin::new(&mut this.upload).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if !this.finished {
+            this.encrypt_chunk(this.buffer.len(), true)?;
+            this.finished = true;
+        }
+        futures::ready!(this.poll_pending(cx))?;
+        Pin::new(&mut this.upload).poll_shutdown(cx)
+    }
+}
+
+/// A [`WrappingObjectStore`] that encrypts the data and index files
+///
+/// See the [module level documentation](self) for more details.
+#[derive(Debug)]
+pub struct EncryptingObjectStoreWrapper {
+    provider: Arc<dyn KeyProvider>,
+    /// Applied to the object store before this wrapper
+    inner: Option<Arc<dyn WrappingObjectStore>>,
+    files: Arc<Mutex<HashMap<Path, EncryptedFile>>>,
+}
+
+impl EncryptingObjectStoreWrapper {
+    /// Create a wrapper encrypting the files with the keys of `provider`
+    ///
+    /// The object store is first wrapped by `inner`, if provided.
+    pub fn new(
+        provider: Arc<dyn KeyProvider>,
+        inner: Option<Arc<dyn WrappingObjectStore>>,
+    ) -> Self {
+        Self {
+            provider,
+            inner,
+            files: Arc::default(),
+        }
+    }
+}
+
+impl WrappingObjectStore for EncryptingObjectStoreWrapper {
+    fn wrap(&self, original: Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
+        let inner = match &self.inner {
+            Some(wrapper) => wrapper.wrap(original),
+            None => original,
+        };
+        Arc::new(EncryptingObjectStore {
+            inner,
+            provider: self.provider.clone(),
+            files: self.files.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    fn wrapper(provider: StaticKeyProvider) -> EncryptingObjectStoreWrapper {
+        EncryptingObjectStoreWrapper::new(Arc::new(provider), None)
+    }
+
+    #[test]
+    fn test_sizes() {
+        for size in [
+            0,
+            1,
+            CHUNK_SIZE - 1,
+            CHUNK_SIZE,
+            CHUNK_SIZE + 1,
+            3 * CHUNK_SIZE,
+        ] {
+            let header = Header::new("k");
+            let encrypted =
+                encrypt(&EncryptionKey::generate().cipher(), &header, &vec![7; size]).unwrap();
+            assert_eq!(plaintext_size(encrypted.len()), size);
+        }
+        let key = EncryptionKey::generate();
+        assert_eq!(EncryptionKey::from_hex(&key.to_hex()).unwrap(), key);
+        assert!(EncryptionKey::from_hex("abc").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_encryption() {
+        let memory = Arc::new(InMemory::new());
+        let key = EncryptionKey::generate();
+        let store = wrapper(StaticKeyProvider::new("k1", key.clone())).wrap(memory.clone());
+
+        let data = (0..200_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let path = Path::from("t.lance/data/a.lance");
+        store.put(&path, Bytes::from(data.clone())).await.unwrap();
+        let multipart_path = Path::from("t.lance/data/b.lance");
+        let (_, mut upload) = store.put_multipart(&multipart_path).await.unwrap();
+        for piece in data.chunks(30_000) {
+            upload.write_all(piece).await.unwrap();
+        }
+        upload.shutdown().await.unwrap();
+
+        for path in [&path, &multipart_path] {
+            // The files are encrypted at rest
+            let raw = memory.get(path).await.unwrap().bytes().await.unwrap();
+            assert!(raw.starts_with(MAGIC));
+            assert!(!raw.windows(1000).any(|w| w == &data[..1000]));
+
+            assert_eq!(store.head(path).await.unwrap().size, data.len());
+            let read = store.get(path).await.unwrap().bytes().await.unwrap();
+            assert_eq!(read, data);
+            let range = CHUNK_SIZE - 10..2 * CHUNK_SIZE + 10;
+            assert_eq!(
+                store.get_range(path, range.clone()).await.unwrap(),
+                data[range]
+            );
+            for (range, expected) in [
+                (GetRange::Bounded(10..20), 10..20),
+                (GetRange::Bounded(10..data.len() + 5), 10..data.len()),
+                (GetRange::Offset(data.len() - 3), data.len() - 3..data.len()),
+                (
+                    GetRange::Suffix(CHUNK_SIZE + 1),
+                    data.len() - CHUNK_SIZE - 1..data.len(),
+                ),
+            ] {
+                let options = GetOptions {
+                    range: Some(range),
+                    ..Default::default()
+                };
+                let result = store.get_opts(path, options).await.unwrap();
+                assert_eq!(result.range, expected);
+                assert_eq!(result.bytes().await.unwrap(), data[expected]);
+            }
+            let options = GetOptions {
+                range: Some(GetRange::Offset(data.len())),
+                ..Default::default()
+            };
+            assert!(store.get_opts(path, options).await.is_err());
+        }
+        // Other files are not encrypted
+        let manifest = Path::from("t.lance/_versions/1.manifest");
+        store.put(&manifest, Bytes::from("manifest")).await.unwrap();
+        assert_eq!(memory.get_range(&manifest, 0..8).await.unwrap(), "manifest");
+
+        // The files written with an old key can still be read after a rotation
+        let rotated = wrapper(
+            StaticKeyProvider::new("k2", EncryptionKey::generate()).with_old_key("k1", key.clone()),
+        )
+        .wrap(memory.clone());
+        assert_eq!(
+            rotated.get(&path).await.unwrap().bytes().await.unwrap(),
+            data
+        );
+
+        // A wrong key or a corrupted file fails to decrypt
+        let wrong =
+            wrapper(StaticKeyProvider::new("k1", EncryptionKey::generate())).wrap(memory.clone());
+        assert!(wrong.get_range(&path, 0..10).await.is_err());
+        let mut raw = memory
+            .get(&path)
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap()
+            .to_vec();
+        raw[HEADER_LEN + 5] ^= 1;
+        memory.put(&path, Bytes::from(raw)).await.unwrap();
+        let store = wrapper(StaticKeyProvider::new("k1", key)).wrap(memory.clone());
+        assert!(store.get_range(&path, 0..10).await.is_err());
+        assert!(store.get_range(&multipart_path, 0..10).await.is_ok());
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A limit on the object store requests of a connection
+//!
+//! With [`crate::connection::ConnectBuilder::max_concurrent_requests`] all of
+//! the tables of a connection share a limit on the number of requests to the
+//! object store running at once.  The requests over the limit wait for a
+//! running request to complete.  A read holds its slot until its bytes are
+//! consumed, and an upload until it is complete.
+//!
+//! Lance reads the files of local tables directly, without going through the
+//! object store, so the limit only applies to their writes.
+
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt};
+use lance::io::WrappingObjectStore;
+use object_store::{
+    path::Path, GetOptions, GetResult, GetResultPayload, ListResult, MultipartId, ObjectMeta,
+    ObjectStore, PutOptions, PutResult, Result,
+};
+use tokio::io::AsyncWrite;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// An object store running at most as many requests at once as the permits of
+/// its semaphore
+#[derive(Debug)]
+struct LimitedObjectStore {
+    inner: Arc<dyn ObjectStore>,
+    semaphore: Arc<Semaphore>,
+}
+
+impl std::fmt::Display for LimitedObjectStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LimitedObjectStore({})", self.inner)
+    }
+}
+
+impl LimitedObjectStore {
+    async fn permit(&self) -> OwnedSemaphorePermit {
+        self.semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the request semaphore is never closed")
+    }
+}
+
+#[async_trait]
+impl ObjectStore for LimitedObjectStore {
+    async fn put(&self, location: &Path, bytes: Bytes) -> Result<PutResult> {
+        let _permit = self.permit().await;
+        self.inner.put(location, bytes).await
+    }
+
+    async fn put_opts(
+        &self,
+        location: &Path,
+        bytes: Bytes,
+        options: PutOptions,
+    ) -> Result<PutResult> {
+        let _permit = self.permit().await;
+        self.inner.put_opts(location, bytes, options).await
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        let permit = self.permit().await;
+        let (id, upload) = self.inner.put_multipart(location).await?;
+        Ok((
+            id,
+            Box::new(LimitedUpload {
+                upload,
+                _permit: permit,
+            }),
+        ))
+    }
+
+    async fn abort_multipart(&self, location: &Path, multipart_id: &MultipartId) -> Result<()> {
+        let _permit = self.permit().await;
+        self.inner.abort_multipart(location, multipart_id).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let permit = self.permit().await;
+        let result = self.inner.get_opts(location, options).await?;
+        let payload = match result.payload {
+            GetResultPayload::Stream(stream) => GetResultPayload::Stream(
+                // The request runs until its bytes are consumed
+                stream
+                    .map(move |bytes| {
+                        let _ = &permit;
+                        bytes
+                    })
+                    .boxed(),
+            ),
+            payload => payload,
+        };
+        Ok(GetResult { payload, ..result })
+    }
+
+    async fn get_range(&self, location: &Path, range: Range<usize>) -> Result<Bytes> {
+        let _permit = self.permit().await;
+        self.inner.get_range(location, range).await
+    }
+
+    async fn get_ranges(&self, location: &Path, ranges: &[Range<usize>]) -> Result<Vec<Bytes>> {
+        let _permit = self.permit().await;
+        self.inner.get_ranges(location, ranges).await
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        let _permit = self.permit().await;
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        let _permit = self.permit().await;
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        let prefix = prefix.cloned();
+        self.permit()
+            .map(move |permit| {
+                self.inner.list(prefix.as_ref()).map(move |meta| {
+                    let _ = &permit;
+                    meta
+                })
+            })
+            .into_stream()
+            .flatten()
+            .boxed()
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        let _permit = self.permit().await;
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        let _permit = self.permit().await;
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        let _permit = self.permit().await;
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+/// A multipart upload holding the permit of its request until it is dropped
+struct LimitedUpload {
+    upload: Box<dyn AsyncWrite + Unpin + Send>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl AsyncWrite for LimitedUpload {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.get_mut().upload).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().upload).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().upload).poll_shutdown(cx)
+    }
+}
+
+/// A [`WrappingObjectStore`] sharing a limit on the concurrent requests
+///
+/// See the [module level documentation](self) for more details.
+#[derive(Debug)]
+pub struct LimitedObjectStoreWrapper {
+    semaphore: Arc<Semaphore>,
+    /// Applied to the object store before this wrapper
+    inner: Option<Arc<dyn WrappingObjectStore>>,
+}
+
+impl LimitedObjectStoreWrapper {
+    /// Create a wrapper running at most `max_requests` requests at once
+    ///
+    /// The object store is first wrapped by `inner`, if provided.
+    pub fn new(max_requests: usize, inner: Option<Arc<dyn WrappingObjectStore>>) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_requests.max(1))),
+            inner,
+        }
+    }
+}
+
+impl WrappingObjectStore for LimitedObjectStoreWrapper {
+    fn wrap(&self, original: Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
+        let inner = match &self.inner {
+            Some(wrapper) => wrapper.wrap(original),
+            None => original,
+        };
+        Arc::new(LimitedObjectStore {
+            inner,
+            semaphore: self.semaphore.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_limit() {
+        let wrapper = LimitedObjectStoreWrapper::new(1, None);
+        let memory: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        // The stores of two tables share the limit
+        let first = wrapper.wrap(memory.clone());
+        let second = wrapper.wrap(memory);
+        let path = Path::from("t.lance/data/a.lance");
+        first.put(&path, Bytes::from("hello")).await.unwrap();
+
+        // An unconsumed read holds the only slot
+        let read = first.get(&path).await.unwrap();
+        let head = second.head(&path);
+        futures::pin_mut!(head);
+        assert!(futures::poll!(head.as_mut()).is_pending());
+        assert_eq!(read.bytes().await.unwrap(), "hello");
+        assert_eq!(head.await.unwrap().size, 5);
+    }
+}
@@ -0,0 +1,49 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-memory databases
+//!
+//! A database connected with `memory://` keeps its tables in an in-memory
+//! object store, which is dropped with the connection.  Lance creates a new,
+//! empty, in-memory store each time a `memory://` dataset is opened, so the
+//! tables of the database are opened with [`InMemoryObjectStoreWrapper`], which
+//! replaces that store with the store of the database.  The tables can be
+//! written to a directory with [`crate::Connection::persist_to`].
+
+use std::sync::Arc;
+
+use lance::io::WrappingObjectStore;
+use object_store::ObjectStore;
+
+/// The scheme of in-memory databases
+pub(crate) const MEMORY_SCHEME: &str = "memory";
+
+/// A [`WrappingObjectStore`] that replaces the object store with the store of
+/// an in-memory database
+#[derive(Debug)]
+pub(crate) struct InMemoryObjectStoreWrapper {
+    store: Arc<dyn ObjectStore>,
+}
+
+impl InMemoryObjectStoreWrapper {
+    pub(crate) fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self { store }
+    }
+}
+
+impl WrappingObjectStore for InMemoryObjectStoreWrapper {
+    fn wrap(&self, _original: Arc<dyn ObjectStore>) -> Arc<dyn ObjectStore> {
+        self.store.clone()
+    }
+}
@@ -375,6 +375,7 @@ mod test {
             .create_table("test", Box::new(datagen.batch(100)))
             .write_options(WriteOptions {
                 lance_write_params: Some(param),
+                ..Default::default()
             })
             .execute()
             .await;
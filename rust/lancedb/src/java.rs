@@ -0,0 +1,191 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JNI bindings used by the Java SDK in `java/`
+//!
+//! This module is only available with the `jni` feature.  It backs the native
+//! methods of `com.lancedb.Connection` and `com.lancedb.Table`.  Handles are
+//! passed to Java as `long` pointers and data is exchanged with Arrow Java
+//! through the Arrow C stream interface: Java allocates an `ArrowArrayStream`
+//! and passes its memory address, which is imported (for writes) or filled in
+//! (for query results, which Java then reads as a `VectorSchemaRoot`).
+
+use arrow::ffi_stream::FFI_ArrowArrayStream;
+use jni::objects::{JClass, JFloatArray, JString};
+use jni::sys::{jint, jlong};
+use jni::JNIEnv;
+
+use crate::arrow::RUNTIME;
+use crate::capi::{export_query, import_stream};
+use crate::error::{Error, Result};
+use crate::{Connection, Table};
+
+const EXCEPTION_CLASS: &str = "com/lancedb/LanceDBException";
+
+impl From<jni::errors::Error> for Error {
+    fn from(e: jni::errors::Error) -> Self {
+        Self::Runtime {
+            message: e.to_string(),
+        }
+    }
+}
+
+/// Unwrap `res`, raising a `LanceDBException` in the JVM if it failed
+///
+/// The returned default value is ignored by Java since the exception is
+/// thrown as soon as the native method returns.
+fn ok_or_throw<T: Default>(env: &mut JNIEnv, res: Result<T>) -> T {
+    match res {
+        Ok(value) => value,
+        Err(err) => {
+            // If throwing fails there is already a pending exception
+            let _ = env.throw_new(EXCEPTION_CLASS, err.to_string());
+            T::default()
+        }
+    }
+}
+
+fn get_string(env: &mut JNIEnv, value: &JString) -> Result<String> {
+    Ok(env.get_string(value)?.into())
+}
+
+unsafe fn handle<'a, T>(handle: jlong) -> Result<&'a T> {
+    (handle as *const T)
+        .as_ref()
+        .ok_or_else(|| Error::InvalidInput {
+            message: "the handle has already been closed".to_string(),
+        })
+}
+
+fn into_handle<T>(value: T) -> jlong {
+    Box::into_raw(Box::new(value)) as jlong
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_lancedb_Connection_nativeConnect(
+    mut env: JNIEnv,
+    _class: JClass,
+    uri: JString,
+) -> jlong {
+    let res = get_string(&mut env, &uri).and_then(|uri| {
+        let conn = RUNTIME.block_on(crate::connect(&uri).execute())?;
+        Ok(into_handle(conn))
+    });
+    ok_or_throw(&mut env, res)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_lancedb_Connection_nativeOpenTable(
+    mut env: JNIEnv,
+    _class: JClass,
+    conn: jlong,
+    name: JString,
+) -> jlong {
+    let res = get_string(&mut env, &name).and_then(|name| {
+        let conn = unsafe { handle::<Connection>(conn) }?;
+        let table = RUNTIME.block_on(conn.open_table(name).execute())?;
+        Ok(into_handle(table))
+    });
+    ok_or_throw(&mut env, res)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_lancedb_Connection_nativeCreateTable(
+    mut env: JNIEnv,
+    _class: JClass,
+    conn: jlong,
+    name: JString,
+    stream_addr: jlong,
+) -> jlong {
+    let res = get_string(&mut env, &name).and_then(|name| {
+        let data = unsafe { import_stream(stream_addr as *mut FFI_ArrowArrayStream) }?;
+        let conn = unsafe { handle::<Connection>(conn) }?;
+        let table = RUNTIME.block_on(conn.create_table(name, data).execute())?;
+        Ok(into_handle(table))
+    });
+    ok_or_throw(&mut env, res)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_lancedb_Connection_nativeClose(
+    _env: JNIEnv,
+    _class: JClass,
+    conn: jlong,
+) {
+    if conn != 0 {
+        drop(unsafe { Box::from_raw(conn as *mut Connection) });
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_lancedb_Table_nativeAdd(
+    mut env: JNIEnv,
+    _class: JClass,
+    table: jlong,
+    stream_addr: jlong,
+) {
+    let res = (|| {
+        let data = unsafe { import_stream(stream_addr as *mut FFI_ArrowArrayStream) }?;
+        let table = unsafe { handle::<Table>(table) }?;
+        RUNTIME.block_on(table.add(data).execute())
+    })();
+    ok_or_throw(&mut env, res)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_lancedb_Table_nativeQuery(
+    mut env: JNIEnv,
+    _class: JClass,
+    table: jlong,
+    filter: JString,
+    vector: JFloatArray,
+    limit: jint,
+    out_addr: jlong,
+) {
+    let res = (|| {
+        let table = unsafe { handle::<Table>(table) }?;
+        let filter = if filter.is_null() {
+            None
+        } else {
+            Some(get_string(&mut env, &filter)?)
+        };
+        let vector = if vector.is_null() {
+            None
+        } else {
+            let mut buf = vec![0.0; env.get_array_length(&vector)? as usize];
+            env.get_float_array_region(&vector, 0, &mut buf)?;
+            Some(buf)
+        };
+        let stream = export_query(
+            table,
+            filter.as_deref(),
+            vector.as_deref(),
+            limit.max(0) as usize,
+        )?;
+        unsafe { std::ptr::write(out_addr as *mut FFI_ArrowArrayStream, stream) };
+        Ok(())
+    })();
+    ok_or_throw(&mut env, res)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_lancedb_Table_nativeClose(
+    _env: JNIEnv,
+    _class: JClass,
+    table: jlong,
+) {
+    if table != 0 {
+        drop(unsafe { Box::from_raw(table as *mut Table) });
+    }
+}
@@ -45,10 +45,14 @@
 //! # });
 //! ```
 //!
+//! Applications that are not async can use the [`blocking`] API instead, which
+//! runs the operations on a runtime owned by LanceDB.
+//!
 //! LanceDB accepts the different form of database path:
 //!
 //! - `/path/to/database` - local database on file system.
 //! - `s3://bucket/path/to/database` or `gs://bucket/path/to/database` - database on cloud object store
+//! - `memory://` - in-memory database, dropped with the connection
 //! - `db://dbname` - Lance Cloud
 //!
 //! You can also use [`ConnectOptions`] to configure the connection to the database.
@@ -182,16 +186,34 @@
 //! ```
 
 pub mod arrow;
+pub mod blocking;
+#[cfg(feature = "capi")]
+pub mod capi;
 pub mod connection;
 pub mod data;
+pub mod embeddings;
 pub mod error;
+pub mod eval;
 pub mod index;
+#[cfg(any(feature = "delta", feature = "iceberg"))]
+pub mod interop;
 pub mod io;
 pub mod ipc;
+#[cfg(feature = "jni")]
+pub mod java;
+pub mod metrics;
 pub mod query;
+pub mod quota;
 #[cfg(feature = "remote")]
 pub(crate) mod remote;
+#[cfg(feature = "fts")]
+pub mod rerankers;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "datafusion")]
+pub mod sql;
 pub mod table;
+pub mod telemetry;
 pub mod utils;
 
 use std::fmt::Display;
@@ -225,14 +247,42 @@ pub enum DistanceType {
     /// distance has a range of (-∞, ∞). If the vectors are normalized (i.e. their
     /// L2 norm is 1), then dot distance is equivalent to the cosine distance.
     Dot,
+    /// Hamming distance.  The number of bits that differ between two binary
+    /// vectors.  Binary vectors are stored as a fixed size list of `uint8`, each
+    /// byte packing 8 dimensions.  Hamming distance has a range of [0, dim * 8].
+    ///
+    /// Note: vector indices cannot be created with the hamming distance, searches
+    /// with the hamming distance are always exhaustive (flat) searches.
+    Hamming,
+    /// MaxSim (late interaction) distance between two sets of vectors.  Used to
+    /// search multivector columns (a list of fixed size lists of floats) with one
+    /// or more query vectors, as in ColBERT style retrieval.  Each query vector is
+    /// matched with its closest vector of the row (by dot distance) and the
+    /// distances of the matches are summed.  With normalized vectors MaxSim
+    /// distance has a range of [0, 2 * number of query vectors].
+    ///
+    /// Note: vector indices cannot be created with the MaxSim distance, searches
+    /// with the MaxSim distance are always exhaustive (flat) searches.
+    MaxSim,
 }
 
-impl From<DistanceType> for LanceDistanceType {
-    fn from(value: DistanceType) -> Self {
+impl TryFrom<DistanceType> for LanceDistanceType {
+    type Error = Error;
+
+    fn try_from(value: DistanceType) -> Result<Self> {
         match value {
-            DistanceType::L2 => Self::L2,
-            DistanceType::Cosine => Self::Cosine,
-            DistanceType::Dot => Self::Dot,
+            DistanceType::L2 => Ok(Self::L2),
+            DistanceType::Cosine => Ok(Self::Cosine),
+            DistanceType::Dot => Ok(Self::Dot),
+            DistanceType::Hamming => Err(Error::InvalidInput {
+                message:
+                    "the hamming distance is only supported for flat searches of binary vectors"
+                        .to_string(),
+            }),
+            DistanceType::MaxSim => Err(Error::InvalidInput {
+                message: "the MaxSim distance is only supported for flat searches of multivectors"
+                    .to_string(),
+            }),
         }
     }
 }
@@ -251,13 +301,25 @@ impl<'a> TryFrom<&'a str> for DistanceType {
     type Error = <LanceDistanceType as TryFrom<&'a str>>::Error;
 
     fn try_from(value: &str) -> std::prelude::v1::Result<Self, Self::Error> {
+        if value.eq_ignore_ascii_case("hamming") {
+            return Ok(Self::Hamming);
+        }
+        if value.eq_ignore_ascii_case("maxsim") {
+            return Ok(Self::MaxSim);
+        }
         LanceDistanceType::try_from(value).map(Self::from)
     }
 }
 
 impl Display for DistanceType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        LanceDistanceType::from(*self).fmt(f)
+        match self {
+            Self::Hamming => write!(f, "hamming"),
+            Self::MaxSim => write!(f, "maxsim"),
+            _ => LanceDistanceType::try_from(*self)
+                .map_err(|_| std::fmt::Error)?
+                .fmt(f),
+        }
     }
 }
 
@@ -0,0 +1,461 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metrics of the operations of a connection, for services running LanceDB
+//!
+//! A connection to LanceDB OSS counts the queries and writes of its tables,
+//! the rows written, the hits and misses of its
+//! [`crate::io::cache::CacheBackend`] and the number of fragments of each of
+//! its tables.  [`crate::connection::Connection::metrics`] returns a snapshot
+//! of the metrics, which [`ConnectionMetrics::to_prometheus`] renders in the
+//! Prometheus text format, for example to serve from a `/metrics` endpoint.
+//!
+//! Counters only increase while the connection is open.  The fragment counts
+//! are updated when a table is opened or written by the connection.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use arrow_array::{RecordBatch, RecordBatchReader};
+use arrow_schema::ArrowError;
+use futures::Stream;
+
+use crate::arrow::{RecordBatchStream, SendableRecordBatchStream};
+use crate::error::Result;
+
+/// The upper bounds of the buckets of the latency histograms, in seconds
+pub const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0,
+];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = LATENCY_BUCKETS
+            .iter()
+            .zip(&self.buckets)
+            .map(|(bound, count)| {
+                cumulative += count.load(Ordering::Relaxed);
+                (*bound, cumulative)
+            })
+            .collect();
+        HistogramSnapshot {
+            buckets,
+            count: self.count.load(Ordering::Relaxed),
+            sum: Duration::from_micros(self.sum_micros.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// A snapshot of a latency histogram
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistogramSnapshot {
+    /// The upper bound of each bucket, in seconds, with the number of
+    /// observations lower than or equal to it
+    pub buckets: Vec<(f64, u64)>,
+    /// The number of observations
+    pub count: u64,
+    /// The sum of the observations
+    pub sum: Duration,
+}
+
+/// A snapshot of the metrics of a connection
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnectionMetrics {
+    /// The queries executed, including the failed ones
+    pub queries: u64,
+    /// The queries which failed
+    pub query_errors: u64,
+    /// The time until the results of the queries were all read
+    pub query_latency: HistogramSnapshot,
+    /// The writes to the tables, such as adds, deletes and index builds,
+    /// including the failed ones
+    pub writes: u64,
+    /// The writes which failed
+    pub write_errors: u64,
+    /// The time taken by the writes
+    pub write_latency: HistogramSnapshot,
+    /// The rows written by the creation of tables and by adds
+    pub rows_written: u64,
+    /// The reads served by the cache backend of the connection
+    pub cache_hits: u64,
+    /// The reads of cacheable files which missed the cache backend
+    pub cache_misses: u64,
+    /// The queries answered from the query cache of the connection
+    pub query_cache_hits: u64,
+    /// The queries which missed the query cache of the connection
+    pub query_cache_misses: u64,
+    /// The number of fragments of each table opened or written
+    pub fragments: BTreeMap<String, u64>,
+}
+
+impl ConnectionMetrics {
+    /// The fraction of the cacheable reads served by the cache backend
+    ///
+    /// None if there was no cacheable read.
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let reads = self.cache_hits + self.cache_misses;
+        (reads > 0).then(|| self.cache_hits as f64 / reads as f64)
+    }
+
+    /// Render the metrics in the Prometheus text exposition format
+    ///
+    /// The metrics are named `lancedb_*`.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            (
+                "lancedb_queries_total",
+                "The queries executed",
+                self.queries,
+            ),
+            (
+                "lancedb_query_errors_total",
+                "The queries which failed",
+                self.query_errors,
+            ),
+            (
+                "lancedb_writes_total",
+                "The writes to the tables",
+                self.writes,
+            ),
+            (
+                "lancedb_write_errors_total",
+                "The writes which failed",
+                self.write_errors,
+            ),
+            (
+                "lancedb_rows_written_total",
+                "The rows written",
+                self.rows_written,
+            ),
+            (
+                "lancedb_cache_hits_total",
+                "The reads served by the cache",
+                self.cache_hits,
+            ),
+            (
+                "lancedb_cache_misses_total",
+                "The cacheable reads which missed the cache",
+                self.cache_misses,
+            ),
+            (
+                "lancedb_query_cache_hits_total",
+                "The queries answered from the query cache",
+                self.query_cache_hits,
+            ),
+            (
+                "lancedb_query_cache_misses_total",
+                "The queries which missed the query cache",
+                self.query_cache_misses,
+            ),
+        ];
+        for (name, help, value) in counters {
+            writeln!(out, "# HELP {} {}", name, help).unwrap();
+            writeln!(out, "# TYPE {} counter", name).unwrap();
+            writeln!(out, "{} {}", name, value).unwrap();
+        }
+        write_histogram(
+            &mut out,
+            "lancedb_query_duration_seconds",
+            "The time until the results of the queries were all read",
+            &self.query_latency,
+        );
+        write_histogram(
+            &mut out,
+            "lancedb_write_duration_seconds",
+            "The time taken by the writes",
+            &self.write_latency,
+        );
+        writeln!(
+            out,
+            "# HELP lancedb_table_fragments The number of fragments of the table"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE lancedb_table_fragments gauge").unwrap();
+        for (table, fragments) in &self.fragments {
+            writeln!(
+                out,
+                "lancedb_table_fragments{{table=\"{}\"}} {}",
+                escape_label(table),
+                fragments
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+fn write_histogram(out: &mut String, name: &str, help: &str, histogram: &HistogramSnapshot) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} histogram", name).unwrap();
+    for (bound, count) in &histogram.buckets {
+        writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count).unwrap();
+    }
+    writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, histogram.count).unwrap();
+    writeln!(out, "{}_sum {}", name, histogram.sum.as_secs_f64()).unwrap();
+    writeln!(out, "{}_count {}", name, histogram.count).unwrap();
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The metrics of a connection, shared by its tables
+#[derive(Debug, Default)]
+pub(crate) struct MetricsRegistry {
+    queries: AtomicU64,
+    query_errors: AtomicU64,
+    query_latency: Histogram,
+    writes: AtomicU64,
+    write_errors: AtomicU64,
+    write_latency: Histogram,
+    rows_written: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    query_cache_hits: AtomicU64,
+    query_cache_misses: AtomicU64,
+    fragments: Mutex<BTreeMap<String, u64>>,
+}
+
+impl MetricsRegistry {
+    /// Count the query started at `start`, once the results of `stream` are all read
+    pub(crate) fn observe_query(
+        self: &Arc<Self>,
+        start: Instant,
+        stream: Result<SendableRecordBatchStream>,
+    ) -> Result<SendableRecordBatchStream> {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        match stream {
+            Ok(stream) => Ok(Box::pin(ObservedRecordBatchStream {
+                stream,
+                registry: self.clone(),
+                start,
+                failed: false,
+                finished: false,
+            })),
+            Err(e) => {
+                self.query_errors.fetch_add(1, Ordering::Relaxed);
+                self.query_latency.observe(start.elapsed());
+                Err(e)
+            }
+        }
+    }
+
+    /// Count the write started at `start`
+    pub(crate) fn observe_write<T>(&self, start: Instant, result: &Result<T>) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        if result.is_err() {
+            self.write_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.write_latency.observe(start.elapsed());
+    }
+
+    pub(crate) fn add_rows_written(&self, rows: u64) {
+        self.rows_written.fetch_add(rows, Ordering::Relaxed);
+    }
+
+    pub(crate) fn observe_cache_read(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn observe_query_cache(&self, hit: bool) {
+        let counter = if hit {
+            &self.query_cache_hits
+        } else {
+            &self.query_cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_fragments(&self, table: &str, fragments: u64) {
+        if let Ok(mut tables) = self.fragments.lock() {
+            tables.insert(table.to_string(), fragments);
+        }
+    }
+
+    pub(crate) fn remove_table(&self, table: &str) {
+        if let Ok(mut tables) = self.fragments.lock() {
+            tables.remove(table);
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> ConnectionMetrics {
+        ConnectionMetrics {
+            queries: self.queries.load(Ordering::Relaxed),
+            query_errors: self.query_errors.load(Ordering::Relaxed),
+            query_latency: self.query_latency.snapshot(),
+            writes: self.writes.load(Ordering::Relaxed),
+            write_errors: self.write_errors.load(Ordering::Relaxed),
+            write_latency: self.write_latency.snapshot(),
+            rows_written: self.rows_written.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+            query_cache_hits: self.query_cache_hits.load(Ordering::Relaxed),
+            query_cache_misses: self.query_cache_misses.load(Ordering::Relaxed),
+            fragments: self
+                .fragments
+                .lock()
+                .map(|tables| tables.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Count the rows read from `data`
+///
+/// The count is only added to the metrics once the write succeeded, so that
+/// writes which are retried are counted once.
+pub(crate) fn count_rows(
+    data: Box<dyn RecordBatchReader + Send>,
+) -> (Box<dyn RecordBatchReader + Send>, Arc<AtomicU64>) {
+    let rows = Arc::new(AtomicU64::new(0));
+    let counted = rows.clone();
+    let schema = data.schema();
+    let data = data.inspect(
+        move |batch: &std::result::Result<RecordBatch, ArrowError>| {
+            if let Ok(batch) = batch {
+                counted.fetch_add(batch.num_rows() as u64, Ordering::Relaxed);
+            }
+        },
+    );
+    (
+        Box::new(arrow_array::RecordBatchIterator::new(data, schema)),
+        rows,
+    )
+}
+
+/// A RecordBatchStream which counts its query once exhausted or dropped
+#[pin_project::pin_project(PinnedDrop)]
+struct ObservedRecordBatchStream {
+    #[pin]
+    stream: SendableRecordBatchStream,
+    registry: Arc<MetricsRegistry>,
+    start: Instant,
+    failed: bool,
+    finished: bool,
+}
+
+impl Stream for ObservedRecordBatchStream {
+    type Item = Result<RecordBatch>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let this = self.project();
+        let next = this.stream.poll_next(cx);
+        match &next {
+            std::task::Poll::Ready(Some(Err(_))) => *this.failed = true,
+            std::task::Poll::Ready(None) if !*this.finished => {
+                *this.finished = true;
+                if *this.failed {
+                    this.registry.query_errors.fetch_add(1, Ordering::Relaxed);
+                }
+                this.registry.query_latency.observe(this.start.elapsed());
+            }
+            _ => {}
+        }
+        next
+    }
+}
+
+#[pin_project::pinned_drop]
+impl PinnedDrop for ObservedRecordBatchStream {
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        if !*this.finished {
+            if *this.failed {
+                this.registry.query_errors.fetch_add(1, Ordering::Relaxed);
+            }
+            this.registry.query_latency.observe(this.start.elapsed());
+        }
+    }
+}
+
+impl RecordBatchStream for ObservedRecordBatchStream {
+    fn schema(&self) -> Arc<arrow_schema::Schema> {
+        self.stream.schema()
+    }
+
+    fn version(&self) -> Option<u64> {
+        self.stream.version()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(500));
+        histogram.observe(Duration::from_millis(20));
+        histogram.observe(Duration::from_secs(60));
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 3);
+        assert_eq!(snapshot.buckets[0], (0.001, 1));
+        assert_eq!(snapshot.buckets[4], (0.025, 2));
+        // The slowest observation is only counted in the +Inf bucket
+        assert_eq!(snapshot.buckets.last().unwrap().1, 2);
+    }
+
+    #[test]
+    fn test_to_prometheus() {
+        let registry = MetricsRegistry::default();
+        registry.observe_write(Instant::now(), &Ok(()));
+        registry.add_rows_written(10);
+        registry.observe_cache_read(true);
+        registry.observe_cache_read(false);
+        registry.set_fragments("my \"table\"", 3);
+        let metrics = registry.snapshot();
+        assert_eq!(metrics.cache_hit_rate(), Some(0.5));
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains("lancedb_writes_total 1\n"));
+        assert!(text.contains("lancedb_rows_written_total 10\n"));
+        assert!(text.contains("lancedb_write_duration_seconds_count 1\n"));
+        assert!(text.contains("lancedb_table_fragments{table=\"my \\\"table\\\"\"} 3\n"));
+    }
+}
@@ -14,15 +14,53 @@
 
 use std::future::Future;
 use std::sync::Arc;
-
-use arrow_array::{make_array, Array, Float16Array, Float32Array, Float64Array};
-use arrow_schema::DataType;
+use std::time::Duration;
+
+use arrow_array::timezone::Tz;
+use arrow_array::{
+    cast::AsArray, make_array, Array, FixedSizeListArray, Float16Array, Float32Array, Float64Array,
+    RecordBatch, StringArray, UInt8Array,
+};
+use arrow_schema::{DataType, Field};
+use chrono::{DateTime, NaiveTime, TimeZone, Utc};
+use futures::stream::{self, BoxStream};
+use futures::{StreamExt, TryStreamExt};
 use half::f16;
+use serde::de::DeserializeOwned;
 
-use crate::arrow::SendableRecordBatchStream;
+use crate::arrow::{
+    block_on, deserialize_batch, BlockingRecordBatchReader, SendableRecordBatchStream,
+};
+#[cfg(feature = "fts")]
+use crate::arrow::{RecordBatchStreamExt, SimpleRecordBatchStream};
 use crate::error::{Error, Result};
-use crate::table::TableInternal;
-use crate::DistanceType;
+#[cfg(feature = "fts")]
+use crate::index::fts::ROW_ID_COLUMN;
+#[cfg(feature = "fts")]
+use crate::rerankers::{RRFReranker, Reranker};
+use crate::table::{blob, TableInternal};
+use crate::utils::default_vector_column;
+use crate::{DistanceType, Table};
+
+pub mod admission;
+pub mod batch;
+pub mod budget;
+pub mod cache;
+pub mod enrich;
+pub mod filter;
+pub mod geo;
+pub mod json;
+pub mod metrics;
+pub mod predicate;
+pub mod scatter;
+pub mod timeout;
+
+use self::enrich::{EnrichSource, EnrichedQuery};
+use self::filter::{Filter, FilterValue};
+use self::metrics::ExecutionMetrics;
+use self::predicate::Predicate;
+use self::scatter::ShardedVectorQuery;
+use self::timeout::CancellationToken;
 
 pub(crate) const DEFAULT_TOP_K: usize = 10;
 
@@ -66,6 +104,64 @@ impl Select {
     }
 }
 
+/// The direction in which the rows are sorted, see [`Query::order_by`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Order {
+    /// From the smallest value to the largest
+    #[default]
+    Asc,
+    /// From the largest value to the smallest
+    Desc,
+}
+
+/// The size of a random sample of the rows, see [`Query::sample`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sample {
+    /// This fraction of the rows, between 0 and 1
+    Fraction(f64),
+    /// This number of rows, or all of the rows if there are fewer
+    Rows(usize),
+}
+
+impl From<f64> for Sample {
+    fn from(fraction: f64) -> Self {
+        Self::Fraction(fraction)
+    }
+}
+
+impl From<usize> for Sample {
+    fn from(rows: usize) -> Self {
+        Self::Rows(rows)
+    }
+}
+
+/// A hint that overrides a decision normally made by the query planner
+///
+/// Hints are intended for power users.  The planner usually picks a good
+/// strategy but unusual data distributions can lead it astray.  Hints are
+/// applied with [`QueryBase::hint`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Hint {
+    /// Perform an exhaustive (flat) search even if a vector index exists
+    ///
+    /// This is equivalent to [`VectorQuery::bypass_vector_index`]
+    ForceFlatSearch,
+    /// Require the index with the given name to be used
+    ///
+    /// The query will fail if no index with this name exists or, for a vector
+    /// search, if the index is not on the column being searched.  It also
+    /// fails if the query is set not to use an index, with
+    /// [`Hint::ForceFlatSearch`] or [`VectorQuery::bypass_vector_index`], or
+    /// if the search is always flat (e.g. a hamming or multivector search).
+    UseIndex(String),
+    /// Do not use any cached indices or metadata
+    ///
+    /// Everything needed by the query is read from storage.  This is slower
+    /// but avoids polluting the cache with data from one-off queries and can
+    /// be used to measure cold query performance.
+    NoCache,
+}
+
 /// A trait for converting a type to a query vector
 ///
 /// This is primarily intended to allow rust users that are unfamiliar with Arrow
@@ -118,6 +214,21 @@ impl IntoQueryVector for Arc<dyn Array> {
         data_type: &DataType,
         _embedding_model_label: &str,
     ) -> Result<Arc<dyn Array>> {
+        // Several query vectors (see [`DistanceType::MaxSim`]) keep their shape and
+        // only their values are converted
+        if let DataType::FixedSizeList(_, dim) = self.data_type() {
+            let vectors = self.as_fixed_size_list();
+            let values = vectors
+                .values()
+                .clone()
+                .to_query_vector(data_type, _embedding_model_label)?;
+            return Ok(Arc::new(FixedSizeListArray::try_new(
+                Arc::new(Field::new("item", values.data_type().clone(), true)),
+                *dim,
+                values,
+                vectors.nulls().cloned(),
+            )?));
+        }
         if data_type != self.data_type() {
             match data_type {
                 // If the embedding wants floating point data we can try and cast
@@ -312,6 +423,17 @@ impl IntoQueryVector for Vec<f32> {
     }
 }
 
+impl IntoQueryVector for &Vec<f32> {
+    fn to_query_vector(
+        self,
+        data_type: &DataType,
+        embedding_model_label: &str,
+    ) -> Result<Arc<dyn Array>> {
+        self.as_slice()
+            .to_query_vector(data_type, embedding_model_label)
+    }
+}
+
 impl IntoQueryVector for Vec<f64> {
     fn to_query_vector(
         self,
@@ -323,6 +445,261 @@ impl IntoQueryVector for Vec<f64> {
     }
 }
 
+// A packed binary vector, searched with [`DistanceType::Hamming`].  Every byte is
+// exactly representable as a float so the bytes are carried as floats like any
+// other query vector and converted back when the search runs.
+impl IntoQueryVector for &[u8] {
+    fn to_query_vector(
+        self,
+        data_type: &DataType,
+        embedding_model_label: &str,
+    ) -> Result<Arc<dyn Array>> {
+        match data_type {
+            DataType::Float16 | DataType::Float32 | DataType::Float64 => {
+                let arr = UInt8Array::from(self.to_vec());
+                Ok(arrow_cast::cast(&arr, data_type)?)
+            }
+            _ => Err(Error::InvalidInput {
+                message: format!(
+                    "failed to create query vector, the input data type was &[u8] but the embedding model \"{}\" expected data type {:?}",
+                    embedding_model_label,
+                    data_type
+                ),
+            }),
+        }
+    }
+}
+
+impl<const N: usize> IntoQueryVector for &[u8; N] {
+    fn to_query_vector(
+        self,
+        data_type: &DataType,
+        embedding_model_label: &str,
+    ) -> Result<Arc<dyn Array>> {
+        self.as_slice()
+            .to_query_vector(data_type, embedding_model_label)
+    }
+}
+
+impl IntoQueryVector for Vec<u8> {
+    fn to_query_vector(
+        self,
+        data_type: &DataType,
+        embedding_model_label: &str,
+    ) -> Result<Arc<dyn Array>> {
+        self.as_slice()
+            .to_query_vector(data_type, embedding_model_label)
+    }
+}
+
+// Several query vectors, searched with [`DistanceType::MaxSim`]
+impl IntoQueryVector for Vec<Vec<f32>> {
+    fn to_query_vector(
+        self,
+        data_type: &DataType,
+        embedding_model_label: &str,
+    ) -> Result<Arc<dyn Array>> {
+        let dim = self.first().map(Vec::len).unwrap_or_default();
+        if self.iter().any(|vector| vector.len() != dim) {
+            return Err(Error::InvalidInput {
+                message:
+                    "failed to create query vector, the query vectors have different dimensions"
+                        .to_string(),
+            });
+        }
+        let vectors = FixedSizeListArray::try_new(
+            Arc::new(Field::new("item", DataType::Float32, true)),
+            dim as i32,
+            Arc::new(Float32Array::from(self.concat())),
+            None,
+        )?;
+        (Arc::new(vectors) as Arc<dyn Array>).to_query_vector(data_type, embedding_model_label)
+    }
+}
+
+// Text is embedded when the query runs, using the embedding function of the
+// vector column, so the requested data type does not apply.
+impl IntoQueryVector for &str {
+    fn to_query_vector(
+        self,
+        _data_type: &DataType,
+        _embedding_model_label: &str,
+    ) -> Result<Arc<dyn Array>> {
+        Ok(Arc::new(StringArray::from(vec![self])))
+    }
+}
+
+impl IntoQueryVector for String {
+    fn to_query_vector(
+        self,
+        data_type: &DataType,
+        embedding_model_label: &str,
+    ) -> Result<Arc<dyn Array>> {
+        self.as_str()
+            .to_query_vector(data_type, embedding_model_label)
+    }
+}
+
+/// A query vector built by combining other vectors
+///
+/// This can be used to search for rows that are "more like these, less like
+/// that" by averaging the embeddings of the examples and subtracting the
+/// embeddings of the counter examples:
+///
+/// ```ignore
+/// let vector = QueryVector::centroid(&[liked_a, liked_b])?
+///     .subtract(&disliked, 0.5)?;
+/// let results = table.query().nearest_to(vector)?.execute().await?;
+/// ```
+///
+/// All vectors that are combined must have the same dimension.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryVector {
+    values: Vec<f32>,
+}
+
+impl QueryVector {
+    pub fn new(values: impl Into<Vec<f32>>) -> Self {
+        Self {
+            values: values.into(),
+        }
+    }
+
+    /// The number of dimensions in the vector
+    pub fn dim(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// The mean of `vectors`
+    pub fn centroid(vectors: &[impl AsRef<[f32]>]) -> Result<Self> {
+        let weight = 1.0 / vectors.len() as f32;
+        Self::weighted(
+            &vectors
+                .iter()
+                .map(|vector| (vector.as_ref(), weight))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// The sum of `vectors`, each multiplied by its weight
+    ///
+    /// Negative weights move the result away from a vector.
+    pub fn weighted(vectors: &[(impl AsRef<[f32]>, f32)]) -> Result<Self> {
+        let Some((first, _)) = vectors.first() else {
+            return Err(Error::InvalidInput {
+                message: "at least one vector is required".to_string(),
+            });
+        };
+        let mut result = Self::new(vec![0.0; first.as_ref().len()]);
+        for (vector, weight) in vectors {
+            result = result.add(vector.as_ref(), *weight)?;
+        }
+        Ok(result)
+    }
+
+    /// Add `other`, multiplied by `weight`, to this vector
+    pub fn add(mut self, other: &[f32], weight: f32) -> Result<Self> {
+        self.check_dim(other.len())?;
+        for (value, other) in self.values.iter_mut().zip(other) {
+            *value += weight * other;
+        }
+        Ok(self)
+    }
+
+    /// Subtract `other`, multiplied by `weight`, from this vector
+    pub fn subtract(self, other: &[f32], weight: f32) -> Result<Self> {
+        self.add(other, -weight)
+    }
+
+    /// Multiply every value in the vector by `factor`
+    pub fn scale(mut self, factor: f32) -> Self {
+        self.values.iter_mut().for_each(|value| *value *= factor);
+        self
+    }
+
+    /// Scale the vector to have a length (L2 norm) of 1
+    ///
+    /// A vector of zeros is returned unchanged.
+    pub fn normalize(self) -> Self {
+        let norm = self.values.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            self.scale(1.0 / norm)
+        } else {
+            self
+        }
+    }
+
+    /// Check that the vector can be compared with the vectors in `column`
+    ///
+    /// If `column` is None then the table must have exactly one vector column
+    /// with the same dimension as this vector.
+    pub fn validate(&self, schema: &arrow_schema::Schema, column: Option<&str>) -> Result<()> {
+        let column = match column {
+            Some(column) => column.to_string(),
+            None => default_vector_column(schema, Some(self.dim() as i32))?,
+        };
+        let field = schema.field_with_name(&column)?;
+        match field.data_type() {
+            DataType::FixedSizeList(item, dim) if item.data_type().is_floating() => {
+                if *dim as usize != self.dim() {
+                    return Err(Error::InvalidInput {
+                        message: format!(
+                            "The query vector has {} dimensions but the column '{}' has {}",
+                            self.dim(),
+                            column,
+                            dim
+                        ),
+                    });
+                }
+                Ok(())
+            }
+            _ => Err(Error::InvalidInput {
+                message: format!("The column '{}' is not a vector column", column),
+            }),
+        }
+    }
+
+    fn check_dim(&self, dim: usize) -> Result<()> {
+        if dim != self.dim() {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "vector dimensions do not match: expected {} but got {}",
+                    self.dim(),
+                    dim
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl AsRef<[f32]> for QueryVector {
+    fn as_ref(&self) -> &[f32] {
+        &self.values
+    }
+}
+
+impl From<Vec<f32>> for QueryVector {
+    fn from(values: Vec<f32>) -> Self {
+        Self::new(values)
+    }
+}
+
+impl IntoQueryVector for QueryVector {
+    fn to_query_vector(
+        self,
+        data_type: &DataType,
+        embedding_model_label: &str,
+    ) -> Result<Arc<dyn Array>> {
+        self.values
+            .to_query_vector(data_type, embedding_model_label)
+    }
+}
+
 /// Common parameters that can be applied to scans and vector queries
 pub trait QueryBase {
     /// Set the maximum number of results to return.
@@ -330,8 +707,8 @@ pub trait QueryBase {
     /// By default, a plain search has no limit.  If this method is not
     /// called then every valid row from the table will be returned.
     ///
-    /// A vector search always has a limit.  If this is not called then
-    /// it will default to 10.
+    /// A vector search or full text search always has a limit.  If this
+    /// is not called then it will default to 10.
     fn limit(self, limit: usize) -> Self;
 
     /// Only return rows which match the filter.
@@ -344,10 +721,49 @@ pub trait QueryBase {
     /// x > 5 OR y = 'test'
     /// ```
     ///
+    /// The supported syntax is described in [`filter`].  An invalid filter
+    /// fails with [`Error::InvalidFilter`] when the query is executed.
+    ///
     /// Filtering performance can often be improved by creating a scalar index
     /// on the filter column(s).
     fn only_if(self, filter: impl AsRef<str>) -> Self;
 
+    /// Only return rows which match the predicate
+    ///
+    /// This is [`Self::only_if`] with a filter built from typed columns and
+    /// values, see [`predicate`]:
+    ///
+    /// ```ignore
+    /// query.only_if_predicate(col("price").gt(10).and(col("tag").is_in(["new", "sale"])))
+    /// ```
+    fn only_if_predicate(self, predicate: Predicate) -> Self;
+
+    /// Only return rows which match the filter, with the values of its numbered
+    /// parameters
+    ///
+    /// `params[0]` is bound to `$1`, `params[1]` to `$2` and so on, see
+    /// [`filter`].  Values from users should always be passed as parameters
+    /// rather than formatted into the filter:
+    ///
+    /// ```ignore
+    /// query.only_if_with_params("user_id = $1 AND tag IN ($2)", [user_id.into(), tags.into()])
+    /// ```
+    fn only_if_with_params(
+        self,
+        filter: impl AsRef<str>,
+        params: impl IntoIterator<Item = FilterValue>,
+    ) -> Self;
+
+    /// Bind a value to the parameter `$name` of the filter
+    ///
+    /// Values are quoted as needed, so strings from users can be used in a
+    /// filter without escaping them by hand:
+    ///
+    /// ```ignore
+    /// query.only_if("category = $category").bind("category", "men's shoes")
+    /// ```
+    fn bind(self, name: impl Into<String>, value: impl Into<FilterValue>) -> Self;
+
     /// Return only the specified columns.
     ///
     /// By default a query will return all columns from the table.  However, this can have
@@ -371,6 +787,83 @@ pub trait QueryBase {
     /// Columns will always be returned in the order given, even if that order is different than
     /// the order used when adding the data.
     fn select(self, selection: Select) -> Self;
+
+    /// Add columns computed from SQL expressions to the selected columns
+    ///
+    /// Unlike [`Select::Dynamic`], which replaces the selection, this keeps the
+    /// columns selected with [`Self::select`] (all of them by default) and
+    /// appends a column for each tuple, named by its first value and computed
+    /// by evaluating its second value during the scan.  A computed column
+    /// replaces a selected column with the same name.
+    ///
+    /// ```ignore
+    /// query.select_with_exprs(&[("price_with_tax", "price * 1.1")])
+    /// ```
+    ///
+    /// This can be called several times to add more columns.
+    fn select_with_exprs(self, exprs: &[(impl AsRef<str>, impl AsRef<str>)]) -> Self;
+
+    /// Provide a hint to the query planner
+    ///
+    /// This method can be called multiple times to provide several hints.
+    /// See [`Hint`] for the available hints.  Conflicting hints (for example,
+    /// [`Hint::ForceFlatSearch`] and [`Hint::UseIndex`]) will cause the query
+    /// to fail when it is executed.
+    fn hint(self, hint: Hint) -> Self;
+
+    /// Only return rows where `column` falls within `range`
+    ///
+    /// The column must be a timestamp or date column.  This is combined with
+    /// any filter set by [`Self::only_if`] and may be called multiple times.
+    ///
+    /// The bounds of the range are converted to the unit and timezone of the
+    /// column so that the filter compares the same instants regardless of the
+    /// timezone the column was written with.  The filter is a simple range
+    /// comparison and so a BTree index on the column (see
+    /// [`crate::index::Index::BTree`]) will be used to skip rows outside of the
+    /// range, which makes "most recent" queries on large tables fast.
+    fn only_within(self, column: impl Into<String>, range: TimeRange) -> Self;
+
+    /// Search the soft deleted rows instead of the rows in the table
+    ///
+    /// The table must have been created with soft delete enabled (see
+    /// [`crate::connection::CreateTableBuilder::soft_delete`]).  Deleted rows
+    /// can be restored with [`crate::Table::undelete`].
+    fn only_deleted(self) -> Self;
+
+    /// Include the row id of each result in the `_rowid` column
+    ///
+    /// The row ids can be used to fetch the rows again with
+    /// [`crate::Table::take`], or to tell apart duplicate rows.
+    fn with_row_id(self) -> Self;
+
+    /// Include the address of each result in the `_rowaddr` column
+    ///
+    /// The address is the fragment holding the row and the offset of the row in
+    /// the fragment, see [`crate::table::row_address`].  This is meant for
+    /// debugging the layout of a table: addresses change when the table is
+    /// compacted.
+    fn with_row_address(self) -> Self;
+
+    /// Leave the blob columns out of the results
+    ///
+    /// This only changes the results of a query selecting all of the columns,
+    /// the default.  The blobs of the rows which are needed can be read
+    /// afterwards with [`crate::Table::take_blobs`], see [`crate::table::blob`].
+    fn exclude_blobs(self) -> Self;
+
+    /// Abort the query if it runs for longer than `timeout`
+    ///
+    /// The timeout covers planning the query and reading all of its results.
+    /// Once it elapses the query fails with [`Error::QueryTimeout`] and stops
+    /// reading the table.  See [`timeout`] for more details.
+    fn timeout(self, timeout: Duration) -> Self;
+
+    /// Abort the query once `token` is cancelled
+    ///
+    /// The query fails with [`Error::QueryCancelled`] and stops reading the
+    /// table.  See [`timeout`] for more details.
+    fn cancellation_token(self, token: CancellationToken) -> Self;
 }
 
 pub trait HasQuery {
@@ -388,43 +881,237 @@ impl<T: HasQuery> QueryBase for T {
         self
     }
 
+    fn only_if_predicate(self, predicate: Predicate) -> Self {
+        self.only_if(predicate.to_sql())
+    }
+
+    fn only_if_with_params(
+        self,
+        filter: impl AsRef<str>,
+        params: impl IntoIterator<Item = FilterValue>,
+    ) -> Self {
+        params
+            .into_iter()
+            .enumerate()
+            .fold(self.only_if(filter), |query, (i, value)| {
+                query.bind((i + 1).to_string(), value)
+            })
+    }
+
+    fn bind(mut self, name: impl Into<String>, value: impl Into<FilterValue>) -> Self {
+        self.mut_query()
+            .filter_params
+            .push((name.into(), value.into()));
+        self
+    }
+
     fn select(mut self, select: Select) -> Self {
         self.mut_query().select = select;
         self
     }
+
+    fn select_with_exprs(mut self, exprs: &[(impl AsRef<str>, impl AsRef<str>)]) -> Self {
+        self.mut_query().computed_columns.extend(
+            exprs
+                .iter()
+                .map(|(name, expr)| (name.as_ref().to_string(), expr.as_ref().to_string())),
+        );
+        self
+    }
+
+    fn hint(mut self, hint: Hint) -> Self {
+        self.mut_query().hints.push(hint);
+        self
+    }
+
+    fn only_within(mut self, column: impl Into<String>, range: TimeRange) -> Self {
+        self.mut_query().time_ranges.push((column.into(), range));
+        self
+    }
+
+    fn only_deleted(mut self) -> Self {
+        self.mut_query().only_deleted = true;
+        self
+    }
+
+    fn with_row_id(mut self) -> Self {
+        self.mut_query().with_row_id = true;
+        self
+    }
+
+    fn with_row_address(mut self) -> Self {
+        self.mut_query().with_row_address = true;
+        self
+    }
+
+    fn exclude_blobs(mut self) -> Self {
+        self.mut_query().exclude_blobs = true;
+        self
+    }
+
+    fn timeout(mut self, timeout: Duration) -> Self {
+        self.mut_query().timeout = Some(timeout);
+        self
+    }
+
+    fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.mut_query().cancellation_token = Some(token);
+        self
+    }
 }
 
-/// Options for controlling the execution of a query
-#[non_exhaustive]
-pub struct QueryExecutionOptions {
-    /// The maximum number of rows that will be contained in a single
-    /// `RecordBatch` delivered by the query.
-    ///
-    /// Note: This is a maximum only.  The query may return smaller
-    /// batches, even in the middle of a query, to avoid forcing
-    /// memory copies due to concatenation.
-    ///
-    /// Note: Slicing an Arrow RecordBatch is a zero-copy operation
-    /// and so the performance penalty of reading smaller batches
-    /// is typically very small.
-    ///
-    /// By default, this is 1024
-    pub max_batch_length: u32,
+/// A range of time, used to filter timestamp and date columns
+///
+/// The range includes the start and excludes the end.  Either bound may be
+/// omitted.  See [`QueryBase::only_within`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeRange {
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
 }
 
-impl Default for QueryExecutionOptions {
-    fn default() -> Self {
+impl TimeRange {
+    /// The range from `start` (inclusive) to `end` (exclusive)
+    pub fn new<Tz: TimeZone>(start: DateTime<Tz>, end: DateTime<Tz>) -> Self {
         Self {
-            max_batch_length: 1024,
+            start: Some(start.with_timezone(&Utc)),
+            end: Some(end.with_timezone(&Utc)),
         }
     }
-}
 
-/// A trait for a query object that can be executed to get results
-///
-/// There are various kinds of queries but they all return results
-/// in the same way.
-pub trait ExecutableQuery {
+    /// All times at or after `start`
+    pub fn since<Tz: TimeZone>(start: DateTime<Tz>) -> Self {
+        Self {
+            start: Some(start.with_timezone(&Utc)),
+            end: None,
+        }
+    }
+
+    /// All times before `end`
+    pub fn before<Tz: TimeZone>(end: DateTime<Tz>) -> Self {
+        Self {
+            start: None,
+            end: Some(end.with_timezone(&Utc)),
+        }
+    }
+
+    /// The most recent `duration`, up to the current time
+    pub fn last(duration: chrono::Duration) -> Self {
+        Self::since(Utc::now() - duration)
+    }
+
+    /// Convert the range to a SQL filter on `column`
+    pub(crate) fn to_filter(&self, schema: &arrow_schema::Schema, column: &str) -> Result<String> {
+        let field = schema.field_with_name(column)?;
+        let quoted = format!("`{}`", column);
+        let mut conditions = Vec::new();
+        match field.data_type() {
+            DataType::Timestamp(_, tz) => {
+                // Timestamp literals are naive, so they are written as the wall clock
+                // time in the column's timezone
+                let tz = tz
+                    .as_deref()
+                    .filter(|tz| *tz != "UTC")
+                    .map(|tz| {
+                        tz.parse::<Tz>().map_err(|e| Error::InvalidInput {
+                            message: format!(
+                                "Unsupported timezone '{}' on column '{}': {}",
+                                tz, column, e
+                            ),
+                        })
+                    })
+                    .transpose()?;
+                let literal = |time: &DateTime<Utc>| {
+                    let time = match &tz {
+                        Some(tz) => time.with_timezone(tz).naive_local(),
+                        None => time.naive_utc(),
+                    };
+                    format!("timestamp '{}'", time.format("%Y-%m-%d %H:%M:%S%.f"))
+                };
+                if let Some(start) = &self.start {
+                    conditions.push(format!("{} >= {}", quoted, literal(start)));
+                }
+                if let Some(end) = &self.end {
+                    conditions.push(format!("{} < {}", quoted, literal(end)));
+                }
+            }
+            DataType::Date32 | DataType::Date64 => {
+                // Dates are compared by day, a partial day at either end of the
+                // range includes the whole day
+                let literal = |time: &DateTime<Utc>| format!("date '{}'", time.date_naive());
+                let is_midnight = |time: &DateTime<Utc>| time.time() == NaiveTime::MIN;
+                if let Some(start) = &self.start {
+                    conditions.push(format!("{} >= {}", quoted, literal(start)));
+                }
+                if let Some(end) = &self.end {
+                    let op = if is_midnight(end) { "<" } else { "<=" };
+                    conditions.push(format!("{} {} {}", quoted, op, literal(end)));
+                }
+            }
+            data_type => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "Time ranges can only be applied to timestamp and date columns, \
+                         the column '{}' has type {}",
+                        column, data_type
+                    ),
+                })
+            }
+        }
+        if conditions.is_empty() {
+            conditions.push("true".to_string());
+        }
+        Ok(conditions.join(" AND "))
+    }
+}
+
+/// Options for controlling the execution of a query
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct QueryExecutionOptions {
+    /// The maximum number of rows that will be contained in a single
+    /// `RecordBatch` delivered by the query.
+    ///
+    /// Note: This is a maximum only.  The query may return smaller
+    /// batches, even in the middle of a query, to avoid forcing
+    /// memory copies due to concatenation.
+    ///
+    /// Note: Slicing an Arrow RecordBatch is a zero-copy operation
+    /// and so the performance penalty of reading smaller batches
+    /// is typically very small.
+    ///
+    /// By default, this is 1024
+    pub max_batch_length: u32,
+    /// The maximum number of batches read ahead of the consumer of the results
+    ///
+    /// Reads are paused once this many batches are waiting to be consumed, so a
+    /// slow consumer, such as a writer to a slow network connection, throttles
+    /// the reads instead of the results being buffered in memory.  Lower values
+    /// use less memory but may leave the disk or network idle.
+    ///
+    /// By default, this is chosen by Lance based on the number of CPUs.
+    pub batch_readahead: Option<usize>,
+    /// The maximum number of fragments, or data files, read at the same time
+    ///
+    /// By default, this is chosen by Lance based on the number of CPUs.
+    pub fragment_readahead: Option<usize>,
+}
+
+impl Default for QueryExecutionOptions {
+    fn default() -> Self {
+        Self {
+            max_batch_length: 1024,
+            batch_readahead: None,
+            fragment_readahead: None,
+        }
+    }
+}
+
+/// A trait for a query object that can be executed to get results
+///
+/// There are various kinds of queries but they all return results
+/// in the same way.
+pub trait ExecutableQuery {
     /// Execute the query with default options and return results
     ///
     /// See [`ExecutableQuery::execute_with_options`] for more details.
@@ -453,6 +1140,84 @@ pub trait ExecutableQuery {
         &self,
         options: QueryExecutionOptions,
     ) -> impl Future<Output = Result<SendableRecordBatchStream>> + Send;
+
+    /// Execute the query and return the results as a synchronous Arrow reader
+    ///
+    /// This is for code that is not async, or for libraries that consume a
+    /// standard [`arrow_array::RecordBatchReader`].  The query is run on a
+    /// runtime owned by LanceDB and each call to `next` blocks the calling
+    /// thread until the next batch is ready.
+    ///
+    /// This may be called from a thread of a multi-threaded tokio runtime, but
+    /// it blocks that thread, and it panics on a current thread runtime.  In
+    /// async code prefer [`ExecutableQuery::execute`].
+    ///
+    /// ```no_run
+    /// # use lancedb::query::ExecutableQuery;
+    /// # let table: lancedb::Table = todo!();
+    /// let reader = table.query().execute_blocking_reader().unwrap();
+    /// for batch in reader {
+    ///     println!("{} rows", batch.unwrap().num_rows());
+    /// }
+    /// ```
+    fn execute_blocking_reader(&self) -> Result<BlockingRecordBatchReader> {
+        let stream = block_on(self.execute())?;
+        Ok(BlockingRecordBatchReader::new(stream))
+    }
+
+    /// Execute the query and deserialize each result row into a `T`
+    ///
+    /// See [`crate::arrow::deserialize_batch`] for details on how rows are mapped
+    /// to `T`.  Extra columns (such as `_distance` in a vector search) are ignored
+    /// unless `T` has a matching field.
+    ///
+    /// ```no_run
+    /// # use futures::TryStreamExt;
+    /// # use lancedb::query::ExecutableQuery;
+    /// #[derive(serde::Deserialize)]
+    /// struct Item {
+    ///     id: i32,
+    ///     vector: Vec<f32>,
+    /// }
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// # let db = lancedb::connect("data/sample-lancedb").execute().await.unwrap();
+    /// # let table = db.open_table("my_table").execute().await.unwrap();
+    /// let items = table
+    ///     .query()
+    ///     .execute_into::<Item>()
+    ///     .await
+    ///     .unwrap()
+    ///     .try_collect::<Vec<_>>()
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    fn execute_into<T: DeserializeOwned + Send + 'static>(
+        &self,
+    ) -> impl Future<Output = Result<BoxStream<'static, Result<T>>>> + Send {
+        let results = self.execute();
+        async move {
+            let rows = results
+                .await?
+                .and_then(|batch| async move { deserialize_batch::<T>(&batch) })
+                .map_ok(|rows| stream::iter(rows.into_iter().map(Ok)))
+                .try_flatten();
+            Ok(rows.boxed())
+        }
+    }
+
+    /// Execute the query to completion and return the results with its metrics
+    ///
+    /// This is meant to debug slow queries: the time spent planning and
+    /// executing the query is measured and, for tables read by this process,
+    /// the rows scanned and the partitions of the index probed are reported.
+    /// See [`metrics::ExecutionMetrics`] for the details.  All of the results
+    /// are held in memory.
+    fn execute_with_metrics(
+        &self,
+    ) -> impl Future<Output = Result<(Vec<RecordBatch>, ExecutionMetrics)>> + Send {
+        metrics::collect_with_metrics(self.execute())
+    }
 }
 
 /// A builder for LanceDB queries.
@@ -473,10 +1238,41 @@ pub struct Query {
 
     /// limit the number of rows to return.
     pub(crate) limit: Option<usize>,
+    /// The number of rows of a scan to skip.
+    pub(crate) offset: Option<usize>,
+    /// Only scan the rows after this row id.
+    pub(crate) after: Option<u64>,
+    /// The columns the rows of a scan are sorted by.
+    pub(crate) order_by: Vec<(String, Order)>,
+    /// Only return a random sample of the rows, and the seed of the sample.
+    pub(crate) sample: Option<(Sample, u64)>,
     /// Apply filter to the returned rows.
     pub(crate) filter: Option<String>,
+    /// Values bound to the parameters of the filter.
+    pub(crate) filter_params: Vec<(String, FilterValue)>,
     /// Select column projection.
     pub(crate) select: Select,
+    /// Columns computed from SQL expressions, added to the projection.
+    pub(crate) computed_columns: Vec<(String, String)>,
+    /// Leave the blob columns out of a [`Select::All`] projection.
+    pub(crate) exclude_blobs: bool,
+    /// Hints for the query planner.
+    pub(crate) hints: Vec<Hint>,
+    /// Include the `_rowid` column in the results.
+    pub(crate) with_row_id: bool,
+    /// Include the `_rowaddr` column in the results.
+    pub(crate) with_row_address: bool,
+    /// Time ranges that the rows must fall within.
+    pub(crate) time_ranges: Vec<(String, TimeRange)>,
+    /// Search the soft deleted rows instead of the table.
+    pub(crate) only_deleted: bool,
+    /// Text to search for with the full text search index.
+    #[cfg(feature = "fts")]
+    pub(crate) full_text_search: Option<String>,
+    /// Abort the query once it has run for this long.
+    pub(crate) timeout: Option<Duration>,
+    /// Abort the query once this token is cancelled.
+    pub(crate) cancellation_token: Option<CancellationToken>,
 }
 
 impl Query {
@@ -484,8 +1280,24 @@ impl Query {
         Self {
             parent,
             limit: None,
+            offset: None,
+            after: None,
+            order_by: Vec::new(),
+            sample: None,
             filter: None,
+            filter_params: Vec::new(),
             select: Select::All,
+            computed_columns: Vec::new(),
+            exclude_blobs: false,
+            hints: Vec::new(),
+            with_row_id: false,
+            with_row_address: false,
+            time_ranges: Vec::new(),
+            only_deleted: false,
+            #[cfg(feature = "fts")]
+            full_text_search: None,
+            timeout: None,
+            cancellation_token: None,
         }
     }
 
@@ -496,6 +1308,66 @@ impl Query {
         VectorQuery::new(self)
     }
 
+    /// The filter of the query, with its parameters bound, combined with its time ranges
+    pub(crate) fn resolved_filter(&self, schema: &arrow_schema::Schema) -> Result<Option<String>> {
+        let filter = match &self.filter {
+            Some(filter) => {
+                let filter = self
+                    .filter_params
+                    .iter()
+                    .fold(Filter::parse(filter)?, |filter, (name, value)| {
+                        filter.bind(name.clone(), value.clone())
+                    });
+                Some(filter.to_sql()?)
+            }
+            None if !self.filter_params.is_empty() => {
+                return Err(Error::InvalidInput {
+                    message: "values were bound to parameters but the query has no filter"
+                        .to_string(),
+                })
+            }
+            None => None,
+        };
+        let mut filters = self
+            .time_ranges
+            .iter()
+            .map(|(column, range)| range.to_filter(schema, column))
+            .collect::<Result<Vec<_>>>()?;
+        if filters.is_empty() {
+            return Ok(filter);
+        }
+        if let Some(filter) = filter {
+            filters.insert(0, format!("({})", filter));
+        }
+        Ok(Some(filters.join(" AND ")))
+    }
+
+    /// The projection of the query, with its computed columns added
+    pub(crate) fn resolved_select(&self, schema: &arrow_schema::Schema) -> Select {
+        let select = match &self.select {
+            Select::All if self.exclude_blobs => Select::Columns(blob::non_blob_columns(schema)),
+            select => select.clone(),
+        };
+        if self.computed_columns.is_empty() {
+            return select;
+        }
+        let mut columns = match &select {
+            Select::All => schema
+                .fields()
+                .iter()
+                .map(|field| (field.name().clone(), format!("`{}`", field.name())))
+                .collect::<Vec<_>>(),
+            Select::Columns(columns) => columns
+                .iter()
+                .map(|column| (column.clone(), format!("`{}`", column)))
+                .collect(),
+            Select::Dynamic(columns) => columns.clone(),
+        };
+        columns.retain(|(name, _)| !self.computed_columns.iter().any(|(c, _)| c == name));
+        columns.extend(self.computed_columns.iter().cloned());
+        Select::Dynamic(columns)
+    }
+
     /// Find the nearest vectors to the given query vector.
     ///
     /// This converts the query from a plain query to a vector query.
@@ -505,7 +1377,9 @@ impl Query {
     /// then an error will be returned.
     ///
     /// By default, there is no embedding model, and the input should be
-    /// vector/slice of floats.
+    /// vector/slice of floats.  If the vector column has an embedding function
+    /// (see [`crate::embeddings`]) then the input may also be a string, which
+    /// is embedded with that function when the query is executed.
     ///
     /// If there is only one vector column (a column whose data type is a
     /// fixed size list of floats) then the column does not need to be specified.
@@ -536,6 +1410,171 @@ impl Query {
         vector_query.query_vector = Some(query_vector);
         Ok(vector_query)
     }
+
+    /// Find the nearest vectors to each of the given query vectors
+    ///
+    /// This is the same as running [`Self::nearest_to`] once per query vector,
+    /// with the same options, but in one call.  The searches share the overhead
+    /// of a query, for example they all read the same version of the table, and
+    /// run concurrently.  This is useful when many small searches are needed
+    /// at once, such as when computing recommendations for many users.
+    ///
+    /// The results of all of the searches are returned in one stream, ordered by
+    /// query vector.  Each result has an additional `query_index` column (see
+    /// [`batch::QUERY_INDEX_COLUMN`]) with the position of its query vector.  The
+    /// limit of the query applies to each search.
+    ///
+    /// ```ignore
+    /// table
+    ///     .query()
+    ///     .nearest_to_batch(&[vec![1.0, 2.0], vec![3.0, 4.0]])?
+    ///     .limit(5)
+    ///     .execute()
+    ///     .await?;
+    /// ```
+    pub fn nearest_to_batch<V: IntoQueryVector>(
+        self,
+        vectors: impl IntoIterator<Item = V>,
+    ) -> Result<VectorQuery> {
+        let batch_vectors = vectors
+            .into_iter()
+            .map(|vector| vector.to_query_vector(&DataType::Float32, "default"))
+            .collect::<Result<Vec<_>>>()?;
+        let Some(first) = batch_vectors.first().cloned() else {
+            return Err(Error::InvalidInput {
+                message: "a batch search needs at least one query vector".to_string(),
+            });
+        };
+        let mut vector_query = self.into_vector();
+        vector_query.query_vector = Some(first);
+        vector_query.batch_vectors = batch_vectors;
+        Ok(vector_query)
+    }
+
+    /// Search for rows matching the given text using the full text search index
+    ///
+    /// This converts the query into a full text search.  A full text search index
+    /// must first be created with [`crate::index::Index::Fts`].  The text is parsed
+    /// as a query over all of the columns in the index, for example:
+    ///
+    /// ```ignore
+    /// puppy
+    /// "exact phrase"
+    /// title:puppy AND body:kitten
+    /// ```
+    ///
+    /// Results are ordered by their BM25 relevance and the score is returned in an
+    /// additional `_score` column.  If no limit is set then the 10 most relevant
+    /// rows are returned.  Any filter is applied before the limit.
+    ///
+    /// Rows added after the index was created will not be found until the index
+    /// is recreated.
+    #[cfg(feature = "fts")]
+    pub fn full_text_search(mut self, text: impl Into<String>) -> Self {
+        self.full_text_search = Some(text.into());
+        self
+    }
+
+    /// Skip the first `offset` rows of the scan
+    ///
+    /// Combined with [`QueryBase::limit`] this returns a page of the rows.  The
+    /// skipped rows are still read, see [`Self::after`] to page through large
+    /// tables.  Only scans can be paginated, not vector or full text searches.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Only return the rows after the row with the id `row_id`
+    ///
+    /// The rows of a scan are returned in the order of their ids, so passing
+    /// the row id of the last row of a page (see [`QueryBase::with_row_id`])
+    /// returns the next page.  Unlike [`Self::offset`] the rows of the previous
+    /// pages are not read again.  See [`crate::table::paginate`].
+    pub fn after(mut self, row_id: u64) -> Self {
+        self.after = Some(row_id);
+        self
+    }
+
+    /// Sort the rows of the scan by the given columns
+    ///
+    /// The rows are sorted by the first column, then rows with equal values by
+    /// the second column and so on.  Null values are last in both directions.
+    /// The limit and offset are applied to the sorted rows, so the 10 most
+    /// recent rows are:
+    ///
+    /// ```ignore
+    /// table.query().order_by([("created_at", Order::Desc)]).limit(10)
+    /// ```
+    ///
+    /// The sort is done in memory, holding the rows up to the limit (or all of
+    /// the rows without a limit).  Only scans can be ordered, not vector or full
+    /// text searches, which are ordered by relevance.
+    pub fn order_by(
+        mut self,
+        columns: impl IntoIterator<Item = (impl Into<String>, Order)>,
+    ) -> Self {
+        self.order_by = columns
+            .into_iter()
+            .map(|(column, order)| (column.into(), order))
+            .collect();
+        self
+    }
+
+    /// Only return a random sample of the rows of the scan
+    ///
+    /// The sample is either a fraction of the rows, `sample(0.1, seed)`, or a
+    /// number of rows, `sample(1000usize, seed)`, of the rows matching the filter.
+    /// The same seed returns the same sample of the same version of the table,
+    /// so a training set can be drawn again.  Without a filter only the sampled
+    /// rows are read.  See [`crate::table::sample`].
+    ///
+    /// Only scans can be sampled, not vector or full text searches, and a
+    /// sample cannot be ordered or paginated.
+    pub fn sample(mut self, sample: impl Into<Sample>, seed: u64) -> Self {
+        self.sample = Some((sample.into(), seed));
+        self
+    }
+
+    /// Describe the plan that will be used to execute the query
+    ///
+    /// This can be used to check whether a filter will use a scalar index.  If
+    /// it does then the plan will contain a `ScalarIndexQuery` step.  If `verbose`
+    /// is true then the plan will include additional details.
+    pub async fn explain_plan(&self, verbose: bool) -> Result<String> {
+        self.parent
+            .clone()
+            .explain_plan(&self.clone().into_vector(), verbose)
+            .await
+    }
+
+    /// Join the results with the rows of another table, or other source, by key
+    ///
+    /// `key` is the column of the results that is looked up in the source.  For
+    /// each batch of results the rows of the source with the same keys are read
+    /// and their columns are added to the batch.  Results with no matching row
+    /// are kept, with nulls in the added columns.  See [`EnrichedQuery`] for how
+    /// to choose the key column of the source and the columns that are added.
+    ///
+    /// This is intended for the top-k results of a search, for example to fetch
+    /// the metadata of the nearest documents from a table that is kept
+    /// separately from the embeddings:
+    ///
+    /// ```ignore
+    /// table
+    ///     .query()
+    ///     .nearest_to(&[1.0, 2.0])?
+    ///     .limit(10)
+    ///     .enrich(documents, "doc_id")
+    ///     .columns(&["title", "url"])
+    /// ```
+    pub fn enrich(
+        self,
+        source: impl Into<EnrichSource>,
+        key: impl Into<String>,
+    ) -> EnrichedQuery<Self> {
+        EnrichedQuery::new(self, source.into(), key.into())
+    }
 }
 
 impl HasQuery for Query {
@@ -549,12 +1588,37 @@ impl ExecutableQuery for Query {
         &self,
         options: QueryExecutionOptions,
     ) -> Result<SendableRecordBatchStream> {
-        Ok(SendableRecordBatchStream::from(
-            self.parent.clone().plain_query(self, options).await?,
-        ))
+        timeout::run_with_deadline(self, self.parent.clone().plain_query(self, options)).await
+    }
+
+    async fn execute_with_metrics(&self) -> Result<(Vec<RecordBatch>, ExecutionMetrics)> {
+        let (batches, mut metrics) = metrics::collect_with_metrics(self.execute()).await?;
+        metrics.scan = self
+            .parent
+            .clone()
+            .scan_stats(&self.clone().into_vector())
+            .await?;
+        Ok((batches, metrics))
     }
 }
 
+/// How a vector search handles the rows not covered by the vector index, see
+/// [`VectorQuery::unindexed`]
+///
+/// These are the rows added since the index was last created or optimized, see
+/// [`crate::Table::optimize_indices`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnindexedPolicy {
+    /// Search the rows with a flat search and merge their results with the
+    /// results of the index
+    #[default]
+    FlatScan,
+    /// Drop the results from the rows, see [`VectorQuery::fast_search`]
+    Skip,
+    /// Fail with [`Error::UnindexedRows`] if there are any such rows
+    Error,
+}
+
 /// A builder for vector searches
 ///
 /// This builder contains methods specific to vector searches.
@@ -572,13 +1636,28 @@ pub struct VectorQuery {
     pub(crate) column: Option<String>,
     // IVF PQ - ANN search.
     pub(crate) query_vector: Option<Arc<dyn Array>>,
+    // The query vectors of a batch search, see [`Query::nearest_to_batch`].
+    pub(crate) batch_vectors: Vec<Arc<dyn Array>>,
     pub(crate) nprobes: usize,
     pub(crate) refine_factor: Option<u32>,
+    /// Add the exact distances of the results, see [`VectorQuery::refine_with_exact`]
+    pub(crate) refine_with_exact: bool,
     pub(crate) distance_type: Option<DistanceType>,
+    // IVF HNSW - graph search.
+    pub(crate) ef: Option<usize>,
     /// Default is true. Set to false to enforce a brute force search.
     pub(crate) use_index: bool,
     /// Apply filter before ANN search/
     pub(crate) prefilter: bool,
+    /// How the rows not covered by the vector index are searched
+    pub(crate) unindexed: UnindexedPolicy,
+    /// Only return results with a distance in [lower_bound, upper_bound)
+    pub(crate) lower_bound: Option<f32>,
+    pub(crate) upper_bound: Option<f32>,
+    /// Only return the closest results of each value of this column
+    pub(crate) group_by: Option<String>,
+    /// The number of results of each value of `group_by`
+    pub(crate) k_per_group: usize,
 }
 
 impl VectorQuery {
@@ -587,11 +1666,19 @@ impl VectorQuery {
             base,
             column: None,
             query_vector: None,
+            batch_vectors: Vec::new(),
             nprobes: 20,
             refine_factor: None,
+            refine_with_exact: false,
             distance_type: None,
+            ef: None,
             use_index: true,
             prefilter: true,
+            unindexed: UnindexedPolicy::default(),
+            lower_bound: None,
+            upper_bound: None,
+            group_by: None,
+            k_per_group: 1,
         }
     }
 
@@ -664,6 +1751,38 @@ impl VectorQuery {
         self
     }
 
+    /// Whether to add the exact distances of the results to the query vector
+    ///
+    /// The distances of the `_distance` column are approximate when the search
+    /// uses a vector index, see [`Self::refine_factor`].  With this, the raw
+    /// vectors of the results are read once the search is done and their exact
+    /// distances are returned in the `_exact_distance` column, next to the
+    /// approximate ones.  The order of the results is not changed.  This is
+    /// meant to evaluate the error and the recall of an index, see
+    /// [`crate::table::rescore`].
+    ///
+    /// The default is false.  Flat searches with [`DistanceType::Hamming`] or
+    /// [`DistanceType::MaxSim`] already return exact distances and fail with this.
+    pub fn refine_with_exact(mut self, refine_with_exact: bool) -> Self {
+        self.refine_with_exact = refine_with_exact;
+        self
+    }
+
+    /// The number of candidates kept while searching the graph of an HNSW index
+    ///
+    /// This argument is only used when the vector column has an IVF HNSW index.
+    /// If there is no index, or the index is IVF PQ, then this value is ignored.
+    ///
+    /// The search keeps the `ef` closest nodes it has found so far and stops
+    /// once none of their neighbors are closer.  Increasing this value will
+    /// increase the recall of your query but will also increase its latency.
+    /// The value should be at least the limit of the query.  By default lance
+    /// uses a value based on the limit.
+    pub fn ef(mut self, ef: usize) -> Self {
+        self.ef = Some(ef);
+        self
+    }
+
     /// Set the distance metric to use
     ///
     /// When performing a vector search we try and find the "nearest" vectors according
@@ -675,12 +1794,76 @@ impl VectorQuery {
     /// type used to train the vector index.  If this is not done then the results will be
     /// invalid.
     ///
+    /// [`DistanceType::MaxSim`] searches a multivector column (a list of fixed size lists
+    /// of floats) and always scans the whole column.  It is used by default when there
+    /// are several query vectors, e.g. a `Vec<Vec<f32>>`.
+    ///
+    /// [`DistanceType::Hamming`] searches a binary vector column (a fixed size list of
+    /// `uint8`) and always scans the whole column.  The query vector holds the packed
+    /// bytes, e.g. a `&[u8]`.  The vector column must be given with [`Self::column`].
+    ///
     /// By default [`DistanceType::L2`] is used.
     pub fn distance_type(mut self, distance_type: DistanceType) -> Self {
         self.distance_type = Some(distance_type);
         self
     }
 
+    /// Only return results whose distance to the query vector is in a range
+    ///
+    /// Results closer than `lower_bound` or at least as far as `upper_bound` are
+    /// dropped.  Either bound can be omitted, e.g. `distance_range(None, Some(0.1))`
+    /// returns the results closer than 0.1, which can be used to check for
+    /// near-duplicates.
+    ///
+    /// The range does not replace the limit of the query: at most `limit` results
+    /// are returned.  Searches with [`DistanceType::Hamming`] or [`DistanceType::MaxSim`]
+    /// return the `limit` closest rows in the range.  Other searches find the `limit`
+    /// closest rows first and then drop those outside of the range, so they can
+    /// return fewer results even if more rows are in the range.
+    pub fn distance_range(mut self, lower_bound: Option<f32>, upper_bound: Option<f32>) -> Self {
+        self.lower_bound = lower_bound;
+        self.upper_bound = upper_bound;
+        self
+    }
+
+    /// Only return the closest result of each value of `column`
+    ///
+    /// When a document is stored as several chunks, each with its own vector,
+    /// the closest chunks often belong to the same few documents.  With
+    /// `distinct_on("document_id")` the search returns the best chunk of each
+    /// of the `limit` closest documents instead.  This is the same as
+    /// `group_by(column).k_per_group(1)`, see [`Self::group_by`].
+    pub fn distinct_on(self, column: impl Into<String>) -> Self {
+        self.group_by(column).k_per_group(1)
+    }
+
+    /// Group the results by their value in `column`
+    ///
+    /// The search returns the [`Self::k_per_group`] closest rows of each group,
+    /// for the `limit` groups whose closest row is closest to the query vector,
+    /// e.g. the 3 best products of each of the 10 best matching categories of a
+    /// faceted search.  The results are ordered by their distance, not by
+    /// group.  Rows with a null in the column form one more group.  The column
+    /// does not have to be selected.
+    ///
+    /// The search is run with a larger limit to find enough rows of each
+    /// group, and again with a larger one if it found too few, so it is slower
+    /// than a plain search when a few groups have many rows close to the query
+    /// vector.  It is still a single query instead of a filtered search per
+    /// group.
+    pub fn group_by(mut self, column: impl Into<String>) -> Self {
+        self.group_by = Some(column.into());
+        self
+    }
+
+    /// The number of results of each group, see [`Self::group_by`]
+    ///
+    /// The default is 1.
+    pub fn k_per_group(mut self, k: usize) -> Self {
+        self.k_per_group = k;
+        self
+    }
+
     /// If this is called then filtering will happen after the vector search instead of
     /// before.
     ///
@@ -699,11 +1882,42 @@ impl VectorQuery {
     /// Post filtering happens during the "refine stage" (described in more detail in
     /// [`Self::refine_factor`]).  This means that setting a higher refine factor can often
     /// help restore some of the results lost by post filtering.
+    ///
+    /// This is the same as `prefilter(false)`, see [`Self::prefilter`].
     pub fn postfilter(mut self) -> Self {
         self.prefilter = false;
         self
     }
 
+    /// Whether the filter is applied before (the default) or after the vector search
+    ///
+    /// The tradeoff between the two is one of recall against latency:
+    ///
+    /// - With a prefilter the rows that do not match the filter are excluded before
+    ///   the index is probed, so the search returns `limit` results whenever at
+    ///   least `limit` rows match, however selective the filter is.  Evaluating the
+    ///   filter adds latency, which a scalar index on the filtered columns reduces.
+    /// - With a postfilter the index is probed first and the filter is only
+    ///   evaluated on the nearest rows it found.  This is faster with a complex
+    ///   filter, or one that matches most rows, but a selective filter can leave
+    ///   fewer than `limit` results, or none.  A higher [`Self::refine_factor`]
+    ///   or [`Self::nprobes`] finds more candidates and recovers some of them.
+    ///
+    /// Searches that do not use a vector index, such as multivector searches and
+    /// searches with [`DistanceType::Hamming`], compute the distance of every
+    /// row and always apply the filter first.
+    pub fn prefilter(mut self, prefilter: bool) -> Self {
+        self.prefilter = prefilter;
+        self
+    }
+
+    /// Describe the plan that will be used to execute the query
+    ///
+    /// See [`Query::explain_plan`] for more details.
+    pub async fn explain_plan(&self, verbose: bool) -> Result<String> {
+        self.base.parent.clone().explain_plan(self, verbose).await
+    }
+
     /// If this is called then any vector index is skipped
     ///
     /// An exhaustive (flat) search will be performed.  The query vector will
@@ -715,6 +1929,80 @@ impl VectorQuery {
         self.use_index = false;
         self
     }
+
+    /// If this is called then only the rows covered by the vector index are searched
+    ///
+    /// By default the rows added since the vector index was last created or
+    /// optimized (see [`crate::table::OptimizeAction::Index`]) are searched with a
+    /// flat search and their results are merged with the results of the index.
+    /// With a fast search the results from these rows are dropped instead, so
+    /// fewer than `limit` results may be returned.  If the vector column has no
+    /// index then nothing is returned.
+    ///
+    /// This is the same as [`Self::unindexed`] with [`UnindexedPolicy::Skip`].
+    /// It has no effect if [`Self::bypass_vector_index`] is called.
+    pub fn fast_search(self) -> Self {
+        self.unindexed(UnindexedPolicy::Skip)
+    }
+
+    /// How the rows not covered by the vector index are searched
+    ///
+    /// The default, [`UnindexedPolicy::FlatScan`], gives the exact behavior of
+    /// the index at the cost of a latency growing with the number of rows added
+    /// since the index was last optimized.  Latency critical services can skip
+    /// these rows, or fail and optimize the index, instead.
+    ///
+    /// This has no effect if [`Self::bypass_vector_index`] is called.
+    pub fn unindexed(mut self, policy: UnindexedPolicy) -> Self {
+        self.unindexed = policy;
+        self
+    }
+
+    /// Combine this vector search with a full text search for `text`
+    ///
+    /// The vector search and full text search are run concurrently and their
+    /// results are merged by a [`Reranker`].  By default this is an
+    /// [`RRFReranker`], which can be changed with [`HybridQuery::rerank`].
+    ///
+    /// Both searches use the same filter and selection and each returns up to
+    /// `limit` rows.  The merged results are ordered by the relevance score,
+    /// which is returned in an additional `_relevance_score` column, and the
+    /// `limit` most relevant rows are returned.
+    ///
+    /// A full text search index must first be created with [`crate::index::Index::Fts`].
+    #[cfg(feature = "fts")]
+    pub fn hybrid(self, text: impl Into<String>) -> HybridQuery {
+        HybridQuery {
+            vector: self,
+            text: text.into(),
+            reranker: Arc::new(RRFReranker::default()),
+        }
+    }
+
+    /// Join the results with the rows of another table, or other source, by key
+    ///
+    /// See [`Query::enrich`]
+    pub fn enrich(
+        self,
+        source: impl Into<EnrichSource>,
+        key: impl Into<String>,
+    ) -> EnrichedQuery<Self> {
+        EnrichedQuery::new(self, source.into(), key.into())
+    }
+
+    /// Run this search on each of `shards`, instead of this table, and merge the results
+    ///
+    /// The shards are searched concurrently and the top-k results of all of the
+    /// shards are merged into the overall top-k by distance.  The shards must
+    /// have the same schema, they can be local or remote tables.  Use
+    /// [`ShardedVectorQuery::execute_with_stats`] to get the latency of each shard.
+    /// See [`crate::query::scatter`] for more details.
+    ///
+    /// The options of the search, such as the filter or `nprobes`, apply to each
+    /// of the shards.
+    pub fn scatter(self, shards: Vec<Table>) -> ShardedVectorQuery {
+        ShardedVectorQuery::new(self, shards)
+    }
 }
 
 impl ExecutableQuery for VectorQuery {
@@ -722,9 +2010,17 @@ impl ExecutableQuery for VectorQuery {
         &self,
         options: QueryExecutionOptions,
     ) -> Result<SendableRecordBatchStream> {
-        Ok(SendableRecordBatchStream::from(
-            self.base.parent.clone().vector_query(self, options).await?,
-        ))
+        timeout::run_with_deadline(
+            &self.base,
+            self.base.parent.clone().vector_query(self, options),
+        )
+        .await
+    }
+
+    async fn execute_with_metrics(&self) -> Result<(Vec<RecordBatch>, ExecutionMetrics)> {
+        let (batches, mut metrics) = metrics::collect_with_metrics(self.execute()).await?;
+        metrics.scan = self.base.parent.clone().scan_stats(self).await?;
+        Ok((batches, metrics))
     }
 }
 
@@ -734,6 +2030,102 @@ impl HasQuery for VectorQuery {
     }
 }
 
+/// A builder for hybrid searches, which combine a vector search and a full
+/// text search
+///
+/// See [`VectorQuery::hybrid`] for more details.
+#[cfg(feature = "fts")]
+#[derive(Debug, Clone)]
+pub struct HybridQuery {
+    pub(crate) vector: VectorQuery,
+    pub(crate) text: String,
+    pub(crate) reranker: Arc<dyn Reranker>,
+}
+
+#[cfg(feature = "fts")]
+impl HybridQuery {
+    /// Set the reranker used to merge the vector and full text search results
+    pub fn rerank(mut self, reranker: Arc<dyn Reranker>) -> Self {
+        self.reranker = reranker;
+        self
+    }
+
+    /// Join the results with the rows of another table, or other source, by key
+    ///
+    /// See [`Query::enrich`]
+    pub fn enrich(
+        self,
+        source: impl Into<EnrichSource>,
+        key: impl Into<String>,
+    ) -> EnrichedQuery<Self> {
+        EnrichedQuery::new(self, source.into(), key.into())
+    }
+}
+
+#[cfg(feature = "fts")]
+impl ExecutableQuery for HybridQuery {
+    async fn execute_with_options(
+        &self,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        if !self.vector.batch_vectors.is_empty() {
+            return Err(Error::NotSupported {
+                message: "a hybrid search cannot have several query vectors".to_string(),
+            });
+        }
+        let mut vector_query = self.vector.clone();
+        vector_query.base.with_row_id = true;
+        let mut fts_query = vector_query.base.clone();
+        fts_query.full_text_search = Some(self.text.clone());
+
+        let parent = vector_query.base.parent.clone();
+        let (vector_results, fts_results) = futures::try_join!(
+            async {
+                parent
+                    .clone()
+                    .vector_query(&vector_query, options.clone())
+                    .await?
+                    .collect_concat()
+                    .await
+            },
+            async {
+                parent
+                    .clone()
+                    .plain_query(&fts_query, options.clone())
+                    .await?
+                    .collect_concat()
+                    .await
+            },
+        )?;
+
+        let results = self
+            .reranker
+            .rerank_hybrid(&self.text, vector_results, fts_results)?;
+        let limit = self.vector.base.limit.unwrap_or(DEFAULT_TOP_K);
+        let mut results = results.slice(0, limit.min(results.num_rows()));
+        if let Some((idx, _)) = results.schema().column_with_name(ROW_ID_COLUMN) {
+            results.remove_column(idx);
+        }
+
+        let batch_size = (options.max_batch_length as usize).max(1);
+        let batches = (0..results.num_rows())
+            .step_by(batch_size)
+            .map(|offset| Ok(results.slice(offset, batch_size.min(results.num_rows() - offset))))
+            .collect::<Vec<_>>();
+        Ok(Box::pin(SimpleRecordBatchStream {
+            schema: results.schema(),
+            stream: stream::iter(batches),
+        }))
+    }
+}
+
+#[cfg(feature = "fts")]
+impl HasQuery for HybridQuery {
+    fn mut_query(&mut self) -> &mut Query {
+        &mut self.vector.base
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
@@ -839,6 +2231,43 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_prefilter() {
+        let tmp_dir = tempdir().unwrap();
+        let conn = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = conn
+            .create_table("my_table", Box::new(make_non_empty_batches()))
+            .execute()
+            .await
+            .unwrap();
+
+        let query = table
+            .query()
+            .limit(10)
+            .only_if("id % 2 == 0")
+            .nearest_to(&[0.1; 4])
+            .unwrap();
+        assert!(query.prefilter);
+        let count_rows = |query: VectorQuery| async move {
+            query
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap()
+                .iter()
+                .map(|batch| batch.num_rows())
+                .sum::<usize>()
+        };
+        assert_eq!(count_rows(query.clone()).await, 10);
+        assert!(count_rows(query.clone().prefilter(false)).await < 10);
+        assert_eq!(count_rows(query.postfilter().prefilter(true)).await, 10);
+    }
+
     #[tokio::test]
     async fn test_select_with_transform() {
         // TODO: Switch back to memory://foo after https://github.com/lancedb/lancedb/issues/1051
@@ -881,6 +2310,138 @@ mod tests {
         });
     }
 
+    #[tokio::test]
+    async fn test_select_with_exprs() {
+        let tmp_dir = tempdir().unwrap();
+        let dataset_path = tmp_dir.path().join("test.lance");
+        let uri = dataset_path.to_str().unwrap();
+
+        let batches = make_non_empty_batches();
+        let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("my_table", Box::new(batches))
+            .execute()
+            .await
+            .unwrap();
+        let num_columns = table.schema().await.unwrap().fields().len();
+
+        // The computed columns are added to all of the columns by default
+        let batch = table
+            .query()
+            .limit(10)
+            .select_with_exprs(&[("id2", "id * 2")])
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(batch.num_columns(), num_columns + 1);
+        let id: &Int32Array = batch.column_by_name("id").unwrap().as_primitive();
+        let id2: &Int32Array = batch.column(num_columns).as_primitive();
+        assert_eq!(batch.schema().field(num_columns).name(), "id2");
+        id.iter().zip(id2.iter()).for_each(|(id, id2)| {
+            assert_eq!(id.unwrap() * 2, id2.unwrap());
+        });
+
+        // ... or to the selected columns, replacing those with the same name
+        let batch = table
+            .query()
+            .limit(10)
+            .select(Select::columns(&["id"]))
+            .select_with_exprs(&[("id", "id + 1"), ("one", "1")])
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(batch.num_columns(), 2);
+        let id: &Int32Array = batch.column_by_name("id").unwrap().as_primitive();
+        assert_eq!(id.value(0), 1);
+    }
+
+    #[test]
+    fn test_query_vector_arithmetic() {
+        let centroid = QueryVector::centroid(&[vec![1.0, 0.0], vec![0.0, 1.0]]).unwrap();
+        assert_eq!(centroid.values(), &[0.5, 0.5]);
+
+        let weighted =
+            QueryVector::weighted(&[(vec![1.0, 2.0], 2.0), (vec![1.0, 1.0], -1.0)]).unwrap();
+        assert_eq!(weighted.values(), &[1.0, 3.0]);
+
+        let vector = centroid.subtract(&[0.5, 0.0], 1.0).unwrap().scale(2.0);
+        assert_eq!(vector.values(), &[0.0, 1.0]);
+        assert_eq!(
+            QueryVector::new(vec![3.0, 4.0]).normalize().values(),
+            &[0.6, 0.8]
+        );
+
+        assert!(QueryVector::centroid(&[vec![1.0], vec![1.0, 2.0]]).is_err());
+        assert!(QueryVector::centroid(&[] as &[Vec<f32>]).is_err());
+
+        let schema = ArrowSchema::new(vec![ArrowField::new(
+            "vector",
+            DataType::FixedSizeList(
+                Arc::new(ArrowField::new("item", DataType::Float32, true)),
+                2,
+            ),
+            false,
+        )]);
+        assert!(vector.validate(&schema, None).is_ok());
+        assert!(vector.validate(&schema, Some("vector")).is_ok());
+        assert!(vector.validate(&schema, Some("missing")).is_err());
+        assert!(QueryVector::new(vec![1.0; 3])
+            .validate(&schema, Some("vector"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_time_range_filter() {
+        use arrow_schema::TimeUnit;
+
+        let schema = ArrowSchema::new(vec![
+            ArrowField::new(
+                "utc",
+                DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+                false,
+            ),
+            ArrowField::new(
+                "local",
+                DataType::Timestamp(TimeUnit::Microsecond, Some("+05:00".into())),
+                false,
+            ),
+            ArrowField::new("day", DataType::Date32, false),
+            ArrowField::new("id", DataType::Int32, false),
+        ]);
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2024, 1, 2, 12, 0, 0).unwrap();
+        let range = TimeRange::new(start, end);
+
+        assert_eq!(
+            range.to_filter(&schema, "utc").unwrap(),
+            "`utc` >= timestamp '2024-01-01 00:00:00' AND `utc` < timestamp '2024-01-02 12:00:00'"
+        );
+        assert_eq!(
+            range.to_filter(&schema, "local").unwrap(),
+            "`local` >= timestamp '2024-01-01 05:00:00' AND `local` < timestamp '2024-01-02 17:00:00'"
+        );
+        assert_eq!(
+            range.to_filter(&schema, "day").unwrap(),
+            "`day` >= date '2024-01-01' AND `day` <= date '2024-01-02'"
+        );
+        assert_eq!(
+            TimeRange::before(start).to_filter(&schema, "day").unwrap(),
+            "`day` < date '2024-01-01'"
+        );
+        assert!(range.to_filter(&schema, "id").is_err());
+        assert!(range.to_filter(&schema, "missing").is_err());
+    }
+
     #[tokio::test]
     async fn test_execute_no_vector() {
         // TODO: Switch back to memory://foo after https://github.com/lancedb/lancedb/issues/1051
@@ -911,7 +2472,23 @@ mod tests {
 
         // Reject bad filter
         let result = table.query().only_if("id = 0 AND").execute().await;
-        assert!(result.is_err());
+        assert!(matches!(
+            result,
+            Err(Error::InvalidFilter { column: 11, .. })
+        ));
+
+        // Bind parameters
+        let batches = table
+            .query()
+            .only_if("id < $max")
+            .bind("max", 3)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
     }
 
     fn make_non_empty_batches() -> impl RecordBatchReader + Send + 'static {
@@ -954,6 +2531,119 @@ mod tests {
             .unwrap()
     }
 
+    #[tokio::test]
+    async fn test_execute_pins_version() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+        let version = table.version().await.unwrap();
+
+        let mut results = table
+            .query()
+            .execute_with_options(QueryExecutionOptions {
+                max_batch_length: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(results.version(), Some(version));
+        let mut num_rows = results.next().await.unwrap().unwrap().num_rows();
+
+        // Rows committed while the results are read are not part of them
+        table
+            .add(Box::new(make_non_empty_batches()))
+            .execute()
+            .await
+            .unwrap();
+        while let Some(batch) = results.next().await {
+            num_rows += batch.unwrap().num_rows();
+        }
+        assert_eq!(num_rows, 512);
+        assert_eq!(table.count_rows(None).await.unwrap(), 1024);
+    }
+
+    #[tokio::test]
+    async fn test_nearest_to_batch() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+        let vectors = vec![vec![0.1; 4], vec![0.5; 4], vec![0.9; 4]];
+
+        let results = table
+            .query()
+            .limit(5)
+            .nearest_to_batch(&vectors)
+            .unwrap()
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(results.version(), Some(table.version().await.unwrap()));
+        let schema = results.schema();
+        let batches = results.try_collect::<Vec<_>>().await.unwrap();
+        let results = arrow::compute::concat_batches(&schema, &batches).unwrap();
+        assert_eq!(results.num_rows(), 15);
+        let query_index = results[batch::QUERY_INDEX_COLUMN]
+            .as_primitive::<arrow_array::types::UInt32Type>()
+            .values()
+            .to_vec();
+        let expected = (0..3).flat_map(|i| vec![i; 5]).collect::<Vec<u32>>();
+        assert_eq!(query_index, expected);
+
+        // Each search returns the results of a search with its vector alone
+        let single = table
+            .query()
+            .limit(5)
+            .nearest_to(vectors[1].as_slice())
+            .unwrap()
+            .execute()
+            .await
+            .unwrap();
+        let schema = single.schema();
+        let batches = single.try_collect::<Vec<_>>().await.unwrap();
+        let single = arrow::compute::concat_batches(&schema, &batches).unwrap();
+        assert_eq!(results["id"].slice(5, 5).as_ref(), single["id"].as_ref());
+
+        let empty: Vec<Vec<f32>> = Vec::new();
+        assert!(table.query().nearest_to_batch(empty).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_metrics() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+
+        let (batches, metrics) = table
+            .query()
+            .limit(7)
+            .nearest_to(&[0.5; 4])
+            .unwrap()
+            .execute_with_metrics()
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 7);
+        assert_eq!(metrics.rows_returned, 7);
+        assert_eq!(metrics.batches_returned, batches.len() as u64);
+        assert!(metrics.bytes_returned > 0);
+        // Without an index every vector is compared with the query vector
+        assert_eq!(
+            metrics.scan,
+            Some(metrics::ScanStats {
+                rows_scanned: 512,
+                index_partitions_probed: 0,
+            })
+        );
+
+        let (_, metrics) = table.query().limit(3).execute_with_metrics().await.unwrap();
+        assert_eq!(metrics.rows_returned, 3);
+        assert_eq!(metrics.scan.unwrap().rows_scanned, 3);
+        let (_, metrics) = table
+            .query()
+            .only_if("id < 100")
+            .execute_with_metrics()
+            .await
+            .unwrap();
+        assert_eq!(metrics.rows_returned, 100);
+        assert_eq!(metrics.scan.unwrap().rows_scanned, 512);
+    }
+
     #[tokio::test]
     async fn test_execute_with_options() {
         let tmp_dir = tempdir().unwrap();
@@ -963,6 +2653,7 @@ mod tests {
             .query()
             .execute_with_options(QueryExecutionOptions {
                 max_batch_length: 10,
+                ..Default::default()
             })
             .await
             .unwrap();
@@ -970,6 +2661,124 @@ mod tests {
         while let Some(batch) = results.next().await {
             assert!(batch.unwrap().num_rows() <= 10);
         }
+
+        // A readahead of one batch is slower but still returns every row
+        let results = table
+            .query()
+            .execute_with_options(QueryExecutionOptions {
+                max_batch_length: 10,
+                batch_readahead: Some(1),
+                fragment_readahead: Some(1),
+            })
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let num_rows = results.iter().map(|batch| batch.num_rows()).sum::<usize>();
+        assert_eq!(num_rows, table.count_rows(None).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_execute_into() {
+        #[derive(serde::Deserialize)]
+        struct Row {
+            id: i32,
+            vector: Vec<f32>,
+        }
+
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+
+        let rows = table
+            .query()
+            .only_if("id < 5")
+            .execute_into::<Row>()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let mut ids = rows.iter().map(|row| row.id).collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec![0, 1, 2, 3, 4]);
+        assert!(rows.iter().all(|row| row.vector.len() == 4));
+    }
+
+    #[test]
+    fn test_execute_blocking_reader() {
+        let tmp_dir = tempdir().unwrap();
+        let table = block_on(make_test_table(&tmp_dir));
+
+        let reader = table
+            .query()
+            .only_if("id < 100")
+            .execute_blocking_reader()
+            .unwrap();
+        assert!(reader.schema().field_with_name("vector").is_ok());
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        let num_rows = batches.iter().map(|batch| batch.num_rows()).sum::<usize>();
+        assert_eq!(num_rows, 100);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_execute_blocking_reader_in_runtime() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+
+        let reader = table.query().limit(10).execute_blocking_reader().unwrap();
+        assert_eq!(
+            reader.map(|batch| batch.unwrap().num_rows()).sum::<usize>(),
+            10
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hints() {
+        let tmp_dir = tempdir().unwrap();
+        let table = make_test_table(&tmp_dir).await;
+
+        let results = table
+            .query()
+            .limit(5)
+            .hint(Hint::ForceFlatSearch)
+            .hint(Hint::NoCache)
+            .nearest_to(&[0.1; 4])
+            .unwrap()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results.iter().map(|b| b.num_rows()).sum::<usize>(), 5);
+
+        let result = table
+            .query()
+            .hint(Hint::UseIndex("missing_idx".to_string()))
+            .nearest_to(&[0.1; 4])
+            .unwrap()
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+
+        let result = table
+            .query()
+            .hint(Hint::ForceFlatSearch)
+            .hint(Hint::UseIndex("vector_idx".to_string()))
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+
+        let result = table
+            .query()
+            .hint(Hint::UseIndex("vector_idx".to_string()))
+            .nearest_to(&[0.1; 4])
+            .unwrap()
+            .bypass_vector_index()
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
     }
 
     #[tokio::test]
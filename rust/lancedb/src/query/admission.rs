@@ -0,0 +1,239 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Limits on the number of queries running at once on a connection
+//!
+//! A query is admitted when it starts executing and runs until its results are
+//! consumed or dropped, since that is when the files of the table are read.
+//! Once [`crate::connection::ConnectBuilder::max_concurrent_queries`] queries are
+//! running, the next queries wait in a queue, in order of arrival.  If the queue
+//! is limited with [`crate::connection::ConnectBuilder::max_queued_queries`] and
+//! full, queries fail right away with [`Error::TooManyQueries`], so that a burst
+//! of queries is shed instead of piling up.  The current state is reported by
+//! [`crate::Connection::query_metrics`].
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::Stream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::arrow::{RecordBatchStream, SendableRecordBatchStream};
+use crate::error::{Error, Result};
+
+/// A snapshot of the queries of a connection
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryMetrics {
+    /// The number of queries allowed to run at once
+    pub max_concurrent: usize,
+    /// The number of queries running
+    pub running: usize,
+    /// The number of queries waiting to run, the depth of the queue
+    pub queued: usize,
+    /// The number of queries admitted since the connection was opened
+    pub admitted: u64,
+    /// The number of queries rejected because the queue was full
+    pub rejected: u64,
+    /// The total time the admitted queries spent in the queue
+    pub queue_time: Duration,
+}
+
+/// The admission control of the queries of a connection
+#[derive(Debug)]
+pub(crate) struct QueryAdmission {
+    max_concurrent: usize,
+    max_queued: Option<usize>,
+    semaphore: Arc<Semaphore>,
+    queued: AtomicUsize,
+    admitted: AtomicU64,
+    rejected: AtomicU64,
+    queue_micros: AtomicU64,
+}
+
+/// Removes a query from the queue, even if it is dropped while waiting
+struct Queued<'a>(&'a AtomicUsize);
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl QueryAdmission {
+    pub(crate) fn new(max_concurrent: usize, max_queued: Option<usize>) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        Self {
+            max_concurrent,
+            max_queued,
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            queued: AtomicUsize::new(0),
+            admitted: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            queue_micros: AtomicU64::new(0),
+        }
+    }
+
+    /// Wait until the query can run
+    ///
+    /// The query runs until the returned permit is dropped.
+    pub(crate) async fn admit(&self) -> Result<QueryPermit> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            self.admitted.fetch_add(1, Ordering::SeqCst);
+            return Ok(QueryPermit { permit });
+        }
+        let queued = self.queued.fetch_add(1, Ordering::SeqCst);
+        let guard = Queued(&self.queued);
+        if self
+            .max_queued
+            .is_some_and(|max_queued| queued >= max_queued)
+        {
+            self.rejected.fetch_add(1, Ordering::SeqCst);
+            return Err(Error::TooManyQueries {
+                running: self.max_concurrent,
+                queued,
+            });
+        }
+        let start = Instant::now();
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the query semaphore is never closed");
+        drop(guard);
+        self.queue_micros
+            .fetch_add(start.elapsed().as_micros() as u64, Ordering::SeqCst);
+        self.admitted.fetch_add(1, Ordering::SeqCst);
+        Ok(QueryPermit { permit })
+    }
+
+    pub(crate) fn metrics(&self) -> QueryMetrics {
+        QueryMetrics {
+            max_concurrent: self.max_concurrent,
+            running: self.max_concurrent - self.semaphore.available_permits(),
+            queued: self.queued.load(Ordering::SeqCst),
+            admitted: self.admitted.load(Ordering::SeqCst),
+            rejected: self.rejected.load(Ordering::SeqCst),
+            queue_time: Duration::from_micros(self.queue_micros.load(Ordering::SeqCst)),
+        }
+    }
+}
+
+/// The right of a query to run
+pub(crate) struct QueryPermit {
+    permit: OwnedSemaphorePermit,
+}
+
+impl QueryPermit {
+    /// Hold the permit until `stream` is dropped
+    pub(crate) fn hold(self, stream: SendableRecordBatchStream) -> SendableRecordBatchStream {
+        Box::pin(AdmittedRecordBatchStream {
+            stream,
+            _permit: self.permit,
+        })
+    }
+}
+
+/// A RecordBatchStream which holds the permit of its query
+#[pin_project::pin_project]
+struct AdmittedRecordBatchStream {
+    #[pin]
+    stream: SendableRecordBatchStream,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Stream for AdmittedRecordBatchStream {
+    type Item = Result<arrow_array::RecordBatch>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+}
+
+impl RecordBatchStream for AdmittedRecordBatchStream {
+    fn schema(&self) -> Arc<arrow_schema::Schema> {
+        self.stream.schema()
+    }
+
+    fn version(&self) -> Option<u64> {
+        self.stream.version()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::ExecutableQuery;
+
+    fn batches() -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..10))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_query_admission() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .max_concurrent_queries(1)
+            .max_queued_queries(1)
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("test", batches()).execute().await.unwrap();
+
+        // The first query runs until its results are dropped
+        let running = table.query().execute().await.unwrap();
+        let metrics = db.query_metrics().unwrap();
+        assert_eq!((metrics.running, metrics.queued), (1, 0));
+
+        let queued = tokio::spawn({
+            let table = table.clone();
+            async move { table.query().execute().await.map(|_| ()) }
+        });
+        while db.query_metrics().unwrap().queued == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        let result = table.query().execute().await;
+        assert!(matches!(result, Err(Error::TooManyQueries { .. })));
+
+        drop(running);
+        queued.await.unwrap().unwrap();
+        let metrics = db.query_metrics().unwrap();
+        assert_eq!(metrics.running, 0);
+        assert_eq!(metrics.queued, 0);
+        assert_eq!(metrics.admitted, 2);
+        assert_eq!(metrics.rejected, 1);
+
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        assert!(db.query_metrics().is_none());
+    }
+}
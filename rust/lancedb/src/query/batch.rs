@@ -0,0 +1,122 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Vector searches for several query vectors at once
+//!
+//! A batch search, created with [`super::Query::nearest_to_batch`], runs one
+//! search per query vector with the same options.  The searches share the
+//! overhead of a query: the table is opened at one version, which also keeps
+//! the indices loaded for all of them, a single admission permit is taken (see
+//! [`super::admission`]) and several searches run concurrently.  The results of
+//! all of the searches are returned in one stream, ordered by query, with an
+//! additional `query_index` column holding the position of the query vector.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use arrow_array::{RecordBatch, UInt32Array};
+use arrow_schema::{DataType, Field, Schema};
+use futures::{stream, StreamExt, TryStreamExt};
+
+use super::VectorQuery;
+use crate::arrow::{SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+
+/// The column holding the position of the query vector of each result
+pub const QUERY_INDEX_COLUMN: &str = "query_index";
+/// The number of searches of a batch running at once
+const BATCH_CONCURRENCY: usize = 8;
+
+/// The searches of a batch search, one per query vector
+fn split(query: &VectorQuery) -> Vec<VectorQuery> {
+    query
+        .batch_vectors
+        .iter()
+        .map(|vector| VectorQuery {
+            query_vector: Some(vector.clone()),
+            batch_vectors: Vec::new(),
+            ..query.clone()
+        })
+        .collect()
+}
+
+fn with_query_index(schema: &Schema) -> Result<Arc<Schema>> {
+    if schema.field_with_name(QUERY_INDEX_COLUMN).is_ok() {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "a batch search cannot return the column {}, it holds the query of each result",
+                QUERY_INDEX_COLUMN
+            ),
+        });
+    }
+    let mut fields = schema.fields().iter().cloned().collect::<Vec<_>>();
+    fields.push(Arc::new(Field::new(
+        QUERY_INDEX_COLUMN,
+        DataType::UInt32,
+        false,
+    )));
+    Ok(Arc::new(Schema::new_with_metadata(
+        fields,
+        schema.metadata().clone(),
+    )))
+}
+
+/// Run the batch search `query`, running each search with `search`
+pub(crate) async fn execute_batch<F, Fut>(
+    query: &VectorQuery,
+    search: F,
+) -> Result<SendableRecordBatchStream>
+where
+    F: Fn(VectorQuery) -> Fut,
+    Fut: Future<Output = Result<SendableRecordBatchStream>> + Send + 'static,
+{
+    let searches = split(query).into_iter().map(search).collect::<Vec<_>>();
+    let mut results = stream::iter(searches)
+        .enumerate()
+        .map(|(query_index, search)| async move {
+            let stream = search.await?;
+            let schema = stream.schema();
+            let batches = stream.try_collect::<Vec<_>>().await?;
+            Result::Ok((query_index as u32, schema, batches))
+        })
+        .buffered(BATCH_CONCURRENCY);
+
+    // The schema is the schema of the first search, all of them are the same
+    let Some(first) = results.try_next().await? else {
+        return Err(Error::InvalidInput {
+            message: "a batch search needs at least one query vector".to_string(),
+        });
+    };
+    let schema = with_query_index(&first.1)?;
+    let output_schema = schema.clone();
+    let stream = stream::once(async { Ok(first) })
+        .chain(results)
+        .map_ok(move |(query_index, _, batches)| {
+            let schema = output_schema.clone();
+            stream::iter(
+                batches
+                    .into_iter()
+                    .map(move |batch| -> Result<RecordBatch> {
+                        let mut columns = batch.columns().to_vec();
+                        columns.push(Arc::new(UInt32Array::from(vec![
+                            query_index;
+                            batch.num_rows()
+                        ])));
+                        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+                    }),
+            )
+        })
+        .try_flatten();
+    Ok(Box::pin(SimpleRecordBatchStream { schema, stream }))
+}
@@ -0,0 +1,258 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Limits on the read-ahead and the memory of the scans of a connection
+//!
+//! A scan reads batches ahead of the consumer of its results, see
+//! [`super::QueryExecutionOptions::batch_readahead`].  The defaults of the
+//! read-ahead of the queries of a connection are set with
+//! [`crate::connection::ConnectBuilder::batch_readahead`] and
+//! [`crate::connection::ConnectBuilder::fragment_readahead`].
+//!
+//! With [`crate::connection::ConnectBuilder::memory_budget`] the batches read
+//! ahead by all of the queries of the connection share a budget.  When a query
+//! starts it reserves the memory of its read-ahead, estimated from the schema
+//! of the table and the size of the batches, and its read-ahead is reduced to
+//! what is left of the budget.  The budget is soft: a query always reads at
+//! least one batch ahead, so it never waits for the budget, and the estimate
+//! of the size of the variable width columns is a guess.  The memory is
+//! released once the results of the query are consumed or dropped.
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use arrow_schema::{DataType, Schema};
+use futures::Stream;
+
+use super::QueryExecutionOptions;
+use crate::arrow::{RecordBatchStream, SendableRecordBatchStream};
+use crate::error::Result;
+
+/// The guessed size of a value of a variable width column, such as a string
+const VARIABLE_WIDTH_BYTES: usize = 64;
+
+/// The estimated size of a value of `data_type`
+pub(crate) fn value_bytes(data_type: &DataType) -> usize {
+    match data_type {
+        DataType::Boolean => 1,
+        DataType::FixedSizeList(field, size) => value_bytes(field.data_type()) * *size as usize,
+        DataType::FixedSizeBinary(size) => *size as usize,
+        DataType::Struct(fields) => fields
+            .iter()
+            .map(|field| value_bytes(field.data_type()))
+            .sum(),
+        DataType::Dictionary(key, _) => value_bytes(key),
+        data_type => data_type.primitive_width().unwrap_or(VARIABLE_WIDTH_BYTES),
+    }
+}
+
+/// The estimated size of a row of `schema`
+pub(crate) fn row_bytes(schema: &Schema) -> usize {
+    schema
+        .fields()
+        .iter()
+        .map(|field| value_bytes(field.data_type()))
+        .sum::<usize>()
+        .max(1)
+}
+
+/// The memory shared by the read-ahead of the queries of a connection
+#[derive(Debug)]
+pub(crate) struct MemoryBudget {
+    capacity: usize,
+    used: AtomicUsize,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Reserve the memory of up to `batches` batches of `batch_bytes` each
+    ///
+    /// At least one batch is reserved, even if the budget is exhausted.
+    pub(crate) fn reserve(self: &Arc<Self>, batch_bytes: usize, batches: usize) -> Reservation {
+        let batch_bytes = batch_bytes.max(1);
+        let mut used = self.used.load(Ordering::SeqCst);
+        loop {
+            let available = self.capacity.saturating_sub(used);
+            let granted = (available / batch_bytes).clamp(1, batches.max(1));
+            let bytes = granted * batch_bytes;
+            match self
+                .used
+                .compare_exchange(used, used + bytes, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => {
+                    return Reservation {
+                        budget: self.clone(),
+                        bytes,
+                        batches: granted,
+                    }
+                }
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    /// The bytes reserved by the running queries
+    #[cfg(test)]
+    pub(crate) fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+}
+
+/// Memory reserved from a [`MemoryBudget`], released when dropped
+#[derive(Debug)]
+pub(crate) struct Reservation {
+    budget: Arc<MemoryBudget>,
+    bytes: usize,
+    /// The number of batches the reserved memory holds
+    batches: usize,
+}
+
+impl Reservation {
+    /// Hold the reservation until `stream` is dropped
+    pub(crate) fn hold(self, stream: SendableRecordBatchStream) -> SendableRecordBatchStream {
+        Box::pin(ReservedRecordBatchStream {
+            stream,
+            _reservation: self,
+        })
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.used.fetch_sub(self.bytes, Ordering::SeqCst);
+    }
+}
+
+/// The limits of the scans of the queries of a connection
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ScanLimits {
+    pub(crate) batch_readahead: Option<usize>,
+    pub(crate) fragment_readahead: Option<usize>,
+    pub(crate) memory_budget: Option<Arc<MemoryBudget>>,
+}
+
+impl ScanLimits {
+    /// The options of a scan of a table with `schema`, and the memory reserved
+    /// for its read-ahead
+    ///
+    /// The options set by the query take precedence over the defaults of the
+    /// connection, but the read-ahead is always limited by the memory budget.
+    pub(crate) fn apply(
+        &self,
+        schema: &Schema,
+        mut options: QueryExecutionOptions,
+    ) -> (QueryExecutionOptions, Option<Reservation>) {
+        options.batch_readahead = options.batch_readahead.or(self.batch_readahead);
+        options.fragment_readahead = options.fragment_readahead.or(self.fragment_readahead);
+        let Some(budget) = &self.memory_budget else {
+            return (options, None);
+        };
+        let batch_bytes = row_bytes(schema) * options.max_batch_length.max(1) as usize;
+        let batches = options.batch_readahead.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        });
+        let reservation = budget.reserve(batch_bytes, batches);
+        options.batch_readahead = Some(reservation.batches);
+        // Each fragment read at the same time holds at least one batch
+        options.fragment_readahead = Some(
+            options
+                .fragment_readahead
+                .unwrap_or(reservation.batches)
+                .min(reservation.batches),
+        );
+        (options, Some(reservation))
+    }
+}
+
+/// A RecordBatchStream which holds the memory reserved by its query
+#[pin_project::pin_project]
+struct ReservedRecordBatchStream {
+    #[pin]
+    stream: SendableRecordBatchStream,
+    _reservation: Reservation,
+}
+
+impl Stream for ReservedRecordBatchStream {
+    type Item = Result<arrow_array::RecordBatch>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+}
+
+impl RecordBatchStream for ReservedRecordBatchStream {
+    fn schema(&self) -> Arc<arrow_schema::Schema> {
+        self.stream.schema()
+    }
+
+    fn version(&self) -> Option<u64> {
+        self.stream.version()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_schema::Field;
+
+    use super::*;
+
+    #[test]
+    fn test_memory_budget() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 4),
+                false,
+            ),
+        ]);
+        assert_eq!(row_bytes(&schema), 8 + 16);
+
+        let limits = ScanLimits {
+            batch_readahead: Some(8),
+            fragment_readahead: None,
+            memory_budget: Some(Arc::new(MemoryBudget::new(24 * 100 * 10))),
+        };
+        let options = QueryExecutionOptions {
+            max_batch_length: 100,
+            ..Default::default()
+        };
+        let (first, first_reservation) = limits.apply(&schema, options.clone());
+        assert_eq!(first.batch_readahead, Some(8));
+        let (second, second_reservation) = limits.apply(&schema, options.clone());
+        assert_eq!(second.batch_readahead, Some(2));
+        assert_eq!(second.fragment_readahead, Some(2));
+        // The budget is soft, a query always reads one batch ahead
+        let (third, _third_reservation) = limits.apply(&schema, options.clone());
+        assert_eq!(third.batch_readahead, Some(1));
+
+        let budget = limits.memory_budget.clone().unwrap();
+        assert_eq!(budget.used(), 24 * 100 * 11);
+        drop(first_reservation);
+        drop(second_reservation);
+        assert_eq!(budget.used(), 24 * 100);
+    }
+}
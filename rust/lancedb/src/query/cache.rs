@@ -0,0 +1,473 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A cache of the results of the queries of a connection
+//!
+//! Applications such as chatbots run the same queries over and over.  With
+//! [`crate::connection::ConnectBuilder::query_cache`] the results of the
+//! queries are kept in memory, and a query identical to a cached one returns
+//! the cached batches without reading the table.  Two queries are identical if
+//! they are on the same version of the same table and all of their parameters
+//! (query vectors, filter, limit, projection, ...) are equal.  The results of a
+//! version never change, so the cached results are never stale: once the table
+//! handle moves to a new version, see
+//! [`crate::connection::ConnectBuilder::read_consistency_interval`], the queries
+//! miss the cache and read the new version.
+//!
+//! The least recently used results are evicted once the cache is full, and
+//! results older than the time to live are not used.  A query is only cached
+//! once all of its results are consumed.  A query whose filter calls `now()`
+//! selects different rows as time passes, so it is never cached.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Poll};
+use std::time::{Duration, Instant};
+
+use arrow_array::RecordBatch;
+use arrow_schema::SchemaRef;
+use futures::{stream, Stream};
+
+use super::filter::Filter;
+use super::{Query, QueryExecutionOptions, VectorQuery};
+use crate::arrow::{
+    RecordBatchStream, SendableRecordBatchStream, SimpleRecordBatchStream,
+    VersionedRecordBatchStream,
+};
+use crate::error::Result;
+
+/// The size and time to live of the query cache of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryCacheOptions {
+    /// The largest number of bytes of results kept in memory
+    ///
+    /// The results of a query larger than this are never cached.  The default
+    /// is 64 MiB.
+    pub capacity: usize,
+    /// How long the results of a query are used, None to keep them until they
+    /// are evicted
+    ///
+    /// The default is 10 minutes.
+    pub ttl: Option<Duration>,
+}
+
+impl Default for QueryCacheOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 64 * 1024 * 1024,
+            ttl: Some(Duration::from_secs(600)),
+        }
+    }
+}
+
+/// Identifies the results of a query
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct QueryKey {
+    table: String,
+    version: u64,
+    query: u64,
+}
+
+impl QueryKey {
+    /// The key of a plain query of `table`, None if it cannot be cached
+    pub(crate) fn plain(
+        table: &str,
+        query: &Query,
+        options: &QueryExecutionOptions,
+    ) -> Option<Self> {
+        (!calls_now(query)).then(|| Self::new(table, "plain", &describe_query(query), options))
+    }
+
+    /// The key of a vector query of `table`, None if it cannot be cached
+    pub(crate) fn vector(
+        table: &str,
+        query: &VectorQuery,
+        options: &QueryExecutionOptions,
+    ) -> Option<Self> {
+        (!calls_now(&query.base))
+            .then(|| Self::new(table, "vector", &describe_vector_query(query), options))
+    }
+
+    fn new(table: &str, kind: &str, description: &str, options: &QueryExecutionOptions) -> Self {
+        let mut hasher = DefaultHasher::new();
+        (kind, description, options.max_batch_length).hash(&mut hasher);
+        Self {
+            table: table.to_string(),
+            version: 0,
+            query: hasher.finish(),
+        }
+    }
+
+    /// The key of the query on `version` of the table
+    pub(crate) fn at_version(mut self, version: u64) -> Self {
+        self.version = version;
+        self
+    }
+}
+
+/// Whether the filter of `query` calls `now()`
+///
+/// A filter which does not parse fails the query, which is then not cached.
+fn calls_now(query: &Query) -> bool {
+    query
+        .filter
+        .as_ref()
+        .and_then(|filter| Filter::parse(filter).ok())
+        .is_some_and(|filter| filter.calls_now())
+}
+
+/// All of the parameters of `query` which affect its results
+///
+/// The fields are listed explicitly so that a new parameter is not forgotten.
+fn describe_query(query: &Query) -> String {
+    let Query {
+        parent: _,
+        limit,
+        offset,
+        after,
+        order_by,
+        sample,
+        filter,
+        filter_params,
+        select,
+        computed_columns,
+        exclude_blobs,
+        hints,
+        with_row_id,
+        with_row_address,
+        time_ranges,
+        only_deleted,
+        #[cfg(feature = "fts")]
+        full_text_search,
+        // Aborting a query does not change its results
+        timeout: _,
+        cancellation_token: _,
+    } = query;
+    #[cfg(not(feature = "fts"))]
+    let full_text_search: Option<String> = None;
+    format!(
+        "{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}",
+        limit,
+        offset,
+        after,
+        order_by,
+        sample,
+        filter,
+        filter_params,
+        select,
+        computed_columns,
+        exclude_blobs,
+        hints,
+        with_row_id,
+        with_row_address,
+        time_ranges,
+        only_deleted,
+        full_text_search
+    )
+}
+
+/// All of the parameters of `query` which affect its results, see [`describe_query`]
+fn describe_vector_query(query: &VectorQuery) -> String {
+    let VectorQuery {
+        base,
+        column,
+        query_vector,
+        batch_vectors,
+        nprobes,
+        refine_factor,
+        refine_with_exact,
+        distance_type,
+        ef,
+        use_index,
+        prefilter,
+        unindexed,
+        lower_bound,
+        upper_bound,
+        group_by,
+        k_per_group,
+    } = query;
+    format!(
+        "{}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}",
+        describe_query(base),
+        column,
+        query_vector,
+        batch_vectors,
+        nprobes,
+        refine_factor,
+        refine_with_exact,
+        distance_type,
+        ef,
+        use_index,
+        prefilter,
+        unindexed,
+        lower_bound,
+        upper_bound,
+        group_by,
+        k_per_group
+    )
+}
+
+#[derive(Debug)]
+struct CachedResults {
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+    size: usize,
+    cached_at: Instant,
+    last_use: u64,
+}
+
+#[derive(Debug, Default)]
+struct LruState {
+    entries: HashMap<QueryKey, CachedResults>,
+    /// The keys by the tick of their last use
+    by_use: BTreeMap<u64, QueryKey>,
+    size: usize,
+    tick: u64,
+}
+
+impl LruState {
+    fn remove(&mut self, key: &QueryKey) {
+        if let Some(results) = self.entries.remove(key) {
+            self.size -= results.size;
+            self.by_use.remove(&results.last_use);
+        }
+    }
+}
+
+/// The query cache of a connection, shared by its tables
+#[derive(Debug)]
+pub(crate) struct QueryCache {
+    options: QueryCacheOptions,
+    state: Mutex<LruState>,
+}
+
+impl QueryCache {
+    pub(crate) fn new(options: QueryCacheOptions) -> Self {
+        Self {
+            options,
+            state: Mutex::new(LruState::default()),
+        }
+    }
+
+    /// The cached results of the query identified by `key`, if any
+    pub(crate) fn get(&self, key: &QueryKey) -> Result<Option<SendableRecordBatchStream>> {
+        let mut state = self.state.lock()?;
+        let expired = state.entries.get(key).map(|results| {
+            self.options
+                .ttl
+                .is_some_and(|ttl| results.cached_at.elapsed() > ttl)
+        });
+        match expired {
+            None => return Ok(None),
+            Some(true) => {
+                state.remove(key);
+                return Ok(None);
+            }
+            Some(false) => {}
+        }
+        state.tick += 1;
+        let tick = state.tick;
+        let results = state.entries.get_mut(key).expect("the results were found");
+        let previous = std::mem::replace(&mut results.last_use, tick);
+        let schema = results.schema.clone();
+        let batches = results.batches.clone();
+        state.by_use.remove(&previous);
+        state.by_use.insert(tick, key.clone());
+        let stream = Box::pin(SimpleRecordBatchStream {
+            schema,
+            stream: stream::iter(batches.into_iter().map(Ok)),
+        });
+        Ok(Some(Box::pin(VersionedRecordBatchStream::new(
+            stream,
+            key.version,
+        ))))
+    }
+
+    fn put(&self, key: QueryKey, schema: SchemaRef, batches: Vec<RecordBatch>, size: usize) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.remove(&key);
+        state.tick += 1;
+        let tick = state.tick;
+        state.size += size;
+        state.by_use.insert(tick, key.clone());
+        state.entries.insert(
+            key,
+            CachedResults {
+                schema,
+                batches,
+                size,
+                cached_at: Instant::now(),
+                last_use: tick,
+            },
+        );
+        while state.size > self.options.capacity {
+            let Some((_, evicted)) = state.by_use.pop_first() else {
+                break;
+            };
+            if let Some(results) = state.entries.remove(&evicted) {
+                state.size -= results.size;
+            }
+        }
+    }
+
+    /// Cache the results of `stream` under `key` once they are all consumed
+    pub(crate) fn fill(
+        self: &Arc<Self>,
+        key: QueryKey,
+        stream: SendableRecordBatchStream,
+    ) -> SendableRecordBatchStream {
+        Box::pin(CachingRecordBatchStream {
+            stream,
+            cache: self.clone(),
+            key: Some(key),
+            batches: Vec::new(),
+            size: 0,
+        })
+    }
+}
+
+/// A RecordBatchStream which caches its batches once it is consumed
+#[pin_project::pin_project]
+struct CachingRecordBatchStream {
+    #[pin]
+    stream: SendableRecordBatchStream,
+    cache: Arc<QueryCache>,
+    /// None once the results cannot be cached
+    key: Option<QueryKey>,
+    batches: Vec<RecordBatch>,
+    size: usize,
+}
+
+impl Stream for CachingRecordBatchStream {
+    type Item = Result<RecordBatch>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        let item = ready!(this.stream.as_mut().poll_next(cx));
+        match &item {
+            Some(Ok(batch)) if this.key.is_some() => {
+                *this.size += batch.get_array_memory_size();
+                if *this.size > this.cache.options.capacity {
+                    *this.key = None;
+                    this.batches.clear();
+                } else {
+                    this.batches.push(batch.clone());
+                }
+            }
+            Some(Ok(_)) => {}
+            Some(Err(_)) => {
+                *this.key = None;
+                this.batches.clear();
+            }
+            None => {
+                if let Some(key) = this.key.take() {
+                    this.cache.put(
+                        key,
+                        this.stream.schema(),
+                        std::mem::take(this.batches),
+                        *this.size,
+                    );
+                }
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl RecordBatchStream for CachingRecordBatchStream {
+    fn schema(&self) -> Arc<arrow_schema::Schema> {
+        self.stream.schema()
+    }
+
+    fn version(&self) -> Option<u64> {
+        self.stream.version()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field, Schema};
+    use futures::TryStreamExt;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+
+    fn batches(start: i32) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(start..start + 10))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_query_cache() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .query_cache(QueryCacheOptions::default())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("t", batches(0)).execute().await.unwrap();
+        let hits_and_misses = || {
+            let metrics = db.metrics().unwrap();
+            (metrics.query_cache_hits, metrics.query_cache_misses)
+        };
+        let count = |filter: &'static str| {
+            let table = table.clone();
+            async move {
+                let batches = table
+                    .query()
+                    .only_if(filter)
+                    .execute()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                batches.iter().map(|batch| batch.num_rows()).sum::<usize>()
+            }
+        };
+
+        assert_eq!(count("i < 5").await, 5);
+        assert_eq!(count("i < 5").await, 5);
+        assert_eq!(hits_and_misses(), (1, 1));
+        assert_eq!(count("i < 3").await, 3);
+        assert_eq!(hits_and_misses(), (1, 2));
+
+        // A new version is not answered from the results of the previous one
+        table.add(batches(0)).execute().await.unwrap();
+        assert_eq!(count("i < 5").await, 10);
+        assert_eq!(hits_and_misses(), (1, 3));
+
+        // The rows selected with now() change over time, so they are not cached
+        let filter = "i < 5 OR now() IS NULL";
+        assert_eq!(count(filter).await, 10);
+        assert_eq!(count(filter).await, 10);
+        assert_eq!(hits_and_misses(), (1, 3));
+    }
+}
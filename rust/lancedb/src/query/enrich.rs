@@ -0,0 +1,475 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Enriching query results with the rows of another source
+//!
+//! Each batch of results is joined, by key, with the matching rows of the source
+//! as it is read.  Only the keys of the batch are looked up, so this is cheap for
+//! the top-k results of a vector search even if the source is large.  Creating a
+//! scalar index on the key column of the source makes the lookups faster.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::compute::{cast, concat_batches, take};
+use arrow::row::{RowConverter, SortField};
+use arrow_array::{new_null_array, Array, ArrayRef, RecordBatch, UInt32Array};
+use arrow_schema::{Field, Schema, SchemaRef};
+use futures::TryStreamExt;
+
+use super::filter::FilterValue;
+use super::{ExecutableQuery, HasQuery, Query, QueryBase, QueryExecutionOptions, Select};
+use crate::arrow::{SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+use crate::Table;
+
+/// The source of the rows used to enrich query results
+#[derive(Clone)]
+pub enum EnrichSource {
+    /// A LanceDB table
+    Table(Table),
+    /// A DataFusion table provider, for example a CSV or parquet file or a table
+    /// of another database
+    #[cfg(feature = "datafusion")]
+    DataFusion(Arc<dyn datafusion::datasource::TableProvider>),
+}
+
+impl std::fmt::Debug for EnrichSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Table(table) => f.debug_tuple("Table").field(&table.name()).finish(),
+            #[cfg(feature = "datafusion")]
+            Self::DataFusion(_) => f.debug_tuple("DataFusion").finish(),
+        }
+    }
+}
+
+impl From<Table> for EnrichSource {
+    fn from(table: Table) -> Self {
+        Self::Table(table)
+    }
+}
+
+#[cfg(feature = "datafusion")]
+impl From<Arc<dyn datafusion::datasource::TableProvider>> for EnrichSource {
+    fn from(provider: Arc<dyn datafusion::datasource::TableProvider>) -> Self {
+        Self::DataFusion(provider)
+    }
+}
+
+impl EnrichSource {
+    async fn schema(&self) -> Result<SchemaRef> {
+        match self {
+            Self::Table(table) => table.schema().await,
+            #[cfg(feature = "datafusion")]
+            Self::DataFusion(provider) => Ok(provider.schema()),
+        }
+    }
+
+    /// The rows whose `key` is one of `keys`, with the columns `key` and `columns`
+    async fn lookup(&self, key: &str, keys: &dyn Array, columns: &[String]) -> Result<RecordBatch> {
+        let mut projection = vec![key.to_string()];
+        projection.extend(columns.iter().cloned());
+        match self {
+            Self::Table(table) => {
+                let values = FilterValue::from_array(key, keys)?;
+                let params = (0..values.len())
+                    .map(|i| format!("$key{}", i))
+                    .collect::<Vec<_>>();
+                let filter = format!("`{}` IN ({})", key, params.join(", "));
+                let mut query = table
+                    .query()
+                    .only_if(filter)
+                    .select(Select::columns(projection.as_slice()));
+                for (i, value) in values.into_iter().enumerate() {
+                    query = query.bind(format!("key{}", i), value);
+                }
+                let stream = query.execute().await?;
+                let schema = stream.schema();
+                let batches = stream.try_collect::<Vec<_>>().await?;
+                Ok(concat_batches(&schema, &batches)?)
+            }
+            #[cfg(feature = "datafusion")]
+            Self::DataFusion(provider) => {
+                use datafusion::common::{Column, ScalarValue};
+                use datafusion::logical_expr::{lit, Expr};
+
+                let list = (0..keys.len())
+                    .filter(|i| keys.is_valid(*i))
+                    .map(|i| ScalarValue::try_from_array(keys, i).map(lit))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                let projection = projection.iter().map(String::as_str).collect::<Vec<_>>();
+                let frame = datafusion::prelude::SessionContext::new()
+                    .read_table(provider.clone())?
+                    .filter(Expr::Column(Column::from_name(key)).in_list(list, false))?
+                    .select_columns(&projection)?;
+                let schema = Arc::new(Schema::from(frame.schema()));
+                let batches = frame.collect().await?;
+                Ok(concat_batches(&schema, &batches)?)
+            }
+        }
+    }
+}
+
+/// A query whose results are joined with the rows of another source
+///
+/// See [`Query::enrich`] for more details.
+///
+/// Each result row is joined with the first row of the source whose key is
+/// equal to the key of the result.  The columns of the source are null if
+/// there is no such row, so the results are never dropped or reordered.
+#[derive(Debug, Clone)]
+pub struct EnrichedQuery<Q> {
+    query: Q,
+    source: EnrichSource,
+    key: String,
+    source_key: Option<String>,
+    columns: Option<Vec<String>>,
+}
+
+impl<Q> EnrichedQuery<Q> {
+    pub(crate) fn new(query: Q, source: EnrichSource, key: String) -> Self {
+        Self {
+            query,
+            source,
+            key,
+            source_key: None,
+            columns: None,
+        }
+    }
+
+    /// The name of the key column in the source
+    ///
+    /// By default this is the same as the key column of the results.
+    pub fn source_key(mut self, source_key: impl Into<String>) -> Self {
+        self.source_key = Some(source_key.into());
+        self
+    }
+
+    /// The columns of the source to add to the results
+    ///
+    /// By default every column of the source, except its key column, is added.
+    /// The columns must not have the same name as one of the result columns.
+    pub fn columns(mut self, columns: &[impl AsRef<str>]) -> Self {
+        self.columns = Some(columns.iter().map(|c| c.as_ref().to_string()).collect());
+        self
+    }
+
+    /// Join the enriched results with the rows of another source
+    ///
+    /// See [`Query::enrich`]
+    pub fn enrich(
+        self,
+        source: impl Into<EnrichSource>,
+        key: impl Into<String>,
+    ) -> EnrichedQuery<Self> {
+        EnrichedQuery::new(self, source.into(), key.into())
+    }
+}
+
+impl<Q: HasQuery> HasQuery for EnrichedQuery<Q> {
+    fn mut_query(&mut self) -> &mut Query {
+        self.query.mut_query()
+    }
+}
+
+/// Joins batches of results with the rows of the source
+struct Enricher {
+    source: EnrichSource,
+    key: String,
+    source_key: String,
+    columns: Vec<String>,
+    schema: SchemaRef,
+}
+
+impl Enricher {
+    fn new<Q>(query: &EnrichedQuery<Q>, results: &Schema, source: &Schema) -> Result<Self> {
+        let source_key = query
+            .source_key
+            .clone()
+            .unwrap_or_else(|| query.key.clone());
+        results
+            .field_with_name(&query.key)
+            .map_err(|_| Error::Schema {
+                message: format!(
+                    "The key column {} is not in the results, it must be selected by the query",
+                    query.key
+                ),
+            })?;
+        source
+            .field_with_name(&source_key)
+            .map_err(|_| Error::Schema {
+                message: format!("The key column {} is not in the source", source_key),
+            })?;
+        let columns = match &query.columns {
+            Some(columns) => columns.clone(),
+            None => source
+                .fields()
+                .iter()
+                .map(|field| field.name().clone())
+                .filter(|name| name != &source_key)
+                .collect(),
+        };
+
+        let mut fields = results.fields().iter().cloned().collect::<Vec<_>>();
+        for column in &columns {
+            if results.field_with_name(column).is_ok() {
+                return Err(Error::Schema {
+                    message: format!(
+                        "The column {} is in both the results and the source, \
+                         select other columns with EnrichedQuery::columns",
+                        column
+                    ),
+                });
+            }
+            let field = source.field_with_name(column)?;
+            fields.push(Arc::new(Field::clone(field).with_nullable(true)));
+        }
+        Ok(Self {
+            source: query.source.clone(),
+            key: query.key.clone(),
+            source_key,
+            columns,
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+
+    async fn enrich(&self, batch: RecordBatch) -> Result<RecordBatch> {
+        let keys = batch[self.key.as_str()].clone();
+        let mut columns = batch.columns().to_vec();
+        if keys.null_count() == keys.len() {
+            for field in &self.schema.fields()[columns.len()..] {
+                columns.push(new_null_array(field.data_type(), batch.num_rows()));
+            }
+            return Ok(RecordBatch::try_new(self.schema.clone(), columns)?);
+        }
+        let matches = self
+            .source
+            .lookup(&self.source_key, keys.as_ref(), &self.columns)
+            .await?;
+
+        let converter = RowConverter::new(vec![SortField::new(keys.data_type().clone())])?;
+        let source_keys = cast(&matches[self.source_key.as_str()], keys.data_type())?;
+        let source_rows = converter.convert_columns(&[source_keys])?;
+        let mut positions = HashMap::with_capacity(source_rows.num_rows());
+        for (position, row) in source_rows.iter().enumerate() {
+            positions
+                .entry(row.as_ref().to_vec())
+                .or_insert(position as u32);
+        }
+        let indices = converter
+            .convert_columns(&[keys])?
+            .iter()
+            .map(|row| positions.get(row.as_ref()).copied())
+            .collect::<UInt32Array>();
+
+        for column in &self.columns {
+            let values: ArrayRef = if matches.num_rows() == 0 {
+                new_null_array(matches[column.as_str()].data_type(), batch.num_rows())
+            } else {
+                take(&matches[column.as_str()], &indices, None)?
+            };
+            columns.push(values);
+        }
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+impl<Q: ExecutableQuery + Send + Sync> ExecutableQuery for EnrichedQuery<Q> {
+    async fn execute_with_options(
+        &self,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        let source_schema = self.source.schema().await?;
+        let results = self.query.execute_with_options(options).await?;
+        let enricher = Arc::new(Enricher::new(self, &results.schema(), &source_schema)?);
+        let schema = enricher.schema.clone();
+        let stream = results.and_then(move |batch| {
+            let enricher = enricher.clone();
+            async move { enricher.enrich(batch).await }
+        });
+        Ok(Box::pin(SimpleRecordBatchStream { schema, stream }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use arrow_array::{FixedSizeListArray, RecordBatchReader};
+    use arrow_array::{Float32Array, Int32Array, RecordBatchIterator, StringArray};
+    use arrow_schema::DataType;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    fn items() -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+                false,
+            ),
+        ]));
+        let values = Float32Array::from_iter_values((0..10).flat_map(|i| [i as f32, 0.0]));
+        let vectors = FixedSizeListArray::try_new(
+            Arc::new(Field::new("item", DataType::Float32, true)),
+            2,
+            Arc::new(values),
+            None,
+        )
+        .unwrap();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..10)),
+                Arc::new(vectors),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_enrich() {
+        let tmp_dir = tempdir().unwrap();
+        let conn = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let items = conn.create_table("items", items()).execute().await.unwrap();
+
+        // Item 1 has no title
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("item_id", DataType::Int64, false),
+            Field::new("title", DataType::Utf8, false),
+        ]));
+        let titles = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(arrow_array::Int64Array::from(vec![0, 2, 3])),
+                Arc::new(StringArray::from(vec!["zero", "two", "three"])),
+            ],
+        )
+        .unwrap();
+        let titles = conn
+            .create_table("titles", RecordBatchIterator::new(vec![Ok(titles)], schema))
+            .execute()
+            .await
+            .unwrap();
+
+        let query = items
+            .query()
+            .nearest_to(&[0.0, 0.0])
+            .unwrap()
+            .limit(4)
+            .enrich(titles.clone(), "id")
+            .source_key("item_id");
+        let batches = query
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(batch.num_columns(), 4);
+        let ids = batch["id"].as_primitive::<arrow_array::types::Int32Type>();
+        assert_eq!(ids.values(), &[0, 1, 2, 3]);
+        let titles_column = batch["title"].as_string::<i32>();
+        assert_eq!(
+            titles_column.iter().collect::<Vec<_>>(),
+            vec![Some("zero"), None, Some("two"), Some("three")]
+        );
+
+        // The key must be selected
+        let query = items
+            .query()
+            .select(Select::columns(&["vector"]))
+            .enrich(titles.clone(), "id")
+            .source_key("item_id");
+        assert!(matches!(query.execute().await, Err(Error::Schema { .. })));
+
+        // The source columns must not clash with the result columns
+        let query = items
+            .query()
+            .enrich(items.clone(), "id")
+            .columns(&["vector"]);
+        assert!(matches!(query.execute().await, Err(Error::Schema { .. })));
+
+        // The same join, from the table
+        let batches = items
+            .join(titles.clone(), "id", &["title"])
+            .source_key("item_id")
+            .only_if("id < 2")
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(batch.num_columns(), 3);
+        assert_eq!(
+            batch["title"].as_string::<i32>().iter().collect::<Vec<_>>(),
+            vec![Some("zero"), None]
+        );
+    }
+
+    #[cfg(feature = "datafusion")]
+    #[tokio::test]
+    async fn test_enrich_datafusion() {
+        use datafusion::datasource::MemTable;
+
+        let tmp_dir = tempdir().unwrap();
+        let conn = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let items = conn.create_table("items", items()).execute().await.unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("price", DataType::Float32, false),
+        ]));
+        let prices = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![5, 1])),
+                Arc::new(Float32Array::from(vec![5.5, 1.5])),
+            ],
+        )
+        .unwrap();
+        let provider: Arc<dyn datafusion::datasource::TableProvider> =
+            Arc::new(MemTable::try_new(schema, vec![vec![prices]]).unwrap());
+
+        let batches = items
+            .query()
+            .only_if("id < 3")
+            .enrich(provider, "id")
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+        let prices = batch["price"].as_primitive::<arrow_array::types::Float32Type>();
+        assert_eq!(
+            prices.iter().collect::<Vec<_>>(),
+            vec![None, Some(1.5), None]
+        );
+    }
+}
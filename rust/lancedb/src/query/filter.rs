@@ -0,0 +1,1941 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The filter grammar
+//!
+//! Filters (see [`super::QueryBase::only_if`]) are parsed by LanceDB before they
+//! are executed.  Syntax errors are reported as [`Error::InvalidFilter`] with the
+//! column at which the error was found.  The parsed filter is then converted to
+//! the SQL dialect understood by the query engine.
+//!
+//! This is version [`FILTER_GRAMMAR_VERSION`] of the grammar.  Keywords are case
+//! insensitive.
+//!
+//! ```text
+//! filter     := or
+//! or         := and ( OR and )*
+//! and        := not ( AND not )*
+//! not        := NOT not | predicate
+//! predicate  := sum [ compare sum
+//!                   | IS [ NOT ] ( NULL | TRUE | FALSE | DISTINCT FROM sum )
+//!                   | [ NOT ] IN ( sum ( , sum )* )
+//!                   | [ NOT ] BETWEEN sum AND sum
+//!                   | [ NOT ] ( LIKE | ILIKE ) sum ]
+//! compare    := = | == | != | <> | < | <= | > | >=
+//! sum        := product ( ( + | - ) product )*
+//! product    := unary ( ( * | / | % | || ) unary )*
+//! unary      := - unary | element
+//! element    := primary ( [ filter ] | :: identifier [ ( number ( , number )* ) ] )*
+//! primary    := literal | parameter | column | function | cast | case | array
+//!             | ( filter )
+//! literal    := number | 'string' | "string" | TRUE | FALSE | NULL
+//!             | TIMESTAMP 'string' | DATE 'string' | INTERVAL 'string'
+//! parameter  := $name
+//! column     := name ( . name )*
+//! name       := identifier | `quoted identifier`
+//! function   := identifier ( [ filter ( , filter )* ] )
+//! cast       := CAST ( filter AS type )
+//! case       := CASE [ filter ] ( WHEN filter THEN filter )+ [ ELSE filter ] END
+//! array      := [ [ filter ( , filter )* ] ]
+//! ```
+//!
+//! In `element` and `array` the outer brackets are literal: `tags[1]` is an
+//! element of a list column and `['a', 'b']` a list, e.g. for
+//! `array_has_any(tags, ['a', 'b'])`.  These were added in version 2 of the
+//! grammar, along with `IS TRUE` and `IS FALSE`.
+//!
+//! Strings use single or double quotes and a quote is escaped by doubling it
+//! (`'it''s'`), `item = "fizz"` and `item = 'fizz'` are the same filter.  Names
+//! which are keywords or are not identifiers, e.g. with a space, are quoted with
+//! backticks (`` `my column` ``).  For compatibility with other dialects `==` is
+//! accepted as `=`.  `x::type` is `CAST(x AS type)` and `||` concatenates
+//! strings.
+//!
+//! Version 3 of the grammar reads double quotes as strings, as the query engine
+//! does, where version 2 read them as identifiers.  It added `||`, `::`, `CASE`
+//! and `IS [NOT] DISTINCT FROM`.
+//!
+//! Parameters are placeholders for values which are provided separately with
+//! [`super::QueryBase::bind`] (or [`Filter::bind`]).  This avoids quoting and
+//! escaping values by hand:
+//!
+//! ```ignore
+//! table
+//!     .query()
+//!     .only_if("category = $category AND price < $max_price")
+//!     .bind("category", "shoes")
+//!     .bind("max_price", 100.0)
+//! ```
+//!
+//! `now()` is the current time, in UTC, and an interval (e.g. `INTERVAL '7
+//! days'` or `INTERVAL '1 day 12 hours'`) can be added to or subtracted from
+//! `now()`, a timestamp or a date.  The time is computed when the filter is
+//! converted to SQL, so that `created_at > now() - INTERVAL '7 days'` selects
+//! the rows of the last week.  The units of intervals go from microseconds to
+//! weeks, months and years are not supported as their length varies.
+//!
+//! The spatial functions `st_dwithin` and `st_within_bbox` filter on points
+//! stored as latitude and longitude columns, see [`super::geo`].
+//!
+//! `json_extract(column, '$.path')` is a value of the documents of a JSON
+//! column, see [`super::json`].
+//!
+//! Parameters may also be numbered, `$1` being the first value given to
+//! [`super::QueryBase::only_if_with_params`] (or [`Filter::bind_all`]).  A list
+//! of values can be bound to a parameter which is the only item of an `IN`
+//! list, an empty list matching no row:
+//!
+//! ```ignore
+//! table
+//!     .query()
+//!     .only_if_with_params(
+//!         "user_id = $1 AND tag IN ($2) AND created > $3",
+//!         [user_id.into(), tags.into(), since.into()],
+//!     )
+//! ```
+
+use std::collections::HashMap;
+
+use arrow::compute::cast;
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, Int64Type};
+use arrow_array::Array;
+use arrow_schema::DataType;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+
+use super::geo::GeoFunction;
+use super::json::json_extract_sql;
+use crate::error::{Error, Result};
+
+/// The version of the filter grammar described in the [module docs](self)
+pub const FILTER_GRAMMAR_VERSION: u32 = 3;
+
+/// A value bound to a parameter of a filter
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Timestamp(DateTime<Utc>),
+    Date(NaiveDate),
+    /// The values of an `IN` list
+    List(Vec<Self>),
+}
+
+fn timestamp_sql(time: &NaiveDateTime) -> String {
+    format!("TIMESTAMP '{}'", time.format("%Y-%m-%d %H:%M:%S%.f"))
+}
+
+/// Parse the text of an interval, a sequence of numbers and units
+fn parse_interval(text: &str) -> std::result::Result<Duration, String> {
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    if words.is_empty() || words.len() % 2 != 0 {
+        return Err(format!(
+            "expected an interval such as '7 days' but found '{}'",
+            text
+        ));
+    }
+    words.chunks(2).try_fold(Duration::zero(), |total, pair| {
+        let amount = pair[0]
+            .parse::<i64>()
+            .map_err(|_| format!("'{}' is not a whole number", pair[0]))?;
+        let unit = pair[1].to_lowercase();
+        match unit.as_str() {
+            "microsecond" | "microseconds" => Some(Duration::microseconds(amount)),
+            "millisecond" | "milliseconds" => Duration::try_milliseconds(amount),
+            "second" | "seconds" => Duration::try_seconds(amount),
+            "minute" | "minutes" => Duration::try_minutes(amount),
+            "hour" | "hours" => Duration::try_hours(amount),
+            "day" | "days" => Duration::try_days(amount),
+            "week" | "weeks" => Duration::try_weeks(amount),
+            _ => return Err(format!("the unit '{}' is not supported", pair[1])),
+        }
+        .and_then(|duration| total.checked_add(&duration))
+        .ok_or_else(|| format!("the interval '{}' is too long", text))
+    })
+}
+
+/// The range of times a filter restricts a column to, both bounds included
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TimeBounds {
+    pub lower: Option<NaiveDateTime>,
+    pub upper: Option<NaiveDateTime>,
+}
+
+impl TimeBounds {
+    fn restrict(&mut self, lower: Option<NaiveDateTime>, upper: Option<NaiveDateTime>) {
+        self.lower = self.lower.max(lower);
+        self.upper = match (self.upper, upper) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+impl FilterValue {
+    pub(crate) fn to_sql(&self) -> String {
+        match self {
+            Self::Null => "NULL".to_string(),
+            Self::Bool(value) => value.to_string().to_uppercase(),
+            Self::Int(value) => value.to_string(),
+            Self::Float(value) => format!("{:?}", value),
+            Self::String(value) => quote_string(value),
+            Self::Timestamp(value) => timestamp_sql(&value.naive_utc()),
+            Self::Date(value) => format!("DATE '{}'", value.format("%Y-%m-%d")),
+            // NULL matches no row, `IN ()` is not valid
+            Self::List(values) if values.is_empty() => "NULL".to_string(),
+            Self::List(values) => values
+                .iter()
+                .map(Self::to_sql)
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+}
+
+impl FilterValue {
+    /// The non-null values of `values`, the array of the column `column`
+    pub(crate) fn from_array(column: &str, values: &dyn Array) -> Result<Vec<Self>> {
+        let data_type = values.data_type();
+        if data_type.is_integer() {
+            let values = cast(values, &DataType::Int64)?;
+            Ok(values
+                .as_primitive::<Int64Type>()
+                .iter()
+                .flatten()
+                .map(Self::Int)
+                .collect())
+        } else if data_type.is_floating() {
+            let values = cast(values, &DataType::Float64)?;
+            Ok(values
+                .as_primitive::<Float64Type>()
+                .iter()
+                .flatten()
+                .map(Self::Float)
+                .collect())
+        } else if matches!(data_type, DataType::Utf8 | DataType::LargeUtf8) {
+            let values = cast(values, &DataType::Utf8)?;
+            Ok(values
+                .as_string::<i32>()
+                .iter()
+                .flatten()
+                .map(Self::from)
+                .collect())
+        } else {
+            Err(Error::Schema {
+                message: format!(
+                    "The column {} has type {} which cannot be used as filter values",
+                    column, data_type
+                ),
+            })
+        }
+    }
+}
+
+impl From<bool> for FilterValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i32> for FilterValue {
+    fn from(value: i32) -> Self {
+        Self::Int(value as i64)
+    }
+}
+
+impl From<i64> for FilterValue {
+    fn from(value: i64) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<u32> for FilterValue {
+    fn from(value: u32) -> Self {
+        Self::Int(value as i64)
+    }
+}
+
+impl From<f32> for FilterValue {
+    fn from(value: f32) -> Self {
+        Self::Float(value as f64)
+    }
+}
+
+impl From<f64> for FilterValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<&str> for FilterValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<String> for FilterValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<DateTime<Utc>> for FilterValue {
+    fn from(value: DateTime<Utc>) -> Self {
+        Self::Timestamp(value)
+    }
+}
+
+impl From<NaiveDate> for FilterValue {
+    fn from(value: NaiveDate) -> Self {
+        Self::Date(value)
+    }
+}
+
+impl<T: Into<Self>> From<Vec<T>> for FilterValue {
+    fn from(values: Vec<T>) -> Self {
+        Self::List(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Self>> From<Option<T>> for FilterValue {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Self::Null)
+    }
+}
+
+fn quote_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+pub(super) fn quote_identifier(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Identifier(String),
+    QuotedIdentifier(String),
+    String(String),
+    Number(String),
+    Parameter(String),
+    Symbol(&'static str),
+}
+
+impl TokenKind {
+    fn describe(&self) -> String {
+        match self {
+            Self::Identifier(name) => format!("'{}'", name),
+            Self::QuotedIdentifier(name) => format!("identifier `{}`", name),
+            Self::String(value) => format!("string {}", quote_string(value)),
+            Self::Number(value) => format!("number {}", value),
+            Self::Parameter(name) => format!("parameter ${}", name),
+            Self::Symbol(symbol) => format!("'{}'", symbol),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    /// The 1-based column of the first character of the token
+    column: usize,
+}
+
+const SYMBOLS: &[&str] = &[
+    "==", "!=", "<>", "<=", ">=", "||", "::", "=", "<", ">", "+", "-", "*", "/", "%", "(", ")",
+    "[", "]", ",", ".",
+];
+
+struct Lexer<'a> {
+    filter: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(filter: &'a str) -> Self {
+        Self {
+            filter,
+            chars: filter.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn error(&self, column: usize, message: impl Into<String>) -> Error {
+        Error::InvalidFilter {
+            filter: self.filter.to_string(),
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    /// Read until the closing `quote`, a doubled quote is an escaped quote
+    fn quoted(&mut self, quote: char, what: &str) -> Result<String> {
+        let start = self.pos + 1;
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.peek(0) {
+                None => return Err(self.error(start, format!("unterminated {}", what))),
+                Some(c) if c == quote => {
+                    if self.peek(1) == Some(quote) {
+                        value.push(quote);
+                        self.pos += 2;
+                    } else {
+                        self.pos += 1;
+                        return Ok(value);
+                    }
+                }
+                Some(c) => {
+                    value.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek(0).is_some_and(&predicate) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn number(&mut self) -> Result<String> {
+        let column = self.pos + 1;
+        let mut number = self.take_while(|c| c.is_ascii_digit());
+        if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+            number.push('.');
+            number.push_str(&self.take_while(|c| c.is_ascii_digit()));
+        }
+        if matches!(self.peek(0), Some('e' | 'E')) {
+            let sign = matches!(self.peek(1), Some('+' | '-'));
+            let digit = self.peek(if sign { 2 } else { 1 });
+            if digit.is_some_and(|c| c.is_ascii_digit()) {
+                number.push('e');
+                if sign {
+                    number.push(self.peek(1).unwrap());
+                }
+                self.pos += if sign { 2 } else { 1 };
+                number.push_str(&self.take_while(|c| c.is_ascii_digit()));
+            }
+        }
+        if self
+            .peek(0)
+            .is_some_and(|c| c.is_alphanumeric() || c == '_')
+        {
+            return Err(self.error(column, "invalid number"));
+        }
+        Ok(number)
+    }
+
+    fn tokenize(mut self) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+        while let Some(c) = self.peek(0) {
+            let column = self.pos + 1;
+            let kind = if c.is_whitespace() {
+                self.pos += 1;
+                continue;
+            } else if c == '\'' || c == '"' {
+                TokenKind::String(self.quoted(c, "string")?)
+            } else if c == '`' {
+                TokenKind::QuotedIdentifier(self.quoted(c, "identifier")?)
+            } else if c.is_ascii_digit()
+                || (c == '.' && self.peek(1).is_some_and(|c| c.is_ascii_digit()))
+            {
+                TokenKind::Number(self.number()?)
+            } else if c == '$' {
+                self.pos += 1;
+                let name = self.take_while(|c| c.is_alphanumeric() || c == '_');
+                if name.is_empty() {
+                    return Err(self.error(column, "expected a parameter name after '$'"));
+                }
+                TokenKind::Parameter(name)
+            } else if c.is_alphabetic() || c == '_' {
+                TokenKind::Identifier(self.take_while(|c| c.is_alphanumeric() || c == '_'))
+            } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| {
+                symbol
+                    .chars()
+                    .enumerate()
+                    .all(|(i, s)| self.peek(i) == Some(s))
+            }) {
+                self.pos += symbol.chars().count();
+                TokenKind::Symbol(symbol)
+            } else {
+                return Err(self.error(column, format!("unexpected character '{}'", c)));
+            };
+            tokens.push(Token { kind, column });
+        }
+        Ok(tokens)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Column(Vec<(String, bool)>),
+    Literal(String),
+    Parameter {
+        name: String,
+        column: usize,
+    },
+    Nested(Box<Self>),
+    Not(Box<Self>),
+    Negative(Box<Self>),
+    Binary {
+        left: Box<Self>,
+        op: &'static str,
+        right: Box<Self>,
+    },
+    /// `IS NULL`, `IS TRUE` or `IS FALSE`
+    Is {
+        expr: Box<Self>,
+        value: &'static str,
+        negated: bool,
+    },
+    InList {
+        expr: Box<Self>,
+        list: Vec<Self>,
+        negated: bool,
+    },
+    Between {
+        expr: Box<Self>,
+        low: Box<Self>,
+        high: Box<Self>,
+        negated: bool,
+    },
+    Like {
+        expr: Box<Self>,
+        op: &'static str,
+        pattern: Box<Self>,
+        negated: bool,
+    },
+    Function {
+        name: String,
+        args: Vec<Self>,
+        column: usize,
+    },
+    Cast {
+        expr: Box<Self>,
+        data_type: String,
+    },
+    Case {
+        operand: Option<Box<Self>>,
+        branches: Vec<(Self, Self)>,
+        otherwise: Option<Box<Self>>,
+    },
+    Interval {
+        duration: Duration,
+        column: usize,
+    },
+    Element {
+        expr: Box<Self>,
+        index: Box<Self>,
+    },
+    Array(Vec<Self>),
+}
+
+const RESERVED: &[&str] = &[
+    "AND", "OR", "NOT", "IS", "NULL", "IN", "BETWEEN", "LIKE", "ILIKE", "TRUE", "FALSE", "AS",
+    "WHEN", "THEN", "ELSE", "END",
+];
+
+struct Parser<'a> {
+    filter: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, column: usize, message: impl Into<String>) -> Error {
+        Error::InvalidFilter {
+            filter: self.filter.to_string(),
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn end_column(&self) -> usize {
+        self.filter.chars().count() + 1
+    }
+
+    /// An error for the current token, which was not what was `expected`
+    fn unexpected(&self, expected: &str) -> Error {
+        match self.peek() {
+            Some(token) => self.error(
+                token.column,
+                format!("expected {} but found {}", expected, token.kind.describe()),
+            ),
+            None => self.error(
+                self.end_column(),
+                format!("expected {} but the filter ended", expected),
+            ),
+        }
+    }
+
+    fn is_keyword(&self, offset: usize, keyword: &str) -> bool {
+        matches!(
+            self.tokens.get(self.pos + offset),
+            Some(Token { kind: TokenKind::Identifier(name), .. }) if name.eq_ignore_ascii_case(keyword)
+        )
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        if self.is_keyword(0, keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.consume_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(keyword))
+        }
+    }
+
+    fn consume_symbol(&mut self, symbols: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Symbol(symbol),
+                ..
+            }) if symbols.contains(symbol) => {
+                let symbol = *symbol;
+                self.pos += 1;
+                Some(symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &'static str) -> Result<()> {
+        self.consume_symbol(&[symbol])
+            .map(|_| ())
+            .ok_or_else(|| self.unexpected(&format!("'{}'", symbol)))
+    }
+
+    fn parse(mut self) -> Result<Expr> {
+        if self.tokens.is_empty() {
+            return Err(self.error(1, "the filter is empty"));
+        }
+        let expr = self.or()?;
+        if self.peek().is_some() {
+            return Err(self.unexpected("AND, OR, or the end of the filter"));
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.consume_keyword("OR") {
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op: "OR",
+                right: Box::new(self.and()?),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.not()?;
+        while self.consume_keyword("AND") {
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op: "AND",
+                right: Box::new(self.not()?),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.consume_keyword("NOT") {
+            Ok(Expr::Not(Box::new(self.not()?)))
+        } else {
+            self.predicate()
+        }
+    }
+
+    fn predicate(&mut self) -> Result<Expr> {
+        let expr = self.sum()?;
+        if let Some(op) = self.consume_symbol(&["=", "==", "!=", "<>", "<", "<=", ">", ">="]) {
+            let op = if op == "==" { "=" } else { op };
+            return Ok(Expr::Binary {
+                left: Box::new(expr),
+                op,
+                right: Box::new(self.sum()?),
+            });
+        }
+        if self.consume_keyword("IS") {
+            let negated = self.consume_keyword("NOT");
+            if self.consume_keyword("DISTINCT") {
+                self.expect_keyword("FROM")?;
+                return Ok(Expr::Binary {
+                    left: Box::new(expr),
+                    op: if negated {
+                        "IS NOT DISTINCT FROM"
+                    } else {
+                        "IS DISTINCT FROM"
+                    },
+                    right: Box::new(self.sum()?),
+                });
+            }
+            let value = ["NULL", "TRUE", "FALSE"]
+                .into_iter()
+                .find(|value| self.consume_keyword(value))
+                .ok_or_else(|| self.unexpected("NULL, TRUE, FALSE or DISTINCT FROM"))?;
+            return Ok(Expr::Is {
+                expr: Box::new(expr),
+                value,
+                negated,
+            });
+        }
+
+        let negated = self.is_keyword(0, "NOT")
+            && ["IN", "BETWEEN", "LIKE", "ILIKE"]
+                .iter()
+                .any(|keyword| self.is_keyword(1, keyword));
+        if negated {
+            self.pos += 1;
+        }
+        if self.consume_keyword("IN") {
+            self.expect_symbol("(")?;
+            let mut list = vec![self.sum()?];
+            while self.consume_symbol(&[","]).is_some() {
+                list.push(self.sum()?);
+            }
+            self.expect_symbol(")")?;
+            Ok(Expr::InList {
+                expr: Box::new(expr),
+                list,
+                negated,
+            })
+        } else if self.consume_keyword("BETWEEN") {
+            let low = self.sum()?;
+            self.expect_keyword("AND")?;
+            let high = self.sum()?;
+            Ok(Expr::Between {
+                expr: Box::new(expr),
+                low: Box::new(low),
+                high: Box::new(high),
+                negated,
+            })
+        } else if self.is_keyword(0, "LIKE") || self.is_keyword(0, "ILIKE") {
+            let op = if self.consume_keyword("LIKE") {
+                "LIKE"
+            } else {
+                self.pos += 1;
+                "ILIKE"
+            };
+            Ok(Expr::Like {
+                expr: Box::new(expr),
+                op,
+                pattern: Box::new(self.sum()?),
+                negated,
+            })
+        } else {
+            Ok(expr)
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr> {
+        let mut expr = self.product()?;
+        while let Some(op) = self.consume_symbol(&["+", "-"]) {
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op,
+                right: Box::new(self.product()?),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while let Some(op) = self.consume_symbol(&["*", "/", "%", "||"]) {
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op,
+                right: Box::new(self.unary()?),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.consume_symbol(&["-"]).is_some() {
+            Ok(Expr::Negative(Box::new(self.unary()?)))
+        } else {
+            self.element()
+        }
+    }
+
+    fn element(&mut self) -> Result<Expr> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.consume_symbol(&["["]).is_some() {
+                let index = self.or()?;
+                self.expect_symbol("]")?;
+                expr = Expr::Element {
+                    expr: Box::new(expr),
+                    index: Box::new(index),
+                };
+            } else if self.consume_symbol(&["::"]).is_some() {
+                expr = Expr::Cast {
+                    expr: Box::new(expr),
+                    data_type: self.data_type(false)?,
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Expr> {
+        let mut items = Vec::new();
+        if self.consume_symbol(&["]"]).is_none() {
+            items.push(self.or()?);
+            while self.consume_symbol(&[","]).is_some() {
+                items.push(self.or()?);
+            }
+            self.expect_symbol("]")?;
+        }
+        Ok(Expr::Array(items))
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.unexpected("a value"));
+        };
+        match token.kind {
+            TokenKind::Number(number) => {
+                self.pos += 1;
+                Ok(Expr::Literal(number))
+            }
+            TokenKind::String(value) => {
+                self.pos += 1;
+                Ok(Expr::Literal(quote_string(&value)))
+            }
+            TokenKind::Parameter(name) => {
+                self.pos += 1;
+                Ok(Expr::Parameter {
+                    name,
+                    column: token.column,
+                })
+            }
+            TokenKind::Symbol("(") => {
+                self.pos += 1;
+                let expr = self.or()?;
+                self.expect_symbol(")")?;
+                Ok(Expr::Nested(Box::new(expr)))
+            }
+            TokenKind::Symbol("[") => {
+                self.pos += 1;
+                self.array()
+            }
+            TokenKind::QuotedIdentifier(_) => self.column(),
+            TokenKind::Identifier(name) => {
+                let upper = name.to_uppercase();
+                match upper.as_str() {
+                    "NULL" | "TRUE" | "FALSE" => {
+                        self.pos += 1;
+                        Ok(Expr::Literal(upper))
+                    }
+                    "TIMESTAMP" | "DATE"
+                        if matches!(
+                            self.tokens.get(self.pos + 1),
+                            Some(Token {
+                                kind: TokenKind::String(_),
+                                ..
+                            })
+                        ) =>
+                    {
+                        let TokenKind::String(value) = &self.tokens[self.pos + 1].kind else {
+                            unreachable!()
+                        };
+                        let literal = format!("{} {}", upper, quote_string(value));
+                        self.pos += 2;
+                        Ok(Expr::Literal(literal))
+                    }
+                    "INTERVAL"
+                        if matches!(
+                            self.tokens.get(self.pos + 1),
+                            Some(Token {
+                                kind: TokenKind::String(_),
+                                ..
+                            })
+                        ) =>
+                    {
+                        let Token {
+                            kind: TokenKind::String(value),
+                            column,
+                        } = &self.tokens[self.pos + 1]
+                        else {
+                            unreachable!()
+                        };
+                        let duration = parse_interval(value)
+                            .map_err(|message| self.error(*column, message))?;
+                        self.pos += 2;
+                        Ok(Expr::Interval {
+                            duration,
+                            column: token.column,
+                        })
+                    }
+                    "CAST"
+                        if self.tokens.get(self.pos + 1).map(|t| &t.kind)
+                            == Some(&TokenKind::Symbol("(")) =>
+                    {
+                        self.pos += 2;
+                        self.cast()
+                    }
+                    "CASE" => {
+                        self.pos += 1;
+                        self.case()
+                    }
+                    _ if RESERVED.contains(&upper.as_str()) => Err(self.unexpected("a value")),
+                    _ if self.tokens.get(self.pos + 1).map(|t| &t.kind)
+                        == Some(&TokenKind::Symbol("(")) =>
+                    {
+                        self.pos += 2;
+                        self.function(name, token.column)
+                    }
+                    _ => self.column(),
+                }
+            }
+            TokenKind::Symbol(_) => Err(self.unexpected("a value")),
+        }
+    }
+
+    fn name(&mut self) -> Result<(String, bool)> {
+        match self.peek().map(|token| token.kind.clone()) {
+            Some(TokenKind::Identifier(name)) => {
+                self.pos += 1;
+                Ok((name, false))
+            }
+            Some(TokenKind::QuotedIdentifier(name)) => {
+                self.pos += 1;
+                Ok((name, true))
+            }
+            _ => Err(self.unexpected("a column name")),
+        }
+    }
+
+    fn column(&mut self) -> Result<Expr> {
+        let mut path = vec![self.name()?];
+        while self.consume_symbol(&["."]).is_some() {
+            path.push(self.name()?);
+        }
+        Ok(Expr::Column(path))
+    }
+
+    fn function(&mut self, name: String, column: usize) -> Result<Expr> {
+        let mut args = Vec::new();
+        if self.consume_symbol(&[")"]).is_none() {
+            args.push(self.or()?);
+            while self.consume_symbol(&[","]).is_some() {
+                args.push(self.or()?);
+            }
+            self.expect_symbol(")")?;
+        }
+        Ok(Expr::Function { name, args, column })
+    }
+
+    fn cast(&mut self) -> Result<Expr> {
+        let expr = self.or()?;
+        self.expect_keyword("AS")?;
+        let data_type = self.data_type(true)?;
+        self.pos += 1;
+        Ok(Expr::Cast {
+            expr: Box::new(expr),
+            data_type,
+        })
+    }
+
+    /// The type of a cast, which is passed through, e.g. `DECIMAL(10, 2)` or
+    /// `TIMESTAMP`
+    ///
+    /// In `CAST` the type ends at the closing parenthesis, which is not
+    /// consumed.  After `::` it is one name, with its arguments if any.
+    fn data_type(&mut self, in_cast: bool) -> Result<String> {
+        let start = self.pos;
+        let mut depth = 0;
+        loop {
+            let nested = in_cast || depth > 0;
+            match self.peek().map(|token| &token.kind) {
+                None if nested => return Err(self.unexpected("')'")),
+                Some(TokenKind::Symbol("(")) if nested || self.pos > start => depth += 1,
+                Some(TokenKind::Symbol(")")) if depth == 0 => break,
+                Some(TokenKind::Symbol(")")) => depth -= 1,
+                Some(TokenKind::Identifier(_)) if nested || self.pos == start => {}
+                Some(TokenKind::Number(_) | TokenKind::Symbol(",")) if nested => {}
+                Some(_) if nested || self.pos == start => {
+                    return Err(self.unexpected("a data type"))
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(self.unexpected("a data type"));
+        }
+        let mut data_type = String::new();
+        for token in &self.tokens[start..self.pos] {
+            match &token.kind {
+                TokenKind::Identifier(name) | TokenKind::Number(name) => {
+                    if data_type.ends_with(|c: char| c.is_alphanumeric()) {
+                        data_type.push(' ');
+                    }
+                    data_type.push_str(name);
+                }
+                TokenKind::Symbol(",") => data_type.push_str(", "),
+                TokenKind::Symbol(symbol) => data_type.push_str(symbol),
+                _ => unreachable!(),
+            }
+        }
+        Ok(data_type)
+    }
+
+    fn case(&mut self) -> Result<Expr> {
+        let operand = if self.is_keyword(0, "WHEN") {
+            None
+        } else {
+            Some(Box::new(self.or()?))
+        };
+        let mut branches = Vec::new();
+        while self.consume_keyword("WHEN") {
+            let condition = self.or()?;
+            self.expect_keyword("THEN")?;
+            branches.push((condition, self.or()?));
+        }
+        if branches.is_empty() {
+            return Err(self.unexpected("WHEN"));
+        }
+        let otherwise = if self.consume_keyword("ELSE") {
+            Some(Box::new(self.or()?))
+        } else if !self.is_keyword(0, "END") {
+            return Err(self.unexpected("WHEN, ELSE or END"));
+        } else {
+            None
+        };
+        self.expect_keyword("END")?;
+        Ok(Expr::Case {
+            operand,
+            branches,
+            otherwise,
+        })
+    }
+}
+
+impl Expr {
+    fn parameters<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Self::Column(_) | Self::Literal(_) | Self::Interval { .. } => {}
+            Self::Parameter { name, .. } => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+            Self::Nested(expr) | Self::Not(expr) | Self::Negative(expr) => expr.parameters(names),
+            Self::Is { expr, .. } | Self::Cast { expr, .. } => expr.parameters(names),
+            Self::Element { expr, index } => {
+                expr.parameters(names);
+                index.parameters(names);
+            }
+            Self::Array(items) => items.iter().for_each(|item| item.parameters(names)),
+            Self::Binary { left, right, .. } => {
+                left.parameters(names);
+                right.parameters(names);
+            }
+            Self::InList { expr, list, .. } => {
+                expr.parameters(names);
+                list.iter().for_each(|item| item.parameters(names));
+            }
+            Self::Between {
+                expr, low, high, ..
+            } => {
+                expr.parameters(names);
+                low.parameters(names);
+                high.parameters(names);
+            }
+            Self::Like { expr, pattern, .. } => {
+                expr.parameters(names);
+                pattern.parameters(names);
+            }
+            Self::Function { args, .. } => args.iter().for_each(|arg| arg.parameters(names)),
+            Self::Case {
+                operand,
+                branches,
+                otherwise,
+            } => {
+                operand.iter().for_each(|operand| operand.parameters(names));
+                for (condition, result) in branches {
+                    condition.parameters(names);
+                    result.parameters(names);
+                }
+                otherwise
+                    .iter()
+                    .for_each(|otherwise| otherwise.parameters(names));
+            }
+        }
+    }
+
+    /// Whether the expression calls `now()`, so its value depends on when it
+    /// is converted to SQL
+    fn calls_now(&self) -> bool {
+        match self {
+            Self::Column(_) | Self::Literal(_) | Self::Parameter { .. } | Self::Interval { .. } => {
+                false
+            }
+            Self::Nested(expr) | Self::Not(expr) | Self::Negative(expr) => expr.calls_now(),
+            Self::Is { expr, .. } | Self::Cast { expr, .. } => expr.calls_now(),
+            Self::Element { expr, index } => expr.calls_now() || index.calls_now(),
+            Self::Array(items) => items.iter().any(Self::calls_now),
+            Self::Binary { left, right, .. } => left.calls_now() || right.calls_now(),
+            Self::InList { expr, list, .. } => expr.calls_now() || list.iter().any(Self::calls_now),
+            Self::Between {
+                expr, low, high, ..
+            } => expr.calls_now() || low.calls_now() || high.calls_now(),
+            Self::Like { expr, pattern, .. } => expr.calls_now() || pattern.calls_now(),
+            Self::Function { name, args, .. } => {
+                is_now(name, args) || args.iter().any(Self::calls_now)
+            }
+            Self::Case {
+                operand,
+                branches,
+                otherwise,
+            } => {
+                operand.as_deref().is_some_and(Self::calls_now)
+                    || branches
+                        .iter()
+                        .any(|(condition, result)| condition.calls_now() || result.calls_now())
+                    || otherwise.as_deref().is_some_and(Self::calls_now)
+            }
+        }
+    }
+
+    /// The value bound to this parameter
+    fn parameter_value<'a>(&self, filter: &'a Filter) -> Result<&'a FilterValue> {
+        let Self::Parameter { name, column } = self else {
+            unreachable!("only parameters have values")
+        };
+        filter.params.get(name).ok_or_else(|| Error::InvalidFilter {
+            filter: filter.text.clone(),
+            column: *column,
+            message: format!("no value was bound to the parameter ${}", name),
+        })
+    }
+
+    /// The time this expression evaluates to, None if it is not a constant time
+    ///
+    /// Timestamp literals are naive, the wall clock time of the column they
+    /// are compared with.
+    fn time(&self, filter: &Filter) -> Result<Option<NaiveDateTime>> {
+        let literal = |literal: &str, prefix: &str| {
+            literal
+                .strip_prefix(prefix)
+                .and_then(|value| value.strip_prefix('\''))
+                .and_then(|value| value.strip_suffix('\''))
+                .map(str::to_string)
+        };
+        Ok(match self {
+            Self::Function { name, args, .. } if is_now(name, args) => Some(filter.now()),
+            Self::Literal(value) => {
+                if let Some(value) = literal(value, "TIMESTAMP ") {
+                    [
+                        "%Y-%m-%d %H:%M:%S%.f",
+                        "%Y-%m-%dT%H:%M:%S%.f",
+                        "%Y-%m-%d %H:%M",
+                    ]
+                    .iter()
+                    .find_map(|format| NaiveDateTime::parse_from_str(&value, format).ok())
+                    .or_else(|| {
+                        NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                            .ok()
+                            .map(|date| date.and_time(NaiveTime::MIN))
+                    })
+                } else if let Some(value) = literal(value, "DATE ") {
+                    NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                        .ok()
+                        .map(|date| date.and_time(NaiveTime::MIN))
+                } else {
+                    None
+                }
+            }
+            Self::Parameter { .. } => match self.parameter_value(filter)? {
+                FilterValue::Timestamp(time) => Some(time.naive_utc()),
+                FilterValue::Date(date) => Some(date.and_time(NaiveTime::MIN)),
+                _ => None,
+            },
+            Self::Nested(expr) => expr.time(filter)?,
+            Self::Binary { left, op, right } => match (op, left.as_ref(), right.as_ref()) {
+                (&"+", Self::Interval { duration, .. }, time)
+                | (&"+", time, Self::Interval { duration, .. }) => time
+                    .time(filter)?
+                    .and_then(|time| time.checked_add_signed(*duration)),
+                (&"-", time, Self::Interval { duration, .. }) => time
+                    .time(filter)?
+                    .and_then(|time| time.checked_sub_signed(*duration)),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    /// The constant value of this expression, None if it is not a constant
+    fn value(&self, filter: &Filter) -> Result<Option<FilterValue>> {
+        Ok(match self {
+            Self::Literal(literal) => match literal.as_str() {
+                "NULL" => Some(FilterValue::Null),
+                "TRUE" => Some(FilterValue::Bool(true)),
+                "FALSE" => Some(FilterValue::Bool(false)),
+                _ if literal.starts_with('\'') => literal
+                    .strip_prefix('\'')
+                    .and_then(|value| value.strip_suffix('\''))
+                    .map(|value| FilterValue::String(value.replace("''", "'"))),
+                _ if literal.starts_with("DATE ") => self
+                    .time(filter)?
+                    .map(|time| FilterValue::Date(time.date())),
+                _ => literal
+                    .parse()
+                    .map(FilterValue::Int)
+                    .or_else(|_| literal.parse().map(FilterValue::Float))
+                    .ok(),
+            },
+            Self::Negative(expr) => match expr.value(filter)? {
+                Some(FilterValue::Int(value)) => Some(FilterValue::Int(-value)),
+                Some(FilterValue::Float(value)) => Some(FilterValue::Float(-value)),
+                _ => None,
+            },
+            Self::Nested(expr) => expr.value(filter)?,
+            Self::Parameter { .. } => Some(self.parameter_value(filter)?.clone()),
+            _ => None,
+        })
+    }
+
+    /// The values `column` is compared to, if this expression is `column = value`
+    /// or `column IN (values)`
+    fn equality_values(&self, filter: &Filter, column: &str) -> Result<Option<Vec<FilterValue>>> {
+        let is_column = |expr: &Self| expr.column_name() == Some(column);
+        let values = match self {
+            Self::Nested(expr) => return expr.equality_values(filter, column),
+            Self::Binary {
+                left,
+                op: "=",
+                right,
+            } if is_column(left) => vec![right.value(filter)?],
+            Self::Binary {
+                left,
+                op: "=",
+                right,
+            } if is_column(right) => vec![left.value(filter)?],
+            Self::InList {
+                expr,
+                list,
+                negated: false,
+            } if is_column(expr) => list
+                .iter()
+                .map(|item| item.value(filter))
+                .collect::<Result<Vec<_>>>()?,
+            _ => return Ok(None),
+        };
+        // A list bound to the only item of the IN list is its list of values
+        Ok(values
+            .into_iter()
+            .map(|value| match value {
+                Some(FilterValue::List(values)) => Some(values),
+                value => value.map(|value| vec![value]),
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|values| values.into_iter().flatten().collect()))
+    }
+
+    /// The column compared by this expression, if it is a top level column
+    fn column_name(&self) -> Option<&str> {
+        match self {
+            Self::Column(path) if path.len() == 1 => Some(path[0].0.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Add the time bounds of the columns this expression restricts to `bounds`
+    ///
+    /// Only the conditions which every row must meet are used, those combined
+    /// with AND.
+    fn time_bounds(&self, filter: &Filter, bounds: &mut HashMap<String, TimeBounds>) {
+        let time = |expr: &Self| expr.time(filter).ok().flatten();
+        match self {
+            Self::Nested(expr) => expr.time_bounds(filter, bounds),
+            Self::Binary {
+                left,
+                op: "AND",
+                right,
+            } => {
+                left.time_bounds(filter, bounds);
+                right.time_bounds(filter, bounds);
+            }
+            Self::Binary { left, op, right } => {
+                let (column, op, time) = match (left.column_name(), right.column_name()) {
+                    (Some(column), _) => (column, *op, time(right)),
+                    // `time < column` is `column > time`
+                    (None, Some(column)) => {
+                        let op = match *op {
+                            "<" => ">",
+                            "<=" => ">=",
+                            ">" => "<",
+                            ">=" => "<=",
+                            op => op,
+                        };
+                        (column, op, time(left))
+                    }
+                    _ => return,
+                };
+                let Some(time) = time else {
+                    return;
+                };
+                let (lower, upper) = match op {
+                    "=" => (Some(time), Some(time)),
+                    ">" | ">=" => (Some(time), None),
+                    "<" | "<=" => (None, Some(time)),
+                    _ => return,
+                };
+                bounds
+                    .entry(column.to_string())
+                    .or_default()
+                    .restrict(lower, upper);
+            }
+            Self::Between {
+                expr,
+                low,
+                high,
+                negated: false,
+            } => {
+                if let Some(column) = expr.column_name() {
+                    bounds
+                        .entry(column.to_string())
+                        .or_default()
+                        .restrict(time(low), time(high));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The value of a numeric argument, None if it is not a number
+    fn number(&self, filter: &Filter) -> Result<Option<f64>> {
+        Ok(match self {
+            Self::Literal(literal) => literal.parse().ok(),
+            Self::Negative(expr) => expr.number(filter)?.map(|value| -value),
+            Self::Nested(expr) => expr.number(filter)?,
+            Self::Parameter { .. } => match self.parameter_value(filter)? {
+                FilterValue::Int(value) => Some(*value as f64),
+                FilterValue::Float(value) => Some(*value),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    /// The SQL of a call to one of the [spatial functions](super::geo)
+    fn geo_to_sql(
+        function: GeoFunction,
+        args: &[Self],
+        column: usize,
+        filter: &Filter,
+    ) -> Result<String> {
+        let error = |message: String| Error::InvalidFilter {
+            filter: filter.text.clone(),
+            column,
+            message,
+        };
+        let [lat, lon, values @ ..] = args else {
+            return Err(error(format!("expected {}", function.signature())));
+        };
+        if values.len() != function.num_values() {
+            return Err(error(format!("expected {}", function.signature())));
+        }
+        let values = values
+            .iter()
+            .map(|value| {
+                value.number(filter)?.ok_or_else(|| {
+                    error(format!(
+                        "the arguments of {} after the columns must be numbers",
+                        function.signature()
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        function
+            .to_sql(&lat.to_sql(filter)?, &lon.to_sql(filter)?, &values)
+            .map_err(error)
+    }
+
+    /// The SQL of a call to [`json_extract`](super::json)
+    fn json_extract_to_sql(args: &[Self], column: usize, filter: &Filter) -> Result<String> {
+        let error = |message: String| Error::InvalidFilter {
+            filter: filter.text.clone(),
+            column,
+            message,
+        };
+        let [document, path] = args else {
+            return Err(error("expected json_extract(column, path)".to_string()));
+        };
+        let Some(FilterValue::String(path)) = path.value(filter)? else {
+            return Err(error(
+                "the path of json_extract must be a string".to_string(),
+            ));
+        };
+        json_extract_sql(&document.to_sql(filter)?, &path).map_err(error)
+    }
+
+    fn to_sql(&self, filter: &Filter) -> Result<String> {
+        let not = |negated: bool| if negated { "NOT " } else { "" };
+        Ok(match self {
+            Self::Column(path) => path
+                .iter()
+                .map(|(name, quoted)| {
+                    if *quoted {
+                        quote_identifier(name)
+                    } else {
+                        name.clone()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("."),
+            Self::Literal(literal) => literal.clone(),
+            Self::Parameter { name, column } => match self.parameter_value(filter)? {
+                FilterValue::List(_) => {
+                    return Err(Error::InvalidFilter {
+                        filter: filter.text.clone(),
+                        column: *column,
+                        message: format!(
+                            "a list was bound to the parameter ${} which is not the only item of an IN list",
+                            name
+                        ),
+                    })
+                }
+                value => value.to_sql(),
+            },
+            Self::Nested(expr) => format!("({})", expr.to_sql(filter)?),
+            Self::Not(expr) => format!("NOT {}", expr.to_sql(filter)?),
+            Self::Negative(expr) => format!("-{}", expr.to_sql(filter)?),
+            Self::Binary { left, right, .. }
+                if matches!(left.as_ref(), Self::Interval { .. })
+                    || matches!(right.as_ref(), Self::Interval { .. }) =>
+            {
+                match self.time(filter)? {
+                    Some(time) => timestamp_sql(&time),
+                    None => return Err(Self::interval_error(left, right, filter)),
+                }
+            }
+            Self::Binary { left, op, right } => {
+                format!("{} {} {}", left.to_sql(filter)?, op, right.to_sql(filter)?)
+            }
+            Self::Is {
+                expr,
+                value,
+                negated,
+            } => {
+                format!("{} IS {}{}", expr.to_sql(filter)?, not(*negated), value)
+            }
+            Self::InList {
+                expr,
+                list,
+                negated,
+            } => format!(
+                "{} {}IN ({})",
+                expr.to_sql(filter)?,
+                not(*negated),
+                match list.as_slice() {
+                    [item @ Self::Parameter { .. }] => item.parameter_value(filter)?.to_sql(),
+                    _ => list
+                        .iter()
+                        .map(|item| item.to_sql(filter))
+                        .collect::<Result<Vec<_>>>()?
+                        .join(", "),
+                }
+            ),
+            Self::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => format!(
+                "{} {}BETWEEN {} AND {}",
+                expr.to_sql(filter)?,
+                not(*negated),
+                low.to_sql(filter)?,
+                high.to_sql(filter)?
+            ),
+            Self::Like {
+                expr,
+                op,
+                pattern,
+                negated,
+            } => format!(
+                "{} {}{} {}",
+                expr.to_sql(filter)?,
+                not(*negated),
+                op,
+                pattern.to_sql(filter)?
+            ),
+            Self::Function { name, args, .. } if is_now(name, args) => {
+                timestamp_sql(&filter.now())
+            }
+            Self::Function { name, args, column } => {
+                if let Some(function) = GeoFunction::from_name(name) {
+                    return Self::geo_to_sql(function, args, *column, filter);
+                }
+                if name.eq_ignore_ascii_case("json_extract") {
+                    return Self::json_extract_to_sql(args, *column, filter);
+                }
+                format!(
+                "{}({})",
+                name,
+                args.iter()
+                    .map(|arg| arg.to_sql(filter))
+                    .collect::<Result<Vec<_>>>()?
+                    .join(", ")
+            )}
+            Self::Cast { expr, data_type } => {
+                format!("CAST({} AS {})", expr.to_sql(filter)?, data_type)
+            }
+            Self::Case {
+                operand,
+                branches,
+                otherwise,
+            } => {
+                let mut sql = "CASE".to_string();
+                if let Some(operand) = operand {
+                    sql.push_str(&format!(" {}", operand.to_sql(filter)?));
+                }
+                for (condition, result) in branches {
+                    sql.push_str(&format!(
+                        " WHEN {} THEN {}",
+                        condition.to_sql(filter)?,
+                        result.to_sql(filter)?
+                    ));
+                }
+                if let Some(otherwise) = otherwise {
+                    sql.push_str(&format!(" ELSE {}", otherwise.to_sql(filter)?));
+                }
+                sql.push_str(" END");
+                sql
+            }
+            Self::Interval { .. } => return Err(Self::interval_error(self, self, filter)),
+            Self::Element { expr, index } => {
+                format!("{}[{}]", expr.to_sql(filter)?, index.to_sql(filter)?)
+            }
+            Self::Array(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|item| item.to_sql(filter))
+                    .collect::<Result<Vec<_>>>()?
+                    .join(", ")
+            ),
+        })
+    }
+
+    /// The error for an interval which is not added to or subtracted from a time
+    fn interval_error(left: &Self, right: &Self, filter: &Filter) -> Error {
+        let column = match (left, right) {
+            (Self::Interval { column, .. }, _) | (_, Self::Interval { column, .. }) => *column,
+            _ => unreachable!("one of the operands is an interval"),
+        };
+        Error::InvalidFilter {
+            filter: filter.text.clone(),
+            column,
+            message: "an interval can only be added to or subtracted from now(), \
+                      a timestamp or a date"
+                .to_string(),
+        }
+    }
+}
+
+fn is_now(name: &str, args: &[Expr]) -> bool {
+    name.eq_ignore_ascii_case("now") && args.is_empty()
+}
+
+/// A parsed filter
+///
+/// See the [module docs](self) for the grammar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    text: String,
+    expr: Expr,
+    params: HashMap<String, FilterValue>,
+    now: Option<DateTime<Utc>>,
+}
+
+impl Filter {
+    /// Parse a filter, returning [`Error::InvalidFilter`] if it is not valid
+    pub fn parse(text: impl Into<String>) -> Result<Self> {
+        let text = text.into();
+        let tokens = Lexer::new(&text).tokenize()?;
+        let expr = Parser {
+            filter: &text,
+            tokens,
+            pos: 0,
+        }
+        .parse()?;
+        Ok(Self {
+            text,
+            expr,
+            params: HashMap::new(),
+            now: None,
+        })
+    }
+
+    /// The names of the parameters in the filter, in the order they first appear
+    pub fn parameters(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.expr.parameters(&mut names);
+        names
+    }
+
+    /// Whether the filter calls `now()`, so that it selects different rows
+    /// depending on when it runs
+    pub fn calls_now(&self) -> bool {
+        self.expr.calls_now()
+    }
+
+    /// Bind a value to the parameter `$name`
+    pub fn bind(mut self, name: impl Into<String>, value: impl Into<FilterValue>) -> Self {
+        self.params.insert(name.into(), value.into());
+        self
+    }
+
+    /// Bind `values` to the numbered parameters, the first value to `$1`
+    pub fn bind_all(self, values: impl IntoIterator<Item = FilterValue>) -> Self {
+        values
+            .into_iter()
+            .enumerate()
+            .fold(self, |filter, (i, value)| {
+                filter.bind((i + 1).to_string(), value)
+            })
+    }
+
+    /// Evaluate `now()` as `time` instead of the time the filter is converted
+    pub fn at<Tz: chrono::TimeZone>(mut self, time: DateTime<Tz>) -> Self {
+        self.now = Some(time.with_timezone(&Utc));
+        self
+    }
+
+    fn now(&self) -> NaiveDateTime {
+        self.now.expect("the time is set by to_sql").naive_utc()
+    }
+
+    /// The time bounds of the columns restricted to a range of times
+    ///
+    /// Parameters without a value are ignored.  This is used to skip the
+    /// fragments of a table outside of the range, see
+    /// [`crate::table::fragment_stats`].
+    pub(crate) fn time_bounds(&self) -> HashMap<String, TimeBounds> {
+        let filter = self.clone().at(self.now.unwrap_or_else(Utc::now));
+        let mut bounds = HashMap::new();
+        filter.expr.time_bounds(&filter, &mut bounds);
+        bounds
+    }
+
+    /// The values `column` must be equal to for a row to match the filter
+    ///
+    /// This is only known for the filters `column = value` and `column IN
+    /// (values)`, where the values are constants or parameters.
+    pub(crate) fn equality_values(&self, column: &str) -> Option<Vec<FilterValue>> {
+        self.expr.equality_values(self, column).ok().flatten()
+    }
+
+    /// Convert the filter to SQL
+    ///
+    /// Every parameter must have a value and every bound value must be used.
+    pub fn to_sql(&self) -> Result<String> {
+        let parameters = self.parameters();
+        if let Some(name) = self
+            .params
+            .keys()
+            .find(|name| !parameters.contains(&name.as_str()))
+        {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "a value was bound to the parameter ${} which is not in the filter \"{}\"",
+                    name, self.text
+                ),
+            });
+        }
+        // The same time is used for every `now()` in the filter
+        let filter = self.clone().at(self.now.unwrap_or_else(Utc::now));
+        self.expr.to_sql(&filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sql(filter: &str) -> String {
+        Filter::parse(filter).unwrap().to_sql().unwrap()
+    }
+
+    fn error_column(filter: &str) -> usize {
+        match Filter::parse(filter) {
+            Err(Error::InvalidFilter { column, .. }) => column,
+            other => panic!("expected an invalid filter error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(sql("x > 10"), "x > 10");
+        assert_eq!(sql("x == 1 and y != 'it''s'"), "x = 1 AND y != 'it''s'");
+        assert_eq!(sql("(a OR b) AND NOT c"), "(a OR b) AND NOT c");
+        assert_eq!(sql("`my col` IS NOT NULL"), "`my col` IS NOT NULL");
+        assert_eq!(
+            sql("s.inner_field IN (1, 2.5, -3)"),
+            "s.inner_field IN (1, 2.5, -3)"
+        );
+        assert_eq!(sql("x not between 1 and 1e3"), "x NOT BETWEEN 1 AND 1e3");
+        assert_eq!(sql("name ilike 'a%'"), "name ILIKE 'a%'");
+        assert_eq!(
+            sql("ts > timestamp '2024-01-01' AND d <= date '2024-01-01'"),
+            "ts > TIMESTAMP '2024-01-01' AND d <= DATE '2024-01-01'"
+        );
+        assert_eq!(
+            sql("regexp_match(name, 'a.*') IS NOT NULL"),
+            "regexp_match(name, 'a.*') IS NOT NULL"
+        );
+        assert_eq!(
+            sql("cast(x AS decimal(10, 2)) * 2 > 1"),
+            "CAST(x AS decimal(10, 2)) * 2 > 1"
+        );
+        assert_eq!(sql("flag = true"), "flag = TRUE");
+    }
+
+    #[test]
+    fn test_lists_and_booleans() {
+        // Filters which were passed to the query engine before the grammar
+        assert_eq!(
+            sql("array_has_any(tags, ['a', 'b'])"),
+            "array_has_any(tags, ['a', 'b'])"
+        );
+        assert_eq!(sql("tags[1] = 'x'"), "tags[1] = 'x'");
+        assert_eq!(
+            sql("matrix[1][id + 1] > 0 AND array_length([]) = 0"),
+            "matrix[1][id + 1] > 0 AND array_length([]) = 0"
+        );
+        assert_eq!(sql("flag is true"), "flag IS TRUE");
+        assert_eq!(sql("flag IS NOT false"), "flag IS NOT FALSE");
+        assert_eq!(
+            Filter::parse("array_has_any(tags, [$1, $2])")
+                .unwrap()
+                .bind_all(["a".into(), "b".into()])
+                .to_sql()
+                .unwrap(),
+            "array_has_any(tags, ['a', 'b'])"
+        );
+        assert_eq!(error_column("tags[1 = 'x'"), 13);
+        assert_eq!(error_column("flag IS 1"), 9);
+    }
+
+    #[test]
+    fn test_dialect() {
+        // Double quotes are strings, as in the query engine
+        assert_eq!(sql("item = \"fizz\""), "item = 'fizz'");
+        assert_eq!(sql("item = \"it's \"\"x\"\"\""), "item = 'it''s \"x\"'");
+        assert_eq!(
+            sql("first || ' ' || last = 'a b'"),
+            "first || ' ' || last = 'a b'"
+        );
+        assert_eq!(sql("x::int > 1"), "CAST(x AS int) > 1");
+        assert_eq!(
+            sql("-price::decimal(10,2) < s.y::bigint * 2"),
+            "-CAST(price AS decimal(10, 2)) < CAST(s.y AS bigint) * 2"
+        );
+        assert_eq!(
+            sql("case when x > 1 then 'big' when x > 0 then \"small\" else 'none' end = 'big'"),
+            "CASE WHEN x > 1 THEN 'big' WHEN x > 0 THEN 'small' ELSE 'none' END = 'big'"
+        );
+        let filter = Filter::parse("CASE category WHEN $1 THEN now() END IS NULL").unwrap();
+        assert_eq!(filter.parameters(), vec!["1"]);
+        assert!(filter.calls_now());
+        assert_eq!(
+            filter
+                .bind("1", "a")
+                .at(DateTime::parse_from_rfc3339("2024-03-08T12:00:00Z").unwrap())
+                .to_sql()
+                .unwrap(),
+            "CASE category WHEN 'a' THEN TIMESTAMP '2024-03-08 12:00:00' END IS NULL"
+        );
+        assert_eq!(
+            sql("x IS DISTINCT FROM y AND z is not distinct from 1"),
+            "x IS DISTINCT FROM y AND z IS NOT DISTINCT FROM 1"
+        );
+        assert_eq!(error_column("CASE WHEN x THEN 1"), 19);
+        assert_eq!(error_column("CASE x END"), 8);
+        assert_eq!(error_column("x:: > 1"), 5);
+        assert_eq!(error_column("x IS DISTINCT y"), 15);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(error_column(""), 1);
+        assert_eq!(error_column("x > AND y"), 5);
+        assert_eq!(error_column("x > 1 y"), 7);
+        assert_eq!(error_column("x = 'abc"), 5);
+        assert_eq!(error_column("x IN (1, 2"), 11);
+        assert_eq!(error_column("x # 1"), 3);
+        assert_eq!(error_column("x = 12abc"), 5);
+
+        let err = Filter::parse("x > AND y").unwrap_err().to_string();
+        assert!(err.contains("expected a value but found 'AND' at column 5"));
+    }
+
+    #[test]
+    fn test_parameters() {
+        let filter =
+            Filter::parse("category = $category AND price < $max OR price < $max").unwrap();
+        assert_eq!(filter.parameters(), vec!["category", "max"]);
+        assert!(matches!(
+            filter.to_sql(),
+            Err(Error::InvalidFilter { column: 12, .. })
+        ));
+
+        let filter = filter
+            .bind("category", "it's")
+            .bind("max", 10.5)
+            .to_sql()
+            .unwrap();
+        assert_eq!(
+            filter,
+            "category = 'it''s' AND price < 10.5 OR price < 10.5"
+        );
+
+        assert!(Filter::parse("x = $x")
+            .unwrap()
+            .bind("x", 1)
+            .bind("y", 2)
+            .to_sql()
+            .is_err());
+        assert_eq!(
+            Filter::parse("x = $x")
+                .unwrap()
+                .bind("x", None::<i32>)
+                .to_sql()
+                .unwrap(),
+            "x = NULL"
+        );
+    }
+
+    #[test]
+    fn test_numbered_parameters() {
+        let since = DateTime::parse_from_rfc3339("2024-03-01T12:30:00.5Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let filter = Filter::parse("user_id = $1 AND tag NOT IN ($2) AND created > $3 OR d = $4")
+            .unwrap()
+            .bind_all([
+                "x' OR 1=1 --".into(),
+                vec!["a", "b'c"].into(),
+                since.into(),
+                NaiveDate::from_ymd_opt(2024, 1, 2).unwrap().into(),
+            ]);
+        assert_eq!(
+            filter.to_sql().unwrap(),
+            "user_id = 'x'' OR 1=1 --' AND tag NOT IN ('a', 'b''c') \
+             AND created > TIMESTAMP '2024-03-01 12:30:00.500' OR d = DATE '2024-01-02'"
+        );
+        assert_eq!(
+            Filter::parse("x IN ($1)")
+                .unwrap()
+                .bind_all([Vec::<i32>::new().into()])
+                .to_sql()
+                .unwrap(),
+            "x IN (NULL)"
+        );
+        // A list cannot be used outside of an IN list
+        assert!(matches!(
+            Filter::parse("x = $1")
+                .unwrap()
+                .bind_all([vec![1, 2].into()])
+                .to_sql(),
+            Err(Error::InvalidFilter { column: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn test_time() {
+        let now = DateTime::parse_from_rfc3339("2024-03-08T12:00:00Z").unwrap();
+        let sql = |filter: &str| Filter::parse(filter).unwrap().at(now).to_sql().unwrap();
+        assert_eq!(
+            sql("created_at > now() - INTERVAL '7 days'"),
+            "created_at > TIMESTAMP '2024-03-01 12:00:00'"
+        );
+        assert_eq!(
+            sql("created_at < DATE '2024-01-01' + interval '1 day 12 hours'"),
+            "created_at < TIMESTAMP '2024-01-02 12:00:00'"
+        );
+        assert_eq!(sql("x <= NOW()"), "x <= TIMESTAMP '2024-03-08 12:00:00'");
+        let calls_now = |filter: &str| Filter::parse(filter).unwrap().calls_now();
+        assert!(calls_now(
+            "a > 1 AND (b < now() - INTERVAL '1 day' OR b IS NULL)"
+        ));
+        assert!(!calls_now("a > 1 AND b < TIMESTAMP '2024-03-08 12:00:00'"));
+        assert_eq!(error_column("x > now() - interval '1 month'"), 22);
+        assert!(matches!(
+            Filter::parse("x > INTERVAL '1 day'").unwrap().to_sql(),
+            Err(Error::InvalidFilter { column: 5, .. })
+        ));
+
+        let bounds = Filter::parse(
+            "(a >= now() - interval '1 hour' AND now() > a) AND b BETWEEN $1 AND DATE '2024-02-01' \
+             AND a < TIMESTAMP '2024-03-08 11:30:00' AND (c > now() OR c IS NULL)",
+        )
+        .unwrap()
+        .bind("1", NaiveDate::from_ymd_opt(2024, 1, 1).unwrap())
+        .at(now)
+        .time_bounds();
+        let time =
+            |text: &str| Some(NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap());
+        assert_eq!(
+            bounds["a"],
+            TimeBounds {
+                lower: time("2024-03-08 11:00"),
+                upper: time("2024-03-08 11:30"),
+            }
+        );
+        assert_eq!(
+            bounds["b"],
+            TimeBounds {
+                lower: time("2024-01-01 00:00"),
+                upper: time("2024-02-01 00:00"),
+            }
+        );
+        assert!(!bounds.contains_key("c"));
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Spatial functions of filters
+//!
+//! Points are stored as two numeric columns, their latitude and longitude in
+//! degrees.  Two functions can be used in filters (see [`super::filter`]):
+//!
+//! * `st_dwithin(lat_column, lon_column, lat, lon, radius)` matches the points
+//!   at most `radius` meters away from the point (`lat`, `lon`)
+//! * `st_within_bbox(lat_column, lon_column, min_lat, min_lon, max_lat, max_lon)`
+//!   matches the points in a bounding box.  The box crosses the antimeridian
+//!   when `min_lon` is greater than `max_lon`.
+//!
+//! The arguments after the columns must be numbers or parameters.  The
+//! functions are converted to comparisons of the columns.  Both start with the
+//! bounding box of the area, which scalar indices on the latitude and
+//! longitude columns speed up, and `st_dwithin` then checks the distance of
+//! the points in the box.  The distance is an equirectangular approximation,
+//! accurate for radii of up to a few hundred kilometers, which is the common
+//! case of a "near me" search combined with a vector search:
+//!
+//! ```ignore
+//! table
+//!     .query()
+//!     .nearest_to(&embedding)?
+//!     .only_if("st_dwithin(lat, lon, $lat, $lon, 5000)")
+//!     .bind("lat", 40.7128)
+//!     .bind("lon", -74.006)
+//! ```
+
+/// The mean radius of the earth, in meters
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// A spatial function of a filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum GeoFunction {
+    DWithin,
+    WithinBbox,
+}
+
+/// A number in SQL, in parentheses if it is negative
+fn number(value: f64) -> String {
+    if value < 0.0 {
+        format!("({:?})", value)
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// The filter `low <= expr <= high`
+///
+/// This is not `BETWEEN`, which the query engine does not plan.
+fn range_sql(expr: &str, low: f64, high: f64) -> String {
+    format!(
+        "{} >= {} AND {} <= {}",
+        expr,
+        number(low),
+        expr,
+        number(high)
+    )
+}
+
+fn check_point(lat: f64, lon: f64) -> std::result::Result<(), String> {
+    if !(-90.0..=90.0).contains(&lat) {
+        return Err(format!("the latitude {} is not between -90 and 90", lat));
+    }
+    if !(-180.0..=180.0).contains(&lon) {
+        return Err(format!("the longitude {} is not between -180 and 180", lon));
+    }
+    Ok(())
+}
+
+/// The filter matching the points in the box, `min_lon` is greater than
+/// `max_lon` if the box crosses the antimeridian
+fn bbox_sql(
+    lat: &str,
+    lon: &str,
+    min_lat: f64,
+    min_lon: f64,
+    max_lat: f64,
+    max_lon: f64,
+) -> String {
+    let lon_filter = if min_lon <= max_lon {
+        range_sql(lon, min_lon, max_lon)
+    } else {
+        format!(
+            "({} >= {} OR {} <= {})",
+            lon,
+            number(min_lon),
+            lon,
+            number(max_lon)
+        )
+    };
+    format!("{} AND {}", range_sql(lat, min_lat, max_lat), lon_filter)
+}
+
+fn dwithin_sql(
+    lat: &str,
+    lon: &str,
+    center_lat: f64,
+    center_lon: f64,
+    radius: f64,
+) -> std::result::Result<String, String> {
+    check_point(center_lat, center_lon)?;
+    if !(radius.is_finite() && radius >= 0.0) {
+        return Err(format!("the radius {} is not a positive distance", radius));
+    }
+    // The radius in degrees of latitude, and the length of a degree of
+    // longitude relative to a degree of latitude at the center
+    let dlat = (radius / EARTH_RADIUS).to_degrees();
+    let scale = center_lat.to_radians().cos();
+    if center_lat.abs() + dlat >= 90.0 {
+        return Err("the area reaches a pole, which is not supported".to_string());
+    }
+    let dlon = dlat / scale;
+    if dlon >= 180.0 {
+        return Err("the area is wider than the earth".to_string());
+    }
+    // The ranges of longitude of the area, with the offset which brings them
+    // next to the center when the area crosses the antimeridian
+    let (min_lon, max_lon) = (center_lon - dlon, center_lon + dlon);
+    let ranges = if min_lon < -180.0 {
+        vec![(min_lon + 360.0, 180.0, -360.0), (-180.0, max_lon, 0.0)]
+    } else if max_lon > 180.0 {
+        vec![(min_lon, 180.0, 0.0), (-180.0, max_lon - 360.0, 360.0)]
+    } else {
+        vec![(min_lon, max_lon, 0.0)]
+    };
+    let delta_lat = format!("({} - {})", lat, number(center_lat));
+    let ranges = ranges
+        .into_iter()
+        .map(|(min_lon, max_lon, offset)| {
+            let delta_lon = if offset == 0.0 {
+                format!("({} - {})", lon, number(center_lon))
+            } else {
+                format!("({} + {} - {})", lon, number(offset), number(center_lon))
+            };
+            format!(
+                "{} AND {} * {} + {} * {} * {} <= {}",
+                range_sql(lon, min_lon, max_lon),
+                delta_lat,
+                delta_lat,
+                delta_lon,
+                delta_lon,
+                number(scale * scale),
+                number(dlat * dlat)
+            )
+        })
+        .collect::<Vec<_>>();
+    let lon_filter = match ranges.as_slice() {
+        [range] => range.clone(),
+        _ => format!("(({}))", ranges.join(") OR (")),
+    };
+    Ok(format!(
+        "({} AND {})",
+        range_sql(lat, center_lat - dlat, center_lat + dlat),
+        lon_filter
+    ))
+}
+
+impl GeoFunction {
+    /// The spatial function called `name`, if any
+    pub(super) fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "st_dwithin" => Some(Self::DWithin),
+            "st_within_bbox" => Some(Self::WithinBbox),
+            _ => None,
+        }
+    }
+
+    pub(super) fn signature(&self) -> &'static str {
+        match self {
+            Self::DWithin => "st_dwithin(lat_column, lon_column, lat, lon, radius)",
+            Self::WithinBbox => {
+                "st_within_bbox(lat_column, lon_column, min_lat, min_lon, max_lat, max_lon)"
+            }
+        }
+    }
+
+    /// The number of arguments after the two columns
+    pub(super) fn num_values(&self) -> usize {
+        match self {
+            Self::DWithin => 3,
+            Self::WithinBbox => 4,
+        }
+    }
+
+    /// The SQL of the function applied to the columns `lat` and `lon`, which
+    /// are SQL expressions, and to `values`
+    ///
+    /// Returns the reason the values are not valid if they are not.
+    pub(super) fn to_sql(
+        self,
+        lat: &str,
+        lon: &str,
+        values: &[f64],
+    ) -> std::result::Result<String, String> {
+        match (self, values) {
+            (Self::DWithin, &[center_lat, center_lon, radius]) => {
+                dwithin_sql(lat, lon, center_lat, center_lon, radius)
+            }
+            (Self::WithinBbox, &[min_lat, min_lon, max_lat, max_lon]) => {
+                check_point(min_lat, min_lon)?;
+                check_point(max_lat, max_lon)?;
+                if min_lat > max_lat {
+                    return Err(format!(
+                        "the minimum latitude {} is greater than the maximum latitude {}",
+                        min_lat, max_lat
+                    ));
+                }
+                Ok(format!(
+                    "({})",
+                    bbox_sql(lat, lon, min_lat, min_lon, max_lat, max_lon)
+                ))
+            }
+            _ => unreachable!("the number of values is checked by the caller"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Float64Array, Int32Array, RecordBatch, RecordBatchIterator};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::error::Error;
+    use crate::query::filter::Filter;
+
+    #[test]
+    fn test_to_sql() {
+        let sql = GeoFunction::WithinBbox
+            .to_sql("lat", "lon", &[1.0, 170.0, 2.0, -170.0])
+            .unwrap();
+        assert_eq!(
+            sql,
+            "(lat >= 1.0 AND lat <= 2.0 AND (lon >= 170.0 OR lon <= (-170.0)))"
+        );
+        assert!(GeoFunction::DWithin
+            .to_sql("lat", "lon", &[89.99, 0.0, 10_000.0])
+            .is_err());
+        assert!(GeoFunction::DWithin
+            .to_sql("lat", "lon", &[0.0, 0.0, -1.0])
+            .is_err());
+
+        let filter = Filter::parse("st_dwithin(lat, lon, $lat, 5, 100)").unwrap();
+        assert!(filter
+            .clone()
+            .bind("lat", 45.0)
+            .to_sql()
+            .unwrap()
+            .starts_with("(lat >= 44.99"));
+        assert!(matches!(
+            filter.bind("lat", "north").to_sql(),
+            Err(Error::InvalidFilter { column: 1, .. })
+        ));
+        assert!(matches!(
+            Filter::parse("x = 1 AND ST_DWITHIN(lat, lon, 1, 2)")
+                .unwrap()
+                .to_sql(),
+            Err(Error::InvalidFilter { column: 11, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_spatial_filters() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        // Points every 0.01 degree (about 1.1km) along the equator, around
+        // the antimeridian
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("lat", DataType::Float64, false),
+            Field::new("lon", DataType::Float64, false),
+        ]));
+        let lons = (-10..=10)
+            .map(|i| {
+                let lon = 180.0 + i as f64 * 0.01;
+                if lon > 180.0 {
+                    lon - 360.0
+                } else {
+                    lon
+                }
+            })
+            .collect::<Vec<_>>();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(-10..=10)),
+                Arc::new(Float64Array::from(vec![0.0; lons.len()])),
+                Arc::new(Float64Array::from(lons)),
+            ],
+        )
+        .unwrap();
+        let table = db
+            .create_table("points", RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await
+            .unwrap();
+
+        // Within 5km of the antimeridian, the points 0.04 degree away or closer
+        let count = table
+            .count_rows(Some("st_dwithin(lat, lon, 0, 180, 5000)".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(count, 9);
+        let count = table
+            .count_rows(Some(
+                "st_within_bbox(lat, lon, -1, 179.955, 1, -179.975)".to_string(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(count, 7);
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Filters on JSON columns
+//!
+//! JSON columns (see [`crate::arrow::JSON_EXTENSION_NAME`]) store a JSON
+//! document per row as text.  `json_extract(column, path)` is the value at
+//! `path` in the document of `column`, e.g.
+//!
+//! ```ignore
+//! table
+//!     .query()
+//!     .only_if("json_extract(meta, '$.source') = 'web'")
+//! ```
+//!
+//! The path starts with `$`, the document, followed by the keys of objects
+//! (`.name`, or `."name"` for keys which are not identifiers) and the indices
+//! of arrays (`[0]`).  The path is a string, or a parameter bound to a string.
+//!
+//! The value is text: strings without their quotes (their escapes are kept
+//! as they are written in the document), numbers and booleans as they are
+//! written and objects and arrays as their JSON text.  A JSON `null` and a
+//! path which is not in the document are `NULL`.  Numbers are compared as
+//! numbers with a cast, e.g. `CAST(json_extract(meta, '$.pages') AS BIGINT) >
+//! 10`.
+//!
+//! The function is converted to regular expressions, which follow the path
+//! through at most [`MAX_DEPTH`] levels of nested objects and arrays inside
+//! the value of each step.  No index speeds up these filters, they read the
+//! whole column.
+
+/// The levels of nesting, inside the value of a step of a path, which can be
+/// matched
+pub const MAX_DEPTH: usize = 8;
+
+/// A JSON string
+const STRING: &str = r#""(?:[^"\\]|\\.)*""#;
+
+/// The pattern matching a value, whose first group is the value without the
+/// quotes of a string and does not match a `null`
+const VALUE: &str = r#"(?s)^(?:null|"?(.*?)"?)$"#;
+
+/// A step of a path
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PathStep {
+    Key(String),
+    Index(usize),
+}
+
+/// The steps of `path`
+fn parse_path(path: &str) -> std::result::Result<Vec<PathStep>, String> {
+    let error = |message: &str| format!("invalid JSON path '{}': {}", path, message);
+    let Some(mut rest) = path.trim().strip_prefix('$') else {
+        return Err(error("a path starts with $"));
+    };
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix(".\"") {
+            let end = quoted
+                .find('"')
+                .ok_or_else(|| error("a quoted key is not closed"))?;
+            steps.push(PathStep::Key(quoted[..end].to_string()));
+            rest = &quoted[end + 1..];
+        } else if let Some(key) = rest.strip_prefix('.') {
+            let end = key.find(['.', '[']).unwrap_or(key.len());
+            if end == 0 {
+                return Err(error("a key is empty"));
+            }
+            steps.push(PathStep::Key(key[..end].to_string()));
+            rest = &key[end..];
+        } else if let Some(index) = rest.strip_prefix('[') {
+            let end = index
+                .find(']')
+                .ok_or_else(|| error("an index is not closed"))?;
+            let value = index[..end]
+                .trim()
+                .parse()
+                .map_err(|_| error("an index is not a number"))?;
+            steps.push(PathStep::Index(value));
+            rest = &index[end + 1..];
+        } else {
+            return Err(error("expected . or [ after a step"));
+        }
+    }
+    Ok(steps)
+}
+
+/// A token of a value nested in at most `depth` levels of objects and arrays
+fn nested_token(depth: usize) -> String {
+    let scalar = format!(r#"{}|[^"{{}}\[\]]"#, STRING);
+    (0..depth).fold(format!("(?:{})", scalar), |inner, _| {
+        format!(r"(?:{}|[{{\[]{}*[}}\]])", scalar, inner)
+    })
+}
+
+/// The pattern matching a document whose value at `step` is the first group
+fn step_pattern(step: &PathStep) -> String {
+    // The tokens of a value, which never contain a comma or a bracket outside
+    // of a nested object, array or string
+    let token = format!(
+        r#"(?:{}|[^"{{}}\[\],]|[{{\[]{}*[}}\]])"#,
+        STRING,
+        nested_token(MAX_DEPTH - 1)
+    );
+    match step {
+        PathStep::Key(key) => {
+            let key = serde_json::Value::String(key.clone()).to_string();
+            format!(
+                r"^\s*\{{\s*(?:{token}*,\s*)*?{}\s*:\s*({token}+?)\s*[,}}]",
+                regex::escape(&key),
+                token = token
+            )
+        }
+        PathStep::Index(index) => format!(
+            r"^\s*\[\s*(?:{token}*,\s*){{{}}}({token}+?)\s*[,\]]",
+            index,
+            token = token
+        ),
+    }
+}
+
+/// A string in SQL
+fn string_sql(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// The SQL of `json_extract(document, path)`, where `document` is a SQL
+/// expression
+///
+/// Returns the reason the path is not valid if it is not.
+pub(super) fn json_extract_sql(document: &str, path: &str) -> std::result::Result<String, String> {
+    let value = parse_path(path)?
+        .iter()
+        .fold(document.to_string(), |value, step| {
+            format!(
+                "array_element(regexp_match({}, {}), 1)",
+                value,
+                string_sql(&step_pattern(step))
+            )
+        });
+    Ok(format!(
+        "array_element(regexp_match({}, {}), 1)",
+        value,
+        string_sql(VALUE)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_schema::{DataType, Field, Schema};
+    use regex::Regex;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::arrow::{json_field, JsonRows, TableRows};
+    use crate::connect;
+    use crate::query::filter::Filter;
+
+    /// The value at `path` in `document`, as the SQL computes it
+    fn extract(document: &str, path: &str) -> Option<String> {
+        let mut value = document.to_string();
+        for step in parse_path(path).unwrap() {
+            let pattern = Regex::new(&step_pattern(&step)).unwrap();
+            value = pattern
+                .captures(&value)?
+                .get(1)
+                .unwrap()
+                .as_str()
+                .to_string();
+        }
+        Regex::new(VALUE)
+            .unwrap()
+            .captures(&value)?
+            .get(1)
+            .map(|value| value.as_str().to_string())
+    }
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(
+            parse_path("$.a.\"b.c\"[2]").unwrap(),
+            vec![
+                PathStep::Key("a".to_string()),
+                PathStep::Key("b.c".to_string()),
+                PathStep::Index(2)
+            ]
+        );
+        assert!(parse_path("a.b").is_err());
+        assert!(parse_path("$.a[x]").is_err());
+        assert!(parse_path("$..a").is_err());
+
+        let document = r#"{"x": {"k": "nested"}, "k": "top, level", "n": 12,
+            "list": [1, {"k": [true, null]}, "}"], "empty": null, "a b": 1}"#;
+        assert_eq!(extract(document, "$.k").as_deref(), Some("top, level"));
+        assert_eq!(extract(document, "$.x.k").as_deref(), Some("nested"));
+        assert_eq!(extract(document, "$.n").as_deref(), Some("12"));
+        assert_eq!(extract(document, "$.list[1].k[0]").as_deref(), Some("true"));
+        assert_eq!(extract(document, "$.list[2]").as_deref(), Some("}"));
+        assert_eq!(
+            extract(document, "$.x").as_deref(),
+            Some(r#"{"k": "nested"}"#)
+        );
+        assert_eq!(extract(document, "$.\"a b\"").as_deref(), Some("1"));
+        assert_eq!(extract(document, "$.list[1].k[1]"), None);
+        assert_eq!(extract(document, "$.list[3]"), None);
+        assert_eq!(extract(document, "$.empty"), None);
+        assert_eq!(extract(document, "$.missing"), None);
+        assert_eq!(extract(document, "$.n.k"), None);
+
+        assert!(matches!(
+            Filter::parse("json_extract(meta, 'source') = 'web'")
+                .unwrap()
+                .to_sql(),
+            Err(crate::Error::InvalidFilter { column: 1, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_json_columns() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let rows = JsonRows::from(vec![
+            serde_json::json!({"id": 1, "meta": {"source": "web", "pages": 3}}),
+            serde_json::json!({"id": 2, "meta": {"source": "mail", "tags": ["a", "b"]}}),
+            serde_json::json!({"id": 3, "meta": null}),
+        ])
+        .json_column("meta");
+        let table = db.create_table("docs", rows).execute().await.unwrap();
+        let schema = table.schema().await.unwrap();
+        assert!(crate::arrow::is_json_field(
+            schema.field_with_name("meta").unwrap()
+        ));
+
+        let count = |filter: &str| {
+            let table = table.clone();
+            let filter = filter.to_string();
+            async move { table.count_rows(Some(filter)).await.unwrap() }
+        };
+        assert_eq!(count("json_extract(meta, '$.source') = 'web'").await, 1);
+        assert_eq!(count("json_extract(meta, '$.tags[1]') = 'b'").await, 1);
+        assert_eq!(count("json_extract(meta, '$.source') IS NULL").await, 1);
+        assert_eq!(
+            count("CAST(json_extract(meta, '$.pages') AS BIGINT) > 2").await,
+            1
+        );
+
+        // Rows with a schema take any JSON value in a JSON column
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            json_field("meta", true),
+        ]));
+        let mut rows = TableRows::new(schema);
+        rows.push_json(serde_json::json!({"id": 4, "meta": {"source": "web"}}))
+            .unwrap();
+        rows.push_json(serde_json::json!({"id": 5, "meta": "web"}))
+            .unwrap();
+        table.add(rows).execute().await.unwrap();
+        assert_eq!(count("json_extract(meta, '$.source') = 'web'").await, 2);
+        assert_eq!(count("json_extract(meta, '$') = 'web'").await, 1);
+    }
+}
@@ -0,0 +1,85 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The metrics of a single query, to debug slow queries
+//!
+//! [`super::ExecutableQuery::execute_with_metrics`] runs a query to completion
+//! and returns its results with [`ExecutionMetrics`].  The plan of a query can
+//! be described without running it with [`super::Query::explain_plan`].
+//!
+//! The timings and the counts of the results are measured.  The work done by
+//! the search, the rows scanned and the partitions of the index probed, is
+//! computed from the statistics of the table and of its indices, since the
+//! Lance readers do not report it.  It is only known for local and object store
+//! tables.  The bytes read from storage are not reported.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use arrow_array::RecordBatch;
+use futures::TryStreamExt;
+
+use crate::arrow::SendableRecordBatchStream;
+use crate::error::Result;
+
+/// The work done by the search of a query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    /// The rows whose vectors were compared with the query vector or, for
+    /// queries without a query vector, the rows read
+    ///
+    /// The rows of the probed partitions of an index are estimated assuming
+    /// that all of the partitions have the same size.
+    pub rows_scanned: u64,
+    /// The partitions of the vector index probed, 0 if no index was used
+    pub index_partitions_probed: u64,
+}
+
+/// The metrics of one execution of a query
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecutionMetrics {
+    /// The time until the query was planned and ready to return results
+    pub planning_time: Duration,
+    /// The time spent returning the results, after the query was planned
+    pub execution_time: Duration,
+    /// The number of rows returned
+    pub rows_returned: u64,
+    /// The number of batches returned
+    pub batches_returned: u64,
+    /// The size in memory of the results, in bytes
+    pub bytes_returned: u64,
+    /// The work done by the search, if known
+    pub scan: Option<ScanStats>,
+}
+
+/// Run the query started by `execute` to completion and measure it
+pub(crate) async fn collect_with_metrics(
+    execute: impl Future<Output = Result<SendableRecordBatchStream>>,
+) -> Result<(Vec<RecordBatch>, ExecutionMetrics)> {
+    let start = Instant::now();
+    let stream = execute.await?;
+    let planning_time = start.elapsed();
+    let batches = stream.try_collect::<Vec<_>>().await?;
+    let mut metrics = ExecutionMetrics {
+        planning_time,
+        execution_time: start.elapsed() - planning_time,
+        batches_returned: batches.len() as u64,
+        ..Default::default()
+    };
+    for batch in &batches {
+        metrics.rows_returned += batch.num_rows() as u64;
+        metrics.bytes_returned += batch.get_array_memory_size() as u64;
+    }
+    Ok((batches, metrics))
+}
@@ -0,0 +1,404 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed filters
+//!
+//! Instead of writing a filter as a string, it can be built from the columns it
+//! compares, see [`super::QueryBase::only_if_predicate`]:
+//!
+//! ```ignore
+//! use lancedb::query::predicate::col;
+//!
+//! table
+//!     .query()
+//!     .only_if_predicate(col("price").gt(10).and(col("tag").is_in(["new", "sale"])))
+//! ```
+//!
+//! A predicate is always a valid filter: the names of the columns are quoted
+//! and the values are escaped, so values from users can be used without the
+//! risk of changing the meaning of the filter.  [`Predicate::check`] checks
+//! that the columns exist and that the values have the type of their columns
+//! before the query is sent.
+
+use arrow_schema::{DataType, Field, Schema};
+
+use super::filter::{quote_identifier, FilterValue};
+use crate::error::{Error, Result};
+
+/// The column with the given name
+///
+/// The fields of a struct column are selected with [`Column::field`].
+pub fn col(name: impl Into<String>) -> Column {
+    Column {
+        path: vec![name.into()],
+    }
+}
+
+/// A column compared by a [`Predicate`], see [`col`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    path: Vec<String>,
+}
+
+impl Column {
+    /// The field `name` of this struct column
+    pub fn field(mut self, name: impl Into<String>) -> Self {
+        self.path.push(name.into());
+        self
+    }
+
+    fn compare(self, op: &'static str, value: impl Into<FilterValue>) -> Predicate {
+        Predicate(Node::Compare {
+            column: self,
+            op,
+            value: value.into(),
+        })
+    }
+
+    /// The rows where the column is equal to `value`
+    pub fn eq(self, value: impl Into<FilterValue>) -> Predicate {
+        self.compare("=", value)
+    }
+
+    /// The rows where the column is not equal to `value`
+    pub fn ne(self, value: impl Into<FilterValue>) -> Predicate {
+        self.compare("!=", value)
+    }
+
+    /// The rows where the column is less than `value`
+    pub fn lt(self, value: impl Into<FilterValue>) -> Predicate {
+        self.compare("<", value)
+    }
+
+    /// The rows where the column is less than or equal to `value`
+    pub fn le(self, value: impl Into<FilterValue>) -> Predicate {
+        self.compare("<=", value)
+    }
+
+    /// The rows where the column is greater than `value`
+    pub fn gt(self, value: impl Into<FilterValue>) -> Predicate {
+        self.compare(">", value)
+    }
+
+    /// The rows where the column is greater than or equal to `value`
+    pub fn ge(self, value: impl Into<FilterValue>) -> Predicate {
+        self.compare(">=", value)
+    }
+
+    /// The rows where the column is between `low` and `high`, inclusive
+    pub fn between(self, low: impl Into<FilterValue>, high: impl Into<FilterValue>) -> Predicate {
+        Predicate(Node::Between {
+            column: self,
+            low: low.into(),
+            high: high.into(),
+        })
+    }
+
+    /// The rows where the column is one of `values`
+    ///
+    /// No row matches an empty list.
+    pub fn is_in<V: Into<FilterValue>>(self, values: impl IntoIterator<Item = V>) -> Predicate {
+        Predicate(Node::InList {
+            column: self,
+            values: values.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    /// The rows where the column is null
+    pub fn is_null(self) -> Predicate {
+        Predicate(Node::IsNull {
+            column: self,
+            negated: false,
+        })
+    }
+
+    /// The rows where the column is not null
+    pub fn is_not_null(self) -> Predicate {
+        Predicate(Node::IsNull {
+            column: self,
+            negated: true,
+        })
+    }
+
+    /// The rows where the string column matches the SQL `LIKE` pattern
+    ///
+    /// `%` matches any number of characters and `_` matches one character.
+    pub fn like(self, pattern: impl Into<String>) -> Predicate {
+        Predicate(Node::Like {
+            column: self,
+            pattern: pattern.into(),
+        })
+    }
+
+    fn to_sql(&self) -> String {
+        self.path
+            .iter()
+            .map(|name| quote_identifier(name))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// The field of the column in `schema`
+    fn resolve<'a>(&self, schema: &'a Schema) -> Result<&'a Field> {
+        let not_found = || Error::Schema {
+            message: format!("the column {} does not exist", self.path.join(".")),
+        };
+        let mut field = schema
+            .field_with_name(&self.path[0])
+            .map_err(|_| not_found())?;
+        for name in &self.path[1..] {
+            field = match field.data_type() {
+                DataType::Struct(fields) => fields
+                    .iter()
+                    .find(|f| f.name() == name)
+                    .ok_or_else(not_found)?,
+                _ => return Err(not_found()),
+            };
+        }
+        Ok(field)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Compare {
+        column: Column,
+        op: &'static str,
+        value: FilterValue,
+    },
+    Between {
+        column: Column,
+        low: FilterValue,
+        high: FilterValue,
+    },
+    InList {
+        column: Column,
+        values: Vec<FilterValue>,
+    },
+    IsNull {
+        column: Column,
+        negated: bool,
+    },
+    Like {
+        column: Column,
+        pattern: String,
+    },
+    And(Box<Self>, Box<Self>),
+    Or(Box<Self>, Box<Self>),
+    Not(Box<Self>),
+}
+
+impl Node {
+    fn to_sql(&self) -> String {
+        // OR binds less tightly than AND, which binds less tightly than NOT
+        let nested = |node: &Self, parenthesize: bool| {
+            if parenthesize {
+                format!("({})", node.to_sql())
+            } else {
+                node.to_sql()
+            }
+        };
+        match self {
+            Self::Compare { column, op, value } => {
+                format!("{} {} {}", column.to_sql(), op, value.to_sql())
+            }
+            Self::Between { column, low, high } => format!(
+                "{} BETWEEN {} AND {}",
+                column.to_sql(),
+                low.to_sql(),
+                high.to_sql()
+            ),
+            Self::InList { values, .. } if values.is_empty() => "FALSE".to_string(),
+            Self::InList { column, values } => format!(
+                "{} IN ({})",
+                column.to_sql(),
+                values
+                    .iter()
+                    .map(FilterValue::to_sql)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::IsNull { column, negated } => format!(
+                "{} IS {}NULL",
+                column.to_sql(),
+                if *negated { "NOT " } else { "" }
+            ),
+            Self::Like { column, pattern } => format!(
+                "{} LIKE {}",
+                column.to_sql(),
+                FilterValue::from(pattern.as_str()).to_sql()
+            ),
+            Self::And(left, right) => format!(
+                "{} AND {}",
+                nested(left, matches!(**left, Self::Or(..))),
+                nested(right, matches!(**right, Self::Or(..)))
+            ),
+            Self::Or(left, right) => format!("{} OR {}", left.to_sql(), right.to_sql()),
+            Self::Not(node) => format!(
+                "NOT {}",
+                nested(node, matches!(**node, Self::And(..) | Self::Or(..)))
+            ),
+        }
+    }
+
+    fn check(&self, schema: &Schema) -> Result<()> {
+        let check_values = |column: &Column, values: &[&FilterValue]| {
+            let field = column.resolve(schema)?;
+            match values
+                .iter()
+                .find(|value| !comparable(value, field.data_type()))
+            {
+                Some(value) => Err(Error::Schema {
+                    message: format!(
+                        "the column {} has type {} which cannot be compared with {:?}",
+                        column.path.join("."),
+                        field.data_type(),
+                        value
+                    ),
+                }),
+                None => Ok(()),
+            }
+        };
+        match self {
+            Self::Compare { column, value, .. } => check_values(column, &[value]),
+            Self::Between { column, low, high } => check_values(column, &[low, high]),
+            Self::InList { column, values } => {
+                check_values(column, &values.iter().collect::<Vec<_>>())
+            }
+            Self::IsNull { column, .. } => column.resolve(schema).map(|_| ()),
+            Self::Like { column, .. } => {
+                let field = column.resolve(schema)?;
+                if matches!(field.data_type(), DataType::Utf8 | DataType::LargeUtf8) {
+                    Ok(())
+                } else {
+                    Err(Error::Schema {
+                        message: format!(
+                            "the column {} has type {} and cannot be matched with LIKE",
+                            column.path.join("."),
+                            field.data_type()
+                        ),
+                    })
+                }
+            }
+            Self::And(left, right) | Self::Or(left, right) => {
+                left.check(schema)?;
+                right.check(schema)
+            }
+            Self::Not(node) => node.check(schema),
+        }
+    }
+}
+
+/// Whether a column of type `data_type` can be compared with `value`
+fn comparable(value: &FilterValue, data_type: &DataType) -> bool {
+    match (value, data_type) {
+        (FilterValue::Null, _) => true,
+        (_, DataType::Dictionary(_, values)) => comparable(value, values),
+        (FilterValue::Bool(_), data_type) => *data_type == DataType::Boolean,
+        (FilterValue::Int(_), data_type) => data_type.is_numeric(),
+        (FilterValue::Float(value), data_type) => value.is_finite() && data_type.is_numeric(),
+        // Strings are cast to dates and timestamps
+        (FilterValue::String(_), data_type) => {
+            matches!(data_type, DataType::Utf8 | DataType::LargeUtf8) || data_type.is_temporal()
+        }
+        (FilterValue::Timestamp(_) | FilterValue::Date(_), data_type) => data_type.is_temporal(),
+        (FilterValue::List(_), _) => false,
+    }
+}
+
+/// A filter built from columns and values, see [the module docs](self)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate(Node);
+
+impl Predicate {
+    /// The rows matching both this predicate and `other`
+    pub fn and(self, other: Self) -> Self {
+        Self(Node::And(Box::new(self.0), Box::new(other.0)))
+    }
+
+    /// The rows matching this predicate or `other`
+    pub fn or(self, other: Self) -> Self {
+        Self(Node::Or(Box::new(self.0), Box::new(other.0)))
+    }
+
+    /// Check that the columns of the predicate are in `schema` and that they can
+    /// be compared with their values
+    ///
+    /// Returns [`Error::Schema`] otherwise.
+    pub fn check(&self, schema: &Schema) -> Result<()> {
+        self.0.check(schema)
+    }
+
+    /// The predicate as a filter, see [`super::filter`]
+    pub fn to_sql(&self) -> String {
+        self.0.to_sql()
+    }
+}
+
+impl std::ops::Not for Predicate {
+    type Output = Self;
+
+    /// The rows not matching this predicate
+    fn not(self) -> Self {
+        Self(Node::Not(Box::new(self.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_schema::Fields;
+
+    use super::*;
+    use crate::query::filter::Filter;
+
+    #[test]
+    fn test_predicate() {
+        let predicate = col("price")
+            .gt(10)
+            .and(col("tag").is_in(["new", "it's"]).or(col("tag").is_null()));
+        assert_eq!(
+            predicate.to_sql(),
+            "`price` > 10 AND (`tag` IN ('new', 'it''s') OR `tag` IS NULL)"
+        );
+        // The predicates are valid filters
+        assert_eq!(
+            Filter::parse(predicate.to_sql()).unwrap().to_sql().unwrap(),
+            predicate.to_sql()
+        );
+        assert_eq!(
+            (!col("s").field("x").between(1.5, 2).and(col("b").eq(true))).to_sql(),
+            "NOT (`s`.`x` BETWEEN 1.5 AND 2 AND `b` = TRUE)"
+        );
+        assert_eq!(col("tag").is_in(Vec::<i32>::new()).to_sql(), "FALSE");
+
+        let schema = Schema::new(vec![
+            Field::new("price", DataType::Float64, false),
+            Field::new("tag", DataType::Utf8, true),
+            Field::new(
+                "s",
+                DataType::Struct(Fields::from(vec![Field::new("x", DataType::Int32, true)])),
+                true,
+            ),
+        ]);
+        assert!(predicate.check(&schema).is_ok());
+        assert!(col("s").field("x").le(3).check(&schema).is_ok());
+        assert!(matches!(
+            col("missing").eq(1).check(&schema),
+            Err(Error::Schema { .. })
+        ));
+        assert!(col("s").field("y").is_null().check(&schema).is_err());
+        assert!(col("price").eq("ten").check(&schema).is_err());
+        assert!(col("price").like("1%").check(&schema).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Vector searches across the shards of a table
+//!
+//! The search is sent to all of the shards at once, each shard returning its own
+//! top-k results, and the results are merged by distance into the overall top-k.
+//! The shards can be any tables with the same schema, local or remote, for
+//! example the tables created by [`crate::Table::split`].  The latency of each
+//! shard is reported so that slow shards can be found.
+
+use std::time::{Duration, Instant};
+
+use arrow::compute::{concat_batches, sort_to_indices, SortOptions};
+use arrow_array::RecordBatch;
+use futures::{future::try_join_all, TryStreamExt};
+
+use super::{ExecutableQuery, HasQuery, Query, QueryExecutionOptions, VectorQuery, DEFAULT_TOP_K};
+use crate::arrow::{take_record_batch, SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+use crate::Table;
+
+const DISTANCE_COLUMN: &str = "_distance";
+
+/// The time taken by one shard to answer a search
+#[derive(Debug, Clone)]
+pub struct ShardStats {
+    /// The name of the shard
+    pub name: String,
+    /// The time from sending the search to the shard to receiving all of its results
+    pub latency: Duration,
+    /// The number of results returned by the shard, before they were merged
+    pub num_rows: usize,
+}
+
+/// The results of a [`ShardedVectorQuery`]
+#[derive(Debug, Clone)]
+pub struct ShardedResults {
+    /// The merged top-k results, ordered by distance
+    pub batch: RecordBatch,
+    /// The stats of each shard, in the order of the shards
+    pub shard_stats: Vec<ShardStats>,
+}
+
+/// A vector search run on several shards
+///
+/// See [`VectorQuery::scatter`] for more details.
+///
+/// Row ids, if requested with [`super::QueryBase::with_row_id`], are only unique
+/// within a shard.
+#[derive(Clone)]
+pub struct ShardedVectorQuery {
+    query: VectorQuery,
+    shards: Vec<Table>,
+}
+
+impl std::fmt::Debug for ShardedVectorQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let shards = self.shards.iter().map(Table::name).collect::<Vec<_>>();
+        f.debug_struct("ShardedVectorQuery")
+            .field("query", &self.query)
+            .field("shards", &shards)
+            .finish()
+    }
+}
+
+impl ShardedVectorQuery {
+    pub(crate) fn new(query: VectorQuery, shards: Vec<Table>) -> Self {
+        Self { query, shards }
+    }
+
+    /// Run the search and return the merged results along with the stats of
+    /// each shard
+    pub async fn execute_with_stats(
+        &self,
+        options: QueryExecutionOptions,
+    ) -> Result<ShardedResults> {
+        if self.shards.is_empty() {
+            return Err(Error::InvalidInput {
+                message: "a sharded search needs at least one shard".to_string(),
+            });
+        }
+        if !self.query.batch_vectors.is_empty() {
+            return Err(Error::NotSupported {
+                message: "a sharded search cannot have several query vectors".to_string(),
+            });
+        }
+        // The shards would each return the closest rows of the same groups
+        if self.query.group_by.is_some() {
+            return Err(Error::NotSupported {
+                message: "the results of a sharded search cannot be grouped".to_string(),
+            });
+        }
+        let searches = self.shards.iter().map(|shard| {
+            let mut query = self.query.clone();
+            query.base.parent = shard.query().parent;
+            let options = options.clone();
+            async move {
+                let start = Instant::now();
+                let stream = query.execute_with_options(options).await?;
+                let schema = stream.schema();
+                let batches = stream.try_collect::<Vec<_>>().await?;
+                let batch = concat_batches(&schema, &batches)?;
+                let stats = ShardStats {
+                    name: shard.name().to_string(),
+                    latency: start.elapsed(),
+                    num_rows: batch.num_rows(),
+                };
+                Ok::<_, Error>((batch, stats))
+            }
+        });
+        let (batches, shard_stats): (Vec<_>, Vec<_>) =
+            try_join_all(searches).await?.into_iter().unzip();
+
+        let schema = batches[0].schema();
+        if let Some(stats) = batches
+            .iter()
+            .zip(&shard_stats)
+            .find_map(|(batch, stats)| (batch.schema() != schema).then_some(stats))
+        {
+            return Err(Error::Schema {
+                message: format!(
+                    "the results of the shards '{}' and '{}' do not have the same schema",
+                    shard_stats[0].name, stats.name
+                ),
+            });
+        }
+        let batch = concat_batches(&schema, &batches)?;
+        let distances = batch
+            .column_by_name(DISTANCE_COLUMN)
+            .ok_or_else(|| Error::Schema {
+                message: format!("the results have no {} column", DISTANCE_COLUMN),
+            })?;
+        let limit = self.query.base.limit.unwrap_or(DEFAULT_TOP_K);
+        let indices = sort_to_indices(
+            distances,
+            Some(SortOptions {
+                descending: false,
+                nulls_first: false,
+            }),
+            Some(limit),
+        )?;
+        Ok(ShardedResults {
+            batch: take_record_batch(&batch, &indices)?,
+            shard_stats,
+        })
+    }
+}
+
+impl HasQuery for ShardedVectorQuery {
+    fn mut_query(&mut self) -> &mut Query {
+        self.query.mut_query()
+    }
+}
+
+impl ExecutableQuery for ShardedVectorQuery {
+    async fn execute_with_options(
+        &self,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        let results = self.execute_with_stats(options).await?;
+        Ok(Box::pin(SimpleRecordBatchStream {
+            schema: results.batch.schema(),
+            stream: futures::stream::iter([Ok(results.batch)]),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float32Type, Int32Type};
+    use arrow_array::{
+        FixedSizeListArray, Float32Array, Int32Array, RecordBatchIterator, RecordBatchReader,
+    };
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::QueryBase;
+
+    fn items(ids: std::ops::Range<i32>) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+                false,
+            ),
+        ]));
+        let values = Float32Array::from_iter_values(ids.clone().flat_map(|i| [i as f32, 0.0]));
+        let vectors = FixedSizeListArray::try_new(
+            Arc::new(Field::new("item", DataType::Float32, true)),
+            2,
+            Arc::new(values),
+            None,
+        )
+        .unwrap();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(ids)),
+                Arc::new(vectors),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_sharded_vector_query() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        // The rows closest to 5 are split across the two shards
+        let even = db
+            .create_table("even", items(0..10))
+            .execute()
+            .await
+            .unwrap();
+        even.delete("id % 2 = 1").await.unwrap();
+        let odd = db
+            .create_table("odd", items(0..10))
+            .execute()
+            .await
+            .unwrap();
+        odd.delete("id % 2 = 0").await.unwrap();
+
+        let results = even
+            .vector_search(&[5.0, 0.0])
+            .unwrap()
+            .scatter(vec![even.clone(), odd.clone()])
+            .limit(3)
+            .execute_with_stats(QueryExecutionOptions::default())
+            .await
+            .unwrap();
+        // 4 and 6 are at the same distance so their order is not defined
+        let ids = results.batch["id"].as_primitive::<Int32Type>().values();
+        assert_eq!(ids[0], 5);
+        let mut rest = ids[1..].to_vec();
+        rest.sort();
+        assert_eq!(rest, vec![4, 6]);
+        let distances = results.batch["_distance"].as_primitive::<Float32Type>();
+        assert_eq!(distances.values().as_ref(), &[0.0, 1.0, 1.0]);
+        assert_eq!(results.shard_stats.len(), 2);
+        assert_eq!(results.shard_stats[0].name, "even");
+        assert_eq!(results.shard_stats[0].num_rows, 3);
+        assert_eq!(results.shard_stats[1].name, "odd");
+
+        let result = even
+            .vector_search(&[5.0, 0.0])
+            .unwrap()
+            .scatter(Vec::new())
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+    }
+}
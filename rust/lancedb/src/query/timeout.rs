@@ -0,0 +1,219 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Timeouts and cancellation of queries
+//!
+//! A query with a timeout, see [`super::QueryBase::timeout`], fails with
+//! [`Error::QueryTimeout`] once the timeout has elapsed since it started
+//! executing, whether it is still being planned or its results are being read.
+//! A query with a [`CancellationToken`], see
+//! [`super::QueryBase::cancellation_token`], fails with [`Error::QueryCancelled`]
+//! once the token is cancelled.  A token can be shared by several queries, for
+//! example all of the queries of a request that the client abandoned.
+//!
+//! The query is aborted cooperatively: the stream of its results returns the
+//! error and drops the scan of the table, which stops reading the table and
+//! computing the distances of a brute-force search.  The batches returned
+//! before the query was aborted are valid.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use arrow_array::RecordBatch;
+use arrow_schema::Schema;
+use futures::{FutureExt, Stream, StreamExt};
+use tokio::time::Sleep;
+use tokio_util::sync::WaitForCancellationFutureOwned;
+
+pub use tokio_util::sync::CancellationToken;
+
+use super::Query;
+use crate::arrow::{RecordBatchStream, SendableRecordBatchStream};
+use crate::error::{Error, Result};
+
+/// Resolves to the error of a query once it must be aborted
+struct Abort {
+    timeout: Duration,
+    sleep: Option<Pin<Box<Sleep>>>,
+    cancelled: Option<Pin<Box<WaitForCancellationFutureOwned>>>,
+}
+
+impl Abort {
+    fn new(timeout: Option<Duration>, token: Option<CancellationToken>) -> Self {
+        Self {
+            timeout: timeout.unwrap_or_default(),
+            sleep: timeout.map(|timeout| Box::pin(tokio::time::sleep(timeout))),
+            cancelled: token.map(|token| Box::pin(token.cancelled_owned())),
+        }
+    }
+}
+
+impl Future for Abort {
+    type Output = Error;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Error> {
+        if let Some(cancelled) = &mut self.cancelled {
+            if cancelled.poll_unpin(cx).is_ready() {
+                return Poll::Ready(Error::QueryCancelled);
+            }
+        }
+        if let Some(sleep) = &mut self.sleep {
+            // The timer only fires on its next tick, check the deadline itself
+            // so that an elapsed timeout aborts the query right away
+            if sleep.deadline() <= tokio::time::Instant::now() || sleep.poll_unpin(cx).is_ready() {
+                return Poll::Ready(Error::QueryTimeout {
+                    timeout: self.timeout,
+                });
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Run `execute`, the execution of `query`, aborting it once the timeout of the
+/// query elapses or its token is cancelled
+pub(crate) async fn run_with_deadline(
+    query: &Query,
+    execute: impl Future<Output = Result<SendableRecordBatchStream>>,
+) -> Result<SendableRecordBatchStream> {
+    if query.timeout.is_none() && query.cancellation_token.is_none() {
+        return execute.await;
+    }
+    let mut abort = Abort::new(query.timeout, query.cancellation_token.clone());
+    let stream = tokio::select! {
+        biased;
+        error = &mut abort => return Err(error),
+        stream = execute => stream?,
+    };
+    Ok(Box::pin(AbortableRecordBatchStream {
+        schema: stream.schema(),
+        version: stream.version(),
+        stream: Some(stream),
+        abort,
+    }))
+}
+
+/// A RecordBatchStream which ends with an error once its query is aborted
+struct AbortableRecordBatchStream {
+    schema: Arc<Schema>,
+    version: Option<u64>,
+    /// None once the stream ended or was aborted
+    stream: Option<SendableRecordBatchStream>,
+    abort: Abort,
+}
+
+impl Stream for AbortableRecordBatchStream {
+    type Item = Result<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.stream.is_none() {
+            return Poll::Ready(None);
+        }
+        if let Poll::Ready(error) = self.abort.poll_unpin(cx) {
+            // Dropping the stream stops the scan
+            self.stream = None;
+            return Poll::Ready(Some(Err(error)));
+        }
+        let next = self.stream.as_mut().unwrap().poll_next_unpin(cx);
+        if let Poll::Ready(None) = next {
+            self.stream = None;
+        }
+        next
+    }
+}
+
+impl RecordBatchStream for AbortableRecordBatchStream {
+    fn schema(&self) -> Arc<Schema> {
+        self.schema.clone()
+    }
+
+    fn version(&self) -> Option<u64> {
+        self.version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field};
+    use futures::TryStreamExt;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase, QueryExecutionOptions};
+
+    fn batches() -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..1000))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_timeout_and_cancellation() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("t", batches()).execute().await.unwrap();
+
+        let rows = table
+            .query()
+            .timeout(Duration::from_secs(60))
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(rows.iter().map(|b| b.num_rows()).sum::<usize>(), 1000);
+
+        assert!(matches!(
+            table.query().timeout(Duration::ZERO).execute().await,
+            Err(Error::QueryTimeout { .. })
+        ));
+
+        let token = CancellationToken::new();
+        let options = QueryExecutionOptions {
+            max_batch_length: 100,
+            ..Default::default()
+        };
+        let mut stream = table
+            .query()
+            .cancellation_token(token.clone())
+            .execute_with_options(options)
+            .await
+            .unwrap();
+        assert!(stream.try_next().await.unwrap().is_some());
+        token.cancel();
+        assert!(matches!(
+            stream.try_next().await,
+            Err(Error::QueryCancelled)
+        ));
+        assert!(stream.next().await.is_none());
+        // The token stays cancelled
+        assert!(matches!(
+            table.query().cancellation_token(token).execute().await,
+            Err(Error::QueryCancelled)
+        ));
+    }
+}
@@ -0,0 +1,379 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Quotas on the tables of a database
+//!
+//! A [`Quota`] is attached to a table name prefix with
+//! [`crate::connection::ConnectBuilder::quota`] and limits the rows and bytes
+//! stored in all of the tables whose name starts with the prefix, as well as the
+//! rate at which rows are written to them.  This allows a single database to be
+//! shared by several tenants, each using their own prefix.
+//!
+//! The usage of the tables is measured when connecting and is then kept up to
+//! date by the writes made through the connection.  Writes that would exceed a
+//! quota fail with [`crate::Error::QuotaExceeded`] and are not committed.
+//!
+//! The bytes of a write are estimated from the in-memory size of the data, while
+//! the stored bytes are the size of the files of the tables, so the bytes limit is
+//! approximate.  Temporary tables are not subject to quotas.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use arrow_array::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_schema::ArrowError;
+use futures::TryStreamExt;
+use lance::Dataset;
+use object_store::path::Path;
+
+use crate::connection::LANCE_FILE_EXTENSION;
+use crate::error::{Error, Result};
+
+/// The limits of a [`Quota`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaLimit {
+    /// The number of rows stored
+    Rows,
+    /// The number of bytes stored
+    Bytes,
+    /// The number of rows written per second
+    WriteRate,
+}
+
+impl std::fmt::Display for QuotaLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rows => write!(f, "rows"),
+            Self::Bytes => write!(f, "bytes"),
+            Self::WriteRate => write!(f, "write rate"),
+        }
+    }
+}
+
+/// Limits on the tables sharing a name prefix
+#[derive(Debug, Clone, Default)]
+pub struct Quota {
+    max_rows: Option<u64>,
+    max_bytes: Option<u64>,
+    max_write_rate: Option<u64>,
+}
+
+impl Quota {
+    /// The maximum number of rows stored in the tables
+    pub fn max_rows(mut self, max_rows: u64) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    /// The maximum number of bytes stored in the tables
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// The maximum number of rows written to the tables per second
+    ///
+    /// Writes are allowed in bursts of up to a second's worth of rows.  A write
+    /// that goes over the rate is still committed, but following writes are
+    /// rejected until the rate has caught up.
+    pub fn max_write_rate(mut self, rows_per_second: u64) -> Self {
+        self.max_write_rate = Some(rows_per_second);
+        self
+    }
+}
+
+/// The rows and bytes stored in a table
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Usage {
+    rows: u64,
+    bytes: u64,
+}
+
+/// Limits the rate of the writes with a token bucket, a token being a row
+#[derive(Debug)]
+struct RateLimiter {
+    rows_per_second: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    fn new(rows_per_second: u64) -> Self {
+        Self {
+            rows_per_second: rows_per_second as f64,
+            tokens: rows_per_second as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rows_per_second).min(self.rows_per_second);
+        self.refilled_at = now;
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    prefix: String,
+    quota: Quota,
+    rate: Option<Mutex<RateLimiter>>,
+}
+
+/// The quotas of a connection
+#[derive(Debug)]
+pub(crate) struct Quotas {
+    rules: Vec<Rule>,
+    store: Arc<dyn object_store::ObjectStore>,
+    base_path: Path,
+    usage: Mutex<HashMap<String, Usage>>,
+}
+
+/// A quota that a write ran into
+#[derive(Debug, Clone)]
+struct Violation {
+    prefix: String,
+    limit: QuotaLimit,
+    usage: u64,
+    max: u64,
+}
+
+/// The rows and bytes written so far
+#[derive(Debug, Default)]
+struct Progress {
+    rows: u64,
+    bytes: u64,
+    violation: Option<Violation>,
+}
+
+/// A write in progress to a table with quotas
+pub(crate) struct QuotaWrite {
+    table: String,
+    rules: Vec<usize>,
+    progress: Arc<Mutex<Progress>>,
+}
+
+impl Quotas {
+    pub(crate) fn new(
+        quotas: Vec<(String, Quota)>,
+        store: Arc<dyn object_store::ObjectStore>,
+        base_path: Path,
+    ) -> Self {
+        let rules = quotas
+            .into_iter()
+            .map(|(prefix, quota)| Rule {
+                rate: quota
+                    .max_write_rate
+                    .map(|rate| Mutex::new(RateLimiter::new(rate))),
+                prefix,
+                quota,
+            })
+            .collect();
+        Self {
+            rules,
+            store,
+            base_path,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether any quota applies to the table `name`
+    pub(crate) fn applies_to(&self, name: &str) -> bool {
+        self.rules.iter().any(|rule| name.starts_with(&rule.prefix))
+    }
+
+    /// Measure the rows and bytes stored in `dataset`, the table `name`
+    pub(crate) async fn measure(&self, name: &str, dataset: &Dataset) -> Result<Usage> {
+        let rows = dataset.count_rows(None).await? as u64;
+        let dir = self
+            .base_path
+            .child(format!("{}.{}", name, LANCE_FILE_EXTENSION));
+        let bytes = self
+            .store
+            .list(Some(&dir))
+            .try_fold(0, |bytes, meta| async move { Ok(bytes + meta.size as u64) })
+            .await?;
+        Ok(Usage { rows, bytes })
+    }
+
+    /// Record the usage of the table `name`
+    pub(crate) fn set_usage(&self, name: &str, usage: Usage) -> Result<()> {
+        self.usage.lock()?.insert(name.to_string(), usage);
+        Ok(())
+    }
+
+    /// Forget the usage of a dropped table
+    pub(crate) fn remove_usage(&self, name: &str) -> Result<()> {
+        self.usage.lock()?.remove(name);
+        Ok(())
+    }
+
+    /// Move the usage of a renamed table to its new name
+    pub(crate) fn rename_usage(&self, old_name: &str, new_name: &str) -> Result<()> {
+        let mut usage = self.usage.lock()?;
+        if let Some(old) = usage.remove(old_name) {
+            usage.insert(new_name.to_string(), old);
+        }
+        Ok(())
+    }
+
+    /// Forget the usage of all of the tables, once the database is dropped
+    pub(crate) fn clear_usage(&self) -> Result<()> {
+        self.usage.lock()?.clear();
+        Ok(())
+    }
+
+    /// Start writing `data` to the table `name`
+    ///
+    /// Fails if the write rate of the table is already exhausted, otherwise
+    /// returns the data to write, which fails once a quota would be exceeded.
+    /// The result of the write must be passed to [`Self::finish_write`].
+    pub(crate) fn start_write(
+        &self,
+        name: &str,
+        data: Box<dyn RecordBatchReader + Send>,
+        overwrite: bool,
+    ) -> Result<(Box<dyn RecordBatchReader + Send>, Option<QuotaWrite>)> {
+        let rules = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| name.starts_with(&rule.prefix))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        if rules.is_empty() {
+            return Ok((data, None));
+        }
+
+        // The rows and bytes each rule has left
+        let usage = self.usage.lock()?;
+        let mut remaining = Vec::with_capacity(rules.len());
+        for &i in &rules {
+            let rule = &self.rules[i];
+            if let Some(rate) = &rule.rate {
+                let mut rate = rate.lock()?;
+                rate.refill();
+                if rate.tokens <= 0.0 {
+                    return Err(Violation {
+                        prefix: rule.prefix.clone(),
+                        limit: QuotaLimit::WriteRate,
+                        usage: (rate.rows_per_second - rate.tokens) as u64,
+                        max: rate.rows_per_second as u64,
+                    }
+                    .into_error(name));
+                }
+            }
+            let used = usage
+                .iter()
+                .filter(|(table, _)| table.starts_with(&rule.prefix))
+                .filter(|(table, _)| !overwrite || table.as_str() != name)
+                .fold(Usage::default(), |total, (_, usage)| Usage {
+                    rows: total.rows + usage.rows,
+                    bytes: total.bytes + usage.bytes,
+                });
+            remaining.push((rule.prefix.clone(), rule.quota.clone(), used));
+        }
+        drop(usage);
+
+        let progress = Arc::new(Mutex::new(Progress::default()));
+        let tracked = progress.clone();
+        let schema = data.schema();
+        let data = data.map(move |batch: std::result::Result<RecordBatch, ArrowError>| {
+            let batch = batch?;
+            let mut progress = tracked.lock().unwrap();
+            progress.rows += batch.num_rows() as u64;
+            progress.bytes += batch.get_array_memory_size() as u64;
+            for (prefix, quota, used) in &remaining {
+                let exceeded = [
+                    (QuotaLimit::Rows, quota.max_rows, used.rows + progress.rows),
+                    (
+                        QuotaLimit::Bytes,
+                        quota.max_bytes,
+                        used.bytes + progress.bytes,
+                    ),
+                ]
+                .into_iter()
+                .find_map(|(limit, max, usage)| {
+                    max.filter(|max| usage > *max).map(|max| Violation {
+                        prefix: prefix.clone(),
+                        limit,
+                        usage,
+                        max,
+                    })
+                });
+                if let Some(violation) = exceeded {
+                    progress.violation = Some(violation);
+                    return Err(ArrowError::ExternalError(
+                        format!("the {} quota was exceeded", quota_name(prefix)).into(),
+                    ));
+                }
+            }
+            Ok(batch)
+        });
+        let write = QuotaWrite {
+            table: name.to_string(),
+            rules,
+            progress,
+        };
+        Ok((
+            Box::new(RecordBatchIterator::new(data, schema)),
+            Some(write),
+        ))
+    }
+
+    /// Finish a write started with [`Self::start_write`]
+    ///
+    /// Replaces the error of a write that was stopped by a quota with
+    /// [`Error::QuotaExceeded`].  The usage of the table must then be updated
+    /// with [`Self::set_usage`].
+    pub(crate) fn finish_write<T>(&self, write: QuotaWrite, result: Result<T>) -> Result<T> {
+        let progress = write.progress.lock()?;
+        match result {
+            Ok(value) => {
+                for i in write.rules {
+                    if let Some(rate) = &self.rules[i].rate {
+                        rate.lock()?.tokens -= progress.rows as f64;
+                    }
+                }
+                Ok(value)
+            }
+            Err(err) => match &progress.violation {
+                Some(violation) => Err(violation.clone().into_error(&write.table)),
+                None => Err(err),
+            },
+        }
+    }
+}
+
+fn quota_name(prefix: &str) -> String {
+    if prefix.is_empty() {
+        "database".to_string()
+    } else {
+        format!("'{}'", prefix)
+    }
+}
+
+impl Violation {
+    fn into_error(self, table: &str) -> Error {
+        Error::QuotaExceeded {
+            table: table.to_string(),
+            prefix: self.prefix,
+            limit: self.limit,
+            usage: self.usage,
+            max: self.max,
+        }
+    }
+}
@@ -12,19 +12,111 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
 use std::time::Duration;
 
 use reqwest::{
-    header::{HeaderMap, HeaderValue},
-    RequestBuilder, Response,
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Request, RequestBuilder, Response, StatusCode,
 };
 
-use crate::error::{Error, Result};
+use crate::error::{is_retryable_status, Error, Result};
+
+/// A hook called on each request sent to LanceDB Cloud
+///
+/// Middleware can add headers, such as auth tokens or tracing headers, sign
+/// the request, or reject it by returning an error.  It is called again each
+/// time a request is retried.
+pub trait Middleware: std::fmt::Debug + Send + Sync {
+    fn on_request(&self, request: Request) -> Result<Request>;
+}
+
+/// How failed requests to LanceDB Cloud are retried
+///
+/// Requests that could not be sent, timed out, or were answered with a 429,
+/// 502, 503 or 504 status are retried.  The delay between attempts doubles
+/// after each retry.
+#[derive(Clone, Debug)]
+pub struct RetryConfig {
+    /// The number of times a request is retried, 3 by default
+    pub retries: u32,
+    /// The delay before the first retry, 500ms by default
+    pub backoff: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Configuration of the HTTP client used to connect to LanceDB Cloud
+///
+/// See [`crate::connection::ConnectBuilder::client_config`]
+#[derive(Clone, Debug)]
+pub struct ClientConfig {
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+    proxy: Option<String>,
+    retry: RetryConfig,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            headers: Vec::new(),
+            timeout: Duration::from_secs(30),
+            proxy: None,
+            retry: RetryConfig::default(),
+            middleware: Vec::new(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Send the header `name` with each request
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// The timeout of each request, 30 seconds by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send all requests through the proxy at `url`
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    /// How failed requests are retried, see [`RetryConfig`]
+    pub fn retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Call `middleware` on each request
+    ///
+    /// Middleware are called in the order they were added.
+    pub fn middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct RestfulLanceDbClient {
     client: reqwest::Client,
     host: String,
+    retry: RetryConfig,
+    middleware: Vec<Arc<dyn Middleware>>,
 }
 
 impl RestfulLanceDbClient {
@@ -37,6 +129,7 @@ impl RestfulLanceDbClient {
         region: &str,
         db_name: &str,
         has_host_override: bool,
+        extra_headers: &[(String, String)],
     ) -> Result<HeaderMap> {
         let mut headers = HeaderMap::new();
         headers.insert(
@@ -62,6 +155,15 @@ impl RestfulLanceDbClient {
                 })?,
             );
         }
+        for (name, value) in extra_headers {
+            let name = HeaderName::try_from(name.as_str()).map_err(|_| Error::Http {
+                message: format!("invalid header name '{}' provided", name),
+            })?;
+            let value = HeaderValue::from_str(value).map_err(|_| Error::Http {
+                message: format!("non-ascii value provided for the header '{}'", name),
+            })?;
+            headers.insert(name, value);
+        }
 
         Ok(headers)
     }
@@ -71,6 +173,7 @@ impl RestfulLanceDbClient {
         api_key: &str,
         region: &str,
         host_override: Option<String>,
+        config: ClientConfig,
     ) -> Result<Self> {
         let parsed_url = url::Url::parse(db_url)?;
         debug_assert_eq!(parsed_url.scheme(), "db");
@@ -80,20 +183,29 @@ impl RestfulLanceDbClient {
             });
         }
         let db_name = parsed_url.host_str().unwrap();
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
+        let mut builder = reqwest::Client::builder()
+            .timeout(config.timeout)
             .default_headers(Self::default_headers(
                 api_key,
                 region,
                 db_name,
                 host_override.is_some(),
-            )?)
-            .build()?;
+                &config.headers,
+            )?);
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        let client = builder.build()?;
         let host = match host_override {
             Some(host_override) => host_override,
             None => format!("https://{}.{}.api.lancedb.com", db_name, region),
         };
-        Ok(Self { client, host })
+        Ok(Self {
+            client,
+            host,
+            retry: config.retry,
+            middleware: config.middleware,
+        })
     }
 
     pub fn get(&self, uri: &str) -> RequestBuilder {
@@ -106,23 +218,109 @@ impl RestfulLanceDbClient {
         self.client.post(full_uri)
     }
 
+    fn should_retry(result: &reqwest::Result<Response>) -> bool {
+        match result {
+            Ok(response) => is_retryable_status(response.status().as_u16()),
+            Err(err) => err.is_connect() || err.is_timeout(),
+        }
+    }
+
+    /// Send a request, through the middleware, retrying it if it fails
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let mut request = request.build()?;
+        let mut backoff = self.retry.backoff;
+        let mut attempt = 0;
+        loop {
+            // Requests with a streaming body cannot be cloned and so are not retried
+            let (attempt_request, retry) = match request.try_clone() {
+                Some(clone) if attempt < self.retry.retries => (clone, Some(request)),
+                _ => (request, None),
+            };
+            let attempt_request = self
+                .middleware
+                .iter()
+                .try_fold(attempt_request, |request, middleware| {
+                    middleware.on_request(request)
+                })?;
+            let result = self.client.execute(attempt_request).await;
+            request = match retry {
+                Some(retry) if Self::should_retry(&result) => retry,
+                _ => return Ok(result?),
+            };
+            log::warn!(
+                "request to LanceDB Cloud failed, retrying in {:?} ({}/{})",
+                backoff,
+                attempt + 1,
+                self.retry.retries
+            );
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+
     async fn rsp_to_str(response: Response) -> String {
         let status = response.status();
         response.text().await.unwrap_or_else(|_| status.to_string())
     }
 
+    /// Fail with [`Error::Remote`] if the response is not a success
     pub async fn check_response(&self, response: Response) -> Result<Response> {
-        let status_int: u16 = u16::from(response.status());
-        if (400..500).contains(&status_int) {
-            Err(Error::InvalidInput {
-                message: Self::rsp_to_str(response).await,
-            })
-        } else if status_int != 200 {
-            Err(Error::Runtime {
+        let status = response.status();
+        if status == StatusCode::OK {
+            Ok(response)
+        } else {
+            Err(Error::Remote {
+                status: status.as_u16(),
                 message: Self::rsp_to_str(response).await,
             })
-        } else {
-            Ok(response)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct AddToken;
+
+    impl Middleware for AddToken {
+        fn on_request(&self, mut request: Request) -> Result<Request> {
+            request
+                .headers_mut()
+                .insert("authorization", HeaderValue::from_static("Bearer token"));
+            Ok(request)
+        }
+    }
+
+    #[test]
+    fn test_client_config() {
+        let config = ClientConfig::default()
+            .header("x-trace-id", "1234")
+            .proxy("http://localhost:8080")
+            .middleware(Arc::new(AddToken));
+        let client =
+            RestfulLanceDbClient::try_new("db://test", "api-key", "us-east-1", None, config)
+                .unwrap();
+        let request = client.post("/v1/table/").build().unwrap();
+        let request = client.middleware[0].on_request(request).unwrap();
+        assert_eq!(request.headers()["authorization"], "Bearer token");
+
+        let headers = RestfulLanceDbClient::default_headers(
+            "api-key",
+            "us-east-1",
+            "test",
+            false,
+            &[("x-trace-id".to_string(), "1234".to_string())],
+        )
+        .unwrap();
+        assert_eq!(headers["x-trace-id"], "1234");
+
+        let config = ClientConfig::default().header("invalid header", "value");
+        assert!(matches!(
+            RestfulLanceDbClient::try_new("db://test", "api-key", "us-east-1", None, config),
+            Err(Error::Http { .. })
+        ));
+    }
+}
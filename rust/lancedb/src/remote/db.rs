@@ -17,20 +17,30 @@ use std::sync::Arc;
 use arrow_array::RecordBatchReader;
 use async_trait::async_trait;
 use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
 use serde::Deserialize;
 use tokio::task::spawn_blocking;
 
 use crate::connection::{
-    ConnectionInternal, CreateTableBuilder, NoData, OpenTableBuilder, TableNamesBuilder,
+    ConnectionInternal, CreateTableBuilder, CreateViewBuilder, NoData, OpenTableBuilder,
+    TableNamesBuilder,
 };
-use crate::error::Result;
+use crate::embeddings::{self, EmbeddingRegistry, WithEmbeddings};
+use crate::error::{Error, Result};
+use crate::table::compression;
+use crate::table::pack::PackageInfo;
+use crate::table::primary_key;
+use crate::table::snapshot::SnapshotInfo;
+use crate::table::{blob, dictionary};
 use crate::Table;
 
-use super::client::RestfulLanceDbClient;
+use super::client::{ClientConfig, RestfulLanceDbClient};
 use super::table::RemoteTable;
 use super::util::batches_to_ipc_bytes;
+use super::ARROW_STREAM_CONTENT_TYPE;
 
-const ARROW_STREAM_CONTENT_TYPE: &str = "application/vnd.apache.arrow.stream";
+/// The number of names requested at once when the names are filtered locally
+const LIST_PAGE_SIZE: u32 = 1000;
 
 #[derive(Deserialize)]
 struct ListTablesResponse {
@@ -40,6 +50,7 @@ struct ListTablesResponse {
 #[derive(Debug)]
 pub struct RemoteDatabase {
     client: RestfulLanceDbClient,
+    embedding_registry: Arc<dyn EmbeddingRegistry>,
 }
 
 impl RemoteDatabase {
@@ -48,9 +59,33 @@ impl RemoteDatabase {
         api_key: &str,
         region: &str,
         host_override: Option<String>,
+        client_config: ClientConfig,
+        embedding_registry: Arc<dyn EmbeddingRegistry>,
     ) -> Result<Self> {
-        let client = RestfulLanceDbClient::try_new(uri, api_key, region, host_override)?;
-        Ok(Self { client })
+        let client =
+            RestfulLanceDbClient::try_new(uri, api_key, region, host_override, client_config)?;
+        Ok(Self {
+            client,
+            embedding_registry,
+        })
+    }
+
+    /// List one page of the table names
+    async fn list_tables(
+        &self,
+        start_after: Option<&str>,
+        limit: Option<u32>,
+    ) -> Result<Vec<String>> {
+        let mut req = self.client.get("/v1/table/");
+        if let Some(limit) = limit {
+            req = req.query(&[("limit", limit)]);
+        }
+        if let Some(start_after) = start_after {
+            req = req.query(&[("page_token", start_after)]);
+        }
+        let rsp = self.client.send(req).await?;
+        let rsp = self.client.check_response(rsp).await?;
+        Ok(rsp.json::<ListTablesResponse>().await?.tables)
     }
 }
 
@@ -63,16 +98,32 @@ impl std::fmt::Display for RemoteDatabase {
 #[async_trait]
 impl ConnectionInternal for RemoteDatabase {
     async fn table_names(&self, options: TableNamesBuilder) -> Result<Vec<String>> {
-        let mut req = self.client.get("/v1/table/");
-        if let Some(limit) = options.limit {
-            req = req.query(&[("limit", limit)]);
+        if !options.has_filter() {
+            return self
+                .list_tables(options.start_after.as_deref(), options.limit)
+                .await;
         }
-        if let Some(start_after) = options.start_after {
-            req = req.query(&[("page_token", start_after)]);
+        // LanceDB Cloud does not filter the names, so the pages are filtered here
+        let mut names = Vec::new();
+        let mut start_after = options.start_after.clone();
+        loop {
+            let page = self
+                .list_tables(start_after.as_deref(), Some(LIST_PAGE_SIZE))
+                .await?;
+            let done = page.len() < LIST_PAGE_SIZE as usize;
+            start_after = page.last().cloned();
+            names.extend(page.into_iter().filter(|name| options.matches(name)));
+            if let Some(limit) = options.limit {
+                if names.len() >= limit as usize {
+                    names.truncate(limit as usize);
+                    break;
+                }
+            }
+            if done {
+                break;
+            }
         }
-        let rsp = req.send().await?;
-        let rsp = self.client.check_response(rsp).await?;
-        Ok(rsp.json::<ListTablesResponse>().await?.tables)
+        Ok(names)
     }
 
     async fn do_create_table(
@@ -80,6 +131,43 @@ impl ConnectionInternal for RemoteDatabase {
         options: CreateTableBuilder<false, NoData>,
         data: Box<dyn RecordBatchReader + Send>,
     ) -> Result<Table> {
+        if options.temporary {
+            return Err(Error::NotSupported {
+                message: "temporary tables are not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if options.soft_delete {
+            return Err(Error::NotSupported {
+                message: "soft delete is not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if options.ttl.is_some() {
+            return Err(Error::NotSupported {
+                message: "time to live is not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        if options.partition_by.is_some() {
+            return Err(Error::NotSupported {
+                message: "partitioned tables are not supported by LanceDB Cloud".to_string(),
+            });
+        }
+        // Embeddings are computed on the client before the data is uploaded
+        let data: Box<dyn RecordBatchReader + Send> = if options.embeddings.is_empty() {
+            data
+        } else {
+            let embeddings =
+                embeddings::resolve(self.embedding_registry.as_ref(), options.embeddings)?;
+            Box::new(WithEmbeddings::try_new(data, embeddings)?)
+        };
+        // The codecs and encodings are sent in the field metadata of the schema
+        let data = compression::with_compression(data, &options.compression)?;
+        let data =
+            dictionary::with_dictionaries(data, &options.dictionary, options.auto_dictionary)?;
+        let data = blob::with_blobs(data, &options.blobs)?;
+        let data = match &options.primary_key {
+            Some(column) => primary_key::with_primary_key(data, column)?,
+            None => data,
+        };
         // TODO: https://github.com/lancedb/lancedb/issues/1026
         // We should accept data from an async source.  In the meantime, spawn this as blocking
         // to make sure we don't block the tokio runtime if the source is slow.
@@ -87,14 +175,21 @@ impl ConnectionInternal for RemoteDatabase {
             .await
             .unwrap()?;
 
-        self.client
-            .post(&format!("/v1/table/{}/create", options.name))
-            .body(data_buffer)
-            .header(CONTENT_TYPE, ARROW_STREAM_CONTENT_TYPE)
-            // This is currently expected by LanceDb cloud but will be removed soon.
-            .header("x-request-id", "na")
-            .send()
+        let rsp = self
+            .client
+            .send(
+                self.client
+                    .post(&format!("/v1/table/{}/create", options.name))
+                    .body(data_buffer)
+                    .header(CONTENT_TYPE, ARROW_STREAM_CONTENT_TYPE)
+                    // This is currently expected by LanceDb cloud but will be removed soon.
+                    .header("x-request-id", "na"),
+            )
             .await?;
+        if rsp.status() == StatusCode::CONFLICT {
+            return Err(Error::TableAlreadyExists { name: options.name });
+        }
+        self.client.check_response(rsp).await?;
 
         Ok(Table::new(Arc::new(RemoteTable::new(
             self.client.clone(),
@@ -102,15 +197,112 @@ impl ConnectionInternal for RemoteDatabase {
         ))))
     }
 
-    async fn do_open_table(&self, _options: OpenTableBuilder) -> Result<Table> {
-        todo!()
+    async fn do_open_table(&self, options: OpenTableBuilder) -> Result<Table> {
+        if options.version.is_some() {
+            return Err(Error::NotSupported {
+                message: "opening a version of a table is not supported by LanceDB Cloud"
+                    .to_string(),
+            });
+        }
+        let table = RemoteTable::new(self.client.clone(), options.name);
+        // Fail early if the table does not exist
+        table.describe().await?;
+        Ok(Table::new(Arc::new(table)))
+    }
+
+    async fn drop_table(&self, name: &str) -> Result<()> {
+        let rsp = self
+            .client
+            .send(self.client.post(&format!("/v1/table/{}/drop/", name)))
+            .await?;
+        if rsp.status() == StatusCode::NOT_FOUND {
+            return Err(Error::TableNotFound {
+                name: name.to_string(),
+            });
+        }
+        self.client.check_response(rsp).await?;
+        Ok(())
     }
 
-    async fn drop_table(&self, _name: &str) -> Result<()> {
-        todo!()
+    async fn rename_table(&self, _old_name: &str, _new_name: &str) -> Result<()> {
+        Err(Error::NotSupported {
+            message: "renaming a table is not supported by LanceDB Cloud".to_string(),
+        })
+    }
+
+    async fn clone_table(&self, _source: &str, _target: &str) -> Result<()> {
+        Err(Error::NotSupported {
+            message: "cloning a table is not supported by LanceDB Cloud".to_string(),
+        })
+    }
+
+    async fn unpack(&self, _path: &std::path::Path, _name: &str) -> Result<PackageInfo> {
+        Err(Error::NotSupported {
+            message: "unpacking a table is not supported by LanceDB Cloud".to_string(),
+        })
+    }
+
+    async fn import_snapshot(&self, _source_uri: &str, _name: &str) -> Result<SnapshotInfo> {
+        Err(Error::NotSupported {
+            message: "importing a snapshot is not supported by LanceDB Cloud".to_string(),
+        })
+    }
+
+    async fn persist_to(&self, _uri: &str) -> Result<()> {
+        Err(Error::NotSupported {
+            message: "persisting a database is not supported by LanceDB Cloud".to_string(),
+        })
     }
 
     async fn drop_db(&self) -> Result<()> {
-        todo!()
+        Err(Error::NotSupported {
+            message: "dropping a database is not supported by LanceDB Cloud".to_string(),
+        })
+    }
+
+    fn namespace(&self, _name: &str) -> Result<Arc<dyn ConnectionInternal>> {
+        Err(Error::NotSupported {
+            message: "namespaces are not supported by LanceDB Cloud".to_string(),
+        })
+    }
+
+    async fn list_namespaces(&self) -> Result<Vec<String>> {
+        Err(Error::NotSupported {
+            message: "namespaces are not supported by LanceDB Cloud".to_string(),
+        })
+    }
+
+    async fn create_namespace(&self, _name: &str) -> Result<()> {
+        Err(Error::NotSupported {
+            message: "namespaces are not supported by LanceDB Cloud".to_string(),
+        })
+    }
+
+    async fn drop_namespace(&self, _name: &str) -> Result<()> {
+        Err(Error::NotSupported {
+            message: "namespaces are not supported by LanceDB Cloud".to_string(),
+        })
+    }
+
+    fn embedding_registry(&self) -> &dyn EmbeddingRegistry {
+        self.embedding_registry.as_ref()
+    }
+
+    async fn do_create_view(&self, _options: CreateViewBuilder) -> Result<Table> {
+        Err(Error::NotSupported {
+            message: "views are not supported by LanceDB Cloud".to_string(),
+        })
+    }
+
+    async fn open_view(&self, _name: &str) -> Result<Table> {
+        Err(Error::NotSupported {
+            message: "views are not supported by LanceDB Cloud".to_string(),
+        })
+    }
+
+    async fn drop_view(&self, _name: &str) -> Result<()> {
+        Err(Error::NotSupported {
+            message: "views are not supported by LanceDB Cloud".to_string(),
+        })
     }
 }
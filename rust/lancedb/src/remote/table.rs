@@ -1,24 +1,211 @@
-use arrow_array::RecordBatchReader;
-use arrow_schema::SchemaRef;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::AsArray, types::Float32Type, FixedSizeListArray, RecordBatch, RecordBatchReader,
+};
+use arrow_ipc::reader::FileReader;
+use arrow_schema::{DataType, Schema, SchemaRef};
 use async_trait::async_trait;
-use lance::dataset::{scanner::DatasetRecordBatchStream, ColumnAlteration, NewColumnTransform};
+use lance::arrow::json::JsonSchema;
+use lance::dataset::{ColumnAlteration, NewColumnTransform};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Response, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::task::spawn_blocking;
 
 use crate::{
+    arrow::{SendableRecordBatchStream, SimpleRecordBatchStream},
     connection::NoData,
-    error::Result,
-    index::{IndexBuilder, IndexConfig},
-    query::{Query, QueryExecutionOptions, VectorQuery},
+    error::{Error, Result},
+    index::{vector::Accelerator, Index, IndexBuilder, IndexConfig, IndexStatistics, IndexType},
+    io::checksum::ChecksumReport,
+    query::{
+        batch, filter::Filter, metrics::ScanStats, Query, QueryExecutionOptions, Select,
+        UnindexedPolicy, VectorQuery, DEFAULT_TOP_K,
+    },
     table::{
-        merge::MergeInsertBuilder, AddDataBuilder, NativeTable, OptimizeAction, OptimizeStats,
-        TableInternal, UpdateBuilder,
+        changes::{ChangeStream, ChangesBuilder},
+        cluster::ClusterBuilder,
+        dry_run::{DryRunOperation, DryRunReport},
+        merge::MergeInsertBuilder,
+        migrate::MigrateVectorDimBuilder,
+        pack::PackageInfo,
+        prewarm::{CacheResidency, PrewarmBuilder},
+        snapshot::SnapshotInfo,
+        split::SplitBuilder,
+        stats::ColumnStatistics,
+        usage::TableStatistics,
+        AddDataBuilder, AddDataMode, DeleteResult, NativeTable, OptimizeAction,
+        OptimizeIndexOptions, OptimizeStats, RowVersion, SchemaPolicy, Table, TableInternal,
+        UpdateBuilder, Version,
     },
+    DistanceType,
 };
 
 use super::client::RestfulLanceDbClient;
+use super::util::batches_to_ipc_bytes;
+use super::ARROW_STREAM_CONTENT_TYPE;
+
+#[derive(Deserialize)]
+struct TableDescription {
+    version: u64,
+    schema: JsonSchema,
+}
+
+#[derive(Deserialize)]
+struct ListIndicesResponse {
+    indexes: Vec<IndexDescription>,
+}
+
+#[derive(Deserialize)]
+struct IndexDescription {
+    index_name: String,
+    columns: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct IndexStatsResponse {
+    index_type: String,
+    num_indexed_rows: usize,
+    num_unindexed_rows: usize,
+}
+
+fn not_supported(operation: &str) -> Error {
+    Error::NotSupported {
+        message: format!("{} is not supported by LanceDB Cloud", operation),
+    }
+}
+
+fn index_type(name: &str) -> Result<IndexType> {
+    match name.to_ascii_uppercase().as_str() {
+        "IVF_PQ" => Ok(IndexType::IvfPq),
+        "BTREE" => Ok(IndexType::BTree),
+        #[cfg(feature = "fts")]
+        "FTS" => Ok(IndexType::Fts),
+        _ => Err(Error::Http {
+            message: format!("unknown index type '{}' returned by the server", name),
+        }),
+    }
+}
 
+/// The body of a query request
+///
+/// The filter is resolved by the caller, with its parameters bound, as the
+/// server does not know about parameters.
+fn query_body(query: &Query, filter: Option<String>) -> Result<Value> {
+    if query.only_deleted {
+        return Err(not_supported("soft delete"));
+    }
+    if query.with_row_address {
+        return Err(not_supported("row addresses"));
+    }
+    if query.offset.is_some() || query.after.is_some() {
+        return Err(not_supported("pagination"));
+    }
+    if !query.order_by.is_empty() {
+        return Err(not_supported("ordered scans"));
+    }
+    if query.sample.is_some() {
+        return Err(not_supported("sampling"));
+    }
+    if !query.computed_columns.is_empty() {
+        return Err(not_supported("computed columns"));
+    }
+    if query.exclude_blobs {
+        return Err(not_supported("excluding the blob columns"));
+    }
+    #[cfg(feature = "fts")]
+    if query.full_text_search.is_some() {
+        return Err(not_supported("full text search from the Rust client"));
+    }
+    let mut body = json!({
+        "k": query.limit.unwrap_or(DEFAULT_TOP_K),
+        "filter": filter,
+        "with_row_id": query.with_row_id,
+    });
+    match &query.select {
+        Select::All => {}
+        Select::Columns(columns) => body["columns"] = json!(columns),
+        Select::Dynamic(_) => return Err(not_supported("a dynamic projection")),
+    }
+    Ok(body)
+}
+
+/// The body of a vector query request, see [`query_body`]
+fn vector_query_body(query: &VectorQuery, filter: Option<String>) -> Result<Value> {
+    let mut body = query_body(&query.base, filter)?;
+    if let Some(query_vector) = &query.query_vector {
+        if !query_vector.data_type().is_floating() {
+            return Err(Error::NotSupported {
+                message: "LanceDB Cloud can only be searched with float query vectors".to_string(),
+            });
+        }
+        let values = arrow_cast::cast(query_vector, &DataType::Float32)?;
+        body["vector"] = json!(values.as_primitive::<Float32Type>().values().to_vec());
+    }
+    if let Some(column) = &query.column {
+        body["vector_column"] = json!(column);
+    }
+    if let Some(distance_type) = query.distance_type {
+        if matches!(distance_type, DistanceType::Hamming | DistanceType::MaxSim) {
+            return Err(not_supported(&format!("the {} distance", distance_type)));
+        }
+        body["metric"] = json!(distance_type.to_string());
+    }
+    if query.lower_bound.is_some() || query.upper_bound.is_some() {
+        return Err(not_supported("a distance range"));
+    }
+    body["nprobes"] = json!(query.nprobes);
+    if query.refine_with_exact {
+        return Err(not_supported("re-scoring with exact distances"));
+    }
+    if query.group_by.is_some() {
+        return Err(not_supported("grouping the results"));
+    }
+    body["refine_factor"] = json!(query.refine_factor);
+    body["prefilter"] = json!(query.prefilter);
+    body["bypass_vector_index"] = json!(!query.use_index);
+    match query.unindexed {
+        UnindexedPolicy::FlatScan => body["fast_search"] = json!(false),
+        UnindexedPolicy::Skip => body["fast_search"] = json!(true),
+        UnindexedPolicy::Error => return Err(not_supported("failing on unindexed rows")),
+    }
+    if let Some(ef) = query.ef {
+        body["ef"] = json!(ef);
+    }
+    Ok(body)
+}
+
+/// Resolve the parameters of `filter`
+fn bind_filter(
+    filter: Option<&str>,
+    params: &[(String, crate::query::filter::FilterValue)],
+) -> Result<Option<String>> {
+    match filter {
+        Some(filter) => {
+            let filter = params
+                .iter()
+                .fold(Filter::parse(filter)?, |filter, (name, value)| {
+                    filter.bind(name.clone(), value.clone())
+                });
+            Ok(Some(filter.to_sql()?))
+        }
+        None if !params.is_empty() => Err(Error::InvalidInput {
+            message: "values were bound to parameters but there is no filter".to_string(),
+        }),
+        None => Ok(None),
+    }
+}
+
+/// A table of a LanceDB Cloud database
+///
+/// Each operation is a request to the server, the table keeps no state.
 #[derive(Debug)]
 pub struct RemoteTable {
-    #[allow(dead_code)]
     client: RestfulLanceDbClient,
     name: String,
 }
@@ -27,6 +214,88 @@ impl RemoteTable {
     pub fn new(client: RestfulLanceDbClient, name: String) -> Self {
         Self { client, name }
     }
+
+    /// Check the response to a request about this table
+    async fn check_table_response(&self, response: Response) -> Result<Response> {
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(Error::TableNotFound {
+                name: self.name.clone(),
+            });
+        }
+        self.client.check_response(response).await
+    }
+
+    /// Send a JSON request to the table endpoint `endpoint`
+    async fn post_json(&self, endpoint: &str, body: &Value) -> Result<Response> {
+        let response = self
+            .client
+            .send(
+                self.client
+                    .post(&format!("/v1/table/{}/{}/", self.name, endpoint))
+                    .json(body),
+            )
+            .await?;
+        self.check_table_response(response).await
+    }
+
+    /// Send `data`, in the Arrow IPC format, to the table endpoint `endpoint`
+    async fn post_data(
+        &self,
+        endpoint: &str,
+        params: &[(&str, String)],
+        data: Box<dyn RecordBatchReader + Send>,
+    ) -> Result<Response> {
+        let body = spawn_blocking(move || batches_to_ipc_bytes(data))
+            .await
+            .map_err(|e| Error::Runtime {
+                message: format!("failed to encode the data: {}", e),
+            })??;
+        let response = self
+            .client
+            .send(
+                self.client
+                    .post(&format!("/v1/table/{}/{}/", self.name, endpoint))
+                    .query(params)
+                    .header(CONTENT_TYPE, ARROW_STREAM_CONTENT_TYPE)
+                    .body(body),
+            )
+            .await?;
+        self.check_table_response(response).await
+    }
+
+    /// Check that the table exists and return its version and schema
+    pub(super) async fn describe(&self) -> Result<(u64, SchemaRef)> {
+        let description = self
+            .post_json("describe", &json!({}))
+            .await?
+            .json::<TableDescription>()
+            .await?;
+        let schema = Schema::try_from(&description.schema)?;
+        Ok((description.version, Arc::new(schema)))
+    }
+
+    /// Run a query, the results are returned by the server in the Arrow IPC
+    /// file format
+    async fn query(&self, body: Value) -> Result<SendableRecordBatchStream> {
+        let bytes = self.post_json("query", &body).await?.bytes().await?;
+        let reader = FileReader::try_new(Cursor::new(bytes), None)?;
+        let schema = reader.schema();
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(Box::pin(SimpleRecordBatchStream {
+            schema,
+            stream: futures::stream::iter(batches.into_iter().map(Ok)),
+        }))
+    }
+
+    /// The filter of `query` with its parameters bound and its time ranges added
+    async fn resolved_filter(&self, query: &Query) -> Result<Option<String>> {
+        if query.time_ranges.is_empty() {
+            return bind_filter(query.filter.as_deref(), &query.filter_params);
+        }
+        // Time ranges are converted according to the type of their column
+        let (_, schema) = self.describe().await?;
+        query.resolved_filter(&schema)
+    }
 }
 
 impl std::fmt::Display for RemoteTable {
@@ -47,77 +316,403 @@ impl TableInternal for RemoteTable {
         &self.name
     }
     async fn version(&self) -> Result<u64> {
-        todo!()
+        Ok(self.describe().await?.0)
     }
     async fn checkout(&self, _version: u64) -> Result<()> {
-        todo!()
+        Err(not_supported("checking out a version"))
     }
     async fn checkout_latest(&self) -> Result<()> {
-        todo!()
+        Err(not_supported("checking out a version"))
+    }
+    async fn list_versions(&self) -> Result<Vec<Version>> {
+        Err(not_supported("listing versions"))
+    }
+    async fn list_tags(&self) -> Result<BTreeMap<String, u64>> {
+        Err(not_supported("tagging versions"))
+    }
+    async fn create_tag(&self, _tag: &str, _version: u64) -> Result<()> {
+        Err(not_supported("tagging versions"))
+    }
+    async fn delete_tag(&self, _tag: &str) -> Result<()> {
+        Err(not_supported("tagging versions"))
     }
     async fn restore(&self) -> Result<()> {
-        todo!()
+        Err(not_supported("restoring a version"))
     }
     async fn schema(&self) -> Result<SchemaRef> {
-        todo!()
+        Ok(self.describe().await?.1)
     }
-    async fn count_rows(&self, _filter: Option<String>) -> Result<usize> {
-        todo!()
+    async fn count_rows(&self, filter: Option<String>) -> Result<usize> {
+        let predicate = bind_filter(filter.as_deref(), &[])?;
+        Ok(self
+            .post_json("count_rows", &json!({ "predicate": predicate }))
+            .await?
+            .json::<usize>()
+            .await?)
     }
     async fn add(
         &self,
-        _add: AddDataBuilder<NoData>,
-        _data: Box<dyn RecordBatchReader + Send>,
+        add: AddDataBuilder<NoData>,
+        data: Box<dyn RecordBatchReader + Send>,
     ) -> Result<()> {
-        todo!()
+        if add.idempotency_key.is_some() {
+            return Err(not_supported("idempotent writes"));
+        }
+        if add.partition_by.is_some() {
+            return Err(not_supported("partitioned writes"));
+        }
+        if add.schema_policy != SchemaPolicy::default() {
+            return Err(not_supported("schema coercion"));
+        }
+        let mode = match add.mode {
+            // The keys were checked by AddDataBuilder::execute
+            AddDataMode::Append | AddDataMode::ErrorOnConflict => "append",
+            AddDataMode::Overwrite => "overwrite",
+            AddDataMode::BulkIngest(_) => return Err(not_supported("bulk ingest")),
+            AddDataMode::OverwriteOnConflict => {
+                return Err(Error::InvalidInput {
+                    message: "overwriting rows on conflict requires a merge insert".to_string(),
+                })
+            }
+        };
+        self.post_data("insert", &[("mode", mode.to_string())], data)
+            .await?;
+        Ok(())
     }
     async fn plain_query(
         &self,
-        _query: &Query,
+        query: &Query,
         _options: QueryExecutionOptions,
-    ) -> Result<DatasetRecordBatchStream> {
-        todo!()
+    ) -> Result<SendableRecordBatchStream> {
+        let filter = self.resolved_filter(query).await?;
+        self.query(query_body(query, filter)?).await
     }
     async fn vector_query(
         &self,
-        _query: &VectorQuery,
+        query: &VectorQuery,
         _options: QueryExecutionOptions,
-    ) -> Result<DatasetRecordBatchStream> {
-        todo!()
+    ) -> Result<SendableRecordBatchStream> {
+        let filter = self.resolved_filter(&query.base).await?;
+        if query.batch_vectors.is_empty() {
+            return self.query(vector_query_body(query, filter)?).await;
+        }
+        // Each query vector of a batch is sent as its own search
+        batch::execute_batch(query, |query| {
+            let table = Self::new(self.client.clone(), self.name.clone());
+            let body = vector_query_body(&query, filter.clone());
+            async move { table.query(body?).await }
+        })
+        .await
+    }
+    async fn explain_plan(&self, _query: &VectorQuery, _verbose: bool) -> Result<String> {
+        Err(not_supported("explaining queries"))
     }
-    async fn update(&self, _update: UpdateBuilder) -> Result<()> {
-        todo!()
+    async fn scan_stats(&self, _query: &VectorQuery) -> Result<Option<ScanStats>> {
+        // The search runs on the server, which does not report its work
+        Ok(None)
     }
-    async fn delete(&self, _predicate: &str) -> Result<()> {
-        todo!()
+    async fn update(&self, update: UpdateBuilder) -> Result<()> {
+        let predicate = bind_filter(update.filter.as_deref(), &update.filter_params)?;
+        self.post_json(
+            "update",
+            &json!({ "predicate": predicate, "updates": update.columns }),
+        )
+        .await?;
+        Ok(())
     }
-    async fn create_index(&self, _index: IndexBuilder) -> Result<()> {
-        todo!()
+    async fn delete(&self, predicate: &str) -> Result<DeleteResult> {
+        let predicate = Filter::parse(predicate)?.to_sql()?;
+        // The server does not report the number of deleted rows, so they are
+        // counted first
+        let num_deleted_rows = self.count_rows(Some(predicate.clone())).await? as u64;
+        if num_deleted_rows > 0 {
+            self.post_json("delete", &json!({ "predicate": predicate }))
+                .await?;
+        }
+        Ok(DeleteResult {
+            num_deleted_rows,
+            version: self.version().await?,
+        })
+    }
+    async fn undelete(&self, _filter: &str) -> Result<()> {
+        Err(not_supported("soft delete"))
+    }
+    async fn create_index(&self, index: IndexBuilder) -> Result<()> {
+        let [column] = index.columns.as_slice() else {
+            return Err(Error::InvalidInput {
+                message: "indices can only be created on a single column".to_string(),
+            });
+        };
+        let index_kind = match index.index {
+            Index::Auto => {
+                let (_, schema) = self.describe().await?;
+                match schema.field_with_name(column)?.data_type() {
+                    DataType::FixedSizeList(..) => Index::IvfPq(Default::default()),
+                    _ => Index::BTree(Default::default()),
+                }
+            }
+            index => index,
+        };
+        match index_kind {
+            Index::IvfPq(params) => {
+                let metric = params.distance_type.to_string();
+                self.post_json(
+                    "create_index",
+                    &json!({
+                        "column": column,
+                        "index_type": "IVF_PQ",
+                        "metric_type": metric,
+                        "replace": index.replace,
+                    }),
+                )
+                .await?;
+            }
+            Index::BTree(_) => {
+                self.post_json(
+                    "create_scalar_index",
+                    &json!({
+                        "column": column,
+                        "index_type": "BTREE",
+                        "replace": index.replace,
+                    }),
+                )
+                .await?;
+            }
+            _ => return Err(not_supported("this index type")),
+        }
+        Ok(())
     }
     async fn merge_insert(
         &self,
-        _params: MergeInsertBuilder,
-        _new_data: Box<dyn RecordBatchReader + Send>,
+        params: MergeInsertBuilder,
+        new_data: Box<dyn RecordBatchReader + Send>,
     ) -> Result<()> {
-        todo!()
+        if params.idempotency_key.is_some() {
+            return Err(not_supported("idempotent writes"));
+        }
+        if params.schema_policy != SchemaPolicy::default() {
+            return Err(not_supported("schema coercion"));
+        }
+        let mut query = params
+            .on
+            .iter()
+            .map(|column| ("on", column.clone()))
+            .collect::<Vec<_>>();
+        query.push((
+            "when_matched_update_all",
+            params.when_matched_update_all.to_string(),
+        ));
+        if let Some(filter) = params.when_matched_update_all_filt {
+            query.push(("when_matched_update_all_filt", filter));
+        }
+        query.push((
+            "when_not_matched_insert_all",
+            params.when_not_matched_insert_all.to_string(),
+        ));
+        query.push((
+            "when_not_matched_by_source_delete",
+            params.when_not_matched_by_source_delete.to_string(),
+        ));
+        if let Some(filter) = params.when_not_matched_by_source_delete_filt {
+            query.push(("when_not_matched_by_source_delete_filt", filter));
+        }
+        self.post_data("merge_insert", &query, new_data).await?;
+        Ok(())
     }
     async fn optimize(&self, _action: OptimizeAction) -> Result<OptimizeStats> {
-        todo!()
+        Err(Error::NotSupported {
+            message: "LanceDB Cloud optimizes tables automatically".to_string(),
+        })
     }
     async fn add_columns(
         &self,
         _transforms: NewColumnTransform,
         _read_columns: Option<Vec<String>>,
     ) -> Result<()> {
-        todo!()
+        Err(not_supported("adding columns"))
     }
     async fn alter_columns(&self, _alterations: &[ColumnAlteration]) -> Result<()> {
-        todo!()
+        Err(not_supported("altering columns"))
     }
     async fn drop_columns(&self, _columns: &[&str]) -> Result<()> {
-        todo!()
+        Err(not_supported("dropping columns"))
+    }
+    async fn update_metadata(
+        &self,
+        _column: Option<&str>,
+        _metadata: HashMap<String, String>,
+    ) -> Result<()> {
+        Err(not_supported("updating metadata"))
     }
     async fn list_indices(&self) -> Result<Vec<IndexConfig>> {
-        todo!()
+        let indices = self
+            .post_json("index/list", &json!({}))
+            .await?
+            .json::<ListIndicesResponse>()
+            .await?;
+        let mut configs = Vec::with_capacity(indices.indexes.len());
+        for index in indices.indexes {
+            // The type of an index is only part of its statistics
+            let Some(stats) = self.index_stats(&index.index_name).await? else {
+                continue;
+            };
+            configs.push(IndexConfig {
+                name: index.index_name,
+                index_type: stats.index_type,
+                columns: index.columns,
+            });
+        }
+        Ok(configs)
+    }
+    async fn row_history(&self, _key_filter: &str) -> Result<Vec<RowVersion>> {
+        Err(not_supported("row history"))
+    }
+    async fn cluster(&self, _params: ClusterBuilder) -> Result<FixedSizeListArray> {
+        Err(not_supported("clustering"))
+    }
+    async fn ivf_centroids(&self, _column: &str) -> Result<FixedSizeListArray> {
+        Err(not_supported("reading the centroids of an index"))
+    }
+    async fn column_stats(&self, _column: &str) -> Result<ColumnStatistics> {
+        Err(not_supported("column statistics"))
+    }
+    async fn stats(&self) -> Result<TableStatistics> {
+        Err(not_supported("table statistics"))
+    }
+    async fn take(&self, _row_ids: &[u64], _select: Select) -> Result<RecordBatch> {
+        Err(not_supported("taking rows by id"))
+    }
+    async fn verify_checksums(&self) -> Result<ChecksumReport> {
+        Err(not_supported("checksums"))
+    }
+    async fn rebuild_missing_indices(&self) -> Result<Vec<String>> {
+        Err(not_supported("rebuilding indices"))
+    }
+    async fn optimize_indices(&self, _options: OptimizeIndexOptions) -> Result<Vec<String>> {
+        Err(not_supported("optimizing indices"))
+    }
+    async fn split(&self, _params: SplitBuilder) -> Result<Vec<Table>> {
+        Err(not_supported("splitting tables"))
+    }
+    async fn prewarm(&self, _params: PrewarmBuilder) -> Result<()> {
+        Err(not_supported("prewarming tables"))
+    }
+    async fn cache_residency(&self) -> Result<CacheResidency> {
+        Err(not_supported("cache residency"))
+    }
+    async fn changes(&self, _params: ChangesBuilder) -> Result<ChangeStream> {
+        Err(not_supported("change feeds"))
+    }
+    async fn gpu_search(&self, _column: &str, _accelerator: Accelerator) -> Result<()> {
+        Err(not_supported("GPU search"))
+    }
+    async fn migrate_vector_dim(&self, _params: MigrateVectorDimBuilder) -> Result<()> {
+        Err(not_supported("migrating vector dimensions"))
+    }
+    async fn pack(&self, _path: &Path) -> Result<PackageInfo> {
+        Err(not_supported("packing tables"))
+    }
+    async fn export_snapshot(&self, _target_uri: &str) -> Result<SnapshotInfo> {
+        Err(not_supported("exporting snapshots"))
+    }
+    async fn dry_run(&self, _operation: DryRunOperation) -> Result<DryRunReport> {
+        Err(not_supported("dry runs"))
+    }
+    async fn index_stats(&self, name: &str) -> Result<Option<IndexStatistics>> {
+        let response = self
+            .client
+            .send(
+                self.client
+                    .post(&format!("/v1/table/{}/index/{}/stats/", self.name, name)),
+            )
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let stats = self
+            .client
+            .check_response(response)
+            .await?
+            .json::<IndexStatsResponse>()
+            .await?;
+        Ok(Some(IndexStatistics {
+            index_type: index_type(&stats.index_type)?,
+            num_indexed_rows: stats.num_indexed_rows,
+            num_unindexed_rows: stats.num_unindexed_rows,
+        }))
+    }
+    async fn drop_index(&self, name: &str) -> Result<()> {
+        let response = self
+            .client
+            .send(
+                self.client
+                    .post(&format!("/v1/table/{}/index/{}/drop/", self.name, name)),
+            )
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(Error::IndexNotFound {
+                name: name.to_string(),
+            });
+        }
+        self.client.check_response(response).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{filter::FilterValue, QueryBase};
+
+    #[test]
+    fn test_bind_filter() {
+        let params = vec![("min".to_string(), FilterValue::from(5))];
+        assert_eq!(
+            bind_filter(Some("id > $min"), &params).unwrap().as_deref(),
+            Some("id > 5")
+        );
+        assert!(bind_filter(None, &params).is_err());
+        assert_eq!(bind_filter(None, &[]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_index_type() {
+        assert_eq!(index_type("IVF_PQ").unwrap(), IndexType::IvfPq);
+        assert_eq!(index_type("btree").unwrap(), IndexType::BTree);
+        assert!(index_type("unknown").is_err());
+    }
+
+    #[test]
+    fn test_vector_query_body() {
+        let client = RestfulLanceDbClient::try_new(
+            "db://test",
+            "api-key",
+            "us-east-1",
+            None,
+            Default::default(),
+        )
+        .unwrap();
+        let table = Arc::new(RemoteTable::new(client, "test".to_string()));
+        let query = Query::new(table)
+            .limit(5)
+            .select(Select::columns(&["id"]))
+            .nearest_to(&[1.0, 2.0])
+            .unwrap()
+            .column("vector")
+            .distance_type(DistanceType::Cosine);
+        let body = vector_query_body(&query, Some("id > 5".to_string())).unwrap();
+        assert_eq!(body["vector"], json!([1.0, 2.0]));
+        assert_eq!(body["vector_column"], json!("vector"));
+        assert_eq!(body["metric"], json!("cosine"));
+        assert_eq!(body["k"], json!(5));
+        assert_eq!(body["filter"], json!("id > 5"));
+        assert_eq!(body["columns"], json!(["id"]));
+        assert_eq!(body["bypass_vector_index"], json!(false));
+
+        let query = query.distance_type(DistanceType::Hamming);
+        assert!(matches!(
+            vector_query_body(&query, None),
+            Err(Error::NotSupported { .. })
+        ));
     }
 }
@@ -0,0 +1,331 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rerankers combine the results of several searches into a single ranking.
+//!
+//! They are used by hybrid search (see [`crate::query::VectorQuery::hybrid`])
+//! to merge the results of a vector search and a full text search.  This module
+//! contains two built-in rerankers, [`RRFReranker`] and [`LinearCombinationReranker`],
+//! and custom rerankers can be created by implementing the [`Reranker`] trait.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::AsArray,
+    types::{Float32Type, UInt64Type},
+    Array, Float32Array, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::error::{Error, Result};
+use crate::index::fts::{ROW_ID_COLUMN, SCORE_COLUMN};
+
+/// The name of the column containing the distance in vector search results
+pub const DISTANCE_COLUMN: &str = "_distance";
+/// The name of the column containing the combined score in hybrid search results
+pub const RELEVANCE_SCORE_COLUMN: &str = "_relevance_score";
+
+/// Combines the results of a vector search and a full text search
+///
+/// Both inputs contain the selected columns and a `_rowid` column which
+/// identifies the row.  The vector search results also contain a `_distance`
+/// column and are ordered from nearest to farthest.  The full text search
+/// results also contain a `_score` column and are ordered from most to least
+/// relevant.  A row may appear in both inputs.
+///
+/// The output should contain each row once, with the selected columns, the
+/// `_rowid` column, and a [`RELEVANCE_SCORE_COLUMN`] column, ordered from most
+/// to least relevant.  The query limit is applied to the output.  See
+/// [`merge_results`] for a helper that builds the output from a score per row.
+pub trait Reranker: std::fmt::Debug + Send + Sync {
+    fn rerank_hybrid(
+        &self,
+        query: &str,
+        vector_results: RecordBatch,
+        fts_results: RecordBatch,
+    ) -> Result<RecordBatch>;
+}
+
+fn column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a Arc<dyn Array>> {
+    batch
+        .column_by_name(name)
+        .ok_or_else(|| Error::InvalidInput {
+            message: format!("The search results are missing the {} column", name),
+        })
+}
+
+fn row_ids(batch: &RecordBatch) -> Result<Vec<u64>> {
+    Ok(column(batch, ROW_ID_COLUMN)?
+        .as_primitive::<UInt64Type>()
+        .values()
+        .to_vec())
+}
+
+fn float_values(batch: &RecordBatch, name: &str) -> Result<Vec<f32>> {
+    let values = arrow_cast::cast(column(batch, name)?, &DataType::Float32)?;
+    Ok(values.as_primitive::<Float32Type>().values().to_vec())
+}
+
+/// Build the hybrid search results from the relevance score of each row
+///
+/// The output contains the rows in `scores`, sorted by descending score, with
+/// the columns shared by both searches and an additional [`RELEVANCE_SCORE_COLUMN`].
+/// Rows with the same score are ordered by row id.
+/// Every row id in `scores` must appear in at least one of the results.
+pub fn merge_results(
+    vector_results: &RecordBatch,
+    fts_results: &RecordBatch,
+    mut scores: Vec<(u64, f32)>,
+) -> Result<RecordBatch> {
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    // The distance and score are specific to each search so they are not kept
+    let fields = vector_results
+        .schema()
+        .fields()
+        .iter()
+        .filter(|field| field.name() != DISTANCE_COLUMN)
+        .cloned()
+        .collect::<Vec<_>>();
+    let names = fields.iter().map(|f| f.name().as_str()).collect::<Vec<_>>();
+    let inputs = [vector_results, fts_results]
+        .into_iter()
+        .map(|batch| {
+            names
+                .iter()
+                .map(|name| column(batch, name).cloned())
+                .collect::<Result<Vec<_>>>()
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut locations = HashMap::new();
+    for (input, batch) in [vector_results, fts_results].into_iter().enumerate() {
+        for (row, row_id) in row_ids(batch)?.into_iter().enumerate() {
+            locations.entry(row_id).or_insert((input, row));
+        }
+    }
+    let indices = scores
+        .iter()
+        .map(|(row_id, _)| {
+            locations
+                .get(row_id)
+                .copied()
+                .ok_or_else(|| Error::InvalidInput {
+                    message: format!("Row {} is not in the search results", row_id),
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut columns = (0..names.len())
+        .map(|i| {
+            let values = inputs
+                .iter()
+                .map(|input| input[i].as_ref())
+                .collect::<Vec<_>>();
+            Ok(arrow::compute::interleave(&values, &indices)?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    columns.push(Arc::new(Float32Array::from_iter_values(
+        scores.iter().map(|(_, score)| *score),
+    )));
+    let mut fields = fields;
+    fields.push(Arc::new(Field::new(
+        RELEVANCE_SCORE_COLUMN,
+        DataType::Float32,
+        false,
+    )));
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+/// Reciprocal rank fusion
+///
+/// Each row is scored by the sum of `1 / (k + rank)` over the searches that
+/// found it, where `rank` is the 1-based position of the row in that search.
+/// Only the order of the results is used, so the distances and scores of the
+/// two searches do not need to be comparable.
+#[derive(Debug, Clone)]
+pub struct RRFReranker {
+    k: f32,
+}
+
+impl Default for RRFReranker {
+    fn default() -> Self {
+        Self { k: 60.0 }
+    }
+}
+
+impl RRFReranker {
+    /// Create a reranker with the given `k`
+    ///
+    /// Larger values reduce the advantage of the top ranked rows.  The default is 60.
+    pub fn new(k: f32) -> Self {
+        Self { k }
+    }
+}
+
+impl Reranker for RRFReranker {
+    fn rerank_hybrid(
+        &self,
+        _query: &str,
+        vector_results: RecordBatch,
+        fts_results: RecordBatch,
+    ) -> Result<RecordBatch> {
+        let mut scores = HashMap::<u64, f32>::new();
+        for batch in [&vector_results, &fts_results] {
+            for (rank, row_id) in row_ids(batch)?.into_iter().enumerate() {
+                *scores.entry(row_id).or_default() += 1.0 / (self.k + rank as f32 + 1.0);
+            }
+        }
+        merge_results(&vector_results, &fts_results, scores.into_iter().collect())
+    }
+}
+
+/// A weighted sum of the vector and full text search scores
+///
+/// Distances are normalized to `[0, 1]` and inverted so that the nearest row
+/// has a score of 1.  BM25 scores are normalized by dividing by the highest
+/// score.  A row that was not found by one of the searches gets a score of 0
+/// for that search.
+#[derive(Debug, Clone)]
+pub struct LinearCombinationReranker {
+    weight: f32,
+}
+
+impl Default for LinearCombinationReranker {
+    fn default() -> Self {
+        Self { weight: 0.7 }
+    }
+}
+
+impl LinearCombinationReranker {
+    /// Create a reranker which gives the vector search score a weight of `weight`
+    ///
+    /// The full text search score is given a weight of `1 - weight`.  The default is 0.7.
+    pub fn new(weight: f32) -> Result<Self> {
+        if !(0.0..=1.0).contains(&weight) {
+            return Err(Error::InvalidInput {
+                message: format!("weight must be between 0 and 1, got {}", weight),
+            });
+        }
+        Ok(Self { weight })
+    }
+}
+
+impl Reranker for LinearCombinationReranker {
+    fn rerank_hybrid(
+        &self,
+        _query: &str,
+        vector_results: RecordBatch,
+        fts_results: RecordBatch,
+    ) -> Result<RecordBatch> {
+        let mut scores = HashMap::<u64, f32>::new();
+
+        let distances = float_values(&vector_results, DISTANCE_COLUMN)?;
+        let min = distances.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = distances.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let range = max - min;
+        for (row_id, distance) in row_ids(&vector_results)?.into_iter().zip(distances) {
+            let similarity = if range > 0.0 {
+                1.0 - (distance - min) / range
+            } else {
+                1.0
+            };
+            *scores.entry(row_id).or_default() += self.weight * similarity;
+        }
+
+        let bm25 = float_values(&fts_results, SCORE_COLUMN)?;
+        let max = bm25.iter().copied().fold(0.0, f32::max);
+        for (row_id, score) in row_ids(&fts_results)?.into_iter().zip(bm25) {
+            let score = if max > 0.0 { score / max } else { 0.0 };
+            *scores.entry(row_id).or_default() += (1.0 - self.weight) * score;
+        }
+
+        merge_results(&vector_results, &fts_results, scores.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, UInt64Array};
+
+    use super::*;
+
+    fn results(ids: &[i32], row_ids: &[u64], scores: &[f32], score_column: &str) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(ROW_ID_COLUMN, DataType::UInt64, false),
+            Field::new(score_column, DataType::Float32, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int32Array::from(ids.to_vec())),
+                Arc::new(UInt64Array::from(row_ids.to_vec())),
+                Arc::new(Float32Array::from(scores.to_vec())),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn test_results() -> (RecordBatch, RecordBatch) {
+        (
+            results(&[1, 2, 3], &[1, 2, 3], &[0.1, 0.5, 0.9], DISTANCE_COLUMN),
+            results(&[3, 4], &[3, 4], &[2.0, 1.0], SCORE_COLUMN),
+        )
+    }
+
+    fn ids(batch: &RecordBatch) -> Vec<i32> {
+        batch["id"]
+            .as_primitive::<arrow_array::types::Int32Type>()
+            .values()
+            .to_vec()
+    }
+
+    #[test]
+    fn test_rrf() {
+        let (vector, fts) = test_results();
+        let batch = RRFReranker::new(1.0)
+            .rerank_hybrid("query", vector, fts)
+            .unwrap();
+        // Row 3 is found by both searches
+        assert_eq!(ids(&batch), vec![3, 1, 2, 4]);
+        let schema = batch.schema();
+        let names = schema
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["id", ROW_ID_COLUMN, RELEVANCE_SCORE_COLUMN]);
+        let scores = batch[RELEVANCE_SCORE_COLUMN].as_primitive::<Float32Type>();
+        assert_eq!(scores.value(0), 1.0 / 4.0 + 1.0 / 2.0);
+    }
+
+    #[test]
+    fn test_linear_combination() {
+        let (vector, fts) = test_results();
+        let batch = LinearCombinationReranker::new(0.5)
+            .unwrap()
+            .rerank_hybrid("query", vector, fts)
+            .unwrap();
+        assert_eq!(ids(&batch), vec![1, 3, 2, 4]);
+        let scores = batch[RELEVANCE_SCORE_COLUMN].as_primitive::<Float32Type>();
+        assert_eq!(scores.values().to_vec(), vec![0.5, 0.5, 0.25, 0.25]);
+
+        assert!(LinearCombinationReranker::new(1.5).is_err());
+    }
+}
@@ -0,0 +1,540 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Serve the tables of a connection over [Arrow Flight](https://arrow.apache.org/docs/format/Flight.html)
+//!
+//! This module is only available with the `server` feature.  [`FlightServer`]
+//! turns a [`Connection`] into a standalone vector database process that any
+//! language with an Arrow Flight client can use:
+//!
+//! - `ListFlights`, `GetFlightInfo` and `GetSchema` describe the tables, the
+//!   path of the descriptor of a table is its name.
+//! - `DoGet` runs a query, the ticket is a JSON [`QueryRequest`].  A ticket
+//!   with a `vector` runs a vector search, otherwise the table is scanned.
+//! - `DoPut` appends the batches to the table named by the path of the
+//!   descriptor, creating the table if it does not exist.  The batches are
+//!   collected in memory before they are written.
+//! - The `vector_search` action runs a vector search, the body is a JSON
+//!   [`QueryRequest`] and the result is an Arrow IPC file.  The
+//!   `list_tables` action returns the names of the tables, one per result,
+//!   and the `drop_table` action drops the table named by the body.
+//!
+//! ```no_run
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let db = lancedb::connect("data/sample-lancedb").execute().await.unwrap();
+//! lancedb::server::FlightServer::new(db)
+//!     .serve("0.0.0.0:50051".parse().unwrap())
+//!     .await
+//!     .unwrap();
+//! # });
+//! ```
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+use arrow_array::{RecordBatch, RecordBatchIterator};
+use arrow_flight::decode::FlightRecordBatchStream;
+use arrow_flight::encode::FlightDataEncoderBuilder;
+use arrow_flight::error::FlightError;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo,
+    HandshakeRequest, HandshakeResponse, PollInfo, PutResult, SchemaAsIpc, SchemaResult, Ticket,
+};
+use arrow_ipc::writer::IpcWriteOptions;
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::arrow::SendableRecordBatchStream;
+use crate::error::{Error, ErrorKind, Result};
+use crate::ipc::batches_to_ipc_file;
+use crate::query::{ExecutableQuery, QueryBase, Select};
+use crate::{Connection, Table};
+
+/// The action running a vector search
+pub const VECTOR_SEARCH_ACTION: &str = "vector_search";
+/// The action listing the tables
+pub const LIST_TABLES_ACTION: &str = "list_tables";
+/// The action dropping a table
+pub const DROP_TABLE_ACTION: &str = "drop_table";
+
+/// A query of a table, the ticket of `DoGet` and the body of the
+/// `vector_search` action
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueryRequest {
+    /// The name of the table
+    pub table: String,
+    /// An SQL filter on the rows
+    #[serde(default)]
+    pub filter: Option<String>,
+    /// The columns to return, all of them by default
+    #[serde(default)]
+    pub columns: Option<Vec<String>>,
+    /// The maximum number of rows to return
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// The query vector of a vector search
+    #[serde(default)]
+    pub vector: Option<Vec<f32>>,
+    /// The vector column to search, only needed if the table has several
+    #[serde(default)]
+    pub column: Option<String>,
+    /// The number of partitions of an IVF index to search
+    #[serde(default)]
+    pub nprobes: Option<usize>,
+}
+
+impl QueryRequest {
+    /// A scan of all of the rows of `table`
+    pub fn scan(table: impl Into<String>) -> Self {
+        Self {
+            table: table.into(),
+            ..Default::default()
+        }
+    }
+
+    fn from_json(bytes: &[u8]) -> std::result::Result<Self, Status> {
+        serde_json::from_slice(bytes)
+            .map_err(|e| Status::invalid_argument(format!("invalid query request: {}", e)))
+    }
+
+    fn to_json(&self) -> Bytes {
+        Bytes::from(serde_json::to_vec(self).expect("query requests are serializable"))
+    }
+
+    async fn execute(&self, table: &Table) -> Result<SendableRecordBatchStream> {
+        let mut query = table.query();
+        if let Some(filter) = &self.filter {
+            query = query.only_if(filter);
+        }
+        if let Some(columns) = &self.columns {
+            query = query.select(Select::columns(columns));
+        }
+        if let Some(limit) = self.limit {
+            query = query.limit(limit);
+        }
+        let Some(vector) = &self.vector else {
+            return query.execute().await;
+        };
+        let mut query = query.nearest_to(vector.as_slice())?;
+        if let Some(column) = &self.column {
+            query = query.column(column);
+        }
+        if let Some(nprobes) = self.nprobes {
+            query = query.nprobes(nprobes);
+        }
+        query.execute().await
+    }
+}
+
+/// The gRPC status of `err`
+fn status(err: Error) -> Status {
+    let message = err.to_string();
+    match err.kind() {
+        ErrorKind::NotFound => Status::not_found(message),
+        ErrorKind::AlreadyExists => Status::already_exists(message),
+        ErrorKind::Conflict => Status::aborted(message),
+        ErrorKind::InvalidInput => Status::invalid_argument(message),
+        ErrorKind::NotSupported => Status::unimplemented(message),
+        ErrorKind::ResourceExhausted => Status::resource_exhausted(message),
+        ErrorKind::Timeout => Status::deadline_exceeded(message),
+        ErrorKind::Cancelled => Status::cancelled(message),
+        _ if err.is_retryable() => Status::unavailable(message),
+        _ => Status::internal(message),
+    }
+}
+
+/// The name of the table of `descriptor`
+fn table_name(descriptor: Option<&FlightDescriptor>) -> std::result::Result<String, Status> {
+    match descriptor.map(|descriptor| descriptor.path.as_slice()) {
+        Some([name]) => Ok(name.clone()),
+        _ => Err(Status::invalid_argument(
+            "the path of the descriptor must be the name of a table",
+        )),
+    }
+}
+
+type FlightStream<T> = Pin<Box<dyn futures::Stream<Item = std::result::Result<T, Status>> + Send>>;
+
+/// An Arrow Flight service over the tables of a [`Connection`]
+///
+/// See the [module level documentation](self) for more details.
+#[derive(Clone)]
+pub struct FlightServer {
+    conn: Connection,
+}
+
+impl FlightServer {
+    /// Serve the tables of `conn`
+    pub fn new(conn: Connection) -> Self {
+        Self { conn }
+    }
+
+    /// The gRPC service, to be added to a [`tonic::transport::Server`] with
+    /// other services
+    pub fn into_service(self) -> FlightServiceServer<Self> {
+        FlightServiceServer::new(self)
+    }
+
+    /// Serve the tables on `addr` until the process is stopped
+    pub async fn serve(self, addr: SocketAddr) -> Result<()> {
+        tonic::transport::Server::builder()
+            .add_service(self.into_service())
+            .serve(addr)
+            .await
+            .map_err(|e| Error::Runtime {
+                message: format!("Flight server failed: {}", e),
+            })
+    }
+
+    async fn open_table(&self, name: &str) -> std::result::Result<Table, Status> {
+        self.conn.open_table(name).execute().await.map_err(status)
+    }
+
+    async fn flight_info(&self, name: &str) -> std::result::Result<FlightInfo, Status> {
+        let table = self.open_table(name).await?;
+        let schema = table.schema().await.map_err(status)?;
+        let num_rows = table.count_rows(None).await.map_err(status)?;
+        let ticket = Ticket::new(QueryRequest::scan(name).to_json());
+        FlightInfo::new()
+            .try_with_schema(&schema)
+            .map_err(|e| Status::internal(e.to_string()))
+            .map(|info| {
+                info.with_descriptor(FlightDescriptor::new_path(vec![name.to_string()]))
+                    .with_endpoint(FlightEndpoint::new().with_ticket(ticket))
+                    .with_total_records(num_rows as i64)
+            })
+    }
+
+    async fn put(&self, name: &str, batches: Vec<RecordBatch>) -> Result<()> {
+        let Some(schema) = batches.first().map(|batch| batch.schema()) else {
+            return Ok(());
+        };
+        let data = RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+        match self.conn.open_table(name).execute().await {
+            Ok(table) => table.add(data).execute().await,
+            Err(Error::TableNotFound { .. }) => self
+                .conn
+                .create_table(name, data)
+                .execute()
+                .await
+                .map(|_| ()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl FlightService for FlightServer {
+    type HandshakeStream = FlightStream<HandshakeResponse>;
+    type ListFlightsStream = FlightStream<FlightInfo>;
+    type DoGetStream = FlightStream<FlightData>;
+    type DoPutStream = FlightStream<PutResult>;
+    type DoActionStream = FlightStream<arrow_flight::Result>;
+    type ListActionsStream = FlightStream<ActionType>;
+    type DoExchangeStream = FlightStream<FlightData>;
+
+    async fn handshake(
+        &self,
+        _request: Request<Streaming<HandshakeRequest>>,
+    ) -> std::result::Result<Response<Self::HandshakeStream>, Status> {
+        // There is no authentication, the server is meant to run in a private network
+        Ok(Response::new(futures::stream::empty().boxed()))
+    }
+
+    async fn list_flights(
+        &self,
+        _request: Request<Criteria>,
+    ) -> std::result::Result<Response<Self::ListFlightsStream>, Status> {
+        let names = self.conn.table_names().execute().await.map_err(status)?;
+        let mut infos = Vec::with_capacity(names.len());
+        for name in names {
+            infos.push(self.flight_info(&name).await);
+        }
+        Ok(Response::new(futures::stream::iter(infos).boxed()))
+    }
+
+    async fn get_flight_info(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> std::result::Result<Response<FlightInfo>, Status> {
+        let name = table_name(Some(request.get_ref()))?;
+        Ok(Response::new(self.flight_info(&name).await?))
+    }
+
+    async fn poll_flight_info(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> std::result::Result<Response<PollInfo>, Status> {
+        Err(Status::unimplemented(
+            "the queries of LanceDB are not long running, use GetFlightInfo",
+        ))
+    }
+
+    async fn get_schema(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> std::result::Result<Response<SchemaResult>, Status> {
+        let name = table_name(Some(request.get_ref()))?;
+        let table = self.open_table(&name).await?;
+        let schema = table.schema().await.map_err(status)?;
+        let result = SchemaAsIpc::new(&schema, &IpcWriteOptions::default())
+            .try_into()
+            .map_err(|e: arrow_schema::ArrowError| Status::internal(e.to_string()))?;
+        Ok(Response::new(result))
+    }
+
+    async fn do_get(
+        &self,
+        request: Request<Ticket>,
+    ) -> std::result::Result<Response<Self::DoGetStream>, Status> {
+        let query = QueryRequest::from_json(&request.get_ref().ticket)?;
+        let table = self.open_table(&query.table).await?;
+        let stream = query.execute(&table).await.map_err(status)?;
+        let batches = stream.map_err(|e| FlightError::ExternalError(Box::new(e)));
+        let data = FlightDataEncoderBuilder::new()
+            .build(batches)
+            .map_err(Status::from);
+        Ok(Response::new(data.boxed()))
+    }
+
+    async fn do_put(
+        &self,
+        request: Request<Streaming<FlightData>>,
+    ) -> std::result::Result<Response<Self::DoPutStream>, Status> {
+        let mut data = request.into_inner();
+        // The descriptor is sent with the first message
+        let Some(first) = data.message().await? else {
+            return Err(Status::invalid_argument("no data was sent"));
+        };
+        let name = table_name(first.flight_descriptor.as_ref())?;
+        let data = futures::stream::once(async { Ok(first) })
+            .chain(data)
+            .map_err(FlightError::Tonic);
+        let batches = FlightRecordBatchStream::new_from_flight_data(data)
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(Status::from)?;
+        self.put(&name, batches).await.map_err(status)?;
+        Ok(Response::new(futures::stream::empty().boxed()))
+    }
+
+    async fn do_action(
+        &self,
+        request: Request<Action>,
+    ) -> std::result::Result<Response<Self::DoActionStream>, Status> {
+        let action = request.into_inner();
+        let results: Vec<Bytes> = match action.r#type.as_str() {
+            VECTOR_SEARCH_ACTION => {
+                let query = QueryRequest::from_json(&action.body)?;
+                if query.vector.is_none() {
+                    return Err(Status::invalid_argument("a vector search needs a vector"));
+                }
+                let table = self.open_table(&query.table).await?;
+                let stream = query.execute(&table).await.map_err(status)?;
+                let schema = stream.schema();
+                let batches = stream.try_collect::<Vec<_>>().await.map_err(status)?;
+                let batches = if batches.is_empty() {
+                    vec![RecordBatch::new_empty(schema)]
+                } else {
+                    batches
+                };
+                vec![Bytes::from(batches_to_ipc_file(&batches).map_err(status)?)]
+            }
+            LIST_TABLES_ACTION => {
+                let names = self.conn.table_names().execute().await.map_err(status)?;
+                names.into_iter().map(Bytes::from).collect()
+            }
+            DROP_TABLE_ACTION => {
+                let name = std::str::from_utf8(&action.body)
+                    .map_err(|_| Status::invalid_argument("the table name is not valid UTF-8"))?;
+                self.conn.drop_table(name).await.map_err(status)?;
+                vec![]
+            }
+            other => {
+                return Err(Status::unimplemented(format!("unknown action {}", other)));
+            }
+        };
+        let results = results
+            .into_iter()
+            .map(|body| Ok(arrow_flight::Result { body }));
+        Ok(Response::new(futures::stream::iter(results).boxed()))
+    }
+
+    async fn list_actions(
+        &self,
+        _request: Request<Empty>,
+    ) -> std::result::Result<Response<Self::ListActionsStream>, Status> {
+        let actions = [
+            (
+                VECTOR_SEARCH_ACTION,
+                "Run a vector search, the body is a JSON query request, the result an Arrow IPC file",
+            ),
+            (LIST_TABLES_ACTION, "List the names of the tables"),
+            (DROP_TABLE_ACTION, "Drop the table named by the body"),
+        ]
+        .map(|(r#type, description)| {
+            Ok(ActionType {
+                r#type: r#type.to_string(),
+                description: description.to_string(),
+            })
+        });
+        Ok(Response::new(futures::stream::iter(actions).boxed()))
+    }
+
+    async fn do_exchange(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> std::result::Result<Response<Self::DoExchangeStream>, Status> {
+        Err(Status::unimplemented("DoExchange is not supported"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::types::Float32Type;
+    use arrow_array::{FixedSizeListArray, Int32Array};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::ipc::ipc_file_to_batches;
+
+    async fn make_server() -> (tempfile::TempDir, FlightServer) {
+        let tmp_dir = tempdir().unwrap();
+        let db = crate::connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+                true,
+            ),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..100)),
+                Arc::new(
+                    FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+                        (0..100).map(|i| Some(vec![Some(i as f32), Some(i as f32)])),
+                        2,
+                    ),
+                ),
+            ],
+        )
+        .unwrap();
+        db.create_table("items", RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await
+            .unwrap();
+        (tmp_dir, FlightServer::new(db))
+    }
+
+    #[tokio::test]
+    async fn test_do_get() {
+        let (_tmp_dir, server) = make_server().await;
+
+        let info = server
+            .get_flight_info(Request::new(FlightDescriptor::new_path(vec![
+                "items".to_string()
+            ])))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(info.total_records, 100);
+
+        let query = QueryRequest {
+            filter: Some("id < 10".to_string()),
+            columns: Some(vec!["id".to_string()]),
+            ..QueryRequest::scan("items")
+        };
+        let data = server
+            .do_get(Request::new(Ticket::new(query.to_json())))
+            .await
+            .unwrap()
+            .into_inner();
+        let batches =
+            FlightRecordBatchStream::new_from_flight_data(data.map_err(FlightError::Tonic))
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 10);
+        assert_eq!(batches[0].num_columns(), 1);
+
+        let missing = server
+            .do_get(Request::new(Ticket::new(
+                QueryRequest::scan("missing").to_json(),
+            )))
+            .await;
+        assert_eq!(missing.err().unwrap().code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_actions() {
+        let (_tmp_dir, server) = make_server().await;
+
+        let query = QueryRequest {
+            vector: Some(vec![10.0, 10.0]),
+            limit: Some(3),
+            ..QueryRequest::scan("items")
+        };
+        let results = server
+            .do_action(Request::new(Action::new(
+                VECTOR_SEARCH_ACTION,
+                query.to_json(),
+            )))
+            .await
+            .unwrap()
+            .into_inner()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batches = ipc_file_to_batches(results[0].body.to_vec())
+            .unwrap()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+
+        let names = server
+            .do_action(Request::new(Action::new(LIST_TABLES_ACTION, "")))
+            .await
+            .unwrap()
+            .into_inner()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(names.len(), 1);
+        assert_eq!(names[0].body, "items");
+
+        server
+            .do_action(Request::new(Action::new(DROP_TABLE_ACTION, "items")))
+            .await
+            .unwrap();
+        assert!(server
+            .conn
+            .table_names()
+            .execute()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,517 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SQL queries over the tables of a database
+//!
+//! [`LanceTableProvider`] exposes a [`Table`] to [DataFusion] so that it can be
+//! queried with SQL.  Projections, limits, and the filters that can be expressed
+//! in the [filter grammar](crate::query::filter) are pushed down into the scan
+//! of the table, everything else (joins, aggregates, sorts, ...) is executed by
+//! DataFusion.
+//!
+//! [`crate::Connection::sql`] runs a query against the tables of a connection:
+//!
+//! ```ignore
+//! let results = conn
+//!     .sql("SELECT category, count(*) FROM items WHERE price < 100 GROUP BY category")
+//!     .await?;
+//! ```
+//!
+//! Vector searches are run with the `vector_search` table function, see
+//! [`vector_search`].
+//!
+//! Like other SQL engines, DataFusion folds unquoted identifiers to lower case,
+//! so table and column names with upper case letters must be double quoted.
+//!
+//! [DataFusion]: https://datafusion.apache.org
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{RecordBatch, RecordBatchOptions};
+use arrow_schema::SchemaRef;
+use async_trait::async_trait;
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result as DataFusionResult};
+use datafusion::execution::context::{SQLOptions, SessionContext, SessionState};
+use datafusion::execution::TaskContext;
+use datafusion::logical_expr::expr::{Between, BinaryExpr, InList, Like};
+use datafusion::logical_expr::{Expr, Operator, TableProviderFilterPushDown, TableType};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::{PartitionStream, StreamingTableExec};
+use datafusion::physical_plan::{ExecutionPlan, RecordBatchStream};
+use datafusion::scalar::ScalarValue;
+use futures::{stream, StreamExt, TryStreamExt};
+
+use crate::arrow::SimpleRecordBatchStream;
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::query::filter::FilterValue;
+use crate::query::{ExecutableQuery, QueryBase, Select};
+use crate::Table;
+use vector_search::{VectorSearchFunction, VECTOR_SEARCH};
+
+pub mod vector_search;
+
+const DISTANCE_COLUMN: &str = "_distance";
+
+/// A filter converted to the filter grammar, with its literals as parameters
+#[derive(Debug, Default, Clone)]
+struct PushedFilter {
+    sql: String,
+    params: Vec<(String, FilterValue)>,
+}
+
+impl PushedFilter {
+    /// Convert `expr`, returns None if it cannot be expressed in the filter grammar
+    fn push(&mut self, expr: &Expr) -> Option<String> {
+        Some(match expr {
+            Expr::Column(column) if !column.name.contains('`') => format!("`{}`", column.name),
+            Expr::Literal(value) => {
+                let value = filter_value(value)?;
+                let name = format!("p{}", self.params.len());
+                self.params.push((name.clone(), value));
+                format!("${}", name)
+            }
+            Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+                let op = match op {
+                    Operator::Eq => "=",
+                    Operator::NotEq => "!=",
+                    Operator::Lt => "<",
+                    Operator::LtEq => "<=",
+                    Operator::Gt => ">",
+                    Operator::GtEq => ">=",
+                    Operator::And => "AND",
+                    Operator::Or => "OR",
+                    Operator::Plus => "+",
+                    Operator::Minus => "-",
+                    Operator::Multiply => "*",
+                    Operator::Divide => "/",
+                    Operator::Modulo => "%",
+                    _ => return None,
+                };
+                format!("({} {} {})", self.push(left)?, op, self.push(right)?)
+            }
+            Expr::Not(expr) => format!("(NOT {})", self.push(expr)?),
+            Expr::Negative(expr) => format!("(-{})", self.push(expr)?),
+            Expr::IsNull(expr) => format!("({} IS NULL)", self.push(expr)?),
+            Expr::IsNotNull(expr) => format!("({} IS NOT NULL)", self.push(expr)?),
+            Expr::Between(Between {
+                expr,
+                negated,
+                low,
+                high,
+            }) => format!(
+                "({} {}BETWEEN {} AND {})",
+                self.push(expr)?,
+                if *negated { "NOT " } else { "" },
+                self.push(low)?,
+                self.push(high)?
+            ),
+            Expr::InList(InList {
+                expr,
+                list,
+                negated,
+            }) if !list.is_empty() => {
+                let expr = self.push(expr)?;
+                let list = list
+                    .iter()
+                    .map(|item| self.push(item))
+                    .collect::<Option<Vec<_>>>()?;
+                format!(
+                    "({} {}IN ({}))",
+                    expr,
+                    if *negated { "NOT " } else { "" },
+                    list.join(", ")
+                )
+            }
+            Expr::Like(Like {
+                negated,
+                expr,
+                pattern,
+                escape_char: None,
+                case_insensitive,
+            }) => format!(
+                "({} {}{} {})",
+                self.push(expr)?,
+                if *negated { "NOT " } else { "" },
+                if *case_insensitive { "ILIKE" } else { "LIKE" },
+                self.push(pattern)?
+            ),
+            _ => return None,
+        })
+    }
+
+    /// Convert the conjunction of `filters`
+    fn from_filters(filters: &[Expr]) -> Option<Self> {
+        if filters.is_empty() {
+            return None;
+        }
+        let mut filter = Self::default();
+        let sql = filters
+            .iter()
+            .map(|expr| filter.push(expr))
+            .collect::<Option<Vec<_>>>()?;
+        filter.sql = sql.join(" AND ");
+        Some(filter)
+    }
+}
+
+fn filter_value(value: &ScalarValue) -> Option<FilterValue> {
+    if value.is_null() {
+        return Some(FilterValue::Null);
+    }
+    Some(match value {
+        ScalarValue::Boolean(Some(v)) => FilterValue::Bool(*v),
+        ScalarValue::Int8(Some(v)) => FilterValue::Int(*v as i64),
+        ScalarValue::Int16(Some(v)) => FilterValue::Int(*v as i64),
+        ScalarValue::Int32(Some(v)) => FilterValue::Int(*v as i64),
+        ScalarValue::Int64(Some(v)) => FilterValue::Int(*v),
+        ScalarValue::UInt8(Some(v)) => FilterValue::Int(*v as i64),
+        ScalarValue::UInt16(Some(v)) => FilterValue::Int(*v as i64),
+        ScalarValue::UInt32(Some(v)) => FilterValue::Int(*v as i64),
+        ScalarValue::UInt64(Some(v)) => FilterValue::Int(i64::try_from(*v).ok()?),
+        ScalarValue::Float32(Some(v)) => FilterValue::Float(*v as f64),
+        ScalarValue::Float64(Some(v)) => FilterValue::Float(*v),
+        ScalarValue::Utf8(Some(v)) | ScalarValue::LargeUtf8(Some(v)) => {
+            FilterValue::String(v.clone())
+        }
+        _ => return None,
+    })
+}
+
+/// A [`TableProvider`] that lets DataFusion query a [`Table`]
+pub struct LanceTableProvider {
+    table: Table,
+    schema: SchemaRef,
+}
+
+impl LanceTableProvider {
+    pub async fn new(table: Table) -> Result<Self> {
+        let schema = table.schema().await?;
+        Ok(Self { table, schema })
+    }
+}
+
+#[async_trait]
+impl TableProvider for LanceTableProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|expr| {
+                if PushedFilter::default().push(expr).is_some() {
+                    TableProviderFilterPushDown::Exact
+                } else {
+                    TableProviderFilterPushDown::Unsupported
+                }
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let schema = match projection {
+            Some(projection) => Arc::new(self.schema.project(projection)?),
+            None => self.schema.clone(),
+        };
+        let scan = TableScan {
+            table: self.table.clone(),
+            schema: schema.clone(),
+            filter: PushedFilter::from_filters(filters),
+            limit,
+            nearest: None,
+        };
+        Ok(Arc::new(StreamingTableExec::try_new(
+            schema,
+            vec![Arc::new(scan)],
+            None,
+            vec![],
+            false,
+        )?))
+    }
+}
+
+/// A scan of a [`Table`] with the pushed down projection, filter, and limit
+struct TableScan {
+    table: Table,
+    schema: SchemaRef,
+    filter: Option<PushedFilter>,
+    limit: Option<usize>,
+    /// Scan the results of a vector search instead of the whole table
+    nearest: Option<NearestTo>,
+}
+
+/// A vector search, see [`vector_search`]
+#[derive(Debug, Clone)]
+struct NearestTo {
+    vector: Vec<f32>,
+    column: Option<String>,
+    k: usize,
+}
+
+fn configure_query<Q: QueryBase>(
+    mut query: Q,
+    columns: Vec<String>,
+    filter: Option<PushedFilter>,
+    limit: Option<usize>,
+) -> Q {
+    query = query.select(Select::Columns(columns));
+    if let Some(filter) = filter {
+        query = query.only_if(filter.sql);
+        for (name, value) in filter.params {
+            query = query.bind(name, value);
+        }
+    }
+    if let Some(limit) = limit {
+        query = query.limit(limit);
+    }
+    query
+}
+
+impl TableScan {
+    async fn scan(
+        table: Table,
+        schema: SchemaRef,
+        filter: Option<PushedFilter>,
+        limit: Option<usize>,
+        nearest: Option<NearestTo>,
+    ) -> Result<impl futures::Stream<Item = Result<RecordBatch>>> {
+        // Scans without columns (e.g. for `count(*)`) still need the number of rows
+        let mut columns = schema
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .filter(|name| name != DISTANCE_COLUMN)
+            .collect::<Vec<_>>();
+        if columns.is_empty() {
+            columns.push(table.schema().await?.field(0).name().clone());
+        }
+        let stream = match nearest {
+            Some(nearest) => {
+                let mut query = table.query().nearest_to(nearest.vector)?;
+                if let Some(column) = &nearest.column {
+                    query = query.column(column);
+                }
+                let limit = limit.map_or(nearest.k, |limit| limit.min(nearest.k));
+                configure_query(query, columns, filter, Some(limit))
+                    .execute()
+                    .await?
+            }
+            None => {
+                configure_query(table.query(), columns, filter, limit)
+                    .execute()
+                    .await?
+            }
+        };
+        Ok(stream.map(move |batch| {
+            let batch = batch?;
+            // The columns are returned in the order of the table
+            let columns = schema
+                .fields()
+                .iter()
+                .map(|field| batch[field.name().as_str()].clone())
+                .collect();
+            let options = RecordBatchOptions::new().with_row_count(Some(batch.num_rows()));
+            Ok(RecordBatch::try_new_with_options(
+                schema.clone(),
+                columns,
+                &options,
+            )?)
+        }))
+    }
+}
+
+impl PartitionStream for TableScan {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, _ctx: Arc<TaskContext>) -> datafusion::execution::SendableRecordBatchStream {
+        let batches = Self::scan(
+            self.table.clone(),
+            self.schema.clone(),
+            self.filter.clone(),
+            self.limit,
+            self.nearest.clone(),
+        );
+        let batches = stream::once(batches)
+            .try_flatten()
+            .map_err(|err| DataFusionError::External(Box::new(err)));
+        Box::pin(RecordBatchStreamAdapter::new(self.schema.clone(), batches))
+    }
+}
+
+/// Open the table or view `name`
+async fn open_table(conn: &Connection, name: &str) -> Result<Table> {
+    match conn.open_table(name).execute().await {
+        Err(Error::TableNotFound { .. }) => conn.open_view(name).await,
+        table => table,
+    }
+}
+
+/// Run the SQL query `sql` against the tables of `conn`
+pub(crate) async fn execute(
+    conn: &Connection,
+    sql: &str,
+) -> Result<crate::arrow::SendableRecordBatchStream> {
+    let ctx = SessionContext::new();
+    let state = ctx.state();
+    let statement = state.sql_to_statement(sql, "generic")?;
+    for reference in state.resolve_table_references(&statement)? {
+        if reference.table() == VECTOR_SEARCH {
+            continue;
+        }
+        let table = open_table(conn, reference.table()).await?;
+        ctx.register_table(reference, Arc::new(LanceTableProvider::new(table).await?))?;
+    }
+    let mut searched = HashMap::new();
+    for name in vector_search::searched_tables(sql)? {
+        let table = open_table(conn, &name).await?;
+        let schema = table.schema().await?;
+        searched.insert(name, (table, schema));
+    }
+    ctx.register_udtf(
+        VECTOR_SEARCH,
+        Arc::new(VectorSearchFunction { tables: searched }),
+    );
+    // Only queries, the tables cannot be modified through SQL
+    let options = SQLOptions::new()
+        .with_allow_ddl(false)
+        .with_allow_dml(false);
+    let stream = ctx
+        .sql_with_options(sql, options)
+        .await?
+        .execute_stream()
+        .await?;
+    Ok(Box::pin(SimpleRecordBatchStream {
+        schema: stream.schema(),
+        stream: stream.map_err(Error::from),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, Int64Array, RecordBatchIterator, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use datafusion::logical_expr::{col, lit};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    #[test]
+    fn test_push_filter() {
+        let filter = PushedFilter::from_filters(&[
+            col("x").gt(lit(10)),
+            col("name").in_list(vec![lit("a"), lit("b")], false),
+        ])
+        .unwrap();
+        assert_eq!(filter.sql, "(`x` > $p0) AND (`name` IN ($p1, $p2))");
+        assert_eq!(
+            filter.params,
+            vec![
+                ("p0".to_string(), FilterValue::Int(10)),
+                ("p1".to_string(), FilterValue::String("a".to_string())),
+                ("p2".to_string(), FilterValue::String("b".to_string())),
+            ]
+        );
+        // Not expressible in the filter grammar
+        assert!(PushedFilter::from_filters(&[col("x").gt(lit(10)), col("x").is_true()]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sql() {
+        let tmp_dir = tempdir().unwrap();
+        let conn = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("category", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..10)),
+                Arc::new(StringArray::from_iter_values((0..10).map(|i| {
+                    if i % 2 == 0 {
+                        "even"
+                    } else {
+                        "odd"
+                    }
+                }))),
+            ],
+        )
+        .unwrap();
+        conn.create_table(
+            "items",
+            RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+        )
+        .execute()
+        .await
+        .unwrap();
+
+        let batches = conn
+            .sql(
+                "SELECT category, count(*) AS n FROM items WHERE id >= 4 \
+                 GROUP BY category ORDER BY category",
+            )
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        let counts = batches[0]["n"]
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(counts.values(), &[3, 3]);
+
+        let batches = conn
+            .sql("SELECT id FROM items WHERE category = 'odd' LIMIT 2")
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+        assert_eq!(batches[0].schema().fields().len(), 1);
+
+        assert!(conn.sql("SELECT * FROM missing").await.is_err());
+        assert!(conn.sql("DROP TABLE items").await.is_err());
+    }
+}
@@ -0,0 +1,328 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `vector_search` table function
+//!
+//! ```sql
+//! vector_search('table', [0.1, 0.2, ...], k [, 'column'])
+//! ```
+//!
+//! returns the `k` rows of `table` nearest to the query vector, with their
+//! distance in a `_distance` column.  The vector column must be given if the
+//! table has more than one.  The results can be filtered, joined, and aggregated
+//! like any other table:
+//!
+//! ```sql
+//! SELECT s.id, s._distance, c.name
+//! FROM vector_search('items', [0.1, 0.2], 10) s
+//! JOIN categories c ON s.category_id = c.id
+//! WHERE s.price < 100
+//! ```
+//!
+//! Filters in the `WHERE` clause are applied to the `k` nearest rows, as with the
+//! default post-filtering of [`crate::query::VectorQuery`], so fewer than `k`
+//! rows may be returned.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::compute::cast;
+use arrow_array::{cast::AsArray, types::Float32Type, Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use async_trait::async_trait;
+use datafusion::datasource::function::TableFunctionImpl;
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result as DataFusionResult};
+use datafusion::execution::context::SessionState;
+use datafusion::logical_expr::{Expr, TableType};
+use datafusion::physical_plan::streaming::StreamingTableExec;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::scalar::ScalarValue;
+use datafusion::sql::sqlparser::dialect::GenericDialect;
+use datafusion::sql::sqlparser::tokenizer::{Token, Tokenizer};
+
+use super::{NearestTo, TableScan, DISTANCE_COLUMN};
+use crate::error::{Error, Result};
+use crate::Table;
+
+/// The name of the table function
+pub(super) const VECTOR_SEARCH: &str = "vector_search";
+
+/// The names of the tables searched by the `vector_search` calls of `sql`
+///
+/// Table functions are planned synchronously, so the tables must be opened
+/// before the query is planned.
+pub(super) fn searched_tables(sql: &str) -> Result<Vec<String>> {
+    let tokens = Tokenizer::new(&GenericDialect {}, sql)
+        .tokenize()
+        .map_err(|e| Error::DataFusion {
+            message: e.to_string(),
+        })?
+        .into_iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_)))
+        .collect::<Vec<_>>();
+    Ok(tokens
+        .windows(3)
+        .filter_map(|window| match window {
+            [Token::Word(word), Token::LParen, Token::SingleQuotedString(name)]
+                if word.value.eq_ignore_ascii_case(VECTOR_SEARCH) =>
+            {
+                Some(name.clone())
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+/// The `vector_search` table function, over the tables opened for a query and
+/// their schemas
+pub(super) struct VectorSearchFunction {
+    pub(super) tables: HashMap<String, (Table, SchemaRef)>,
+}
+
+fn invalid_argument(message: impl Into<String>) -> DataFusionError {
+    DataFusionError::Plan(format!(
+        "{}, expected {}('table', [vector], k [, 'column'])",
+        message.into(),
+        VECTOR_SEARCH
+    ))
+}
+
+fn string_argument(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Literal(ScalarValue::Utf8(Some(value))) => Some(value),
+        _ => None,
+    }
+}
+
+fn number_argument(expr: &Expr) -> Option<f64> {
+    match expr {
+        Expr::Negative(expr) => number_argument(expr).map(|value| -value),
+        Expr::Literal(ScalarValue::Float64(Some(value))) => Some(*value),
+        Expr::Literal(ScalarValue::Float32(Some(value))) => Some(*value as f64),
+        Expr::Literal(ScalarValue::Int64(Some(value))) => Some(*value as f64),
+        Expr::Literal(ScalarValue::Int32(Some(value))) => Some(*value as f64),
+        _ => None,
+    }
+}
+
+fn vector_argument(expr: &Expr) -> Option<Vec<f32>> {
+    match expr {
+        Expr::ScalarFunction(function) if function.name() == "make_array" => function
+            .args
+            .iter()
+            .map(|arg| number_argument(arg).map(|value| value as f32))
+            .collect(),
+        // Array literals may already be folded into a list
+        Expr::Literal(ScalarValue::List(list)) if list.len() == 1 && list.is_valid(0) => {
+            let values = cast(&list.value(0), &DataType::Float32).ok()?;
+            let values = values.as_primitive::<Float32Type>();
+            (values.null_count() == 0).then(|| values.values().to_vec())
+        }
+        _ => None,
+    }
+}
+
+impl TableFunctionImpl for VectorSearchFunction {
+    fn call(&self, args: &[Expr]) -> DataFusionResult<Arc<dyn TableProvider>> {
+        if args.len() != 3 && args.len() != 4 {
+            return Err(invalid_argument(format!("{} arguments", args.len())));
+        }
+        let name = string_argument(&args[0])
+            .ok_or_else(|| invalid_argument("the table must be a string"))?;
+        let (table, schema) = self
+            .tables
+            .get(name)
+            .ok_or_else(|| DataFusionError::Plan(format!("table '{}' was not found", name)))?;
+        let vector = vector_argument(&args[1])
+            .ok_or_else(|| invalid_argument("the vector must be an array of numbers"))?;
+        let k = match number_argument(&args[2]) {
+            Some(k) if k >= 1.0 && k.fract() == 0.0 => k as usize,
+            _ => return Err(invalid_argument("k must be a positive integer")),
+        };
+        let column = match args.get(3) {
+            Some(arg) => Some(
+                string_argument(arg)
+                    .ok_or_else(|| invalid_argument("the column must be a string"))?
+                    .to_string(),
+            ),
+            None => None,
+        };
+        Ok(Arc::new(VectorSearchProvider::new(
+            table.clone(),
+            schema,
+            NearestTo { vector, column, k },
+        )))
+    }
+}
+
+/// The results of a vector search, as a table
+struct VectorSearchProvider {
+    table: Table,
+    schema: SchemaRef,
+    nearest: NearestTo,
+}
+
+impl VectorSearchProvider {
+    fn new(table: Table, schema: &Schema, nearest: NearestTo) -> Self {
+        let mut fields = schema.fields().iter().cloned().collect::<Vec<_>>();
+        fields.push(Arc::new(Field::new(
+            DISTANCE_COLUMN,
+            DataType::Float32,
+            true,
+        )));
+        Self {
+            table,
+            schema: Arc::new(Schema::new(fields)),
+            nearest,
+        }
+    }
+}
+
+#[async_trait]
+impl TableProvider for VectorSearchProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let schema = match projection {
+            Some(projection) => Arc::new(self.schema.project(projection)?),
+            None => self.schema.clone(),
+        };
+        let scan = TableScan {
+            table: self.table.clone(),
+            schema: schema.clone(),
+            filter: None,
+            limit,
+            nearest: Some(self.nearest.clone()),
+        };
+        Ok(Arc::new(StreamingTableExec::try_new(
+            schema,
+            vec![Arc::new(scan)],
+            None,
+            vec![],
+            false,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{
+        FixedSizeListArray, Float32Array, Int32Array, RecordBatch, RecordBatchIterator,
+    };
+    use futures::TryStreamExt;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    #[test]
+    fn test_searched_tables() {
+        let tables = searched_tables(
+            "SELECT * FROM VECTOR_SEARCH( 'a', [1, 2], 3) JOIN b ON true \
+             UNION SELECT * FROM vector_search('c', [1, 2], 3, 'v')",
+        )
+        .unwrap();
+        assert_eq!(tables, vec!["a".to_string(), "c".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_vector_search() {
+        let tmp_dir = tempdir().unwrap();
+        let conn = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+                false,
+            ),
+        ]));
+        let vectors = (0..10).flat_map(|i| [i as f32, 0.0]).collect::<Vec<_>>();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..10)),
+                Arc::new(
+                    FixedSizeListArray::try_new(
+                        Arc::new(Field::new("item", DataType::Float32, true)),
+                        2,
+                        Arc::new(Float32Array::from(vectors)),
+                        None,
+                    )
+                    .unwrap(),
+                ),
+            ],
+        )
+        .unwrap();
+        conn.create_table(
+            "items",
+            RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+        )
+        .execute()
+        .await
+        .unwrap();
+
+        let batches = conn
+            .sql(
+                "SELECT id, _distance FROM vector_search('items', [2.1, 0.0], 3) \
+                 WHERE id != 2 ORDER BY _distance",
+            )
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let ids = batches
+            .iter()
+            .flat_map(|batch| {
+                batch["id"]
+                    .as_primitive::<arrow_array::types::Int32Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        // The filter applies to the 3 nearest rows
+        assert_eq!(ids, vec![3, 1]);
+
+        assert!(conn
+            .sql("SELECT * FROM vector_search('items', [2.1, 0.0])")
+            .await
+            .is_err());
+        assert!(conn
+            .sql("SELECT * FROM vector_search('missing', [2.1, 0.0], 3)")
+            .await
+            .is_err());
+    }
+}
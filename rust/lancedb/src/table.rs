@@ -14,54 +14,164 @@
 
 //! LanceDB Table APIs
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use arrow::array::AsArray;
 use arrow::datatypes::Float32Type;
-use arrow_array::{RecordBatchIterator, RecordBatchReader};
+use arrow_array::{
+    Array, FixedSizeListArray, Float32Array, RecordBatch, RecordBatchIterator, RecordBatchReader,
+};
 use arrow_schema::{DataType, Field, Schema, SchemaRef};
 use async_trait::async_trait;
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
+use futures::TryStreamExt;
 use lance::dataset::builder::DatasetBuilder;
 use lance::dataset::cleanup::RemovalStats;
 use lance::dataset::optimize::{
     compact_files, CompactionMetrics, CompactionOptions, IndexRemapperOptions,
 };
 use lance::dataset::scanner::{DatasetRecordBatchStream, Scanner};
+use lance::dataset::transaction::Operation;
 pub use lance::dataset::ColumnAlteration;
 pub use lance::dataset::NewColumnTransform;
 pub use lance::dataset::ReadParams;
+pub use lance::dataset::Version;
 use lance::dataset::{
     Dataset, UpdateBuilder as LanceUpdateBuilder, WhenMatched, WriteMode, WriteParams,
 };
 use lance::dataset::{MergeInsertBuilder as LanceMergeInsertBuilder, WhenNotMatchedBySource};
-use lance::io::WrappingObjectStore;
+use lance::io::{ObjectStore, ObjectStoreParams, WrappingObjectStore};
+use lance_index::vector::hnsw::builder::HnswBuildParams;
+use lance_index::vector::ivf::IvfBuildParams;
+use lance_index::vector::pq::PQBuildParams;
+use lance_index::vector::sq::builder::SQBuildParams;
 use lance_index::IndexType;
 use lance_index::{optimize::OptimizeOptions, DatasetIndexExt};
+use lance_table::io::commit::CommitHandler;
 use log::info;
 use snafu::whatever;
 
-use crate::arrow::IntoArrow;
+use crate::arrow::{IntoArrow, SendableRecordBatchStream, VersionedRecordBatchStream};
 use crate::connection::NoData;
+use crate::data::parquet::ExportParquetBuilder;
+use crate::data::sanitize;
+use crate::embeddings::{self, EmbeddingRegistry, WithEmbeddings};
 use crate::error::{Error, Result};
-use crate::index::vector::{IvfPqIndexBuilder, VectorIndex, VectorIndexStatistics};
-use crate::index::IndexConfig;
+#[cfg(feature = "fts")]
+use crate::index::fts::{self, FtsIndex, FtsIndexBuilder};
+#[cfg(feature = "cuda")]
+use crate::index::vector::sample_training_vectors;
+use crate::index::vector::{
+    check_centroids, Accelerator, IvfHnswPqIndexBuilder, IvfHnswSqIndexBuilder, IvfIndexStatistics,
+    IvfPqIndexBuilder, VectorIndex, VectorIndexStatistics,
+};
 use crate::index::{
     vector::{suggested_num_partitions, suggested_num_sub_vectors},
-    Index, IndexBuilder,
+    Index, IndexBuilder, IndexJob,
 };
+use crate::index::{IndexConfig, IndexStatistics};
+use crate::io::checksum::{self, ChecksumReport};
+use crate::metrics::{self, MetricsRegistry};
+use crate::query::admission::{QueryAdmission, QueryPermit};
+use crate::query::batch;
+use crate::query::budget::{Reservation, ScanLimits};
+use crate::query::cache::{QueryCache, QueryKey};
+use crate::query::enrich::{EnrichSource, EnrichedQuery};
+use crate::query::filter::{Filter, FilterValue};
+use crate::query::metrics::ScanStats;
 use crate::query::{
-    IntoQueryVector, Query, QueryExecutionOptions, Select, VectorQuery, DEFAULT_TOP_K,
+    Hint, IntoQueryVector, Query, QueryExecutionOptions, Select, UnindexedPolicy, VectorQuery,
+    DEFAULT_TOP_K,
+};
+use crate::quota::{QuotaWrite, Quotas};
+use crate::telemetry::OperationSpan;
+use crate::utils::{
+    default_vector_column, read_local_through_wrapper, PatchReadParam, PatchWriteParam,
 };
-use crate::utils::{default_vector_column, PatchReadParam, PatchWriteParam};
 
+use self::aggregate::AggregateBuilder;
+pub use self::auto_compact::AutoCompaction;
+pub use self::auto_index::{AutoIndex, OptimizeIndexOptions};
+use self::blob::BlobReader;
+pub use self::bulk_ingest::BulkIngestOptions;
+use self::changes::{ChangeStream, ChangesBuilder};
+use self::cluster::ClusterBuilder;
+pub use self::commit::CommitBackoff;
+pub(crate) use self::commit::DEFAULT_COMMIT_RETRIES;
+use self::compatibility::CompatibilityReport;
+pub use self::compression::Compression;
 use self::dataset::DatasetConsistencyWrapper;
+use self::dedup::FindDuplicatesBuilder;
+use self::dry_run::{DryRunOperation, DryRunReport};
+use self::export::ExportVectorsBuilder;
+use self::fragment_stats::FragmentStatsMetrics;
 use self::merge::MergeInsertBuilder;
-
+use self::migrate::{MigrateVectorDimBuilder, VectorProjector};
+use self::pack::PackageInfo;
+use self::prewarm::{CacheResidency, PrewarmBuilder};
+use self::snapshot::SnapshotInfo;
+use self::split::{SplitBuilder, SplitStrategy};
+use self::stats::ColumnStatistics;
+use self::tags::Tags;
+pub use self::ttl::Ttl;
+use self::usage::TableStatistics;
+use self::validate::VectorCheck;
+use self::vector_stats::VectorStatsBuilder;
+
+pub mod aggregate;
+mod auto_compact;
+mod auto_index;
+pub mod blob;
+pub mod bulk_ingest;
+pub mod changes;
+pub mod cluster;
+mod columns;
+mod commit;
+pub mod compatibility;
+pub mod compression;
 pub(crate) mod dataset;
+pub mod dedup;
+pub mod dictionary;
+pub mod dry_run;
+pub mod export;
+mod fast_search;
+mod flat;
+pub mod fragment_stats;
+mod gpu;
+mod group;
+pub mod hooks;
+mod idempotency;
+mod index_recovery;
+mod lookup;
 pub mod merge;
+pub mod metadata;
+pub mod migrate;
+mod order;
+pub mod pack;
+pub mod paginate;
+pub mod partition;
+pub mod prewarm;
+pub(crate) mod primary_key;
+pub mod rescore;
+pub mod row_address;
+pub mod sample;
+pub mod snapshot;
+pub mod split;
+mod stale;
+pub mod stats;
+pub mod tags;
+pub mod transaction;
+pub(crate) mod trash;
+pub mod ttl;
+pub mod usage;
+mod validate;
+pub mod vector_stats;
+pub(crate) mod view;
+pub mod writer;
 
 /// Optimize the dataset.
 ///
@@ -73,11 +183,15 @@ pub enum OptimizeAction {
     /// Run optimization on every, with default options.
     All,
     /// Compact files in the dataset
+    ///
+    /// This also permanently removes any soft deleted rows, see [`Table::undelete`].
     Compact {
         options: CompactionOptions,
         remap_options: Option<Arc<dyn IndexRemapperOptions>>,
     },
     /// Prune old version of datasets.
+    ///
+    /// Versions named by a tag (see [`Table::tags`]) are kept.
     Prune {
         /// The duration of time to keep versions of the dataset.
         older_than: Duration,
@@ -85,16 +199,71 @@ pub enum OptimizeAction {
         /// If you are sure that there are no in-progress transactions, then you can set this to True to delete all files older than `older_than`.
         delete_unverified: Option<bool>,
     },
+    /// Rewrite the fragments with deleted rows so the rows are removed from disk
+    ///
+    /// Deleted rows are only marked as deleted until the fragment holding them is
+    /// rewritten.  Fragments where at least `threshold` (between 0 and 1) of the
+    /// rows are deleted are rewritten, small fragments are also merged as with
+    /// [`OptimizeAction::Compact`].  Soft deleted rows are permanently removed, see
+    /// [`Table::undelete`].  The space is reclaimed once the old versions are
+    /// pruned.
+    PurgeDeletions { threshold: f32 },
+    /// Collect the statistics of the fragments used to skip them in scans
+    ///
+    /// Only the fragments added since the statistics were last collected are
+    /// scanned, see [`fragment_stats`].
+    FragmentStats,
+    /// Delete the rows whose time to live expired, see [`ttl`]
+    ///
+    /// This does nothing for tables without a time to live.
+    Expire,
     /// Optimize index.
     Index(OptimizeOptions),
 }
 
+/// The options of the compaction of [`OptimizeAction::PurgeDeletions`]
+fn purge_options(threshold: f32) -> Result<CompactionOptions> {
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(Error::InvalidInput {
+            message: format!("the threshold must be between 0 and 1, got {}", threshold),
+        });
+    }
+    Ok(CompactionOptions {
+        materialize_deletions: true,
+        materialize_deletions_threshold: threshold,
+        ..Default::default()
+    })
+}
+
 impl Default for OptimizeAction {
     fn default() -> Self {
         Self::All
     }
 }
 
+impl OptimizeAction {
+    /// The actions run by this action, in order
+    fn into_steps(self) -> Vec<Self> {
+        match self {
+            // Expired rows are deleted first, so that compaction reclaims them
+            Self::All => vec![
+                Self::Expire,
+                Self::Compact {
+                    options: CompactionOptions::default(),
+                    remap_options: None,
+                },
+                Self::FragmentStats,
+                Self::Prune {
+                    older_than: Duration::try_days(7).unwrap(),
+                    delete_unverified: None,
+                },
+                Self::Index(OptimizeOptions::default()),
+            ],
+            action => vec![action],
+        }
+    }
+}
+
 /// Statistics about the optimization.
 pub struct OptimizeStats {
     /// Stats of the file compaction.
@@ -102,6 +271,49 @@ pub struct OptimizeStats {
 
     /// Stats of the version pruning
     pub prune: Option<RemovalStats>,
+
+    /// Stats of the rewrite of fragments with deleted rows
+    pub purge: Option<CompactionMetrics>,
+
+    /// The number of expired rows deleted, if the table has a time to live
+    pub expired_rows: Option<u64>,
+
+    /// Stats of the collection of the fragment statistics
+    pub fragment_stats: Option<FragmentStatsMetrics>,
+
+    /// The number of files removed from storage, less the files that were added
+    pub files_reclaimed: usize,
+
+    /// The number of bytes removed from storage, less the bytes that were added
+    ///
+    /// Compacting writes new files, the old files are only removed from storage
+    /// when the old versions are pruned.  So this is 0 for a compaction on its
+    /// own and the space is reclaimed by the next prune.
+    pub bytes_reclaimed: u64,
+}
+
+/// The result of [`Table::delete`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeleteResult {
+    /// The number of rows that were deleted
+    pub num_deleted_rows: u64,
+    /// The version of the table after the delete
+    ///
+    /// This is the current version if no row matched, as nothing is committed.
+    pub version: u64,
+}
+
+/// The value of a row in one version of the table, see [`Table::row_history`]
+#[derive(Debug, Clone)]
+pub struct RowVersion {
+    /// The version of the table
+    pub version: u64,
+    /// When the version was created
+    pub timestamp: DateTime<Utc>,
+    /// The rows matching the key filter in this version
+    ///
+    /// This is empty if the row was deleted in this version.
+    pub rows: RecordBatch,
 }
 
 /// Options to use when writing data
@@ -114,6 +326,19 @@ pub struct WriteOptions {
     ///
     /// If set, these will take precedence over any overlapping `OpenTableBuilder` options
     pub lance_write_params: Option<WriteParams>,
+    /// The number of times the commit is retried if it conflicts with a
+    /// concurrent writer
+    ///
+    /// Defaults to the retries of the connection, see
+    /// [`crate::connection::ConnectBuilder::commit_retries`].  Adds are only
+    /// retried if this is set, since their input is then buffered in memory to
+    /// be written again.
+    pub max_commit_retries: Option<u32>,
+    /// How long to wait before retrying a conflicting commit
+    ///
+    /// Defaults to the backoff of the connection, see
+    /// [`crate::connection::ConnectBuilder::commit_backoff`].
+    pub commit_backoff: Option<CommitBackoff>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -123,6 +348,76 @@ pub enum AddDataMode {
     Append,
     /// The existing table will be overwritten with the new data
     Overwrite,
+    /// Rows whose primary key is already in the table replace the existing
+    /// rows, the other rows are appended
+    ///
+    /// If several rows of the data have the same key the last one is kept.
+    /// The table must have a primary key, see
+    /// [`crate::connection::CreateTableBuilder::primary_key`].
+    OverwriteOnConflict,
+    /// Fail with [`Error::PrimaryKeyConflict`] if the primary key of a row is
+    /// already in the table or appears more than once in the data
+    ///
+    /// The table must have a primary key, see
+    /// [`crate::connection::CreateTableBuilder::primary_key`].
+    ErrorOnConflict,
+    /// Rows will be appended to the table, writing several fragments in
+    /// parallel and committing them at once
+    ///
+    /// This is much faster for the initial load of a large table.  The data
+    /// must have the schema of the table.  See [`bulk_ingest`].
+    BulkIngest(BulkIngestOptions),
+}
+
+/// How the schema of added data is matched to the schema of the table
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaMode {
+    /// The data must have the schema of the table (the default)
+    #[default]
+    Strict,
+    /// The data is converted to the schema of the table
+    ///
+    /// The columns are reordered to match the table and cast to the types of
+    /// the table, for example `Int32` to `Int64`, `Utf8` to `LargeUtf8` or a
+    /// timestamp to another unit.  Nullable columns missing from the data are
+    /// filled with nulls.  Missing columns which are not nullable and types
+    /// which can't be cast fail with [`Error::Schema`], as do the columns which
+    /// are not in the table unless they are ignored with
+    /// [`AddDataBuilder::on_extra`].
+    ///
+    /// This does not apply when the table is overwritten, since the data then
+    /// replaces the schema of the table.
+    Coerce,
+}
+
+/// What to do with the columns of the table missing from added data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingColumns {
+    /// Fail with [`Error::Schema`] (the default)
+    #[default]
+    Error,
+    /// Fill the missing columns with nulls
+    ///
+    /// Missing columns which are not nullable still fail with [`Error::Schema`].
+    FillNull,
+}
+
+/// What to do with the columns of added data which are not in the table
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExtraColumns {
+    /// Fail with [`Error::Schema`] (the default)
+    #[default]
+    Error,
+    /// Drop the extra columns
+    Ignore,
+}
+
+/// How added data is fitted to the schema of the table
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct SchemaPolicy {
+    pub(crate) mode: SchemaMode,
+    pub(crate) on_missing: MissingColumns,
+    pub(crate) on_extra: ExtraColumns,
 }
 
 /// A builder for configuring a [`crate::connection::Connection::create_table`] or [`Table::add`]
@@ -131,7 +426,10 @@ pub struct AddDataBuilder<T: IntoArrow> {
     parent: Arc<dyn TableInternal>,
     pub(crate) data: T,
     pub(crate) mode: AddDataMode,
+    pub(crate) schema_policy: SchemaPolicy,
     pub(crate) write_options: WriteOptions,
+    pub(crate) idempotency_key: Option<String>,
+    pub(crate) partition_by: Option<String>,
 }
 
 impl<T: IntoArrow> std::fmt::Debug for AddDataBuilder<T> {
@@ -139,7 +437,10 @@ impl<T: IntoArrow> std::fmt::Debug for AddDataBuilder<T> {
         f.debug_struct("AddDataBuilder")
             .field("parent", &self.parent)
             .field("mode", &self.mode)
+            .field("schema_policy", &self.schema_policy)
             .field("write_options", &self.write_options)
+            .field("idempotency_key", &self.idempotency_key)
+            .field("partition_by", &self.partition_by)
             .finish()
     }
 }
@@ -150,21 +451,80 @@ impl<T: IntoArrow> AddDataBuilder<T> {
         self
     }
 
+    /// How the schema of the data is matched to the schema of the table
+    ///
+    /// By default the data must have the schema of the table, see
+    /// [`SchemaMode::Coerce`] to convert it instead.
+    pub fn schema_mode(mut self, schema_mode: SchemaMode) -> Self {
+        self.schema_policy.mode = schema_mode;
+        self
+    }
+
+    /// What to do with the columns of the table missing from the data
+    ///
+    /// By default the data must have all of the columns of the table.  With
+    /// [`MissingColumns::FillNull`] the nullable columns may be left out, so
+    /// producers of sparse data don't have to add columns of nulls.
+    pub fn on_missing(mut self, on_missing: MissingColumns) -> Self {
+        self.schema_policy.on_missing = on_missing;
+        self
+    }
+
+    /// What to do with the columns of the data which are not in the table
+    ///
+    /// By default they fail the add, with [`ExtraColumns::Ignore`] they are
+    /// dropped.
+    pub fn on_extra(mut self, on_extra: ExtraColumns) -> Self {
+        self.schema_policy.on_extra = on_extra;
+        self
+    }
+
     pub fn write_options(mut self, options: WriteOptions) -> Self {
         self.write_options = options;
         self
     }
 
+    /// Apply the write only once, however many times it is retried
+    ///
+    /// The key is recorded with the table once the data is added.  Executing a
+    /// write with a key that was already recorded does nothing, so a write that
+    /// may or may not have been applied (e.g. after a timeout) can be safely
+    /// retried with the same key.  Each distinct write should use a distinct key.
+    pub fn idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Group the appended rows into fragments by their value in `column`
+    ///
+    /// Tables created with [`crate::connection::CreateTableBuilder::partition_by`]
+    /// are partitioned by their column on every append, this partitions a
+    /// single append of any table.  See [`partition`] for the details.
+    pub fn partition_by(mut self, column: impl Into<String>) -> Self {
+        self.partition_by = Some(column.into());
+        self
+    }
+
     pub async fn execute(self) -> Result<()> {
         let parent = self.parent.clone();
         let data = self.data.into_arrow()?;
         let without_data = AddDataBuilder::<NoData> {
             data: NoData {},
             mode: self.mode,
+            schema_policy: self.schema_policy,
             parent: self.parent,
             write_options: self.write_options,
+            idempotency_key: self.idempotency_key,
+            partition_by: self.partition_by,
         };
-        parent.add(without_data, data).await
+        match without_data.mode {
+            AddDataMode::OverwriteOnConflict | AddDataMode::ErrorOnConflict => {
+                primary_key::add_with_keys(parent, without_data, data).await
+            }
+            AddDataMode::Append | AddDataMode::Overwrite | AddDataMode::BulkIngest(_) => {
+                parent.add(without_data, data).await
+            }
+        }
     }
 }
 
@@ -173,7 +533,9 @@ impl<T: IntoArrow> AddDataBuilder<T> {
 pub struct UpdateBuilder {
     parent: Arc<dyn TableInternal>,
     pub(crate) filter: Option<String>,
+    pub(crate) filter_params: Vec<(String, FilterValue)>,
     pub(crate) columns: Vec<(String, String)>,
+    pub(crate) write_options: WriteOptions,
 }
 
 impl UpdateBuilder {
@@ -181,10 +543,21 @@ impl UpdateBuilder {
         Self {
             parent,
             filter: None,
+            filter_params: Vec::new(),
             columns: Vec::new(),
+            write_options: WriteOptions::default(),
         }
     }
 
+    /// Override how the update is retried if it conflicts with a concurrent writer
+    ///
+    /// Only [`WriteOptions::max_commit_retries`] and
+    /// [`WriteOptions::commit_backoff`] apply to updates.
+    pub fn write_options(mut self, options: WriteOptions) -> Self {
+        self.write_options = options;
+        self
+    }
+
     /// Limits the update operation to rows matching the given filter
     ///
     /// If a row does not match the filter then it will be left unchanged.
@@ -193,6 +566,31 @@ impl UpdateBuilder {
         self
     }
 
+    /// Limits the update to rows matching the filter, with the values of its
+    /// numbered parameters
+    ///
+    /// See [`crate::query::QueryBase::only_if_with_params`].
+    pub fn only_if_with_params(
+        self,
+        filter: impl Into<String>,
+        params: impl IntoIterator<Item = FilterValue>,
+    ) -> Self {
+        params
+            .into_iter()
+            .enumerate()
+            .fold(self.only_if(filter), |update, (i, value)| {
+                update.bind((i + 1).to_string(), value)
+            })
+    }
+
+    /// Bind a value to the parameter `$name` of the filter
+    ///
+    /// See [`crate::query::QueryBase::bind`].
+    pub fn bind(mut self, name: impl Into<String>, value: impl Into<FilterValue>) -> Self {
+        self.filter_params.push((name.into(), value.into()));
+        self
+    }
+
     /// Specifies a column to update
     ///
     /// This method may be called multiple times to update multiple columns
@@ -221,16 +619,51 @@ impl UpdateBuilder {
         self
     }
 
-    /// Executes the update operation
-    pub async fn execute(self) -> Result<()> {
+    fn check_columns(&self) -> Result<()> {
         if self.columns.is_empty() {
             Err(Error::InvalidInput {
                 message: "at least one column must be specified in an update operation".to_string(),
             })
         } else {
-            self.parent.clone().update(self).await
+            Ok(())
+        }
+    }
+
+    /// The SQL of the filter, with the bound values of its parameters
+    pub(crate) fn predicate(&self) -> Result<Option<String>> {
+        match &self.filter {
+            Some(predicate) => {
+                let filter = self
+                    .filter_params
+                    .iter()
+                    .fold(Filter::parse(predicate)?, |filter, (name, value)| {
+                        filter.bind(name.clone(), value.clone())
+                    });
+                Ok(Some(filter.to_sql()?))
+            }
+            None if !self.filter_params.is_empty() => Err(Error::InvalidInput {
+                message: "values were bound to parameters but the update has no filter".to_string(),
+            }),
+            None => Ok(None),
         }
     }
+
+    /// Executes the update operation
+    pub async fn execute(self) -> Result<()> {
+        self.check_columns()?;
+        self.parent.clone().update(self).await
+    }
+
+    /// Report what the update would do, without updating anything
+    ///
+    /// See [`dry_run`].
+    pub async fn dry_run(self) -> Result<DryRunReport> {
+        self.check_columns()?;
+        self.parent
+            .clone()
+            .dry_run(DryRunOperation::Update(Box::new(self)))
+            .await
+    }
 }
 
 #[async_trait]
@@ -248,27 +681,35 @@ pub(crate) trait TableInternal: std::fmt::Display + std::fmt::Debug + Send + Syn
         &self,
         query: &Query,
         options: QueryExecutionOptions,
-    ) -> Result<DatasetRecordBatchStream>;
+    ) -> Result<SendableRecordBatchStream>;
     async fn vector_query(
         &self,
         query: &VectorQuery,
         options: QueryExecutionOptions,
-    ) -> Result<DatasetRecordBatchStream>;
+    ) -> Result<SendableRecordBatchStream>;
+    /// Describe the plan that would be used to execute the query
+    async fn explain_plan(&self, query: &VectorQuery, verbose: bool) -> Result<String>;
+    /// The work done by the search of the query, if known
+    async fn scan_stats(&self, query: &VectorQuery) -> Result<Option<ScanStats>>;
     async fn add(
         &self,
         add: AddDataBuilder<NoData>,
         data: Box<dyn arrow_array::RecordBatchReader + Send>,
     ) -> Result<()>;
-    async fn delete(&self, predicate: &str) -> Result<()>;
+    async fn delete(&self, predicate: &str) -> Result<DeleteResult>;
+    async fn undelete(&self, filter: &str) -> Result<()>;
     async fn update(&self, update: UpdateBuilder) -> Result<()>;
     async fn create_index(&self, index: IndexBuilder) -> Result<()>;
     async fn list_indices(&self) -> Result<Vec<IndexConfig>>;
+    async fn index_stats(&self, name: &str) -> Result<Option<IndexStatistics>>;
+    async fn drop_index(&self, name: &str) -> Result<()>;
     async fn merge_insert(
         &self,
         params: MergeInsertBuilder,
         new_data: Box<dyn RecordBatchReader + Send>,
     ) -> Result<()>;
     async fn optimize(&self, action: OptimizeAction) -> Result<OptimizeStats>;
+    async fn dry_run(&self, operation: DryRunOperation) -> Result<DryRunReport>;
     async fn add_columns(
         &self,
         transforms: NewColumnTransform,
@@ -276,10 +717,36 @@ pub(crate) trait TableInternal: std::fmt::Display + std::fmt::Debug + Send + Syn
     ) -> Result<()>;
     async fn alter_columns(&self, alterations: &[ColumnAlteration]) -> Result<()>;
     async fn drop_columns(&self, columns: &[&str]) -> Result<()>;
+    async fn update_metadata(
+        &self,
+        column: Option<&str>,
+        metadata: HashMap<String, String>,
+    ) -> Result<()>;
     async fn version(&self) -> Result<u64>;
     async fn checkout(&self, version: u64) -> Result<()>;
     async fn checkout_latest(&self) -> Result<()>;
+    async fn list_versions(&self) -> Result<Vec<Version>>;
+    async fn list_tags(&self) -> Result<BTreeMap<String, u64>>;
+    async fn create_tag(&self, tag: &str, version: u64) -> Result<()>;
+    async fn delete_tag(&self, tag: &str) -> Result<()>;
     async fn restore(&self) -> Result<()>;
+    async fn row_history(&self, key_filter: &str) -> Result<Vec<RowVersion>>;
+    async fn cluster(&self, params: ClusterBuilder) -> Result<FixedSizeListArray>;
+    async fn ivf_centroids(&self, column: &str) -> Result<FixedSizeListArray>;
+    async fn column_stats(&self, column: &str) -> Result<ColumnStatistics>;
+    async fn stats(&self) -> Result<TableStatistics>;
+    async fn take(&self, row_ids: &[u64], select: Select) -> Result<RecordBatch>;
+    async fn verify_checksums(&self) -> Result<ChecksumReport>;
+    async fn rebuild_missing_indices(&self) -> Result<Vec<String>>;
+    async fn optimize_indices(&self, options: OptimizeIndexOptions) -> Result<Vec<String>>;
+    async fn split(&self, params: SplitBuilder) -> Result<Vec<Table>>;
+    async fn prewarm(&self, params: PrewarmBuilder) -> Result<()>;
+    async fn cache_residency(&self) -> Result<CacheResidency>;
+    async fn changes(&self, params: ChangesBuilder) -> Result<ChangeStream>;
+    async fn gpu_search(&self, column: &str, accelerator: Accelerator) -> Result<()>;
+    async fn migrate_vector_dim(&self, params: MigrateVectorDimBuilder) -> Result<()>;
+    async fn pack(&self, path: &Path) -> Result<PackageInfo>;
+    async fn export_snapshot(&self, target_uri: &str) -> Result<SnapshotInfo>;
 }
 
 /// A Table is a collection of strong typed Rows.
@@ -319,6 +786,20 @@ impl Table {
         self.inner.schema().await
     }
 
+    /// Check whether the table can move to the schema `new_schema`
+    ///
+    /// The report classifies each difference between the schema of the table and
+    /// `new_schema` as adding a column, widening a column (for example `Int32` to
+    /// `Int64`, or making it nullable), or breaking (removing a column, narrowing
+    /// or changing its type, ...).  Nothing is changed, so this can be used to
+    /// reject an incompatible producer before it writes to the table.
+    ///
+    /// See [`compatibility`] for how the schemas are compared.
+    pub async fn check_compatibility(&self, new_schema: &Schema) -> Result<CompatibilityReport> {
+        let schema = self.schema().await?;
+        Ok(compatibility::check_compatibility(&schema, new_schema))
+    }
+
     /// Count the number of rows in this dataset.
     ///
     /// # Arguments
@@ -334,15 +815,35 @@ impl Table {
     ///
     /// * `batches` data to be added to the Table
     /// * `options` options to control how data is added
+    ///
+    /// Unless the table is overwritten, the vectors added to a vector column
+    /// must have the dimension and value type of the column, otherwise the add
+    /// fails with [`Error::VectorMismatch`] naming the column and the batch.
+    ///
+    /// The data may also be an async [`SendableRecordBatchStream`], which is
+    /// consumed as it is written instead of being collected first.
     pub fn add<T: IntoArrow>(&self, batches: T) -> AddDataBuilder<T> {
         AddDataBuilder {
             parent: self.inner.clone(),
             data: batches,
             mode: AddDataMode::Append,
+            schema_policy: SchemaPolicy::default(),
             write_options: WriteOptions::default(),
+            idempotency_key: None,
+            partition_by: None,
         }
     }
 
+    /// Create a writer buffering the rows written to it and adding them to the
+    /// table in large batches
+    ///
+    /// This avoids creating many small fragments and versions when the rows
+    /// arrive a few at a time, for example from a stream of events.  See
+    /// [`writer::TableWriter`].
+    pub fn writer(&self) -> writer::TableWriter {
+        writer::TableWriter::new(self.clone())
+    }
+
     /// Update existing records in the Table
     ///
     /// An update operation can be used to adjust existing values.  Use the
@@ -353,6 +854,22 @@ impl Table {
     /// An optional condition can be specified (e.g. "only update if the old
     /// value is 0")
     ///
+    /// The expressions are evaluated by the table, so existing rows can be
+    /// backfilled without reading them first:
+    ///
+    /// ```
+    /// # use lancedb::Table;
+    /// # async fn doctest_helper(tbl: Table) {
+    /// tbl.update()
+    ///     .only_if("category = $category")
+    ///     .bind("category", "a")
+    ///     .column("score", "score * 2")
+    ///     .execute()
+    ///     .await
+    ///     .unwrap();
+    /// # }
+    /// ```
+    ///
     /// Note: if your condition is something like "some_id_column == 7" and
     /// you are updating many rows (with different ids) then you will get
     /// better performance with a single [`merge_insert`] call instead of
@@ -363,6 +880,11 @@ impl Table {
 
     /// Delete the rows from table that match the predicate.
     ///
+    /// Returns the number of rows deleted, counted from the deletion files
+    /// written rather than by a separate scan.  If no row matches the
+    /// predicate nothing is committed, and the version returned is the
+    /// current version of the table.
+    ///
     /// # Arguments
     /// - `predicate` - The SQL predicate string to filter the rows to be deleted.
     ///
@@ -407,13 +929,122 @@ impl Table {
     ///     .execute()
     ///     .await
     ///     .unwrap();
-    /// tbl.delete("id > 5").await.unwrap();
+    /// let result = tbl.delete("id > 5").await.unwrap();
+    /// assert_eq!(result.num_deleted_rows, 4);
     /// # });
     /// ```
-    pub async fn delete(&self, predicate: &str) -> Result<()> {
+    pub async fn delete(&self, predicate: &str) -> Result<DeleteResult> {
         self.inner.delete(predicate).await
     }
 
+    /// Report what [`Self::delete`] would do, without deleting anything
+    ///
+    /// See [`dry_run`].
+    pub async fn delete_dry_run(&self, predicate: &str) -> Result<DryRunReport> {
+        self.inner
+            .dry_run(DryRunOperation::Delete(predicate.to_string()))
+            .await
+    }
+
+    /// The primary key column of the table, if it has one
+    ///
+    /// See [`crate::connection::CreateTableBuilder::primary_key`].
+    pub async fn primary_key(&self) -> Result<Option<String>> {
+        let schema = self.schema().await?;
+        Ok(primary_key::primary_key(&schema).map(str::to_string))
+    }
+
+    /// Get the row whose primary key is `key`
+    ///
+    /// Returns a batch with the row, or None if no row has the key.  The table
+    /// must have a primary key, see [`crate::connection::CreateTableBuilder::primary_key`].
+    /// A scalar index on the key column makes the lookup faster.
+    pub async fn get(&self, key: impl Into<FilterValue>) -> Result<Option<RecordBatch>> {
+        primary_key::get(self.inner.clone(), key.into()).await
+    }
+
+    /// Delete the rows whose `column` is one of `keys`
+    ///
+    /// This is the same as [`Self::delete`] with the filter `column IN (...)`,
+    /// without having to format and quote the keys.  Null keys are ignored.
+    /// The keys can be integers, floats, or strings.
+    ///
+    /// A scalar index on `column` makes finding the rows to delete faster.
+    pub async fn delete_keys(&self, column: &str, keys: &dyn Array) -> Result<DeleteResult> {
+        let values = FilterValue::from_array(column, keys)?;
+        if values.is_empty() {
+            return Ok(DeleteResult {
+                num_deleted_rows: 0,
+                version: self.version().await?,
+            });
+        }
+        let params = (0..values.len())
+            .map(|i| format!("$key{}", i))
+            .collect::<Vec<_>>();
+        let filter = values.into_iter().enumerate().fold(
+            Filter::parse(format!("`{}` IN ({})", column, params.join(", ")))?,
+            |filter, (i, value)| filter.bind(format!("key{}", i), value),
+        );
+        self.delete(&filter.to_sql()?).await
+    }
+
+    /// Read the rows with the ids `row_ids`, in that order
+    ///
+    /// Row ids are returned in the `_rowid` column of the results of a query.
+    /// The rows are read directly, without scanning the table, which makes
+    /// this much faster than a query filtering on the row ids.  The row ids of
+    /// deleted rows, or of rows moved by a compaction, are invalid.
+    ///
+    /// `select` can be [`Select::All`] or [`Select::Columns`].
+    pub async fn take(&self, row_ids: &[u64], select: Select) -> Result<RecordBatch> {
+        self.inner.take(row_ids, select).await
+    }
+
+    /// Read the blobs of the binary column `column` of the rows with the ids `row_ids`
+    ///
+    /// Only `column` is read, as with [`Self::take`].  Queries can leave the
+    /// blobs out of their results with
+    /// [`crate::query::QueryBase::exclude_blobs`] and the blobs of the rows
+    /// which are needed are then read with this.  Null values are `None`.  See
+    /// [`blob`] for more details.
+    pub async fn take_blobs(
+        &self,
+        row_ids: &[u64],
+        column: &str,
+    ) -> Result<Vec<Option<BlobReader>>> {
+        let batch = self.take(row_ids, Select::columns(&[column])).await?;
+        blob::readers(column, batch.column(0))
+    }
+
+    /// Read the rows whose `column` is one of `values`
+    ///
+    /// The rows are returned in the order of the values they match, values
+    /// matching no row are skipped.  The keys can be integers, floats, or
+    /// strings.  Only `column` is scanned to find the rows, which are then read
+    /// as with [`Self::take`].  A scalar index on `column` makes the lookup
+    /// much faster.
+    ///
+    /// `select` can be [`Select::All`] or [`Select::Columns`].
+    pub async fn lookup(
+        &self,
+        column: &str,
+        values: &dyn Array,
+        select: Select,
+    ) -> Result<RecordBatch> {
+        lookup::lookup(self.inner.clone(), column, values, select).await
+    }
+
+    /// Restore the soft deleted rows that match the filter
+    ///
+    /// If the table was created with soft delete enabled (see
+    /// [`crate::connection::CreateTableBuilder::soft_delete`]) then deleted rows are
+    /// kept in a hidden trash until the table is compacted.  Soft deleted rows can
+    /// be found with [`crate::query::QueryBase::only_deleted`].  Restored rows are
+    /// added back to the table and removed from the trash.
+    pub async fn undelete(&self, filter: &str) -> Result<()> {
+        self.inner.undelete(filter).await
+    }
+
     /// Create an index on the provided column(s).
     ///
     /// Indices are used to speed up searches and are often needed when the size of the table
@@ -474,6 +1105,18 @@ impl Table {
         )
     }
 
+    /// Create an index in the background
+    ///
+    /// This is the same as [`Self::create_index`] with the default options but
+    /// returns immediately.  The returned [`IndexJob`] reports the progress of
+    /// the build and can be used to wait for it or cancel it.  Use
+    /// [`IndexBuilder::spawn`] to set other options.
+    ///
+    /// This must be called from within a tokio runtime.
+    pub fn create_index_async(&self, columns: &[impl AsRef<str>], index: Index) -> IndexJob {
+        self.create_index(columns, index).spawn()
+    }
+
     /// Create a builder for a merge insert operation
     ///
     /// This operation can add rows, update rows, and remove rows all in a single
@@ -647,6 +1290,132 @@ impl Table {
         Query::new(self.inner.clone())
     }
 
+    /// Join the rows of this table with the rows of `other` whose `on` column
+    /// has the same value
+    ///
+    /// This is [`Query::enrich`] of a query of this table: every row is kept
+    /// and the `projection` columns of `other` are added, null when no row of
+    /// `other` matches.  All the columns of `other` but `on` are added when
+    /// `projection` is empty.  `other` can be a table or a DataFusion table
+    /// provider, see [`EnrichSource`].
+    ///
+    /// The returned query can be filtered and limited like any other query.  To
+    /// enrich the results of a vector search, use [`VectorQuery::enrich`]:
+    ///
+    /// ```ignore
+    /// table
+    ///     .vector_search(&[1.0, 2.0])?
+    ///     .limit(10)
+    ///     .enrich(documents, "doc_id")
+    ///     .columns(&["title", "url"])
+    /// ```
+    pub fn join(
+        &self,
+        other: impl Into<EnrichSource>,
+        on: impl Into<String>,
+        projection: &[impl AsRef<str>],
+    ) -> EnrichedQuery<Query> {
+        let join = self.query().enrich(other, on);
+        if projection.is_empty() {
+            join
+        } else {
+            join.columns(projection)
+        }
+    }
+
+    /// Export the vectors in `column` to a file, for training a model outside of LanceDB
+    ///
+    /// Only the vector column and an id column (the row id by default) are read,
+    /// and they are streamed to the file so the table does not need to fit in
+    /// memory.  Use [`ExportVectorsBuilder::format`] to choose between a NumPy
+    /// `.npy` file and a Parquet file.
+    ///
+    /// ```no_run
+    /// # use lancedb::table::export::VectorExportFormat;
+    /// # async fn export(tbl: &lancedb::Table) -> lancedb::Result<()> {
+    /// let num_vectors = tbl
+    ///     .export_vectors("vector")
+    ///     .format(VectorExportFormat::Parquet)
+    ///     .write("/tmp/vectors.parquet")
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn export_vectors(&self, column: impl Into<String>) -> ExportVectorsBuilder {
+        ExportVectorsBuilder::new(self.query(), column.into())
+    }
+
+    /// Cluster the vectors in `column` into `k` clusters with kmeans
+    ///
+    /// The centroids are trained on a sample of the vectors, with the same kmeans
+    /// used to train a vector index, and then the id of the closest centroid is
+    /// written to every row in a new column (`cluster_id` by default).  Rows
+    /// with a null vector get a null cluster id.  The cluster ids can be used to
+    /// explore a dataset, to find near duplicates, or to sample a diverse subset
+    /// of the rows.
+    ///
+    /// Rows added later do not have a cluster id until the clustering is run again.
+    pub fn cluster(&self, column: impl Into<String>, k: usize) -> ClusterBuilder {
+        ClusterBuilder::new(self.inner.clone(), column.into(), k)
+    }
+
+    /// The IVF centroids of the vector index on `column`
+    ///
+    /// The centroid of partition `i` is at index `i`.  They can be given to the
+    /// index of another table with the same kind of vectors, see
+    /// [`crate::index::vector::IvfPqIndexBuilder::centroids`], so that its
+    /// index is built without training kmeans.
+    pub async fn ivf_centroids(&self, column: &str) -> Result<FixedSizeListArray> {
+        self.inner.ivf_centroids(column).await
+    }
+
+    /// Split the table into several smaller tables, the shards
+    ///
+    /// The shards are created in the same database and are named after the table
+    /// (see [`SplitBuilder::name_prefix`]).  The table itself is not modified.
+    /// Splitting a table keeps the indices of each shard small enough to build and
+    /// search quickly, the shards can then be searched concurrently.  Indices can
+    /// be built on each of the shards with [`SplitBuilder::index`].
+    ///
+    /// See [`SplitStrategy`] for how the rows are assigned to the shards.
+    pub fn split(&self, strategy: SplitStrategy) -> SplitBuilder {
+        SplitBuilder::new(self.inner.clone(), strategy)
+    }
+
+    /// Answer the vector searches of `column` on a GPU
+    ///
+    /// With [`Accelerator::Cuda`] the vectors of the column are copied, in half
+    /// precision, to the memory of the device and the distances of the vector
+    /// searches of the column are computed there.  This is meant for tables that
+    /// are searched at a very high rate.  The results are approximate, like the
+    /// results of a vector index.  The vectors are copied again after the table
+    /// changes.  [`Accelerator::Cpu`] frees the memory of the device.
+    ///
+    /// Searches the GPU cannot answer, for example searches with a filter or
+    /// searches that fail on the device, are run on the CPU.
+    ///
+    /// This requires the `cuda` feature.
+    pub async fn gpu_search(&self, column: &str, accelerator: Accelerator) -> Result<()> {
+        self.inner.gpu_search(column, accelerator).await
+    }
+
+    /// Find rows whose vectors in `column` are within `threshold` of each other
+    ///
+    /// Every row is searched for with its own vector, so this runs one vector
+    /// search per row and should be used with a vector index on the column.
+    /// Without an index every search is a full scan.  The threshold is a
+    /// distance of the index's distance type (note that L2 distances are
+    /// squared).  The result has the pairs of duplicate row ids and the groups
+    /// they form, which can be used to delete redundant rows, for example with
+    /// `_rowid IN (...)`.
+    pub fn find_duplicates(
+        &self,
+        column: impl Into<String>,
+        threshold: f32,
+    ) -> FindDuplicatesBuilder {
+        FindDuplicatesBuilder::new(self.inner.clone(), column.into(), threshold)
+    }
+
     /// Search the table with a given query vector.
     ///
     /// This is a convenience method for preparing a vector query and
@@ -662,11 +1431,40 @@ impl Table {
     ///
     /// Modeled after ``VACUUM`` in PostgreSQL.
     /// Not all implementations support explicit optimization.
+    ///
+    /// Tables that are written with many small appends accumulate small
+    /// fragments, which slow down reads.  [`OptimizeAction::Compact`] merges them,
+    /// [`OptimizeAction::PurgeDeletions`] rewrites fragments with deleted rows
+    /// and [`OptimizeAction::Prune`] removes the files of old versions from
+    /// storage.  The returned [`OptimizeStats`] reports what each step did and how
+    /// many files and bytes were reclaimed.
     pub async fn optimize(&self, action: OptimizeAction) -> Result<OptimizeStats> {
         self.inner.optimize(action).await
     }
 
+    /// Report what [`Self::optimize`] would do, without changing the table
+    ///
+    /// See [`dry_run`].
+    pub async fn optimize_dry_run(&self, action: OptimizeAction) -> Result<DryRunReport> {
+        self.inner.dry_run(DryRunOperation::Optimize(action)).await
+    }
+
+    /// Export the rows of the table to a Parquet file
+    ///
+    /// `uri` is a path or the URI of an object store, such as
+    /// `s3://bucket/table.parquet`.  The rows are streamed to the file, so the
+    /// table does not need to fit in memory.  See [`crate::data::parquet`].
+    pub fn export_parquet(&self, uri: impl Into<String>) -> ExportParquetBuilder {
+        ExportParquetBuilder::new(self.query(), uri.into())
+    }
+
     /// Add new columns to the table, providing values to fill in.
+    ///
+    /// The values are computed from the existing columns, either with SQL
+    /// expressions ([`NewColumnTransform::SqlExpressions`]) or with a function
+    /// ([`NewColumnTransform::BatchUDF`]).  `read_columns` are the columns the
+    /// function reads.  See [`Self::add_null_columns`] to add columns without
+    /// values.
     pub async fn add_columns(
         &self,
         transforms: NewColumnTransform,
@@ -675,16 +1473,137 @@ impl Table {
         self.inner.add_columns(transforms, read_columns).await
     }
 
+    /// Add the columns of `schema` to the table, with null values
+    ///
+    /// This is useful to add metadata fields that are filled in later, for
+    /// example with [`Self::update`].  The columns must be nullable and must not
+    /// exist yet.  The existing data files are not rewritten.
+    pub async fn add_null_columns(&self, schema: SchemaRef) -> Result<()> {
+        columns::add_null_columns(self.inner.as_ref(), schema).await
+    }
+
     /// Change a column's name or nullability.
+    ///
+    /// See [`Self::alter_column_types`] to change the type of a column.
     pub async fn alter_columns(&self, alterations: &[ColumnAlteration]) -> Result<()> {
         self.inner.alter_columns(alterations).await
     }
 
+    /// Change the type of columns, converting their values
+    ///
+    /// Each column is given as its name and its new type.  The conversion fails,
+    /// leaving the column unchanged, if one of the values cannot be represented
+    /// in the new type (for example 1000 as an `Int8`).  Columns with an index
+    /// must have the index dropped first, it can be created again afterwards.
+    ///
+    /// The column is rewritten and moves to the end of the schema.  This takes
+    /// three versions of the table per column: the converted values are added as
+    /// a new column, the original column is dropped, and the new column is
+    /// renamed.  If the process stops in between, the new column may remain
+    /// under a temporary name.
+    pub async fn alter_column_types(&self, columns: &[(impl AsRef<str>, DataType)]) -> Result<()> {
+        let columns = columns
+            .iter()
+            .map(|(name, data_type)| (name.as_ref().to_string(), data_type.clone()))
+            .collect::<Vec<_>>();
+        columns::alter_column_types(self.inner.as_ref(), &columns).await
+    }
+
+    /// Change the dimension of the vectors in `column` to `new_dim`
+    ///
+    /// Every stored vector is mapped by `projector`, for example a
+    /// [`migrate::Truncate`] of Matryoshka embeddings or a
+    /// [`migrate::LinearProjection`] with a PCA matrix, to switch to cheaper
+    /// embeddings without computing them again.  The indices on the column are
+    /// rebuilt with the default parameters.  If the column is computed by an
+    /// embedding function, the function computing the new vectors must be given
+    /// with [`MigrateVectorDimBuilder::embedding_name`].
+    ///
+    /// Like [`Self::alter_column_types`], the column is rewritten in several
+    /// versions of the table and moves to the end of the schema.  Queries must
+    /// use vectors of the new dimension afterwards.
+    pub fn migrate_vector_dim(
+        &self,
+        column: impl Into<String>,
+        new_dim: usize,
+        projector: Arc<dyn VectorProjector>,
+    ) -> MigrateVectorDimBuilder {
+        MigrateVectorDimBuilder::new(self.inner.clone(), column.into(), new_dim, projector)
+    }
+
+    /// Write the checked out version of the table to a single file at `path`
+    ///
+    /// The package is a compressed archive with the data, the deletions and the
+    /// indices of the version, but not the older versions of the table.  It can
+    /// be copied to another machine and unpacked into a table with
+    /// [`crate::Connection::unpack`], which is an easy way to distribute a
+    /// prebuilt dataset.  By convention the file has the `.lancepkg` extension.
+    pub async fn pack(&self, path: impl AsRef<Path>) -> Result<PackageInfo> {
+        self.inner.pack(path.as_ref()).await
+    }
+
+    /// Copy the checked out version of the table to the directory `target_uri`
+    ///
+    /// The copy, with the data, the deletions, the indices and the manifest of
+    /// the version, is a table of its own, which can be in another object store
+    /// accessed with the storage options of the table.  It is consistent even if
+    /// the table is written during the copy, and an interrupted copy is resumed
+    /// by exporting the same version again.  The snapshot is brought back into a
+    /// database with [`crate::Connection::import_snapshot`].  See [`snapshot`].
+    pub async fn export_snapshot(&self, target_uri: &str) -> Result<SnapshotInfo> {
+        self.inner.export_snapshot(target_uri).await
+    }
+
     /// Remove columns from the table.
     pub async fn drop_columns(&self, columns: &[&str]) -> Result<()> {
         self.inner.drop_columns(columns).await
     }
 
+    /// Get the metadata of the table, see [`metadata`]
+    pub async fn metadata(&self) -> Result<HashMap<String, String>> {
+        Ok(self.schema().await?.metadata().clone())
+    }
+
+    /// Add key-value pairs to the metadata of the table in a new version
+    ///
+    /// Existing keys are overwritten and the other keys are kept.  See
+    /// [`metadata`] for the keys which cannot be set.
+    pub async fn update_metadata(
+        &self,
+        metadata: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Result<()> {
+        let metadata = metadata
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        self.inner.update_metadata(None, metadata).await
+    }
+
+    /// Get the metadata of the column `column`, see [`metadata`]
+    pub async fn field_metadata(&self, column: &str) -> Result<HashMap<String, String>> {
+        Ok(self
+            .schema()
+            .await?
+            .field_with_name(column)?
+            .metadata()
+            .clone())
+    }
+
+    /// Add key-value pairs to the metadata of the column `column` in a new version
+    ///
+    /// Like [`Self::update_metadata`] for the metadata of a column.
+    pub async fn update_field_metadata(
+        &self,
+        column: &str,
+        metadata: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Result<()> {
+        let metadata = metadata
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        self.inner.update_metadata(Some(column), metadata).await
+    }
+
     /// Retrieve the version of the table
     ///
     /// LanceDb supports versioning.  Every operation that modifies the table increases
@@ -721,7 +1640,80 @@ impl Table {
         self.inner.checkout_latest().await
     }
 
-    /// Restore the table to the currently checked out version
+    /// Checks out the version of the Table that was current at `timestamp`
+    ///
+    /// This is the latest version created at or before `timestamp`.  It fails if
+    /// the table has no such version, for example because it was created later or
+    /// the version was removed by [`OptimizeAction::Prune`].
+    ///
+    /// See [`Self::checkout`] for what it means for a table to be checked out.
+    pub async fn checkout_timestamp(&self, timestamp: DateTime<Utc>) -> Result<()> {
+        let version = self
+            .inner
+            .list_versions()
+            .await?
+            .into_iter()
+            .filter(|version| version.timestamp <= timestamp)
+            .max_by_key(|version| version.version)
+            .ok_or_else(|| Error::InvalidInput {
+                message: format!("The table {} has no version at {}", self.name(), timestamp),
+            })?;
+        self.inner.checkout(version.version).await
+    }
+
+    /// List the versions of the Table, in the order they were created
+    ///
+    /// Versions removed by [`OptimizeAction::Prune`] are not included.
+    pub async fn list_versions(&self) -> Result<Vec<Version>> {
+        self.inner.list_versions().await
+    }
+
+    /// The tags of the Table, names for its versions
+    ///
+    /// ```ignore
+    /// table.tags().create("v1.0", table.version().await?).await?;
+    /// table.checkout_tag("v1.0").await?;
+    /// ```
+    pub fn tags(&self) -> Tags {
+        Tags::new(self.inner.clone())
+    }
+
+    /// Checks out the version of the Table named `tag`
+    ///
+    /// See [`Self::tags`] and [`Self::checkout`].
+    pub async fn checkout_tag(&self, tag: &str) -> Result<()> {
+        let version = self.tags().get_version(tag).await?;
+        self.inner.checkout(version).await
+    }
+
+    /// Get the rows inserted, updated and deleted since a version of the table
+    ///
+    /// The changes are computed from the fragments of the versions so only the
+    /// changed rows are read, which makes it cheap to keep a cache or an external
+    /// search index in sync with the table.  The changes are up to the latest
+    /// version, or to [`changes::ChangesBuilder::to_version`], and
+    /// [`changes::ChangesBuilder::watch`] follows the new versions as they are
+    /// committed.  See [`changes`] for how updates are reported.
+    pub fn changes(&self, since_version: u64) -> ChangesBuilder {
+        ChangesBuilder::new(self.inner.clone(), since_version)
+    }
+
+    /// Get the values a row had in each version of the table
+    ///
+    /// The row is identified by `key_filter`, for example `id = 42`.  The result
+    /// contains an entry for the first version in which a row matched the filter
+    /// and for every later version in which the matching rows changed.  If the
+    /// rows were deleted then the entry for that version has no rows.  Versions
+    /// removed by [`OptimizeAction::Prune`] are not included.
+    ///
+    /// Every version of the table is scanned so this is intended for debugging,
+    /// for example to find out when a document's embedding or metadata changed.
+    /// Creating a scalar index on the key column makes it faster.
+    pub async fn row_history(&self, key_filter: &str) -> Result<Vec<RowVersion>> {
+        self.inner.row_history(key_filter).await
+    }
+
+    /// Restore the table to the currently checked out version
     ///
     /// This operation will fail if checkout has not been called previously
     ///
@@ -735,10 +1727,156 @@ impl Table {
         self.inner.restore().await
     }
 
+    /// Restore the table to `version`
+    ///
+    /// This is the same as calling [`Self::checkout`] and then [`Self::restore`].
+    /// A new version is created with the data of `version`, so the versions in
+    /// between are not lost and can still be checked out.
+    pub async fn restore_version(&self, version: u64) -> Result<()> {
+        self.inner.checkout(version).await?;
+        self.inner.restore().await
+    }
+
     /// List all indices that have been created with [`Self::create_index`]
+    ///
+    /// See [`Self::index_stats`] for how much of the table each index covers.
     pub async fn list_indices(&self) -> Result<Vec<IndexConfig>> {
         self.inner.list_indices().await
     }
+
+    /// Get statistics describing the values of a column
+    ///
+    /// The statistics (null count, min, max, approximate distinct count, and a
+    /// histogram for numeric columns) are computed with a scan of the column the
+    /// first time they are requested.  After that they are kept up to date as
+    /// data is added to the table, so requesting them again is cheap.  Other
+    /// changes to the table, such as deletes and updates, cause the statistics to
+    /// be recomputed the next time they are requested.
+    ///
+    /// Statistics are supported for boolean, numeric, temporal, string, and binary
+    /// columns.
+    pub async fn column_stats(&self, column: impl AsRef<str>) -> Result<ColumnStatistics> {
+        self.inner.column_stats(column.as_ref()).await
+    }
+
+    /// Get statistics describing the rows and the storage of the table
+    ///
+    /// This includes the number of rows, the number of deleted rows waiting to
+    /// be removed by compaction, the number of fragments, and the bytes used by
+    /// the data files, each index, and (estimated) each column.  The statistics
+    /// are read from the metadata of the table and a listing of its files, the
+    /// data is not scanned.
+    pub async fn stats(&self) -> Result<TableStatistics> {
+        self.inner.stats().await
+    }
+
+    /// Aggregate the rows of the table
+    ///
+    /// The aggregates are computed as the table is scanned and only the result,
+    /// one row per group, is returned:
+    ///
+    /// ```ignore
+    /// let counts = table
+    ///     .aggregate()
+    ///     .group_by("label")
+    ///     .count()
+    ///     .min("score")
+    ///     .execute()
+    ///     .await?;
+    /// ```
+    ///
+    /// See [`aggregate::AggregateBuilder`] for the aggregates.
+    pub fn aggregate(&self) -> AggregateBuilder {
+        AggregateBuilder::new(self.clone())
+    }
+
+    /// Get statistics describing the vectors of a column
+    ///
+    /// The statistics (dimension, distribution of the norms, centroid, and an
+    /// estimate of the recall of the vector index of the column) are computed
+    /// with a scan of the column every time they are requested.  Comparing them
+    /// with the statistics taken when the index was trained shows whether the
+    /// embeddings drifted, see [`vector_stats::VectorStatistics::drift`], and
+    /// whether the IVF centroids of the index should be retrained.
+    pub fn vector_stats(&self, column: impl Into<String>) -> VectorStatsBuilder {
+        VectorStatsBuilder::new(self.clone(), column.into())
+    }
+
+    /// Check all of the data and index files of the table against their checksums
+    ///
+    /// Checksums are written when the connection is opened with
+    /// [`crate::connection::ConnectBuilder::checksums`].  Every file is read, so
+    /// this is expensive on large tables and is meant to be run periodically
+    /// rather than before each query.  Files without a checksum are reported as
+    /// missing rather than corrupted.
+    pub async fn verify_checksums(&self) -> Result<ChecksumReport> {
+        self.inner.verify_checksums().await
+    }
+
+    /// Rebuild the indices whose files are lost or corrupted
+    ///
+    /// An index is considered damaged if its files are missing, or if they do not
+    /// match their checksum (see [`Self::verify_checksums`]).  Vector searches on
+    /// a column with a damaged index fall back to a flat search and log a warning
+    /// until the index is rebuilt.
+    ///
+    /// The parameters of a damaged index cannot be read back, so each one is
+    /// rebuilt with [`Index::Auto`] on the same columns.  Returns the names of the
+    /// indices that were rebuilt.
+    pub async fn rebuild_missing_indices(&self) -> Result<Vec<String>> {
+        self.inner.rebuild_missing_indices().await
+    }
+
+    /// Index the rows added since the indices were created or last optimized
+    ///
+    /// Searches scan the rows that are not covered by an index, so their latency
+    /// grows as rows are added.  This adds the new rows to the existing indices
+    /// without retraining them, unless [`OptimizeIndexOptions::retrain_threshold`]
+    /// is set and a vector index has too many new rows, in which case that index
+    /// is rebuilt with [`Index::Auto`].  Returns the names of the retrained
+    /// indices.  See [`crate::connection::ConnectBuilder::auto_index`] to do
+    /// this automatically after adds.
+    pub async fn optimize_indices(&self, options: OptimizeIndexOptions) -> Result<Vec<String>> {
+        self.inner.optimize_indices(options).await
+    }
+
+    /// Load the indices of the table, and optionally some of its columns, into
+    /// the caches
+    ///
+    /// The first searches after a table is opened read the partitions of the
+    /// indices they probe from storage, and so are much slower than the following
+    /// ones.  Prewarming a table before it serves queries avoids this.  See
+    /// [`prewarm`] for what is loaded, and [`Self::cache_residency`] to check it.
+    pub fn prewarm(&self) -> PrewarmBuilder {
+        PrewarmBuilder::new(self.inner.clone())
+    }
+
+    /// Load the index with the given name into the index cache, see [`Self::prewarm`]
+    pub async fn prewarm_index(&self, name: impl AsRef<str>) -> Result<()> {
+        self.prewarm().indices(&[name]).execute().await
+    }
+
+    /// Describe what is loaded in the caches of the table
+    pub async fn cache_residency(&self) -> Result<CacheResidency> {
+        self.inner.cache_residency().await
+    }
+
+    /// Get statistics about the index with the given name
+    ///
+    /// Returns None if there is no index with that name.  The statistics include
+    /// how many rows were added since the index was last created or optimized
+    /// (see [`OptimizeAction::Index`]) and so are not yet covered by it.
+    pub async fn index_stats(&self, name: impl AsRef<str>) -> Result<Option<IndexStatistics>> {
+        self.inner.index_stats(name.as_ref()).await
+    }
+
+    /// Drop the index with the given name
+    ///
+    /// The names of the indices are returned by [`Self::list_indices`].  Returns
+    /// [`Error::IndexNotFound`] if there is no index with that name.
+    pub async fn drop_index(&self, name: impl AsRef<str>) -> Result<()> {
+        self.inner.drop_index(name.as_ref()).await
+    }
 }
 
 impl From<NativeTable> for Table {
@@ -763,6 +1901,53 @@ pub struct NativeTable {
     // This comes from the connection options. We store here so we can pass down
     // to the dataset when we recreate it (for example, in checkout_latest).
     read_consistency_interval: Option<std::time::Duration>,
+
+    // The embedding functions of the connection, used to compute the embedding
+    // columns of the table.
+    embedding_registry: Option<Arc<dyn EmbeddingRegistry>>,
+
+    // The quotas of the connection, enforced when writing to the table.
+    quotas: Option<Arc<Quotas>>,
+
+    // The number of times a commit that conflicts with a concurrent writer is retried.
+    commit_retries: u32,
+
+    // The wait before retrying a conflicting commit.
+    commit_backoff: CommitBackoff,
+
+    // When to compact the small fragments of the table after an add.
+    auto_compaction: Option<AutoCompaction>,
+
+    // When to optimize the indices of the table after an add.
+    auto_index: Option<AutoIndex>,
+
+    // Whether a query of a version which is no longer available is retried on the latest version.
+    retry_stale_queries: bool,
+
+    // The vectors kept on a GPU to answer vector searches, shared by the clones of the table.
+    #[cfg_attr(not(feature = "cuda"), allow(dead_code))]
+    gpu_search: Arc<gpu::GpuSearch>,
+
+    // The hooks of the connection, called around the commits of the table.
+    commit_hooks: Vec<Arc<dyn hooks::CommitHook>>,
+
+    // The limit on the queries of the connection, applied to the queries of the table.
+    query_admission: Option<Arc<QueryAdmission>>,
+
+    // The read-ahead and memory budget of the scans of the connection.
+    scan_limits: ScanLimits,
+
+    // The cache of the results of the queries, shared by the tables of the connection.
+    query_cache: Option<Arc<QueryCache>>,
+
+    // The metrics of the connection, counting the queries and writes of the table.
+    metrics: Option<Arc<MetricsRegistry>>,
+
+    // The handler of the commits of the table, see [`crate::io::commit_lock`].
+    commit_handler: Option<Arc<dyn CommitHandler>>,
+
+    // The indices and columns loaded by [`Table::prewarm`], shared by the clones of the table.
+    prewarmed: Arc<Mutex<prewarm::Prewarmed>>,
 }
 
 impl std::fmt::Display for NativeTable {
@@ -820,10 +2005,11 @@ impl NativeTable {
     ) -> Result<Self> {
         let params = params.unwrap_or_default();
         // patch the params if we have a write store wrapper
-        let params = match write_store_wrapper.clone() {
+        let mut params = match write_store_wrapper.clone() {
             Some(wrapper) => params.patch_with_store_wrapper(wrapper)?,
             None => params,
         };
+        let through_store = read_local_through_wrapper(uri, &mut params)?;
 
         let storage_options = params
             .store_options
@@ -831,6 +2017,7 @@ impl NativeTable {
             .unwrap_or_default()
             .storage_options
             .unwrap_or_default();
+        let commit_handler = params.commit_handler.clone();
 
         let dataset = DatasetBuilder::from_uri(uri)
             .with_read_params(params)
@@ -843,7 +2030,8 @@ impl NativeTable {
                 source => Error::Lance { source },
             })?;
 
-        let dataset = DatasetConsistencyWrapper::new_latest(dataset, read_consistency_interval);
+        let dataset = DatasetConsistencyWrapper::new_latest(dataset, read_consistency_interval)
+            .read_through_store(through_store);
 
         Ok(Self {
             name: name.to_string(),
@@ -852,9 +2040,218 @@ impl NativeTable {
             store_wrapper: write_store_wrapper,
             storage_options,
             read_consistency_interval,
+            embedding_registry: None,
+            quotas: None,
+            commit_retries: DEFAULT_COMMIT_RETRIES,
+            commit_backoff: CommitBackoff::default(),
+            auto_compaction: None,
+            auto_index: None,
+            retry_stale_queries: false,
+            gpu_search: Arc::default(),
+            commit_hooks: Vec::new(),
+            query_admission: None,
+            scan_limits: ScanLimits::default(),
+            query_cache: None,
+            metrics: None,
+            commit_handler,
+            prewarmed: Arc::default(),
         })
     }
 
+    /// Use the embedding functions in `registry` to compute the table's embedding columns
+    pub(crate) fn with_embedding_registry(mut self, registry: Arc<dyn EmbeddingRegistry>) -> Self {
+        self.embedding_registry = Some(registry);
+        self
+    }
+
+    /// Admit the queries of the table with `admission`
+    pub(crate) fn with_query_admission(mut self, admission: Option<Arc<QueryAdmission>>) -> Self {
+        self.query_admission = admission;
+        self
+    }
+
+    /// Limit the read-ahead of the scans of the table with `limits`
+    pub(crate) fn with_scan_limits(mut self, limits: ScanLimits) -> Self {
+        self.scan_limits = limits;
+        self
+    }
+
+    /// Count the queries and writes of the table in `metrics`
+    pub(crate) fn with_metrics(mut self, metrics: Option<Arc<MetricsRegistry>>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Update the number of fragments of the table in the metrics
+    pub(crate) async fn report_fragments(&self) {
+        if let Some(metrics) = &self.metrics {
+            if let Ok(dataset) = self.dataset.get().await {
+                metrics.set_fragments(&self.name, dataset.count_fragments() as u64);
+            }
+        }
+    }
+
+    /// Count the query started at `start` in the metrics
+    fn observe_query(
+        &self,
+        start: Instant,
+        stream: Result<SendableRecordBatchStream>,
+    ) -> Result<SendableRecordBatchStream> {
+        match &self.metrics {
+            Some(metrics) => metrics.observe_query(start, stream),
+            None => stream,
+        }
+    }
+
+    /// Cache the results of the queries of the table in `cache`
+    pub(crate) fn with_query_cache(mut self, cache: Option<Arc<QueryCache>>) -> Self {
+        self.query_cache = cache;
+        self
+    }
+
+    /// The key of a query of the current version in the query cache, if the
+    /// queries are cached
+    async fn query_cache_key(
+        &self,
+        key: impl FnOnce() -> Option<QueryKey>,
+    ) -> Result<Option<QueryKey>> {
+        let Some(key) = self.query_cache.as_ref().and_then(|_| key()) else {
+            return Ok(None);
+        };
+        let version = self.dataset.get().await?.version().version;
+        Ok(Some(key.at_version(version)))
+    }
+
+    /// The cached results of the query identified by `key`, if any
+    fn cached_results(&self, key: Option<&QueryKey>) -> Result<Option<SendableRecordBatchStream>> {
+        let (Some(cache), Some(key)) = (&self.query_cache, key) else {
+            return Ok(None);
+        };
+        let stream = cache.get(key)?;
+        if let Some(metrics) = &self.metrics {
+            metrics.observe_query_cache(stream.is_some());
+        }
+        Ok(stream)
+    }
+
+    /// Cache the results of the query identified by `key`, which were read
+    /// from `version`
+    fn cache_results(
+        &self,
+        key: Option<QueryKey>,
+        version: u64,
+        stream: SendableRecordBatchStream,
+    ) -> SendableRecordBatchStream {
+        match (&self.query_cache, key) {
+            (Some(cache), Some(key)) => cache.fill(key.at_version(version), stream),
+            _ => stream,
+        }
+    }
+
+    /// Wait until a query of the table can run, see [`QueryAdmission::admit`]
+    /// The options of a scan of the table within the limits of the connection,
+    /// and the memory reserved for its read-ahead, see [`ScanLimits::apply`]
+    async fn limit_scan(
+        &self,
+        options: QueryExecutionOptions,
+    ) -> Result<(QueryExecutionOptions, Option<Reservation>)> {
+        if self.scan_limits.memory_budget.is_none() {
+            return Ok(self.scan_limits.apply(&Schema::empty(), options));
+        }
+        let schema = Schema::from(self.dataset.get().await?.schema());
+        Ok(self.scan_limits.apply(&schema, options))
+    }
+
+    async fn admit_query(&self) -> Result<Option<QueryPermit>> {
+        match &self.query_admission {
+            Some(admission) => Ok(Some(admission.admit().await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Enforce `quotas` when writing to the table
+    pub(crate) fn with_quotas(mut self, quotas: Option<Arc<Quotas>>) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    /// Start a write of `data`, see [`Quotas::start_write`]
+    fn start_quota_write(
+        &self,
+        data: Box<dyn RecordBatchReader + Send>,
+        overwrite: bool,
+    ) -> Result<(Box<dyn RecordBatchReader + Send>, Option<QuotaWrite>)> {
+        match &self.quotas {
+            Some(quotas) => quotas.start_write(&self.name, data, overwrite),
+            None => Ok((data, None)),
+        }
+    }
+
+    /// Finish a write started with [`Self::start_quota_write`]
+    fn finish_quota_write<T>(&self, write: Option<QuotaWrite>, result: Result<T>) -> Result<T> {
+        match (&self.quotas, write) {
+            (Some(quotas), Some(write)) => quotas.finish_write(write, result),
+            _ => result,
+        }
+    }
+
+    /// Measure the usage of the table after a write
+    pub(crate) async fn update_quota_usage(&self) {
+        let Some(quotas) = &self.quotas else {
+            return;
+        };
+        if !quotas.applies_to(&self.name) {
+            return;
+        }
+        let result = async {
+            let usage = quotas
+                .measure(&self.name, &*self.dataset.get().await?)
+                .await?;
+            quotas.set_usage(&self.name, usage)
+        };
+        // The data has been written, the usage is measured again after the next write
+        if let Err(e) = result.await {
+            log::warn!("Failed to measure the quota usage of {}: {}", self.name, e);
+        }
+    }
+
+    /// Fit `data` to the schema of the table with `policy`
+    async fn with_schema_policy(
+        &self,
+        data: Box<dyn RecordBatchReader + Send>,
+        policy: SchemaPolicy,
+    ) -> Result<Box<dyn RecordBatchReader + Send>> {
+        if policy == SchemaPolicy::default() {
+            return Ok(data);
+        }
+        let schema = self.schema().await?;
+        sanitize::fit_to_table(data, &schema, policy)
+    }
+
+    /// Add the embedding columns defined on the table to `data`
+    async fn with_embeddings(
+        &self,
+        data: Box<dyn RecordBatchReader + Send>,
+    ) -> Result<Box<dyn RecordBatchReader + Send>> {
+        let schema = Schema::from(self.dataset.get().await?.schema());
+        let definitions = embeddings::definitions_from_metadata(schema.metadata())?;
+        if definitions.is_empty() {
+            return Ok(data);
+        }
+        let registry = self.embedding_registry()?;
+        let embeddings = embeddings::resolve(registry, definitions)?;
+        Ok(Box::new(WithEmbeddings::try_new(data, embeddings)?))
+    }
+
+    fn embedding_registry(&self) -> Result<&dyn EmbeddingRegistry> {
+        self.embedding_registry
+            .as_deref()
+            .ok_or_else(|| Error::InvalidInput {
+                message: "The table has embedding functions but was not opened from a connection"
+                    .to_string(),
+            })
+    }
+
     fn get_table_name(uri: &str) -> Result<String> {
         let path = Path::new(uri);
         let name = path
@@ -885,6 +2282,82 @@ impl NativeTable {
             )
     }
 
+    /// Write the deletion files of the rows of `dataset` matching `predicate`,
+    /// without committing them
+    ///
+    /// Returns the delete to commit and the number of rows it deletes, counted
+    /// from the deletion files, or None if no row matches.
+    async fn stage_delete(dataset: &Dataset, predicate: &str) -> Result<Option<(Operation, u64)>> {
+        let mut updated_fragments = Vec::new();
+        let mut deleted_fragment_ids = Vec::new();
+        let mut num_deleted_rows = 0;
+        for fragment in dataset.get_fragments() {
+            let before = fragment.metadata().clone();
+            let num_rows = fragment.count_rows().await?;
+            match fragment.delete(predicate).await? {
+                None => {
+                    deleted_fragment_ids.push(before.id);
+                    num_deleted_rows += num_rows;
+                }
+                Some(after) if after.metadata().deletion_file != before.deletion_file => {
+                    num_deleted_rows += num_rows - after.count_rows().await?;
+                    updated_fragments.push(after.metadata().clone());
+                }
+                Some(_) => {}
+            }
+        }
+        if updated_fragments.is_empty() && deleted_fragment_ids.is_empty() {
+            return Ok(None);
+        }
+        let operation = Operation::Delete {
+            updated_fragments,
+            deleted_fragment_ids,
+            predicate: predicate.to_string(),
+        };
+        Ok(Some((operation, num_deleted_rows as u64)))
+    }
+
+    /// Parameters to write a hidden dataset stored in the table's directory
+    pub(crate) fn sidecar_write_params(&self, mode: WriteMode) -> Result<WriteParams> {
+        let params = WriteParams {
+            mode,
+            store_params: Some(ObjectStoreParams {
+                storage_options: Some(self.storage_options.clone()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        match self.store_wrapper.clone() {
+            Some(wrapper) => params.patch_with_store_wrapper(wrapper),
+            None => Ok(params),
+        }
+    }
+
+    /// Open a hidden dataset stored in the table's directory, if it exists
+    pub(crate) async fn open_sidecar(&self, uri: &str) -> Result<Option<Dataset>> {
+        let params = ReadParams {
+            store_options: Some(ObjectStoreParams {
+                storage_options: Some(self.storage_options.clone()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut params = match self.store_wrapper.clone() {
+            Some(wrapper) => params.patch_with_store_wrapper(wrapper)?,
+            None => params,
+        };
+        read_local_through_wrapper(uri, &mut params)?;
+        match DatasetBuilder::from_uri(uri)
+            .with_read_params(params)
+            .load()
+            .await
+        {
+            Ok(dataset) => Ok(Some(dataset)),
+            Err(lance::Error::DatasetNotFound { .. }) => Ok(None),
+            Err(source) => Err(Error::Lance { source }),
+        }
+    }
+
     fn supported_vector_data_type(dtype: &DataType) -> bool {
         match dtype {
             DataType::FixedSizeList(inner, _) => DataType::is_floating(inner.data_type()),
@@ -892,6 +2365,24 @@ impl NativeTable {
         }
     }
 
+    /// Binary vectors pack 8 dimensions into each byte and are searched with
+    /// [`DistanceType::Hamming`]
+    fn binary_vector_data_type(dtype: &DataType) -> bool {
+        match dtype {
+            DataType::FixedSizeList(inner, _) => inner.data_type() == &DataType::UInt8,
+            _ => false,
+        }
+    }
+
+    /// Multivectors hold several vectors per row and are searched with
+    /// [`DistanceType::MaxSim`]
+    fn multivector_data_type(dtype: &DataType) -> bool {
+        match dtype {
+            DataType::List(inner) => Self::supported_vector_data_type(inner.data_type()),
+            _ => false,
+        }
+    }
+
     /// Creates a new Table
     ///
     /// # Arguments
@@ -925,7 +2416,13 @@ impl NativeTable {
             .unwrap_or_default()
             .storage_options
             .unwrap_or_default();
+        let commit_handler = params.commit_handler.clone();
 
+        let mut read_params = ReadParams {
+            store_options: params.store_params.clone(),
+            commit_handler: commit_handler.clone(),
+            ..Default::default()
+        };
         let dataset = Dataset::write(batches, uri, Some(params))
             .await
             .map_err(|e| match e {
@@ -934,13 +2431,41 @@ impl NativeTable {
                 },
                 source => Error::Lance { source },
             })?;
+        // The written dataset reads local files directly, it is opened again
+        // to read them through the wrapper
+        let through_store = read_local_through_wrapper(uri, &mut read_params)?;
+        let dataset = if through_store {
+            DatasetBuilder::from_uri(uri)
+                .with_read_params(read_params)
+                .with_version(dataset.version().version)
+                .load()
+                .await?
+        } else {
+            dataset
+        };
         Ok(Self {
             name: name.to_string(),
             uri: uri.to_string(),
-            dataset: DatasetConsistencyWrapper::new_latest(dataset, read_consistency_interval),
+            dataset: DatasetConsistencyWrapper::new_latest(dataset, read_consistency_interval)
+                .read_through_store(through_store),
             store_wrapper: write_store_wrapper,
             storage_options,
             read_consistency_interval,
+            embedding_registry: None,
+            quotas: None,
+            commit_retries: DEFAULT_COMMIT_RETRIES,
+            commit_backoff: CommitBackoff::default(),
+            auto_compaction: None,
+            auto_index: None,
+            retry_stale_queries: false,
+            gpu_search: Arc::default(),
+            commit_hooks: Vec::new(),
+            query_admission: None,
+            scan_limits: ScanLimits::default(),
+            query_cache: None,
+            metrics: None,
+            commit_handler,
+            prewarmed: Arc::default(),
         })
     }
 
@@ -964,7 +2489,7 @@ impl NativeTable {
         .await
     }
 
-    async fn optimize_indices(&self, options: &OptimizeOptions) -> Result<()> {
+    async fn update_indices(&self, options: &OptimizeOptions) -> Result<()> {
         info!("LanceDB: optimizing indices: {:?}", options);
         self.dataset
             .get_mut()
@@ -1029,7 +2554,64 @@ impl NativeTable {
         Ok(metrics)
     }
 
-    // TODO: why are these individual methods and not some single "get_stats" method?
+    /// Run one of the steps of [`OptimizeAction::into_steps`]
+    async fn optimize_step(&self, action: OptimizeAction, stats: &mut OptimizeStats) -> Result<()> {
+        match action {
+            OptimizeAction::All => unreachable!("OptimizeAction::All is split into its steps"),
+            OptimizeAction::Compact {
+                options,
+                remap_options,
+            } => {
+                stats.compaction = Some(self.compact_files(options, remap_options).await?);
+                self.empty_trash().await?;
+            }
+            OptimizeAction::Prune {
+                older_than,
+                delete_unverified,
+            } => {
+                let older_than = self.protect_tagged_versions(older_than).await?;
+                stats.prune = Some(
+                    self.cleanup_old_versions(older_than, delete_unverified)
+                        .await?,
+                );
+            }
+            OptimizeAction::PurgeDeletions { threshold } => {
+                let options = purge_options(threshold)?;
+                stats.purge = Some(self.compact_files(options, None).await?);
+                self.empty_trash().await?;
+            }
+            OptimizeAction::Expire => {
+                stats.expired_rows = self.expire().await?;
+            }
+            OptimizeAction::FragmentStats => {
+                stats.fragment_stats = Some(self.collect_fragment_stats().await?);
+            }
+            OptimizeAction::Index(options) => {
+                self.update_indices(&options).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of files and bytes stored in the table's directory
+    async fn storage_usage(&self) -> Result<(usize, u64)> {
+        let params = ObjectStoreParams {
+            storage_options: Some(self.storage_options.clone()),
+            ..Default::default()
+        };
+        let (store, path) = ObjectStore::from_uri_and_params(&self.uri, &params).await?;
+        let objects = store
+            .inner
+            .list(Some(&path))
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok((
+            objects.len(),
+            objects.iter().map(|object| object.size as u64).sum(),
+        ))
+    }
+
+    // See also [`Table::stats`], which gathers these in a single call
     pub async fn count_fragments(&self) -> Result<usize> {
         Ok(self.dataset.get().await?.count_fragments())
     }
@@ -1104,9 +2686,12 @@ impl NativeTable {
                 ),
             });
         }
+        let distance_type: lance_linalg::distance::DistanceType = index.distance_type.try_into()?;
 
         let num_partitions = if let Some(n) = index.num_partitions {
             n
+        } else if let Some(centroids) = &index.centroids {
+            centroids.len() as u32
         } else {
             suggested_num_partitions(self.count_rows(None).await?)
         };
@@ -1122,15 +2707,64 @@ impl NativeTable {
                 }),
             }?
         };
+        let lance_idx_params = match (&index.centroids, index.accelerator) {
+            (Some(centroids), _) => {
+                let centroids = check_centroids(centroids, field, index.num_partitions)?;
+                let ivf = IvfBuildParams::try_with_centroids(centroids.len(), centroids)?;
+                let pq = PQBuildParams {
+                    num_sub_vectors: num_sub_vectors as usize,
+                    num_bits: 8,
+                    max_iters: index.max_iterations as usize,
+                    ..Default::default()
+                };
+                lance::index::vector::VectorIndexParams::with_ivf_pq_params(distance_type, ivf, pq)
+            }
+            (None, Accelerator::Cpu) => lance::index::vector::VectorIndexParams::ivf_pq(
+                num_partitions as usize,
+                /*num_bits=*/ 8,
+                num_sub_vectors as usize,
+                false,
+                distance_type,
+                index.max_iterations as usize,
+            ),
+            #[cfg(feature = "cuda")]
+            (None, Accelerator::Cuda(device)) => {
+                let (values, dim) = sample_training_vectors(
+                    &*self.dataset.get().await?,
+                    field.name(),
+                    num_partitions as usize,
+                    index.sample_rate,
+                )
+                .await?;
+                let centroids = crate::index::kmeans::train_centroids(
+                    values,
+                    dim,
+                    num_partitions as usize,
+                    index.max_iterations,
+                    index.distance_type,
+                    device,
+                )
+                .await?;
+                let ivf = IvfBuildParams::try_with_centroids(
+                    num_partitions as usize,
+                    Arc::new(centroids),
+                )?;
+                let pq = PQBuildParams {
+                    num_sub_vectors: num_sub_vectors as usize,
+                    num_bits: 8,
+                    max_iters: index.max_iterations as usize,
+                    ..Default::default()
+                };
+                lance::index::vector::VectorIndexParams::with_ivf_pq_params(distance_type, ivf, pq)
+            }
+            #[cfg(not(feature = "cuda"))]
+            (None, Accelerator::Cuda(_)) => {
+                return Err(Error::InvalidInput {
+                    message: "training an index on a GPU requires the `cuda` feature".to_string(),
+                })
+            }
+        };
         let mut dataset = self.dataset.get_mut().await?;
-        let lance_idx_params = lance::index::vector::VectorIndexParams::ivf_pq(
-            num_partitions as usize,
-            /*num_bits=*/ 8,
-            num_sub_vectors as usize,
-            false,
-            index.distance_type.into(),
-            index.max_iterations as usize,
-        );
         dataset
             .create_index(
                 &[field.name()],
@@ -1143,14 +2777,184 @@ impl NativeTable {
         Ok(())
     }
 
-    async fn create_auto_index(&self, field: &Field, opts: IndexBuilder) -> Result<()> {
-        if Self::supported_vector_data_type(field.data_type()) {
-            self.create_ivf_pq_index(IvfPqIndexBuilder::default(), field, opts.replace)
-                .await
-        } else if Self::supported_btree_data_type(field.data_type()) {
-            self.create_btree_index(field, opts).await
-        } else {
-            Err(Error::InvalidInput {
+    /// The IVF parameters of the IVF HNSW indices
+    async fn ivf_params(
+        &self,
+        name: &str,
+        field: &Field,
+        num_partitions: Option<u32>,
+        sample_rate: u32,
+        max_iterations: u32,
+        centroids: Option<&FixedSizeListArray>,
+    ) -> Result<IvfBuildParams> {
+        if !Self::supported_vector_data_type(field.data_type()) {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "An {} index cannot be created on the column `{}` which has data type {}",
+                    name,
+                    field.name(),
+                    field.data_type()
+                ),
+            });
+        }
+        if let Some(centroids) = centroids {
+            let centroids = check_centroids(centroids, field, num_partitions)?;
+            return Ok(IvfBuildParams::try_with_centroids(
+                centroids.len(),
+                centroids,
+            )?);
+        }
+        let num_partitions = if let Some(n) = num_partitions {
+            n
+        } else {
+            suggested_num_partitions(self.count_rows(None).await?)
+        };
+        let mut ivf = IvfBuildParams::new(num_partitions as usize);
+        ivf.sample_rate = sample_rate as usize;
+        ivf.max_iters = max_iterations as usize;
+        Ok(ivf)
+    }
+
+    /// The IVF and HNSW parameters shared by the IVF HNSW indices
+    #[allow(clippy::too_many_arguments)]
+    async fn ivf_hnsw_params(
+        &self,
+        name: &str,
+        field: &Field,
+        num_partitions: Option<u32>,
+        sample_rate: u32,
+        max_iterations: u32,
+        centroids: Option<&FixedSizeListArray>,
+        num_edges: u32,
+        ef_construction: u32,
+    ) -> Result<(IvfBuildParams, HnswBuildParams)> {
+        let ivf = self
+            .ivf_params(
+                name,
+                field,
+                num_partitions,
+                sample_rate,
+                max_iterations,
+                centroids,
+            )
+            .await?;
+        let hnsw = HnswBuildParams::default()
+            .num_edges(num_edges as usize)
+            .ef_construction(ef_construction as usize);
+        Ok((ivf, hnsw))
+    }
+
+    async fn create_ivf_hnsw_pq_index(
+        &self,
+        index: IvfHnswPqIndexBuilder,
+        field: &Field,
+        replace: bool,
+    ) -> Result<()> {
+        let (ivf, hnsw) = self
+            .ivf_hnsw_params(
+                "IVF HNSW PQ",
+                field,
+                index.num_partitions,
+                index.sample_rate,
+                index.max_iterations,
+                index.centroids.as_ref(),
+                index.num_edges,
+                index.ef_construction,
+            )
+            .await?;
+        let num_sub_vectors = match (index.num_sub_vectors, field.data_type()) {
+            (Some(n), _) => n,
+            (None, arrow_schema::DataType::FixedSizeList(_, n)) => {
+                suggested_num_sub_vectors(*n as u32)
+            }
+            (None, _) => unreachable!("the field is checked to be a vector column"),
+        };
+        let pq = PQBuildParams {
+            num_sub_vectors: num_sub_vectors as usize,
+            num_bits: 8,
+            max_iters: index.max_iterations as usize,
+            ..Default::default()
+        };
+        let lance_idx_params = lance::index::vector::VectorIndexParams::with_ivf_hnsw_pq_params(
+            index.distance_type.try_into()?,
+            ivf,
+            hnsw,
+            pq,
+        );
+        let mut dataset = self.dataset.get_mut().await?;
+        dataset
+            .create_index(
+                &[field.name()],
+                IndexType::Vector,
+                None,
+                &lance_idx_params,
+                replace,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn create_ivf_hnsw_sq_index(
+        &self,
+        index: IvfHnswSqIndexBuilder,
+        field: &Field,
+        replace: bool,
+    ) -> Result<()> {
+        let (ivf, hnsw) = self
+            .ivf_hnsw_params(
+                "IVF HNSW SQ",
+                field,
+                index.num_partitions,
+                index.sample_rate,
+                index.max_iterations,
+                index.centroids.as_ref(),
+                index.num_edges,
+                index.ef_construction,
+            )
+            .await?;
+        let lance_idx_params = lance::index::vector::VectorIndexParams::with_ivf_hnsw_sq_params(
+            index.distance_type.try_into()?,
+            ivf,
+            hnsw,
+            SQBuildParams::default(),
+        );
+        let mut dataset = self.dataset.get_mut().await?;
+        dataset
+            .create_index(
+                &[field.name()],
+                IndexType::Vector,
+                None,
+                &lance_idx_params,
+                replace,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn create_auto_index(&self, field: &Field, replace: bool) -> Result<()> {
+        if Self::supported_vector_data_type(field.data_type()) {
+            self.create_ivf_pq_index(IvfPqIndexBuilder::default(), field, replace)
+                .await
+        } else if Self::supported_btree_data_type(field.data_type()) {
+            self.create_btree_index(field, replace).await
+        } else if Self::binary_vector_data_type(field.data_type()) {
+            Err(Error::InvalidInput {
+                message: format!(
+                    "vector indices cannot be created on the binary vector column `{}`, \
+                     hamming distance searches are always flat searches",
+                    field.name()
+                ),
+            })
+        } else if Self::multivector_data_type(field.data_type()) {
+            Err(Error::InvalidInput {
+                message: format!(
+                    "vector indices cannot be created on the multivector column `{}`, \
+                     MaxSim searches are always flat searches",
+                    field.name()
+                ),
+            })
+        } else {
+            Err(Error::InvalidInput {
                 message: format!(
                     "there are no indices supported for the field `{}` with the data type {}",
                     field.name(),
@@ -1160,7 +2964,7 @@ impl NativeTable {
         }
     }
 
-    async fn create_btree_index(&self, field: &Field, opts: IndexBuilder) -> Result<()> {
+    async fn create_btree_index(&self, field: &Field, replace: bool) -> Result<()> {
         if !Self::supported_btree_data_type(field.data_type()) {
             return Err(Error::Schema {
                 message: format!(
@@ -1179,69 +2983,371 @@ impl NativeTable {
                 IndexType::Scalar,
                 None,
                 &lance_idx_params,
-                opts.replace,
+                replace,
             )
             .await?;
         Ok(())
     }
 
+    #[cfg(feature = "fts")]
+    async fn create_fts_index(
+        &self,
+        fts: &FtsIndexBuilder,
+        columns: &[String],
+        replace: bool,
+    ) -> Result<()> {
+        if columns.is_empty() {
+            return Err(Error::InvalidInput {
+                message: "A full text search index requires at least one column".to_string(),
+            });
+        }
+        let schema = self.schema().await?;
+        for column in columns {
+            let field = schema.field_with_name(column)?;
+            if !matches!(field.data_type(), DataType::Utf8 | DataType::LargeUtf8) {
+                return Err(Error::Schema {
+                    message: format!(
+                        "A full text search index cannot be created on the field `{}` which has data type {}",
+                        field.name(),
+                        field.data_type()
+                    ),
+                });
+            }
+        }
+
+        let dataset = self.dataset.get().await?;
+        let mut scanner = dataset.scan();
+        scanner.project(columns)?;
+        scanner.with_row_id();
+        let batches = scanner.try_into_stream().await?.into();
+        FtsIndex::create(&self.uri, columns, fts, replace, batches).await
+    }
+
+    #[cfg(feature = "fts")]
+    async fn fts_query(
+        &self,
+        query: &Query,
+        text: &str,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        if query.only_deleted {
+            return Err(Error::NotSupported {
+                message: "full text search cannot be used to search deleted rows".to_string(),
+            });
+        }
+        if !query.computed_columns.is_empty() {
+            return Err(Error::NotSupported {
+                message: "computed columns are not supported with full text search".to_string(),
+            });
+        }
+        let dataset = self.dataset.get().await?;
+        fts::execute_query(&self.uri, &dataset, query, text, options).await
+    }
+
+    /// Load the current version of the dataset with empty caches
+    ///
+    /// Used to satisfy [`Hint::NoCache`]
+    async fn load_uncached(&self, version: u64) -> Result<Dataset> {
+        let params = ReadParams {
+            index_cache_size: 0,
+            metadata_cache_size: 0,
+            store_options: Some(ObjectStoreParams {
+                storage_options: Some(self.storage_options.clone()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let params = match self.store_wrapper.clone() {
+            Some(wrapper) => params.patch_with_store_wrapper(wrapper)?,
+            None => params,
+        };
+        Ok(DatasetBuilder::from_uri(&self.uri)
+            .with_version(version)
+            .with_read_params(params)
+            .load()
+            .await?)
+    }
+
+    /// The vector column and query vector of a vector query
+    ///
+    /// A query that is not a vector (e.g. text) is embedded by the column's
+    /// embedding function.  Returns None if the query has no query vector.
+    pub(super) fn resolve_query_vector(
+        &self,
+        dataset: &Dataset,
+        query: &VectorQuery,
+    ) -> Result<Option<(String, Arc<dyn Array>)>> {
+        let mut column = query.column.clone();
+        let query_vector = match query.query_vector.as_ref() {
+            Some(input) if !input.data_type().is_floating() => {
+                let schema = Schema::from(dataset.schema());
+                let (dest_column, query_vector) = embeddings::embed_query(
+                    self.embedding_registry()?,
+                    schema.metadata(),
+                    column.as_deref(),
+                    input.clone(),
+                )?;
+                column = Some(dest_column);
+                query_vector
+            }
+            Some(query_vector) => query_vector.clone(),
+            None => return Ok(None),
+        };
+
+        let column = if let Some(col) = column {
+            col
+        } else {
+            // Infer a vector column with the same dimension of the query vector.
+            let arrow_schema = Schema::from(dataset.schema());
+            default_vector_column(&arrow_schema, Some(query_vector.len() as i32))?
+        };
+        let field = dataset.schema().field(&column).ok_or(Error::Schema {
+            message: format!("Column {} not found in dataset schema", column),
+        })?;
+        if let arrow_schema::DataType::FixedSizeList(f, dim) = field.data_type() {
+            if !f.data_type().is_floating() {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "The data type of the vector column '{}' is not a floating point type",
+                        column
+                    ),
+                });
+            }
+            if dim != query_vector.len() as i32 {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "The dimension of the query vector does not match with the dimension of the vector column '{}':
+                            query dim={}, expected vector dim={}",
+                        column,
+                        query_vector.len(),
+                        dim,
+                    ),
+                });
+            }
+        }
+        Ok(Some((column, query_vector)))
+    }
+
+    /// The index named by the [`Hint::UseIndex`] of `query`, if any
+    ///
+    /// Fails if the query can't use an index: with [`Hint::ForceFlatSearch`],
+    /// [`VectorQuery::bypass_vector_index`], or a search which is always flat.
+    fn index_hint(query: &VectorQuery) -> Result<Option<&str>> {
+        let hints = &query.base.hints;
+        let Some(name) = hints.iter().find_map(|hint| match hint {
+            Hint::UseIndex(name) => Some(name.as_str()),
+            _ => None,
+        }) else {
+            return Ok(None);
+        };
+        let conflict = if hints.contains(&Hint::ForceFlatSearch) {
+            "the hint ForceFlatSearch"
+        } else if !query.use_index {
+            "bypass_vector_index"
+        } else if Self::is_flat_query(query) {
+            "a search which is always flat"
+        } else {
+            return Ok(Some(name));
+        };
+        Err(Error::InvalidInput {
+            message: format!(
+                "the hint UseIndex({}) cannot be combined with {}",
+                name, conflict
+            ),
+        })
+    }
+
+    /// Check that the index named by a [`Hint::UseIndex`] exists and, if
+    /// `column` is provided, that it covers that column
+    async fn validate_index_hint(
+        dataset: &Dataset,
+        name: &str,
+        column: Option<&str>,
+    ) -> Result<()> {
+        let indices = dataset.load_indices().await?;
+        let Some(index) = indices.iter().find(|idx| idx.name == name) else {
+            return Err(Error::InvalidInput {
+                message: format!("Hint requested index '{}' which does not exist", name),
+            });
+        };
+        if let Some(column) = column {
+            let covers_column = index.fields.iter().any(|field_id| {
+                dataset
+                    .schema()
+                    .field_by_id(*field_id)
+                    .map(|field| field.name == column)
+                    .unwrap_or(false)
+            });
+            if !covers_column {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "Hint requested index '{}' but it is not an index on column '{}'",
+                        name, column
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// A copy of this table pinned to its current version, and that version
+    ///
+    /// All of the reads of the copy see the same version, even if new versions
+    /// are committed meanwhile, so a query made of several reads is consistent.
+    async fn pinned(&self) -> Result<(Self, u64)> {
+        let dataset = self.dataset.get().await?.clone();
+        let version = dataset.version().version;
+        let table = Self {
+            dataset: dataset::DatasetConsistencyWrapper::new_time_travel(dataset),
+            ..self.clone()
+        };
+        Ok((table, version))
+    }
+
+    async fn plain_query_impl(
+        &self,
+        query: &Query,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        #[cfg(feature = "fts")]
+        if let Some(text) = &query.full_text_search {
+            Self::validate_pagination(&query.clone().into_vector())?;
+            return self.fts_query(query, text, options).await;
+        }
+        if query.sample.is_some() {
+            return self.sampled_query(query, options).await;
+        }
+        if !query.order_by.is_empty() {
+            return self.ordered_query(query, options).await;
+        }
+        if query.after.is_some() {
+            return self.query_after(query, options).await;
+        }
+        Ok(self
+            .generic_query(&query.clone().into_vector(), options)
+            .await?
+            .into())
+    }
+
+    async fn vector_query_impl(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        if query.group_by.is_some() {
+            return self.grouped_search(query, options).await;
+        }
+        if query.refine_with_exact {
+            return self.rescored_search(query, options).await;
+        }
+        self.search(query, options).await
+    }
+
+    async fn search(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        #[cfg(feature = "fts")]
+        if query.base.full_text_search.is_some() {
+            return Err(Error::NotSupported {
+                message: "full text search cannot be combined with a vector search, \
+                          use VectorQuery::hybrid instead"
+                    .to_string(),
+            });
+        }
+        Self::validate_distance_range(query)?;
+        Self::validate_pagination(query)?;
+        Self::index_hint(query)?;
+        if Self::is_flat_query(query) {
+            return self.flat_query(query, options).await;
+        }
+        if query.use_index && !query.base.hints.contains(&Hint::ForceFlatSearch) {
+            match query.unindexed {
+                UnindexedPolicy::FlatScan => {}
+                UnindexedPolicy::Skip => {
+                    let stream = self.fast_search(query, options).await?;
+                    return Ok(Self::with_distance_range(stream, query));
+                }
+                UnindexedPolicy::Error => self.check_indexed(query).await?,
+            }
+        }
+        #[cfg(feature = "cuda")]
+        if let Some(stream) = self.gpu_query(query, options.clone()).await? {
+            return Ok(Self::with_distance_range(stream, query));
+        }
+        let stream = self.query_with_fallback(query, options).await?;
+        Ok(Self::with_distance_range(stream, query))
+    }
+
     async fn generic_query(
         &self,
         query: &VectorQuery,
         options: QueryExecutionOptions,
     ) -> Result<DatasetRecordBatchStream> {
+        let scanner = self.create_scanner(query, options).await?;
+        Ok(scanner.try_into_stream().await?)
+    }
+
+    async fn create_scanner(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<Scanner> {
+        let hints = &query.base.hints;
+        let force_flat = hints.contains(&Hint::ForceFlatSearch);
+        let use_index = Self::index_hint(query)?;
+
         let ds_ref = self.dataset.get().await?;
+        let uncached;
+        let ds_ref: &Dataset = if query.base.only_deleted {
+            uncached = self.trash().await?;
+            &uncached
+        } else if hints.contains(&Hint::NoCache) {
+            uncached = self.load_uncached(ds_ref.version().version).await?;
+            &uncached
+        } else {
+            &ds_ref
+        };
         let mut scanner: Scanner = ds_ref.scan();
 
-        if let Some(query_vector) = query.query_vector.as_ref() {
-            // If there is a vector query, default to limit=10 if unspecified
-            let column = if let Some(col) = query.column.as_ref() {
-                col.clone()
-            } else {
-                // Infer a vector column with the same dimension of the query vector.
-                let arrow_schema = Schema::from(ds_ref.schema());
-                default_vector_column(&arrow_schema, Some(query_vector.len() as i32))?
-            };
-            let field = ds_ref.schema().field(&column).ok_or(Error::Schema {
-                message: format!("Column {} not found in dataset schema", column),
-            })?;
-            if let arrow_schema::DataType::FixedSizeList(f, dim) = field.data_type() {
-                if !f.data_type().is_floating() {
-                    return Err(Error::InvalidInput {
-                        message: format!(
-                            "The data type of the vector column '{}' is not a floating point type",
-                            column
-                        ),
-                    });
+        let is_search =
+            if let Some((column, query_vector)) = self.resolve_query_vector(ds_ref, query)? {
+                if let Some(index_name) = use_index {
+                    Self::validate_index_hint(ds_ref, index_name, Some(&column)).await?;
                 }
-                if dim != query_vector.len() as i32 {
-                    return Err(Error::InvalidInput {
-                        message: format!(
-                            "The dimension of the query vector does not match with the dimension of the vector column '{}':
-                                query dim={}, expected vector dim={}",
-                            column,
-                            query_vector.len(),
-                            dim,
-                        ),
-                    });
+                // If there is a vector query, default to limit=10 if unspecified
+                let query_vector = query_vector.as_primitive::<Float32Type>();
+                scanner.nearest(
+                    &column,
+                    query_vector,
+                    query.base.limit.unwrap_or(DEFAULT_TOP_K),
+                )?;
+                true
+            } else {
+                if let Some(index_name) = use_index {
+                    Self::validate_index_hint(ds_ref, index_name, None).await?;
                 }
-            }
-            let query_vector = query_vector.as_primitive::<Float32Type>();
-            scanner.nearest(
-                &column,
-                query_vector,
-                query.base.limit.unwrap_or(DEFAULT_TOP_K),
-            )?;
-        } else {
-            // If there is no vector query, it's ok to not have a limit
-            scanner.limit(query.base.limit.map(|limit| limit as i64), None)?;
-        }
+                // If there is no vector query, it's ok to not have a limit
+                scanner.limit(
+                    query.base.limit.map(|limit| limit as i64),
+                    query.base.offset.map(|offset| offset as i64),
+                )?;
+                false
+            };
         scanner.nprobs(query.nprobes);
-        scanner.use_index(query.use_index);
+        // A UseIndex hint was checked not to conflict with either setting
+        scanner.use_index(use_index.is_some() || (query.use_index && !force_flat));
         scanner.prefilter(query.prefilter);
         scanner.batch_size(options.max_batch_length as usize);
+        if let Some(batch_readahead) = options.batch_readahead {
+            scanner.batch_readahead(batch_readahead.max(1));
+        }
+        if let Some(fragment_readahead) = options.fragment_readahead {
+            scanner.fragment_readahead(fragment_readahead.max(1));
+        }
 
-        match &query.base.select {
+        match query.base.resolved_select(&Schema::from(ds_ref.schema())) {
             Select::Columns(select) => {
                 scanner.project(select.as_slice())?;
             }
@@ -1251,18 +3357,34 @@ impl NativeTable {
             Select::All => { /* Do nothing */ }
         }
 
-        if let Some(filter) = &query.base.filter {
+        let filter = query.base.resolved_filter(&Schema::from(ds_ref.schema()))?;
+        if let Some(filter) = &filter {
             scanner.filter(filter)?;
+            // The fragment statistics describe the fragments of the table, not
+            // those of the trash
+            if !is_search && !query.base.only_deleted {
+                if let Some(fragments) = self.pruned_fragments(ds_ref, filter).await? {
+                    scanner.with_fragments(fragments);
+                }
+            }
+        }
+
+        if query.base.with_row_id {
+            scanner.with_row_id();
         }
 
         if let Some(refine_factor) = query.refine_factor {
             scanner.refine(refine_factor);
         }
 
+        if let Some(ef) = query.ef {
+            scanner.ef(ef);
+        }
+
         if let Some(distance_type) = query.distance_type {
-            scanner.distance_metric(distance_type.into());
+            scanner.distance_metric(distance_type.try_into()?);
         }
-        Ok(scanner.try_into_stream().await?)
+        Ok(scanner)
     }
 }
 
@@ -1295,124 +3417,645 @@ impl TableInternal for NativeTable {
         self.dataset.reload().await
     }
 
-    async fn restore(&self) -> Result<()> {
-        let version =
-            self.dataset
-                .time_travel_version()
-                .await
-                .ok_or_else(|| Error::InvalidInput {
-                    message: "you must run checkout before running restore".to_string(),
-                })?;
-        {
-            // Use get_mut_unchecked as restore is the only "write" operation that is allowed
-            // when the table is in time travel mode.
-            // Also, drop the guard after .restore because as_latest will need it
-            let mut dataset = self.dataset.get_mut_unchecked().await?;
-            debug_assert_eq!(dataset.version().version, version);
-            dataset.restore().await?;
-        }
-        self.dataset
-            .as_latest(self.read_consistency_interval)
-            .await?;
-        Ok(())
+    async fn list_versions(&self) -> Result<Vec<Version>> {
+        Ok(self.dataset.get().await?.versions().await?)
     }
 
-    async fn schema(&self) -> Result<SchemaRef> {
-        let lance_schema = self.dataset.get().await?.schema().clone();
-        Ok(Arc::new(Schema::from(&lance_schema)))
+    async fn list_tags(&self) -> Result<BTreeMap<String, u64>> {
+        self.list_tags_impl().await
     }
 
-    async fn count_rows(&self, filter: Option<String>) -> Result<usize> {
-        Ok(self.dataset.get().await?.count_rows(filter).await?)
+    async fn create_tag(&self, tag: &str, version: u64) -> Result<()> {
+        self.create_tag_impl(tag, version).await
     }
 
-    async fn add(
-        &self,
-        add: AddDataBuilder<NoData>,
-        data: Box<dyn RecordBatchReader + Send>,
-    ) -> Result<()> {
-        let mut lance_params = add.write_options.lance_write_params.unwrap_or(WriteParams {
-            mode: match add.mode {
-                AddDataMode::Append => WriteMode::Append,
-                AddDataMode::Overwrite => WriteMode::Overwrite,
-            },
-            ..Default::default()
-        });
+    async fn delete_tag(&self, tag: &str) -> Result<()> {
+        self.delete_tag_impl(tag).await
+    }
 
-        // Bring storage options from table
-        let storage_options = lance_params
-            .store_params
-            .get_or_insert(Default::default())
-            .storage_options
-            .get_or_insert(Default::default());
-        for (key, value) in self.storage_options.iter() {
-            if !storage_options.contains_key(key) {
-                storage_options.insert(key.clone(), value.clone());
-            }
-        }
+    async fn cluster(&self, params: ClusterBuilder) -> Result<FixedSizeListArray> {
+        self.cluster_impl(params).await
+    }
 
-        // patch the params if we have a write store wrapper
-        let lance_params = match self.store_wrapper.clone() {
-            Some(wrapper) => lance_params.patch_with_store_wrapper(wrapper)?,
-            None => lance_params,
+    async fn ivf_centroids(&self, column: &str) -> Result<FixedSizeListArray> {
+        let dataset = self.dataset.get().await?.clone();
+        let Some(DataType::FixedSizeList(_, dim)) =
+            dataset.schema().field(column).map(|f| f.data_type())
+        else {
+            return Err(Error::Schema {
+                message: format!("the column {} is not a vector column", column),
+            });
+        };
+        let Some(index) = self.list_indices().await?.into_iter().find(|index| {
+            index.index_type == crate::index::IndexType::IvfPq && index.columns == [column]
+        }) else {
+            return Err(Error::IndexNotFound {
+                name: format!("{}_idx", column),
+            });
+        };
+        let stats = dataset.index_statistics(&index.name).await?;
+        let stats: IvfIndexStatistics = whatever!(
+            serde_json::from_str(&stats),
+            "error deserializing index statistics {stats}",
+        );
+        // The segments added by optimizing the index share the centroids of the first
+        let Some(centroids) = stats
+            .indices
+            .into_iter()
+            .map(|segment| segment.centroids)
+            .find(|centroids| !centroids.is_empty())
+        else {
+            return Err(Error::NotSupported {
+                message: format!("the index {} does not describe its centroids", index.name),
+            });
         };
+        let values = Float32Array::from_iter_values(centroids.into_iter().flatten());
+        Ok(FixedSizeListArray::try_new(
+            Arc::new(Field::new("item", DataType::Float32, true)),
+            dim,
+            Arc::new(values),
+            None,
+        )?)
+    }
 
-        self.dataset.ensure_mutable().await?;
+    async fn split(&self, params: SplitBuilder) -> Result<Vec<Table>> {
+        self.split_impl(params).await
+    }
 
-        let dataset = Dataset::write(data, &self.uri, Some(lance_params)).await?;
-        self.dataset.set_latest(dataset).await;
-        Ok(())
+    async fn prewarm(&self, params: PrewarmBuilder) -> Result<()> {
+        self.prewarm_impl(params).await
     }
 
-    async fn create_index(&self, opts: IndexBuilder) -> Result<()> {
-        if opts.columns.len() != 1 {
-            return Err(Error::Schema {
-                message: "Multi-column (composite) indices are not yet supported".to_string(),
-            });
-        }
-        let schema = self.schema().await?;
+    async fn cache_residency(&self) -> Result<CacheResidency> {
+        self.cache_residency_impl().await
+    }
 
-        let field = schema.field_with_name(&opts.columns[0])?;
+    async fn changes(&self, params: ChangesBuilder) -> Result<ChangeStream> {
+        self.changes_impl(params).await
+    }
 
-        match opts.index {
-            Index::Auto => self.create_auto_index(field, opts).await,
-            Index::BTree(_) => self.create_btree_index(field, opts).await,
-            Index::IvfPq(ivf_pq) => self.create_ivf_pq_index(ivf_pq, field, opts.replace).await,
-        }
+    async fn gpu_search(&self, column: &str, accelerator: Accelerator) -> Result<()> {
+        self.gpu_search_impl(column, accelerator).await
     }
 
-    async fn update(&self, update: UpdateBuilder) -> Result<()> {
+    async fn migrate_vector_dim(&self, params: MigrateVectorDimBuilder) -> Result<()> {
+        self.run_with_hooks("migrate_vector_dim", self.migrate_vector_dim_impl(params))
+            .await
+    }
+
+    async fn pack(&self, path: &Path) -> Result<PackageInfo> {
+        self.pack_impl(path).await
+    }
+
+    async fn export_snapshot(&self, target_uri: &str) -> Result<SnapshotInfo> {
+        self.export_snapshot_impl(target_uri).await
+    }
+
+    async fn dry_run(&self, operation: DryRunOperation) -> Result<DryRunReport> {
+        self.dry_run_impl(operation).await
+    }
+
+    async fn column_stats(&self, column: &str) -> Result<ColumnStatistics> {
+        self.column_stats_impl(column).await
+    }
+
+    async fn stats(&self) -> Result<TableStatistics> {
+        self.stats_impl().await
+    }
+
+    async fn take(&self, row_ids: &[u64], select: Select) -> Result<RecordBatch> {
+        self.take_rows(row_ids, select).await
+    }
+
+    async fn verify_checksums(&self) -> Result<ChecksumReport> {
+        let params = ObjectStoreParams {
+            storage_options: Some(self.storage_options.clone()),
+            ..Default::default()
+        };
+        let (store, path) = ObjectStore::from_uri_and_params(&self.uri, &params).await?;
+        Ok(checksum::verify_dir(store.inner.as_ref(), &path).await?)
+    }
+
+    async fn rebuild_missing_indices(&self) -> Result<Vec<String>> {
+        self.run_with_hooks("rebuild_missing_indices", self.rebuild_damaged_indices())
+            .await
+    }
+
+    async fn optimize_indices(&self, options: OptimizeIndexOptions) -> Result<Vec<String>> {
+        self.run_with_hooks("optimize_indices", self.optimize_indices_impl(&options))
+            .await
+    }
+
+    async fn row_history(&self, key_filter: &str) -> Result<Vec<RowVersion>> {
+        let filter = Filter::parse(key_filter)?.to_sql()?;
         let dataset = self.dataset.get().await?.clone();
-        let mut builder = LanceUpdateBuilder::new(Arc::new(dataset));
-        if let Some(predicate) = update.filter {
-            builder = builder.update_where(&predicate)?;
+        let mut history: Vec<RowVersion> = Vec::new();
+        for version in dataset.versions().await? {
+            let dataset = dataset.checkout_version(version.version).await?;
+            let mut scanner = dataset.scan();
+            scanner.filter(&filter)?;
+            let batches = scanner
+                .try_into_stream()
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?;
+            let schema = Arc::new(Schema::from(dataset.schema()));
+            let rows = arrow::compute::concat_batches(&schema, &batches)?;
+            let changed = match history.last() {
+                Some(last) => last.rows != rows,
+                None => rows.num_rows() > 0,
+            };
+            if changed {
+                history.push(RowVersion {
+                    version: version.version,
+                    timestamp: version.timestamp,
+                    rows,
+                });
+            }
         }
+        Ok(history)
+    }
+
+    async fn restore(&self) -> Result<()> {
+        self.run_with_hooks("restore", async move {
+            let version =
+                self.dataset
+                    .time_travel_version()
+                    .await
+                    .ok_or_else(|| Error::InvalidInput {
+                        message: "you must run checkout before running restore".to_string(),
+                    })?;
+            {
+                // Use get_mut_unchecked as restore is the only "write" operation that is allowed
+                // when the table is in time travel mode.
+                // Also, drop the guard after .restore because as_latest will need it
+                let mut dataset = self.dataset.get_mut_unchecked().await?;
+                debug_assert_eq!(dataset.version().version, version);
+                dataset.restore().await?;
+            }
+            self.dataset
+                .as_latest(self.read_consistency_interval)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn schema(&self) -> Result<SchemaRef> {
+        let lance_schema = self.dataset.get().await?.schema().clone();
+        Ok(Arc::new(Schema::from(&lance_schema)))
+    }
 
-        for (column, value) in update.columns {
-            builder = builder.set(column, &value)?;
+    async fn count_rows(&self, filter: Option<String>) -> Result<usize> {
+        let filter = filter
+            .map(|filter| Filter::parse(filter)?.to_sql())
+            .transpose()?;
+        let dataset = self.dataset.get().await?.clone();
+        if let Some(filter) = &filter {
+            if let Some(fragments) = self.pruned_fragments(&dataset, filter).await? {
+                let mut scanner = dataset.scan();
+                scanner.with_fragments(fragments);
+                scanner.filter(filter)?;
+                return Ok(scanner.count_rows().await? as usize);
+            }
         }
+        Ok(dataset.count_rows(filter).await?)
+    }
 
-        let operation = builder.build()?;
-        let ds = operation.execute().await?;
-        self.dataset.set_latest(ds.as_ref().clone()).await;
-        Ok(())
+    async fn add(
+        &self,
+        add: AddDataBuilder<NoData>,
+        data: Box<dyn RecordBatchReader + Send>,
+    ) -> Result<()> {
+        self.run_with_hooks("add", async move {
+            let mut lance_params = add.write_options.lance_write_params.unwrap_or(WriteParams {
+                mode: match add.mode {
+                    // The keys were checked by AddDataBuilder::execute
+                    AddDataMode::Append
+                    | AddDataMode::ErrorOnConflict
+                    | AddDataMode::BulkIngest(_) => WriteMode::Append,
+                    AddDataMode::Overwrite => WriteMode::Overwrite,
+                    AddDataMode::OverwriteOnConflict => {
+                        return Err(Error::InvalidInput {
+                            message: "overwriting rows on conflict requires a merge insert"
+                                .to_string(),
+                        })
+                    }
+                },
+                ..Default::default()
+            });
+
+            // Bring storage options from table
+            let storage_options = lance_params
+                .store_params
+                .get_or_insert(Default::default())
+                .storage_options
+                .get_or_insert(Default::default());
+            for (key, value) in self.storage_options.iter() {
+                if !storage_options.contains_key(key) {
+                    storage_options.insert(key.clone(), value.clone());
+                }
+            }
+
+            if lance_params.commit_handler.is_none() {
+                lance_params.commit_handler = self.commit_handler.clone();
+            }
+
+            // patch the params if we have a write store wrapper
+            let lance_params = match self.store_wrapper.clone() {
+                Some(wrapper) => lance_params.patch_with_store_wrapper(wrapper)?,
+                None => lance_params,
+            };
+
+            self.dataset.ensure_mutable().await?;
+            if let Some(key) = &add.idempotency_key {
+                if self.applied_version(key).await?.is_some() {
+                    return Ok(());
+                }
+            }
+
+            let mode = lance_params.mode;
+            let schema_policy = add.schema_policy;
+            let partition_by = match add.partition_by {
+                Some(column) => Some(column),
+                None => partition::partition_column(&*self.schema().await?),
+            };
+            let (version, added_stats, rows_written) = if let (Some(column), WriteMode::Append) =
+                (&partition_by, mode)
+            {
+                self.partitioned_add(data, column, schema_policy, lance_params)
+                    .await?
+            } else if let AddDataMode::BulkIngest(options) = add.mode {
+                self.bulk_ingest(data, options, schema_policy, lance_params)
+                    .await?
+            } else {
+                // Retrying needs the input again, so it is buffered when retries are asked for
+                let retries = add.write_options.max_commit_retries.unwrap_or(0);
+                let (buffered, mut input) = if retries > 0 {
+                    let schema = data.schema();
+                    let batches = data.collect::<std::result::Result<Vec<_>, _>>()?;
+                    (Some((schema, batches)), None)
+                } else {
+                    (None, Some(data))
+                };
+                self.retry_on_conflict_with(
+                    "add",
+                    Some(retries),
+                    add.write_options.commit_backoff,
+                    || {
+                        let data: Box<dyn RecordBatchReader + Send> = match &buffered {
+                            Some((schema, batches)) => Box::new(RecordBatchIterator::new(
+                                batches.clone().into_iter().map(Ok),
+                                schema.clone(),
+                            )),
+                            None => input.take().expect("an add without retries runs once"),
+                        };
+                        let lance_params = lance_params.clone();
+                        async move {
+                            let data = self.with_embeddings(data).await?;
+                            // An overwrite replaces the schema, so the vectors may change
+                            let (data, vector_check) = if matches!(mode, WriteMode::Overwrite) {
+                                (data, VectorCheck::default())
+                            } else {
+                                let data = self.with_schema_policy(data, schema_policy).await?;
+                                validate::check_vectors(&*self.schema().await?, data)?
+                            };
+                            let (data, rows_written) = metrics::count_rows(data);
+                            let (data, added_stats) = self.track_stats(data).await?;
+                            let (data, quota_write) =
+                                self.start_quota_write(data, matches!(mode, WriteMode::Overwrite))?;
+                            let dataset = Dataset::write(data, &self.uri, Some(lance_params))
+                                .await
+                                .map_err(Error::from);
+                            let dataset = vector_check.finish(dataset);
+                            let dataset = self.finish_quota_write(quota_write, dataset)?;
+                            let version = dataset.version().version;
+                            self.dataset.set_latest(dataset).await?;
+                            Ok((version, added_stats, rows_written))
+                        }
+                    },
+                )
+                .await?
+            };
+            if let Some(key) = &add.idempotency_key {
+                self.record_idempotency_key(key, version).await?;
+            }
+            if let Some(metrics) = &self.metrics {
+                metrics.add_rows_written(rows_written.load(Ordering::Relaxed));
+            }
+            if let Err(e) = self.auto_compact().await {
+                // The data has been added, the next add compacts again
+                log::warn!(
+                    "Failed to compact the small fragments of {}: {}",
+                    self.name,
+                    e
+                );
+            }
+            if let Err(e) = self.auto_optimize_indices().await {
+                // The rows are searched without the index until the next optimization
+                log::warn!("Failed to optimize the indices of {}: {}", self.name, e);
+            }
+            self.update_quota_usage().await;
+            if let Some(added_stats) = added_stats {
+                // The data has been added, stale statistics are recomputed when requested
+                if let Err(e) = self.update_stats(added_stats, mode).await {
+                    log::warn!(
+                        "Failed to update the column statistics of {}: {}",
+                        self.name,
+                        e
+                    );
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn create_index(&self, opts: IndexBuilder) -> Result<()> {
+        self.run_with_hooks("create_index", async move {
+            #[cfg(feature = "fts")]
+            if let Index::Fts(fts) = &opts.index {
+                return self
+                    .create_fts_index(fts, &opts.columns, opts.replace)
+                    .await;
+            }
+            if opts.columns.len() != 1 {
+                return Err(Error::Schema {
+                    message: "Multi-column (composite) indices are not yet supported".to_string(),
+                });
+            }
+            let schema = self.schema().await?;
+
+            let field = schema.field_with_name(&opts.columns[0])?;
+
+            match opts.index {
+                Index::Auto => self.create_auto_index(field, opts.replace).await,
+                Index::BTree(_) => self.create_btree_index(field, opts.replace).await,
+                Index::IvfPq(ivf_pq) => self.create_ivf_pq_index(ivf_pq, field, opts.replace).await,
+                Index::IvfHnswPq(ivf_hnsw_pq) => {
+                    self.create_ivf_hnsw_pq_index(ivf_hnsw_pq, field, opts.replace)
+                        .await
+                }
+                Index::IvfHnswSq(ivf_hnsw_sq) => {
+                    self.create_ivf_hnsw_sq_index(ivf_hnsw_sq, field, opts.replace)
+                        .await
+                }
+                #[cfg(feature = "fts")]
+                Index::Fts(_) => unreachable!("full text search indices are created above"),
+            }
+        })
+        .await
+    }
+
+    async fn update(&self, update: UpdateBuilder) -> Result<()> {
+        self.run_with_hooks("update", async move {
+            let predicate = update.predicate()?;
+            let schema = self.schema().await?;
+            if let Some((column, _)) = update
+                .columns
+                .iter()
+                .find(|(column, _)| schema.field_with_name(column).is_err())
+            {
+                return Err(Error::Schema {
+                    message: format!("The column {} does not exist", column),
+                });
+            }
+            let columns = &update.columns;
+            let predicate = predicate.as_deref();
+            let options = &update.write_options;
+            self.retry_on_conflict_with(
+                "update",
+                options.max_commit_retries,
+                options.commit_backoff,
+                move || async move {
+                    let dataset = self.dataset.get().await?.clone();
+                    let mut builder = LanceUpdateBuilder::new(Arc::new(dataset));
+                    if let Some(predicate) = predicate {
+                        builder = builder.update_where(predicate)?;
+                    }
+
+                    for (column, value) in columns {
+                        builder = builder.set(column, value)?;
+                    }
+
+                    let operation = builder.build()?;
+                    let ds = operation.execute().await?;
+                    self.dataset.set_latest(ds.as_ref().clone()).await?;
+                    Ok(())
+                },
+            )
+            .await
+        })
+        .await
     }
 
     async fn plain_query(
         &self,
         query: &Query,
         options: QueryExecutionOptions,
-    ) -> Result<DatasetRecordBatchStream> {
-        self.generic_query(&query.clone().into_vector(), options)
-            .await
+    ) -> Result<SendableRecordBatchStream> {
+        let start = Instant::now();
+        let key = self
+            .query_cache_key(|| QueryKey::plain(&self.uri, query, &options))
+            .await?;
+        if let Some(stream) = self.cached_results(key.as_ref())? {
+            return self.observe_query(start, Ok(stream));
+        }
+        // The row addresses are read from the row ids
+        let with_row_address = query.with_row_address;
+        let with_row_id = query.with_row_id;
+        let mut with_row_ids;
+        let query = if with_row_address && !with_row_id {
+            with_row_ids = query.clone();
+            with_row_ids.with_row_id = true;
+            &with_row_ids
+        } else {
+            query
+        };
+        let span = OperationSpan::query(&self.name, "plain");
+        let stream = span
+            .clone()
+            .run_query(async {
+                let permit = self.admit_query().await?;
+                let (options, reservation) = self.limit_scan(options.clone()).await?;
+                let (stream, version) = self
+                    .query_pinned(|table| {
+                        let options = options.clone();
+                        async move { table.plain_query_impl(query, options).await }
+                    })
+                    .await?;
+                span.record_version(version);
+                let stream = if with_row_address {
+                    Self::with_row_address(stream, with_row_id)
+                } else {
+                    stream
+                };
+                let stream: SendableRecordBatchStream =
+                    Box::pin(VersionedRecordBatchStream::new(stream, version));
+                let stream = self.cache_results(key, version, stream);
+                let stream = match reservation {
+                    Some(reservation) => reservation.hold(stream),
+                    None => stream,
+                };
+                Ok(match permit {
+                    Some(permit) => permit.hold(stream),
+                    None => stream,
+                })
+            })
+            .await;
+        self.observe_query(start, stream)
     }
 
     async fn vector_query(
         &self,
         query: &VectorQuery,
         options: QueryExecutionOptions,
-    ) -> Result<DatasetRecordBatchStream> {
-        self.generic_query(query, options).await
+    ) -> Result<SendableRecordBatchStream> {
+        let start = Instant::now();
+        let key = self
+            .query_cache_key(|| QueryKey::vector(&self.uri, query, &options))
+            .await?;
+        if let Some(stream) = self.cached_results(key.as_ref())? {
+            return self.observe_query(start, Ok(stream));
+        }
+        let with_row_address = query.base.with_row_address;
+        let with_row_id = query.base.with_row_id;
+        let mut with_row_ids;
+        let query = if with_row_address && !with_row_id {
+            with_row_ids = query.clone();
+            with_row_ids.base.with_row_id = true;
+            &with_row_ids
+        } else {
+            query
+        };
+        let span = OperationSpan::query(&self.name, "vector");
+        let stream = span
+            .clone()
+            .run_query(async {
+                let permit = self.admit_query().await?;
+                let (options, reservation) = self.limit_scan(options.clone()).await?;
+                let (stream, version) = self
+                    .query_pinned(|table| {
+                        let options = options.clone();
+                        async move {
+                            if query.batch_vectors.is_empty() {
+                                table.vector_query_impl(query, options).await
+                            } else {
+                                // The searches of a batch share the pinned version and the permit
+                                batch::execute_batch(query, |query| {
+                                    let table = table.clone();
+                                    let options = options.clone();
+                                    async move { table.vector_query_impl(&query, options).await }
+                                })
+                                .await
+                            }
+                        }
+                    })
+                    .await?;
+                span.record_version(version);
+                let stream = if with_row_address {
+                    Self::with_row_address(stream, with_row_id)
+                } else {
+                    stream
+                };
+                let stream: SendableRecordBatchStream =
+                    Box::pin(VersionedRecordBatchStream::new(stream, version));
+                let stream = self.cache_results(key, version, stream);
+                let stream = match reservation {
+                    Some(reservation) => reservation.hold(stream),
+                    None => stream,
+                };
+                Ok(match permit {
+                    Some(permit) => permit.hold(stream),
+                    None => stream,
+                })
+            })
+            .await;
+        self.observe_query(start, stream)
+    }
+
+    async fn explain_plan(&self, query: &VectorQuery, verbose: bool) -> Result<String> {
+        #[cfg(feature = "fts")]
+        if query.base.full_text_search.is_some() {
+            return Err(Error::NotSupported {
+                message: "full text searches cannot be explained".to_string(),
+            });
+        }
+        let scanner = self
+            .create_scanner(query, QueryExecutionOptions::default())
+            .await?;
+        Ok(scanner.explain_plan(verbose).await?)
+    }
+
+    async fn scan_stats(&self, query: &VectorQuery) -> Result<Option<ScanStats>> {
+        #[cfg(feature = "fts")]
+        if query.base.full_text_search.is_some() {
+            return Ok(None);
+        }
+        let num_rows = self.count_rows(None).await? as u64;
+        let dataset = self.dataset.get().await?.clone();
+        let Some((column, _)) = self.resolve_query_vector(&dataset, query)? else {
+            // Without a filter a scan stops once it has read `limit` rows
+            let rows_scanned = match query.base.limit {
+                Some(limit) if query.base.filter.is_none() => num_rows.min(limit as u64),
+                _ => num_rows,
+            };
+            return Ok(Some(ScanStats {
+                rows_scanned,
+                index_partitions_probed: 0,
+            }));
+        };
+        let searches = query.batch_vectors.len().max(1) as u64;
+        let flat = ScanStats {
+            rows_scanned: num_rows * searches,
+            index_partitions_probed: 0,
+        };
+        let hints = &query.base.hints;
+        if !query.use_index || hints.contains(&Hint::ForceFlatSearch) || Self::is_flat_query(query)
+        {
+            return Ok(Some(flat));
+        }
+        let index_hint = hints.iter().find_map(|hint| match hint {
+            Hint::UseIndex(name) => Some(name.as_str()),
+            _ => None,
+        });
+        let Some(index) = self.list_indices().await?.into_iter().find(|index| {
+            index.index_type == crate::index::IndexType::IvfPq
+                && index.columns == [column.as_str()]
+                && index_hint.map_or(true, |name| name == index.name)
+        }) else {
+            return Ok(Some(flat));
+        };
+
+        let stats = dataset.index_statistics(&index.name).await?;
+        let stats: IvfIndexStatistics = whatever!(
+            serde_json::from_str(&stats),
+            "error deserializing index statistics {stats}",
+        );
+        let mut index_partitions_probed = 0;
+        let mut indexed_rows_scanned = 0.0;
+        for segment in &stats.indices {
+            // The statistics of older indices may not describe the partitions
+            let Some(num_partitions) = segment.num_partitions.filter(|n| *n > 0) else {
+                return Ok(None);
+            };
+            let probed = num_partitions.min(query.nprobes as u64);
+            index_partitions_probed += probed;
+            indexed_rows_scanned += probed as f64 / num_partitions as f64;
+        }
+        // Assume the rows are spread evenly over the segments and their partitions
+        if !stats.indices.is_empty() {
+            indexed_rows_scanned *= stats.num_indexed_rows as f64 / stats.indices.len() as f64;
+        }
+        let unindexed_rows_scanned = if query.unindexed == UnindexedPolicy::Skip {
+            0
+        } else {
+            stats.num_unindexed_rows
+        };
+        Ok(Some(ScanStats {
+            rows_scanned: (indexed_rows_scanned.round() as u64 + unindexed_rows_scanned) * searches,
+            index_partitions_probed: index_partitions_probed * searches,
+        }))
     }
 
     async fn merge_insert(
@@ -1420,87 +4063,139 @@ impl TableInternal for NativeTable {
         params: MergeInsertBuilder,
         new_data: Box<dyn RecordBatchReader + Send>,
     ) -> Result<()> {
-        let dataset = Arc::new(self.dataset.get().await?.clone());
-        let mut builder = LanceMergeInsertBuilder::try_new(dataset.clone(), params.on)?;
-        match (
-            params.when_matched_update_all,
-            params.when_matched_update_all_filt,
-        ) {
-            (false, _) => builder.when_matched(WhenMatched::DoNothing),
-            (true, None) => builder.when_matched(WhenMatched::UpdateAll),
-            (true, Some(filt)) => builder.when_matched(WhenMatched::update_if(&dataset, &filt)?),
-        };
-        if params.when_not_matched_insert_all {
-            builder.when_not_matched(lance::dataset::WhenNotMatched::InsertAll);
-        } else {
-            builder.when_not_matched(lance::dataset::WhenNotMatched::DoNothing);
-        }
-        if params.when_not_matched_by_source_delete {
-            let behavior = if let Some(filter) = params.when_not_matched_by_source_delete_filt {
-                WhenNotMatchedBySource::delete_if(dataset.as_ref(), &filter)?
-            } else {
-                WhenNotMatchedBySource::Delete
+        self.run_with_hooks("merge_insert", async move {
+            if let Some(key) = &params.idempotency_key {
+                if self.applied_version(key).await?.is_some() {
+                    return Ok(());
+                }
+            }
+            let dataset = Arc::new(self.dataset.get().await?.clone());
+            let mut builder = LanceMergeInsertBuilder::try_new(dataset.clone(), params.on)?;
+            match (
+                params.when_matched_update_all,
+                params.when_matched_update_all_filt,
+            ) {
+                (false, _) => builder.when_matched(WhenMatched::DoNothing),
+                (true, None) => builder.when_matched(WhenMatched::UpdateAll),
+                (true, Some(filt)) => {
+                    builder.when_matched(WhenMatched::update_if(&dataset, &filt)?)
+                }
             };
-            builder.when_not_matched_by_source(behavior);
-        } else {
-            builder.when_not_matched_by_source(WhenNotMatchedBySource::Keep);
-        }
-        let job = builder.try_build()?;
-        let new_dataset = job.execute_reader(new_data).await?;
-        self.dataset.set_latest(new_dataset.as_ref().clone()).await;
-        Ok(())
+            if params.when_not_matched_insert_all {
+                builder.when_not_matched(lance::dataset::WhenNotMatched::InsertAll);
+            } else {
+                builder.when_not_matched(lance::dataset::WhenNotMatched::DoNothing);
+            }
+            if params.when_not_matched_by_source_delete {
+                let behavior = if let Some(filter) = params.when_not_matched_by_source_delete_filt {
+                    WhenNotMatchedBySource::delete_if(dataset.as_ref(), &filter)?
+                } else {
+                    WhenNotMatchedBySource::Delete
+                };
+                builder.when_not_matched_by_source(behavior);
+            } else {
+                builder.when_not_matched_by_source(WhenNotMatchedBySource::Keep);
+            }
+            let job = builder.try_build()?;
+            let new_data = self.with_embeddings(new_data).await?;
+            let new_data = self
+                .with_schema_policy(new_data, params.schema_policy)
+                .await?;
+            // Updated rows count towards the quotas as if they were new rows
+            let (new_data, quota_write) = self.start_quota_write(new_data, false)?;
+            let new_dataset = job
+                .execute_reader(new_data)
+                .await
+                .map_err(|e| self.commit_conflict("merge_insert", 1, e.into()));
+            let new_dataset = self.finish_quota_write(quota_write, new_dataset)?;
+            self.dataset
+                .set_latest(new_dataset.as_ref().clone())
+                .await?;
+            if let Some(key) = &params.idempotency_key {
+                self.record_idempotency_key(key, new_dataset.version().version)
+                    .await?;
+            }
+            self.update_quota_usage().await;
+            Ok(())
+        })
+        .await
     }
 
     /// Delete rows from the table
-    async fn delete(&self, predicate: &str) -> Result<()> {
-        self.dataset.get_mut().await?.delete(predicate).await?;
-        Ok(())
+    async fn delete(&self, predicate: &str) -> Result<DeleteResult> {
+        self.run_with_hooks("delete", async move {
+            let filter = Filter::parse(predicate)?;
+            let predicate = filter.to_sql()?;
+            if self.soft_delete_enabled().await? {
+                self.move_to_trash(&predicate).await?;
+            } else if let Some(result) = self.delete_partitions(&filter, &predicate).await? {
+                // All of the deleted rows were in fragments of the deleted keys
+                return Ok(result);
+            }
+            let predicate = predicate.as_str();
+            self.retry_on_conflict("delete", move || async move {
+                self.dataset.ensure_mutable().await?;
+                let dataset = self.dataset.get().await?.clone();
+                let read_version = dataset.version().version;
+                // A conflict reloads the dataset and deletes again
+                let Some((operation, num_deleted_rows)) =
+                    Self::stage_delete(&dataset, predicate).await?
+                else {
+                    return Ok(DeleteResult {
+                        num_deleted_rows: 0,
+                        version: read_version,
+                    });
+                };
+                let params = self.sidecar_write_params(WriteMode::Append)?;
+                let dataset = Dataset::commit(
+                    &self.uri,
+                    operation,
+                    Some(read_version),
+                    params.store_params,
+                    self.commit_handler.clone(),
+                )
+                .await?;
+                let version = dataset.version().version;
+                self.dataset.set_latest(dataset).await?;
+                Ok(DeleteResult {
+                    num_deleted_rows,
+                    version,
+                })
+            })
+            .await
+        })
+        .await
+    }
+
+    async fn undelete(&self, filter: &str) -> Result<()> {
+        self.run_with_hooks("undelete", async move {
+            let filter = Filter::parse(filter)?.to_sql()?;
+            self.restore_from_trash(&filter).await
+        })
+        .await
     }
 
     async fn optimize(&self, action: OptimizeAction) -> Result<OptimizeStats> {
-        let mut stats = OptimizeStats {
-            compaction: None,
-            prune: None,
-        };
-        match action {
-            OptimizeAction::All => {
-                stats.compaction = self
-                    .optimize(OptimizeAction::Compact {
-                        options: CompactionOptions::default(),
-                        remap_options: None,
-                    })
-                    .await?
-                    .compaction;
-                stats.prune = self
-                    .optimize(OptimizeAction::Prune {
-                        older_than: Duration::try_days(7).unwrap(),
-                        delete_unverified: None,
-                    })
-                    .await?
-                    .prune;
-                self.optimize(OptimizeAction::Index(OptimizeOptions::default()))
-                    .await?;
-            }
-            OptimizeAction::Compact {
-                options,
-                remap_options,
-            } => {
-                stats.compaction = Some(self.compact_files(options, remap_options).await?);
-            }
-            OptimizeAction::Prune {
-                older_than,
-                delete_unverified,
-            } => {
-                stats.prune = Some(
-                    self.cleanup_old_versions(older_than, delete_unverified)
-                        .await?,
-                );
-            }
-            OptimizeAction::Index(options) => {
-                self.optimize_indices(&options).await?;
+        self.run_with_hooks("optimize", async move {
+            let (files_before, bytes_before) = self.storage_usage().await?;
+            let mut stats = OptimizeStats {
+                compaction: None,
+                prune: None,
+                purge: None,
+                expired_rows: None,
+                fragment_stats: None,
+                files_reclaimed: 0,
+                bytes_reclaimed: 0,
+            };
+            for step in action.into_steps() {
+                self.optimize_step(step, &mut stats).await?;
             }
-        }
-        Ok(stats)
+            let (files_after, bytes_after) = self.storage_usage().await?;
+            stats.files_reclaimed = files_before.saturating_sub(files_after);
+            stats.bytes_reclaimed = bytes_before.saturating_sub(bytes_after);
+            Ok(stats)
+        })
+        .await
     }
 
     async fn add_columns(
@@ -1508,32 +4203,59 @@ impl TableInternal for NativeTable {
         transforms: NewColumnTransform,
         read_columns: Option<Vec<String>>,
     ) -> Result<()> {
-        self.dataset
-            .get_mut()
-            .await?
-            .add_columns(transforms, read_columns)
-            .await?;
-        Ok(())
+        self.run_with_hooks("add_columns", async move {
+            self.dataset
+                .get_mut()
+                .await?
+                .add_columns(transforms, read_columns)
+                .await?;
+            Ok(())
+        })
+        .await
     }
 
     async fn alter_columns(&self, alterations: &[ColumnAlteration]) -> Result<()> {
-        self.dataset
-            .get_mut()
-            .await?
-            .alter_columns(alterations)
-            .await?;
-        Ok(())
+        self.run_with_hooks("alter_columns", async move {
+            self.retry_on_conflict("alter_columns", move || async move {
+                self.dataset
+                    .get_mut()
+                    .await?
+                    .alter_columns(alterations)
+                    .await?;
+                Ok(())
+            })
+            .await
+        })
+        .await
     }
 
     async fn drop_columns(&self, columns: &[&str]) -> Result<()> {
-        self.dataset.get_mut().await?.drop_columns(columns).await?;
-        Ok(())
+        self.run_with_hooks("drop_columns", async move {
+            self.retry_on_conflict("drop_columns", move || async move {
+                self.dataset.get_mut().await?.drop_columns(columns).await?;
+                Ok(())
+            })
+            .await
+        })
+        .await
+    }
+
+    async fn update_metadata(
+        &self,
+        column: Option<&str>,
+        metadata: HashMap<String, String>,
+    ) -> Result<()> {
+        self.run_with_hooks(
+            "update_metadata",
+            self.update_metadata_impl(column, metadata),
+        )
+        .await
     }
 
     async fn list_indices(&self) -> Result<Vec<IndexConfig>> {
         let dataset = self.dataset.get().await?;
-        let indices = dataset.load_indices().await?;
-        indices.iter().map(|idx| {
+        let lance_indices = dataset.load_indices().await?;
+        let indices = lance_indices.iter().map(|idx| {
             let mut is_vector = false;
             let mut columns = Vec::with_capacity(idx.fields.len());
             for field_id in &idx.fields {
@@ -1545,21 +4267,112 @@ impl TableInternal for NativeTable {
                 }
                 columns.push(field.name.clone());
             }
-            Ok(IndexConfig { index_type: if is_vector { crate::index::IndexType::IvfPq } else { crate::index::IndexType::BTree }, columns })
-        }).collect::<Result<Vec<_>>>()
+            Ok(IndexConfig { name: idx.name.clone(), index_type: if is_vector { crate::index::IndexType::IvfPq } else { crate::index::IndexType::BTree }, columns })
+        }).collect::<Result<Vec<_>>>()?;
+        #[cfg(feature = "fts")]
+        let indices = {
+            let mut indices = indices;
+            if FtsIndex::exists(&self.uri) {
+                indices.push(IndexConfig {
+                    name: fts::FTS_INDEX_NAME.to_string(),
+                    index_type: crate::index::IndexType::Fts,
+                    columns: FtsIndex::open(&self.uri)?.columns(),
+                });
+            }
+            indices
+        };
+        Ok(indices)
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use std::iter;
-    use std::sync::atomic::{AtomicBool, Ordering};
-    use std::sync::Arc;
-    use std::time::Duration;
 
-    use arrow_array::{
-        Array, BooleanArray, Date32Array, FixedSizeListArray, Float32Array, Float64Array,
-        Int32Array, Int64Array, LargeStringArray, RecordBatch, RecordBatchIterator,
+    async fn index_stats(&self, name: &str) -> Result<Option<IndexStatistics>> {
+        let Some(index) = self
+            .list_indices()
+            .await?
+            .into_iter()
+            .find(|index| index.name == name)
+        else {
+            return Ok(None);
+        };
+        #[cfg(feature = "fts")]
+        if index.index_type == crate::index::IndexType::Fts {
+            let num_indexed_rows = FtsIndex::open(&self.uri)?.num_rows()?;
+            let num_rows = self.count_rows(None).await?;
+            return Ok(Some(IndexStatistics {
+                index_type: index.index_type,
+                num_indexed_rows,
+                num_unindexed_rows: num_rows.saturating_sub(num_indexed_rows),
+            }));
+        }
+        let stats = self.dataset.get().await?.index_statistics(name).await?;
+        let stats: VectorIndexStatistics = whatever!(
+            serde_json::from_str(&stats),
+            "error deserializing index statistics {stats}",
+        );
+        Ok(Some(IndexStatistics {
+            index_type: index.index_type,
+            num_indexed_rows: stats.num_indexed_rows,
+            num_unindexed_rows: stats.num_unindexed_rows,
+        }))
+    }
+
+    async fn drop_index(&self, name: &str) -> Result<()> {
+        self.run_with_hooks("drop_index", async move {
+            #[cfg(feature = "fts")]
+            if name == fts::FTS_INDEX_NAME && FtsIndex::exists(&self.uri) {
+                return FtsIndex::drop(&self.uri);
+            }
+            let dataset = self.dataset.get().await?.clone();
+            let indices = dataset.load_indices().await?;
+            let removed_indices = indices
+                .iter()
+                .filter(|index| index.name == name)
+                .cloned()
+                .collect::<Vec<_>>();
+            if removed_indices.is_empty() {
+                return Err(Error::IndexNotFound {
+                    name: name.to_string(),
+                });
+            }
+            let params = WriteParams {
+                store_params: Some(ObjectStoreParams {
+                    storage_options: Some(self.storage_options.clone()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let params = match self.store_wrapper.clone() {
+                Some(wrapper) => params.patch_with_store_wrapper(wrapper)?,
+                None => params,
+            };
+            self.dataset.ensure_mutable().await?;
+            let dataset = Dataset::commit(
+                &self.uri,
+                Operation::CreateIndex {
+                    new_indices: Vec::new(),
+                    removed_indices,
+                },
+                Some(dataset.version().version),
+                params.store_params,
+                self.commit_handler.clone(),
+            )
+            .await?;
+            self.dataset.set_latest(dataset).await?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use arrow_array::{
+        Array, BooleanArray, Date32Array, FixedSizeListArray, Float32Array, Float64Array,
+        Int32Array, Int64Array, LargeStringArray, RecordBatch, RecordBatchIterator,
         RecordBatchReader, StringArray, TimestampMillisecondArray, TimestampNanosecondArray,
         UInt32Array,
     };
@@ -1575,6 +4388,7 @@ mod tests {
     use crate::connection::ConnectBuilder;
     use crate::index::scalar::BTreeIndexBuilder;
     use crate::query::{ExecutableQuery, QueryBase};
+    use crate::DistanceType;
 
     use super::*;
 
@@ -1630,534 +4444,2172 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_add() {
+    async fn test_soft_delete() {
         let tmp_dir = tempdir().unwrap();
         let uri = tmp_dir.path().to_str().unwrap();
         let conn = connect(uri).execute().await.unwrap();
 
-        let batches = make_test_batches();
-        let schema = batches.schema().clone();
-        let table = conn.create_table("test", batches).execute().await.unwrap();
-        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+        let table = conn
+            .create_table("test", make_test_batches())
+            .soft_delete(true)
+            .execute()
+            .await
+            .unwrap();
+        let count_deleted = || {
+            let table = table.clone();
+            async move {
+                table
+                    .query()
+                    .only_deleted()
+                    .execute()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+                    .iter()
+                    .map(|batch| batch.num_rows())
+                    .sum::<usize>()
+            }
+        };
+        assert_eq!(count_deleted().await, 0);
 
-        let new_batches = RecordBatchIterator::new(
-            vec![RecordBatch::try_new(
-                schema.clone(),
-                vec![Arc::new(Int32Array::from_iter_values(100..110))],
-            )
-            .unwrap()]
-            .into_iter()
-            .map(Ok),
-            schema.clone(),
-        );
+        table.delete("i < 3").await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 7);
+        assert_eq!(count_deleted().await, 3);
 
-        table.add(new_batches).execute().await.unwrap();
-        assert_eq!(table.count_rows(None).await.unwrap(), 20);
-        assert_eq!(table.name(), "test");
+        table.undelete("i = 1").await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 8);
+        assert_eq!(table.count_rows(Some("i = 1".into())).await.unwrap(), 1);
+        assert_eq!(count_deleted().await, 2);
+
+        // Compaction empties the trash
+        table
+            .optimize(OptimizeAction::Compact {
+                options: CompactionOptions::default(),
+                remap_options: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(count_deleted().await, 0);
+        table.undelete("i = 0").await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 8);
+
+        // Soft delete must be enabled to search deleted rows
+        let table = conn
+            .create_table("hard", make_test_batches())
+            .execute()
+            .await
+            .unwrap();
+        table.delete("i < 3").await.unwrap();
+        assert!(table.query().only_deleted().execute().await.is_err());
     }
 
     #[tokio::test]
-    async fn test_merge_insert() {
+    async fn test_optimize() {
         let tmp_dir = tempdir().unwrap();
         let uri = tmp_dir.path().to_str().unwrap();
         let conn = connect(uri).execute().await.unwrap();
 
-        // Create a dataset with i=0..10
-        let batches = merge_insert_test_batches(0, 0);
         let table = conn
-            .create_table("my_table", batches)
+            .create_table("test", make_test_batches())
             .execute()
             .await
             .unwrap();
-        assert_eq!(table.count_rows(None).await.unwrap(), 10);
-
-        // Create new data with i=5..15
-        let new_batches = Box::new(merge_insert_test_batches(5, 1));
+        for _ in 0..5 {
+            table.add(make_test_batches()).execute().await.unwrap();
+        }
+        let native = table.as_native().unwrap();
+        assert_eq!(native.count_fragments().await.unwrap(), 6);
 
-        // Perform a "insert if not exists"
-        let mut merge_insert_builder = table.merge_insert(&["i"]);
-        merge_insert_builder.when_not_matched_insert_all();
-        merge_insert_builder.execute(new_batches).await.unwrap();
-        // Only 5 rows should actually be inserted
-        assert_eq!(table.count_rows(None).await.unwrap(), 15);
+        let stats = table
+            .optimize(OptimizeAction::Compact {
+                options: CompactionOptions::default(),
+                remap_options: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(stats.compaction.unwrap().fragments_removed, 6);
+        assert_eq!(native.count_fragments().await.unwrap(), 1);
 
-        // Create new data with i=15..25 (no id matches)
-        let new_batches = Box::new(merge_insert_test_batches(15, 2));
-        // Perform a "bulk update" (should not affect anything)
-        let mut merge_insert_builder = table.merge_insert(&["i"]);
-        merge_insert_builder.when_matched_update_all(None);
-        merge_insert_builder.execute(new_batches).await.unwrap();
-        // No new rows should have been inserted
-        assert_eq!(table.count_rows(None).await.unwrap(), 15);
-        assert_eq!(
-            table.count_rows(Some("age = 2".to_string())).await.unwrap(),
-            0
-        );
+        table.delete("i < 5").await.unwrap();
+        assert!(native.count_deleted_rows().await.unwrap() > 0);
+        assert!(table
+            .optimize(OptimizeAction::PurgeDeletions { threshold: 2.0 })
+            .await
+            .is_err());
+        let stats = table
+            .optimize(OptimizeAction::PurgeDeletions { threshold: 0.0 })
+            .await
+            .unwrap();
+        assert_eq!(stats.purge.unwrap().fragments_removed, 1);
+        assert_eq!(native.count_deleted_rows().await.unwrap(), 0);
 
-        // Conditional update that only replaces the age=0 data
-        let new_batches = Box::new(merge_insert_test_batches(5, 3));
-        let mut merge_insert_builder = table.merge_insert(&["i"]);
-        merge_insert_builder.when_matched_update_all(Some("target.age = 0".to_string()));
-        merge_insert_builder.execute(new_batches).await.unwrap();
-        assert_eq!(
-            table.count_rows(Some("age = 3".to_string())).await.unwrap(),
-            5
-        );
+        let stats = table
+            .optimize(OptimizeAction::Prune {
+                older_than: chrono::Duration::zero(),
+                delete_unverified: Some(true),
+            })
+            .await
+            .unwrap();
+        assert!(stats.prune.unwrap().old_versions > 0);
+        assert!(stats.files_reclaimed > 0);
+        assert!(stats.bytes_reclaimed > 0);
     }
 
     #[tokio::test]
-    async fn test_add_overwrite() {
+    async fn test_delete_result() {
         let tmp_dir = tempdir().unwrap();
         let uri = tmp_dir.path().to_str().unwrap();
         let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("test", make_test_batches())
+            .execute()
+            .await
+            .unwrap();
+        let version = table.version().await.unwrap();
 
-        let batches = make_test_batches();
-        let schema = batches.schema().clone();
-        let table = conn.create_table("test", batches).execute().await.unwrap();
-        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+        let result = table.delete("i < 3").await.unwrap();
+        assert_eq!(result.num_deleted_rows, 3);
+        assert_eq!(result.version, version + 1);
 
-        let batches = vec![RecordBatch::try_new(
-            schema.clone(),
-            vec![Arc::new(Int32Array::from_iter_values(100..110))],
-        )
-        .unwrap()]
-        .into_iter()
-        .map(Ok);
+        // Nothing matches, so nothing is committed
+        let result = table.delete("i < 3").await.unwrap();
+        assert_eq!(result.num_deleted_rows, 0);
+        assert_eq!(result.version, version + 1);
 
-        let new_batches = RecordBatchIterator::new(batches.clone(), schema.clone());
+        let keys = Int32Array::from(vec![Some(4), None, Some(5), Some(42)]);
+        let result = table.delete_keys("i", &keys).await.unwrap();
+        assert_eq!(result.num_deleted_rows, 2);
+        assert_eq!(result.version, version + 2);
+        assert_eq!(table.count_rows(None).await.unwrap(), 5);
 
-        // Can overwrite using AddDataOptions::mode
-        table
-            .add(new_batches)
-            .mode(AddDataMode::Overwrite)
-            .execute()
+        let result = table
+            .delete_keys("i", &Int32Array::from(Vec::<i32>::new()))
             .await
             .unwrap();
-        assert_eq!(table.count_rows(None).await.unwrap(), 10);
-        assert_eq!(table.name(), "test");
+        assert_eq!(result.num_deleted_rows, 0);
+        let keys = arrow_array::BooleanArray::from(vec![true]);
+        assert!(table.delete_keys("i", &keys).await.is_err());
+    }
 
-        // Can overwrite using underlying WriteParams (which
-        // take precedence over AddDataOptions::mode)
+    #[tokio::test]
+    async fn test_commit_conflict() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
 
-        let param: WriteParams = WriteParams {
-            mode: WriteMode::Overwrite,
-            ..Default::default()
-        };
+        // Two handles on the same table, the second one does not see the writes
+        // of the first one
+        let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("test", make_test_batches())
+            .execute()
+            .await
+            .unwrap();
+        let stale = conn.open_table("test").execute().await.unwrap();
+        table.delete("i = 0").await.unwrap();
+        // Rewrites the same fragment, so the delete is retried on the latest version
+        stale.delete("i = 1").await.unwrap();
+        assert_eq!(stale.count_rows(None).await.unwrap(), 8);
+
+        let conn = connect(uri).commit_retries(0).execute().await.unwrap();
+        let table = conn.open_table("test").execute().await.unwrap();
+        let stale = conn.open_table("test").execute().await.unwrap();
+        table.delete("i = 2").await.unwrap();
+        match stale.delete("i = 3").await {
+            Err(Error::CommitConflict {
+                table,
+                operation,
+                attempts,
+                ..
+            }) => {
+                assert_eq!(table, "test");
+                assert_eq!(operation, "delete");
+                assert_eq!(attempts, 1);
+            }
+            result => panic!("expected a commit conflict, got {:?}", result),
+        }
+        stale.checkout_latest().await.unwrap();
+        assert_eq!(stale.count_rows(None).await.unwrap(), 7);
 
-        let new_batches = RecordBatchIterator::new(batches.clone(), schema.clone());
+        // A single write can override the retries of the connection
+        let stale = conn.open_table("test").execute().await.unwrap();
+        table.delete("i = 4").await.unwrap();
+        stale
+            .update()
+            .only_if("i = 5")
+            .column("i", "50")
+            .write_options(WriteOptions {
+                max_commit_retries: Some(1),
+                commit_backoff: Some(CommitBackoff::none()),
+                ..Default::default()
+            })
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(
+            stale.count_rows(Some("i = 50".to_string())).await.unwrap(),
+            1
+        );
+
+        // An add is written on top of the latest version, so it does not conflict
+        // with an earlier overwrite, with or without (buffered) retries
+        let stale = conn.open_table("test").execute().await.unwrap();
         table
-            .add(new_batches)
+            .add(make_test_batches())
+            .mode(AddDataMode::Overwrite)
+            .execute()
+            .await
+            .unwrap();
+        stale.add(make_test_batches()).execute().await.unwrap();
+        assert_eq!(stale.count_rows(None).await.unwrap(), 20);
+        stale
+            .add(make_test_batches())
             .write_options(WriteOptions {
-                lance_write_params: Some(param),
+                max_commit_retries: Some(1),
+                ..Default::default()
             })
-            .mode(AddDataMode::Append)
             .execute()
             .await
             .unwrap();
-        assert_eq!(table.count_rows(None).await.unwrap(), 10);
-        assert_eq!(table.name(), "test");
+        assert_eq!(stale.count_rows(None).await.unwrap(), 30);
     }
 
     #[tokio::test]
-    async fn test_update_with_predicate() {
+    async fn test_evolve_columns() {
         let tmp_dir = tempdir().unwrap();
-        let dataset_path = tmp_dir.path().join("test.lance");
-        let uri = dataset_path.to_str().unwrap();
-        let conn = connect(uri)
-            .read_consistency_interval(Duration::from_secs(0))
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("test", make_test_batches())
             .execute()
             .await
             .unwrap();
 
-        let schema = Arc::new(Schema::new(vec![
-            Field::new("id", DataType::Int32, false),
-            Field::new("name", DataType::Utf8, false),
-        ]));
-
-        let record_batch_iter = RecordBatchIterator::new(
-            vec![RecordBatch::try_new(
-                schema.clone(),
-                vec![
-                    Arc::new(Int32Array::from_iter_values(0..10)),
-                    Arc::new(StringArray::from_iter_values(vec![
-                        "a", "b", "c", "d", "e", "f", "g", "h", "i", "j",
-                    ])),
-                ],
-            )
-            .unwrap()]
-            .into_iter()
-            .map(Ok),
-            schema.clone(),
+        let vector_type =
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2);
+        table
+            .add_null_columns(Arc::new(Schema::new(vec![
+                Field::new("tag", DataType::Utf8, true),
+                Field::new("vector", vector_type.clone(), true),
+            ])))
+            .await
+            .unwrap();
+        let schema = table.schema().await.unwrap();
+        assert_eq!(
+            schema.field_with_name("vector").unwrap().data_type(),
+            &vector_type
         );
+        assert_eq!(
+            table
+                .count_rows(Some("tag IS NULL".to_string()))
+                .await
+                .unwrap(),
+            10
+        );
+        assert!(table
+            .add_null_columns(Arc::new(Schema::new(vec![Field::new(
+                "required",
+                DataType::Utf8,
+                false
+            )])))
+            .await
+            .is_err());
+        assert!(table
+            .add_null_columns(Arc::new(Schema::new(vec![Field::new(
+                "tag",
+                DataType::Utf8,
+                true
+            )])))
+            .await
+            .is_err());
 
-        let table = conn
-            .create_table("my_table", record_batch_iter)
-            .execute()
+        table
+            .alter_column_types(&[("i", DataType::Int64)])
             .await
             .unwrap();
+        let schema = table.schema().await.unwrap();
+        assert_eq!(
+            schema
+                .fields()
+                .iter()
+                .map(|f| f.name().as_str())
+                .collect::<Vec<_>>(),
+            vec!["tag", "vector", "i"]
+        );
+        assert_eq!(
+            schema.field_with_name("i").unwrap().data_type(),
+            &DataType::Int64
+        );
+        assert_eq!(
+            table.count_rows(Some("i >= 5".to_string())).await.unwrap(),
+            5
+        );
 
+        // Values that do not fit fail the conversion
         table
             .update()
-            .only_if("id > 5")
-            .column("name", "'foo'")
+            .column("i", "i * 100")
             .execute()
             .await
             .unwrap();
+        assert!(table
+            .alter_column_types(&[("i", DataType::Int8)])
+            .await
+            .is_err());
+        let schema = table.schema().await.unwrap();
+        assert_eq!(
+            schema.field_with_name("i").unwrap().data_type(),
+            &DataType::Int64
+        );
 
-        let mut batches = table
-            .query()
-            .select(Select::columns(&["id", "name"]))
+        table.drop_columns(&["tag"]).await.unwrap();
+        assert_eq!(table.schema().await.unwrap().fields().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_row_history() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("text", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec!["a", "b"])),
+            ],
+        )
+        .unwrap();
+        let table = conn
+            .create_table(
+                "test",
+                RecordBatchIterator::new(vec![Ok(batch.clone())], schema.clone()),
+            )
             .execute()
             .await
-            .unwrap()
-            .try_collect::<Vec<_>>()
+            .unwrap();
+        // v2 changes the row, v3 does not, v4 deletes it
+        table
+            .update()
+            .only_if("id = 1")
+            .column("text", "'c'")
+            .execute()
+            .await
+            .unwrap();
+        table
+            .update()
+            .only_if("id = 2")
+            .column("text", "'d'")
+            .execute()
             .await
             .unwrap();
+        table.delete("id = 1").await.unwrap();
 
-        while let Some(batch) = batches.pop() {
-            let ids = batch
-                .column(0)
-                .as_any()
-                .downcast_ref::<Int32Array>()
-                .unwrap()
-                .iter()
-                .collect::<Vec<_>>();
-            let names = batch
-                .column(1)
-                .as_any()
-                .downcast_ref::<StringArray>()
-                .unwrap()
-                .iter()
-                .collect::<Vec<_>>();
-            for (i, name) in names.iter().enumerate() {
-                let id = ids[i].unwrap();
-                let name = name.unwrap();
-                if id > 5 {
-                    assert_eq!(name, "foo");
-                } else {
-                    assert_eq!(name, &format!("{}", (b'a' + id as u8) as char));
-                }
-            }
-        }
+        let history = table.row_history("id = 1").await.unwrap();
+        let versions = history.iter().map(|v| v.version).collect::<Vec<_>>();
+        assert_eq!(versions, vec![1, 2, 4]);
+        let texts = history
+            .iter()
+            .map(|v| {
+                v.rows["text"]
+                    .as_string::<i32>()
+                    .iter()
+                    .map(|text| text.unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(texts, vec![vec!["a"], vec!["c"], vec![]]);
+
+        assert!(table.row_history("id = 3").await.unwrap().is_empty());
     }
 
     #[tokio::test]
-    async fn test_update_all_types() {
+    async fn test_column_stats() {
+        use crate::query::filter::FilterValue;
+
         let tmp_dir = tempdir().unwrap();
-        let dataset_path = tmp_dir.path().join("test.lance");
-        let uri = dataset_path.to_str().unwrap();
-        let conn = connect(uri)
-            .read_consistency_interval(Duration::from_secs(0))
-            .execute()
-            .await
-            .unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
 
         let schema = Arc::new(Schema::new(vec![
-            Field::new("int32", DataType::Int32, false),
-            Field::new("int64", DataType::Int64, false),
-            Field::new("uint32", DataType::UInt32, false),
-            Field::new("string", DataType::Utf8, false),
-            Field::new("large_string", DataType::LargeUtf8, false),
-            Field::new("float32", DataType::Float32, false),
-            Field::new("float64", DataType::Float64, false),
-            Field::new("bool", DataType::Boolean, false),
-            Field::new("date32", DataType::Date32, false),
-            Field::new(
-                "timestamp_ns",
-                DataType::Timestamp(TimeUnit::Nanosecond, None),
-                false,
-            ),
-            Field::new(
-                "timestamp_ms",
-                DataType::Timestamp(TimeUnit::Millisecond, None),
-                false,
-            ),
-            Field::new(
-                "vec_f32",
-                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
-                false,
-            ),
-            Field::new(
-                "vec_f64",
-                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float64, true)), 2),
-                false,
-            ),
+            Field::new("id", DataType::Int32, true),
+            Field::new("text", DataType::Utf8, true),
         ]));
-
-        let record_batch_iter = RecordBatchIterator::new(
-            vec![RecordBatch::try_new(
+        // Lance reads back the nulls of primitive columns as zeros, so the nulls
+        // are strings
+        let make_batch = |ids: Vec<i32>, texts: Vec<Option<&str>>| {
+            RecordBatchIterator::new(
+                vec![RecordBatch::try_new(
+                    schema.clone(),
+                    vec![
+                        Arc::new(Int32Array::from(ids)),
+                        Arc::new(StringArray::from(texts)),
+                    ],
+                )],
                 schema.clone(),
-                vec![
-                    Arc::new(Int32Array::from_iter_values(0..10)),
-                    Arc::new(Int64Array::from_iter_values(0..10)),
-                    Arc::new(UInt32Array::from_iter_values(0..10)),
-                    Arc::new(StringArray::from_iter_values(vec![
-                        "a", "b", "c", "d", "e", "f", "g", "h", "i", "j",
-                    ])),
-                    Arc::new(LargeStringArray::from_iter_values(vec![
-                        "a", "b", "c", "d", "e", "f", "g", "h", "i", "j",
-                    ])),
-                    Arc::new(Float32Array::from_iter_values((0..10).map(|i| i as f32))),
-                    Arc::new(Float64Array::from_iter_values((0..10).map(|i| i as f64))),
-                    Arc::new(Into::<BooleanArray>::into(vec![
-                        true, false, true, false, true, false, true, false, true, false,
-                    ])),
-                    Arc::new(Date32Array::from_iter_values(0..10)),
-                    Arc::new(TimestampNanosecondArray::from_iter_values(0..10)),
-                    Arc::new(TimestampMillisecondArray::from_iter_values(0..10)),
-                    Arc::new(
-                        create_fixed_size_list(
-                            Float32Array::from_iter_values((0..20).map(|i| i as f32)),
-                            2,
-                        )
-                        .unwrap(),
-                    ),
-                    Arc::new(
-                        create_fixed_size_list(
-                            Float64Array::from_iter_values((0..20).map(|i| i as f64)),
-                            2,
-                        )
-                        .unwrap(),
-                    ),
-                ],
             )
-            .unwrap()]
-            .into_iter()
-            .map(Ok),
-            schema.clone(),
-        );
-
+        };
         let table = conn
-            .create_table("my_table", record_batch_iter)
+            .create_table(
+                "test",
+                make_batch(vec![1, 2, 2], vec![Some("a"), Some("b"), None]),
+            )
             .execute()
             .await
             .unwrap();
 
-        // check it can do update for each type
-        let updates: Vec<(&str, &str)> = vec![
-            ("string", "'foo'"),
-            ("large_string", "'large_foo'"),
-            ("int32", "1"),
-            ("int64", "1"),
-            ("uint32", "1"),
-            ("float32", "1.0"),
-            ("float64", "1.0"),
-            ("bool", "true"),
-            ("date32", "1"),
-            ("timestamp_ns", "1"),
-            ("timestamp_ms", "1"),
-            ("vec_f32", "[1.0, 1.0]"),
-            ("vec_f64", "[1.0, 1.0]"),
-        ];
+        let stats = table.column_stats("id").await.unwrap();
+        assert_eq!(stats.num_rows, 3);
+        assert_eq!(stats.null_count, 0);
+        assert_eq!(stats.min, Some(FilterValue::Int(1)));
+        assert_eq!(stats.max, Some(FilterValue::Int(2)));
+        assert_eq!(stats.approx_distinct, 2);
+        let stats = table.column_stats("text").await.unwrap();
+        assert_eq!(stats.null_count, 1);
+        assert_eq!(stats.approx_distinct, 2);
+
+        // Updated as data is added
+        table
+            .add(make_batch(vec![10, 2], vec![Some("c"), Some("a")]))
+            .execute()
+            .await
+            .unwrap();
+        let stats = table.column_stats("id").await.unwrap();
+        assert_eq!(stats.num_rows, 5);
+        assert_eq!(stats.max, Some(FilterValue::Int(10)));
+        assert_eq!(stats.approx_distinct, 3);
+        let histogram = stats.histogram.unwrap();
+        assert_eq!(histogram.iter().map(|b| b.count).sum::<usize>(), 5);
+        assert_eq!(table.column_stats("text").await.unwrap().approx_distinct, 3);
+
+        // Recomputed after a delete
+        table.delete("id = 10").await.unwrap();
+        let stats = table.column_stats("id").await.unwrap();
+        assert_eq!(stats.num_rows, 4);
+        assert_eq!(stats.max, Some(FilterValue::Int(2)));
+
+        assert!(table.column_stats("missing").await.is_err());
+    }
 
-        let mut update_op = table.update();
-        for (column, value) in updates {
-            update_op = update_op.column(column, value);
-        }
-        update_op.execute().await.unwrap();
+    #[tokio::test]
+    async fn test_max_sim_search() {
+        use arrow::buffer::OffsetBuffer;
+        use arrow_array::ListArray;
 
-        let mut batches = table
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let item = Arc::new(Field::new("item", DataType::Float32, true));
+        let vector = Arc::new(Field::new(
+            "item",
+            DataType::FixedSizeList(item.clone(), 2),
+            true,
+        ));
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new("vectors", DataType::List(vector.clone()), true),
+        ]));
+        let vectors = FixedSizeListArray::try_new(
+            item,
+            2,
+            Arc::new(Float32Array::from(vec![
+                1.0, 0.0, 0.6, 0.8, 1.0, 0.0, 0.0, 1.0,
+            ])),
+            None,
+        )
+        .unwrap();
+        let multivectors = ListArray::try_new(
+            vector,
+            OffsetBuffer::from_lengths([1, 1, 2]),
+            Arc::new(vectors),
+            None,
+        )
+        .unwrap();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..3)),
+                Arc::new(multivectors),
+            ],
+        )
+        .unwrap();
+        let table = conn
+            .create_table(
+                "test",
+                RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        // Several query vectors are searched with MaxSim and the column is inferred
+        let batches = table
             .query()
-            .select(Select::columns(&[
-                "string",
-                "large_string",
-                "int32",
-                "int64",
-                "uint32",
-                "float32",
-                "float64",
-                "bool",
-                "date32",
-                "timestamp_ns",
-                "timestamp_ms",
-                "vec_f32",
-                "vec_f64",
-            ]))
+            .nearest_to(vec![vec![1.0f32, 0.0], vec![0.0, 1.0]])
+            .unwrap()
             .execute()
             .await
             .unwrap()
             .try_collect::<Vec<_>>()
             .await
             .unwrap();
-        let batch = batches.pop().unwrap();
-
-        macro_rules! assert_column {
-            ($column:expr, $array_type:ty, $expected:expr) => {
-                let array = $column
-                    .as_any()
-                    .downcast_ref::<$array_type>()
-                    .unwrap()
-                    .iter()
-                    .collect::<Vec<_>>();
-                for v in array {
-                    assert_eq!(v, Some($expected));
-                }
-            };
-        }
-
-        assert_column!(batch.column(0), StringArray, "foo");
-        assert_column!(batch.column(1), LargeStringArray, "large_foo");
-        assert_column!(batch.column(2), Int32Array, 1);
-        assert_column!(batch.column(3), Int64Array, 1);
-        assert_column!(batch.column(4), UInt32Array, 1);
-        assert_column!(batch.column(5), Float32Array, 1.0);
-        assert_column!(batch.column(6), Float64Array, 1.0);
-        assert_column!(batch.column(7), BooleanArray, true);
-        assert_column!(batch.column(8), Date32Array, 1);
-        assert_column!(batch.column(9), TimestampNanosecondArray, 1);
-        assert_column!(batch.column(10), TimestampMillisecondArray, 1);
-
-        let array = batch
-            .column(11)
-            .as_any()
-            .downcast_ref::<FixedSizeListArray>()
-            .unwrap()
-            .iter()
-            .collect::<Vec<_>>();
-        for v in array {
-            let v = v.unwrap();
-            let f32array = v.as_any().downcast_ref::<Float32Array>().unwrap();
-            for v in f32array {
-                assert_eq!(v, Some(1.0));
-            }
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+        let ids = batch["id"].as_primitive::<arrow_array::types::Int32Type>();
+        let distances = batch["_distance"].as_primitive::<Float32Type>();
+        assert_eq!(ids.values(), &[2, 1, 0]);
+        for (distance, expected) in distances.values().iter().zip([0.0, 0.6, 1.0]) {
+            assert!((distance - expected).abs() < 1e-6);
         }
 
-        let array = batch
-            .column(12)
-            .as_any()
-            .downcast_ref::<FixedSizeListArray>()
+        // Multivectors cannot be indexed or searched with other distance types
+        assert!(table
+            .create_index(&["vectors"], Index::Auto)
+            .execute()
+            .await
+            .is_err());
+        assert!(table
+            .query()
+            .nearest_to(vec![vec![1.0f32, 0.0]])
             .unwrap()
-            .iter()
-            .collect::<Vec<_>>();
-        for v in array {
-            let v = v.unwrap();
-            let f64array = v.as_any().downcast_ref::<Float64Array>().unwrap();
-            for v in f64array {
-                assert_eq!(v, Some(1.0));
-            }
-        }
+            .distance_type(DistanceType::L2)
+            .execute()
+            .await
+            .is_err());
     }
 
-    #[tokio::test]
-    async fn test_update_via_expr() {
-        let tmp_dir = tempdir().unwrap();
-        let dataset_path = tmp_dir.path().join("test.lance");
-        let uri = dataset_path.to_str().unwrap();
-        let conn = connect(uri)
-            .read_consistency_interval(Duration::from_secs(0))
+    #[tokio::test]
+    async fn test_hamming_search() {
+        use arrow_array::types::UInt8Type;
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new(
+                "bits",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::UInt8, true)), 2),
+                true,
+            ),
+        ]));
+        let vectors = [
+            [0x00, 0x00],
+            [0xff, 0x00],
+            [0x01, 0x00],
+            [0xff, 0xff],
+            [0x07, 0x00],
+        ];
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..5)),
+                Arc::new(FixedSizeListArray::from_iter_primitive::<UInt8Type, _, _>(
+                    vectors.iter().map(|v| Some(v.map(Some))),
+                    2,
+                )),
+            ],
+        )
+        .unwrap();
+        let table = conn
+            .create_table(
+                "test",
+                RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        let batches = table
+            .query()
+            .nearest_to(&[0x01u8, 0x00])
+            .unwrap()
+            .column("bits")
+            .distance_type(DistanceType::Hamming)
+            .select(Select::columns(&["id"]))
+            .limit(3)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(batch.num_columns(), 2);
+        let ids = batch["id"].as_primitive::<arrow_array::types::Int32Type>();
+        let distances = batch["_distance"].as_primitive::<Float32Type>();
+        assert_eq!(ids.values(), &[2, 0, 4]);
+        assert_eq!(distances.values(), &[0.0, 1.0, 2.0]);
+
+        // Binary vectors cannot be indexed
+        assert!(table
+            .create_index(&["bits"], Index::Auto)
+            .execute()
+            .await
+            .is_err());
+        // and the hamming distance is only supported for flat searches
+        assert!(table
+            .create_index(
+                &["bits"],
+                Index::IvfPq(IvfPqIndexBuilder::default().distance_type(DistanceType::Hamming))
+            )
+            .execute()
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_verify_checksums() {
+        use crate::io::checksum::ChecksumMode;
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri)
+            .checksums(ChecksumMode::Verify)
+            .execute()
+            .await
+            .unwrap();
+        let table = conn
+            .create_table("test", make_test_batches())
+            .execute()
+            .await
+            .unwrap();
+        let report = table.verify_checksums().await.unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.verified, 1);
+        assert!(report.missing.is_empty());
+
+        // Flip a byte of the data file
+        let data_dir = tmp_dir.path().join("test.lance").join("data");
+        let data_file = std::fs::read_dir(data_dir)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let mut bytes = std::fs::read(&data_file).unwrap();
+        bytes[0] ^= 0xff;
+        std::fs::write(&data_file, bytes).unwrap();
+
+        let report = table.verify_checksums().await.unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.corrupted.len(), 1);
+        // Reading the corrupted file fails instead of returning wrong results
+        let conn = connect(uri)
+            .checksums(ChecksumMode::Verify)
+            .execute()
+            .await
+            .unwrap();
+        let table = conn.open_table("test").execute().await.unwrap();
+        let result = match table.query().execute().await {
+            Ok(stream) => stream.try_collect::<Vec<_>>().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_encryption() {
+        use crate::io::encryption::{EncryptionKey, StaticKeyProvider};
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let key = EncryptionKey::generate();
+        let provider = Arc::new(StaticKeyProvider::new("k1", key));
+        let conn = connect(uri)
+            .encryption(provider.clone())
+            .execute()
+            .await
+            .unwrap();
+        let table = conn
+            .create_table("test", make_test_batches())
+            .execute()
+            .await
+            .unwrap();
+        table.add(make_test_batches()).execute().await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 20);
+
+        // The data files are encrypted on disk
+        let data_dir = tmp_dir.path().join("test.lance").join("data");
+        for file in std::fs::read_dir(data_dir).unwrap() {
+            let bytes = std::fs::read(file.unwrap().path()).unwrap();
+            assert!(bytes.starts_with(b"LDBENC"));
+        }
+
+        let conn = connect(uri).encryption(provider).execute().await.unwrap();
+        let table = conn.open_table("test").execute().await.unwrap();
+        let batches = table
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 20);
+
+        // The files cannot be read without the key.  Lance does not check the
+        // files it reads, so this is a connection with another key rather than
+        // one without encryption, which would read the ciphertext as is
+        let other = Arc::new(StaticKeyProvider::new("k2", EncryptionKey::generate()));
+        let conn = connect(uri).encryption(other).execute().await.unwrap();
+        let table = conn.open_table("test").execute().await.unwrap();
+        let result = match table.query().execute().await {
+            Ok(stream) => stream.try_collect::<Vec<_>>().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_distance_range() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+                true,
+            ),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..10)),
+                Arc::new(
+                    FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+                        (0..10).map(|i| Some(vec![Some(i as f32), Some(0.0)])),
+                        2,
+                    ),
+                ),
+            ],
+        )
+        .unwrap();
+        let table = conn
+            .create_table(
+                "test",
+                RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        let search = |lower_bound: Option<f32>, upper_bound: Option<f32>| {
+            let table = table.clone();
+            async move {
+                let batches = table
+                    .query()
+                    .nearest_to(&[0.0, 0.0])
+                    .unwrap()
+                    .distance_range(lower_bound, upper_bound)
+                    .limit(10)
+                    .execute()
+                    .await?
+                    .try_collect::<Vec<_>>()
+                    .await?;
+                Result::Ok(
+                    batches
+                        .iter()
+                        .flat_map(|batch| {
+                            batch["id"]
+                                .as_primitive::<arrow_array::types::Int32Type>()
+                                .values()
+                                .to_vec()
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+        // The L2 distances are the squares of the ids
+        assert_eq!(search(Some(1.0), Some(9.0)).await.unwrap(), vec![1, 2]);
+        assert_eq!(search(None, Some(0.5)).await.unwrap(), vec![0]);
+        assert_eq!(search(Some(64.0), None).await.unwrap(), vec![8, 9]);
+        assert!(search(Some(2.0), Some(1.0)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_find_duplicates() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "vector",
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+            false,
+        )]));
+        // Rows 0, 2 and 3 are near duplicates, row 1 is not
+        let vectors = Float32Array::from(vec![1.0, 1.0, 5.0, 5.0, 1.0, 1.01, 1.01, 1.0]);
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(create_fixed_size_list(vectors, 2).unwrap())],
+        )
+        .unwrap();
+        let table = conn
+            .create_table("test", RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await
+            .unwrap();
+
+        let duplicates = table
+            .find_duplicates("vector", 0.01)
+            .execute()
+            .await
+            .unwrap();
+        let pairs = duplicates
+            .pairs
+            .iter()
+            .map(|pair| (pair.first, pair.second))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, vec![(0, 2), (0, 3), (2, 3)]);
+        assert_eq!(duplicates.groups, vec![vec![0, 2, 3]]);
+
+        let duplicates = table
+            .find_duplicates("vector", 0.00001)
+            .execute()
+            .await
+            .unwrap();
+        assert!(duplicates.pairs.is_empty());
+        assert!(duplicates.groups.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_export_vectors() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 4),
+                false,
+            ),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..10)),
+                Arc::new(
+                    create_fixed_size_list(
+                        Float32Array::from_iter_values((0..40).map(|v| v as f32)),
+                        4,
+                    )
+                    .unwrap(),
+                ),
+            ],
+        )
+        .unwrap();
+        let table = conn
+            .create_table("test", RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await
+            .unwrap();
+
+        let npy_path = tmp_dir.path().join("vectors.npy");
+        let num_rows = table
+            .export_vectors("vector")
+            .id_column("id")
+            .only_if("id >= 5")
+            .write(&npy_path)
+            .await
+            .unwrap();
+        assert_eq!(num_rows, 5);
+        let npy = std::fs::read(&npy_path).unwrap();
+        assert!(npy.starts_with(b"\x93NUMPY\x01\x00"));
+        let header_len = 10 + u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        assert_eq!(header_len % 64, 0);
+        let header = std::str::from_utf8(&npy[10..header_len]).unwrap();
+        assert!(header.contains("'descr': [('id', '<i8'), ('vector', '<f4', (4,))]"));
+        assert!(header.contains("'shape': (5,)"));
+        assert_eq!(npy.len(), header_len + 5 * (8 + 4 * 4));
+        // The first record is the row with id 5
+        let record = &npy[header_len..header_len + 24];
+        assert_eq!(i64::from_le_bytes(record[..8].try_into().unwrap()), 5);
+        assert_eq!(f32::from_le_bytes(record[8..12].try_into().unwrap()), 20.0);
+
+        let parquet_path = tmp_dir.path().join("vectors.parquet");
+        let num_rows = table
+            .export_vectors("vector")
+            .format(export::VectorExportFormat::Parquet)
+            .write(&parquet_path)
+            .await
+            .unwrap();
+        assert_eq!(num_rows, 10);
+        let reader = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(
+            std::fs::File::open(&parquet_path).unwrap(),
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(batch.num_rows(), 10);
+        assert_eq!(batch.schema().field(0).name(), "_rowid");
+        assert_eq!(batch.schema().field(1).name(), "vector");
+
+        assert!(matches!(
+            table.export_vectors("id").write(&npy_path).await,
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_add() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let batches = make_test_batches();
+        let schema = batches.schema().clone();
+        let table = conn.create_table("test", batches).execute().await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+
+        let new_batches = RecordBatchIterator::new(
+            vec![RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from_iter_values(100..110))],
+            )
+            .unwrap()]
+            .into_iter()
+            .map(Ok),
+            schema.clone(),
+        );
+
+        table.add(new_batches).execute().await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 20);
+        assert_eq!(table.name(), "test");
+    }
+
+    #[tokio::test]
+    async fn test_add_schema_mode() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::LargeUtf8, true),
+            Field::new("ts", DataType::Timestamp(TimeUnit::Nanosecond, None), true),
+            Field::new("note", DataType::Utf8, true),
+        ]));
+        let table = conn
+            .create_empty_table("test", schema)
+            .execute()
+            .await
+            .unwrap();
+
+        // Other types, another order and a missing nullable column
+        let data_schema = Arc::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, true),
+            Field::new("ts", DataType::Timestamp(TimeUnit::Millisecond, None), true),
+            Field::new("id", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            data_schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["a", "b"])),
+                Arc::new(TimestampMillisecondArray::from(vec![1, 2])),
+                Arc::new(Int32Array::from(vec![1, 2])),
+            ],
+        )
+        .unwrap();
+        let data = || RecordBatchIterator::new(vec![Ok(batch.clone())], data_schema.clone());
+        assert!(table.add(data()).execute().await.is_err());
+        table
+            .add(data())
+            .schema_mode(SchemaMode::Coerce)
+            .execute()
+            .await
+            .unwrap();
+
+        let batches = table
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = &batches[0];
+        assert_eq!(
+            batch["id"].as_any().downcast_ref::<Int64Array>().unwrap(),
+            &Int64Array::from(vec![1, 2])
+        );
+        assert_eq!(
+            batch["name"]
+                .as_any()
+                .downcast_ref::<LargeStringArray>()
+                .unwrap(),
+            &LargeStringArray::from(vec!["a", "b"])
+        );
+        assert_eq!(
+            batch["ts"]
+                .as_any()
+                .downcast_ref::<TimestampNanosecondArray>()
+                .unwrap(),
+            &TimestampNanosecondArray::from(vec![1_000_000, 2_000_000])
+        );
+        // Lance reads back the nulls of primitive columns as zeros, not those of strings
+        assert_eq!(batch["note"].null_count(), 2);
+
+        // Columns which are not in the table are rejected
+        let extra_schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("extra", DataType::Int32, true),
+        ]));
+        let extra = RecordBatch::try_new(
+            extra_schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![3])),
+                Arc::new(Int32Array::from(vec![3])),
+            ],
+        )
+        .unwrap();
+        let err = table
+            .add(RecordBatchIterator::new(vec![Ok(extra)], extra_schema))
+            .schema_mode(SchemaMode::Coerce)
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Schema { .. }), "{}", err);
+        assert_eq!(table.count_rows(None).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_add_missing_and_extra_columns() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("tag", DataType::Utf8, true),
+        ]));
+        let table = conn
+            .create_empty_table("test", schema)
+            .execute()
+            .await
+            .unwrap();
+
+        // The rows only have an id, and a column the table doesn't have
+        let data_schema = Arc::new(Schema::new(vec![
+            Field::new("extra", DataType::Utf8, true),
+            Field::new("id", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            data_schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["x", "y"])),
+                Arc::new(Int32Array::from(vec![1, 2])),
+            ],
+        )
+        .unwrap();
+        let data = || RecordBatchIterator::new(vec![Ok(batch.clone())], data_schema.clone());
+
+        let err = table
+            .add(data())
+            .on_missing(MissingColumns::FillNull)
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("'extra'"), "{}", err);
+        let err = table
+            .add(data())
+            .on_extra(ExtraColumns::Ignore)
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("'tag' is missing"), "{}", err);
+
+        table
+            .add(data())
+            .on_missing(MissingColumns::FillNull)
+            .on_extra(ExtraColumns::Ignore)
+            .execute()
+            .await
+            .unwrap();
+        let batches = table
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let names = batches[0]
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["id", "tag"]);
+        assert_eq!(batches[0]["tag"].null_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let table = conn
+            .create_table("my_table", merge_insert_test_batches(0, 0))
+            .execute()
+            .await
+            .unwrap();
+
+        // A retried add is only applied once
+        for _ in 0..2 {
+            table
+                .add(merge_insert_test_batches(10, 0))
+                .idempotency_key("add-1")
+                .execute()
+                .await
+                .unwrap();
+        }
+        assert_eq!(table.count_rows(None).await.unwrap(), 20);
+        table
+            .add(merge_insert_test_batches(20, 0))
+            .idempotency_key("add-2")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 30);
+
+        // A retried merge insert is only applied once
+        for _ in 0..2 {
+            let mut merge_insert_builder = table.merge_insert(&["i"]);
+            merge_insert_builder
+                .when_not_matched_insert_all()
+                .idempotency_key("merge-1");
+            merge_insert_builder
+                .execute(merge_insert_test_batches(25, 1))
+                .await
+                .unwrap();
+        }
+        assert_eq!(table.count_rows(None).await.unwrap(), 35);
+        let version = table.version().await.unwrap();
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder
+            .when_matched_update_all(None)
+            .idempotency_key("merge-1");
+        merge_insert_builder
+            .execute(merge_insert_test_batches(0, 2))
+            .await
+            .unwrap();
+        assert_eq!(table.version().await.unwrap(), version);
+        assert_eq!(
+            table.count_rows(Some("age = 2".to_string())).await.unwrap(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_insert() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        // Create a dataset with i=0..10
+        let batches = merge_insert_test_batches(0, 0);
+        let table = conn
+            .create_table("my_table", batches)
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+
+        // Create new data with i=5..15
+        let new_batches = Box::new(merge_insert_test_batches(5, 1));
+
+        // Perform a "insert if not exists"
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder.when_not_matched_insert_all();
+        merge_insert_builder.execute(new_batches).await.unwrap();
+        // Only 5 rows should actually be inserted
+        assert_eq!(table.count_rows(None).await.unwrap(), 15);
+
+        // Create new data with i=15..25 (no id matches)
+        let new_batches = Box::new(merge_insert_test_batches(15, 2));
+        // Perform a "bulk update" (should not affect anything)
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder.when_matched_update_all(None);
+        merge_insert_builder.execute(new_batches).await.unwrap();
+        // No new rows should have been inserted
+        assert_eq!(table.count_rows(None).await.unwrap(), 15);
+        assert_eq!(
+            table.count_rows(Some("age = 2".to_string())).await.unwrap(),
+            0
+        );
+
+        // Conditional update that only replaces the age=0 data
+        let new_batches = Box::new(merge_insert_test_batches(5, 3));
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder.when_matched_update_all(Some("target.age = 0".to_string()));
+        merge_insert_builder.execute(new_batches).await.unwrap();
+        assert_eq!(
+            table.count_rows(Some("age = 3".to_string())).await.unwrap(),
+            5
+        );
+
+        // Full upsert that also removes rows missing from the source
+        let new_batches = merge_insert_test_batches(10, 4);
+        let mut merge_insert_builder = table.merge_insert(&["i"]);
+        merge_insert_builder
+            .when_matched_update_all(None)
+            .when_not_matched_insert_all()
+            .when_not_matched_by_source_delete(None);
+        merge_insert_builder.execute(new_batches).await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+        assert_eq!(
+            table.count_rows(Some("age = 4".to_string())).await.unwrap(),
+            10
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_overwrite() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let batches = make_test_batches();
+        let schema = batches.schema().clone();
+        let table = conn.create_table("test", batches).execute().await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+
+        let batches = vec![RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(100..110))],
+        )
+        .unwrap()]
+        .into_iter()
+        .map(Ok);
+
+        let new_batches = RecordBatchIterator::new(batches.clone(), schema.clone());
+
+        // Can overwrite using AddDataOptions::mode
+        table
+            .add(new_batches)
+            .mode(AddDataMode::Overwrite)
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+        assert_eq!(table.name(), "test");
+
+        // Can overwrite using underlying WriteParams (which
+        // take precedence over AddDataOptions::mode)
+
+        let param: WriteParams = WriteParams {
+            mode: WriteMode::Overwrite,
+            ..Default::default()
+        };
+
+        let new_batches = RecordBatchIterator::new(batches.clone(), schema.clone());
+        table
+            .add(new_batches)
+            .write_options(WriteOptions {
+                lance_write_params: Some(param),
+                ..Default::default()
+            })
+            .mode(AddDataMode::Append)
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+        assert_eq!(table.name(), "test");
+    }
+
+    #[tokio::test]
+    async fn test_update_with_predicate() {
+        let tmp_dir = tempdir().unwrap();
+        let dataset_path = tmp_dir.path().join("test.lance");
+        let uri = dataset_path.to_str().unwrap();
+        let conn = connect(uri)
+            .read_consistency_interval(Duration::from_secs(0))
+            .execute()
+            .await
+            .unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+        ]));
+
+        let record_batch_iter = RecordBatchIterator::new(
+            vec![RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from_iter_values(0..10)),
+                    Arc::new(StringArray::from_iter_values(vec![
+                        "a", "b", "c", "d", "e", "f", "g", "h", "i", "j",
+                    ])),
+                ],
+            )
+            .unwrap()]
+            .into_iter()
+            .map(Ok),
+            schema.clone(),
+        );
+
+        let table = conn
+            .create_table("my_table", record_batch_iter)
+            .execute()
+            .await
+            .unwrap();
+
+        table
+            .update()
+            .only_if("id > 5")
+            .column("name", "'foo'")
+            .execute()
+            .await
+            .unwrap();
+
+        let mut batches = table
+            .query()
+            .select(Select::columns(&["id", "name"]))
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        while let Some(batch) = batches.pop() {
+            let ids = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>();
+            let names = batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>();
+            for (i, name) in names.iter().enumerate() {
+                let id = ids[i].unwrap();
+                let name = name.unwrap();
+                if id > 5 {
+                    assert_eq!(name, "foo");
+                } else {
+                    assert_eq!(name, &format!("{}", (b'a' + id as u8) as char));
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_all_types() {
+        let tmp_dir = tempdir().unwrap();
+        let dataset_path = tmp_dir.path().join("test.lance");
+        let uri = dataset_path.to_str().unwrap();
+        let conn = connect(uri)
+            .read_consistency_interval(Duration::from_secs(0))
+            .execute()
+            .await
+            .unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("int32", DataType::Int32, false),
+            Field::new("int64", DataType::Int64, false),
+            Field::new("uint32", DataType::UInt32, false),
+            Field::new("string", DataType::Utf8, false),
+            Field::new("large_string", DataType::LargeUtf8, false),
+            Field::new("float32", DataType::Float32, false),
+            Field::new("float64", DataType::Float64, false),
+            Field::new("bool", DataType::Boolean, false),
+            Field::new("date32", DataType::Date32, false),
+            Field::new(
+                "timestamp_ns",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+            Field::new(
+                "timestamp_ms",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new(
+                "vec_f32",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+                false,
+            ),
+            Field::new(
+                "vec_f64",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float64, true)), 2),
+                false,
+            ),
+        ]));
+
+        let record_batch_iter = RecordBatchIterator::new(
+            vec![RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from_iter_values(0..10)),
+                    Arc::new(Int64Array::from_iter_values(0..10)),
+                    Arc::new(UInt32Array::from_iter_values(0..10)),
+                    Arc::new(StringArray::from_iter_values(vec![
+                        "a", "b", "c", "d", "e", "f", "g", "h", "i", "j",
+                    ])),
+                    Arc::new(LargeStringArray::from_iter_values(vec![
+                        "a", "b", "c", "d", "e", "f", "g", "h", "i", "j",
+                    ])),
+                    Arc::new(Float32Array::from_iter_values((0..10).map(|i| i as f32))),
+                    Arc::new(Float64Array::from_iter_values((0..10).map(|i| i as f64))),
+                    Arc::new(Into::<BooleanArray>::into(vec![
+                        true, false, true, false, true, false, true, false, true, false,
+                    ])),
+                    Arc::new(Date32Array::from_iter_values(0..10)),
+                    Arc::new(TimestampNanosecondArray::from_iter_values(0..10)),
+                    Arc::new(TimestampMillisecondArray::from_iter_values(0..10)),
+                    Arc::new(
+                        create_fixed_size_list(
+                            Float32Array::from_iter_values((0..20).map(|i| i as f32)),
+                            2,
+                        )
+                        .unwrap(),
+                    ),
+                    Arc::new(
+                        create_fixed_size_list(
+                            Float64Array::from_iter_values((0..20).map(|i| i as f64)),
+                            2,
+                        )
+                        .unwrap(),
+                    ),
+                ],
+            )
+            .unwrap()]
+            .into_iter()
+            .map(Ok),
+            schema.clone(),
+        );
+
+        let table = conn
+            .create_table("my_table", record_batch_iter)
+            .execute()
+            .await
+            .unwrap();
+
+        // check it can do update for each type
+        let updates: Vec<(&str, &str)> = vec![
+            ("string", "'foo'"),
+            ("large_string", "'large_foo'"),
+            ("int32", "1"),
+            ("int64", "1"),
+            ("uint32", "1"),
+            ("float32", "1.0"),
+            ("float64", "1.0"),
+            ("bool", "true"),
+            ("date32", "1"),
+            ("timestamp_ns", "1"),
+            ("timestamp_ms", "1"),
+            ("vec_f32", "[1.0, 1.0]"),
+            ("vec_f64", "[1.0, 1.0]"),
+        ];
+
+        let mut update_op = table.update();
+        for (column, value) in updates {
+            update_op = update_op.column(column, value);
+        }
+        update_op.execute().await.unwrap();
+
+        let mut batches = table
+            .query()
+            .select(Select::columns(&[
+                "string",
+                "large_string",
+                "int32",
+                "int64",
+                "uint32",
+                "float32",
+                "float64",
+                "bool",
+                "date32",
+                "timestamp_ns",
+                "timestamp_ms",
+                "vec_f32",
+                "vec_f64",
+            ]))
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = batches.pop().unwrap();
+
+        macro_rules! assert_column {
+            ($column:expr, $array_type:ty, $expected:expr) => {
+                let array = $column
+                    .as_any()
+                    .downcast_ref::<$array_type>()
+                    .unwrap()
+                    .iter()
+                    .collect::<Vec<_>>();
+                for v in array {
+                    assert_eq!(v, Some($expected));
+                }
+            };
+        }
+
+        assert_column!(batch.column(0), StringArray, "foo");
+        assert_column!(batch.column(1), LargeStringArray, "large_foo");
+        assert_column!(batch.column(2), Int32Array, 1);
+        assert_column!(batch.column(3), Int64Array, 1);
+        assert_column!(batch.column(4), UInt32Array, 1);
+        assert_column!(batch.column(5), Float32Array, 1.0);
+        assert_column!(batch.column(6), Float64Array, 1.0);
+        assert_column!(batch.column(7), BooleanArray, true);
+        assert_column!(batch.column(8), Date32Array, 1);
+        assert_column!(batch.column(9), TimestampNanosecondArray, 1);
+        assert_column!(batch.column(10), TimestampMillisecondArray, 1);
+
+        let array = batch
+            .column(11)
+            .as_any()
+            .downcast_ref::<FixedSizeListArray>()
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        for v in array {
+            let v = v.unwrap();
+            let f32array = v.as_any().downcast_ref::<Float32Array>().unwrap();
+            for v in f32array {
+                assert_eq!(v, Some(1.0));
+            }
+        }
+
+        let array = batch
+            .column(12)
+            .as_any()
+            .downcast_ref::<FixedSizeListArray>()
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        for v in array {
+            let v = v.unwrap();
+            let f64array = v.as_any().downcast_ref::<Float64Array>().unwrap();
+            for v in f64array {
+                assert_eq!(v, Some(1.0));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_via_expr() {
+        let tmp_dir = tempdir().unwrap();
+        let dataset_path = tmp_dir.path().join("test.lance");
+        let uri = dataset_path.to_str().unwrap();
+        let conn = connect(uri)
+            .read_consistency_interval(Duration::from_secs(0))
+            .execute()
+            .await
+            .unwrap();
+        let tbl = conn
+            .create_table("my_table", make_test_batches())
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(1, tbl.count_rows(Some("i == 0".to_string())).await.unwrap());
+        tbl.update().column("i", "i+1").execute().await.unwrap();
+        assert_eq!(0, tbl.count_rows(Some("i == 0".to_string())).await.unwrap());
+
+        tbl.update()
+            .only_if("i > $min")
+            .bind("min", 5)
+            .column("i", "i * 10")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(
+            5,
+            tbl.count_rows(Some("i >= 60".to_string())).await.unwrap()
+        );
+
+        let err = tbl
+            .update()
+            .column("missing", "1")
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Schema { .. }), "{:?}", err);
+        let err = tbl
+            .update()
+            .bind("min", 5)
+            .column("i", "1")
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+
+    #[derive(Default, Debug)]
+    struct NoOpCacheWrapper {
+        called: AtomicBool,
+    }
+
+    impl NoOpCacheWrapper {
+        fn called(&self) -> bool {
+            self.called.load(Ordering::Relaxed)
+        }
+    }
+
+    impl WrappingObjectStore for NoOpCacheWrapper {
+        fn wrap(
+            &self,
+            original: Arc<dyn object_store::ObjectStore>,
+        ) -> Arc<dyn object_store::ObjectStore> {
+            self.called.store(true, Ordering::Relaxed);
+            original
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_table_options() {
+        let tmp_dir = tempdir().unwrap();
+        let dataset_path = tmp_dir.path().join("test.lance");
+        let uri = dataset_path.to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let batches = make_test_batches();
+
+        conn.create_table("my_table", batches)
+            .execute()
+            .await
+            .unwrap();
+
+        let wrapper = Arc::new(NoOpCacheWrapper::default());
+
+        let object_store_params = ObjectStoreParams {
+            object_store_wrapper: Some(wrapper.clone()),
+            ..Default::default()
+        };
+        let param = ReadParams {
+            store_options: Some(object_store_params),
+            ..Default::default()
+        };
+        assert!(!wrapper.called());
+        conn.open_table("my_table")
+            .lance_read_params(param)
+            .execute()
+            .await
+            .unwrap();
+        assert!(wrapper.called());
+    }
+
+    fn merge_insert_test_batches(
+        offset: i32,
+        age: i32,
+    ) -> impl RecordBatchReader + Send + Sync + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("i", DataType::Int32, false),
+            Field::new("age", DataType::Int32, false),
+        ]));
+        RecordBatchIterator::new(
+            vec![RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from_iter_values(offset..(offset + 10))),
+                    Arc::new(Int32Array::from_iter_values(iter::repeat(age).take(10))),
+                ],
+            )],
+            schema,
+        )
+    }
+
+    fn make_test_batches() -> impl RecordBatchReader + Send + Sync + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        RecordBatchIterator::new(
+            vec![RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from_iter_values(0..10))],
+            )],
+            schema,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_create_index() {
+        use arrow_array::RecordBatch;
+        use arrow_schema::{DataType, Field, Schema as ArrowSchema};
+        use rand;
+        use std::iter::repeat_with;
+
+        use arrow_array::Float32Array;
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let dimension = 16;
+        let schema = Arc::new(ArrowSchema::new(vec![Field::new(
+            "embeddings",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dimension,
+            ),
+            false,
+        )]));
+
+        let mut rng = rand::thread_rng();
+        let float_arr = Float32Array::from(
+            repeat_with(|| rng.gen::<f32>())
+                .take(512 * dimension as usize)
+                .collect::<Vec<f32>>(),
+        );
+
+        let vectors = Arc::new(create_fixed_size_list(float_arr, dimension).unwrap());
+        let batches = RecordBatchIterator::new(
+            vec![RecordBatch::try_new(schema.clone(), vec![vectors.clone()]).unwrap()]
+                .into_iter()
+                .map(Ok),
+            schema,
+        );
+
+        let table = conn.create_table("test", batches).execute().await.unwrap();
+
+        assert_eq!(
+            table
+                .as_native()
+                .unwrap()
+                .count_indexed_rows("my_index")
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            table
+                .as_native()
+                .unwrap()
+                .count_unindexed_rows("my_index")
+                .await
+                .unwrap(),
+            None
+        );
+
+        table
+            .create_index(&["embeddings"], Index::Auto)
+            .execute()
+            .await
+            .unwrap();
+
+        let index_configs = table.list_indices().await.unwrap();
+        assert_eq!(index_configs.len(), 1);
+        let index = index_configs.into_iter().next().unwrap();
+        assert_eq!(index.index_type, crate::index::IndexType::IvfPq);
+        assert_eq!(index.columns, vec!["embeddings".to_string()]);
+        assert_eq!(table.count_rows(None).await.unwrap(), 512);
+        assert_eq!(table.name(), "test");
+
+        let indices = table.as_native().unwrap().load_indices().await.unwrap();
+        let index_uuid = &indices[0].index_uuid;
+        assert_eq!(
+            table
+                .as_native()
+                .unwrap()
+                .count_indexed_rows(index_uuid)
+                .await
+                .unwrap(),
+            Some(512)
+        );
+        assert_eq!(
+            table
+                .as_native()
+                .unwrap()
+                .count_unindexed_rows(index_uuid)
+                .await
+                .unwrap(),
+            Some(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fast_search() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let dimension = 16;
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "embeddings",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dimension,
+            ),
+            false,
+        )]));
+        let make_batches = |values: Vec<f32>| {
+            let vectors = create_fixed_size_list(Float32Array::from(values), dimension).unwrap();
+            RecordBatchIterator::new(
+                vec![RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(vectors)],
+                )],
+                schema.clone(),
+            )
+        };
+        let mut rng = rand::thread_rng();
+        let values = (0..512 * dimension).map(|_| rng.gen::<f32>()).collect();
+        let table = conn
+            .create_table("test", make_batches(values))
+            .execute()
+            .await
+            .unwrap();
+        table
+            .create_index(&["embeddings"], Index::Auto)
+            .execute()
+            .await
+            .unwrap();
+        // Unindexed rows exactly matching the query vector
+        table
+            .add(make_batches(vec![10.0; 2 * dimension as usize]))
+            .execute()
+            .await
+            .unwrap();
+
+        let closest_distance = |fast_search: bool| {
+            let table = table.clone();
+            async move {
+                let mut query = table
+                    .query()
+                    .nearest_to(vec![10.0f32; dimension as usize])
+                    .unwrap()
+                    .limit(1);
+                if fast_search {
+                    query = query.fast_search();
+                }
+                let batches = query
+                    .execute()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                batches[0]["_distance"]
+                    .as_primitive::<Float32Type>()
+                    .value(0)
+            }
+        };
+        assert_eq!(closest_distance(false).await, 0.0);
+        assert!(closest_distance(true).await > 0.0);
+
+        let result = table
+            .query()
+            .nearest_to(vec![10.0f32; dimension as usize])
+            .unwrap()
+            .unindexed(UnindexedPolicy::Error)
+            .execute()
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::UnindexedRows { num_rows: 2, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_missing_indices() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let dimension = 16;
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "embeddings",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dimension,
+            ),
+            false,
+        )]));
+        let mut rng = rand::thread_rng();
+        let values = (0..512 * dimension)
+            .map(|_| rng.gen::<f32>())
+            .collect::<Vec<_>>();
+        let vectors = create_fixed_size_list(Float32Array::from(values), dimension).unwrap();
+        let batches = RecordBatchIterator::new(
+            vec![RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(vectors)],
+            )],
+            schema.clone(),
+        );
+        let table = conn.create_table("test", batches).execute().await.unwrap();
+        table
+            .create_index(&["embeddings"], Index::Auto)
+            .execute()
+            .await
+            .unwrap();
+        assert!(table.rebuild_missing_indices().await.unwrap().is_empty());
+
+        // Lose the files of the index
+        let indices_dir = tmp_dir.path().join("test.lance").join("_indices");
+        for entry in std::fs::read_dir(&indices_dir).unwrap() {
+            let dir = entry.unwrap().path();
+            for file in std::fs::read_dir(&dir).unwrap() {
+                std::fs::remove_file(file.unwrap().path()).unwrap();
+            }
+        }
+
+        let search = || async {
+            table
+                .query()
+                .nearest_to(vec![0.5f32; dimension as usize])
+                .unwrap()
+                .limit(5)
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap()
+                .iter()
+                .map(|batch| batch.num_rows())
+                .sum::<usize>()
+        };
+        // Falls back to a flat search
+        assert_eq!(search().await, 5);
+
+        let rebuilt = table.rebuild_missing_indices().await.unwrap();
+        assert_eq!(rebuilt, vec!["embeddings_idx".to_string()]);
+        assert_eq!(table.list_indices().await.unwrap().len(), 1);
+        assert!(table.rebuild_missing_indices().await.unwrap().is_empty());
+        assert_eq!(search().await, 5);
+    }
+
+    #[tokio::test]
+    async fn test_create_index_async() {
+        use crate::index::{IndexBuildStage, IndexProgress};
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let dimension = 16;
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "embeddings",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dimension,
+            ),
+            false,
+        )]));
+        let float_arr = Float32Array::from_iter_values((0..512 * dimension).map(|i| i as f32));
+        let vectors = Arc::new(create_fixed_size_list(float_arr, dimension).unwrap());
+        let batches = RecordBatchIterator::new(
+            vec![Ok(
+                RecordBatch::try_new(schema.clone(), vec![vectors]).unwrap()
+            )],
+            schema,
+        );
+        let table = conn.create_table("test", batches).execute().await.unwrap();
+
+        // A cancelled build does not create an index
+        let job = table.create_index_async(&["embeddings"], Index::Auto);
+        job.cancel();
+        assert_eq!(job.progress().stage, IndexBuildStage::Cancelled);
+        assert!(job.await_done().await.is_err());
+        assert!(table.list_indices().await.unwrap().is_empty());
+
+        let job = table.create_index_async(
+            &["embeddings"],
+            Index::IvfPq(IvfPqIndexBuilder::default().num_partitions(2)),
+        );
+        let progress = job.progress();
+        assert_eq!(progress.stage, IndexBuildStage::Pending);
+        while !job.is_finished() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            job.progress(),
+            IndexProgress {
+                stage: IndexBuildStage::Done,
+                total_rows: 512,
+                rows_processed: 512,
+                num_partitions: Some(2),
+                partitions_trained: 2,
+            }
+        );
+        job.await_done().await.unwrap();
+        assert_eq!(table.list_indices().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cluster() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        // Two well separated groups of vectors
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 4),
+                false,
+            ),
+        ]));
+        let values = (0..200)
+            .flat_map(|row| {
+                let offset = if row < 100 { 0.0 } else { 100.0 };
+                (0..4).map(move |i| offset + ((row + i) % 5) as f32)
+            })
+            .collect::<Vec<_>>();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..200)),
+                Arc::new(create_fixed_size_list(Float32Array::from(values), 4).unwrap()),
+            ],
+        )
+        .unwrap();
+        let table = conn
+            .create_table("test", RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await
+            .unwrap();
+
+        let centroids = table
+            .cluster("vector", 2)
+            .sample_rate(100)
             .execute()
             .await
             .unwrap();
-        let tbl = conn
-            .create_table("my_table", make_test_batches())
+        assert_eq!(centroids.len(), 2);
+        let batches = table
+            .query()
+            .select(Select::columns(&["id", "cluster_id"]))
             .execute()
             .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
             .unwrap();
-        assert_eq!(1, tbl.count_rows(Some("i == 0".to_string())).await.unwrap());
-        tbl.update().column("i", "i+1").execute().await.unwrap();
-        assert_eq!(0, tbl.count_rows(Some("i == 0".to_string())).await.unwrap());
-    }
-
-    #[derive(Default, Debug)]
-    struct NoOpCacheWrapper {
-        called: AtomicBool,
-    }
-
-    impl NoOpCacheWrapper {
-        fn called(&self) -> bool {
-            self.called.load(Ordering::Relaxed)
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+        let ids = batch["id"].as_primitive::<arrow_array::types::Int32Type>();
+        let clusters = batch["cluster_id"].as_primitive::<arrow_array::types::UInt32Type>();
+        let first = clusters.value(ids.values().iter().position(|id| *id == 0).unwrap());
+        for row in 0..batch.num_rows() {
+            assert_eq!(clusters.value(row) == first, ids.value(row) < 100);
         }
-    }
 
-    impl WrappingObjectStore for NoOpCacheWrapper {
-        fn wrap(
-            &self,
-            original: Arc<dyn object_store::ObjectStore>,
-        ) -> Arc<dyn object_store::ObjectStore> {
-            self.called.store(true, Ordering::Relaxed);
-            original
-        }
+        // Clustering again replaces the column
+        table.cluster("vector", 3).execute().await.unwrap();
+        let schema = table.schema().await.unwrap();
+        assert_eq!(schema.fields().len(), 3);
+        assert!(table.cluster("id", 2).execute().await.is_err());
     }
 
     #[tokio::test]
-    async fn test_open_table_options() {
+    async fn test_split() {
         let tmp_dir = tempdir().unwrap();
-        let dataset_path = tmp_dir.path().join("test.lance");
-        let uri = dataset_path.to_str().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
         let conn = connect(uri).execute().await.unwrap();
 
-        let batches = make_test_batches();
-
-        conn.create_table("my_table", batches)
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("category", DataType::Utf8, false),
+        ]));
+        // 50 rows of "a", 30 of "b" and 20 of "c"
+        let categories = (0..100)
+            .map(|id| match id {
+                0..=49 => "a",
+                50..=79 => "b",
+                _ => "c",
+            })
+            .collect::<Vec<_>>();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..100)),
+                Arc::new(StringArray::from(categories)),
+            ],
+        )
+        .unwrap();
+        let table = conn
+            .create_table("test", RecordBatchIterator::new(vec![Ok(batch)], schema))
             .execute()
             .await
             .unwrap();
 
-        let wrapper = Arc::new(NoOpCacheWrapper::default());
-
-        let object_store_params = ObjectStoreParams {
-            object_store_wrapper: Some(wrapper.clone()),
-            ..Default::default()
-        };
-        let param = ReadParams {
-            store_options: Some(object_store_params),
-            ..Default::default()
-        };
-        assert!(!wrapper.called());
-        conn.open_table("my_table")
-            .lance_read_params(param)
+        let shards = table
+            .split(SplitStrategy::BySize { max_rows: 40 })
             .execute()
             .await
             .unwrap();
-        assert!(wrapper.called());
-    }
-
-    fn merge_insert_test_batches(
-        offset: i32,
-        age: i32,
-    ) -> impl RecordBatchReader + Send + Sync + 'static {
-        let schema = Arc::new(Schema::new(vec![
-            Field::new("i", DataType::Int32, false),
-            Field::new("age", DataType::Int32, false),
-        ]));
-        RecordBatchIterator::new(
-            vec![RecordBatch::try_new(
-                schema.clone(),
-                vec![
-                    Arc::new(Int32Array::from_iter_values(offset..(offset + 10))),
-                    Arc::new(Int32Array::from_iter_values(iter::repeat(age).take(10))),
-                ],
-            )],
-            schema,
-        )
-    }
+        let mut rows = Vec::new();
+        for (i, shard) in shards.iter().enumerate() {
+            assert_eq!(shard.name(), format!("test_shard_{}", i));
+            rows.push(shard.count_rows(None).await.unwrap());
+        }
+        assert_eq!(rows, vec![40, 40, 20]);
+        assert_eq!(table.count_rows(None).await.unwrap(), 100);
+        assert!(conn
+            .table_names()
+            .execute()
+            .await
+            .unwrap()
+            .contains(&"test_shard_2".to_string()));
 
-    fn make_test_batches() -> impl RecordBatchReader + Send + Sync + 'static {
-        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
-        RecordBatchIterator::new(
-            vec![RecordBatch::try_new(
-                schema.clone(),
-                vec![Arc::new(Int32Array::from_iter_values(0..10))],
-            )],
-            schema,
-        )
+        // The shards already exist
+        assert!(matches!(
+            table
+                .split(SplitStrategy::BySize { max_rows: 40 })
+                .execute()
+                .await,
+            Err(Error::TableAlreadyExists { .. })
+        ));
+
+        let shards = table
+            .split(SplitStrategy::ByColumn {
+                column: "category".to_string(),
+                num_shards: 2,
+            })
+            .name_prefix("by_category_")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(shards.len(), 2);
+        let mut rows = Vec::new();
+        for shard in &shards {
+            rows.push(shard.count_rows(None).await.unwrap());
+        }
+        assert_eq!(rows, vec![50, 50]);
+        assert_eq!(
+            shards[0]
+                .count_rows(Some("category = 'a'".to_string()))
+                .await
+                .unwrap(),
+            50
+        );
     }
 
     #[tokio::test]
-    async fn test_create_index() {
-        use arrow_array::RecordBatch;
-        use arrow_schema::{DataType, Field, Schema as ArrowSchema};
-        use rand;
-        use std::iter::repeat_with;
-
-        use arrow_array::Float32Array;
+    async fn test_create_ivf_hnsw_index() {
+        use crate::index::vector::{IvfHnswPqIndexBuilder, IvfHnswSqIndexBuilder};
 
         let tmp_dir = tempdir().unwrap();
         let uri = tmp_dir.path().to_str().unwrap();
         let conn = connect(uri).execute().await.unwrap();
 
         let dimension = 16;
-        let schema = Arc::new(ArrowSchema::new(vec![Field::new(
+        let schema = Arc::new(Schema::new(vec![Field::new(
             "embeddings",
             DataType::FixedSizeList(
                 Arc::new(Field::new("item", DataType::Float32, true)),
@@ -2165,77 +6617,265 @@ mod tests {
             ),
             false,
         )]));
-
         let mut rng = rand::thread_rng();
         let float_arr = Float32Array::from(
-            repeat_with(|| rng.gen::<f32>())
+            iter::repeat_with(|| rng.gen::<f32>())
                 .take(512 * dimension as usize)
                 .collect::<Vec<f32>>(),
         );
-
         let vectors = Arc::new(create_fixed_size_list(float_arr, dimension).unwrap());
         let batches = RecordBatchIterator::new(
-            vec![RecordBatch::try_new(schema.clone(), vec![vectors.clone()]).unwrap()]
-                .into_iter()
-                .map(Ok),
+            vec![RecordBatch::try_new(schema.clone(), vec![vectors])],
             schema,
         );
-
         let table = conn.create_table("test", batches).execute().await.unwrap();
 
+        table
+            .create_index(
+                &["embeddings"],
+                Index::IvfHnswPq(
+                    IvfHnswPqIndexBuilder::default()
+                        .num_partitions(2)
+                        .num_edges(8)
+                        .ef_construction(50),
+                ),
+            )
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.list_indices().await.unwrap().len(), 1);
+        let results = table
+            .query()
+            .nearest_to(&[0.5; 16])
+            .unwrap()
+            .ef(40)
+            .limit(5)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results.iter().map(|b| b.num_rows()).sum::<usize>(), 5);
+
+        table
+            .create_index(
+                &["embeddings"],
+                Index::IvfHnswSq(IvfHnswSqIndexBuilder::default().num_partitions(2)),
+            )
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.list_indices().await.unwrap().len(), 1);
+
+        // The SQ codes are a quarter of the vectors, the PQ codes much smaller
+        let sq_footprint = table
+            .create_index(
+                &["embeddings"],
+                Index::IvfHnswSq(IvfHnswSqIndexBuilder::default().num_partitions(2)),
+            )
+            .memory_footprint()
+            .await
+            .unwrap();
+        assert_eq!(sq_footprint.vector_bytes, 512 * 16 * 4);
+        assert_eq!(sq_footprint.code_bytes, 512 * 16 + 512 * 8);
+        assert!(sq_footprint.graph_bytes > 0);
+        let pq_footprint = table
+            .create_index(&["embeddings"], Index::Auto)
+            .memory_footprint()
+            .await
+            .unwrap();
+        assert!(pq_footprint.code_bytes < sq_footprint.code_bytes);
+        assert!(Index::BTree(Default::default())
+            .memory_footprint(512, 16)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_index_with_centroids() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let dimension = 16;
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "embeddings",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dimension,
+            ),
+            false,
+        )]));
+        let mut rng = rand::thread_rng();
+        let mut tables = Vec::new();
+        for name in ["tenant_a", "tenant_b"] {
+            let float_arr = Float32Array::from(
+                iter::repeat_with(|| rng.gen::<f32>())
+                    .take(512 * dimension as usize)
+                    .collect::<Vec<f32>>(),
+            );
+            let vectors = Arc::new(create_fixed_size_list(float_arr, dimension).unwrap());
+            let batches = RecordBatchIterator::new(
+                vec![RecordBatch::try_new(schema.clone(), vec![vectors])],
+                schema.clone(),
+            );
+            tables.push(conn.create_table(name, batches).execute().await.unwrap());
+        }
+
+        tables[0]
+            .create_index(
+                &["embeddings"],
+                Index::IvfPq(IvfPqIndexBuilder::default().num_partitions(4)),
+            )
+            .execute()
+            .await
+            .unwrap();
+        let centroids = tables[0].ivf_centroids("embeddings").await.unwrap();
+        assert_eq!(centroids.len(), 4);
+        assert_eq!(centroids.value_length(), dimension);
+
+        // The centroids must match the partitions and the vectors
+        let result = tables[1]
+            .create_index(
+                &["embeddings"],
+                Index::IvfPq(
+                    IvfPqIndexBuilder::default()
+                        .num_partitions(8)
+                        .centroids(centroids.clone()),
+                ),
+            )
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+        let wrong_dimension =
+            create_fixed_size_list(Float32Array::from_iter_values((0..32).map(|i| i as f32)), 8)
+                .unwrap();
+        let result = tables[1]
+            .create_index(
+                &["embeddings"],
+                Index::IvfHnswSq(IvfHnswSqIndexBuilder::default().centroids(wrong_dimension)),
+            )
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+
+        tables[1]
+            .create_index(
+                &["embeddings"],
+                Index::IvfPq(IvfPqIndexBuilder::default().centroids(centroids.clone())),
+            )
+            .execute()
+            .await
+            .unwrap();
         assert_eq!(
-            table
-                .as_native()
-                .unwrap()
-                .count_indexed_rows("my_index")
-                .await
-                .unwrap(),
-            None
+            tables[1].ivf_centroids("embeddings").await.unwrap(),
+            centroids
         );
-        assert_eq!(
-            table
-                .as_native()
-                .unwrap()
-                .count_unindexed_rows("my_index")
-                .await
-                .unwrap(),
-            None
+        let results = tables[1]
+            .query()
+            .nearest_to(&[0.5; 16])
+            .unwrap()
+            .limit(5)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results.iter().map(|b| b.num_rows()).sum::<usize>(), 5);
+    }
+
+    #[cfg(not(feature = "cuda"))]
+    #[tokio::test]
+    async fn test_create_index_cuda_requires_feature() {
+        use crate::index::vector::Accelerator;
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "embeddings",
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 4),
+            false,
+        )]));
+        let vectors =
+            create_fixed_size_list(Float32Array::from_iter_values((0..64).map(|i| i as f32)), 4)
+                .unwrap();
+        let batches = RecordBatchIterator::new(
+            vec![RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(vectors)],
+            )],
+            schema,
+        );
+        let table = conn.create_table("test", batches).execute().await.unwrap();
+        let result = table
+            .create_index(
+                &["embeddings"],
+                Index::IvfPq(IvfPqIndexBuilder::default().accelerator(Accelerator::Cuda(0))),
+            )
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+        assert!(table.list_indices().await.unwrap().is_empty());
+    }
+
+    #[cfg(not(feature = "cuda"))]
+    #[tokio::test]
+    async fn test_gpu_search_requires_feature() {
+        use crate::index::vector::Accelerator;
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(
+                "embeddings",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 4),
+                false,
+            ),
+            Field::new("id", DataType::Int32, false),
+        ]));
+        let vectors =
+            create_fixed_size_list(Float32Array::from_iter_values((0..64).map(|i| i as f32)), 4)
+                .unwrap();
+        let batches = RecordBatchIterator::new(
+            vec![RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(vectors),
+                    Arc::new(Int32Array::from_iter_values(0..16)),
+                ],
+            )],
+            schema,
         );
+        let table = conn.create_table("test", batches).execute().await.unwrap();
 
+        let result = table.gpu_search("embeddings", Accelerator::Cuda(0)).await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+        let result = table.gpu_search("id", Accelerator::Cuda(0)).await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+        let result = table.gpu_search("missing", Accelerator::Cuda(0)).await;
+        assert!(matches!(result, Err(Error::Schema { .. })));
         table
-            .create_index(&["embeddings"], Index::Auto)
-            .execute()
+            .gpu_search("embeddings", Accelerator::Cpu)
             .await
             .unwrap();
 
-        let index_configs = table.list_indices().await.unwrap();
-        assert_eq!(index_configs.len(), 1);
-        let index = index_configs.into_iter().next().unwrap();
-        assert_eq!(index.index_type, crate::index::IndexType::IvfPq);
-        assert_eq!(index.columns, vec!["embeddings".to_string()]);
-        assert_eq!(table.count_rows(None).await.unwrap(), 512);
-        assert_eq!(table.name(), "test");
-
-        let indices = table.as_native().unwrap().load_indices().await.unwrap();
-        let index_uuid = &indices[0].index_uuid;
-        assert_eq!(
-            table
-                .as_native()
-                .unwrap()
-                .count_indexed_rows(index_uuid)
-                .await
-                .unwrap(),
-            Some(512)
-        );
-        assert_eq!(
-            table
-                .as_native()
-                .unwrap()
-                .count_unindexed_rows(index_uuid)
-                .await
-                .unwrap(),
-            Some(0)
-        );
+        // Searches keep running on the CPU
+        let results = table
+            .query()
+            .nearest_to(&[0.0, 1.0, 2.0, 3.0])
+            .unwrap()
+            .limit(1)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let ids = results[0]["id"].as_primitive::<arrow_array::types::Int32Type>();
+        assert_eq!(ids.value(0), 0);
     }
 
     fn create_fixed_size_list<T: Array>(values: T, list_size: i32) -> Result<FixedSizeListArray> {
@@ -2309,6 +6949,321 @@ mod tests {
         let index = index_configs.into_iter().next().unwrap();
         assert_eq!(index.index_type, crate::index::IndexType::BTree);
         assert_eq!(index.columns, vec!["i".to_string()]);
+
+        // Inspect and drop the index by name
+        assert_eq!(index.name, "i_idx");
+        table.add(some_sample_data()).execute().await.unwrap();
+        let stats = table.index_stats(&index.name).await.unwrap().unwrap();
+        assert_eq!(stats.index_type, crate::index::IndexType::BTree);
+        assert_eq!(stats.num_indexed_rows, 1);
+        assert_eq!(stats.num_unindexed_rows, 1);
+        assert_eq!(table.index_stats("missing").await.unwrap(), None);
+
+        table.drop_index(&index.name).await.unwrap();
+        assert!(table.list_indices().await.unwrap().is_empty());
+        assert!(matches!(
+            table.drop_index(&index.name).await,
+            Err(Error::IndexNotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_scalar_index_filter_pushdown() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("user_id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..1000)),
+                Arc::new(StringArray::from_iter_values(
+                    (0..1000).map(|i| format!("user{}", i)),
+                )),
+            ],
+        )
+        .unwrap();
+        let conn = ConnectBuilder::new(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("users", RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await
+            .unwrap();
+
+        let query = table.query().only_if("user_id = 42");
+        let plan = query.explain_plan(false).await.unwrap();
+        assert!(!plan.contains("MaterializeIndex"), "{}", plan);
+
+        table
+            .create_index(&["user_id"], Index::BTree(BTreeIndexBuilder::default()))
+            .execute()
+            .await
+            .unwrap();
+
+        let plan = query.explain_plan(false).await.unwrap();
+        assert!(plan.contains("MaterializeIndex"), "{}", plan);
+        let batches = query
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(batch.num_rows(), 1);
+        assert_eq!(batch["name"].as_string::<i32>().value(0), "user42");
+    }
+
+    #[tokio::test]
+    async fn test_time_range_with_scalar_index() {
+        use chrono::{TimeZone, Utc};
+
+        use crate::query::TimeRange;
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+
+        // One row per hour, starting at the epoch
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(
+                "ts",
+                DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+                false,
+            ),
+            Field::new("day", DataType::Date32, false),
+        ]));
+        let hour = 60 * 60 * 1000;
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(
+                    TimestampMillisecondArray::from_iter_values((0..72).map(|i| i * hour))
+                        .with_timezone("UTC"),
+                ),
+                Arc::new(Date32Array::from_iter_values((0..72).map(|i| i / 24))),
+            ],
+        )
+        .unwrap();
+        let conn = ConnectBuilder::new(uri).execute().await.unwrap();
+        let table = conn
+            .create_table(
+                "events",
+                RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+        for column in ["ts", "day"] {
+            table
+                .create_index(&[column], Index::BTree(BTreeIndexBuilder::default()))
+                .execute()
+                .await
+                .unwrap();
+        }
+
+        let count = |column: &'static str, range: TimeRange| {
+            let table = table.clone();
+            async move {
+                table
+                    .query()
+                    .only_within(column, range)
+                    .execute()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+                    .iter()
+                    .map(|batch| batch.num_rows())
+                    .sum::<usize>()
+            }
+        };
+        let start = Utc.with_ymd_and_hms(1970, 1, 2, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(1970, 1, 2, 6, 0, 0).unwrap();
+        assert_eq!(count("ts", TimeRange::new(start, end)).await, 6);
+        assert_eq!(count("ts", TimeRange::since(start)).await, 48);
+        assert_eq!(count("day", TimeRange::new(start, end)).await, 24);
+        assert_eq!(count("day", TimeRange::before(start)).await, 24);
+    }
+
+    #[cfg(feature = "fts")]
+    #[tokio::test]
+    async fn test_full_text_search() {
+        use arrow_array::types::Int32Type;
+
+        use crate::index::fts::{FtsIndexBuilder, SCORE_COLUMN};
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("text", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..4)),
+                Arc::new(StringArray::from(vec![
+                    Some("the quick brown fox"),
+                    Some("a lazy dog"),
+                    None,
+                    Some("the fox and the dog"),
+                ])),
+            ],
+        )
+        .unwrap();
+        let table = conn
+            .create_table(
+                "fts",
+                RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        table
+            .create_index(&["text"], Index::Fts(FtsIndexBuilder::default()))
+            .execute()
+            .await
+            .unwrap();
+        let indices = table.list_indices().await.unwrap();
+        assert!(indices
+            .iter()
+            .any(|idx| idx.index_type == crate::index::IndexType::Fts
+                && idx.columns == vec!["text".to_string()]));
+
+        let batches = table
+            .query()
+            .full_text_search("fox")
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert!(batch.column_by_name(SCORE_COLUMN).is_some());
+        let mut ids = batch["id"].as_primitive::<Int32Type>().values().to_vec();
+        ids.sort();
+        assert_eq!(ids, vec![0, 3]);
+
+        let batches = table
+            .query()
+            .full_text_search("fox")
+            .only_if("id > 0")
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 1);
+
+        // Only string columns can be indexed
+        assert!(table
+            .create_index(&["id"], Index::Fts(FtsIndexBuilder::default()))
+            .execute()
+            .await
+            .is_err());
+    }
+
+    #[cfg(feature = "fts")]
+    #[tokio::test]
+    async fn test_hybrid_search() {
+        use arrow_array::types::Int32Type;
+
+        use crate::index::fts::FtsIndexBuilder;
+        use crate::rerankers::{LinearCombinationReranker, RELEVANCE_SCORE_COLUMN};
+
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let conn = connect(uri).execute().await.unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("text", DataType::Utf8, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+                false,
+            ),
+        ]));
+        let vectors = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            (0..4).map(|i| Some(vec![Some(i as f32), Some(0.0)])),
+            2,
+        );
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..4)),
+                Arc::new(StringArray::from(vec![
+                    "a lazy dog",
+                    "a sleepy cat",
+                    "the quick brown fox",
+                    "a red fox",
+                ])),
+                Arc::new(vectors),
+            ],
+        )
+        .unwrap();
+        let table = conn
+            .create_table(
+                "hybrid",
+                RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+        table
+            .create_index(&["text"], Index::Fts(FtsIndexBuilder::default()))
+            .execute()
+            .await
+            .unwrap();
+
+        let query = table
+            .query()
+            .nearest_to(&[0.0, 0.0])
+            .unwrap()
+            .limit(2)
+            .hybrid("fox");
+        let batches = query
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert!(batch.column_by_name(RELEVANCE_SCORE_COLUMN).is_some());
+        assert!(batch.column_by_name("_rowid").is_none());
+        // The nearest row and the most relevant text match share the top rank
+        let ids = batch["id"].as_primitive::<Int32Type>().values().to_vec();
+        assert_eq!(ids[0], 0);
+        assert!(ids[1] == 2 || ids[1] == 3);
+
+        let batches = query
+            .rerank(Arc::new(LinearCombinationReranker::new(0.0).unwrap()))
+            .limit(4)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+        // Only the text score counts so both foxes come first
+        assert_eq!(batch.num_rows(), 4);
+        let ids = batch["id"].as_primitive::<Int32Type>().values().to_vec();
+        let mut top = ids[..2].to_vec();
+        top.sort();
+        assert_eq!(top, vec![2, 3]);
     }
 
     #[tokio::test]
@@ -2384,4 +7339,101 @@ mod tests {
         table.checkout(version).await.unwrap();
         assert!(table.add(some_sample_data()).execute().await.is_err())
     }
+
+    #[tokio::test]
+    async fn test_list_versions() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+
+        let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("my_table", some_sample_data())
+            .execute()
+            .await
+            .unwrap();
+        table.add(some_sample_data()).execute().await.unwrap();
+        table.add(some_sample_data()).execute().await.unwrap();
+
+        let versions = table.list_versions().await.unwrap();
+        assert_eq!(
+            versions.iter().map(|v| v.version).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        table
+            .checkout_timestamp(versions[1].timestamp)
+            .await
+            .unwrap();
+        assert_eq!(table.version().await.unwrap(), 2);
+        assert_eq!(table.count_rows(None).await.unwrap(), 2);
+        assert!(table
+            .checkout_timestamp(versions[0].timestamp - chrono::Duration::seconds(1))
+            .await
+            .is_err());
+        table.checkout_latest().await.unwrap();
+        assert_eq!(table.version().await.unwrap(), 3);
+
+        let pinned = conn
+            .open_table_with_version("my_table", 1)
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(pinned.version().await.unwrap(), 1);
+        assert_eq!(pinned.count_rows(None).await.unwrap(), 1);
+        assert!(pinned.add(some_sample_data()).execute().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tags() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+
+        let conn = connect(uri).execute().await.unwrap();
+        let table = conn
+            .create_table("my_table", some_sample_data())
+            .execute()
+            .await
+            .unwrap();
+        table.add(some_sample_data()).execute().await.unwrap();
+        table.add(some_sample_data()).execute().await.unwrap();
+
+        let tags = table.tags();
+        tags.create("v1.0", 1).await.unwrap();
+        tags.create("v2.0", 2).await.unwrap();
+        assert!(tags.create("v1.0", 3).await.is_err());
+        assert!(tags.create("v9", 9).await.is_err());
+        assert!(tags.create("bad tag", 3).await.is_err());
+        assert_eq!(
+            tags.list().await.unwrap().into_iter().collect::<Vec<_>>(),
+            vec![("v1.0".to_string(), 1), ("v2.0".to_string(), 2)]
+        );
+
+        table.checkout_tag("v1.0").await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 1);
+        table.checkout_latest().await.unwrap();
+        assert!(table.checkout_tag("v3.0").await.is_err());
+
+        tags.delete("v1.0").await.unwrap();
+        assert!(tags.delete("v1.0").await.is_err());
+        assert_eq!(tags.get_version("v2.0").await.unwrap(), 2);
+
+        // The tagged version is kept by a prune
+        table
+            .optimize(OptimizeAction::Prune {
+                older_than: chrono::Duration::zero(),
+                delete_unverified: Some(true),
+            })
+            .await
+            .unwrap();
+        let versions = table.list_versions().await.unwrap();
+        assert!(versions.iter().any(|v| v.version == 2));
+        table.checkout_tag("v2.0").await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 2);
+        table.checkout_latest().await.unwrap();
+
+        table.restore_version(2).await.unwrap();
+        assert_eq!(table.version().await.unwrap(), 4);
+        assert_eq!(table.count_rows(None).await.unwrap(), 2);
+        table.add(some_sample_data()).execute().await.unwrap();
+    }
 }
@@ -0,0 +1,516 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Aggregating the rows of a table
+//!
+//! An aggregation scans the columns it needs and folds each batch into one
+//! accumulator per group as it is read, so only the groups are held in memory
+//! and the result is a single small batch.  The groups are compared on the
+//! [row format](arrow::row) of their values, and so are the values of
+//! [`AggregateBuilder::min`] and [`AggregateBuilder::max`], which therefore
+//! work with every column type that can be sorted.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::compute::{lexsort_to_indices, SortColumn, SortOptions};
+use arrow::row::{OwnedRow, RowConverter, SortField};
+use arrow_array::{
+    cast::AsArray, new_null_array, types::Float64Type, Array, ArrayRef, Float64Array, Int64Array,
+    RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema};
+use futures::TryStreamExt;
+
+use super::Table;
+use crate::arrow::take_record_batch;
+use crate::error::{Error, Result};
+use crate::query::{ExecutableQuery, QueryBase, Select};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Aggregate {
+    Count,
+    Min(String),
+    Max(String),
+    Sum(String),
+    Mean(String),
+}
+
+impl Aggregate {
+    fn column(&self) -> Option<&str> {
+        match self {
+            Self::Count => None,
+            Self::Min(column) | Self::Max(column) | Self::Sum(column) | Self::Mean(column) => {
+                Some(column)
+            }
+        }
+    }
+
+    /// The name of the column of the aggregate in the results
+    fn name(&self) -> String {
+        match self {
+            Self::Count => "count".to_string(),
+            Self::Min(column) => format!("min_{}", column),
+            Self::Max(column) => format!("max_{}", column),
+            Self::Sum(column) => format!("sum_{}", column),
+            Self::Mean(column) => format!("mean_{}", column),
+        }
+    }
+}
+
+/// The state of an aggregate for one group
+#[derive(Debug, Clone)]
+enum Accumulator {
+    Count(i64),
+    /// The smallest or largest non-null value seen, in the row format
+    Extreme(Option<OwnedRow>),
+    Sum {
+        sum: f64,
+        count: i64,
+    },
+}
+
+/// How an aggregate reads its column
+enum Reader {
+    Count,
+    Extreme {
+        converter: RowConverter,
+        data_type: DataType,
+        max: bool,
+    },
+    Sum,
+}
+
+/// A builder used to aggregate the rows of a table
+///
+/// See [`super::Table::aggregate`] for more context
+#[derive(Clone)]
+pub struct AggregateBuilder {
+    table: Table,
+    filter: Option<String>,
+    group_by: Vec<String>,
+    aggregates: Vec<Aggregate>,
+}
+
+impl AggregateBuilder {
+    pub(super) fn new(table: Table) -> Self {
+        Self {
+            table,
+            filter: None,
+            group_by: Vec::new(),
+            aggregates: Vec::new(),
+        }
+    }
+
+    /// Only aggregate the rows matching the filter, see [`QueryBase::only_if`]
+    pub fn only_if(mut self, filter: impl Into<String>) -> Self {
+        self.filter = Some(filter.into());
+        self
+    }
+
+    /// Aggregate the rows of each distinct value of `column` separately
+    ///
+    /// This can be called several times to group by several columns.  Null is
+    /// a group of its own.
+    pub fn group_by(mut self, column: impl Into<String>) -> Self {
+        self.group_by.push(column.into());
+        self
+    }
+
+    /// Count the rows, in the `count` column
+    pub fn count(mut self) -> Self {
+        self.aggregates.push(Aggregate::Count);
+        self
+    }
+
+    /// The smallest non-null value of `column`, in the `min_<column>` column
+    pub fn min(mut self, column: impl Into<String>) -> Self {
+        self.aggregates.push(Aggregate::Min(column.into()));
+        self
+    }
+
+    /// The largest non-null value of `column`, in the `max_<column>` column
+    pub fn max(mut self, column: impl Into<String>) -> Self {
+        self.aggregates.push(Aggregate::Max(column.into()));
+        self
+    }
+
+    /// The sum of the non-null values of the numeric `column`, as a float in
+    /// the `sum_<column>` column
+    pub fn sum(mut self, column: impl Into<String>) -> Self {
+        self.aggregates.push(Aggregate::Sum(column.into()));
+        self
+    }
+
+    /// The mean of the non-null values of the numeric `column`, in the
+    /// `mean_<column>` column
+    pub fn mean(mut self, column: impl Into<String>) -> Self {
+        self.aggregates.push(Aggregate::Mean(column.into()));
+        self
+    }
+
+    /// Scan the table and compute the aggregates
+    ///
+    /// Returns one row per group, sorted by the values of the group by columns
+    /// with the null group last, with the group by columns followed by the aggregates in the order they
+    /// were added.  Without group by columns there is a single row.
+    pub async fn execute(self) -> Result<RecordBatch> {
+        if self.aggregates.is_empty() {
+            return Err(Error::InvalidInput {
+                message: "an aggregation needs at least one aggregate".to_string(),
+            });
+        }
+        let schema = self.table.schema().await?;
+        let readers = self
+            .aggregates
+            .iter()
+            .map(|aggregate| {
+                let Some(column) = aggregate.column() else {
+                    return Ok(Reader::Count);
+                };
+                let data_type = schema.field_with_name(column)?.data_type();
+                match aggregate {
+                    Aggregate::Min(_) | Aggregate::Max(_) => Ok(Reader::Extreme {
+                        converter: RowConverter::new(vec![SortField::new(data_type.clone())])?,
+                        data_type: data_type.clone(),
+                        max: matches!(aggregate, Aggregate::Max(_)),
+                    }),
+                    _ if data_type.is_numeric() => Ok(Reader::Sum),
+                    _ => Err(Error::InvalidInput {
+                        message: format!(
+                            "the column {} has type {} which cannot be summed",
+                            column, data_type
+                        ),
+                    }),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let group_fields = self
+            .group_by
+            .iter()
+            .map(|column| Ok(schema.field_with_name(column)?.clone()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut columns = self.group_by.clone();
+        for column in self.aggregates.iter().filter_map(Aggregate::column) {
+            if !columns.iter().any(|c| c == column) {
+                columns.push(column.to_string());
+            }
+        }
+        if columns.is_empty() {
+            // Only counting the rows
+            let count = self.table.count_rows(self.filter.clone()).await? as i64;
+            return self.results(&[], &[vec![Accumulator::Count(count)]], &readers);
+        }
+
+        let group_converter = RowConverter::new(
+            group_fields
+                .iter()
+                .map(|field| SortField::new(field.data_type().clone()))
+                .collect(),
+        )?;
+        let mut query = self.table.query().select(Select::columns(&columns));
+        if let Some(filter) = &self.filter {
+            query = query.only_if(filter);
+        }
+        let mut stream = query.execute().await?;
+        let initial = readers
+            .iter()
+            .map(|reader| match reader {
+                Reader::Count => Accumulator::Count(0),
+                Reader::Extreme { .. } => Accumulator::Extreme(None),
+                Reader::Sum => Accumulator::Sum { sum: 0.0, count: 0 },
+            })
+            .collect::<Vec<_>>();
+        let mut groups: HashMap<OwnedRow, usize> = HashMap::new();
+        let mut keys = Vec::new();
+        let mut accumulators = Vec::new();
+        if self.group_by.is_empty() {
+            accumulators.push(initial.clone());
+        }
+        while let Some(batch) = stream.try_next().await? {
+            // The group of each row
+            let group_of_row = if self.group_by.is_empty() {
+                vec![0; batch.num_rows()]
+            } else {
+                let group_columns = self
+                    .group_by
+                    .iter()
+                    .map(|column| batch[column.as_str()].clone())
+                    .collect::<Vec<_>>();
+                let rows = group_converter.convert_columns(&group_columns)?;
+                rows.iter()
+                    .map(|row| {
+                        *groups.entry(row.owned()).or_insert_with(|| {
+                            keys.push(row.owned());
+                            accumulators.push(initial.clone());
+                            keys.len() - 1
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            for (i, (aggregate, reader)) in self.aggregates.iter().zip(&readers).enumerate() {
+                match reader {
+                    Reader::Count => {
+                        for group in &group_of_row {
+                            if let Accumulator::Count(count) = &mut accumulators[*group][i] {
+                                *count += 1;
+                            }
+                        }
+                    }
+                    Reader::Extreme { converter, max, .. } => {
+                        let values = batch[aggregate.column().unwrap()].clone();
+                        let rows = converter.convert_columns(std::slice::from_ref(&values))?;
+                        for (row, group) in group_of_row.iter().enumerate() {
+                            if values.is_null(row) {
+                                continue;
+                            }
+                            let value = rows.row(row);
+                            if let Accumulator::Extreme(extreme) = &mut accumulators[*group][i] {
+                                let replace = match extreme {
+                                    None => true,
+                                    Some(current) if *max => value > current.row(),
+                                    Some(current) => value < current.row(),
+                                };
+                                if replace {
+                                    *extreme = Some(value.owned());
+                                }
+                            }
+                        }
+                    }
+                    Reader::Sum => {
+                        let values = arrow_cast::cast(
+                            &batch[aggregate.column().unwrap()],
+                            &DataType::Float64,
+                        )?;
+                        let values = values.as_primitive::<Float64Type>();
+                        for (row, group) in group_of_row.iter().enumerate() {
+                            if values.is_null(row) {
+                                continue;
+                            }
+                            if let Accumulator::Sum { sum, count } = &mut accumulators[*group][i] {
+                                *sum += values.value(row);
+                                *count += 1;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let group_columns = if self.group_by.is_empty() {
+            Vec::new()
+        } else if keys.is_empty() {
+            group_fields
+                .iter()
+                .map(|field| new_null_array(field.data_type(), 0))
+                .collect()
+        } else {
+            group_converter.convert_rows(keys.iter().map(|key| key.row()))?
+        };
+        let results = self.results(&group_columns, &accumulators, &readers)?;
+        if self.group_by.is_empty() || results.num_rows() == 0 {
+            return Ok(results);
+        }
+        let sort_columns = group_columns
+            .into_iter()
+            .map(|values| SortColumn {
+                values,
+                options: Some(SortOptions {
+                    descending: false,
+                    nulls_first: false,
+                }),
+            })
+            .collect::<Vec<_>>();
+        let indices = lexsort_to_indices(&sort_columns, None)?;
+        Ok(take_record_batch(&results, &indices)?)
+    }
+
+    /// The batch of the results, one row per group
+    fn results(
+        &self,
+        group_columns: &[ArrayRef],
+        accumulators: &[Vec<Accumulator>],
+        readers: &[Reader],
+    ) -> Result<RecordBatch> {
+        let mut fields = self
+            .group_by
+            .iter()
+            .zip(group_columns)
+            .map(|(name, values)| Field::new(name, values.data_type().clone(), true))
+            .collect::<Vec<_>>();
+        let mut columns = group_columns.to_vec();
+        for (i, (aggregate, reader)) in self.aggregates.iter().zip(readers).enumerate() {
+            let values: ArrayRef = match (aggregate, reader) {
+                (_, Reader::Count) => Arc::new(
+                    accumulators
+                        .iter()
+                        .map(|group| match &group[i] {
+                            Accumulator::Count(count) => *count,
+                            _ => unreachable!("count accumulator"),
+                        })
+                        .collect::<Int64Array>(),
+                ),
+                (
+                    _,
+                    Reader::Extreme {
+                        converter,
+                        data_type,
+                        ..
+                    },
+                ) => {
+                    // The groups without non-null values
+                    let null = converter.convert_columns(&[new_null_array(data_type, 1)])?;
+                    let null = null.row(0);
+                    converter
+                        .convert_rows(accumulators.iter().map(|group| match &group[i] {
+                            Accumulator::Extreme(Some(value)) => value.row(),
+                            _ => null,
+                        }))?
+                        .remove(0)
+                }
+                (aggregate, Reader::Sum) => Arc::new(
+                    accumulators
+                        .iter()
+                        .map(|group| match &group[i] {
+                            Accumulator::Sum { count: 0, .. } => None,
+                            Accumulator::Sum { sum, count } => match aggregate {
+                                Aggregate::Mean(_) => Some(sum / *count as f64),
+                                _ => Some(*sum),
+                            },
+                            _ => unreachable!("sum accumulator"),
+                        })
+                        .collect::<Float64Array>(),
+                ),
+            };
+            fields.push(Field::new(
+                aggregate.name(),
+                values.data_type().clone(),
+                true,
+            ));
+            columns.push(values);
+        }
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, RecordBatchIterator, RecordBatchReader, StringArray};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    fn batch() -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("label", DataType::Utf8, true),
+            Field::new("score", DataType::Int32, true),
+        ]));
+        // Lance reads back the nulls of primitive columns as zeros, so only the
+        // labels have nulls
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![
+                    Some("b"),
+                    Some("a"),
+                    Some("b"),
+                    None,
+                    Some("a"),
+                    Some("b"),
+                ])),
+                Arc::new(Int32Array::from(vec![
+                    Some(3),
+                    Some(10),
+                    Some(-1),
+                    Some(4),
+                    Some(10),
+                    Some(7),
+                ])),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_aggregate() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("t", batch()).execute().await.unwrap();
+
+        let results = table
+            .aggregate()
+            .group_by("label")
+            .count()
+            .min("score")
+            .max("label")
+            .mean("score")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(results.num_rows(), 3);
+        let labels = results["label"].as_string::<i32>();
+        assert_eq!(
+            labels.iter().collect::<Vec<_>>(),
+            vec![Some("a"), Some("b"), None]
+        );
+        let counts = results["count"].as_primitive::<arrow_array::types::Int64Type>();
+        assert_eq!(counts.values(), &[2, 3, 1]);
+        let mins = results["min_score"].as_primitive::<arrow_array::types::Int32Type>();
+        assert_eq!(
+            mins.iter().collect::<Vec<_>>(),
+            vec![Some(10), Some(-1), Some(4)]
+        );
+        let max_labels = results["max_label"].as_string::<i32>();
+        assert_eq!(
+            max_labels.iter().collect::<Vec<_>>(),
+            vec![Some("a"), Some("b"), None]
+        );
+        let means = results["mean_score"].as_primitive::<Float64Type>();
+        assert_eq!(
+            means.iter().collect::<Vec<_>>(),
+            vec![Some(10.0), Some(3.0), Some(4.0)]
+        );
+
+        let results = table
+            .aggregate()
+            .only_if("score > 0")
+            .count()
+            .sum("score")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(results.num_rows(), 1);
+        assert_eq!(
+            results["count"]
+                .as_primitive::<arrow_array::types::Int64Type>()
+                .value(0),
+            5
+        );
+        assert_eq!(
+            results["sum_score"].as_primitive::<Float64Type>().value(0),
+            34.0
+        );
+        assert!(table.aggregate().sum("label").execute().await.is_err());
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compaction of the small fragments of a table after writes
+//!
+//! Each add writes at least one new fragment, so an application making many
+//! small adds ends up with many small fragments, which slow down reads, until
+//! [`super::Table::optimize`] is called.  With automatic compaction, set with
+//! [`crate::connection::ConnectBuilder::auto_compaction`], an add which leaves
+//! the table with too many small fragments compacts some of them before it
+//! returns.  The work of each compaction is bounded, so that the latency of an
+//! add stays predictable; the remaining small fragments are compacted by the
+//! following adds.
+//!
+//! Compaction permanently removes soft deleted rows, see
+//! [`crate::connection::CreateTableBuilder::soft_delete`], so tables keeping
+//! deleted rows are never compacted automatically.  Neither are partitioned
+//! tables, see [`super::partition`], whose fragments each hold one key.
+
+use std::sync::Arc;
+
+use arrow_schema::Schema;
+use lance::dataset::index::DatasetIndexRemapperOptions;
+use lance::dataset::optimize::{
+    commit_compaction, plan_compaction, CompactionMetrics, CompactionOptions,
+};
+use lance::Dataset;
+
+use super::{partition, NativeTable};
+use crate::error::Result;
+
+/// When to compact the small fragments of a table after an add
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoCompaction {
+    /// Fragments with fewer rows than this are small, and are merged into
+    /// fragments of up to this many rows
+    ///
+    /// The default is 1024 * 1024 rows, the target size of the fragments
+    /// written by [`super::OptimizeAction::Compact`].
+    pub small_fragment_rows: usize,
+    /// Compact once the table has more small fragments than this
+    ///
+    /// The default is 16.
+    pub max_small_fragments: usize,
+    /// The largest number of fragments rewritten by one compaction
+    ///
+    /// Fragments are compacted in groups, at least one group is compacted even
+    /// if it has more fragments.  The default is 64.
+    pub max_fragments_per_run: usize,
+}
+
+impl Default for AutoCompaction {
+    fn default() -> Self {
+        Self {
+            small_fragment_rows: 1024 * 1024,
+            max_small_fragments: 16,
+            max_fragments_per_run: 64,
+        }
+    }
+}
+
+/// The number of fragments of `dataset` with fewer than `small_fragment_rows` rows
+fn count_small_fragments(dataset: &Dataset, small_fragment_rows: usize) -> usize {
+    dataset
+        .get_fragments()
+        .iter()
+        .filter(|fragment| {
+            fragment
+                .metadata()
+                .physical_rows
+                .map_or(true, |rows| rows < small_fragment_rows)
+        })
+        .count()
+}
+
+impl NativeTable {
+    /// Compact the small fragments of the table after adds, according to `auto_compaction`
+    pub(crate) fn with_auto_compaction(mut self, auto_compaction: Option<AutoCompaction>) -> Self {
+        self.auto_compaction = auto_compaction;
+        self
+    }
+
+    /// Compact some of the small fragments if there are too many of them
+    ///
+    /// Returns the metrics of the compaction, if one ran.
+    pub(super) async fn auto_compact(&self) -> Result<Option<CompactionMetrics>> {
+        let Some(auto_compaction) = self.auto_compaction else {
+            return Ok(None);
+        };
+        if self.soft_delete_enabled().await? {
+            return Ok(None);
+        }
+        let dataset = self.dataset.get().await?.clone();
+        // Compacting would merge the fragments of different keys
+        if partition::partition_column(&Schema::from(dataset.schema())).is_some() {
+            return Ok(None);
+        }
+        let small_fragments = count_small_fragments(&dataset, auto_compaction.small_fragment_rows);
+        if small_fragments <= auto_compaction.max_small_fragments {
+            return Ok(None);
+        }
+
+        let options = CompactionOptions {
+            target_rows_per_fragment: auto_compaction.small_fragment_rows,
+            ..Default::default()
+        };
+        let plan = plan_compaction(&dataset, &options).await?;
+        let mut tasks = Vec::new();
+        let mut fragments = 0;
+        for task in plan.compaction_tasks() {
+            let task_fragments = task.task.fragments.len();
+            if !tasks.is_empty()
+                && fragments + task_fragments > auto_compaction.max_fragments_per_run
+            {
+                break;
+            }
+            fragments += task_fragments;
+            tasks.push(task);
+        }
+        if tasks.is_empty() {
+            return Ok(None);
+        }
+        let completed =
+            futures::future::try_join_all(tasks.iter().map(|task| task.execute(&dataset))).await?;
+
+        let mut dataset_mut = self.dataset.get_mut().await?;
+        let metrics = commit_compaction(
+            &mut dataset_mut,
+            completed,
+            Arc::new(DatasetIndexRemapperOptions::default()),
+        )
+        .await?;
+        Ok(Some(metrics))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    fn batch(start: i32) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(start..start + 10))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_auto_compaction() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri)
+            .auto_compaction(AutoCompaction {
+                small_fragment_rows: 100,
+                max_small_fragments: 3,
+                max_fragments_per_run: 64,
+            })
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("test", batch(0)).execute().await.unwrap();
+        let native = table.as_native().unwrap();
+        let num_fragments = || async { native.dataset.get().await.unwrap().get_fragments().len() };
+
+        for i in 1..3 {
+            table.add(batch(i * 10)).execute().await.unwrap();
+        }
+        assert_eq!(num_fragments().await, 3);
+        // The fourth small fragment is one too many
+        table.add(batch(30)).execute().await.unwrap();
+        assert_eq!(num_fragments().await, 1);
+        assert_eq!(table.count_rows(None).await.unwrap(), 40);
+    }
+}
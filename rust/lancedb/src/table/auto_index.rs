@@ -0,0 +1,246 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Indexing of the rows added after an index was created
+//!
+//! The rows added to a table are not covered by its indices until they are
+//! optimized.  Searches still find them, but have to scan them, so the latency
+//! of the searches grows with the number of unindexed rows.
+//! [`super::Table::optimize_indices`] indexes them without rebuilding the
+//! indices: the new rows are assigned to the existing IVF partitions of the
+//! vector indices, and written as a delta which is merged with the most recent
+//! deltas.  The centroids of the partitions are only trained when an index is
+//! created, so once most of the rows of an index were added after it was
+//! trained the index should be retrained, see
+//! [`OptimizeIndexOptions::retrain_threshold`].
+//!
+//! With automatic indexing, set with [`crate::connection::ConnectBuilder::auto_index`],
+//! an add which leaves an index with too many unindexed rows optimizes the
+//! indices before it returns.
+
+use lance_index::optimize::OptimizeOptions;
+
+use super::{NativeTable, TableInternal};
+use crate::error::Result;
+use crate::index::IndexType;
+
+/// How [`super::Table::optimize_indices`] updates the indices
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OptimizeIndexOptions {
+    /// The number of the most recent deltas of each index the new rows are
+    /// merged with
+    ///
+    /// 0 writes the new rows as a new delta, which is the fastest but leaves
+    /// searches with one more delta to read.  The default is 1.
+    pub num_indices_to_merge: usize,
+    /// Retrain a vector index once its unindexed rows outnumber its indexed
+    /// rows by this factor
+    ///
+    /// Retraining rebuilds the index with the default parameters, see
+    /// [`crate::index::Index::Auto`].  With 0.5 an index is retrained when the
+    /// rows added since it was last optimized are more than half of the rows it
+    /// covers.  The default is to never retrain.
+    pub retrain_threshold: Option<f64>,
+}
+
+impl Default for OptimizeIndexOptions {
+    fn default() -> Self {
+        Self {
+            num_indices_to_merge: 1,
+            retrain_threshold: None,
+        }
+    }
+}
+
+/// When to optimize the indices of a table after an add
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoIndex {
+    /// Optimize the indices once one of them has more unindexed rows than this
+    ///
+    /// The default is 10,000 rows.
+    pub max_unindexed_rows: usize,
+    /// How the indices are optimized
+    pub options: OptimizeIndexOptions,
+}
+
+impl Default for AutoIndex {
+    fn default() -> Self {
+        Self {
+            max_unindexed_rows: 10_000,
+            options: OptimizeIndexOptions::default(),
+        }
+    }
+}
+
+impl NativeTable {
+    /// Optimize the indices of the table after adds, according to `auto_index`
+    pub(crate) fn with_auto_index(mut self, auto_index: Option<AutoIndex>) -> Self {
+        self.auto_index = auto_index;
+        self
+    }
+
+    /// Index the unindexed rows of the table, retraining the vector indices
+    /// which drifted too far from their training data
+    ///
+    /// Returns the names of the retrained indices.
+    pub(super) async fn optimize_indices_impl(
+        &self,
+        options: &OptimizeIndexOptions,
+    ) -> Result<Vec<String>> {
+        let mut retrained = Vec::new();
+        let mut unindexed = false;
+        for index in self.list_indices().await? {
+            if !matches!(index.index_type, IndexType::IvfPq | IndexType::BTree) {
+                continue;
+            }
+            let Some(stats) = self.index_stats(&index.name).await? else {
+                continue;
+            };
+            if stats.num_unindexed_rows == 0 {
+                continue;
+            }
+            let retrain = index.index_type == IndexType::IvfPq
+                && options.retrain_threshold.is_some_and(|threshold| {
+                    stats.num_unindexed_rows as f64 > threshold * stats.num_indexed_rows as f64
+                });
+            if retrain {
+                let schema = self.schema().await?;
+                for column in &index.columns {
+                    self.create_auto_index(schema.field_with_name(column)?, true)
+                        .await?;
+                }
+                retrained.push(index.name);
+            } else {
+                unindexed = true;
+            }
+        }
+        if unindexed {
+            self.update_indices(&OptimizeOptions {
+                num_indices_to_merge: options.num_indices_to_merge,
+            })
+            .await?;
+        }
+        Ok(retrained)
+    }
+
+    /// Optimize the indices if one of them has too many unindexed rows
+    ///
+    /// Returns whether the indices were optimized.
+    pub(super) async fn auto_optimize_indices(&self) -> Result<bool> {
+        let Some(auto_index) = self.auto_index else {
+            return Ok(false);
+        };
+        let mut optimize = false;
+        for index in self.list_indices().await? {
+            if !matches!(index.index_type, IndexType::IvfPq | IndexType::BTree) {
+                continue;
+            }
+            if let Some(stats) = self.index_stats(&index.name).await? {
+                optimize |= stats.num_unindexed_rows > auto_index.max_unindexed_rows;
+            }
+        }
+        if optimize {
+            self.optimize_indices_impl(&auto_index.options).await?;
+        }
+        Ok(optimize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{
+        FixedSizeListArray, Float32Array, Int32Array, RecordBatch, RecordBatchIterator,
+        RecordBatchReader,
+    };
+    use arrow_schema::{DataType, Field, Schema};
+    use lance::arrow::FixedSizeListArrayExt;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::index::Index;
+
+    fn batch(start: i32, num_rows: i32) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 8),
+                true,
+            ),
+        ]));
+        let values =
+            Float32Array::from_iter_values((0..num_rows * 8).map(|v| ((v * 7919) % 101) as f32));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(start..start + num_rows)),
+                Arc::new(FixedSizeListArray::try_new_from_values(values, 8).unwrap()),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_optimize_indices() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .auto_index(AutoIndex {
+                max_unindexed_rows: 100,
+                options: OptimizeIndexOptions::default(),
+            })
+            .execute()
+            .await
+            .unwrap();
+        let table = db
+            .create_table("test", batch(0, 512))
+            .execute()
+            .await
+            .unwrap();
+        table
+            .create_index(&["id"], Index::Auto)
+            .execute()
+            .await
+            .unwrap();
+        let unindexed = || async {
+            table
+                .index_stats("id_idx")
+                .await
+                .unwrap()
+                .unwrap()
+                .num_unindexed_rows
+        };
+
+        table.add(batch(512, 50)).execute().await.unwrap();
+        assert_eq!(unindexed().await, 50);
+        // The add leaving more than 100 unindexed rows indexes them
+        table.add(batch(562, 60)).execute().await.unwrap();
+        assert_eq!(unindexed().await, 0);
+
+        table.add(batch(622, 10)).execute().await.unwrap();
+        let retrained = table
+            .optimize_indices(OptimizeIndexOptions {
+                num_indices_to_merge: 0,
+                retrain_threshold: Some(0.5),
+            })
+            .await
+            .unwrap();
+        // Only vector indices are retrained
+        assert!(retrained.is_empty());
+        assert_eq!(unindexed().await, 0);
+    }
+}
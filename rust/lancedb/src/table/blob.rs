@@ -0,0 +1,296 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Blob columns
+//!
+//! A blob column is a binary column holding large values, such as the original
+//! documents or images of the embeddings of a table.  Reading the blobs with
+//! every query would make scans much slower, so they are usually left out of
+//! the results, with [`crate::query::QueryBase::exclude_blobs`], and the blobs
+//! of the rows which are needed are read afterwards with
+//! [`super::Table::take_blobs`].
+//!
+//! The blob columns are chosen when the table is created, with
+//! [`crate::connection::CreateTableBuilder::blob_column`], and are marked in the
+//! metadata of their fields under the key read by the Lance encoders.  Like the
+//! codecs of [`super::compression`], the marker is applied by the Lance file
+//! formats which store blobs out of line.  Files written in the version 1
+//! format store the blobs with the other columns.
+
+use std::collections::HashSet;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+use arrow::buffer::Buffer;
+use arrow_array::cast::AsArray;
+use arrow_array::{
+    Array, GenericBinaryArray, OffsetSizeTrait, RecordBatchIterator, RecordBatchReader,
+};
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::error::{Error, Result};
+
+/// The field metadata key marking a blob column
+pub const BLOB_METADATA_KEY: &str = "lance-encoding:blob";
+
+/// Whether `field` is a blob column
+pub fn is_blob(field: &Field) -> bool {
+    field
+        .metadata()
+        .get(BLOB_METADATA_KEY)
+        .is_some_and(|value| value == "true")
+}
+
+fn is_binary(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Binary | DataType::LargeBinary)
+}
+
+/// The names of the columns of `schema` which are not blob columns
+pub(crate) fn non_blob_columns(schema: &Schema) -> Vec<String> {
+    schema
+        .fields()
+        .iter()
+        .filter(|field| !is_blob(field))
+        .map(|field| field.name().clone())
+        .collect()
+}
+
+/// The schema `schema` with `columns` marked as blob columns
+pub(crate) fn schema_with_blobs(schema: &Schema, columns: &HashSet<String>) -> Result<Schema> {
+    for column in columns {
+        match schema.field_with_name(column) {
+            Ok(field) if is_binary(field.data_type()) => {}
+            Ok(field) => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "The blob column '{}' has the type {} but must be a binary column",
+                        column,
+                        field.data_type()
+                    ),
+                })
+            }
+            Err(_) => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "Cannot make '{}' a blob column, it is not in the schema",
+                        column
+                    ),
+                })
+            }
+        }
+    }
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            if columns.contains(field.name()) {
+                let mut metadata = field.metadata().clone();
+                metadata.insert(BLOB_METADATA_KEY.to_string(), "true".to_string());
+                Arc::new(field.as_ref().clone().with_metadata(metadata))
+            } else {
+                field.clone()
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// The data `data` with `columns` marked as blob columns
+pub(crate) fn with_blobs(
+    data: Box<dyn RecordBatchReader + Send>,
+    columns: &HashSet<String>,
+) -> Result<Box<dyn RecordBatchReader + Send>> {
+    if columns.is_empty() {
+        return Ok(data);
+    }
+    let schema = Arc::new(schema_with_blobs(&data.schema(), columns)?);
+    let batch_schema = schema.clone();
+    Ok(Box::new(RecordBatchIterator::new(
+        data.map(move |batch| batch.and_then(|batch| batch.with_schema(batch_schema.clone()))),
+        schema,
+    )))
+}
+
+/// A blob read with [`super::Table::take_blobs`]
+///
+/// The blob is read with [`Read`] and [`Seek`].  It refers to the buffer it
+/// was read into, without copying it.
+#[derive(Debug, Clone)]
+pub struct BlobReader {
+    data: Buffer,
+    position: u64,
+}
+
+impl BlobReader {
+    /// The size of the blob, in bytes
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Whether the blob is empty
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The bytes of the blob
+    pub fn as_bytes(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let start = (self.position as usize).min(self.data.len());
+        let remaining = &self.data.as_slice()[start..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for BlobReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.data.len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "cannot seek before the start of the blob",
+            )),
+        }
+    }
+}
+
+fn binary_readers<O: OffsetSizeTrait>(array: &GenericBinaryArray<O>) -> Vec<Option<BlobReader>> {
+    let offsets = array.value_offsets();
+    (0..array.len())
+        .map(|i| {
+            array.is_valid(i).then(|| {
+                let start = offsets[i].as_usize();
+                let end = offsets[i + 1].as_usize();
+                BlobReader {
+                    data: array.values().slice_with_length(start, end - start),
+                    position: 0,
+                }
+            })
+        })
+        .collect()
+}
+
+/// The readers of the values of `array`, the binary column `column`
+pub(crate) fn readers(column: &str, array: &dyn Array) -> Result<Vec<Option<BlobReader>>> {
+    match array.data_type() {
+        DataType::Binary => Ok(binary_readers(array.as_binary::<i32>())),
+        DataType::LargeBinary => Ok(binary_readers(array.as_binary::<i64>())),
+        data_type => Err(Error::InvalidInput {
+            message: format!(
+                "The column '{}' has the type {} but blobs are read from binary columns",
+                column, data_type
+            ),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, LargeBinaryArray, RecordBatch, UInt64Array};
+    use futures::TryStreamExt;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+
+    #[tokio::test]
+    async fn test_blobs() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("image", DataType::LargeBinary, true),
+        ]));
+        let images = (0..10)
+            .map(|i| (i != 3).then(|| vec![i as u8; 1000 * (i + 1)]))
+            .collect::<Vec<_>>();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..10)),
+                Arc::new(LargeBinaryArray::from_iter(images.iter().cloned())),
+            ],
+        )
+        .unwrap();
+        let table = db
+            .create_table(
+                "t",
+                RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+            )
+            .blob_column("image")
+            .execute()
+            .await
+            .unwrap();
+        let table_schema = table.schema().await.unwrap();
+        assert!(is_blob(table_schema.field_with_name("image").unwrap()));
+
+        let batches = table
+            .query()
+            .only_if("id >= 2")
+            .exclude_blobs()
+            .with_row_id()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = &batches[0];
+        assert!(batch.column_by_name("image").is_none());
+        let row_ids = batch["_rowid"]
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap()
+            .values()
+            .to_vec();
+
+        let blobs = table.take_blobs(&row_ids[..3], "image").await.unwrap();
+        assert_eq!(blobs.len(), 3);
+        let mut blob = blobs[0].clone().unwrap();
+        assert_eq!(blob.len(), 3000);
+        let mut content = Vec::new();
+        blob.read_to_end(&mut content).unwrap();
+        assert_eq!(Some(content), images[2]);
+        blob.seek(SeekFrom::End(-10)).unwrap();
+        let mut tail = [0u8; 20];
+        assert_eq!(blob.read(&mut tail).unwrap(), 10);
+        assert!(blob.seek(SeekFrom::Current(-100)).is_ok());
+        assert!(blob.seek(SeekFrom::Start(0)).is_ok());
+        assert!(blobs[1].is_none());
+
+        assert!(matches!(
+            table.take_blobs(&row_ids[..1], "id").await,
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+}
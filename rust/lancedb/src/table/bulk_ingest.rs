@@ -0,0 +1,326 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parallel writes of large amounts of data
+//!
+//! A plain add encodes and uploads the data files one after the other.  With
+//! [`super::AddDataMode::BulkIngest`] the data is cut into chunks of the target
+//! fragment size and the fragments are written in parallel, by tasks of the
+//! Tokio runtime, then committed at once as a single append.  This is meant
+//! for the initial load of large tables, where the time is spent encoding and
+//! uploading.
+//!
+//! The data is read in order, and the fragments keep the order of the rows,
+//! but at most `parallelism` chunks are held in memory at a time.  The data
+//! must have the schema of the table.  If a fragment fails to be written
+//! nothing is committed; the files already written are not part of any
+//! version and are removed by [`super::OptimizeAction::Prune`] with
+//! `delete_unverified`.  Bulk ingests are not retried on conflicts, see
+//! [`super::WriteOptions::max_commit_retries`].
+
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use arrow_array::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_schema::{Schema, SchemaRef};
+use futures::{StreamExt, TryStreamExt};
+use lance::dataset::fragment::FileFragment;
+use lance::dataset::transaction::Operation;
+use lance::dataset::{Dataset, WriteParams};
+use lance::datatypes::Field;
+use lance_table::format::Fragment;
+
+use super::stats::AddedStats;
+use super::{NativeTable, SchemaPolicy};
+use crate::error::{Error, Result};
+use crate::metrics;
+
+/// How a bulk ingest writes its fragments, see
+/// [`super::AddDataMode::BulkIngest`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BulkIngestOptions {
+    /// The number of fragments written at the same time
+    ///
+    /// The default is the number of CPUs.
+    pub parallelism: usize,
+    /// The number of rows of each fragment, except the last one
+    ///
+    /// The default is 1024 * 1024 rows, the target size of the fragments
+    /// written by [`super::OptimizeAction::Compact`].
+    pub target_fragment_rows: usize,
+}
+
+impl Default for BulkIngestOptions {
+    fn default() -> Self {
+        Self {
+            parallelism: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            target_fragment_rows: 1024 * 1024,
+        }
+    }
+}
+
+/// The batches of a reader, regrouped in chunks of `rows` rows
+struct Chunks {
+    data: Box<dyn RecordBatchReader + Send>,
+    rows: usize,
+    /// The rows of the last batch read which did not fit in the last chunk
+    rest: Option<RecordBatch>,
+}
+
+impl Iterator for Chunks {
+    type Item = Result<Vec<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = Vec::new();
+        let mut rows = 0;
+        while rows < self.rows {
+            let batch = match self.rest.take() {
+                Some(batch) => batch,
+                None => match self.data.next() {
+                    Some(Ok(batch)) => batch,
+                    Some(Err(e)) => return Some(Err(e.into())),
+                    None => break,
+                },
+            };
+            let taken = batch.num_rows().min(self.rows - rows);
+            if taken < batch.num_rows() {
+                self.rest = Some(batch.slice(taken, batch.num_rows() - taken));
+            }
+            rows += taken;
+            chunk.push(batch.slice(0, taken));
+        }
+        (rows > 0).then_some(Ok(chunk))
+    }
+}
+
+/// The ids of `fields` and of their children, depth first
+fn field_ids(fields: &[Field], ids: &mut Vec<i32>) {
+    for field in fields {
+        ids.push(field.id);
+        field_ids(&field.children, ids);
+    }
+}
+
+impl NativeTable {
+    /// Check that fragments written with the schema `schema` can be added to
+    /// `dataset`
+    pub(super) fn check_bulk_ingest_schema(dataset: &Dataset, schema: &Schema) -> Result<()> {
+        // Fragments are written with the field ids of a new table
+        let written = lance::datatypes::Schema::try_from(schema)?;
+        let (mut written_ids, mut table_ids) = (Vec::new(), Vec::new());
+        field_ids(&written.fields, &mut written_ids);
+        field_ids(&dataset.schema().fields, &mut table_ids);
+        let table_schema = Schema::from(dataset.schema());
+        let same_fields = schema.fields().len() == table_schema.fields().len()
+            && schema
+                .fields()
+                .iter()
+                .zip(table_schema.fields())
+                .all(|(a, b)| {
+                    a.name() == b.name()
+                        && a.data_type() == b.data_type()
+                        && a.is_nullable() == b.is_nullable()
+                });
+        if !same_fields || written_ids != table_ids {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "a bulk ingest requires data with the schema of the table, and a table \
+                     whose columns were never altered: {:?} != {:?}",
+                    schema, table_schema
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// Write `data` to new fragments in parallel, without committing them
+    ///
+    /// The fragments have `options.target_fragment_rows` rows, except the
+    /// last one, and are numbered when they are committed.
+    pub(super) async fn write_fragments(
+        &self,
+        data: Box<dyn RecordBatchReader + Send>,
+        options: BulkIngestOptions,
+        params: &WriteParams,
+    ) -> Result<Vec<Fragment>> {
+        let schema: SchemaRef = data.schema();
+        let chunks = Chunks {
+            data,
+            rows: options.target_fragment_rows.max(1),
+            rest: None,
+        };
+        futures::stream::iter(chunks)
+            .map(|chunk| {
+                let uri = self.uri.clone();
+                let schema = schema.clone();
+                let params = params.clone();
+                async move {
+                    let reader = RecordBatchIterator::new(chunk?.into_iter().map(Ok), schema);
+                    // The fragments are renumbered when they are committed
+                    tokio::spawn(async move {
+                        FileFragment::create(&uri, 0, reader, Some(params)).await
+                    })
+                    .await
+                    .map_err(|e| Error::Runtime {
+                        message: format!("failed to write a fragment: {}", e),
+                    })?
+                    .map_err(Error::from)
+                }
+            })
+            .buffered(options.parallelism.max(1))
+            .try_collect::<Vec<_>>()
+            .await
+    }
+
+    /// Write `data` to new fragments in parallel and append them to the table
+    ///
+    /// Returns the new version, with the statistics and the number of rows
+    /// added, as the retried writes of [`super::TableInternal::add`].
+    pub(super) async fn bulk_ingest(
+        &self,
+        data: Box<dyn RecordBatchReader + Send>,
+        options: BulkIngestOptions,
+        schema_policy: SchemaPolicy,
+        params: WriteParams,
+    ) -> Result<(u64, Option<AddedStats>, Arc<AtomicU64>)> {
+        let dataset = self.dataset.get().await?.clone();
+        let read_version = dataset.version().version;
+        let data = self.with_embeddings(data).await?;
+        let data = self.with_schema_policy(data, schema_policy).await?;
+        Self::check_bulk_ingest_schema(&dataset, &data.schema())?;
+        let (data, rows_written) = metrics::count_rows(data);
+        let (data, added_stats) = self.track_stats(data).await?;
+        let (data, quota_write) = self.start_quota_write(data, false)?;
+
+        let params = WriteParams {
+            max_rows_per_file: options.target_fragment_rows.max(1),
+            ..params
+        };
+        let fragments = self.write_fragments(data, options, &params).await;
+
+        let result = match fragments {
+            Ok(fragments) if fragments.is_empty() => Ok(dataset),
+            Ok(fragments) => Dataset::commit(
+                &self.uri,
+                Operation::Append { fragments },
+                Some(read_version),
+                params.store_params.clone(),
+                params.commit_handler.clone(),
+            )
+            .await
+            .map_err(Error::from),
+            Err(e) => Err(e),
+        };
+        let dataset = self.finish_quota_write(quota_write, result)?;
+        let version = dataset.version().version;
+        self.dataset.set_latest(dataset).await?;
+        Ok((version, added_stats, rows_written))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int32Type;
+    use arrow_array::Int32Array;
+    use arrow_schema::{DataType, Field as ArrowField};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+    use crate::table::AddDataMode;
+
+    fn batches(schema: &SchemaRef, rows: i32) -> impl RecordBatchReader + Send + 'static {
+        let batches = (0..rows)
+            .step_by(7)
+            .map(|start| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from_iter_values(
+                        start..(start + 7).min(rows),
+                    ))],
+                )
+            })
+            .collect::<Vec<_>>();
+        RecordBatchIterator::new(batches, schema.clone())
+    }
+
+    #[tokio::test]
+    async fn test_bulk_ingest() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(arrow_schema::Schema::new(vec![ArrowField::new(
+            "i",
+            DataType::Int32,
+            false,
+        )]));
+        let table = db
+            .create_empty_table("test", schema.clone())
+            .execute()
+            .await
+            .unwrap();
+        let version = table.version().await.unwrap();
+
+        table
+            .add(batches(&schema, 1000))
+            .mode(AddDataMode::BulkIngest(BulkIngestOptions {
+                parallelism: 4,
+                target_fragment_rows: 100,
+            }))
+            .execute()
+            .await
+            .unwrap();
+        // Committed once, with fragments of the target size
+        assert_eq!(table.version().await.unwrap(), version + 1);
+        let dataset = table.as_native().unwrap().dataset.get().await.unwrap();
+        assert_eq!(dataset.get_fragments().len(), 10);
+        drop(dataset);
+
+        // The order of the rows is kept
+        let rows = table
+            .query()
+            .limit(1000)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let values = rows
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_primitive::<Int32Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+
+        let other = Arc::new(arrow_schema::Schema::new(vec![ArrowField::new(
+            "j",
+            DataType::Int32,
+            false,
+        )]));
+        let result = table
+            .add(batches(&other, 10))
+            .mode(AddDataMode::BulkIngest(BulkIngestOptions::default()))
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+    }
+}
@@ -0,0 +1,585 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The rows changed between two versions of a table
+//!
+//! The changes are found by comparing the fragments of the two versions, without
+//! scanning the rows which did not change:
+//!
+//! - the rows of the fragments added since the first version were inserted
+//! - the rows of the fragments removed since the first version were deleted
+//! - the rows deleted from the fragments of both versions were deleted
+//!
+//! The changes are the net changes between the two versions: a row inserted and
+//! deleted in between is not reported.  An update rewrites the updated rows, so
+//! it is seen as the deletion of the old rows and the insertion of the new ones,
+//! and so is a compaction.  If the table has a primary key (see
+//! [`crate::connection::CreateTableBuilder::primary_key`]) the deleted and
+//! inserted rows with the same key are reported as updated rows, or not at all if
+//! their values did not change, as after a compaction.  This requires holding all
+//! of the changes in memory, otherwise they are read as they are streamed.
+//!
+//! The rows of the changes have a `_rowid` column.  The deleted rows have their
+//! values and row ids in the first version.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use arrow::compute::{concat_batches, filter_record_batch};
+use arrow::row::{RowConverter, SortField};
+use arrow_array::{
+    cast::AsArray, types::UInt64Type, ArrayRef, BooleanArray, RecordBatch, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use lance::dataset::fragment::FileFragment;
+use lance::Dataset;
+use lance_table::format::Fragment;
+
+use super::primary_key::primary_key;
+use super::{NativeTable, TableInternal};
+use crate::error::{Error, Result};
+
+const ROW_ID_COLUMN: &str = "_rowid";
+
+/// How a row changed, see [`Change`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    /// The rows were added
+    Inserted,
+    /// The values of the rows, identified by their primary key, changed
+    Updated,
+    /// The rows were removed
+    Deleted,
+}
+
+/// Rows changed between two versions of a table
+#[derive(Debug, Clone)]
+pub struct Change {
+    /// How the rows changed
+    pub kind: ChangeKind,
+    /// The new values of inserted or updated rows, or the last values of deleted
+    /// rows, with their row ids
+    pub rows: RecordBatch,
+}
+
+/// The stream of the changes between two versions of a table
+pub struct ChangeStream {
+    since_version: u64,
+    to_version: u64,
+    stream: Pin<Box<dyn Stream<Item = Result<Change>> + Send>>,
+}
+
+impl ChangeStream {
+    /// The version the changes are from
+    pub fn since_version(&self) -> u64 {
+        self.since_version
+    }
+
+    /// The version the changes are to
+    ///
+    /// Pass this to [`super::Table::changes`] to get the following changes.
+    pub fn to_version(&self) -> u64 {
+        self.to_version
+    }
+}
+
+impl Stream for ChangeStream {
+    type Item = Result<Change>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.stream.poll_next_unpin(cx)
+    }
+}
+
+/// A builder used to get the changes to a table
+///
+/// See [`super::Table::changes`] for more context
+#[derive(Clone)]
+pub struct ChangesBuilder {
+    parent: Arc<dyn TableInternal>,
+    pub(crate) since_version: u64,
+    pub(crate) to_version: Option<u64>,
+}
+
+impl ChangesBuilder {
+    pub(super) fn new(parent: Arc<dyn TableInternal>, since_version: u64) -> Self {
+        Self {
+            parent,
+            since_version,
+            to_version: None,
+        }
+    }
+
+    /// Get the changes up to this version instead of the latest version
+    pub fn to_version(mut self, version: u64) -> Self {
+        self.to_version = Some(version);
+        self
+    }
+
+    /// Get the changes
+    ///
+    /// Fails if one of the versions was removed by
+    /// [`super::OptimizeAction::Prune`].
+    pub async fn execute(self) -> Result<ChangeStream> {
+        self.parent.clone().changes(self).await
+    }
+
+    /// Follow the changes to the table
+    ///
+    /// The latest version of the table is checked every `interval`, and the
+    /// changes since the previously checked version are returned as new versions
+    /// are committed.  The stream never ends.  An error, for example while the
+    /// table cannot be reached, is returned and the table is checked again after
+    /// the interval.
+    pub fn watch(self, interval: Duration) -> Pin<Box<dyn Stream<Item = Result<Change>> + Send>> {
+        let state = (self, None::<ChangeStream>, false);
+        Box::pin(stream::unfold(
+            state,
+            move |(mut builder, mut current, mut wait)| async move {
+                loop {
+                    if let Some(changes) = current.as_mut() {
+                        match changes.next().await {
+                            Some(change) => return Some((change, (builder, current, wait))),
+                            None => {
+                                builder.since_version = changes.to_version();
+                            }
+                        }
+                    }
+                    if wait {
+                        tokio::time::sleep(interval).await;
+                    }
+                    match builder.clone().execute().await {
+                        Ok(changes) => {
+                            wait = changes.to_version() == builder.since_version;
+                            current = Some(changes);
+                        }
+                        Err(err) => return Some((Err(err), (builder, None, true))),
+                    }
+                }
+            },
+        ))
+    }
+}
+
+/// A part of the changes, read as the changes are streamed
+enum Task {
+    /// All of the rows of a fragment of the new version
+    Inserted(Fragment),
+    /// All of the rows of a fragment of the old version
+    DeletedFragment(Fragment),
+    /// Rows of the old version
+    DeletedRows(Vec<u64>),
+}
+
+impl Task {
+    async fn read(self, old: &Dataset, new: &Dataset) -> Result<Change> {
+        match self {
+            Self::Inserted(fragment) => Ok(Change {
+                kind: ChangeKind::Inserted,
+                rows: scan_fragment(new, fragment).await?,
+            }),
+            Self::DeletedFragment(fragment) => Ok(Change {
+                kind: ChangeKind::Deleted,
+                rows: scan_fragment(old, fragment).await?,
+            }),
+            Self::DeletedRows(row_ids) => {
+                let rows = old.take_rows(&row_ids, old.schema()).await?;
+                let mut columns = rows.columns().to_vec();
+                columns.push(Arc::new(UInt64Array::from(row_ids)));
+                Ok(Change {
+                    kind: ChangeKind::Deleted,
+                    rows: RecordBatch::try_new(Arc::new(with_row_id_schema(old)), columns)?,
+                })
+            }
+        }
+    }
+}
+
+/// The schema of the rows of `dataset` with their row ids
+fn with_row_id_schema(dataset: &Dataset) -> Schema {
+    let mut fields = Schema::from(dataset.schema()).fields().to_vec();
+    fields.push(Arc::new(Field::new(ROW_ID_COLUMN, DataType::UInt64, true)));
+    Schema::new(fields)
+}
+
+/// All of the rows of `fragment`, with their row ids
+async fn scan_fragment(dataset: &Dataset, fragment: Fragment) -> Result<RecordBatch> {
+    let mut scanner = dataset.scan();
+    scanner.with_fragments(vec![fragment]);
+    scanner.with_row_id();
+    let batches = scanner
+        .try_into_stream()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(concat_batches(
+        &Arc::new(with_row_id_schema(dataset)),
+        &batches,
+    )?)
+}
+
+/// The ids of the rows of `fragment`
+async fn row_ids(dataset: &Dataset, fragment: &FileFragment) -> Result<Vec<u64>> {
+    let mut scanner = dataset.scan();
+    scanner.with_fragments(vec![fragment.metadata().clone()]);
+    scanner.with_row_id();
+    scanner.project(&[dataset.schema().fields[0].name.as_str()])?;
+    let batches = scanner
+        .try_into_stream()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(batches
+        .iter()
+        .flat_map(|batch| {
+            batch[ROW_ID_COLUMN]
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec()
+        })
+        .collect())
+}
+
+/// The name of the first data file of a fragment, which changes if the fragment
+/// is replaced by a fragment with the same id, as after an overwrite
+fn first_file(fragment: &FileFragment) -> Option<&str> {
+    fragment
+        .metadata()
+        .files
+        .first()
+        .map(|file| file.path.as_str())
+}
+
+/// The parts of the changes from `old` to `new`
+async fn plan(old: &Dataset, new: &Dataset) -> Result<Vec<Task>> {
+    let old_fragments = old
+        .get_fragments()
+        .into_iter()
+        .map(|fragment| (fragment.id(), fragment))
+        .collect::<HashMap<_, _>>();
+    let mut new_fragments = new.get_fragments();
+    new_fragments.sort_by_key(|fragment| fragment.id());
+
+    let mut tasks = Vec::new();
+    let mut kept = Vec::new();
+    for fragment in new_fragments {
+        match old_fragments.get(&fragment.id()) {
+            Some(old_fragment) if first_file(old_fragment) == first_file(&fragment) => {
+                // Rows are only ever deleted from a fragment
+                kept.push(fragment.id());
+                if old_fragment.count_rows().await? != fragment.count_rows().await? {
+                    let remaining = row_ids(new, &fragment).await?;
+                    let deleted = row_ids(old, old_fragment)
+                        .await?
+                        .into_iter()
+                        .filter(|row_id| remaining.binary_search(row_id).is_err())
+                        .collect();
+                    tasks.push(Task::DeletedRows(deleted));
+                }
+            }
+            _ => tasks.push(Task::Inserted(fragment.metadata().clone())),
+        }
+    }
+    let mut removed = old_fragments
+        .into_iter()
+        .filter(|(id, _)| !kept.contains(id))
+        .map(|(_, fragment)| fragment)
+        .collect::<Vec<_>>();
+    removed.sort_by_key(|fragment| fragment.id());
+    tasks.extend(
+        removed
+            .into_iter()
+            .map(|fragment| Task::DeletedFragment(fragment.metadata().clone())),
+    );
+    Ok(tasks)
+}
+
+/// The rows of `batch` where `keep` is true, or None if there are none
+fn select(batch: &RecordBatch, keep: Vec<bool>) -> Result<Option<RecordBatch>> {
+    let batch = filter_record_batch(batch, &BooleanArray::from(keep))?;
+    Ok((batch.num_rows() > 0).then_some(batch))
+}
+
+/// Pair the deleted and inserted rows with the same primary key
+fn match_keys(key: &str, changes: Vec<Change>) -> Result<Vec<Change>> {
+    let concat = |kind: ChangeKind| -> Result<Option<RecordBatch>> {
+        let batches = changes
+            .iter()
+            .filter(|change| change.kind == kind)
+            .map(|change| &change.rows)
+            .collect::<Vec<_>>();
+        match batches.first() {
+            Some(first) => Ok(Some(concat_batches(&first.schema(), batches.clone())?)),
+            None => Ok(None),
+        }
+    };
+    let (Some(deleted), Some(inserted)) =
+        (concat(ChangeKind::Deleted)?, concat(ChangeKind::Inserted)?)
+    else {
+        return Ok(changes);
+    };
+
+    let key_converter = RowConverter::new(vec![SortField::new(deleted[key].data_type().clone())])?;
+    let deleted_keys = key_converter.convert_columns(&[deleted[key].clone()])?;
+    let inserted_keys = key_converter.convert_columns(&[inserted[key].clone()])?;
+    let mut deleted_rows = deleted_keys
+        .iter()
+        .enumerate()
+        .map(|(i, row)| (row.owned(), i))
+        .collect::<HashMap<_, _>>();
+
+    // The values of the rows, compared if the schema did not change
+    let data = |batch: &RecordBatch| -> (Vec<Field>, Vec<ArrayRef>) {
+        batch
+            .schema()
+            .fields()
+            .iter()
+            .zip(batch.columns())
+            .filter(|(field, _)| field.name() != ROW_ID_COLUMN)
+            .map(|(field, column)| (field.as_ref().clone(), column.clone()))
+            .unzip()
+    };
+    let (deleted_fields, deleted_data) = data(&deleted);
+    let (inserted_fields, inserted_data) = data(&inserted);
+    let values = if deleted_fields == inserted_fields {
+        let converter = RowConverter::new(
+            deleted_fields
+                .iter()
+                .map(|field| SortField::new(field.data_type().clone()))
+                .collect(),
+        )?;
+        Some((
+            converter.convert_columns(&deleted_data)?,
+            converter.convert_columns(&inserted_data)?,
+        ))
+    } else {
+        None
+    };
+
+    let mut is_inserted = vec![true; inserted.num_rows()];
+    let mut is_updated = vec![false; inserted.num_rows()];
+    for (i, row) in inserted_keys.iter().enumerate() {
+        if let Some(j) = deleted_rows.remove(&row.owned()) {
+            is_inserted[i] = false;
+            is_updated[i] = match &values {
+                Some((deleted_values, inserted_values)) => {
+                    deleted_values.row(j) != inserted_values.row(i)
+                }
+                None => true,
+            };
+        }
+    }
+    let mut is_deleted = vec![false; deleted.num_rows()];
+    for j in deleted_rows.into_values() {
+        is_deleted[j] = true;
+    }
+
+    let mut matched = Vec::new();
+    for (kind, batch, keep) in [
+        (ChangeKind::Inserted, &inserted, is_inserted),
+        (ChangeKind::Updated, &inserted, is_updated),
+        (ChangeKind::Deleted, &deleted, is_deleted),
+    ] {
+        if let Some(rows) = select(batch, keep)? {
+            matched.push(Change { kind, rows });
+        }
+    }
+    Ok(matched)
+}
+
+impl NativeTable {
+    pub(super) async fn changes_impl(&self, params: ChangesBuilder) -> Result<ChangeStream> {
+        let dataset = self.dataset.get().await?.clone();
+        let to_version = match params.to_version {
+            Some(version) => version,
+            None => dataset.latest_version_id().await?,
+        };
+        let since_version = params.since_version;
+        if since_version > to_version {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "cannot get the changes from version {} to the earlier version {}",
+                    since_version, to_version
+                ),
+            });
+        }
+        let old = dataset.checkout_version(since_version).await?;
+        let new = dataset.checkout_version(to_version).await?;
+        let tasks = plan(&old, &new).await?;
+        let key = primary_key(&Schema::from(new.schema())).map(str::to_string);
+        let changes = stream::iter(tasks).then(move |task| {
+            let (old, new) = (old.clone(), new.clone());
+            async move { task.read(&old, &new).await }
+        });
+
+        let stream: Pin<Box<dyn Stream<Item = Result<Change>> + Send>> = match key {
+            Some(key) => {
+                let changes = changes.try_collect::<Vec<_>>().await?;
+                Box::pin(stream::iter(match_keys(&key, changes)?.into_iter().map(Ok)))
+            }
+            None => Box::pin(changes),
+        };
+        Ok(ChangeStream {
+            since_version,
+            to_version,
+            stream,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{cast::AsArray, types::Int32Type, Int32Array, RecordBatchIterator};
+    use arrow_array::{RecordBatchReader, StringArray};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    fn batch(ids: Vec<i32>, values: Vec<&str>) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("value", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(ids)),
+                Arc::new(StringArray::from(values)),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    /// The ids of the rows changed in each way
+    async fn changes(changes: ChangeStream) -> HashMap<ChangeKind, Vec<i32>> {
+        let mut ids: HashMap<ChangeKind, Vec<i32>> = HashMap::new();
+        for change in changes.try_collect::<Vec<_>>().await.unwrap() {
+            ids.entry(change.kind)
+                .or_default()
+                .extend(change.rows["id"].as_primitive::<Int32Type>().values());
+        }
+        for ids in ids.values_mut() {
+            ids.sort();
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_changes() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db
+            .create_table("t", batch(vec![1, 2, 3], vec!["a", "b", "c"]))
+            .execute()
+            .await
+            .unwrap();
+        let first = table.version().await.unwrap();
+        table
+            .add(batch(vec![4, 5], vec!["d", "e"]))
+            .execute()
+            .await
+            .unwrap();
+        table.delete("id = 2 OR id = 5").await.unwrap();
+        table
+            .update()
+            .only_if("id = 3")
+            .column("value", "'z'")
+            .execute()
+            .await
+            .unwrap();
+
+        let stream = table.changes(first).execute().await.unwrap();
+        assert_eq!(stream.since_version(), first);
+        assert_eq!(stream.to_version(), table.version().await.unwrap());
+        let ids = changes(stream).await;
+        // Without a primary key the update is a delete and an insert
+        assert_eq!(ids[&ChangeKind::Inserted], vec![3, 4]);
+        assert_eq!(ids[&ChangeKind::Deleted], vec![2, 3]);
+        assert!(!ids.contains_key(&ChangeKind::Updated));
+
+        let ids = changes(
+            table
+                .changes(first)
+                .to_version(first + 1)
+                .execute()
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(ids[&ChangeKind::Inserted], vec![4, 5]);
+        assert!(!ids.contains_key(&ChangeKind::Deleted));
+
+        let latest = table.version().await.unwrap();
+        assert!(changes(table.changes(latest).execute().await.unwrap())
+            .await
+            .is_empty());
+        assert!(table
+            .changes(latest + 1)
+            .to_version(first)
+            .execute()
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_changes_with_primary_key() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db
+            .create_table("t", batch(vec![1, 2, 3], vec!["a", "b", "c"]))
+            .primary_key("id")
+            .execute()
+            .await
+            .unwrap();
+        let first = table.version().await.unwrap();
+        table
+            .update()
+            .only_if("id = 3")
+            .column("value", "'z'")
+            .execute()
+            .await
+            .unwrap();
+        table.delete("id = 1").await.unwrap();
+
+        let ids = changes(table.changes(first).execute().await.unwrap()).await;
+        assert_eq!(ids[&ChangeKind::Updated], vec![3]);
+        assert_eq!(ids[&ChangeKind::Deleted], vec![1]);
+        assert!(!ids.contains_key(&ChangeKind::Inserted));
+
+        // Watch for the next changes
+        let mut watch = table
+            .changes(table.version().await.unwrap())
+            .watch(Duration::from_millis(10));
+        table
+            .add(batch(vec![6], vec!["f"]))
+            .execute()
+            .await
+            .unwrap();
+        let change = watch.next().await.unwrap().unwrap();
+        assert_eq!(change.kind, ChangeKind::Inserted);
+        assert_eq!(change.rows["id"].as_primitive::<Int32Type>().values(), &[6]);
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! K-means clustering of a vector column
+//!
+//! The centroids are trained with the same kmeans used to train the IVF
+//! partitions of a vector index and every row is then assigned to its closest
+//! centroid in a new column.
+
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, Array, FixedSizeListArray, RecordBatch, UInt32Array};
+use arrow_schema::{DataType, Field, Schema};
+use lance::dataset::{BatchUDF, NewColumnTransform};
+use lance_linalg::distance::{cosine_distance, dot_distance, l2_distance};
+use rand::{rngs::SmallRng, SeedableRng};
+
+use super::{NativeTable, TableInternal};
+use crate::error::{Error, Result};
+use crate::index::vector::{sample_training_vectors, Accelerator};
+use crate::DistanceType;
+
+/// A builder used to cluster the vectors of a table
+///
+/// See [`super::Table::cluster`] for more context
+pub struct ClusterBuilder {
+    parent: Arc<dyn TableInternal>,
+    pub(crate) column: String,
+    pub(crate) k: usize,
+    pub(crate) output_column: String,
+    pub(crate) distance_type: DistanceType,
+    pub(crate) sample_rate: u32,
+    pub(crate) max_iterations: u32,
+    pub(crate) accelerator: Accelerator,
+}
+
+impl ClusterBuilder {
+    pub(super) fn new(parent: Arc<dyn TableInternal>, column: String, k: usize) -> Self {
+        Self {
+            parent,
+            column,
+            k,
+            output_column: "cluster_id".to_string(),
+            distance_type: DistanceType::L2,
+            sample_rate: 256,
+            max_iterations: 50,
+            accelerator: Accelerator::Cpu,
+        }
+    }
+
+    /// The name of the column the cluster ids are written to, the default is `cluster_id`
+    ///
+    /// If the column already exists it is replaced.
+    pub fn output_column(mut self, output_column: impl Into<String>) -> Self {
+        self.output_column = output_column.into();
+        self
+    }
+
+    /// The [`DistanceType`] used to compare vectors, the default is [`DistanceType::L2`]
+    pub fn distance_type(mut self, distance_type: DistanceType) -> Self {
+        self.distance_type = distance_type;
+        self
+    }
+
+    /// The number of training vectors per cluster, the default is 256
+    ///
+    /// See [`crate::index::vector::IvfPqIndexBuilder::sample_rate`]
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// The maximum number of kmeans iterations, the default is 50
+    pub fn max_iterations(mut self, max_iterations: u32) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// The device used to run kmeans, the default is [`Accelerator::Cpu`]
+    pub fn accelerator(mut self, accelerator: Accelerator) -> Self {
+        self.accelerator = accelerator;
+        self
+    }
+
+    /// Train the clusters and write the cluster id of every row
+    ///
+    /// Returns the centroids, the centroid of cluster `i` is at index `i`.
+    pub async fn execute(self) -> Result<FixedSizeListArray> {
+        self.parent.clone().cluster(self).await
+    }
+}
+
+/// The index of the centroid closest to `vector`
+fn closest_centroid(vector: &[f32], centroids: &[f32], distance_type: DistanceType) -> Option<u32> {
+    centroids
+        .chunks_exact(vector.len())
+        .map(|centroid| match distance_type {
+            DistanceType::Cosine => cosine_distance(vector, centroid),
+            DistanceType::Dot => dot_distance(vector, centroid),
+            _ => l2_distance(vector, centroid),
+        })
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(id, _)| id as u32)
+}
+
+impl NativeTable {
+    pub(super) async fn cluster_impl(&self, params: ClusterBuilder) -> Result<FixedSizeListArray> {
+        if params.k == 0 {
+            return Err(Error::InvalidInput {
+                message: "the number of clusters must be greater than 0".to_string(),
+            });
+        }
+        if matches!(
+            params.distance_type,
+            DistanceType::Hamming | DistanceType::MaxSim
+        ) {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "clustering does not support the {} distance",
+                    params.distance_type
+                ),
+            });
+        }
+        let dataset = self.dataset.get().await?.clone();
+        let (values, dim) =
+            sample_training_vectors(&dataset, &params.column, params.k, params.sample_rate).await?;
+        let centroids = match params.accelerator {
+            Accelerator::Cpu => {
+                let centroids =
+                    lance_index::vector::kmeans::train_kmeans::<arrow_array::types::Float32Type>(
+                        &values,
+                        None,
+                        dim,
+                        params.k,
+                        params.max_iterations,
+                        1,
+                        SmallRng::from_entropy(),
+                        params.distance_type.try_into()?,
+                        params.sample_rate as usize,
+                    )
+                    .await?;
+                FixedSizeListArray::try_new(
+                    Arc::new(Field::new("item", DataType::Float32, true)),
+                    dim as i32,
+                    Arc::new(centroids),
+                    None,
+                )?
+            }
+            #[cfg(feature = "cuda")]
+            Accelerator::Cuda(device) => {
+                crate::index::kmeans::train_centroids(
+                    values,
+                    dim,
+                    params.k,
+                    params.max_iterations,
+                    params.distance_type,
+                    device,
+                )
+                .await?
+            }
+            #[cfg(not(feature = "cuda"))]
+            Accelerator::Cuda(_) => {
+                return Err(Error::InvalidInput {
+                    message: "clustering on a GPU requires the `cuda` feature".to_string(),
+                })
+            }
+        };
+
+        if dataset.schema().field(&params.output_column).is_some() {
+            self.drop_columns(&[params.output_column.as_str()]).await?;
+        }
+        let output_schema = Arc::new(Schema::new(vec![Field::new(
+            &params.output_column,
+            DataType::UInt32,
+            true,
+        )]));
+        let mapper_schema = output_schema.clone();
+        let column = params.column.clone();
+        let distance_type = params.distance_type;
+        let centroid_values = centroids
+            .values()
+            .as_primitive::<arrow_array::types::Float32Type>()
+            .values()
+            .to_vec();
+        let mapper = move |batch: &RecordBatch| -> lance::Result<RecordBatch> {
+            let vectors = batch[column.as_str()].as_fixed_size_list();
+            let values = arrow_cast::cast(vectors.values(), &DataType::Float32)?;
+            let values = values.as_primitive::<arrow_array::types::Float32Type>();
+            let ids = (0..vectors.len())
+                .map(|row| {
+                    if vectors.is_null(row) {
+                        return None;
+                    }
+                    let vector = &values.values()[row * dim..(row + 1) * dim];
+                    closest_centroid(vector, &centroid_values, distance_type)
+                })
+                .collect::<UInt32Array>();
+            Ok(RecordBatch::try_new(
+                mapper_schema.clone(),
+                vec![Arc::new(ids)],
+            )?)
+        };
+        self.add_columns(
+            NewColumnTransform::BatchUDF(BatchUDF {
+                mapper: Box::new(mapper),
+                output_schema,
+                result_checkpoint: None,
+            }),
+            Some(vec![params.column]),
+        )
+        .await?;
+        Ok(centroids)
+    }
+}
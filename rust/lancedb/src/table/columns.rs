@@ -0,0 +1,150 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Schema changes built on top of adding, altering, and dropping columns
+//!
+//! Lance adds columns by computing their values from the existing columns, so
+//! null columns are added with a function that returns nulls.  It cannot change
+//! the type of a column, so a column is cast by adding a converted copy of it,
+//! dropping the original, and renaming the copy.  Each of these steps is a
+//! separate version of the table.
+
+use std::sync::Arc;
+
+use arrow::compute::{cast_with_options, CastOptions};
+use arrow_array::{new_null_array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use lance::dataset::{BatchUDF, ColumnAlteration, NewColumnTransform};
+
+use super::TableInternal;
+use crate::error::{Error, Result};
+
+/// The suffix of the copy of a column while it is cast
+const CAST_SUFFIX: &str = "__lancedb_cast";
+
+pub(super) async fn add_null_columns(table: &dyn TableInternal, fields: SchemaRef) -> Result<()> {
+    let schema = table.schema().await?;
+    for field in fields.fields() {
+        if !field.is_nullable() {
+            return Err(Error::Schema {
+                message: format!(
+                    "The column {} must be nullable to be filled with nulls",
+                    field.name()
+                ),
+            });
+        }
+        if schema.field_with_name(field.name()).is_ok() {
+            return Err(Error::Schema {
+                message: format!("The column {} already exists", field.name()),
+            });
+        }
+    }
+    // Any column will do, it is only read to know the number of rows
+    let Some(read_column) = schema.fields().first().map(|field| field.name().clone()) else {
+        return Err(Error::Schema {
+            message: "columns cannot be added to a table without columns".to_string(),
+        });
+    };
+
+    let output_schema = fields.clone();
+    let mapper = move |batch: &RecordBatch| -> lance::Result<RecordBatch> {
+        let columns = output_schema
+            .fields()
+            .iter()
+            .map(|field| new_null_array(field.data_type(), batch.num_rows()))
+            .collect();
+        Ok(RecordBatch::try_new(output_schema.clone(), columns)?)
+    };
+    table
+        .add_columns(
+            NewColumnTransform::BatchUDF(BatchUDF {
+                mapper: Box::new(mapper),
+                output_schema: fields,
+                result_checkpoint: None,
+            }),
+            Some(vec![read_column]),
+        )
+        .await
+}
+
+pub(super) async fn alter_column_types(
+    table: &dyn TableInternal,
+    columns: &[(String, DataType)],
+) -> Result<()> {
+    let schema = table.schema().await?;
+    let indices = table.list_indices().await?;
+    for (column, data_type) in columns {
+        let field = schema.field_with_name(column).map_err(|_| Error::Schema {
+            message: format!("The column {} does not exist", column),
+        })?;
+        if let Some(index) = indices.iter().find(|index| index.columns.contains(column)) {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "The column {} has the index {}, drop the index before changing its type",
+                    column, index.name
+                ),
+            });
+        }
+        if !arrow::compute::can_cast_types(field.data_type(), data_type) {
+            return Err(Error::Schema {
+                message: format!(
+                    "The column {} cannot be converted from {} to {}",
+                    column,
+                    field.data_type(),
+                    data_type
+                ),
+            });
+        }
+    }
+
+    for (column, data_type) in columns {
+        let field = schema.field_with_name(column)?;
+        if field.data_type() == data_type {
+            continue;
+        }
+        let copy = format!("{}{}", column, CAST_SUFFIX);
+        let output_schema = Arc::new(Schema::new(vec![Field::new(
+            &copy,
+            data_type.clone(),
+            field.is_nullable(),
+        )]));
+        let mapper_schema = output_schema.clone();
+        let source = column.clone();
+        let target = data_type.clone();
+        let mapper = move |batch: &RecordBatch| -> lance::Result<RecordBatch> {
+            // Fail instead of replacing the values that do not fit with nulls
+            let options = CastOptions {
+                safe: false,
+                ..Default::default()
+            };
+            let values = cast_with_options(&batch[source.as_str()], &target, &options)?;
+            Ok(RecordBatch::try_new(mapper_schema.clone(), vec![values])?)
+        };
+        table
+            .add_columns(
+                NewColumnTransform::BatchUDF(BatchUDF {
+                    mapper: Box::new(mapper),
+                    output_schema,
+                    result_checkpoint: None,
+                }),
+                Some(vec![column.clone()]),
+            )
+            .await?;
+        table.drop_columns(&[column.as_str()]).await?;
+        table
+            .alter_columns(&[ColumnAlteration::new(copy).rename(column.clone())])
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,227 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Retrying commits that conflict with concurrent writers
+//!
+//! Lance rebases a commit onto the commits of concurrent writers when their
+//! changes are compatible, for example two appends.  Otherwise the commit fails
+//! with a conflict, for example when two deletes rewrite the same fragment.
+//! Operations that can be run again, such as deletes and updates, are then
+//! retried against the latest version of the table, up to the number of retries
+//! of the connection (see [`crate::connection::ConnectBuilder::commit_retries`]).
+//! Each retry waits according to a [`CommitBackoff`], so that the writers that
+//! conflicted do not conflict again right away.  Both can be overridden for a
+//! single write with [`super::WriteOptions`].
+//!
+//! Adds consume their input, so they are only retried when
+//! [`super::WriteOptions::max_commit_retries`] is set, and their input is then
+//! buffered in memory.
+//!
+//! Each conflict is logged.  Once the retries are exhausted the conflict is
+//! returned as an [`Error::CommitConflict`].
+
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+
+use super::NativeTable;
+use crate::error::{Error, Result};
+
+/// The number of times a conflicting commit is retried by default
+pub const DEFAULT_COMMIT_RETRIES: u32 = 5;
+
+/// How long to wait before retrying a conflicting commit
+///
+/// The wait starts at [`Self::initial`] and is multiplied by
+/// [`Self::multiplier`] after each retry, up to [`Self::max`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommitBackoff {
+    /// The wait before the first retry, 10ms by default
+    pub initial: Duration,
+    /// The longest wait before a retry, 1s by default
+    pub max: Duration,
+    /// The factor applied to the wait after each retry, 2 by default
+    pub multiplier: f64,
+    /// Wait a random duration between zero and the wait, true by default
+    ///
+    /// This spreads out the retries of writers that conflicted together.
+    pub jitter: bool,
+}
+
+impl Default for CommitBackoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(10),
+            max: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: true,
+        }
+    }
+}
+
+impl CommitBackoff {
+    /// Retry right away, without waiting
+    pub fn none() -> Self {
+        Self {
+            initial: Duration::ZERO,
+            max: Duration::ZERO,
+            multiplier: 1.0,
+            jitter: false,
+        }
+    }
+
+    /// The wait before the retry number `retry`, starting at 1
+    pub(crate) fn delay(&self, retry: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(retry.saturating_sub(1) as i32);
+        let delay = self
+            .initial
+            .mul_f64(factor.min(u32::MAX as f64))
+            .min(self.max);
+        if self.jitter && !delay.is_zero() {
+            delay.mul_f64(rand::thread_rng().gen::<f64>())
+        } else {
+            delay
+        }
+    }
+}
+
+/// The version committed by the concurrent writer, if `err` is a commit conflict
+fn conflicting_version(err: &Error) -> Option<u64> {
+    match err {
+        Error::Lance {
+            source: lance::Error::CommitConflict { version, .. },
+        } => Some(*version),
+        _ => None,
+    }
+}
+
+impl NativeTable {
+    /// Retry conflicting commits up to `retries` times
+    pub(crate) fn with_commit_retries(mut self, retries: u32) -> Self {
+        self.commit_retries = retries;
+        self
+    }
+
+    /// Wait according to `backoff` before retrying a conflicting commit
+    pub(crate) fn with_commit_backoff(mut self, backoff: CommitBackoff) -> Self {
+        self.commit_backoff = backoff;
+        self
+    }
+
+    /// Convert a commit conflict of the `operation` to [`Error::CommitConflict`]
+    ///
+    /// Other errors are returned unchanged.
+    pub(super) fn commit_conflict(&self, operation: &str, attempts: u32, err: Error) -> Error {
+        match conflicting_version(&err) {
+            Some(version) => Error::CommitConflict {
+                table: self.name.clone(),
+                operation: operation.to_string(),
+                version,
+                attempts,
+                message: match err {
+                    Error::Lance {
+                        source: lance::Error::CommitConflict { source, .. },
+                    } => source.to_string(),
+                    err => err.to_string(),
+                },
+            },
+            None => err,
+        }
+    }
+
+    /// Run `commit`, running it again on the latest version of the table if it
+    /// conflicts with a concurrent commit
+    ///
+    /// `commit` must read the dataset each time it is called so that it is run
+    /// against the reloaded dataset.
+    pub(super) async fn retry_on_conflict<T, F, Fut>(&self, operation: &str, commit: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.retry_on_conflict_with(operation, None, None, commit)
+            .await
+    }
+
+    /// Like [`Self::retry_on_conflict`], with the retries and the backoff of
+    /// the table overridden by those given
+    pub(super) async fn retry_on_conflict_with<T, F, Fut>(
+        &self,
+        operation: &str,
+        retries: Option<u32>,
+        backoff: Option<CommitBackoff>,
+        mut commit: F,
+    ) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let retries = retries.unwrap_or(self.commit_retries);
+        let backoff = backoff.unwrap_or(self.commit_backoff);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let err = match commit().await {
+                Err(err) if conflicting_version(&err).is_some() => err,
+                result => return result,
+            };
+            if attempts > retries {
+                return Err(self.commit_conflict(operation, attempts, err));
+            }
+            let delay = backoff.delay(attempts);
+            log::warn!(
+                "commit conflict: table={} operation={} winning_version={} attempt={} \
+                 max_attempts={} error=\"{}\", retrying on the latest version in {:?}",
+                self.name,
+                operation,
+                conflicting_version(&err).unwrap_or_default(),
+                attempts,
+                retries + 1,
+                err,
+                delay
+            );
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            self.dataset.reload().await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_backoff() {
+        let backoff = CommitBackoff {
+            jitter: false,
+            ..Default::default()
+        };
+        assert_eq!(backoff.delay(1), Duration::from_millis(10));
+        assert_eq!(backoff.delay(2), Duration::from_millis(20));
+        assert_eq!(backoff.delay(4), Duration::from_millis(80));
+        assert_eq!(backoff.delay(100), Duration::from_secs(1));
+
+        let backoff = CommitBackoff::default();
+        for retry in 1..10 {
+            assert!(backoff.delay(retry) <= Duration::from_secs(1));
+        }
+        assert_eq!(CommitBackoff::none().delay(3), Duration::ZERO);
+    }
+}
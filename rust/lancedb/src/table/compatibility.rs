@@ -0,0 +1,348 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checking the compatibility of a schema with the schema of a table
+//!
+//! A proposed schema, for example the schema of a new version of a producer, is
+//! compared column by column with the schema of the table (see
+//! [`super::Table::check_compatibility`]).  Columns are matched by name, nested
+//! fields of structs are compared recursively, and the order of the columns is
+//! ignored.
+
+use std::fmt;
+
+use arrow_schema::{DataType, Field, Fields, Schema};
+
+/// How compatible a change to a schema is, from most to least compatible
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CompatibilityLevel {
+    /// The schemas are the same
+    Identical,
+    /// Only nullable columns are added, all of the existing data stays valid
+    AddOnly,
+    /// Columns are changed to types that can hold all of their current values,
+    /// or are made nullable
+    Widening,
+    /// Columns are removed, made non-nullable, changed to a narrower or an
+    /// unrelated type, or non-nullable columns are added
+    Breaking,
+}
+
+/// A change to a column between the current and the proposed schema
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaChange {
+    /// A column is in the proposed schema only
+    Added { nullable: bool },
+    /// A column is in the current schema only
+    Removed,
+    /// The type of a column changed
+    TypeChanged { from: DataType, to: DataType },
+    /// A column became nullable (`true`) or non-nullable (`false`)
+    NullabilityChanged { nullable: bool },
+}
+
+impl SchemaChange {
+    /// How compatible the change is
+    pub fn level(&self) -> CompatibilityLevel {
+        match self {
+            Self::Added { nullable: true } => CompatibilityLevel::AddOnly,
+            Self::Added { nullable: false } => CompatibilityLevel::Breaking,
+            Self::Removed => CompatibilityLevel::Breaking,
+            Self::TypeChanged { from, to } => {
+                if is_widening(from, to) {
+                    CompatibilityLevel::Widening
+                } else {
+                    CompatibilityLevel::Breaking
+                }
+            }
+            Self::NullabilityChanged { nullable: true } => CompatibilityLevel::Widening,
+            Self::NullabilityChanged { nullable: false } => CompatibilityLevel::Breaking,
+        }
+    }
+}
+
+/// A change to a column, see [`CompatibilityReport::changes`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnChange {
+    /// The path of the column, nested fields are separated by `.`
+    pub column: String,
+    pub change: SchemaChange,
+}
+
+impl fmt::Display for ColumnChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.change {
+            SchemaChange::Added { nullable } => write!(
+                f,
+                "{} is added ({})",
+                self.column,
+                if *nullable {
+                    "nullable"
+                } else {
+                    "non-nullable"
+                }
+            ),
+            SchemaChange::Removed => write!(f, "{} is removed", self.column),
+            SchemaChange::TypeChanged { from, to } => {
+                write!(f, "{} changes type from {} to {}", self.column, from, to)
+            }
+            SchemaChange::NullabilityChanged { nullable: true } => {
+                write!(f, "{} becomes nullable", self.column)
+            }
+            SchemaChange::NullabilityChanged { nullable: false } => {
+                write!(f, "{} becomes non-nullable", self.column)
+            }
+        }
+    }
+}
+
+/// The result of [`super::Table::check_compatibility`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompatibilityReport {
+    /// The least compatible level of the changes
+    pub level: CompatibilityLevel,
+    /// The changes to the columns, in the order of the current schema followed by
+    /// the added columns
+    pub changes: Vec<ColumnChange>,
+}
+
+impl CompatibilityReport {
+    /// Whether the proposed schema can be adopted without breaking the table,
+    /// that is, if no change is [`CompatibilityLevel::Breaking`]
+    pub fn is_compatible(&self) -> bool {
+        self.level < CompatibilityLevel::Breaking
+    }
+
+    /// The changes that are [`CompatibilityLevel::Breaking`]
+    pub fn breaking_changes(&self) -> impl Iterator<Item = &ColumnChange> {
+        self.changes
+            .iter()
+            .filter(|change| change.change.level() == CompatibilityLevel::Breaking)
+    }
+}
+
+/// Compare the schema `proposed` with the schema `current`
+pub fn check_compatibility(current: &Schema, proposed: &Schema) -> CompatibilityReport {
+    let mut changes = Vec::new();
+    compare_fields(None, current.fields(), proposed.fields(), &mut changes);
+    let level = changes
+        .iter()
+        .map(|change| change.change.level())
+        .max()
+        .unwrap_or(CompatibilityLevel::Identical);
+    CompatibilityReport { level, changes }
+}
+
+fn compare_fields(
+    parent: Option<&str>,
+    current: &Fields,
+    proposed: &Fields,
+    changes: &mut Vec<ColumnChange>,
+) {
+    let path = |field: &Field| match parent {
+        Some(parent) => format!("{}.{}", parent, field.name()),
+        None => field.name().clone(),
+    };
+    for field in current.iter() {
+        let column = path(field);
+        match proposed.find(field.name()) {
+            None => changes.push(ColumnChange {
+                column,
+                change: SchemaChange::Removed,
+            }),
+            Some((_, new_field)) => {
+                match (field.data_type(), new_field.data_type()) {
+                    (DataType::Struct(fields), DataType::Struct(new_fields)) => {
+                        compare_fields(Some(&column), fields, new_fields, changes)
+                    }
+                    (from, to) if from != to => changes.push(ColumnChange {
+                        column: column.clone(),
+                        change: SchemaChange::TypeChanged {
+                            from: from.clone(),
+                            to: to.clone(),
+                        },
+                    }),
+                    _ => {}
+                }
+                if field.is_nullable() != new_field.is_nullable() {
+                    changes.push(ColumnChange {
+                        column,
+                        change: SchemaChange::NullabilityChanged {
+                            nullable: new_field.is_nullable(),
+                        },
+                    });
+                }
+            }
+        }
+    }
+    for field in proposed.iter() {
+        if current.find(field.name()).is_none() {
+            changes.push(ColumnChange {
+                column: path(field),
+                change: SchemaChange::Added {
+                    nullable: field.is_nullable(),
+                },
+            });
+        }
+    }
+}
+
+/// The number of bits of an integer type and whether it is signed
+fn integer_width(data_type: &DataType) -> Option<(u32, bool)> {
+    match data_type {
+        DataType::Int8 => Some((8, true)),
+        DataType::Int16 => Some((16, true)),
+        DataType::Int32 => Some((32, true)),
+        DataType::Int64 => Some((64, true)),
+        DataType::UInt8 => Some((8, false)),
+        DataType::UInt16 => Some((16, false)),
+        DataType::UInt32 => Some((32, false)),
+        DataType::UInt64 => Some((64, false)),
+        _ => None,
+    }
+}
+
+/// The number of bits of the significand of a float type
+fn float_precision(data_type: &DataType) -> Option<u32> {
+    match data_type {
+        DataType::Float16 => Some(11),
+        DataType::Float32 => Some(24),
+        DataType::Float64 => Some(53),
+        _ => None,
+    }
+}
+
+/// Whether every value of type `from` can be represented exactly as type `to`
+fn is_widening(from: &DataType, to: &DataType) -> bool {
+    use DataType::*;
+
+    if from == to {
+        return true;
+    }
+    if let (Some((from_bits, from_signed)), Some((to_bits, to_signed))) =
+        (integer_width(from), integer_width(to))
+    {
+        return match (from_signed, to_signed) {
+            (true, true) | (false, false) => to_bits >= from_bits,
+            (false, true) => to_bits > from_bits,
+            (true, false) => false,
+        };
+    }
+    if let (Some((from_bits, _)), Some(precision)) = (integer_width(from), float_precision(to)) {
+        return from_bits < precision;
+    }
+    if let (Some(from_precision), Some(to_precision)) = (float_precision(from), float_precision(to))
+    {
+        return to_precision >= from_precision;
+    }
+    match (from, to) {
+        (Utf8, LargeUtf8) | (Binary, LargeBinary) => true,
+        (List(from), List(to) | LargeList(to)) | (LargeList(from), LargeList(to)) => {
+            is_widening_field(from, to)
+        }
+        (FixedSizeList(from, from_size), FixedSizeList(to, to_size)) => {
+            from_size == to_size && is_widening_field(from, to)
+        }
+        (FixedSizeList(from, _), List(to) | LargeList(to)) => is_widening_field(from, to),
+        (Date32, Date64) => true,
+        _ => false,
+    }
+}
+
+fn is_widening_field(from: &Field, to: &Field) -> bool {
+    is_widening(from.data_type(), to.data_type()) && (to.is_nullable() || !from.is_nullable())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn vector(data_type: DataType, dim: i32) -> DataType {
+        DataType::FixedSizeList(Arc::new(Field::new("item", data_type, true)), dim)
+    }
+
+    #[test]
+    fn test_check_compatibility() {
+        let current = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("text", DataType::Utf8, true),
+            Field::new("vector", vector(DataType::Float16, 4), true),
+            Field::new(
+                "meta",
+                DataType::Struct(vec![Field::new("score", DataType::Float32, false)].into()),
+                true,
+            ),
+        ]);
+        let report = check_compatibility(&current, &current);
+        assert_eq!(report.level, CompatibilityLevel::Identical);
+        assert!(report.changes.is_empty());
+
+        let mut fields = current.fields().iter().cloned().collect::<Vec<_>>();
+        fields.push(Arc::new(Field::new("extra", DataType::Utf8, true)));
+        let report = check_compatibility(&current, &Schema::new(fields.clone()));
+        assert_eq!(report.level, CompatibilityLevel::AddOnly);
+
+        let widened = Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("text", DataType::LargeUtf8, true),
+            Field::new("vector", vector(DataType::Float32, 4), true),
+            Field::new(
+                "meta",
+                DataType::Struct(vec![Field::new("score", DataType::Float64, false)].into()),
+                true,
+            ),
+        ]);
+        let report = check_compatibility(&current, &widened);
+        assert_eq!(report.level, CompatibilityLevel::Widening);
+        assert!(report.is_compatible());
+        assert_eq!(report.changes.len(), 5);
+        assert_eq!(report.changes[4].column, "meta.score");
+
+        let breaking = Schema::new(vec![
+            Field::new("id", DataType::UInt32, false),
+            Field::new("vector", vector(DataType::Float32, 8), true),
+            Field::new(
+                "meta",
+                DataType::Struct(vec![Field::new("score", DataType::Float32, false)].into()),
+                false,
+            ),
+        ]);
+        let report = check_compatibility(&current, &breaking);
+        assert_eq!(report.level, CompatibilityLevel::Breaking);
+        assert!(!report.is_compatible());
+        let breaking = report
+            .breaking_changes()
+            .map(|change| change.column.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(breaking, vec!["id", "text", "vector", "meta"]);
+        assert_eq!(
+            report.changes[0].to_string(),
+            "id changes type from Int32 to UInt32"
+        );
+    }
+
+    #[test]
+    fn test_is_widening() {
+        assert!(is_widening(&DataType::Int8, &DataType::Int64));
+        assert!(is_widening(&DataType::UInt16, &DataType::Int32));
+        assert!(!is_widening(&DataType::UInt32, &DataType::Int32));
+        assert!(!is_widening(&DataType::Int32, &DataType::UInt64));
+        assert!(is_widening(&DataType::Int16, &DataType::Float32));
+        assert!(!is_widening(&DataType::Int32, &DataType::Float32));
+        assert!(is_widening(&DataType::Int32, &DataType::Float64));
+        assert!(!is_widening(&DataType::Float64, &DataType::Float32));
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The compression codecs of the columns of a table
+//!
+//! The codec of a column is chosen when the table is created, with
+//! [`crate::connection::CreateTableBuilder::column_compression`], and is
+//! stored in the metadata of the field of the column, under the keys read by
+//! the Lance encoders.  Large text columns benefit from heavy compression while
+//! vector columns, which compress poorly, are better left uncompressed.
+//!
+//! The codecs are applied by the Lance file formats which support per column
+//! compression.  Files written in the version 1 format, the format of the
+//! tables written by this release, store the columns uncompressed; the codecs
+//! are kept in the schema and apply once the table is written in a format
+//! supporting them.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{RecordBatchIterator, RecordBatchReader};
+use arrow_schema::{Field, Schema};
+
+use crate::error::{Error, Result};
+
+/// The field metadata key holding the compression codec of a column
+pub const COMPRESSION_METADATA_KEY: &str = "lance-encoding:compression";
+/// The field metadata key holding the compression level of a column
+pub const COMPRESSION_LEVEL_METADATA_KEY: &str = "lance-encoding:compression-level";
+
+/// The range of the levels of zstd
+const ZSTD_LEVELS: std::ops::RangeInclusive<i32> = 1..=22;
+
+/// The compression codec of a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Store the column uncompressed
+    None,
+    /// Fast compression with lz4
+    Lz4,
+    /// Compression with zstd at the given level, from 1 to 22
+    ///
+    /// Higher levels compress better and more slowly.  The default level of
+    /// zstd is used when the level is not given.
+    Zstd(Option<i32>),
+}
+
+impl Compression {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Lz4 => "lz4",
+            Self::Zstd(_) => "zstd",
+        }
+    }
+
+    fn validate(&self, column: &str) -> Result<()> {
+        match self {
+            Self::Zstd(Some(level)) if !ZSTD_LEVELS.contains(level) => Err(Error::InvalidInput {
+                message: format!(
+                    "The zstd level of the column '{}' is {} but must be between {} and {}",
+                    column,
+                    level,
+                    ZSTD_LEVELS.start(),
+                    ZSTD_LEVELS.end()
+                ),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The codec stored in the metadata of `field`, if any
+    pub fn from_field(field: &Field) -> Option<Self> {
+        let metadata = field.metadata();
+        match metadata.get(COMPRESSION_METADATA_KEY)?.as_str() {
+            "none" => Some(Self::None),
+            "lz4" => Some(Self::Lz4),
+            "zstd" => Some(Self::Zstd(
+                metadata
+                    .get(COMPRESSION_LEVEL_METADATA_KEY)
+                    .and_then(|level| level.parse().ok()),
+            )),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, field: &Field) -> Field {
+        let mut metadata = field.metadata().clone();
+        metadata.insert(
+            COMPRESSION_METADATA_KEY.to_string(),
+            self.name().to_string(),
+        );
+        match self {
+            Self::Zstd(Some(level)) => metadata.insert(
+                COMPRESSION_LEVEL_METADATA_KEY.to_string(),
+                level.to_string(),
+            ),
+            _ => metadata.remove(COMPRESSION_LEVEL_METADATA_KEY),
+        };
+        field.clone().with_metadata(metadata)
+    }
+}
+
+/// The compression of a column, chosen by its tradeoff of size and speed
+///
+/// Each level is a codec, see the conversion into [`Compression`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionLevel {
+    /// No compression, for the columns which compress poorly such as vectors
+    None,
+    /// The fastest reads and writes, with lz4
+    Fast,
+    /// A balance of size and speed, with the default level of zstd
+    Balanced,
+    /// The smallest columns, with a high level of zstd and slow writes
+    Smallest,
+}
+
+/// The zstd level of [`CompressionLevel::Smallest`]
+const SMALLEST_ZSTD_LEVEL: i32 = 19;
+
+impl From<CompressionLevel> for Compression {
+    fn from(level: CompressionLevel) -> Self {
+        match level {
+            CompressionLevel::None => Self::None,
+            CompressionLevel::Fast => Self::Lz4,
+            CompressionLevel::Balanced => Self::Zstd(None),
+            CompressionLevel::Smallest => Self::Zstd(Some(SMALLEST_ZSTD_LEVEL)),
+        }
+    }
+}
+
+/// The schema `schema` with the codecs of `codecs` set on its columns
+pub(crate) fn schema_with_compression(
+    schema: &Schema,
+    codecs: &HashMap<String, Compression>,
+) -> Result<Schema> {
+    for (column, codec) in codecs {
+        if schema.field_with_name(column).is_err() {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "Cannot set the compression of the column '{}', it is not in the schema",
+                    column
+                ),
+            });
+        }
+        codec.validate(column)?;
+    }
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| match codecs.get(field.name()) {
+            Some(codec) => Arc::new(codec.apply(field)),
+            None => field.clone(),
+        })
+        .collect::<Vec<_>>();
+    Ok(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// The data `data` with the codecs of `codecs` set on the columns of its schema
+pub(crate) fn with_compression(
+    data: Box<dyn RecordBatchReader + Send>,
+    codecs: &HashMap<String, Compression>,
+) -> Result<Box<dyn RecordBatchReader + Send>> {
+    if codecs.is_empty() {
+        return Ok(data);
+    }
+    let schema = Arc::new(schema_with_compression(&data.schema(), codecs)?);
+    let batch_schema = schema.clone();
+    Ok(Box::new(RecordBatchIterator::new(
+        data.map(move |batch| batch.and_then(|batch| batch.with_schema(batch_schema.clone()))),
+        schema,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_schema::DataType;
+
+    use super::*;
+
+    #[test]
+    fn test_schema_with_compression() {
+        let schema = Schema::new(vec![
+            Field::new("text", DataType::Utf8, false),
+            Field::new("id", DataType::Int32, false),
+        ]);
+        let codecs = HashMap::from([("text".to_string(), Compression::Zstd(Some(19)))]);
+        let compressed = schema_with_compression(&schema, &codecs).unwrap();
+        assert_eq!(
+            Compression::from_field(compressed.field_with_name("text").unwrap()),
+            Some(Compression::Zstd(Some(19)))
+        );
+        assert_eq!(
+            Compression::from_field(compressed.field_with_name("id").unwrap()),
+            None
+        );
+
+        // Changing the codec drops the level of the previous one
+        let codecs = HashMap::from([("text".to_string(), Compression::Lz4)]);
+        let recompressed = schema_with_compression(&compressed, &codecs).unwrap();
+        let text = recompressed.field_with_name("text").unwrap();
+        assert_eq!(Compression::from_field(text), Some(Compression::Lz4));
+        assert!(!text.metadata().contains_key(COMPRESSION_LEVEL_METADATA_KEY));
+
+        let codecs = HashMap::from([("text".to_string(), CompressionLevel::Smallest.into())]);
+        let smallest = schema_with_compression(&schema, &codecs).unwrap();
+        assert_eq!(
+            Compression::from_field(smallest.field_with_name("text").unwrap()),
+            Some(Compression::Zstd(Some(19)))
+        );
+
+        let codecs = HashMap::from([("missing".to_string(), Compression::None)]);
+        assert!(matches!(
+            schema_with_compression(&schema, &codecs),
+            Err(Error::InvalidInput { .. })
+        ));
+        let codecs = HashMap::from([("text".to_string(), Compression::Zstd(Some(23)))]);
+        assert!(matches!(
+            schema_with_compression(&schema, &codecs),
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+}
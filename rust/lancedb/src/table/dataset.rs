@@ -26,8 +26,10 @@ use crate::error::Result;
 /// A wrapper around a [Dataset] that provides lazy-loading and consistency checks.
 ///
 /// This can be cloned cheaply. It supports concurrent reads or exclusive writes.
+/// The flag is set when the dataset is read through an object store that the
+/// datasets returned by writes do not have, see [`Self::read_through_store`].
 #[derive(Debug, Clone)]
-pub struct DatasetConsistencyWrapper(Arc<RwLock<DatasetRef>>);
+pub struct DatasetConsistencyWrapper(Arc<RwLock<DatasetRef>>, bool);
 
 /// A wrapper around a [Dataset] that provides consistency checks.
 ///
@@ -110,27 +112,54 @@ impl DatasetRef {
         }
     }
 
-    fn set_latest(&mut self, dataset: Dataset) {
+    async fn set_latest(&mut self, dataset: Dataset, through_store: bool) -> Result<()> {
         match self {
             Self::Latest {
                 dataset: ref mut ds,
                 ..
             } => {
-                *ds = dataset;
+                *ds = if through_store {
+                    ds.checkout_version(dataset.version().version).await?
+                } else {
+                    dataset
+                };
             }
             _ => unreachable!("Dataset should be in latest mode at this point"),
         }
+        Ok(())
     }
 }
 
 impl DatasetConsistencyWrapper {
     /// Create a new wrapper in the latest version mode.
     pub fn new_latest(dataset: Dataset, read_consistency_interval: Option<Duration>) -> Self {
-        Self(Arc::new(RwLock::new(DatasetRef::Latest {
-            dataset,
-            read_consistency_interval,
-            last_consistency_check: None,
-        })))
+        Self(
+            Arc::new(RwLock::new(DatasetRef::Latest {
+                dataset,
+                read_consistency_interval,
+                last_consistency_check: None,
+            })),
+            false,
+        )
+    }
+
+    /// Create a new wrapper pinned to the version of `dataset`.
+    pub fn new_time_travel(dataset: Dataset) -> Self {
+        let version = dataset.version().version;
+        Self(
+            Arc::new(RwLock::new(DatasetRef::TimeTravel { dataset, version })),
+            false,
+        )
+    }
+
+    /// Check out the versions given to [`Self::set_latest`] from the current
+    /// dataset, so that they keep reading through its object store
+    ///
+    /// The datasets returned by the writes of Lance read local files directly,
+    /// see [`crate::utils::read_local_through_wrapper`].
+    pub fn read_through_store(mut self, through_store: bool) -> Self {
+        self.1 = through_store;
+        self
     }
 
     /// Get an immutable reference to the dataset.
@@ -178,8 +207,8 @@ impl DatasetConsistencyWrapper {
     ///
     /// This is usually done after some write operation, which inherently will
     /// have the latest version.
-    pub async fn set_latest(&self, dataset: Dataset) {
-        self.0.write().await.set_latest(dataset);
+    pub async fn set_latest(&self, dataset: Dataset) -> Result<()> {
+        self.0.write().await.set_latest(dataset, self.1).await
     }
 
     pub async fn reload(&self) -> Result<()> {
@@ -0,0 +1,250 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Near duplicate detection
+//!
+//! Every vector in the table is used as a query vector and the rows returned
+//! within the distance threshold are reported as duplicates of it.  The searches
+//! use the vector index on the column, if there is one.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, types::Float32Type, types::UInt64Type, Array};
+use futures::{stream, TryStreamExt};
+
+use super::TableInternal;
+use crate::error::{Error, Result};
+use crate::query::{ExecutableQuery, Query, QueryBase, Select};
+use crate::DistanceType;
+
+const ROW_ID_COLUMN: &str = "_rowid";
+const DISTANCE_COLUMN: &str = "_distance";
+
+/// Two rows whose vectors are within the threshold of each other
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicatePair {
+    /// The row id of the first row, always less than `second`
+    pub first: u64,
+    /// The row id of the second row
+    pub second: u64,
+    /// The distance between the two vectors
+    pub distance: f32,
+}
+
+/// The result of [`super::Table::find_duplicates`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Duplicates {
+    /// Every pair of rows found within the threshold, ordered by row id
+    pub pairs: Vec<DuplicatePair>,
+    /// The pairs joined into groups of rows that are (transitively) duplicates
+    ///
+    /// Each group is sorted and the groups are ordered by their first row id.
+    /// Keeping the first row of each group and deleting the rest removes all
+    /// of the duplicates.
+    pub groups: Vec<Vec<u64>>,
+}
+
+/// A builder used to find near duplicate rows
+///
+/// See [`super::Table::find_duplicates`] for more context
+pub struct FindDuplicatesBuilder {
+    parent: Arc<dyn TableInternal>,
+    column: String,
+    threshold: f32,
+    filter: Option<String>,
+    candidates: usize,
+    nprobes: Option<usize>,
+    distance_type: Option<DistanceType>,
+    concurrency: usize,
+}
+
+impl FindDuplicatesBuilder {
+    pub(super) fn new(parent: Arc<dyn TableInternal>, column: String, threshold: f32) -> Self {
+        Self {
+            parent,
+            column,
+            threshold,
+            filter: None,
+            candidates: 10,
+            nprobes: None,
+            distance_type: None,
+            concurrency: 16,
+        }
+    }
+
+    /// Only look for duplicates among the rows matching the filter
+    pub fn only_if(mut self, filter: impl AsRef<str>) -> Self {
+        self.filter = Some(filter.as_ref().to_string());
+        self
+    }
+
+    /// The number of nearest neighbors checked for each row, the default is 10
+    ///
+    /// A row with more duplicates than this is still found, as long as its
+    /// duplicates are close enough to each other to end up in the same group.
+    pub fn candidates(mut self, candidates: usize) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    /// The number of partitions searched for each row, see [`crate::query::VectorQuery::nprobes`]
+    pub fn nprobes(mut self, nprobes: usize) -> Self {
+        self.nprobes = Some(nprobes);
+        self
+    }
+
+    /// The distance type, see [`crate::query::VectorQuery::distance_type`]
+    ///
+    /// The threshold is a distance of this type.  This should match the
+    /// distance type of the vector index.
+    pub fn distance_type(mut self, distance_type: DistanceType) -> Self {
+        self.distance_type = Some(distance_type);
+        self
+    }
+
+    /// The number of searches run at the same time, the default is 16
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Search for the duplicates of every row
+    pub async fn execute(self) -> Result<Duplicates> {
+        let mut scan = Query::new(self.parent.clone()).select(Select::columns(&[&self.column]));
+        if let Some(filter) = &self.filter {
+            scan = scan.only_if(filter);
+        }
+        scan.with_row_id = true;
+        let batches = scan.execute().await?;
+
+        let this = &self;
+        let pairs = batches
+            .map_ok(|batch| {
+                let row_ids = batch[ROW_ID_COLUMN].as_primitive::<UInt64Type>().clone();
+                let vectors = batch[this.column.as_str()].as_fixed_size_list().clone();
+                let nulls = vectors.nulls().cloned();
+                stream::iter(
+                    (0..batch.num_rows())
+                        .filter(move |&row| {
+                            nulls.as_ref().map_or(true, |nulls| nulls.is_valid(row))
+                        })
+                        .map(move |row| Ok::<_, Error>((row_ids.value(row), vectors.value(row)))),
+                )
+            })
+            .try_flatten()
+            .map_ok(|(row_id, vector)| this.neighbors(row_id, vector))
+            .try_buffer_unordered(self.concurrency)
+            .try_concat()
+            .await?;
+
+        // Each pair is usually found from both of its rows
+        let mut unique = BTreeMap::new();
+        for pair in pairs {
+            unique.entry((pair.first, pair.second)).or_insert(pair);
+        }
+        let pairs = unique.into_values().collect::<Vec<_>>();
+        let groups = groups(&pairs);
+        Ok(Duplicates { pairs, groups })
+    }
+
+    async fn neighbors(&self, row_id: u64, vector: Arc<dyn Array>) -> Result<Vec<DuplicatePair>> {
+        let mut query = Query::new(self.parent.clone())
+            .nearest_to(vector)?
+            .column(&self.column)
+            .select(Select::columns(&[&self.column]))
+            // The row itself is one of the results
+            .limit(self.candidates + 1);
+        if let Some(filter) = &self.filter {
+            query = query.only_if(filter);
+        }
+        if let Some(nprobes) = self.nprobes {
+            query = query.nprobes(nprobes);
+        }
+        if let Some(distance_type) = self.distance_type {
+            query = query.distance_type(distance_type);
+        }
+        query.base.with_row_id = true;
+
+        let batches = query.execute().await?.try_collect::<Vec<_>>().await?;
+        let mut pairs = Vec::new();
+        for batch in batches {
+            let row_ids = batch[ROW_ID_COLUMN].as_primitive::<UInt64Type>();
+            let distances = batch[DISTANCE_COLUMN].as_primitive::<Float32Type>();
+            for (other, distance) in row_ids.values().iter().zip(distances.values().iter()) {
+                if *other != row_id && *distance <= self.threshold {
+                    pairs.push(DuplicatePair {
+                        first: row_id.min(*other),
+                        second: row_id.max(*other),
+                        distance: *distance,
+                    });
+                }
+            }
+        }
+        Ok(pairs)
+    }
+}
+
+/// The connected components of the graph formed by the pairs
+fn groups(pairs: &[DuplicatePair]) -> Vec<Vec<u64>> {
+    fn find(parents: &mut HashMap<u64, u64>, id: u64) -> u64 {
+        let parent = *parents.entry(id).or_insert(id);
+        if parent == id {
+            return id;
+        }
+        let root = find(parents, parent);
+        parents.insert(id, root);
+        root
+    }
+
+    let mut parents = HashMap::new();
+    for pair in pairs {
+        let first = find(&mut parents, pair.first);
+        let second = find(&mut parents, pair.second);
+        if first != second {
+            // The smallest row id is the root, so roots are the first row of their group
+            parents.insert(first.max(second), first.min(second));
+        }
+    }
+    let mut groups = BTreeMap::<u64, Vec<u64>>::new();
+    let ids = parents.keys().copied().collect::<Vec<_>>();
+    for id in ids {
+        let root = find(&mut parents, id);
+        groups.entry(root).or_default().push(id);
+    }
+    groups
+        .into_values()
+        .map(|mut group| {
+            group.sort_unstable();
+            group
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups() {
+        let pair = |first, second| DuplicatePair {
+            first,
+            second,
+            distance: 0.0,
+        };
+        let pairs = vec![pair(1, 5), pair(2, 3), pair(3, 7), pair(5, 9), pair(0, 9)];
+        assert_eq!(groups(&pairs), vec![vec![0, 1, 5, 9], vec![2, 3, 7]]);
+        assert!(groups(&[]).is_empty());
+    }
+}
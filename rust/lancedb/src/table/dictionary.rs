@@ -0,0 +1,239 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Dictionary encoding of string columns
+//!
+//! A dictionary encoded column stores each distinct value once, and each row
+//! as an index into the distinct values.  This shrinks the columns with few
+//! distinct values, such as tags or categories, and speeds up the filters on
+//! them.  The string columns to encode are chosen when the table is created,
+//! explicitly with [`crate::connection::CreateTableBuilder::dictionary_encode`]
+//! or from the cardinality of the initial data with
+//! [`crate::connection::CreateTableBuilder::auto_dictionary_encode`], and the
+//! choice is stored in the metadata of the field of the column.
+//!
+//! Like the codecs of [`super::compression`], the encoding is applied by the
+//! Lance file formats which support per column encodings.  Files written in
+//! the version 1 format store the columns plain.  This is preferable to giving
+//! a column an Arrow dictionary type: a version 1 table reads the dictionary of
+//! such a column from its first data file, so every later write would have to
+//! use the exact same dictionary.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::{Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::error::{Error, Result};
+
+/// The field metadata key read by the Lance encoders to choose the dictionary
+/// encoding: a column is encoded when its number of values divided by this is
+/// more than its number of distinct values
+pub const DICTIONARY_METADATA_KEY: &str = "lance-encoding:dict-divisor";
+/// The divisor which encodes every column with repeated values
+const ALWAYS_ENCODE: &str = "1";
+
+/// Whether `field` is marked for dictionary encoding
+pub fn is_dictionary_encoded(field: &Field) -> bool {
+    field
+        .metadata()
+        .get(DICTIONARY_METADATA_KEY)
+        .is_some_and(|divisor| divisor == ALWAYS_ENCODE)
+}
+
+fn is_string(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Utf8 | DataType::LargeUtf8)
+}
+
+/// The string columns of `batch` whose ratio of distinct values to rows is at
+/// most `max_ratio`
+fn low_cardinality_columns(batch: &RecordBatch, max_ratio: f64) -> Vec<String> {
+    if batch.num_rows() == 0 {
+        return Vec::new();
+    }
+    let max_distinct = (batch.num_rows() as f64 * max_ratio) as usize;
+    batch
+        .schema()
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .filter(|(field, _)| is_string(field.data_type()))
+        .filter(|(_, array)| {
+            let distinct = match array.data_type() {
+                DataType::Utf8 => array
+                    .as_string::<i32>()
+                    .iter()
+                    .collect::<HashSet<_>>()
+                    .len(),
+                _ => array
+                    .as_string::<i64>()
+                    .iter()
+                    .collect::<HashSet<_>>()
+                    .len(),
+            };
+            distinct <= max_distinct
+        })
+        .map(|(field, _)| field.name().clone())
+        .collect()
+}
+
+/// The schema `schema` with `columns` marked for dictionary encoding
+pub(crate) fn schema_with_dictionaries(
+    schema: &Schema,
+    columns: &HashSet<String>,
+) -> Result<Schema> {
+    for column in columns {
+        match schema.field_with_name(column) {
+            Ok(field) if is_string(field.data_type()) => {}
+            Ok(field) => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "Cannot dictionary encode the column '{}' of type {}, only string \
+                         columns can be",
+                        column,
+                        field.data_type()
+                    ),
+                })
+            }
+            Err(_) => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "Cannot dictionary encode the column '{}', it is not in the schema",
+                        column
+                    ),
+                })
+            }
+        }
+    }
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            if columns.contains(field.name()) {
+                let mut metadata = field.metadata().clone();
+                metadata.insert(
+                    DICTIONARY_METADATA_KEY.to_string(),
+                    ALWAYS_ENCODE.to_string(),
+                );
+                Arc::new(field.as_ref().clone().with_metadata(metadata))
+            } else {
+                field.clone()
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// The data `data` with `columns` marked for dictionary encoding
+///
+/// With `auto_ratio`, the string columns of low cardinality in the first batch
+/// of `data` are marked as well, see [`low_cardinality_columns`].
+pub(crate) fn with_dictionaries(
+    mut data: Box<dyn RecordBatchReader + Send>,
+    columns: &HashSet<String>,
+    auto_ratio: Option<f64>,
+) -> Result<Box<dyn RecordBatchReader + Send>> {
+    if columns.is_empty() && auto_ratio.is_none() {
+        return Ok(data);
+    }
+    let mut columns = columns.clone();
+    let first = match auto_ratio {
+        Some(max_ratio) => {
+            let first = data.next().transpose()?;
+            if let Some(batch) = &first {
+                columns.extend(low_cardinality_columns(batch, max_ratio));
+            }
+            first
+        }
+        None => None,
+    };
+    if columns.is_empty() {
+        let schema = data.schema();
+        return Ok(Box::new(RecordBatchIterator::new(
+            first.map(Ok).into_iter().chain(data),
+            schema,
+        )));
+    }
+    let schema = Arc::new(schema_with_dictionaries(&data.schema(), &columns)?);
+    let batch_schema = schema.clone();
+    Ok(Box::new(RecordBatchIterator::new(
+        first
+            .map(Ok)
+            .into_iter()
+            .chain(data)
+            .map(move |batch| batch.and_then(|batch| batch.with_schema(batch_schema.clone()))),
+        schema,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, StringArray};
+
+    use super::*;
+
+    #[test]
+    fn test_with_dictionaries() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("tag", DataType::Utf8, false),
+            Field::new("name", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..100)),
+                Arc::new(StringArray::from_iter_values(
+                    (0..100).map(|i| ["red", "green", "blue"][i % 3]),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    (0..100).map(|i| format!("name {}", i)),
+                )),
+            ],
+        )
+        .unwrap();
+        let data = || -> Box<dyn RecordBatchReader + Send> {
+            Box::new(RecordBatchIterator::new(
+                vec![Ok(batch.clone()), Ok(batch.clone())],
+                schema.clone(),
+            ))
+        };
+
+        let encoded = with_dictionaries(data(), &HashSet::new(), Some(0.1)).unwrap();
+        let encoded_schema = encoded.schema();
+        assert!(is_dictionary_encoded(
+            encoded_schema.field_with_name("tag").unwrap()
+        ));
+        assert!(!is_dictionary_encoded(
+            encoded_schema.field_with_name("name").unwrap()
+        ));
+        let batches = encoded.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.len(), 2);
+        assert!(batches.iter().all(|b| b.schema() == encoded_schema));
+
+        let columns = HashSet::from(["name".to_string()]);
+        let encoded = with_dictionaries(data(), &columns, None).unwrap();
+        assert!(is_dictionary_encoded(
+            encoded.schema().field_with_name("name").unwrap()
+        ));
+
+        let columns = HashSet::from(["id".to_string()]);
+        assert!(matches!(
+            with_dictionaries(data(), &columns, None),
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+}
@@ -0,0 +1,389 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Previews of destructive operations
+//!
+//! A dry run reports what a delete ([`super::Table::delete_dry_run`]), an
+//! update ([`super::UpdateBuilder::dry_run`]) or an optimization
+//! ([`super::Table::optimize_dry_run`]) would do, without committing anything:
+//! the number of rows changed, the fragments rewritten and the size of the
+//! files freed, see [`DryRunReport`].  This is meant to check large maintenance
+//! operations on production tables before running them.
+//!
+//! The operation is previewed on the current version of the table, a write
+//! committed before the operation runs changes what it does.  The steps of
+//! [`OptimizeAction::All`] are all previewed on the current version too, so the
+//! preview of the compaction does not account for the rows deleted by the
+//! expiry which runs before it.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use arrow_array::{cast::AsArray, types::UInt64Type};
+use chrono::{Duration, Utc};
+use futures::TryStreamExt;
+use lance::dataset::optimize::{plan_compaction, CompactionOptions};
+use lance::io::{ObjectStore, ObjectStoreParams};
+use lance::Dataset;
+use lance_table::format::Fragment;
+use object_store::path::Path;
+
+use super::pack::{version_files, DATA_DIR, DELETIONS_DIR};
+use super::{purge_options, NativeTable, OptimizeAction, UpdateBuilder};
+use crate::error::Result;
+use crate::query::filter::Filter;
+
+const ROW_ID_COLUMN: &str = "_rowid";
+
+/// What a destructive operation would do to the table
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DryRunReport {
+    /// The number of rows deleted or updated
+    ///
+    /// For an optimization, the number of rows deleted because their time to
+    /// live expired plus the number of rows copied by the compaction.
+    pub num_rows: u64,
+    /// The ids of the fragments which are changed, in increasing order
+    ///
+    /// A delete or an update marks the rows as deleted in their fragments, a
+    /// compaction rewrites the fragments into new ones.
+    pub fragments_to_rewrite: Vec<u64>,
+    /// The size of the files which are no longer part of the table once the
+    /// operation is committed
+    ///
+    /// These are the files of the fragments all of whose rows are deleted or
+    /// updated, or of the fragments rewritten by a compaction.  They are only
+    /// removed from storage when their versions are pruned.  For a prune, this
+    /// is the size of the files it removes.  Files which are in no version,
+    /// left by failed writes, are not counted.
+    pub bytes_to_delete: u64,
+}
+
+impl DryRunReport {
+    fn merge(&mut self, other: Self) {
+        self.num_rows += other.num_rows;
+        self.bytes_to_delete += other.bytes_to_delete;
+        let fragments = self
+            .fragments_to_rewrite
+            .drain(..)
+            .chain(other.fragments_to_rewrite)
+            .collect::<BTreeSet<_>>();
+        self.fragments_to_rewrite = fragments.into_iter().collect();
+    }
+}
+
+/// The operation previewed by [`super::TableInternal::dry_run`]
+pub(crate) enum DryRunOperation {
+    Delete(String),
+    Update(Box<UpdateBuilder>),
+    Optimize(OptimizeAction),
+}
+
+/// The files stored in the directory of a table
+struct TableFiles {
+    store: ObjectStore,
+    dir: Path,
+    /// The size of each file, by its path relative to the directory
+    sizes: HashMap<String, u64>,
+}
+
+impl TableFiles {
+    async fn list(table: &NativeTable) -> Result<Self> {
+        let params = ObjectStoreParams {
+            storage_options: Some(table.storage_options.clone()),
+            ..Default::default()
+        };
+        let (store, dir) = ObjectStore::from_uri_and_params(&table.uri, &params).await?;
+        let sizes = store
+            .inner
+            .list(Some(&dir))
+            .try_collect::<Vec<_>>()
+            .await?
+            .into_iter()
+            .map(|meta| {
+                let relative = meta
+                    .location
+                    .prefix_match(&dir)
+                    .expect("listed files are under the table directory")
+                    .map(|part| part.as_ref().to_string())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                (relative, meta.size as u64)
+            })
+            .collect();
+        Ok(Self { store, dir, sizes })
+    }
+
+    fn size(&self, file: &str) -> u64 {
+        self.sizes.get(file).copied().unwrap_or(0)
+    }
+
+    /// The size of the data files and of the deletion file of `fragment`
+    fn fragment_size(&self, fragment: &Fragment) -> u64 {
+        let data = fragment
+            .files
+            .iter()
+            .map(|file| self.size(&format!("{}/{}", DATA_DIR, file.path)))
+            .sum::<u64>();
+        let deletions = fragment.deletion_file.as_ref().map_or(0, |file| {
+            // The extension of the deletion file depends on its type
+            let prefix = format!(
+                "{}/{}-{}-{}.",
+                DELETIONS_DIR, fragment.id, file.read_version, file.id
+            );
+            self.sizes
+                .iter()
+                .filter(|(path, _)| path.starts_with(&prefix))
+                .map(|(_, size)| size)
+                .sum()
+        });
+        data + deletions
+    }
+}
+
+/// What deleting the rows matching `predicate`, or all of the rows if there is
+/// no predicate, would do
+///
+/// An update deletes the rows it changes from their fragments and writes them
+/// to new fragments, so it frees the same files.
+async fn preview_delete(
+    dataset: &Dataset,
+    files: &TableFiles,
+    predicate: Option<&str>,
+) -> Result<DryRunReport> {
+    let mut scanner = dataset.scan();
+    if let Some(predicate) = predicate {
+        scanner.filter(predicate)?;
+    }
+    scanner.with_row_id();
+    scanner.project(&[dataset.schema().fields[0].name.as_str()])?;
+    // The number of matching rows of each fragment, the high half of a row id
+    // is the id of its fragment
+    let mut matches = BTreeMap::<u64, usize>::new();
+    let mut batches = scanner.try_into_stream().await?;
+    while let Some(batch) = batches.try_next().await? {
+        for row_id in batch[ROW_ID_COLUMN].as_primitive::<UInt64Type>().values() {
+            *matches.entry(row_id >> 32).or_default() += 1;
+        }
+    }
+
+    let mut report = DryRunReport {
+        num_rows: matches.values().sum::<usize>() as u64,
+        fragments_to_rewrite: matches.keys().copied().collect(),
+        bytes_to_delete: 0,
+    };
+    for fragment in dataset.get_fragments() {
+        let Some(count) = matches.get(&fragment.metadata().id) else {
+            continue;
+        };
+        // A fragment without any row left is removed from the table
+        if *count == fragment.count_rows().await? {
+            report.bytes_to_delete += files.fragment_size(fragment.metadata());
+        }
+    }
+    Ok(report)
+}
+
+/// What compacting the table with `options` would do
+async fn preview_compaction(
+    dataset: &Dataset,
+    files: &TableFiles,
+    options: &CompactionOptions,
+) -> Result<DryRunReport> {
+    let plan = plan_compaction(dataset, options).await?;
+    let rewritten = plan
+        .compaction_tasks()
+        .flat_map(|task| task.task.fragments.into_iter().map(|fragment| fragment.id))
+        .collect::<BTreeSet<_>>();
+    let mut report = DryRunReport {
+        fragments_to_rewrite: rewritten.iter().copied().collect(),
+        ..Default::default()
+    };
+    for fragment in dataset.get_fragments() {
+        if rewritten.contains(&fragment.metadata().id) {
+            report.num_rows += fragment.count_rows().await? as u64;
+            report.bytes_to_delete += files.fragment_size(fragment.metadata());
+        }
+    }
+    Ok(report)
+}
+
+impl NativeTable {
+    pub(super) async fn dry_run_impl(&self, operation: DryRunOperation) -> Result<DryRunReport> {
+        let dataset = self.dataset.get().await?.clone();
+        let files = TableFiles::list(self).await?;
+        match operation {
+            DryRunOperation::Delete(predicate) => {
+                let predicate = Filter::parse(&predicate)?.to_sql()?;
+                preview_delete(&dataset, &files, Some(&predicate)).await
+            }
+            DryRunOperation::Update(update) => {
+                let predicate = update.predicate()?;
+                preview_delete(&dataset, &files, predicate.as_deref()).await
+            }
+            DryRunOperation::Optimize(action) => {
+                let mut report = DryRunReport::default();
+                for step in action.into_steps() {
+                    report.merge(self.preview_optimize_step(&dataset, &files, step).await?);
+                }
+                Ok(report)
+            }
+        }
+    }
+
+    /// What one of the steps of [`OptimizeAction::into_steps`] would do
+    async fn preview_optimize_step(
+        &self,
+        dataset: &Dataset,
+        files: &TableFiles,
+        action: OptimizeAction,
+    ) -> Result<DryRunReport> {
+        match action {
+            OptimizeAction::All => unreachable!("OptimizeAction::All is split into its steps"),
+            OptimizeAction::Compact { options, .. } => {
+                preview_compaction(dataset, files, &options).await
+            }
+            OptimizeAction::PurgeDeletions { threshold } => {
+                preview_compaction(dataset, files, &purge_options(threshold)?).await
+            }
+            OptimizeAction::Prune { older_than, .. } => {
+                self.preview_prune(dataset, files, older_than).await
+            }
+            OptimizeAction::Expire => match self.expired_rows_filter().await? {
+                Some(filter) => {
+                    let predicate = Filter::parse(&filter)?.to_sql()?;
+                    preview_delete(dataset, files, Some(&predicate)).await
+                }
+                None => Ok(DryRunReport::default()),
+            },
+            // These only add statistics and indices
+            OptimizeAction::FragmentStats | OptimizeAction::Index(_) => Ok(DryRunReport::default()),
+        }
+    }
+
+    /// What pruning the versions older than `older_than` would do
+    ///
+    /// The files removed are the files of the pruned versions which are not in
+    /// any of the versions kept.
+    async fn preview_prune(
+        &self,
+        dataset: &Dataset,
+        files: &TableFiles,
+        older_than: Duration,
+    ) -> Result<DryRunReport> {
+        let cutoff = Utc::now() - self.protect_tagged_versions(older_than).await?;
+        let latest = dataset.version().version;
+        let mut kept = HashSet::new();
+        let mut pruned = HashSet::new();
+        for version in dataset.versions().await? {
+            let checked_out = dataset.checkout_version(version.version).await?;
+            let (_, version_files) = version_files(&checked_out, &files.store, &files.dir).await?;
+            if version.version == latest || version.timestamp >= cutoff {
+                kept.extend(version_files);
+            } else {
+                pruned.extend(version_files);
+            }
+        }
+        Ok(DryRunReport {
+            bytes_to_delete: pruned.difference(&kept).map(|file| files.size(file)).sum(),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    fn batches(start: i32) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(start..start + 10))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("t", batches(0)).execute().await.unwrap();
+        table.add(batches(10)).execute().await.unwrap();
+        let version = table.version().await.unwrap();
+
+        // The first fragment is emptied, the second one keeps 5 rows
+        let report = table.delete_dry_run("i < 15").await.unwrap();
+        assert_eq!(report.num_rows, 15);
+        assert_eq!(report.fragments_to_rewrite, vec![0, 1]);
+        assert!(report.bytes_to_delete > 0);
+        let report = table.delete_dry_run("i >= 12").await.unwrap();
+        assert_eq!(report.fragments_to_rewrite, vec![1]);
+        assert_eq!(report.bytes_to_delete, 0);
+
+        let report = table
+            .update()
+            .only_if("i >= 10")
+            .column("i", "i + 1")
+            .dry_run()
+            .await
+            .unwrap();
+        assert_eq!(report.num_rows, 10);
+        assert_eq!(report.fragments_to_rewrite, vec![1]);
+        assert!(report.bytes_to_delete > 0);
+        assert!(table.update().dry_run().await.is_err());
+
+        // The two small fragments are merged
+        let report = table
+            .optimize_dry_run(OptimizeAction::Compact {
+                options: CompactionOptions::default(),
+                remap_options: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(report.num_rows, 20);
+        assert_eq!(report.fragments_to_rewrite, vec![0, 1]);
+        assert!(report.bytes_to_delete > 0);
+
+        // Nothing was committed
+        assert_eq!(table.version().await.unwrap(), version);
+        assert_eq!(table.count_rows(None).await.unwrap(), 20);
+
+        // Pruning every version but the latest removes the deletion file of
+        // the previous version
+        table.delete("i < 2").await.unwrap();
+        table.delete("i < 4").await.unwrap();
+        let prune = || OptimizeAction::Prune {
+            older_than: Duration::zero(),
+            delete_unverified: Some(true),
+        };
+        let report = table.optimize_dry_run(prune()).await.unwrap();
+        assert_eq!(report.num_rows, 0);
+        assert!(report.fragments_to_rewrite.is_empty());
+        assert!(report.bytes_to_delete > 0);
+        table.optimize(prune()).await.unwrap();
+        let report = table.optimize_dry_run(prune()).await.unwrap();
+        assert_eq!(report.bytes_to_delete, 0);
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bulk export of the vectors in a table
+//!
+//! Training a model (or an index) outside of LanceDB usually only needs the
+//! vectors and a way to map them back to rows.  The export streams just the
+//! vector column and an id column out of the table, a batch at a time, so the
+//! whole table never needs to fit in memory.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, types::*, Array, ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Schema};
+use futures::TryStreamExt;
+use parquet::arrow::ArrowWriter;
+
+use crate::error::{Error, Result};
+use crate::query::{ExecutableQuery, Query, QueryBase, Select};
+
+const ROW_ID_COLUMN: &str = "_rowid";
+
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(source: parquet::errors::ParquetError) -> Self {
+        Self::Other {
+            message: format!("Parquet error: {}", source),
+            source: Some(Box::new(source)),
+        }
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::Runtime {
+        message: format!("Failed to write {}: {}", path.display(), e),
+    }
+}
+
+/// The file format of an export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VectorExportFormat {
+    /// A NumPy `.npy` file holding a structured array with an `id` and a `vector` field
+    ///
+    /// The file can be loaded with `numpy.load` (or memory mapped with
+    /// `mmap_mode="r"`) and the vectors read as a 2D array with `array["vector"]`.
+    /// The ids must be integers.
+    #[default]
+    Npy,
+    /// A Parquet file with the id column and the vector column
+    Parquet,
+}
+
+/// A builder used to export the vectors of a table
+///
+/// See [`super::Table::export_vectors`] for more context
+pub struct ExportVectorsBuilder {
+    query: Query,
+    column: String,
+    id_column: Option<String>,
+    format: VectorExportFormat,
+}
+
+impl ExportVectorsBuilder {
+    pub(super) fn new(query: Query, column: String) -> Self {
+        Self {
+            query,
+            column,
+            id_column: None,
+            format: VectorExportFormat::default(),
+        }
+    }
+
+    /// The format of the exported file, the default is [`VectorExportFormat::Npy`]
+    pub fn format(mut self, format: VectorExportFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// The column used to identify each vector
+    ///
+    /// By default the row id (`_rowid`) is exported.
+    pub fn id_column(mut self, column: impl Into<String>) -> Self {
+        self.id_column = Some(column.into());
+        self
+    }
+
+    /// Only export the vectors of the rows matching the filter
+    pub fn only_if(mut self, filter: impl AsRef<str>) -> Self {
+        self.query = self.query.only_if(filter);
+        self
+    }
+
+    /// Write the vectors to the file at `path`, replacing it if it exists
+    ///
+    /// Returns the number of vectors written.
+    pub async fn write(self, path: impl AsRef<Path>) -> Result<usize> {
+        let path = path.as_ref();
+        let id_column = self
+            .id_column
+            .clone()
+            .unwrap_or_else(|| ROW_ID_COLUMN.to_string());
+        let mut query = match &self.id_column {
+            Some(id_column) => self
+                .query
+                .select(Select::columns(&[id_column, &self.column])),
+            None => self.query.select(Select::columns(&[&self.column])),
+        };
+        query.with_row_id = self.id_column.is_none();
+        let mut stream = query.execute().await?;
+
+        let stream_schema = stream.schema();
+        let id_field = stream_schema.field_with_name(&id_column)?;
+        let vector_field = stream_schema.field_with_name(&self.column)?;
+        let (value_type, dim) = match vector_field.data_type() {
+            DataType::FixedSizeList(item, dim) if item.data_type().is_floating() => {
+                (item.data_type().clone(), *dim as usize)
+            }
+            data_type => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "the column '{}' is not a vector column, its type is {}",
+                        self.column, data_type
+                    ),
+                })
+            }
+        };
+        if self.format == VectorExportFormat::Npy && !id_field.data_type().is_integer() {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "Npy exports require integer ids but the id column is {}, export to Parquet instead",
+                    id_field.data_type()
+                ),
+            });
+        }
+        let schema = Arc::new(Schema::new(vec![id_field.clone(), vector_field.clone()]));
+
+        let file = File::create(path).map_err(|e| io_error(path, e))?;
+        let mut writer = match self.format {
+            VectorExportFormat::Npy => ExportWriter::Npy(
+                NpyWriter::try_new(file, id_field.data_type(), &value_type, dim)
+                    .map_err(|e| io_error(path, e))?,
+            ),
+            VectorExportFormat::Parquet => {
+                ExportWriter::Parquet(ArrowWriter::try_new(file, schema.clone(), None)?)
+            }
+        };
+
+        let mut num_rows = 0;
+        while let Some(batch) = stream.try_next().await? {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    batch[id_column.as_str()].clone(),
+                    batch[self.column.as_str()].clone(),
+                ],
+            )?;
+            num_rows += batch.num_rows();
+            writer.write(&batch, path)?;
+        }
+        writer.finish(path)?;
+        Ok(num_rows)
+    }
+}
+
+enum ExportWriter {
+    Npy(NpyWriter),
+    Parquet(ArrowWriter<File>),
+}
+
+impl ExportWriter {
+    fn write(&mut self, batch: &RecordBatch, path: &Path) -> Result<()> {
+        match self {
+            Self::Npy(writer) => {
+                let ids = npy_ids(batch.column(0))?;
+                writer
+                    .write(ids.as_ref(), batch.column(1))
+                    .map_err(|e| io_error(path, e))
+            }
+            Self::Parquet(writer) => Ok(writer.write(batch)?),
+        }
+    }
+
+    fn finish(self, path: &Path) -> Result<()> {
+        match self {
+            Self::Npy(writer) => writer.finish().map_err(|e| io_error(path, e)),
+            Self::Parquet(writer) => {
+                writer.close()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Row ids are exported as is, any other integer ids are exported as `i64`
+fn npy_ids(ids: &ArrayRef) -> Result<ArrayRef> {
+    match ids.data_type() {
+        DataType::UInt64 | DataType::Int64 => Ok(ids.clone()),
+        _ => Ok(arrow_cast::cast(ids.as_ref(), &DataType::Int64)?),
+    }
+}
+
+/// The numpy type description of an arrow type
+fn npy_descr(data_type: &DataType) -> Option<&'static str> {
+    match data_type {
+        DataType::Int64 => Some("<i8"),
+        DataType::UInt64 => Some("<u8"),
+        DataType::Float16 => Some("<f2"),
+        DataType::Float32 => Some("<f4"),
+        DataType::Float64 => Some("<f8"),
+        _ => None,
+    }
+}
+
+/// Append the values of `array` to `buf` as little endian bytes
+fn extend_le_bytes(buf: &mut Vec<u8>, array: &dyn Array) {
+    match array.data_type() {
+        DataType::Int64 => array
+            .as_primitive::<Int64Type>()
+            .values()
+            .iter()
+            .for_each(|v| buf.extend_from_slice(&v.to_le_bytes())),
+        DataType::UInt64 => array
+            .as_primitive::<UInt64Type>()
+            .values()
+            .iter()
+            .for_each(|v| buf.extend_from_slice(&v.to_le_bytes())),
+        DataType::Float16 => array
+            .as_primitive::<Float16Type>()
+            .values()
+            .iter()
+            .for_each(|v| buf.extend_from_slice(&v.to_le_bytes())),
+        DataType::Float32 => array
+            .as_primitive::<Float32Type>()
+            .values()
+            .iter()
+            .for_each(|v| buf.extend_from_slice(&v.to_le_bytes())),
+        DataType::Float64 => array
+            .as_primitive::<Float64Type>()
+            .values()
+            .iter()
+            .for_each(|v| buf.extend_from_slice(&v.to_le_bytes())),
+        data_type => unreachable!("no npy type for {}", data_type),
+    }
+}
+
+/// Writes a `.npy` file (format version 1.0) holding a 1D structured array
+///
+/// The number of rows is not known until the stream is exhausted, so the header
+/// is written with room for the largest possible row count and rewritten by
+/// [`Self::finish`].
+struct NpyWriter {
+    file: BufWriter<File>,
+    descr: String,
+    header_len: usize,
+    id_size: usize,
+    vector_size: usize,
+    num_rows: usize,
+    buf: Vec<u8>,
+}
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+
+impl NpyWriter {
+    fn try_new(
+        file: File,
+        id_type: &DataType,
+        value_type: &DataType,
+        dim: usize,
+    ) -> std::io::Result<Self> {
+        // Ids are cast per batch by `npy_ids`
+        let id_type = match id_type {
+            DataType::UInt64 => DataType::UInt64,
+            _ => DataType::Int64,
+        };
+        let value_descr = npy_descr(value_type).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("vectors of {} can not be exported to npy", value_type),
+            )
+        })?;
+        let descr = format!(
+            "[('id', '{}'), ('vector', '{}', ({},))]",
+            npy_descr(&id_type).unwrap(),
+            value_descr,
+            dim
+        );
+        let header_len = Self::header(&descr, u64::MAX as usize).len();
+        let mut writer = Self {
+            file: BufWriter::new(file),
+            descr,
+            header_len,
+            id_size: 8,
+            vector_size: value_type.primitive_width().unwrap() * dim,
+            num_rows: 0,
+            buf: Vec::new(),
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    fn header(descr: &str, num_rows: usize) -> Vec<u8> {
+        let dict = format!(
+            "{{'descr': {}, 'fortran_order': False, 'shape': ({},), }}",
+            descr, num_rows
+        );
+        // The magic string, the version, the header length, and the header
+        // (terminated by a newline) are padded to a multiple of 64 bytes
+        let unpadded = NPY_MAGIC.len() + 2 + dict.len() + 1;
+        let padding = (64 - unpadded % 64) % 64;
+        let len = (dict.len() + padding + 1) as u16;
+
+        let mut header = NPY_MAGIC.to_vec();
+        header.extend_from_slice(&len.to_le_bytes());
+        header.extend_from_slice(dict.as_bytes());
+        header.extend(std::iter::repeat(b' ').take(padding));
+        header.push(b'\n');
+        header
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let mut header = Self::header(&self.descr, self.num_rows);
+        // Pad with spaces so the header always fills the space reserved for it
+        let newline = header.pop();
+        header.resize(self.header_len - 1, b' ');
+        header.extend(newline);
+        let len = (header.len() - NPY_MAGIC.len() - 2) as u16;
+        header[NPY_MAGIC.len()..NPY_MAGIC.len() + 2].copy_from_slice(&len.to_le_bytes());
+        self.file.write_all(&header)
+    }
+
+    fn write(&mut self, ids: &dyn Array, vectors: &ArrayRef) -> std::io::Result<()> {
+        let vectors = vectors.as_fixed_size_list();
+        let mut ids_buf = Vec::with_capacity(ids.len() * self.id_size);
+        extend_le_bytes(&mut ids_buf, ids);
+        let mut vectors_buf = Vec::with_capacity(vectors.len() * self.vector_size);
+        extend_le_bytes(&mut vectors_buf, vectors.values().as_ref());
+
+        // Interleave the ids and the vectors, one record per row
+        self.buf.clear();
+        for row in 0..ids.len() {
+            self.buf
+                .extend_from_slice(&ids_buf[row * self.id_size..(row + 1) * self.id_size]);
+            self.buf.extend_from_slice(
+                &vectors_buf[row * self.vector_size..(row + 1) * self.vector_size],
+            );
+        }
+        self.file.write_all(&self.buf)?;
+        self.num_rows += ids.len();
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Vector searches restricted to the rows covered by the vector index
+//!
+//! A vector search also searches the rows added since the index was built and
+//! merges them with the results of the index.  A fast search drops the results
+//! from these rows, recognized by the fragment in their row id, after searching
+//! for as many more results as there are such rows.  With
+//! [`UnindexedPolicy::Error`] the search fails instead if there are such rows.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use arrow::compute::filter_record_batch;
+use arrow_array::{cast::AsArray, types::UInt64Type, BooleanArray, RecordBatch};
+use arrow_schema::Schema;
+use futures::TryStreamExt;
+use lance::Dataset;
+use lance_index::DatasetIndexExt;
+
+use super::NativeTable;
+use crate::arrow::{SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+use crate::query::{QueryExecutionOptions, VectorQuery, DEFAULT_TOP_K};
+
+const ROW_ID_COLUMN: &str = "_rowid";
+
+/// The ids of the fragments covered by an index on `column`
+async fn indexed_fragments(dataset: &Dataset, column: &str) -> Result<HashSet<u64>> {
+    let Some(field) = dataset.schema().field(column) else {
+        return Ok(HashSet::new());
+    };
+    let indices = dataset.load_indices().await?;
+    let bitmaps = indices
+        .iter()
+        .filter(|index| index.fields.contains(&field.id))
+        .filter_map(|index| index.fragment_bitmap.as_ref())
+        .collect::<Vec<_>>();
+    Ok(dataset
+        .get_fragments()
+        .iter()
+        .map(|fragment| fragment.id() as u64)
+        .filter(|id| bitmaps.iter().any(|bitmap| bitmap.contains(*id as u32)))
+        .collect())
+}
+
+/// The number of rows of `dataset` outside of `fragments`
+fn unindexed_rows(dataset: &Dataset, fragments: &HashSet<u64>) -> usize {
+    dataset
+        .get_fragments()
+        .iter()
+        .map(|fragment| fragment.metadata())
+        .filter(|fragment| !fragments.contains(&fragment.id))
+        .map(|fragment| fragment.physical_rows.unwrap_or_default())
+        .sum()
+}
+
+/// Drop the rows of `batch` outside of `fragments`, the rows past the first
+/// `remaining` ones, and the row id column unless `with_row_id`
+fn indexed_rows(
+    batch: RecordBatch,
+    fragments: &HashSet<u64>,
+    remaining: &mut usize,
+    with_row_id: bool,
+) -> Result<RecordBatch> {
+    let row_ids = batch[ROW_ID_COLUMN].as_primitive::<UInt64Type>();
+    let keep = row_ids
+        .values()
+        .iter()
+        .map(|row_id| Some(fragments.contains(&(row_id >> 32))))
+        .collect::<BooleanArray>();
+    let mut batch = filter_record_batch(&batch, &keep)?;
+    batch = batch.slice(0, batch.num_rows().min(*remaining));
+    *remaining -= batch.num_rows();
+    if !with_row_id {
+        let (index, _) = batch.schema().column_with_name(ROW_ID_COLUMN).unwrap();
+        batch.remove_column(index);
+    }
+    Ok(batch)
+}
+
+impl NativeTable {
+    /// Fail with [`Error::UnindexedRows`] if some rows of the searched column are
+    /// not covered by its index
+    pub(super) async fn check_indexed(&self, query: &VectorQuery) -> Result<()> {
+        let dataset = self.dataset.get().await?.clone();
+        let Some((column, _)) = self.resolve_query_vector(&dataset, query)? else {
+            return Ok(());
+        };
+        let fragments = indexed_fragments(&dataset, &column).await?;
+        let num_rows = unindexed_rows(&dataset, &fragments) as u64;
+        if num_rows > 0 {
+            return Err(Error::UnindexedRows { column, num_rows });
+        }
+        Ok(())
+    }
+
+    pub(super) async fn fast_search(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        let dataset = self.dataset.get().await?.clone();
+        let Some((column, query_vector)) = self.resolve_query_vector(&dataset, query)? else {
+            return Err(Error::InvalidInput {
+                message: "a fast search requires a query vector".to_string(),
+            });
+        };
+        let fragments = indexed_fragments(&dataset, &column).await?;
+
+        // The query is already embedded, if needed.  The results from the
+        // unindexed rows are dropped, so as many more results are searched for
+        let limit = query.base.limit.unwrap_or(DEFAULT_TOP_K);
+        let mut search = query.clone();
+        search.column = Some(column);
+        search.query_vector = Some(query_vector);
+        search.base.with_row_id = true;
+        search.base.limit = Some(limit + unindexed_rows(&dataset, &fragments));
+        let stream: SendableRecordBatchStream = self.generic_query(&search, options).await?.into();
+
+        let with_row_id = query.base.with_row_id;
+        let mut remaining = limit;
+        let mut schema = stream.schema();
+        if !with_row_id {
+            let fields = schema
+                .fields()
+                .iter()
+                .filter(|field| field.name() != ROW_ID_COLUMN)
+                .cloned()
+                .collect::<Vec<_>>();
+            schema = Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()));
+        }
+        Ok(Box::pin(SimpleRecordBatchStream {
+            schema,
+            stream: stream.and_then(move |batch| {
+                std::future::ready(indexed_rows(batch, &fragments, &mut remaining, with_row_id))
+            }),
+        }))
+    }
+}
@@ -0,0 +1,482 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Flat searches with the distance types lance does not support
+//!
+//! These are the hamming distance, over binary vectors, and MaxSim, over
+//! multivector columns.  The rows are scanned (with the query's filter) and the
+//! closest `limit` rows are kept as the scan goes.
+//!
+//! The distance range of a vector query is also applied here, for flat searches
+//! while scanning and for other searches to their results.
+
+use std::sync::Arc;
+
+use arrow::compute::{concat_batches, filter_record_batch, sort_to_indices, take};
+use arrow_array::{
+    cast::AsArray, types::Float32Type, types::UInt8Type, Array, ArrayRef, BooleanArray,
+    Float32Array, RecordBatch,
+};
+use arrow_ord::sort::SortOptions;
+use arrow_schema::{DataType, Field, Schema};
+use futures::{stream, TryStreamExt};
+use lance_linalg::distance::dot;
+
+use super::NativeTable;
+use crate::arrow::{SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+use crate::query::{QueryExecutionOptions, Select, VectorQuery, DEFAULT_TOP_K};
+use crate::DistanceType;
+
+const DISTANCE_COLUMN: &str = "_distance";
+
+/// A query vector prepared for a flat search
+enum FlatQuery {
+    /// Packed bytes, compared with the hamming distance
+    Hamming(Vec<u8>),
+    /// One or more query vectors (flattened), compared with MaxSim
+    MaxSim { vectors: Vec<f32>, dim: usize },
+}
+
+/// The number of bits that differ between two packed binary vectors
+fn hamming_distance(a: &[u8], b: &[u8]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a ^ b).count_ones())
+        .sum::<u32>() as f32
+}
+
+/// The MaxSim distance between the query vectors and the vectors of a row
+///
+/// Each query vector is matched with its closest vector of the row and the dot
+/// distances of the matches are summed.  This is the number of query vectors
+/// minus the late interaction (ColBERT) score.
+fn max_sim_distance<'a>(
+    query: &[f32],
+    dim: usize,
+    vectors: impl Iterator<Item = &'a [f32]> + Clone,
+) -> Option<f32> {
+    query
+        .chunks_exact(dim)
+        .map(|q| {
+            vectors
+                .clone()
+                .map(|v| 1.0 - dot(q, v))
+                .min_by(|a, b| a.total_cmp(b))
+        })
+        .sum()
+}
+
+/// Convert the query vector back to the packed bytes it was created from
+fn query_bytes(query_vector: &dyn Array) -> Result<Vec<u8>> {
+    let invalid = || Error::InvalidInput {
+        message: "the query vector of a hamming distance search must contain bytes".to_string(),
+    };
+    if !query_vector.data_type().is_floating() || query_vector.null_count() > 0 {
+        return Err(invalid());
+    }
+    let values = arrow_cast::cast(query_vector, &DataType::Float32)?;
+    values
+        .as_primitive::<Float32Type>()
+        .values()
+        .iter()
+        .map(|value| {
+            if value.fract() == 0.0 && (0.0..=255.0).contains(value) {
+                Ok(*value as u8)
+            } else {
+                Err(invalid())
+            }
+        })
+        .collect()
+}
+
+fn dimension_mismatch(column: &str, query_dim: usize, dim: i32) -> Error {
+    Error::InvalidInput {
+        message: format!(
+            "The dimension of the query vector does not match with the dimension of the vector column '{}':
+                query dim={}, expected vector dim={}",
+            column, query_dim, dim,
+        ),
+    }
+}
+
+impl FlatQuery {
+    /// Prepare a hamming distance search, returns the vector column and the query
+    fn hamming(query: &VectorQuery, schema: &Schema) -> Result<(String, Self)> {
+        let column = query.column.clone().ok_or_else(|| Error::InvalidInput {
+            message: "the vector column must be specified for a hamming distance search"
+                .to_string(),
+        })?;
+        let query_bytes = query_bytes(query.query_vector.as_ref().unwrap().as_ref())?;
+        match schema.field_with_name(&column)?.data_type() {
+            DataType::FixedSizeList(item, dim) if item.data_type() == &DataType::UInt8 => {
+                if *dim as usize != query_bytes.len() {
+                    return Err(dimension_mismatch(&column, query_bytes.len(), *dim));
+                }
+            }
+            data_type => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "the hamming distance requires a binary vector column (a fixed size list of uint8) but the column '{}' has data type {}",
+                        column, data_type
+                    ),
+                })
+            }
+        }
+        Ok((column, Self::Hamming(query_bytes)))
+    }
+
+    /// Prepare a MaxSim search, returns the vector column and the query
+    fn max_sim(query: &VectorQuery, schema: &Schema) -> Result<(String, Self)> {
+        let query_vector = query.query_vector.as_ref().unwrap();
+        let (values, dim) = match query_vector.data_type() {
+            DataType::FixedSizeList(_, dim) => (
+                query_vector.as_fixed_size_list().values().clone(),
+                *dim as usize,
+            ),
+            data_type if data_type.is_floating() => (query_vector.clone(), query_vector.len()),
+            data_type => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "the query vectors of a MaxSim search must be floats but were {}",
+                        data_type
+                    ),
+                })
+            }
+        };
+        let values = arrow_cast::cast(&values, &DataType::Float32)?;
+        if values.null_count() > 0 || dim == 0 {
+            return Err(Error::InvalidInput {
+                message: "the query vectors of a MaxSim search cannot be empty or contain nulls"
+                    .to_string(),
+            });
+        }
+
+        let multivector_dim = |data_type: &DataType| match data_type {
+            DataType::List(item) => match item.data_type() {
+                DataType::FixedSizeList(item, dim) if item.data_type().is_floating() => Some(*dim),
+                _ => None,
+            },
+            _ => None,
+        };
+        let column = match &query.column {
+            Some(column) => {
+                let data_type = schema.field_with_name(column)?.data_type();
+                match multivector_dim(data_type) {
+                    Some(column_dim) if column_dim as usize == dim => column.clone(),
+                    Some(column_dim) => return Err(dimension_mismatch(column, dim, column_dim)),
+                    None => {
+                        return Err(Error::InvalidInput {
+                            message: format!(
+                                "MaxSim requires a multivector column (a list of fixed size lists of floats) but the column '{}' has data type {}",
+                                column, data_type
+                            ),
+                        })
+                    }
+                }
+            }
+            None => {
+                // Infer a multivector column with the same dimension as the query vectors
+                let candidates = schema
+                    .fields()
+                    .iter()
+                    .filter(|field| multivector_dim(field.data_type()) == Some(dim as i32))
+                    .map(|field| field.name().clone())
+                    .collect::<Vec<_>>();
+                match candidates.as_slice() {
+                    [column] => column.clone(),
+                    [] => {
+                        return Err(Error::Schema {
+                            message: format!("No multivector column found with dimension {}", dim),
+                        })
+                    }
+                    _ => {
+                        return Err(Error::Schema {
+                            message: format!(
+                                "More than one multivector columns found, \
+                                    please specify which column to search: {:?}",
+                                candidates
+                            ),
+                        })
+                    }
+                }
+            }
+        };
+        let vectors = values.as_primitive::<Float32Type>().values().to_vec();
+        Ok((column, Self::MaxSim { vectors, dim }))
+    }
+
+    /// The distance of every row, null if the row has no vectors
+    fn distances(&self, column: &dyn Array) -> Result<Float32Array> {
+        match self {
+            Self::Hamming(query) => {
+                let vectors = column.as_fixed_size_list();
+                let values = vectors.values().as_primitive::<UInt8Type>().values();
+                Ok((0..vectors.len())
+                    .map(|row| {
+                        let start = vectors.value_offset(row) as usize;
+                        (!vectors.is_null(row))
+                            .then(|| hamming_distance(&values[start..start + query.len()], query))
+                    })
+                    .collect())
+            }
+            Self::MaxSim {
+                vectors: query,
+                dim,
+            } => {
+                let lists = column.as_list::<i32>();
+                let vectors = lists.values().as_fixed_size_list();
+                let values = arrow_cast::cast(vectors.values(), &DataType::Float32)?;
+                let values = values.as_primitive::<Float32Type>().values();
+                Ok((0..lists.len())
+                    .map(|row| {
+                        if lists.is_null(row) {
+                            return None;
+                        }
+                        let offsets = lists.value_offsets();
+                        let rows = (offsets[row] as usize..offsets[row + 1] as usize)
+                            .filter(|idx| !vectors.is_null(*idx))
+                            .map(|idx| {
+                                let start = vectors.value_offset(idx) as usize;
+                                &values[start..start + dim]
+                            });
+                        max_sim_distance(query, *dim, rows)
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+/// Which of `distances` are in the distance range of `query`, null distances are not
+fn in_distance_range(distances: &Float32Array, query: &VectorQuery) -> BooleanArray {
+    distances
+        .iter()
+        .map(|distance| {
+            distance.map(|distance| {
+                query.lower_bound.map_or(true, |lower| distance >= lower)
+                    && query.upper_bound.map_or(true, |upper| distance < upper)
+            })
+        })
+        .collect()
+}
+
+/// Drop the rows of `batch` whose `_distance` is outside of the range of `query`
+fn filter_distance_range(batch: RecordBatch, query: &VectorQuery) -> Result<RecordBatch> {
+    let keep = in_distance_range(batch[DISTANCE_COLUMN].as_primitive::<Float32Type>(), query);
+    Ok(filter_record_batch(&batch, &keep)?)
+}
+
+/// Add the `_distance` column to a batch of the scan, rows without a distance or
+/// outside of the distance range are dropped
+fn with_distances(
+    batch: RecordBatch,
+    column: &str,
+    flat_query: &FlatQuery,
+    query: &VectorQuery,
+) -> Result<RecordBatch> {
+    let distances = flat_query.distances(batch[column].as_ref())?;
+    let mut fields = batch.schema().fields().to_vec();
+    fields.push(Arc::new(Field::new(
+        DISTANCE_COLUMN,
+        DataType::Float32,
+        true,
+    )));
+    let mut columns = batch.columns().to_vec();
+    let keep = in_distance_range(&distances, query);
+    columns.push(Arc::new(distances));
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+    Ok(filter_record_batch(&batch, &keep)?)
+}
+
+/// The `limit` rows of `batch` with the smallest distance, in order of distance
+fn closest(batch: &RecordBatch, limit: usize) -> Result<RecordBatch> {
+    let indices = sort_to_indices(
+        &batch[DISTANCE_COLUMN],
+        Some(SortOptions {
+            descending: false,
+            nulls_first: false,
+        }),
+        Some(limit),
+    )?;
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| take(column, &indices, None))
+        .collect::<std::result::Result<Vec<ArrayRef>, _>>()?;
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
+
+impl NativeTable {
+    pub(super) fn validate_distance_range(query: &VectorQuery) -> Result<()> {
+        if query.lower_bound.is_none() && query.upper_bound.is_none() {
+            return Ok(());
+        }
+        if query.query_vector.is_none() {
+            return Err(Error::InvalidInput {
+                message: "a distance range requires a query vector".to_string(),
+            });
+        }
+        if let (Some(lower), Some(upper)) = (query.lower_bound, query.upper_bound) {
+            if lower > upper {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "the lower bound of the distance range ({}) is greater than its upper bound ({})",
+                        lower, upper
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop the results of `stream` outside of the distance range of `query`
+    pub(super) fn with_distance_range(
+        stream: SendableRecordBatchStream,
+        query: &VectorQuery,
+    ) -> SendableRecordBatchStream {
+        if query.lower_bound.is_none() && query.upper_bound.is_none() {
+            return stream;
+        }
+        let query = query.clone();
+        let schema = stream.schema();
+        Box::pin(SimpleRecordBatchStream {
+            schema,
+            stream: stream
+                .and_then(move |batch| std::future::ready(filter_distance_range(batch, &query))),
+        })
+    }
+
+    /// Whether a vector query must be run with [`Self::flat_query`]
+    pub(super) fn is_flat_query(query: &VectorQuery) -> bool {
+        matches!(
+            query.distance_type,
+            Some(DistanceType::Hamming | DistanceType::MaxSim)
+        ) || matches!(
+            query.query_vector.as_ref().map(|v| v.data_type()),
+            Some(DataType::FixedSizeList(..))
+        )
+    }
+
+    pub(super) async fn flat_query(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        if query.query_vector.is_none() {
+            return Err(Error::InvalidInput {
+                message: "a flat search requires a query vector".to_string(),
+            });
+        }
+        let schema = Schema::from(self.dataset.get().await?.schema());
+        let (column, flat_query) = match query.distance_type {
+            Some(DistanceType::Hamming) => FlatQuery::hamming(query, &schema)?,
+            Some(DistanceType::MaxSim) | None => FlatQuery::max_sim(query, &schema)?,
+            Some(distance_type) => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                    "multiple query vectors can only be searched with the MaxSim distance, not {}",
+                    distance_type
+                ),
+                })
+            }
+        };
+
+        // The vector column is needed to compute the distances even if it is not selected
+        let mut scan = query.clone();
+        scan.query_vector = None;
+        scan.distance_type = None;
+        scan.base.limit = None;
+        let projected = match &mut scan.base.select {
+            Select::All => true,
+            Select::Columns(columns) => {
+                let projected = columns.contains(&column);
+                if !projected {
+                    columns.push(column.clone());
+                }
+                projected
+            }
+            Select::Dynamic(columns) => {
+                let projected = columns.iter().any(|(name, _)| name == &column);
+                if !projected {
+                    columns.push((column.clone(), column.clone()));
+                }
+                projected
+            }
+        };
+
+        let limit = query.base.limit.unwrap_or(DEFAULT_TOP_K);
+        let mut batches: SendableRecordBatchStream =
+            self.generic_query(&scan, options.clone()).await?.into();
+        let mut results: Option<RecordBatch> = None;
+        while let Some(batch) = batches.try_next().await? {
+            let batch = with_distances(batch, &column, &flat_query, query)?;
+            let batch = match results.take() {
+                Some(results) => concat_batches(&batch.schema(), &[results, batch])?,
+                None => batch,
+            };
+            results = Some(closest(&batch, limit)?);
+        }
+
+        let mut results = match results {
+            Some(results) => results,
+            None => {
+                let mut fields = batches.schema().fields().to_vec();
+                fields.push(Arc::new(Field::new(
+                    DISTANCE_COLUMN,
+                    DataType::Float32,
+                    true,
+                )));
+                RecordBatch::new_empty(Arc::new(Schema::new(fields)))
+            }
+        };
+        if !projected {
+            if let Some((idx, _)) = results.schema().column_with_name(&column) {
+                results.remove_column(idx);
+            }
+        }
+
+        let batch_size = (options.max_batch_length as usize).max(1);
+        let batches = (0..results.num_rows())
+            .step_by(batch_size)
+            .map(|offset| Ok(results.slice(offset, batch_size.min(results.num_rows() - offset))))
+            .collect::<Vec<_>>();
+        Ok(Box::pin(SimpleRecordBatchStream {
+            schema: results.schema(),
+            stream: stream::iter(batches),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hamming_distance() {
+        assert_eq!(hamming_distance(&[0b1010, 0xff], &[0b1010, 0xff]), 0.0);
+        assert_eq!(hamming_distance(&[0b1010, 0x00], &[0b0101, 0xff]), 12.0);
+    }
+
+    #[test]
+    fn test_max_sim_distance() {
+        let row: [&[f32]; 2] = [&[1.0, 0.0], &[0.0, 1.0]];
+        // Each query vector matches one of the row's vectors exactly
+        let distance = max_sim_distance(&[1.0, 0.0, 0.0, 1.0], 2, row.iter().copied());
+        assert_eq!(distance, Some(0.0));
+        let distance = max_sim_distance(&[1.0, 0.0, 0.5, 0.5], 2, row.iter().copied());
+        assert_eq!(distance, Some(0.5));
+        assert_eq!(max_sim_distance(&[1.0, 0.0], 2, [].iter().copied()), None);
+    }
+}
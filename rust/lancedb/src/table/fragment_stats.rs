@@ -0,0 +1,560 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Statistics of the fragments of a table, used to skip fragments in scans
+//!
+//! A table is stored as fragments, each holding a set of its rows.  The
+//! smallest and largest values of the timestamp and date columns of each
+//! fragment are collected by [`super::OptimizeAction::FragmentStats`] (part of
+//! [`super::OptimizeAction::All`]) and stored in a hidden dataset in the
+//! table's directory.
+//!
+//! A scan whose filter restricts one of these columns to a range of time, e.g.
+//! `created_at > now() - INTERVAL '7 days'` or
+//! [`crate::query::QueryBase::only_within`], then only reads the fragments
+//! whose values overlap the range.  Tables which are appended to over time
+//! keep their fragments in time order, so a query on the last week does not
+//! read years of data.  The fragments without statistics, such as those added
+//! since the last optimize, are always read.  Vector searches are not pruned,
+//! their filters are applied with the scalar indices of the columns.
+//!
+//! The values of a fragment never change, deleting rows can only narrow their
+//! range.  So the statistics of a fragment remain valid until a compaction
+//! replaces it, except when an older version of the table, with fewer deleted
+//! rows, is read or restored, which is detected with the version of the
+//! deletions the statistics were collected with.  The statistics are stored by
+//! the data file of the fragment rather than its id, as the ids of the
+//! fragments start from 0 again after the table is overwritten.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::compute::kernels::aggregate;
+use arrow_array::{
+    cast::AsArray, types::Int64Type, Int32Array, Int64Array, RecordBatch, RecordBatchIterator,
+    StringArray, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use chrono::{Duration, NaiveDate, NaiveDateTime, TimeZone};
+use futures::TryStreamExt;
+use lance::dataset::fragment::FileFragment;
+use lance::dataset::WriteMode;
+use lance::Dataset;
+use lance_table::format::Fragment;
+
+use super::partition::data_file;
+use super::NativeTable;
+use crate::error::Result;
+use crate::query::filter::{Filter, TimeBounds};
+
+const FRAGMENT_STATS_DIR: &str = "_fragment_stats";
+
+/// The statistics collected by [`super::OptimizeAction::FragmentStats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FragmentStatsMetrics {
+    /// The number of fragments which were scanned to collect their statistics
+    pub fragments_scanned: usize,
+    /// The number of fragments whose statistics were removed, as they are no
+    /// longer part of the table
+    pub fragments_removed: usize,
+}
+
+/// The range of the values of a column in a fragment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ValueRange {
+    /// The version of the deletions of the fragment when it was scanned
+    deletion_version: u64,
+    /// The underlying integer values, `min` is greater than `max` if all of
+    /// the values are null
+    min: i64,
+    max: i64,
+}
+
+/// The statistics of the fragments, by the data file of the fragment and field
+/// id
+///
+/// Field ids are used rather than names, a column which is dropped and added
+/// back is a new field.
+type FragmentStats = HashMap<(String, i32), ValueRange>;
+
+fn fragment_stats_schema() -> Arc<Schema> {
+    Arc::new(Schema::new(vec![
+        Field::new("data_file", DataType::Utf8, false),
+        Field::new("field_id", DataType::Int32, false),
+        Field::new("deletion_version", DataType::UInt64, false),
+        Field::new("min", DataType::Int64, false),
+        Field::new("max", DataType::Int64, false),
+    ]))
+}
+
+fn deletion_version(fragment: &Fragment) -> u64 {
+    fragment
+        .deletion_file
+        .as_ref()
+        .map_or(0, |file| file.read_version)
+}
+
+fn is_temporal(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Timestamp(_, _) | DataType::Date32 | DataType::Date64
+    )
+}
+
+/// The underlying integer value of `time` in a column of type `data_type`
+///
+/// Times are the wall clock time of the column, as in filters.  Returns None
+/// if the time cannot be represented, which is always a valid bound.
+fn column_value(time: NaiveDateTime, data_type: &DataType, earliest: bool) -> Option<i64> {
+    match data_type {
+        DataType::Timestamp(unit, tz) => {
+            let utc = match tz.as_deref() {
+                None | Some("UTC") => time.and_utc(),
+                Some(tz) => {
+                    let tz = tz.parse::<arrow_array::timezone::Tz>().ok()?;
+                    let local = tz.from_local_datetime(&time);
+                    let local = if earliest {
+                        local.earliest()
+                    } else {
+                        local.latest()
+                    };
+                    local?.naive_utc().and_utc()
+                }
+            };
+            match unit {
+                TimeUnit::Second => Some(utc.timestamp()),
+                TimeUnit::Millisecond => Some(utc.timestamp_millis()),
+                TimeUnit::Microsecond => Some(utc.timestamp_micros()),
+                TimeUnit::Nanosecond => utc.timestamp_nanos_opt(),
+            }
+        }
+        DataType::Date32 => {
+            let epoch = NaiveDate::from_ymd_opt(1970, 1, 1)?;
+            Some((time.date() - epoch).num_days())
+        }
+        DataType::Date64 => Some(time.and_utc().timestamp_millis()),
+        _ => None,
+    }
+}
+
+/// Whether the values of a fragment may be within `bounds`
+fn overlaps(range: &ValueRange, bounds: &TimeBounds, data_type: &DataType) -> bool {
+    let lower = bounds
+        .lower
+        .and_then(|lower| column_value(lower, data_type, true));
+    let upper = bounds
+        .upper
+        .and_then(|upper| column_value(upper, data_type, false));
+    // The times are rounded down, which can only keep more fragments
+    lower.map_or(true, |lower| range.max >= lower)
+        && upper.map_or(true, |upper| range.min <= upper)
+        && range.min <= range.max
+}
+
+/// The ranges of the values of the columns of `fragment`
+async fn scan_fragment(
+    dataset: &Dataset,
+    fragment: &FileFragment,
+    columns: &[(&str, i32)],
+) -> Result<Vec<(i32, ValueRange)>> {
+    let mut scanner = dataset.scan();
+    scanner.with_fragments(vec![fragment.metadata().clone()]);
+    scanner.project(&columns.iter().map(|(name, _)| *name).collect::<Vec<_>>())?;
+    let mut ranges = columns
+        .iter()
+        .map(|(_, field_id)| {
+            (
+                *field_id,
+                ValueRange {
+                    deletion_version: deletion_version(fragment.metadata()),
+                    min: i64::MAX,
+                    max: i64::MIN,
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut stream = scanner.try_into_stream().await?;
+    while let Some(batch) = stream.try_next().await? {
+        for ((name, _), (_, range)) in columns.iter().zip(ranges.iter_mut()) {
+            let values = arrow_cast::cast(&batch[*name], &DataType::Int64)?;
+            let values = values.as_primitive::<Int64Type>();
+            if let Some(min) = aggregate::min(values) {
+                range.min = range.min.min(min);
+            }
+            if let Some(max) = aggregate::max(values) {
+                range.max = range.max.max(max);
+            }
+        }
+    }
+    Ok(ranges)
+}
+
+impl NativeTable {
+    fn fragment_stats_uri(&self) -> String {
+        format!("{}/{}", self.uri.trim_end_matches('/'), FRAGMENT_STATS_DIR)
+    }
+
+    /// The stored statistics, None if they were never collected
+    async fn load_fragment_stats(&self) -> Result<Option<FragmentStats>> {
+        let Some(dataset) = self.open_sidecar(&self.fragment_stats_uri()).await? else {
+            return Ok(None);
+        };
+        let batches = dataset
+            .scan()
+            .try_into_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        let mut stats = HashMap::new();
+        for batch in batches {
+            // Statistics stored by fragment id are collected again
+            let Some(data_files) = batch.column_by_name("data_file") else {
+                break;
+            };
+            let data_files = data_files.as_string::<i32>();
+            let field_ids = batch["field_id"].as_primitive::<arrow_array::types::Int32Type>();
+            let deletion_versions =
+                batch["deletion_version"].as_primitive::<arrow_array::types::UInt64Type>();
+            let mins = batch["min"].as_primitive::<Int64Type>();
+            let maxs = batch["max"].as_primitive::<Int64Type>();
+            for row in 0..batch.num_rows() {
+                stats.insert(
+                    (data_files.value(row).to_string(), field_ids.value(row)),
+                    ValueRange {
+                        deletion_version: deletion_versions.value(row),
+                        min: mins.value(row),
+                        max: maxs.value(row),
+                    },
+                );
+            }
+        }
+        Ok(Some(stats))
+    }
+
+    async fn save_fragment_stats(&self, stats: &FragmentStats, exists: bool) -> Result<()> {
+        let schema = fragment_stats_schema();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from_iter_values(
+                    stats.keys().map(|(data_file, _)| data_file.as_str()),
+                )),
+                Arc::new(Int32Array::from_iter_values(
+                    stats.keys().map(|(_, field_id)| *field_id),
+                )),
+                Arc::new(UInt64Array::from_iter_values(
+                    stats.values().map(|range| range.deletion_version),
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    stats.values().map(|range| range.min),
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    stats.values().map(|range| range.max),
+                )),
+            ],
+        )?;
+        let mode = if exists {
+            WriteMode::Overwrite
+        } else {
+            WriteMode::Create
+        };
+        let dataset = Dataset::write(
+            RecordBatchIterator::new(vec![Ok(batch)], schema),
+            &self.fragment_stats_uri(),
+            Some(self.sidecar_write_params(mode)?),
+        )
+        .await?;
+        // Only the latest version of the statistics is ever read
+        dataset
+            .cleanup_old_versions(Duration::zero(), Some(true))
+            .await?;
+        Ok(())
+    }
+
+    /// Collect the statistics of the fragments which have none, and remove
+    /// those of the fragments no longer in the table
+    pub(super) async fn collect_fragment_stats(&self) -> Result<FragmentStatsMetrics> {
+        let dataset = self.dataset.get().await?.clone();
+        let columns = dataset
+            .schema()
+            .fields
+            .iter()
+            .filter(|field| is_temporal(&field.data_type()))
+            .map(|field| (field.name.as_str(), field.id))
+            .collect::<Vec<_>>();
+        let stored = self.load_fragment_stats().await?;
+        let exists = stored.is_some();
+        let mut stats = stored.unwrap_or_default();
+        let mut metrics = FragmentStatsMetrics::default();
+
+        let fragments = dataset.get_fragments();
+        let mut removed = stats
+            .keys()
+            .map(|(file, _)| file.clone())
+            .filter(|file| {
+                !fragments
+                    .iter()
+                    .any(|fragment| data_file(fragment.metadata()) == Some(file.as_str()))
+            })
+            .collect::<Vec<_>>();
+        removed.sort_unstable();
+        removed.dedup();
+        metrics.fragments_removed = removed.len();
+        stats.retain(|(file, field_id), _| {
+            !removed.contains(file) && columns.iter().any(|(_, id)| id == field_id)
+        });
+
+        for fragment in &fragments {
+            let Some(file) = data_file(fragment.metadata()) else {
+                continue;
+            };
+            let version = deletion_version(fragment.metadata());
+            // Statistics collected before rows were deleted are still valid
+            let missing = columns
+                .iter()
+                .filter(|(_, field_id)| {
+                    stats
+                        .get(&(file.to_string(), *field_id))
+                        .map_or(true, |range| range.deletion_version > version)
+                })
+                .copied()
+                .collect::<Vec<_>>();
+            if missing.is_empty() {
+                continue;
+            }
+            for (field_id, range) in scan_fragment(&dataset, fragment, &missing).await? {
+                stats.insert((file.to_string(), field_id), range);
+            }
+            metrics.fragments_scanned += 1;
+        }
+        if metrics != FragmentStatsMetrics::default() || !exists {
+            self.save_fragment_stats(&stats, exists).await?;
+        }
+        Ok(metrics)
+    }
+
+    /// The fragments of `dataset` which may hold rows matching `filter`, None
+    /// if no fragment can be skipped
+    pub(crate) async fn pruned_fragments(
+        &self,
+        dataset: &Dataset,
+        filter: &str,
+    ) -> Result<Option<Vec<Fragment>>> {
+        // Filters the grammar doesn't cover are not pruned
+        let Ok(filter) = Filter::parse(filter) else {
+            return Ok(None);
+        };
+        let schema = Schema::from(dataset.schema());
+        let bounds = filter
+            .time_bounds()
+            .into_iter()
+            .filter_map(|(column, bounds)| {
+                let field = dataset.schema().field(&column)?;
+                let data_type = schema.field_with_name(&column).ok()?.data_type().clone();
+                is_temporal(&data_type).then_some((field.id, data_type, bounds))
+            })
+            .collect::<Vec<_>>();
+        if bounds.is_empty() {
+            return Ok(None);
+        }
+        let Some(stats) = self.load_fragment_stats().await? else {
+            return Ok(None);
+        };
+        let fragments = dataset.get_fragments();
+        let num_fragments = fragments.len();
+        let mut kept = fragments
+            .into_iter()
+            .map(|fragment| fragment.metadata().clone())
+            .filter(|fragment| {
+                let Some(file) = data_file(fragment) else {
+                    return true;
+                };
+                bounds.iter().all(|(field_id, data_type, bounds)| {
+                    match stats.get(&(file.to_string(), *field_id)) {
+                        Some(range) if range.deletion_version <= deletion_version(fragment) => {
+                            overlaps(range, bounds, data_type)
+                        }
+                        _ => true,
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        if kept.len() == num_fragments {
+            return Ok(None);
+        }
+        if kept.is_empty() {
+            // An empty list of fragments would scan all of them, one is kept
+            // and the filter excludes its rows
+            kept.extend(
+                dataset
+                    .get_fragments()
+                    .first()
+                    .map(|f| f.metadata().clone()),
+            );
+        }
+        Ok(Some(kept))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::TimestampMicrosecondArray;
+    use chrono::{DateTime, Utc};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase, TimeRange};
+    use crate::table::{AddDataMode, OptimizeAction};
+
+    #[tokio::test]
+    async fn test_fragment_pruning() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "created_at",
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            true,
+        )]));
+        let start = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        // A fragment of 10 rows for each of the 5 days after `start`
+        let batch = |day: i64| {
+            let times = (0..10).map(|hour| {
+                (start + Duration::try_days(day).unwrap() + Duration::try_hours(hour).unwrap())
+                    .timestamp_micros()
+            });
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(
+                    TimestampMicrosecondArray::from_iter_values(times).with_timezone("UTC"),
+                )],
+            )
+            .unwrap()
+        };
+        let table = db
+            .create_table(
+                "events",
+                RecordBatchIterator::new(vec![Ok(batch(0))], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+        for day in 1..5 {
+            table
+                .add(RecordBatchIterator::new(
+                    vec![Ok(batch(day))],
+                    schema.clone(),
+                ))
+                .execute()
+                .await
+                .unwrap();
+        }
+        let native = table.as_native().unwrap();
+        let dataset = native.dataset.get().await.unwrap().clone();
+        let filter = "created_at >= TIMESTAMP '2024-01-04 00:00:00'";
+        // Nothing is pruned before the statistics are collected
+        assert!(native
+            .pruned_fragments(&dataset, filter)
+            .await
+            .unwrap()
+            .is_none());
+
+        let stats = table.optimize(OptimizeAction::FragmentStats).await.unwrap();
+        assert_eq!(
+            stats.fragment_stats,
+            Some(FragmentStatsMetrics {
+                fragments_scanned: 5,
+                fragments_removed: 0,
+            })
+        );
+        let fragments = native
+            .pruned_fragments(&dataset, filter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            fragments.iter().map(|f| f.id).collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+        assert_eq!(
+            table.count_rows(Some(filter.to_string())).await.unwrap(),
+            20
+        );
+        let now = start + Duration::try_days(5).unwrap();
+        let recent = format!(
+            "created_at > TIMESTAMP '{}' - INTERVAL '36 hours'",
+            now.format("%Y-%m-%d %H:%M:%S")
+        );
+        assert_eq!(table.count_rows(Some(recent)).await.unwrap(), 10);
+        let rows = table
+            .query()
+            .only_within(
+                "created_at",
+                TimeRange::new(start, start + Duration::try_hours(5).unwrap()),
+            )
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(rows.iter().map(|b| b.num_rows()).sum::<usize>(), 5);
+        assert_eq!(
+            table
+                .count_rows(Some("created_at < DATE '2023-01-01'".to_string()))
+                .await
+                .unwrap(),
+            0
+        );
+
+        // Collecting again only scans the new fragments
+        table
+            .add(RecordBatchIterator::new(vec![Ok(batch(5))], schema.clone()))
+            .execute()
+            .await
+            .unwrap();
+        let stats = table.optimize(OptimizeAction::FragmentStats).await.unwrap();
+        assert_eq!(stats.fragment_stats.unwrap().fragments_scanned, 1);
+
+        // The fragment of the overwritten table reuses the id 0, whose
+        // statistics are those of the first day
+        table
+            .add(RecordBatchIterator::new(vec![Ok(batch(4))], schema.clone()))
+            .mode(AddDataMode::Overwrite)
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(
+            table.count_rows(Some(filter.to_string())).await.unwrap(),
+            10
+        );
+        let stats = table.optimize(OptimizeAction::FragmentStats).await.unwrap();
+        assert_eq!(
+            stats.fragment_stats,
+            Some(FragmentStatsMetrics {
+                fragments_scanned: 1,
+                fragments_removed: 6,
+            })
+        );
+        assert_eq!(
+            table.count_rows(Some(filter.to_string())).await.unwrap(),
+            10
+        );
+    }
+}
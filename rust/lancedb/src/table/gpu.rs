@@ -0,0 +1,388 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Vector searches on a GPU
+//!
+//! With [`super::Table::gpu_search`] the vectors of a column are kept in the
+//! memory of a CUDA device, quantized to half precision, and the distances of
+//! the vector searches of that column are computed there.  Only the vectors are
+//! kept on the device, the closest rows are read from the table.
+//!
+//! The vectors are those of the version of the table when they were loaded.
+//! They are loaded again by the first search after the table changes.
+//!
+//! Searches that the GPU cannot answer are run on the CPU as usual.  These are
+//! searches with a filter or hints, of another column, with a distance type
+//! other than L2, cosine, or dot, that must be exact (see
+//! [`crate::query::VectorQuery::bypass_vector_index`]), and searches that fail
+//! on the device.
+
+use arrow_schema::DataType;
+
+use super::NativeTable;
+use crate::error::{Error, Result};
+use crate::index::vector::Accelerator;
+
+#[cfg(feature = "cuda")]
+use {
+    crate::arrow::{SendableRecordBatchStream, SimpleRecordBatchStream},
+    crate::index::fts::ROW_ID_COLUMN,
+    crate::query::{QueryExecutionOptions, Select, UnindexedPolicy, VectorQuery, DEFAULT_TOP_K},
+    crate::DistanceType,
+    arrow_array::{
+        cast::AsArray,
+        types::{Float32Type, UInt64Type},
+        Array, Float32Array, RecordBatch, UInt64Array,
+    },
+    arrow_schema::{Field, Schema},
+    candle_core::{DType, Device, Tensor},
+    futures::TryStreamExt,
+    lance::Dataset,
+    std::sync::Arc,
+};
+
+/// The vectors kept on the GPU by [`super::Table::gpu_search`], if any
+#[derive(Debug, Default)]
+pub struct GpuSearch {
+    #[cfg(feature = "cuda")]
+    resident: tokio::sync::RwLock<Option<Resident>>,
+}
+
+#[cfg(feature = "cuda")]
+fn gpu_error(e: candle_core::Error) -> Error {
+    Error::Runtime {
+        message: format!("GPU search failed: {}", e),
+    }
+}
+
+/// The vectors of a column in the memory of a device
+#[cfg(feature = "cuda")]
+#[derive(Debug)]
+struct Resident {
+    column: String,
+    device: usize,
+    /// The version of the table the vectors were loaded from
+    version: u64,
+    /// The row id of each vector
+    row_ids: Arc<Vec<u64>>,
+    /// The vectors, one row per vector, in half precision
+    vectors: Tensor,
+    /// The squared L2 norm of each vector, computed before quantizing
+    norms: Tensor,
+}
+
+#[cfg(feature = "cuda")]
+impl Resident {
+    /// Copy the non-null vectors of `column` to the CUDA device `device`
+    async fn load(dataset: &Dataset, column: &str, device: usize) -> Result<Self> {
+        let mut scanner = dataset.scan();
+        scanner.project(&[column])?;
+        scanner.with_row_id();
+        let batches = scanner
+            .try_into_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut row_ids = Vec::new();
+        let mut values = Vec::new();
+        let mut dim = 0;
+        for batch in batches {
+            let vectors = batch[column].as_fixed_size_list();
+            dim = vectors.value_length() as usize;
+            let floats = arrow_cast::cast(vectors.values(), &DataType::Float32)?;
+            let floats = floats.as_primitive::<Float32Type>().values();
+            let ids = batch[ROW_ID_COLUMN].as_primitive::<UInt64Type>();
+            for row in 0..vectors.len() {
+                if vectors.is_null(row) {
+                    continue;
+                }
+                let start = vectors.value_offset(row) as usize;
+                row_ids.push(ids.value(row));
+                values.extend_from_slice(&floats[start..start + dim]);
+            }
+        }
+
+        let num_vectors = row_ids.len();
+        let (vectors, norms) = tokio::task::spawn_blocking(move || {
+            let device = Device::new_cuda(device)?;
+            upload(&device, values, num_vectors, dim)
+        })
+        .await
+        .map_err(|e| Error::Runtime {
+            message: format!("GPU search task failed: {}", e),
+        })?
+        .map_err(gpu_error)?;
+        Ok(Self {
+            column: column.to_string(),
+            device,
+            version: dataset.version().version,
+            row_ids: Arc::new(row_ids),
+            vectors,
+            norms,
+        })
+    }
+}
+
+/// Copy `num_vectors` vectors of `dim` values to `device`, returns the vectors
+/// in half precision and their squared norms
+#[cfg(feature = "cuda")]
+fn upload(
+    device: &Device,
+    values: Vec<f32>,
+    num_vectors: usize,
+    dim: usize,
+) -> candle_core::Result<(Tensor, Tensor)> {
+    let vectors = Tensor::from_vec(values, (num_vectors, dim), device)?;
+    let norms = vectors.sqr()?.sum(1)?;
+    Ok((vectors.to_dtype(DType::F16)?, norms))
+}
+
+/// The distance between `query` and each of `vectors`
+///
+/// The distances are those computed by lance: the squared euclidean distance,
+/// and one minus the cosine similarity or the dot product.
+#[cfg(feature = "cuda")]
+fn distances(
+    vectors: &Tensor,
+    norms: &Tensor,
+    query: &[f32],
+    distance_type: DistanceType,
+) -> candle_core::Result<Vec<f32>> {
+    let device = vectors.device();
+    let query_norm = query.iter().map(|x| x * x).sum::<f32>() as f64;
+    let query = Tensor::from_slice(query, (query.len(), 1), device)?.to_dtype(DType::F16)?;
+    let scores = vectors.matmul(&query)?.to_dtype(DType::F32)?.squeeze(1)?;
+    let distances = match distance_type {
+        DistanceType::Cosine => scores
+            .div(&norms.sqrt()?.affine(query_norm.sqrt(), 0.0)?)?
+            .affine(-1.0, 1.0)?,
+        DistanceType::Dot => scores.affine(-1.0, 1.0)?,
+        // |v|^2 - 2 v.q + |q|^2, clamped as quantization can make it negative
+        _ => norms
+            .sub(&scores.affine(2.0, 0.0)?)?
+            .affine(1.0, query_norm)?
+            .relu()?,
+    };
+    distances.to_vec1::<f32>()
+}
+
+/// The indices of the `limit` smallest `distances`, closest first
+#[cfg(feature = "cuda")]
+fn closest(distances: &[f32], limit: usize) -> Vec<usize> {
+    if limit == 0 {
+        return Vec::new();
+    }
+    let compare = |a: &usize, b: &usize| distances[*a].total_cmp(&distances[*b]);
+    let mut indices = (0..distances.len())
+        .filter(|i| !distances[*i].is_nan())
+        .collect::<Vec<_>>();
+    if limit < indices.len() {
+        indices.select_nth_unstable_by(limit, compare);
+        indices.truncate(limit);
+    }
+    indices.sort_by(compare);
+    indices
+}
+
+impl NativeTable {
+    pub(super) async fn gpu_search_impl(
+        &self,
+        column: &str,
+        accelerator: Accelerator,
+    ) -> Result<()> {
+        let dataset = self.dataset.get().await?;
+        match dataset.schema().field(column).map(|f| f.data_type()) {
+            Some(DataType::FixedSizeList(item, _)) if item.data_type().is_floating() => {}
+            Some(data_type) => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "the column {} must be a vector of floats to be searched on a GPU, \
+                         but has type {}",
+                        column, data_type
+                    ),
+                })
+            }
+            None => {
+                return Err(Error::Schema {
+                    message: format!("The column {} does not exist", column),
+                })
+            }
+        }
+        match accelerator {
+            #[cfg(feature = "cuda")]
+            Accelerator::Cpu => {
+                *self.gpu_search.resident.write().await = None;
+                Ok(())
+            }
+            #[cfg(not(feature = "cuda"))]
+            Accelerator::Cpu => Ok(()),
+            #[cfg(feature = "cuda")]
+            Accelerator::Cuda(device) => {
+                let resident = Resident::load(&dataset, column, device).await?;
+                *self.gpu_search.resident.write().await = Some(resident);
+                Ok(())
+            }
+            #[cfg(not(feature = "cuda"))]
+            Accelerator::Cuda(_) => Err(Error::InvalidInput {
+                message: "searching on a GPU requires the `cuda` feature".to_string(),
+            }),
+        }
+    }
+
+    /// Run `query` on the GPU, returns None if it must be run on the CPU
+    #[cfg(feature = "cuda")]
+    pub(super) async fn gpu_query(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<Option<SendableRecordBatchStream>> {
+        let distance_type = query.distance_type.unwrap_or(DistanceType::L2);
+        if query.base.filter.is_some()
+            || !query.base.time_ranges.is_empty()
+            || !query.base.hints.is_empty()
+            || query.base.only_deleted
+            || matches!(query.base.select, Select::Dynamic(_))
+            || !query.base.computed_columns.is_empty()
+            || !query.use_index
+            || query.unindexed == UnindexedPolicy::Skip
+            || !matches!(
+                distance_type,
+                DistanceType::L2 | DistanceType::Cosine | DistanceType::Dot
+            )
+        {
+            return Ok(None);
+        }
+        let (column, device) = match &*self.gpu_search.resident.read().await {
+            Some(resident) => (resident.column.clone(), resident.device),
+            None => return Ok(None),
+        };
+        let dataset = self.dataset.get().await?.clone();
+        let Some((query_column, query_vector)) = self.resolve_query_vector(&dataset, query)? else {
+            return Ok(None);
+        };
+        if query_column != column {
+            return Ok(None);
+        }
+
+        let version = dataset.version().version;
+        let stale = match &*self.gpu_search.resident.read().await {
+            Some(resident) => resident.version != version,
+            None => return Ok(None),
+        };
+        if stale {
+            let mut resident = self.gpu_search.resident.write().await;
+            // Another search may have reloaded the vectors, or search was disabled
+            match &*resident {
+                Some(current) if current.version == version => {}
+                Some(_) => match Resident::load(&dataset, &column, device).await {
+                    Ok(loaded) => *resident = Some(loaded),
+                    Err(err) => {
+                        log::warn!(
+                            "failed to load the vectors of {} on the GPU, searching on the CPU: {}",
+                            column,
+                            err
+                        );
+                        return Ok(None);
+                    }
+                },
+                None => return Ok(None),
+            }
+        }
+
+        let (row_ids, vectors, norms) = match &*self.gpu_search.resident.read().await {
+            Some(resident) => (
+                resident.row_ids.clone(),
+                resident.vectors.clone(),
+                resident.norms.clone(),
+            ),
+            None => return Ok(None),
+        };
+        if row_ids.is_empty() {
+            return Ok(None);
+        }
+        let query_vector = arrow_cast::cast(query_vector.as_ref(), &DataType::Float32)?;
+        let query_vector = query_vector.as_primitive::<Float32Type>().values().to_vec();
+        let computed = tokio::task::spawn_blocking(move || {
+            distances(&vectors, &norms, &query_vector, distance_type)
+        })
+        .await
+        .map_err(|e| Error::Runtime {
+            message: format!("GPU search task failed: {}", e),
+        })?;
+        let computed = match computed {
+            Ok(computed) => computed,
+            Err(err) => {
+                log::warn!(
+                    "the GPU search of {} failed, searching on the CPU: {}",
+                    column,
+                    err
+                );
+                return Ok(None);
+            }
+        };
+
+        let limit = query.base.limit.unwrap_or(DEFAULT_TOP_K);
+        let indices = closest(&computed, limit);
+        let matches = indices.iter().map(|i| row_ids[*i]).collect::<Vec<_>>();
+        let projection = match &query.base.select {
+            Select::Columns(columns) => dataset.schema().project(columns)?,
+            _ => dataset.schema().clone(),
+        };
+        let batch = dataset.take_rows(&matches, &projection).await?;
+
+        let mut fields = batch.schema().fields().to_vec();
+        let mut columns = batch.columns().to_vec();
+        if query.base.with_row_id {
+            fields.push(Arc::new(Field::new(ROW_ID_COLUMN, DataType::UInt64, false)));
+            columns.push(Arc::new(UInt64Array::from(matches)));
+        }
+        fields.push(Arc::new(Field::new("_distance", DataType::Float32, true)));
+        columns.push(Arc::new(Float32Array::from_iter_values(
+            indices.iter().map(|i| computed[*i]),
+        )));
+        let schema = Arc::new(Schema::new(fields));
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+        let batch_size = (options.max_batch_length as usize).max(1);
+        let batches = (0..batch.num_rows())
+            .step_by(batch_size)
+            .map(|offset| Ok(batch.slice(offset, batch_size.min(batch.num_rows() - offset))))
+            .collect::<Vec<_>>();
+        Ok(Some(Box::pin(SimpleRecordBatchStream {
+            schema,
+            stream: futures::stream::iter(batches),
+        })))
+    }
+}
+
+#[cfg(all(test, feature = "cuda"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distances() {
+        // Computed on the CPU device, the kernels are the same
+        let values = vec![1.0, 0.0, 0.0, 2.0, 3.0, 4.0];
+        let (vectors, norms) = upload(&Device::Cpu, values, 3, 2).unwrap();
+        let l2 = distances(&vectors, &norms, &[1.0, 0.0], DistanceType::L2).unwrap();
+        assert_eq!(l2, vec![0.0, 5.0, 20.0]);
+        let dot = distances(&vectors, &norms, &[1.0, 0.0], DistanceType::Dot).unwrap();
+        assert_eq!(dot, vec![0.0, 1.0, -2.0]);
+        let cosine = distances(&vectors, &norms, &[0.0, 1.0], DistanceType::Cosine).unwrap();
+        assert!((cosine[1] - 0.0).abs() < 1e-3);
+        assert!((cosine[2] - 0.2).abs() < 1e-3);
+        assert_eq!(closest(&l2, 2), vec![0, 1]);
+    }
+}
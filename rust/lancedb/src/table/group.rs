@@ -0,0 +1,294 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Vector searches grouping their results, see [`VectorQuery::group_by`]
+//!
+//! The search asks for more results than needed, then goes through them in the
+//! order of the distances: the first `limit` groups seen are returned, with at
+//! most `k_per_group` rows each.  If some of these groups have fewer rows, and
+//! the search may have left some out, it runs again with a larger limit.
+//! Groups with many rows close to the query vector, e.g. a long document split
+//! in many chunks, make the search run several times.
+
+use std::collections::HashMap;
+
+use arrow::compute::concat_batches;
+use arrow::row::{RowConverter, SortField};
+use arrow_array::{RecordBatch, UInt32Array};
+use futures::{stream, TryStreamExt};
+
+use super::{NativeTable, TableInternal};
+use crate::arrow::{take_record_batch, SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+use crate::query::{QueryExecutionOptions, Select, VectorQuery, DEFAULT_TOP_K};
+
+/// How many more results than needed are asked for, at each attempt
+const OVERFETCH_FACTOR: usize = 4;
+
+/// The first `k` rows of each of the first `limit` groups of `column` in `batch`
+///
+/// Returns the rows and whether all of the groups were found, with `k` rows each.
+fn grouped_rows(
+    batch: &RecordBatch,
+    column: &str,
+    limit: usize,
+    k: usize,
+) -> Result<(RecordBatch, bool)> {
+    let keys = batch
+        .column_by_name(column)
+        .ok_or_else(|| Error::InvalidInput {
+            message: format!("the column '{}' to group by is not in the results", column),
+        })?;
+    let converter = RowConverter::new(vec![SortField::new(keys.data_type().clone())])?;
+    let rows = converter.convert_columns(std::slice::from_ref(keys))?;
+    let mut groups = HashMap::new();
+    let mut indices = Vec::new();
+    for row in 0..batch.num_rows() {
+        let key = rows.row(row);
+        if groups.len() == limit && !groups.contains_key(&key) {
+            continue;
+        }
+        let count = groups.entry(key).or_insert(0);
+        if *count < k {
+            *count += 1;
+            indices.push(row as u32);
+        }
+    }
+    let complete = groups.len() == limit && groups.values().all(|count| *count == k);
+    Ok((
+        take_record_batch(batch, &UInt32Array::from(indices))?,
+        complete,
+    ))
+}
+
+impl NativeTable {
+    /// Run the vector search `query`, keeping the closest results of each
+    /// value of its `group_by` column
+    pub(super) async fn grouped_search(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        let column = query
+            .group_by
+            .clone()
+            .expect("the query is grouped by a column");
+        let limit = query.base.limit.unwrap_or(DEFAULT_TOP_K);
+        let k = query.k_per_group;
+        if k == 0 {
+            return Err(Error::InvalidInput {
+                message: "k_per_group must be at least 1".to_string(),
+            });
+        }
+        let num_rows = self.count_rows(None).await?;
+
+        let mut search = query.clone();
+        search.group_by = None;
+        // The column is read to group the results even if it is not returned
+        let added = match &mut search.base.select {
+            Select::All => false,
+            Select::Columns(columns) if columns.contains(&column) => false,
+            Select::Columns(columns) => {
+                columns.push(column.clone());
+                true
+            }
+            Select::Dynamic(columns) if columns.iter().any(|(name, _)| *name == column) => false,
+            Select::Dynamic(columns) => {
+                columns.push((column.clone(), column.clone()));
+                true
+            }
+        };
+        let needed = limit.saturating_mul(k);
+        let mut fetch = needed.saturating_mul(OVERFETCH_FACTOR);
+        let mut results = loop {
+            search.base.limit = Some(fetch.min(num_rows).max(needed));
+            let stream = if search.refine_with_exact {
+                self.rescored_search(&search, options.clone()).await?
+            } else {
+                self.search(&search, options.clone()).await?
+            };
+            let schema = stream.schema();
+            let batches = stream.try_collect::<Vec<_>>().await?;
+            let batch = concat_batches(&schema, &batches)?;
+            // Fewer results than asked for means there are no more to find
+            let exhausted = batch.num_rows() < fetch || fetch >= num_rows;
+            let (results, complete) = grouped_rows(&batch, &column, limit, k)?;
+            if complete || exhausted {
+                break results;
+            }
+            fetch = fetch.saturating_mul(OVERFETCH_FACTOR);
+        };
+        if added {
+            let index = results.schema().index_of(&column)?;
+            results.remove_column(index);
+        }
+        Ok(Box::pin(SimpleRecordBatchStream {
+            schema: results.schema(),
+            stream: stream::iter([Ok(results)]),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{
+        cast::AsArray, types::Int32Type, FixedSizeListArray, Float32Array, Int32Array,
+        RecordBatchIterator,
+    };
+    use arrow_schema::{DataType, Field, Schema};
+    use lance::arrow::FixedSizeListArrayExt;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+
+    /// 10 documents of 10 chunks, the chunks of document i are at i * 10 + j
+    async fn chunks_table(db: &crate::Connection) -> crate::Table {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("document_id", DataType::Int32, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 1),
+                false,
+            ),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values((0..100).map(|i| i / 10))),
+                Arc::new(
+                    FixedSizeListArray::try_new_from_values(
+                        Float32Array::from_iter_values((0..100).map(|i| i as f32)),
+                        1,
+                    )
+                    .unwrap(),
+                ),
+            ],
+        )
+        .unwrap();
+        db.create_table("chunks", RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_distinct_on() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = chunks_table(&db).await;
+
+        let results = table
+            .query()
+            .limit(3)
+            .select(Select::columns(&["document_id"]))
+            .nearest_to(&[0.0])
+            .unwrap()
+            .distinct_on("document_id")
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&results[0].schema(), &results).unwrap();
+        let documents = batch["document_id"]
+            .as_primitive::<Int32Type>()
+            .values()
+            .to_vec();
+        assert_eq!(documents, vec![0, 1, 2]);
+
+        // The column is read even if it is not returned
+        let results = table
+            .query()
+            .limit(20)
+            .select(Select::columns(&["vector"]))
+            .nearest_to(&[95.0])
+            .unwrap()
+            .distinct_on("document_id")
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&results[0].schema(), &results).unwrap();
+        // There are only 10 documents
+        assert_eq!(batch.num_rows(), 10);
+        assert!(batch.column_by_name("document_id").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_group_by() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = chunks_table(&db).await;
+
+        let results = table
+            .query()
+            .limit(2)
+            .nearest_to(&[0.0])
+            .unwrap()
+            .group_by("document_id")
+            .k_per_group(3)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&results[0].schema(), &results).unwrap();
+        let documents = batch["document_id"]
+            .as_primitive::<Int32Type>()
+            .values()
+            .to_vec();
+        assert_eq!(documents, vec![0, 0, 0, 1, 1, 1]);
+
+        // All of the chunks of the two closest documents
+        let results = table
+            .query()
+            .limit(2)
+            .nearest_to(&[15.0])
+            .unwrap()
+            .group_by("document_id")
+            .k_per_group(10)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&results[0].schema(), &results).unwrap();
+        assert_eq!(batch.num_rows(), 20);
+
+        let result = table
+            .query()
+            .nearest_to(&[0.0])
+            .unwrap()
+            .group_by("document_id")
+            .k_per_group(0)
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+    }
+}
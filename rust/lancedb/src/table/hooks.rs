@@ -0,0 +1,375 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hooks called around the commits of the tables of a connection
+//!
+//! Hooks are registered with [`crate::connection::ConnectBuilder::commit_hook`]
+//! and are called for each operation that writes to a table, such as an add, a
+//! delete or the creation of an index.  [`CommitHook::before_commit`] is called
+//! before the operation writes anything and can reject it, for example to make
+//! a table read-only or to forbid some operations.  The hooks only get a
+//! [`CommitSummary`] of the operation, not the data being written, so they
+//! cannot validate the rows of a write.  [`CommitHook::after_commit`] is called once
+//! the new version is committed, for example to trigger a replication or to
+//! purge a cache.  Operations that do not commit a new version, such as a delete
+//! that matches no rows, are not reported after the commit.
+//!
+//! Counting the rows of a version reads the metadata of its fragments, so the
+//! row counts of the summary are only computed if a hook asks for them with
+//! [`CommitHook::row_counts`].
+//!
+//! A closure called after the commits, for example to write an audit log, can be
+//! registered with [`crate::connection::ConnectBuilder::on_commit`] instead of
+//! implementing [`CommitHook`]:
+//!
+//! ```ignore
+//! let db = lancedb::connect("data/db")
+//!     .on_commit(|summary| {
+//!         log::info!(
+//!             "{} {} -> version {:?}, {:?} rows",
+//!             summary.table,
+//!             summary.operation,
+//!             summary.version,
+//!             summary.num_rows
+//!         )
+//!     })
+//!     .execute()
+//!     .await?;
+//! ```
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::NativeTable;
+use crate::error::Result;
+use crate::telemetry::OperationSpan;
+
+/// A summary of an operation writing to a table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitSummary {
+    /// The name of the table
+    pub table: String,
+    /// The operation, for example `add`, `delete` or `create_index`
+    pub operation: String,
+    /// The version of the table when the operation started
+    pub read_version: u64,
+    /// The version committed by the operation, None before the commit
+    pub version: Option<u64>,
+    /// The number of rows of the table when the operation started, None if no
+    /// hook asks for the row counts
+    pub read_num_rows: Option<u64>,
+    /// The number of rows of the version committed by the operation, None
+    /// before the commit or if no hook asks for the row counts
+    pub num_rows: Option<u64>,
+}
+
+/// A hook called before and after the commits of the tables of a connection
+///
+/// See [`crate::table::hooks`] for more details.
+#[async_trait::async_trait]
+pub trait CommitHook: std::fmt::Debug + Send + Sync {
+    /// Whether the hook uses the row counts of the summary
+    ///
+    /// The rows are counted before and after the operation if any hook returns
+    /// true.  The default is false.
+    fn row_counts(&self) -> bool {
+        false
+    }
+
+    /// Called before the operation writes anything
+    ///
+    /// Returning an error aborts the operation, the error is returned to the caller.
+    async fn before_commit(&self, summary: &CommitSummary) -> Result<()> {
+        let _ = summary;
+        Ok(())
+    }
+
+    /// Called after the operation committed a new version
+    ///
+    /// The commit cannot be undone, errors are logged.
+    async fn after_commit(&self, summary: &CommitSummary) -> Result<()> {
+        let _ = summary;
+        Ok(())
+    }
+}
+
+/// A hook calling a closure after the commits, see
+/// [`crate::connection::ConnectBuilder::on_commit`]
+pub(crate) struct OnCommit(Box<dyn Fn(&CommitSummary) + Send + Sync>);
+
+impl OnCommit {
+    pub(crate) fn new(callback: impl Fn(&CommitSummary) + Send + Sync + 'static) -> Self {
+        Self(Box::new(callback))
+    }
+}
+
+impl std::fmt::Debug for OnCommit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OnCommit").finish_non_exhaustive()
+    }
+}
+
+#[async_trait::async_trait]
+impl CommitHook for OnCommit {
+    fn row_counts(&self) -> bool {
+        true
+    }
+
+    async fn after_commit(&self, summary: &CommitSummary) -> Result<()> {
+        (self.0)(summary);
+        Ok(())
+    }
+}
+
+impl NativeTable {
+    /// Call `hooks` around the commits of the table
+    pub(crate) fn with_commit_hooks(mut self, hooks: Vec<Arc<dyn CommitHook>>) -> Self {
+        self.commit_hooks = hooks;
+        self
+    }
+
+    /// Run the `operation`, calling the commit hooks before and after it
+    ///
+    /// The operation runs in a `lancedb.write` span, see [`crate::telemetry`],
+    /// and is counted in the metrics of the connection, see [`crate::metrics`].
+    pub(super) async fn run_with_hooks<T>(
+        &self,
+        operation: &str,
+        write: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let start = Instant::now();
+        let span = OperationSpan::write(&self.name, operation);
+        let result = span
+            .clone()
+            .run(self.run_write(operation, write, &span))
+            .await;
+        if let Some(metrics) = &self.metrics {
+            metrics.observe_write(start, &result);
+            self.report_fragments().await;
+        }
+        result
+    }
+
+    async fn run_write<T>(
+        &self,
+        operation: &str,
+        write: impl Future<Output = Result<T>>,
+        span: &OperationSpan,
+    ) -> Result<T> {
+        if self.commit_hooks.is_empty() {
+            let result = write.await?;
+            if span.is_recording() {
+                span.record_version(self.dataset.get().await?.version().version);
+            }
+            return Ok(result);
+        }
+        let row_counts = self.commit_hooks.iter().any(|hook| hook.row_counts());
+        let dataset = self.dataset.get().await?.clone();
+        let mut summary = CommitSummary {
+            table: self.name.clone(),
+            operation: operation.to_string(),
+            read_version: dataset.version().version,
+            version: None,
+            read_num_rows: None,
+            num_rows: None,
+        };
+        if row_counts {
+            summary.read_num_rows = Some(dataset.count_rows(None).await? as u64);
+        }
+        for hook in &self.commit_hooks {
+            hook.before_commit(&summary).await?;
+        }
+        let result = write.await?;
+        let dataset = self.dataset.get().await?.clone();
+        let version = dataset.version().version;
+        span.record_version(version);
+        if version != summary.read_version {
+            summary.version = Some(version);
+            if row_counts {
+                summary.num_rows = Some(dataset.count_rows(None).await? as u64);
+            }
+            for hook in &self.commit_hooks {
+                if let Err(e) = hook.after_commit(&summary).await {
+                    log::warn!(
+                        "commit hook failed after the commit: table={} operation={} version={} error=\"{}\"",
+                        self.name,
+                        operation,
+                        version,
+                        e
+                    );
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::error::Error;
+
+    #[derive(Debug, Default)]
+    struct RecordingHook {
+        row_counts: bool,
+        committed: Mutex<Vec<CommitSummary>>,
+    }
+
+    #[async_trait::async_trait]
+    impl CommitHook for RecordingHook {
+        fn row_counts(&self) -> bool {
+            self.row_counts
+        }
+
+        async fn before_commit(&self, summary: &CommitSummary) -> Result<()> {
+            if summary.operation == "drop_columns" {
+                return Err(Error::InvalidInput {
+                    message: "columns cannot be dropped".to_string(),
+                });
+            }
+            Ok(())
+        }
+
+        async fn after_commit(&self, summary: &CommitSummary) -> Result<()> {
+            self.committed.lock().unwrap().push(summary.clone());
+            Ok(())
+        }
+    }
+
+    fn batches() -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("i", DataType::Int32, false),
+            Field::new("j", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..10)),
+                Arc::new(Int32Array::from_iter_values(0..10)),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_commit_hooks() {
+        let tmp_dir = tempdir().unwrap();
+        let hook = Arc::new(RecordingHook {
+            row_counts: true,
+            ..Default::default()
+        });
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .commit_hook(hook.clone())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("test", batches()).execute().await.unwrap();
+        let version = table.version().await.unwrap();
+
+        table.add(batches()).execute().await.unwrap();
+        // Deleting no rows does not commit a new version
+        table.delete("i > 100").await.unwrap();
+        table.delete("i < 5").await.unwrap();
+        let result = table.drop_columns(&["j"]).await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+        assert_eq!(table.schema().await.unwrap().fields().len(), 2);
+
+        let committed = hook.committed.lock().unwrap().clone();
+        assert_eq!(
+            committed,
+            vec![
+                CommitSummary {
+                    table: "test".to_string(),
+                    operation: "add".to_string(),
+                    read_version: version,
+                    version: Some(version + 1),
+                    read_num_rows: Some(10),
+                    num_rows: Some(20),
+                },
+                CommitSummary {
+                    table: "test".to_string(),
+                    operation: "delete".to_string(),
+                    read_version: version + 1,
+                    version: Some(version + 2),
+                    read_num_rows: Some(20),
+                    num_rows: Some(10),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_commit_hooks_without_row_counts() {
+        let tmp_dir = tempdir().unwrap();
+        let hook = Arc::new(RecordingHook::default());
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .commit_hook(hook.clone())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("test", batches()).execute().await.unwrap();
+        let version = table.version().await.unwrap();
+        table.add(batches()).execute().await.unwrap();
+
+        // No hook asks for the row counts, so the rows are not counted
+        let committed = hook.committed.lock().unwrap().clone();
+        assert_eq!(
+            committed,
+            vec![CommitSummary {
+                table: "test".to_string(),
+                operation: "add".to_string(),
+                read_version: version,
+                version: Some(version + 1),
+                read_num_rows: None,
+                num_rows: None,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_on_commit() {
+        let tmp_dir = tempdir().unwrap();
+        let operations = Arc::new(Mutex::new(Vec::new()));
+        let recorded = operations.clone();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .on_commit(move |summary| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((summary.operation.clone(), summary.num_rows))
+            })
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("test", batches()).execute().await.unwrap();
+        table.add(batches()).execute().await.unwrap();
+        table.delete("i = 0").await.unwrap();
+        assert_eq!(
+            *operations.lock().unwrap(),
+            vec![
+                ("add".to_string(), Some(20)),
+                ("delete".to_string(), Some(18))
+            ]
+        );
+    }
+}
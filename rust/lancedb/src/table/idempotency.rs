@@ -0,0 +1,102 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Idempotency keys of writes
+//!
+//! An add or a merge insert can be given an idempotency key (see
+//! [`super::AddDataBuilder::idempotency_key`]).  The keys of the applied writes
+//! are recorded, with the version of the table they committed, in a hidden
+//! dataset in the table's directory.  A write whose key was already recorded is
+//! skipped, so a request that is retried after a timeout is only applied once.
+//!
+//! The key is recorded right after the write is committed.  If the process fails
+//! in between, the key is not recorded and a retry applies the write again.
+
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, types::UInt64Type, RecordBatch, RecordBatchIterator};
+use arrow_array::{StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use futures::TryStreamExt;
+use lance::dataset::WriteMode;
+use lance::Dataset;
+
+use super::NativeTable;
+use crate::error::Result;
+use crate::query::filter::Filter;
+
+const IDEMPOTENCY_DIR: &str = "_idempotency";
+
+fn idempotency_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("key", DataType::Utf8, false),
+        Field::new("version", DataType::UInt64, false),
+    ]))
+}
+
+impl NativeTable {
+    fn idempotency_uri(&self) -> String {
+        format!("{}/{}", self.uri.trim_end_matches('/'), IDEMPOTENCY_DIR)
+    }
+
+    /// The version committed by the write with the idempotency key `key`, if it
+    /// was already applied
+    pub(super) async fn applied_version(&self, key: &str) -> Result<Option<u64>> {
+        let Some(dataset) = self.open_sidecar(&self.idempotency_uri()).await? else {
+            return Ok(None);
+        };
+        let filter = Filter::parse("key = $key")?.bind("key", key).to_sql()?;
+        let mut scanner = dataset.scan();
+        scanner.filter(&filter)?;
+        scanner.project(&["version"])?;
+        let batches = scanner
+            .try_into_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(batches
+            .iter()
+            .flat_map(|batch| {
+                batch["version"]
+                    .as_primitive::<UInt64Type>()
+                    .values()
+                    .to_vec()
+            })
+            .min())
+    }
+
+    /// Record that the write with the idempotency key `key` committed `version`
+    pub(super) async fn record_idempotency_key(&self, key: &str, version: u64) -> Result<()> {
+        let schema = idempotency_schema();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![key])),
+                Arc::new(UInt64Array::from(vec![version])),
+            ],
+        )?;
+        let mode = if self.open_sidecar(&self.idempotency_uri()).await?.is_some() {
+            WriteMode::Append
+        } else {
+            WriteMode::Create
+        };
+        Dataset::write(
+            RecordBatchIterator::new(vec![Ok(batch)], schema),
+            &self.idempotency_uri(),
+            Some(self.sidecar_write_params(mode)?),
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recovery from lost or corrupted index files
+//!
+//! An index is damaged if its directory in `_indices` is empty or if one of its
+//! files does not match its checksum (see [`crate::io::checksum`]).  A vector
+//! search that fails while one of the indices on the searched column is damaged
+//! is retried as a flat search, and a warning is logged.  The damaged indices
+//! can then be rebuilt with [`crate::Table::rebuild_missing_indices`].
+
+use std::collections::HashSet;
+
+use futures::{stream, StreamExt, TryStreamExt};
+use lance::io::{ObjectStore, ObjectStoreParams};
+use lance::Dataset;
+use lance_index::DatasetIndexExt;
+
+use super::{NativeTable, TableInternal};
+use crate::arrow::{SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+use crate::io::checksum::{self, ChecksumStatus};
+use crate::query::{QueryExecutionOptions, VectorQuery};
+
+const INDICES_DIR: &str = "_indices";
+
+impl NativeTable {
+    /// The uuids of the indices of `dataset` whose files are lost or corrupted
+    async fn damaged_indices(&self, dataset: &Dataset) -> Result<HashSet<String>> {
+        let params = ObjectStoreParams {
+            storage_options: Some(self.storage_options.clone()),
+            ..Default::default()
+        };
+        let (store, path) = ObjectStore::from_uri_and_params(&self.uri, &params).await?;
+        let mut damaged = HashSet::new();
+        for index in dataset.load_indices().await?.iter() {
+            let uuid = index.uuid.to_string();
+            let dir = path.child(INDICES_DIR).child(uuid.as_str());
+            let files = store.inner.list(Some(&dir)).try_collect::<Vec<_>>().await?;
+            let mut is_damaged = files.is_empty();
+            for file in files {
+                if is_damaged {
+                    break;
+                }
+                let status = checksum::verify_object(store.inner.as_ref(), &file.location).await?;
+                is_damaged = matches!(status, ChecksumStatus::Corrupted { .. });
+            }
+            if is_damaged {
+                damaged.insert(uuid);
+            }
+        }
+        Ok(damaged)
+    }
+
+    /// The names of the damaged indices on `column`
+    async fn damaged_column_indices(&self, dataset: &Dataset, column: &str) -> Result<Vec<String>> {
+        let damaged = self.damaged_indices(dataset).await?;
+        if damaged.is_empty() {
+            return Ok(Vec::new());
+        }
+        let Some(field) = dataset.schema().field(column) else {
+            return Ok(Vec::new());
+        };
+        Ok(dataset
+            .load_indices()
+            .await?
+            .iter()
+            .filter(|index| index.fields.contains(&field.id))
+            .filter(|index| damaged.contains(&index.uuid.to_string()))
+            .map(|index| index.name.clone())
+            .collect())
+    }
+
+    /// Run a vector search, falling back to a flat search if it fails because
+    /// the index on the searched column is damaged
+    ///
+    /// Errors raised while reading the index only surface once the stream is
+    /// polled, so the first batch is read before the stream is returned.
+    pub(super) async fn query_with_fallback(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        let indexed = async {
+            let mut stream: SendableRecordBatchStream =
+                self.generic_query(query, options.clone()).await?.into();
+            let first = stream.try_next().await?;
+            Ok::<_, Error>((stream, first))
+        }
+        .await;
+        let err = match indexed {
+            Ok((stream, first)) => {
+                let schema = stream.schema();
+                return Ok(Box::pin(SimpleRecordBatchStream {
+                    schema,
+                    stream: stream::iter(first.map(Ok)).chain(stream),
+                }));
+            }
+            Err(err) if !query.use_index => return Err(err),
+            Err(err) => err,
+        };
+
+        let dataset = self.dataset.get().await?.clone();
+        let Some((column, _)) = self.resolve_query_vector(&dataset, query)? else {
+            return Err(err);
+        };
+        let damaged = self.damaged_column_indices(&dataset, &column).await?;
+        if damaged.is_empty() {
+            return Err(err);
+        }
+        log::warn!(
+            "The index {} on column {} of table {} is damaged, falling back to a flat search. \
+             Rebuild it with Table::rebuild_missing_indices. The search failed with: {}",
+            damaged.join(", "),
+            column,
+            self.name,
+            err
+        );
+        let mut flat = query.clone();
+        flat.use_index = false;
+        Ok(self.generic_query(&flat, options).await?.into())
+    }
+
+    pub(super) async fn rebuild_damaged_indices(&self) -> Result<Vec<String>> {
+        let dataset = self.dataset.get().await?.clone();
+        let damaged = self.damaged_indices(&dataset).await?;
+        let mut rebuilt: Vec<(String, Vec<String>)> = Vec::new();
+        for index in dataset.load_indices().await?.iter() {
+            if !damaged.contains(&index.uuid.to_string())
+                || rebuilt.iter().any(|(name, _)| name == &index.name)
+            {
+                continue;
+            }
+            let columns = index
+                .fields
+                .iter()
+                .map(|id| {
+                    dataset
+                        .schema()
+                        .field_by_id(*id)
+                        .map(|field| field.name.clone())
+                        .ok_or_else(|| Error::Runtime {
+                            message: format!(
+                                "The index {} references a field with id {} which does not exist",
+                                index.name, id
+                            ),
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            rebuilt.push((index.name.clone(), columns));
+        }
+
+        let schema = self.schema().await?;
+        for (name, columns) in &rebuilt {
+            self.drop_index(name).await?;
+            for column in columns {
+                self.create_auto_index(schema.field_with_name(column)?, true)
+                    .await?;
+            }
+        }
+        Ok(rebuilt.into_iter().map(|(name, _)| name).collect())
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Point reads of rows by row id or by key
+//!
+//! [`super::Table::take`] reads rows by their row id, the `_rowid` column of
+//! the results of a query, without scanning the table: only the pages holding
+//! the rows of the selected columns are read.  [`super::Table::lookup`] first
+//! finds the row ids of the keys, reading only the key column, which is fast
+//! with a scalar index on it, then takes the rows.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::UInt64Type;
+use arrow_array::{Array, RecordBatch};
+use arrow_schema::Schema;
+use futures::TryStreamExt;
+
+use super::{NativeTable, TableInternal};
+use crate::error::{Error, Result};
+use crate::query::filter::{Filter, FilterValue};
+use crate::query::{ExecutableQuery, Query, QueryBase, Select};
+
+const ROW_ID_COLUMN: &str = "_rowid";
+
+/// The keys are looked up this many at a time
+const LOOKUP_BATCH_SIZE: usize = 1024;
+
+impl NativeTable {
+    /// Read the rows with the ids `row_ids`, in that order
+    pub(super) async fn take_rows(&self, row_ids: &[u64], select: Select) -> Result<RecordBatch> {
+        let dataset = self.dataset.get().await?;
+        let projection = match select {
+            Select::All => dataset.schema().clone(),
+            Select::Columns(columns) => dataset.schema().project(&columns)?,
+            Select::Dynamic(_) => {
+                return Err(Error::InvalidInput {
+                    message: "only columns can be selected when taking rows".to_string(),
+                })
+            }
+        };
+        if row_ids.is_empty() {
+            return Ok(RecordBatch::new_empty(Arc::new(Schema::from(&projection))));
+        }
+        Ok(dataset.take_rows(row_ids, &projection).await?)
+    }
+}
+
+/// The filter matching the rows whose `column` is one of `keys`
+fn key_filter(column: &str, keys: &[FilterValue]) -> Result<String> {
+    let params = (0..keys.len())
+        .map(|i| format!("$key{}", i))
+        .collect::<Vec<_>>();
+    keys.iter()
+        .enumerate()
+        .fold(
+            Filter::parse(format!("`{}` IN ({})", column, params.join(", ")))?,
+            |filter, (i, key)| filter.bind(format!("key{}", i), key.clone()),
+        )
+        .to_sql()
+}
+
+/// Read the rows of `table` whose `column` is one of `values`
+///
+/// The rows are returned in the order of the values they match.
+pub(super) async fn lookup(
+    table: Arc<dyn TableInternal>,
+    column: &str,
+    values: &dyn Array,
+    select: Select,
+) -> Result<RecordBatch> {
+    let keys = FilterValue::from_array(column, values)?;
+    // The values are compared once converted to filter values, so that keys of
+    // different integer types match
+    let mut positions = HashMap::new();
+    for (position, key) in keys.iter().enumerate() {
+        positions.entry(format!("{:?}", key)).or_insert(position);
+    }
+
+    let mut found = Vec::new();
+    for chunk in keys.chunks(LOOKUP_BATCH_SIZE) {
+        let mut query = Query::new(table.clone())
+            .only_if(key_filter(column, chunk)?)
+            .select(Select::columns(&[column]));
+        query.with_row_id = true;
+        let batches = query.execute().await?.try_collect::<Vec<_>>().await?;
+        for batch in batches {
+            let row_ids = batch
+                .column_by_name(ROW_ID_COLUMN)
+                .ok_or_else(|| Error::Runtime {
+                    message: "the results of the lookup have no row ids".to_string(),
+                })?
+                .as_primitive::<UInt64Type>()
+                .clone();
+            let batch_keys = batch.column_by_name(column).ok_or_else(|| Error::Runtime {
+                message: format!("the results of the lookup have no column {}", column),
+            })?;
+            let batch_keys = FilterValue::from_array(column, batch_keys.as_ref())?;
+            for (key, row_id) in batch_keys.iter().zip(row_ids.values()) {
+                if let Some(position) = positions.get(&format!("{:?}", key)) {
+                    found.push((*position, *row_id));
+                }
+            }
+        }
+    }
+    found.sort_unstable();
+    let row_ids = found
+        .into_iter()
+        .map(|(_, row_id)| row_id)
+        .collect::<Vec<_>>();
+    table.take(&row_ids, select).await
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{
+        Int32Array, Int64Array, RecordBatchIterator, RecordBatchReader, StringArray,
+    };
+    use arrow_schema::{DataType, Field};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    fn batch() -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..100)),
+                Arc::new(StringArray::from_iter_values(
+                    (0..100).map(|i| format!("name{}", i)),
+                )),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_take_and_lookup() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let table = db.create_table("test", batch()).execute().await.unwrap();
+
+        // The rows of the first fragment have the ids 0, 1, ...
+        let rows = table
+            .take(&[5, 2], Select::columns(&["name"]))
+            .await
+            .unwrap();
+        assert_eq!(rows.num_columns(), 1);
+        let names = rows.column(0).as_string::<i32>();
+        assert_eq!(names.value(0), "name5");
+        assert_eq!(names.value(1), "name2");
+
+        let rows = table
+            .lookup("id", &Int64Array::from(vec![42, 1000, 7]), Select::All)
+            .await
+            .unwrap();
+        assert_eq!(rows.num_rows(), 2);
+        let ids = rows
+            .column_by_name("id")
+            .unwrap()
+            .as_primitive::<arrow_array::types::Int32Type>();
+        assert_eq!(ids.values(), &[42, 7]);
+
+        let rows = table
+            .lookup("id", &Int32Array::from(Vec::<i32>::new()), Select::All)
+            .await
+            .unwrap();
+        assert_eq!(rows.num_rows(), 0);
+        assert_eq!(rows.num_columns(), 2);
+    }
+}
@@ -14,23 +14,24 @@
 
 use std::sync::Arc;
 
-use arrow_array::RecordBatchReader;
-
+use crate::arrow::IntoArrow;
 use crate::Result;
 
-use super::TableInternal;
+use super::{SchemaMode, SchemaPolicy, TableInternal};
 
 /// A builder used to create and run a merge insert operation
 ///
 /// See [`super::Table::merge_insert`] for more context
 pub struct MergeInsertBuilder {
     table: Arc<dyn TableInternal>,
-    pub(super) on: Vec<String>,
-    pub(super) when_matched_update_all: bool,
-    pub(super) when_matched_update_all_filt: Option<String>,
-    pub(super) when_not_matched_insert_all: bool,
-    pub(super) when_not_matched_by_source_delete: bool,
-    pub(super) when_not_matched_by_source_delete_filt: Option<String>,
+    pub(crate) on: Vec<String>,
+    pub(crate) when_matched_update_all: bool,
+    pub(crate) when_matched_update_all_filt: Option<String>,
+    pub(crate) when_not_matched_insert_all: bool,
+    pub(crate) when_not_matched_by_source_delete: bool,
+    pub(crate) when_not_matched_by_source_delete_filt: Option<String>,
+    pub(crate) idempotency_key: Option<String>,
+    pub(crate) schema_policy: SchemaPolicy,
 }
 
 impl MergeInsertBuilder {
@@ -43,6 +44,8 @@ impl MergeInsertBuilder {
             when_not_matched_insert_all: false,
             when_not_matched_by_source_delete: false,
             when_not_matched_by_source_delete_filt: None,
+            idempotency_key: None,
+            schema_policy: SchemaPolicy::default(),
         }
     }
 
@@ -94,10 +97,31 @@ impl MergeInsertBuilder {
         self
     }
 
+    /// Apply the merge insert only once, however many times it is retried
+    ///
+    /// See [`super::AddDataBuilder::idempotency_key`]
+    pub fn idempotency_key(&mut self, key: impl Into<String>) -> &mut Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// How the schema of the new data is matched to the schema of the table
+    ///
+    /// See [`SchemaMode::Coerce`]
+    pub fn schema_mode(&mut self, schema_mode: SchemaMode) -> &mut Self {
+        self.schema_policy.mode = schema_mode;
+        self
+    }
+
     /// Executes the merge insert operation
     ///
+    /// The new data can be anything that implements [`IntoArrow`].  The
+    /// whole operation is committed as a single new version of the table
+    /// so concurrent readers never observe a partially applied merge.
+    ///
     /// Nothing is returned but the [`super::Table`] is updated
-    pub async fn execute(self, new_data: Box<dyn RecordBatchReader + Send>) -> Result<()> {
+    pub async fn execute(self, new_data: impl IntoArrow) -> Result<()> {
+        let new_data = new_data.into_arrow()?;
         self.table.clone().merge_insert(self, new_data).await
     }
 }
@@ -0,0 +1,180 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metadata stored with a table
+//!
+//! Applications can store key-value pairs with the table, such as a
+//! description, the owner of the table or the embedding model of its vectors,
+//! see [`super::Table::update_metadata`], and with each of its columns, see
+//! [`super::Table::update_field_metadata`].  The metadata is stored in the
+//! schema of the table, so it is versioned with the table and returned in the
+//! schema of the results of the queries.
+//!
+//! LanceDB and Lance store their own settings, such as the primary key or the
+//! compression of a column, in the same metadata under keys starting with
+//! `lancedb::` or `lance`.  These keys cannot be set with these methods.
+
+use std::collections::HashMap;
+
+use lance::dataset::transaction::Operation;
+use lance::dataset::WriteParams;
+use lance::datatypes::Schema;
+use lance::io::ObjectStoreParams;
+use lance::Dataset;
+
+use super::NativeTable;
+use crate::error::{Error, Result};
+
+/// The prefixes of the keys reserved for LanceDB and Lance
+const RESERVED_PREFIXES: [&str; 2] = ["lancedb::", "lance"];
+
+fn validate_keys(metadata: &HashMap<String, String>) -> Result<()> {
+    match metadata.keys().find(|key| {
+        RESERVED_PREFIXES
+            .iter()
+            .any(|prefix| key.starts_with(prefix))
+    }) {
+        Some(key) => Err(Error::InvalidInput {
+            message: format!("the metadata key {} is reserved for LanceDB", key),
+        }),
+        None => Ok(()),
+    }
+}
+
+impl NativeTable {
+    /// Commit `schema`, with the same fields as the schema of the table, in a
+    /// new version on top of `read_version`
+    pub(super) async fn commit_schema(&self, schema: Schema, read_version: u64) -> Result<()> {
+        let params = WriteParams {
+            store_params: Some(ObjectStoreParams {
+                storage_options: Some(self.storage_options.clone()),
+                object_store_wrapper: self.store_wrapper.clone(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        self.dataset.ensure_mutable().await?;
+        let dataset = Dataset::commit(
+            &self.uri,
+            Operation::Project { schema },
+            Some(read_version),
+            params.store_params,
+            self.commit_handler.clone(),
+        )
+        .await?;
+        self.dataset.set_latest(dataset).await?;
+        Ok(())
+    }
+
+    /// Add `metadata` to the metadata of the table, or of its `column`
+    pub(super) async fn update_metadata_impl(
+        &self,
+        column: Option<&str>,
+        metadata: HashMap<String, String>,
+    ) -> Result<()> {
+        validate_keys(&metadata)?;
+        let metadata = &metadata;
+        self.retry_on_conflict("update_metadata", move || async move {
+            let dataset = self.dataset.get().await?.clone();
+            let mut schema = dataset.schema().clone();
+            let target = match column {
+                Some(column) => {
+                    &mut schema
+                        .fields
+                        .iter_mut()
+                        .find(|field| field.name == column)
+                        .ok_or_else(|| Error::InvalidInput {
+                            message: format!("the column {} does not exist", column),
+                        })?
+                        .metadata
+                }
+                None => &mut schema.metadata,
+            };
+            target.extend(metadata.clone());
+            self.commit_schema(schema, dataset.version().version).await
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field};
+    use tempfile::tempdir;
+
+    use crate::connect;
+
+    fn batch() -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(arrow_schema::Schema::new(vec![Field::new(
+            "i",
+            DataType::Int32,
+            false,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..10))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_metadata() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("t", batch()).execute().await.unwrap();
+        let version = table.version().await.unwrap();
+
+        table
+            .update_metadata([("owner", "search-team"), ("description", "products")])
+            .await
+            .unwrap();
+        table
+            .update_field_metadata("i", [("model", "all-MiniLM-L6-v2")])
+            .await
+            .unwrap();
+        table.update_metadata([("owner", "ml-team")]).await.unwrap();
+        assert_eq!(table.version().await.unwrap(), version + 3);
+
+        // The metadata is persisted with the table
+        let table = db.open_table("t").execute().await.unwrap();
+        let metadata = table.metadata().await.unwrap();
+        assert_eq!(metadata["owner"], "ml-team");
+        assert_eq!(metadata["description"], "products");
+        assert_eq!(
+            table.field_metadata("i").await.unwrap()["model"],
+            "all-MiniLM-L6-v2"
+        );
+        // The metadata of the table is versioned
+        table.checkout(version + 1).await.unwrap();
+        assert_eq!(table.metadata().await.unwrap()["owner"], "search-team");
+        table.checkout_latest().await.unwrap();
+
+        assert!(table
+            .update_metadata([("lancedb::primary_key", "i")])
+            .await
+            .is_err());
+        assert!(table
+            .update_field_metadata("missing", [("a", "b")])
+            .await
+            .is_err());
+        assert!(table.field_metadata("missing").await.is_err());
+    }
+}
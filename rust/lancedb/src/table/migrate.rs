@@ -0,0 +1,495 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Changing the dimension of the vectors of a column
+//!
+//! Every stored vector is passed through a [`VectorProjector`], such as a
+//! [`Truncate`] of Matryoshka embeddings or a [`LinearProjection`] with a PCA
+//! matrix.  Like [`crate::Table::alter_column_types`], the projected vectors are
+//! added as a new column, the original column is dropped and the new column is
+//! renamed, so the column moves to the end of the schema.  The indices on the
+//! column are dropped before and rebuilt after.
+
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::AsArray, types::Float32Type, Array, FixedSizeListArray, Float32Array, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema};
+use lance::dataset::{BatchUDF, ColumnAlteration, NewColumnTransform};
+
+use super::{NativeTable, TableInternal};
+use crate::embeddings::{self, EMBEDDING_METADATA_KEY};
+use crate::error::{Error, Result};
+
+/// The suffix of the copy of a column while its vectors are projected
+const MIGRATE_SUFFIX: &str = "__lancedb_migrate";
+
+/// Maps a vector to a vector with a different dimension
+pub trait VectorProjector: std::fmt::Debug + Send + Sync {
+    /// Check that the projector maps vectors of `input_dim` to `output_dim`
+    ///
+    /// This is called once before any vector is projected.
+    fn check(&self, input_dim: usize, output_dim: usize) -> Result<()>;
+
+    /// Write the projection of `vector` to `output`
+    fn project(&self, vector: &[f32], output: &mut [f32]);
+}
+
+/// Keeps the first values of each vector, as done with Matryoshka embeddings
+#[derive(Debug, Clone)]
+pub struct Truncate {
+    normalize: bool,
+}
+
+impl Default for Truncate {
+    fn default() -> Self {
+        Self { normalize: true }
+    }
+}
+
+impl Truncate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the truncated vectors are scaled to a unit length, the default is true
+    ///
+    /// Matryoshka embeddings are normally normalized again after truncation.
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+}
+
+impl VectorProjector for Truncate {
+    fn check(&self, input_dim: usize, output_dim: usize) -> Result<()> {
+        if output_dim > input_dim {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "vectors of dimension {} cannot be truncated to dimension {}",
+                    input_dim, output_dim
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    fn project(&self, vector: &[f32], output: &mut [f32]) {
+        output.copy_from_slice(&vector[..output.len()]);
+        if self.normalize {
+            let norm = output.iter().map(|v| v * v).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                output.iter_mut().for_each(|v| *v /= norm);
+            }
+        }
+    }
+}
+
+/// Multiplies each vector by a matrix, for example the components of a PCA
+#[derive(Debug, Clone)]
+pub struct LinearProjection {
+    rows: Vec<Vec<f32>>,
+    mean: Option<Vec<f32>>,
+}
+
+impl LinearProjection {
+    /// Project with `rows`, one row per output dimension
+    ///
+    /// Output value `i` is the dot product of row `i` with the input vector.
+    pub fn new(rows: Vec<Vec<f32>>) -> Self {
+        Self { rows, mean: None }
+    }
+
+    /// Subtract `mean` from each vector before it is projected
+    ///
+    /// PCA components are computed on centered vectors, so the mean of the
+    /// training vectors is normally given as well.
+    pub fn mean(mut self, mean: Vec<f32>) -> Self {
+        self.mean = Some(mean);
+        self
+    }
+}
+
+impl VectorProjector for LinearProjection {
+    fn check(&self, input_dim: usize, output_dim: usize) -> Result<()> {
+        if self.rows.len() != output_dim {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "the projection has {} rows but the new dimension is {}",
+                    self.rows.len(),
+                    output_dim
+                ),
+            });
+        }
+        let mut lengths = self
+            .rows
+            .iter()
+            .map(Vec::len)
+            .chain(self.mean.as_ref().map(Vec::len));
+        if let Some(length) = lengths.find(|length| *length != input_dim) {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "the projection has a row of length {} but the vectors have dimension {}",
+                    length, input_dim
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    fn project(&self, vector: &[f32], output: &mut [f32]) {
+        let centered;
+        let vector = match &self.mean {
+            Some(mean) => {
+                centered = vector
+                    .iter()
+                    .zip(mean)
+                    .map(|(v, m)| v - m)
+                    .collect::<Vec<_>>();
+                centered.as_slice()
+            }
+            None => vector,
+        };
+        for (value, row) in output.iter_mut().zip(&self.rows) {
+            *value = row.iter().zip(vector).map(|(a, b)| a * b).sum();
+        }
+    }
+}
+
+/// A builder used to change the dimension of the vectors of a column
+///
+/// See [`super::Table::migrate_vector_dim`] for more context
+pub struct MigrateVectorDimBuilder {
+    parent: Arc<dyn TableInternal>,
+    pub(crate) column: String,
+    pub(crate) new_dim: usize,
+    pub(crate) projector: Arc<dyn VectorProjector>,
+    pub(crate) embedding_name: Option<String>,
+}
+
+impl MigrateVectorDimBuilder {
+    pub(super) fn new(
+        parent: Arc<dyn TableInternal>,
+        column: String,
+        new_dim: usize,
+        projector: Arc<dyn VectorProjector>,
+    ) -> Self {
+        Self {
+            parent,
+            column,
+            new_dim,
+            projector,
+            embedding_name: None,
+        }
+    }
+
+    /// The embedding function which computes the new vectors
+    ///
+    /// This is required if the column is computed by an embedding function (see
+    /// [`crate::embeddings`]), since the previous function computes vectors of
+    /// the previous dimension.  The function must be registered with the
+    /// connection and its vectors must have the new dimension.  The table
+    /// metadata is updated to use it for the rows added afterwards.
+    pub fn embedding_name(mut self, embedding_name: impl Into<String>) -> Self {
+        self.embedding_name = Some(embedding_name.into());
+        self
+    }
+
+    /// Project the vectors and rebuild the indices on the column
+    pub async fn execute(self) -> Result<()> {
+        self.parent.clone().migrate_vector_dim(self).await
+    }
+}
+
+/// The dimension of the vector type `data_type`
+fn vector_dim(data_type: &DataType) -> Option<(Arc<Field>, usize)> {
+    match data_type {
+        DataType::FixedSizeList(item, dim) if item.data_type().is_floating() => {
+            Some((item.clone(), *dim as usize))
+        }
+        _ => None,
+    }
+}
+
+impl NativeTable {
+    pub(super) async fn migrate_vector_dim_impl(
+        &self,
+        params: MigrateVectorDimBuilder,
+    ) -> Result<()> {
+        let column = params.column.as_str();
+        let schema = Schema::from(self.dataset.get().await?.schema());
+        let field = schema.field_with_name(column).map_err(|_| Error::Schema {
+            message: format!("The column {} does not exist", column),
+        })?;
+        let Some((item, dim)) = vector_dim(field.data_type()) else {
+            return Err(Error::Schema {
+                message: format!(
+                    "The column {} with the data type {} is not a vector column",
+                    column,
+                    field.data_type()
+                ),
+            });
+        };
+        if params.new_dim == 0 {
+            return Err(Error::InvalidInput {
+                message: "the new dimension must be greater than 0".to_string(),
+            });
+        }
+        params.projector.check(dim, params.new_dim)?;
+
+        let mut definitions = embeddings::definitions_from_metadata(schema.metadata())?;
+        let definition = definitions
+            .iter_mut()
+            .find(|definition| definition.dest_column() == column);
+        match (definition, &params.embedding_name) {
+            (Some(definition), Some(name)) => {
+                let function =
+                    self.embedding_registry()?
+                        .get(name)
+                        .ok_or_else(|| Error::InvalidInput {
+                            message: format!(
+                            "The embedding function '{}' is not registered with this connection",
+                            name
+                        ),
+                        })?;
+                if vector_dim(&function.dest_type()).map(|(_, dim)| dim) != Some(params.new_dim) {
+                    return Err(Error::InvalidInput {
+                        message: format!(
+                            "the embedding function {} computes {} and not vectors of dimension {}",
+                            name,
+                            function.dest_type(),
+                            params.new_dim
+                        ),
+                    });
+                }
+                definition.embedding_name = name.clone();
+            }
+            (Some(definition), None) => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "The column {} is computed by the embedding function {}, give the \
+                         function which computes vectors of dimension {} with embedding_name",
+                        column, definition.embedding_name, params.new_dim
+                    ),
+                })
+            }
+            (None, Some(name)) => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "The column {} is not computed by an embedding function, so it cannot \
+                         use the embedding function {}",
+                        column, name
+                    ),
+                })
+            }
+            (None, None) => {}
+        }
+
+        let indices = self
+            .list_indices()
+            .await?
+            .into_iter()
+            .filter(|index| index.columns.iter().any(|c| c == column))
+            .map(|index| index.name)
+            .collect::<Vec<_>>();
+        for index in &indices {
+            self.drop_index(index).await?;
+        }
+
+        let copy = format!("{}{}", column, MIGRATE_SUFFIX);
+        let output_schema = Arc::new(Schema::new(vec![Field::new(
+            &copy,
+            DataType::FixedSizeList(item.clone(), params.new_dim as i32),
+            field.is_nullable(),
+        )]));
+        let mapper_schema = output_schema.clone();
+        let source = column.to_string();
+        let new_dim = params.new_dim;
+        let projector = params.projector.clone();
+        let mapper = move |batch: &RecordBatch| -> lance::Result<RecordBatch> {
+            let vectors = batch[source.as_str()].as_fixed_size_list();
+            let values = arrow_cast::cast(vectors.values(), &DataType::Float32)?;
+            let values = values.as_primitive::<Float32Type>().values();
+            let mut projected = vec![0.0; vectors.len() * new_dim];
+            for (row, output) in projected.chunks_exact_mut(new_dim).enumerate() {
+                if vectors.is_valid(row) {
+                    let start = vectors.value_offset(row) as usize;
+                    projector.project(&values[start..start + dim], output);
+                }
+            }
+            let projected = arrow_cast::cast(&Float32Array::from(projected), item.data_type())?;
+            let vectors = FixedSizeListArray::try_new(
+                item.clone(),
+                new_dim as i32,
+                projected,
+                vectors.nulls().cloned(),
+            )?;
+            Ok(RecordBatch::try_new(
+                mapper_schema.clone(),
+                vec![Arc::new(vectors)],
+            )?)
+        };
+        self.add_columns(
+            NewColumnTransform::BatchUDF(BatchUDF {
+                mapper: Box::new(mapper),
+                output_schema,
+                result_checkpoint: None,
+            }),
+            Some(vec![column.to_string()]),
+        )
+        .await?;
+        self.drop_columns(&[column]).await?;
+        self.alter_columns(&[ColumnAlteration::new(copy).rename(column.to_string())])
+            .await?;
+
+        if params.embedding_name.is_some() {
+            let metadata = serde_json::to_string(&definitions).map_err(|e| Error::Runtime {
+                message: format!("Failed to serialize the embedding functions: {}", e),
+            })?;
+            self.replace_schema_metadata(EMBEDDING_METADATA_KEY, metadata)
+                .await?;
+        }
+
+        if !indices.is_empty() {
+            let schema = self.schema().await?;
+            self.create_auto_index(schema.field_with_name(column)?, true)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Set the schema metadata `key` to `value` in a new version
+    async fn replace_schema_metadata(&self, key: &str, value: String) -> Result<()> {
+        let dataset = self.dataset.get().await?.clone();
+        let mut schema = dataset.schema().clone();
+        schema.metadata.insert(key.to_string(), value);
+        self.commit_schema(schema, dataset.version().version).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, RecordBatchIterator, RecordBatchReader};
+    use futures::TryStreamExt;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+
+    fn items() -> impl RecordBatchReader + Send + 'static {
+        let item = Arc::new(Field::new("item", DataType::Float32, true));
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("vector", DataType::FixedSizeList(item.clone(), 4), true),
+        ]));
+        let values = Float32Array::from_iter_values((0..4).flat_map(|i| {
+            let i = i as f32;
+            [3.0 * i, 4.0 * i, 1.0, 1.0]
+        }));
+        let vectors = FixedSizeListArray::try_new(item, 4, Arc::new(values), None).unwrap();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..4)),
+                Arc::new(vectors),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    async fn vectors(table: &crate::Table) -> Vec<Vec<f32>> {
+        let batches = table
+            .query()
+            .select(crate::query::Select::columns(&["vector"]))
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        batches
+            .iter()
+            .flat_map(|batch| {
+                let vectors = batch["vector"].as_fixed_size_list().clone();
+                (0..vectors.len())
+                    .map(|row| {
+                        vectors
+                            .value(row)
+                            .as_primitive::<Float32Type>()
+                            .values()
+                            .to_vec()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_migrate_vector_dim() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("test", items()).execute().await.unwrap();
+
+        table
+            .migrate_vector_dim("vector", 2, Arc::new(Truncate::new()))
+            .execute()
+            .await
+            .unwrap();
+        let schema = table.schema().await.unwrap();
+        assert!(matches!(
+            schema.field_with_name("vector").unwrap().data_type(),
+            DataType::FixedSizeList(_, 2)
+        ));
+        assert_eq!(
+            vectors(&table).await[1..],
+            vec![vec![0.6, 0.8], vec![0.6, 0.8], vec![0.6, 0.8]]
+        );
+
+        // Swap the two values
+        let swap = LinearProjection::new(vec![vec![0.0, 1.0], vec![1.0, 0.0]]);
+        table
+            .migrate_vector_dim("vector", 2, Arc::new(swap))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(vectors(&table).await[1], vec![0.8, 0.6]);
+
+        let result = table
+            .migrate_vector_dim("vector", 3, Arc::new(Truncate::new()))
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+        let result = table
+            .migrate_vector_dim(
+                "vector",
+                1,
+                Arc::new(LinearProjection::new(vec![vec![1.0]])),
+            )
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+        let result = table
+            .migrate_vector_dim("id", 1, Arc::new(Truncate::new()))
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::Schema { .. })));
+        assert_eq!(table.count_rows(None).await.unwrap(), 4);
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Ordered scans, see [`crate::query::Query::order_by`]
+//!
+//! The rows are sorted as they are scanned.  With a limit only the top rows
+//! seen so far are kept, so "the 10 most recent rows" holds 10 rows in memory
+//! whatever the size of the table.  Without a limit all of the matching rows
+//! are held in memory and sorted.
+
+use arrow::compute::{concat_batches, lexsort_to_indices, SortColumn};
+use arrow_array::RecordBatch;
+use arrow_ord::sort::SortOptions;
+use futures::{stream, TryStreamExt};
+
+use super::NativeTable;
+use crate::arrow::{take_record_batch, SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+use crate::query::{Order, Query, QueryExecutionOptions, Select};
+
+/// The first `limit` rows of `batch` in the order `order_by`
+fn sort(
+    batch: &RecordBatch,
+    order_by: &[(String, Order)],
+    limit: Option<usize>,
+) -> Result<RecordBatch> {
+    let columns = order_by
+        .iter()
+        .map(|(column, order)| {
+            let values = batch.column_by_name(column).ok_or_else(|| Error::Schema {
+                message: format!("the results have no column {} to order by", column),
+            })?;
+            Ok(SortColumn {
+                values: values.clone(),
+                options: Some(SortOptions {
+                    descending: *order == Order::Desc,
+                    nulls_first: false,
+                }),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let indices = lexsort_to_indices(&columns, limit)?;
+    Ok(take_record_batch(batch, &indices)?)
+}
+
+impl NativeTable {
+    /// Scan the rows matching `query` and sort them
+    pub(super) async fn ordered_query(
+        &self,
+        query: &Query,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        if query.after.is_some() {
+            return Err(Error::InvalidInput {
+                message: "after cannot be combined with order_by, use offset instead".to_string(),
+            });
+        }
+
+        // The columns to order by are read even if they are not selected
+        let mut scan = query.clone();
+        scan.limit = None;
+        scan.offset = None;
+        let mut added = Vec::new();
+        for (column, _) in &query.order_by {
+            match &mut scan.select {
+                Select::All => {}
+                Select::Columns(columns) => {
+                    if !columns.contains(column) {
+                        columns.push(column.clone());
+                        added.push(column.clone());
+                    }
+                }
+                Select::Dynamic(columns) => {
+                    if !columns.iter().any(|(name, _)| name == column) {
+                        columns.push((column.clone(), column.clone()));
+                        added.push(column.clone());
+                    }
+                }
+            }
+        }
+        let mut stream: SendableRecordBatchStream = self
+            .generic_query(&scan.into_vector(), options)
+            .await?
+            .into();
+
+        let schema = stream.schema();
+        let offset = query.offset.unwrap_or(0);
+        let top = query.limit.map(|limit| offset + limit);
+        let sorted = if let Some(top) = top {
+            // Only the top rows so far are kept, so each concat is bounded
+            let mut sorted = RecordBatch::new_empty(schema.clone());
+            while let Some(batch) = stream.try_next().await? {
+                let batch = concat_batches(&schema, [&sorted, &batch])?;
+                sorted = sort(&batch, &query.order_by, Some(top))?;
+            }
+            sorted
+        } else {
+            let batches = stream.try_collect::<Vec<_>>().await?;
+            sort(&concat_batches(&schema, &batches)?, &query.order_by, None)?
+        };
+        let length = query
+            .limit
+            .unwrap_or(usize::MAX)
+            .min(sorted.num_rows().saturating_sub(offset));
+        let mut sorted = sorted.slice(offset.min(sorted.num_rows()), length);
+        for column in added {
+            let (index, _) = sorted.schema().column_with_name(&column).unwrap();
+            sorted.remove_column(index);
+        }
+        Ok(Box::pin(SimpleRecordBatchStream {
+            schema: sorted.schema(),
+            stream: stream::iter([Ok(sorted)]),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{
+        cast::AsArray, types::Int32Type, Int32Array, RecordBatchIterator, RecordBatchReader,
+        StringArray,
+    };
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+
+    // Lance reads back the nulls of primitive columns as zeros, so the dates are strings
+    fn batch(values: Vec<Option<&str>>) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("created_at", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..values.len() as i32)),
+                Arc::new(StringArray::from(values)),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_order_by() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db
+            .create_table(
+                "t",
+                batch(vec![
+                    Some("2024-05-01"),
+                    None,
+                    Some("2024-09-01"),
+                    Some("2024-01-01"),
+                    Some("2024-07-01"),
+                ]),
+            )
+            .execute()
+            .await
+            .unwrap();
+        let ids = |query: Query| async move {
+            let batches = query
+                .execute()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert!(batches
+                .iter()
+                .all(|b| b.column_by_name("created_at").is_none()));
+            batches
+                .iter()
+                .flat_map(|b| b["id"].as_primitive::<Int32Type>().values().to_vec())
+                .collect::<Vec<_>>()
+        };
+
+        // The most recent rows, the null last
+        let query = table
+            .query()
+            .select(Select::columns(&["id"]))
+            .order_by([("created_at", Order::Desc)]);
+        assert_eq!(ids(query.clone()).await, vec![2, 4, 0, 3, 1]);
+        assert_eq!(ids(query.clone().limit(2)).await, vec![2, 4]);
+        assert_eq!(ids(query.offset(1).limit(2)).await, vec![4, 0]);
+        assert_eq!(
+            ids(table
+                .query()
+                .select(Select::columns(&["id"]))
+                .only_if("id > 0")
+                .order_by([("created_at", Order::Asc)])
+                .limit(2))
+            .await,
+            vec![3, 4]
+        );
+    }
+}
@@ -0,0 +1,389 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Single file packages of a table
+//!
+//! A package is a gzip compressed tar archive holding one version of a table:
+//! its manifest, the data and deletion files of its fragments and the files of
+//! its indices.  The older versions of the table, and the files only they
+//! reference, are left out.  This makes a package a convenient way to
+//! distribute a prebuilt dataset, which is unpacked into a table of another
+//! database with [`crate::Connection::unpack`].
+//!
+//! The first entry of the archive, `lancepkg.json`, describes the package (see
+//! [`PackageInfo`]).  The other entries are the files of the table, with paths
+//! relative to the directory of the table.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::TryStreamExt;
+use lance::io::{ObjectStore, ObjectStoreParams};
+use lance::Dataset;
+use lance_index::DatasetIndexExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use super::NativeTable;
+use crate::error::{Error, Result};
+
+/// The version of the layout of the packages written by this version
+pub const PACKAGE_FORMAT: u32 = 1;
+/// The name of the entry describing the package
+const PACKAGE_HEADER: &str = "lancepkg.json";
+pub(crate) const VERSIONS_DIR: &str = "_versions";
+/// The latest manifest, read by the older versions of Lance
+pub(crate) const LATEST_MANIFEST: &str = "_latest.manifest";
+pub(super) const DATA_DIR: &str = "data";
+pub(super) const DELETIONS_DIR: &str = "_deletions";
+pub(super) const INDICES_DIR: &str = "_indices";
+/// The directory of the full text search index, which is not in the manifest
+const FTS_INDEX_DIR: &str = "fts";
+/// The number of entries read ahead of the writes when unpacking
+const UNPACK_BUFFER: usize = 4;
+
+/// The description of a package, stored in the package
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageInfo {
+    /// The layout of the package, see [`PACKAGE_FORMAT`]
+    pub format: u32,
+    /// The name of the packed table
+    pub table: String,
+    /// The packed version of the table
+    pub version: u64,
+    /// The number of files of the table in the package
+    pub files: usize,
+    /// The total size of the files, before compression
+    pub bytes: u64,
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::io(
+        format!("Failed to access the package {}", path.display()),
+        e,
+    )
+}
+
+fn invalid_package(path: &Path, reason: impl std::fmt::Display) -> Error {
+    Error::InvalidInput {
+        message: format!("{} is not a valid package: {}", path.display(), reason),
+    }
+}
+
+/// Append the file `name` with `data` to `archive`
+fn append<W: std::io::Write>(
+    archive: &mut tar::Builder<W>,
+    name: &str,
+    data: &[u8],
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, name, data)
+}
+
+/// Check that the entry `name` of a package stays in the directory of the table
+fn check_entry(path: &Path, name: &str) -> Result<()> {
+    let safe = Path::new(name)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if safe {
+        Ok(())
+    } else {
+        Err(invalid_package(
+            path,
+            format!("the entry {} is outside of the table", name),
+        ))
+    }
+}
+
+/// Read the entries of the package at `path`, sending them to `entries`
+///
+/// Stops early if the receiver is dropped.
+fn read_package(path: &Path, entries: mpsc::Sender<Result<(String, Vec<u8>)>>) {
+    let read = || -> Result<()> {
+        let file = File::open(path).map_err(|e| io_error(path, e))?;
+        let mut archive = tar::Archive::new(GzDecoder::new(file));
+        for entry in archive.entries().map_err(|e| io_error(path, e))? {
+            let mut entry = entry.map_err(|e| io_error(path, e))?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry
+                .path()
+                .map_err(|e| io_error(path, e))?
+                .to_string_lossy()
+                .to_string();
+            let mut data = Vec::with_capacity(entry.size() as usize);
+            entry
+                .read_to_end(&mut data)
+                .map_err(|e| io_error(path, e))?;
+            if entries.blocking_send(Ok((name, data))).is_err() {
+                return Ok(());
+            }
+        }
+        Ok(())
+    };
+    if let Err(e) = read() {
+        let _ = entries.blocking_send(Err(e));
+    }
+}
+
+/// Unpack the package at `path`, calling `write` with the path and the data of
+/// each file of the table
+pub(crate) async fn unpack<F, Fut>(path: &Path, mut write: F) -> Result<PackageInfo>
+where
+    F: FnMut(String, Vec<u8>) -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    let (sender, mut entries) = mpsc::channel(UNPACK_BUFFER);
+    let reader = {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || read_package(&path, sender))
+    };
+
+    let info = match entries.recv().await.transpose()? {
+        Some((name, data)) if name == PACKAGE_HEADER => {
+            serde_json::from_slice::<PackageInfo>(&data).map_err(|e| invalid_package(path, e))?
+        }
+        _ => {
+            return Err(invalid_package(
+                path,
+                format!("{} is missing", PACKAGE_HEADER),
+            ))
+        }
+    };
+    if info.format > PACKAGE_FORMAT {
+        return Err(Error::NotSupported {
+            message: format!(
+                "the package {} has format {}, this version of LanceDB reads up to format {}",
+                path.display(),
+                info.format,
+                PACKAGE_FORMAT
+            ),
+        });
+    }
+
+    let mut files = 0;
+    while let Some(entry) = entries.recv().await {
+        let (name, data) = entry?;
+        check_entry(path, &name)?;
+        write(name, data).await?;
+        files += 1;
+    }
+    reader.await.map_err(|e| Error::Runtime {
+        message: format!("Failed to read the package {}: {}", path.display(), e),
+    })?;
+    if files != info.files {
+        return Err(invalid_package(
+            path,
+            format!("it has {} files but {} were expected", files, info.files),
+        ));
+    }
+    Ok(info)
+}
+
+/// The version of `dataset`, stored in `dir` of `store`, and its files relative to
+/// `dir`, starting with its manifest
+pub(super) async fn version_files(
+    dataset: &Dataset,
+    store: &ObjectStore,
+    dir: &object_store::path::Path,
+) -> Result<(u64, Vec<String>)> {
+    let version = dataset.version().version;
+    let mut files = vec![format!("{}/{}.manifest", VERSIONS_DIR, version)];
+    let mut deletions = Vec::new();
+    for fragment in dataset.get_fragments() {
+        let metadata = fragment.metadata();
+        for file in &metadata.files {
+            files.push(format!("{}/{}", DATA_DIR, file.path));
+        }
+        if let Some(file) = &metadata.deletion_file {
+            deletions.push(format!(
+                "{}/{}-{}-{}.",
+                DELETIONS_DIR, metadata.id, file.read_version, file.id
+            ));
+        }
+    }
+
+    // The names of the deletion files and of the files of the indices are
+    // not in the manifest, they are found by listing their directories
+    let mut dirs = vec![
+        DELETIONS_DIR.to_string(),
+        format!("{}/{}", INDICES_DIR, FTS_INDEX_DIR),
+    ];
+    for index in dataset.load_indices().await?.iter() {
+        dirs.push(format!("{}/{}", INDICES_DIR, index.uuid));
+    }
+    for sub_dir in dirs {
+        // Not dir.child(), which would escape the / of the nested directories
+        let prefix = dir
+            .parts()
+            .chain(object_store::path::Path::from(sub_dir.as_str()).parts())
+            .collect::<object_store::path::Path>();
+        let listed = store
+            .inner
+            .list(Some(&prefix))
+            .try_collect::<Vec<_>>()
+            .await?;
+        for meta in listed {
+            let relative = meta
+                .location
+                .prefix_match(dir)
+                .expect("listed files are under the table directory")
+                .map(|part| part.as_ref().to_string())
+                .collect::<Vec<_>>()
+                .join("/");
+            if sub_dir != DELETIONS_DIR
+                || deletions.iter().any(|prefix| relative.starts_with(prefix))
+            {
+                files.push(relative);
+            }
+        }
+    }
+    Ok((version, files))
+}
+
+impl NativeTable {
+    pub(super) async fn pack_impl(&self, path: &Path) -> Result<PackageInfo> {
+        let params = ObjectStoreParams {
+            storage_options: Some(self.storage_options.clone()),
+            ..Default::default()
+        };
+        let (store, dir) = ObjectStore::from_uri_and_params(&self.uri, &params).await?;
+        let dataset = self.dataset.get().await?.clone();
+        let (version, files) = version_files(&dataset, &store, &dir).await?;
+        let locations = files
+            .iter()
+            .map(|file| {
+                dir.parts()
+                    .chain(object_store::path::Path::from(file.as_str()).parts())
+                    .collect::<object_store::path::Path>()
+            })
+            .collect::<Vec<_>>();
+        let mut info = PackageInfo {
+            format: PACKAGE_FORMAT,
+            table: self.name.clone(),
+            version,
+            // The manifest is packed a second time as the latest manifest
+            files: files.len() + 1,
+            bytes: 0,
+        };
+        let manifest = store.inner.get(&locations[0]).await?.bytes().await?;
+        info.bytes += manifest.len() as u64;
+        for location in &locations[1..] {
+            info.bytes += store.inner.head(location).await?.size as u64;
+        }
+
+        // The files are read one at a time, so the table does not need to fit
+        // in memory
+        let output = File::create(path).map_err(|e| io_error(path, e))?;
+        let mut archive = tar::Builder::new(GzEncoder::new(output, Compression::default()));
+        let header = serde_json::to_vec_pretty(&info).map_err(|e| Error::Runtime {
+            message: format!("Failed to describe the package: {}", e),
+        })?;
+        append(&mut archive, PACKAGE_HEADER, &header).map_err(|e| io_error(path, e))?;
+        append(&mut archive, &files[0], &manifest).map_err(|e| io_error(path, e))?;
+        for (file, location) in files.iter().zip(&locations).skip(1) {
+            let data = store.inner.get(location).await?.bytes().await?;
+            append(&mut archive, file, &data).map_err(|e| io_error(path, e))?;
+        }
+        append(&mut archive, LATEST_MANIFEST, &manifest).map_err(|e| io_error(path, e))?;
+        archive
+            .into_inner()
+            .and_then(|encoder| encoder.finish())
+            .map_err(|e| io_error(path, e))?;
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::index::{scalar::BTreeIndexBuilder, Index};
+
+    fn batches(start: i32) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(start..start + 10))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_pack_and_unpack() {
+        let tmp_dir = tempdir().unwrap();
+        let source = connect(tmp_dir.path().join("source").to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = source
+            .create_table("test", batches(0))
+            .execute()
+            .await
+            .unwrap();
+        table.add(batches(10)).execute().await.unwrap();
+        table.delete("i < 5").await.unwrap();
+        table
+            .create_index(&["i"], Index::BTree(BTreeIndexBuilder::default()))
+            .execute()
+            .await
+            .unwrap();
+
+        let package = tmp_dir.path().join("test.lancepkg");
+        let info = table.pack(&package).await.unwrap();
+        assert_eq!(info.table, "test");
+        assert_eq!(info.version, table.version().await.unwrap());
+
+        let target = connect(tmp_dir.path().join("target").to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let unpacked = target.unpack(&package, "copy").await.unwrap();
+        assert_eq!(unpacked.count_rows(None).await.unwrap(), 15);
+        assert_eq!(unpacked.list_versions().await.unwrap().len(), 1);
+        assert_eq!(unpacked.list_indices().await.unwrap().len(), 1);
+        let rows = unpacked
+            .count_rows(Some("i >= 15".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(rows, 5);
+        // The unpacked table can be written
+        unpacked.add(batches(20)).execute().await.unwrap();
+        assert_eq!(unpacked.count_rows(None).await.unwrap(), 25);
+
+        let result = target.unpack(&package, "copy").await;
+        assert!(matches!(result, Err(Error::TableAlreadyExists { .. })));
+
+        // A file that is not a package leaves no table behind
+        let invalid = tmp_dir.path().join("invalid.lancepkg");
+        std::fs::write(&invalid, b"not a package").unwrap();
+        assert!(target.unpack(&invalid, "invalid").await.is_err());
+        assert_eq!(target.table_names().execute().await.unwrap(), vec!["copy"]);
+    }
+}
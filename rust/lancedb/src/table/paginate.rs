@@ -0,0 +1,240 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Paging through the rows of a table
+//!
+//! A scan returns the rows in the order of their row ids.  With
+//! [`crate::query::Query::offset`] a page starts after skipping rows, which
+//! still reads all of the skipped rows.  With [`crate::query::Query::after`] a
+//! page starts after the row id of the last row of the previous page: the
+//! fragments before that row are not read, so every page costs the same.
+//!
+//! ```ignore
+//! let mut after = None;
+//! loop {
+//!     let mut query = table.query().with_row_id().limit(100);
+//!     if let Some(row_id) = after {
+//!         query = query.after(row_id);
+//!     }
+//!     let page = query.execute().await?.try_collect::<Vec<_>>().await?;
+//!     // ... the last row id of the page, or stop if the page is empty
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use arrow::compute::filter_record_batch;
+use arrow_array::{cast::AsArray, types::UInt64Type, BooleanArray, RecordBatch};
+use arrow_schema::Schema;
+use futures::{StreamExt, TryStreamExt};
+
+use super::NativeTable;
+use crate::arrow::{SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+use crate::query::{Query, QueryExecutionOptions, VectorQuery};
+
+const ROW_ID_COLUMN: &str = "_rowid";
+
+/// Keep the rows of `batch` after the row id `after`, and drop the row id
+/// column unless `with_row_id`
+fn rows_after(batch: RecordBatch, after: u64, with_row_id: bool) -> Result<RecordBatch> {
+    let keep = batch[ROW_ID_COLUMN]
+        .as_primitive::<UInt64Type>()
+        .values()
+        .iter()
+        .map(|row_id| Some(*row_id > after))
+        .collect::<BooleanArray>();
+    let mut batch = filter_record_batch(&batch, &keep)?;
+    if !with_row_id {
+        let (index, _) = batch.schema().column_with_name(ROW_ID_COLUMN).unwrap();
+        batch.remove_column(index);
+    }
+    Ok(batch)
+}
+
+impl NativeTable {
+    /// Fail if a vector search is paginated, ordered or sampled, only scans can be
+    pub(super) fn validate_pagination(query: &VectorQuery) -> Result<()> {
+        if query.base.offset.is_some()
+            || query.base.after.is_some()
+            || !query.base.order_by.is_empty()
+            || query.base.sample.is_some()
+        {
+            return Err(Error::InvalidInput {
+                message: "offset, after, order_by and sample only apply to scans, not to searches"
+                    .to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Scan the rows after the row id `query.after`
+    pub(super) async fn query_after(
+        &self,
+        query: &Query,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        let after = query.after.expect("the query has a row id to start after");
+        if query.offset.is_some() || query.only_deleted {
+            return Err(Error::InvalidInput {
+                message: "after cannot be combined with offset or only_deleted".to_string(),
+            });
+        }
+        // The fragment of a row is the upper half of its row id
+        let first_fragment = after >> 32;
+        let fragments = self
+            .dataset
+            .get()
+            .await?
+            .get_fragments()
+            .iter()
+            .filter(|fragment| fragment.id() as u64 >= first_fragment)
+            .map(|fragment| fragment.metadata().clone())
+            .collect::<Vec<_>>();
+
+        // The limit is applied once the rows up to `after` are dropped
+        let mut scan = query.clone();
+        scan.after = None;
+        scan.limit = None;
+        scan.with_row_id = true;
+        let mut scanner = self.create_scanner(&scan.into_vector(), options).await?;
+        scanner.with_fragments(fragments);
+        let stream: SendableRecordBatchStream = scanner.try_into_stream().await?.into();
+
+        let with_row_id = query.with_row_id;
+        let mut schema = stream.schema();
+        if !with_row_id {
+            let fields = schema
+                .fields()
+                .iter()
+                .filter(|field| field.name() != ROW_ID_COLUMN)
+                .cloned()
+                .collect::<Vec<_>>();
+            schema = Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()));
+        }
+        let stream = stream
+            .and_then(move |batch| std::future::ready(rows_after(batch, after, with_row_id)))
+            .scan(query.limit.unwrap_or(usize::MAX), |remaining, batch| {
+                if *remaining == 0 {
+                    return std::future::ready(None);
+                }
+                std::future::ready(Some(batch.map(|batch| {
+                    let num_rows = batch.num_rows().min(*remaining);
+                    *remaining -= num_rows;
+                    batch.slice(0, num_rows)
+                })))
+            });
+        Ok(Box::pin(SimpleRecordBatchStream { schema, stream }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{types::Int32Type, Int32Array, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+
+    fn batch(start: i32) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(start..start + 10))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    async fn page(query: Query) -> (Vec<i32>, Option<u64>) {
+        let batches = query
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let values = batches
+            .iter()
+            .flat_map(|batch| batch["i"].as_primitive::<Int32Type>().values().to_vec())
+            .collect();
+        let last = batches
+            .iter()
+            .rev()
+            .find(|batch| batch.num_rows() > 0)
+            .and_then(|batch| batch.column_by_name(ROW_ID_COLUMN))
+            .map(|row_ids| {
+                let row_ids = row_ids.as_primitive::<UInt64Type>();
+                row_ids.value(row_ids.len() - 1)
+            });
+        (values, last)
+    }
+
+    #[tokio::test]
+    async fn test_pagination() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("t", batch(0)).execute().await.unwrap();
+        table.add(batch(10)).execute().await.unwrap();
+
+        let (values, _) = page(table.query().offset(8).limit(4)).await;
+        assert_eq!(values, vec![8, 9, 10, 11]);
+
+        // Keyset pagination across the two fragments
+        let mut seen = Vec::new();
+        let mut after = None;
+        loop {
+            let mut query = table.query().only_if("i % 2 = 0").with_row_id().limit(3);
+            if let Some(row_id) = after {
+                query = query.after(row_id);
+            }
+            let (values, last) = page(query).await;
+            if values.is_empty() {
+                break;
+            }
+            seen.extend(values);
+            after = last;
+        }
+        assert_eq!(seen, (0..20).step_by(2).collect::<Vec<_>>());
+
+        // The row ids are only returned if requested
+        let batches = table
+            .query()
+            .after(3)
+            .limit(2)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert!(batches[0].column_by_name(ROW_ID_COLUMN).is_none());
+
+        assert!(matches!(
+            table
+                .query()
+                .offset(1)
+                .nearest_to(&[1.0])
+                .unwrap()
+                .execute()
+                .await,
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+}
@@ -0,0 +1,557 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Grouping the rows of a table into fragments by key
+//!
+//! A table can be partitioned by a column when it is created, see
+//! [`crate::connection::CreateTableBuilder::partition_by`], and a single add
+//! can be partitioned with [`super::AddDataBuilder::partition_by`].  The rows
+//! appended to the table are then grouped by their value in the column, e.g. a
+//! tenant id or a day, and each fragment only holds the rows of one key.
+//!
+//! Scans filtered on the key read fewer fragments (see
+//! [`super::fragment_stats`] for the pruning of date columns).  In a table
+//! partitioned when it is created, deleting all of the rows of some keys, with
+//! a filter `column = value` or `column IN (values)`, removes their fragments
+//! from the table without reading them or writing deletion files.  The key of each fragment is recorded in a hidden
+//! dataset in the table's directory when the fragment is written.
+//!
+//! The data of a partitioned add is held in memory while it is grouped, and
+//! the keys should be few enough that each one has a good number of rows: a
+//! fragment is written for each key of each add.  Overwrites are not
+//! partitioned.  Tables partitioned when they are created are not compacted
+//! automatically (see [`crate::connection::ConnectBuilder::auto_compaction`])
+//! since compacting merges the fragments of different keys.  Deletes still
+//! apply to merged fragments, as to those of other tables, by deleting rows.
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use arrow::compute::concat_batches;
+use arrow::row::{RowConverter, SortField};
+use arrow_array::{
+    cast::AsArray,
+    types::{Date32Type, Int64Type, UInt64Type},
+    Array, RecordBatch, RecordBatchIterator, RecordBatchReader, StringArray, UInt32Array,
+    UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use futures::{StreamExt, TryStreamExt};
+use lance::dataset::fragment::FileFragment;
+use lance::dataset::transaction::Operation;
+use lance::dataset::{Dataset, WriteMode, WriteParams};
+use lance_table::format::Fragment;
+
+use super::bulk_ingest::BulkIngestOptions;
+use super::stats::AddedStats;
+use super::{validate, DeleteResult, NativeTable, SchemaPolicy, TableInternal};
+use crate::arrow::take_record_batch;
+use crate::error::{Error, Result};
+use crate::metrics;
+use crate::query::filter::{Filter, FilterValue};
+
+/// The schema metadata key which records the partition column of a table
+pub(crate) const PARTITION_BY_METADATA_KEY: &str = "lancedb::partition_by";
+const PARTITIONS_DIR: &str = "_partitions";
+
+/// The partition column of the table with the schema `schema`, if it has one
+pub(crate) fn partition_column(schema: &Schema) -> Option<String> {
+    schema.metadata().get(PARTITION_BY_METADATA_KEY).cloned()
+}
+
+fn check_partition_column(schema: &Schema, column: &str) -> Result<DataType> {
+    let field = schema
+        .field_with_name(column)
+        .map_err(|_| Error::InvalidInput {
+            message: format!("the partition column '{}' is not in the data", column),
+        })?;
+    let data_type = field.data_type();
+    if !(data_type.is_integer()
+        || matches!(
+            data_type,
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Date32
+        ))
+    {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "the partition column '{}' must be an integer, string or date column, not {}",
+                column, data_type
+            ),
+        });
+    }
+    Ok(data_type.clone())
+}
+
+/// Record `column` as the partition column of the table created from `data`
+pub(crate) fn with_partition_by(
+    data: Box<dyn RecordBatchReader + Send>,
+    column: &str,
+) -> Result<Box<dyn RecordBatchReader + Send>> {
+    let schema = data.schema();
+    check_partition_column(&schema, column)?;
+    let mut metadata = schema.metadata().clone();
+    metadata.insert(PARTITION_BY_METADATA_KEY.to_string(), column.to_string());
+    let schema = Arc::new(Schema::new_with_metadata(schema.fields().clone(), metadata));
+    let batch_schema = schema.clone();
+    let batches =
+        data.map(move |batch| batch.and_then(|batch| batch.with_schema(batch_schema.clone())));
+    Ok(Box::new(RecordBatchIterator::new(batches, schema)))
+}
+
+/// The key of the row `row` of `array` as SQL, as it is recorded
+fn key_sql(array: &dyn Array, row: usize) -> Result<String> {
+    if array.is_null(row) {
+        return Ok(FilterValue::Null.to_sql());
+    }
+    let value = arrow::compute::cast(&array.slice(row, 1), &normalized_type(array.data_type()))?;
+    Ok(match value.data_type() {
+        DataType::Int64 => FilterValue::Int(value.as_primitive::<Int64Type>().value(0)),
+        DataType::Date32 => match value.as_primitive::<Date32Type>().value_as_date(0) {
+            Some(date) => FilterValue::Date(date),
+            None => FilterValue::Null,
+        },
+        _ => FilterValue::String(value.as_string::<i32>().value(0).to_string()),
+    }
+    .to_sql())
+}
+
+fn normalized_type(data_type: &DataType) -> DataType {
+    match data_type {
+        data_type if data_type.is_integer() => DataType::Int64,
+        DataType::Date32 => DataType::Date32,
+        _ => DataType::Utf8,
+    }
+}
+
+/// The key a value of a filter matches, as it is recorded, in a column of type
+/// `data_type`
+///
+/// Returns None if this is not known, e.g. for a float compared to an integer
+/// column.
+fn filter_key_sql(value: &FilterValue, data_type: &DataType) -> Option<String> {
+    let value = match (value, normalized_type(data_type)) {
+        (FilterValue::Int(_), DataType::Int64)
+        | (FilterValue::String(_), DataType::Utf8)
+        | (FilterValue::Date(_), DataType::Date32) => value.clone(),
+        (FilterValue::Float(value), DataType::Int64)
+            if value.fract() == 0.0 && value.abs() < i64::MAX as f64 =>
+        {
+            FilterValue::Int(*value as i64)
+        }
+        _ => return None,
+    };
+    Some(value.to_sql())
+}
+
+/// The data file identifying a fragment, a fragment id can be reused once an
+/// older version is restored
+pub(super) fn data_file(fragment: &Fragment) -> Option<&str> {
+    fragment.files.first().map(|file| file.path.as_str())
+}
+
+fn partitions_schema() -> Arc<Schema> {
+    Arc::new(Schema::new(vec![
+        Field::new("fragment_id", DataType::UInt64, false),
+        Field::new("data_file", DataType::Utf8, false),
+        Field::new("key", DataType::Utf8, false),
+    ]))
+}
+
+/// The rows of `batch` grouped by their value in `column`, in the order the
+/// keys first appear
+fn group_by_key(batch: &RecordBatch, column: &str) -> Result<Vec<(String, RecordBatch)>> {
+    let keys = batch[column].clone();
+    let converter = RowConverter::new(vec![SortField::new(keys.data_type().clone())])?;
+    let rows = converter.convert_columns(std::slice::from_ref(&keys))?;
+    let mut groups = Vec::<(usize, Vec<u32>)>::new();
+    let mut positions = HashMap::new();
+    for row in 0..batch.num_rows() {
+        let position = *positions.entry(rows.row(row)).or_insert_with(|| {
+            groups.push((row, Vec::new()));
+            groups.len() - 1
+        });
+        groups[position].1.push(row as u32);
+    }
+    groups
+        .into_iter()
+        .map(|(first, indices)| {
+            let rows = take_record_batch(batch, &UInt32Array::from(indices))?;
+            Ok((key_sql(&keys, first)?, rows))
+        })
+        .collect()
+}
+
+impl NativeTable {
+    fn partitions_uri(&self) -> String {
+        format!("{}/{}", self.uri.trim_end_matches('/'), PARTITIONS_DIR)
+    }
+
+    /// The recorded keys of the fragments, by fragment id with their data file
+    async fn load_partitions(&self) -> Result<HashMap<u64, (String, String)>> {
+        let Some(dataset) = self.open_sidecar(&self.partitions_uri()).await? else {
+            return Ok(HashMap::new());
+        };
+        let batches = dataset
+            .scan()
+            .try_into_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        let mut partitions = HashMap::new();
+        for batch in batches {
+            let fragment_ids = batch["fragment_id"].as_primitive::<UInt64Type>();
+            let data_files = batch["data_file"].as_string::<i32>();
+            let keys = batch["key"].as_string::<i32>();
+            for row in 0..batch.num_rows() {
+                partitions.insert(
+                    fragment_ids.value(row),
+                    (
+                        data_files.value(row).to_string(),
+                        keys.value(row).to_string(),
+                    ),
+                );
+            }
+        }
+        Ok(partitions)
+    }
+
+    /// Record the keys of new fragments
+    async fn record_partitions(&self, fragments: &[(&Fragment, String)]) -> Result<()> {
+        let fragments = fragments
+            .iter()
+            .filter_map(|(fragment, key)| Some((fragment.id, data_file(fragment)?, key.as_str())))
+            .collect::<Vec<_>>();
+        let schema = partitions_schema();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(UInt64Array::from_iter_values(
+                    fragments.iter().map(|(id, _, _)| *id),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    fragments.iter().map(|(_, file, _)| *file),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    fragments.iter().map(|(_, _, key)| *key),
+                )),
+            ],
+        )?;
+        let mode = if self.open_sidecar(&self.partitions_uri()).await?.is_some() {
+            WriteMode::Append
+        } else {
+            WriteMode::Create
+        };
+        Dataset::write(
+            RecordBatchIterator::new(vec![Ok(batch)], schema),
+            &self.partitions_uri(),
+            Some(self.sidecar_write_params(mode)?),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Write the rows of `data` to new fragments, a fragment only holding the
+    /// rows of one key of `column`
+    ///
+    /// Returns the fragments, to be committed, with their keys.
+    async fn write_partitions(
+        &self,
+        data: Box<dyn RecordBatchReader + Send>,
+        column: &str,
+        params: &WriteParams,
+    ) -> Result<(Vec<Fragment>, Vec<String>)> {
+        let schema: SchemaRef = data.schema();
+        let batches = data.collect::<std::result::Result<Vec<_>, _>>()?;
+        let batch = concat_batches(&schema, &batches)?;
+        drop(batches);
+        let max_rows = params.max_rows_per_file.max(1);
+        let (keys, chunks): (Vec<_>, Vec<_>) = group_by_key(&batch, column)?
+            .into_iter()
+            .flat_map(|(key, rows)| {
+                (0..rows.num_rows())
+                    .step_by(max_rows)
+                    .map(|offset| {
+                        let length = max_rows.min(rows.num_rows() - offset);
+                        (key.clone(), rows.slice(offset, length))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unzip();
+        let fragments = futures::stream::iter(chunks)
+            .map(|rows| {
+                let uri = self.uri.clone();
+                let schema = schema.clone();
+                let params = params.clone();
+                async move {
+                    let reader = RecordBatchIterator::new(vec![Ok(rows)], schema);
+                    // The fragments are renumbered when they are committed
+                    tokio::spawn(async move {
+                        FileFragment::create(&uri, 0, reader, Some(params)).await
+                    })
+                    .await
+                    .map_err(|e| Error::Runtime {
+                        message: format!("failed to write a fragment: {}", e),
+                    })?
+                    .map_err(Error::from)
+                }
+            })
+            .buffered(BulkIngestOptions::default().parallelism.max(1))
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok((fragments, keys))
+    }
+
+    /// Append `data` to the table with a fragment for each of its keys in
+    /// `column`
+    ///
+    /// Returns the new version, with the statistics and the number of rows
+    /// added, as the retried writes of [`super::TableInternal::add`].
+    pub(super) async fn partitioned_add(
+        &self,
+        data: Box<dyn RecordBatchReader + Send>,
+        column: &str,
+        schema_policy: SchemaPolicy,
+        params: WriteParams,
+    ) -> Result<(u64, Option<AddedStats>, Arc<AtomicU64>)> {
+        let dataset = self.dataset.get().await?.clone();
+        let read_version = dataset.version().version;
+        let data = self.with_embeddings(data).await?;
+        let data = self.with_schema_policy(data, schema_policy).await?;
+        check_partition_column(&data.schema(), column)?;
+        let (data, vector_check) = validate::check_vectors(&*self.schema().await?, data)?;
+        let (data, rows_written) = metrics::count_rows(data);
+        let (data, added_stats) = self.track_stats(data).await?;
+        let (data, quota_write) = self.start_quota_write(data, false)?;
+
+        let (fragments, keys) = match self.write_partitions(data, column, &params).await {
+            Ok((fragments, keys)) => (Ok(fragments), keys),
+            Err(e) => (Err(e), Vec::new()),
+        };
+        let result = match fragments {
+            Ok(fragments) if fragments.is_empty() => Ok(dataset),
+            Ok(fragments) => Dataset::commit(
+                &self.uri,
+                Operation::Append { fragments },
+                Some(read_version),
+                params.store_params.clone(),
+                params.commit_handler.clone(),
+            )
+            .await
+            .map_err(Error::from),
+            Err(e) => Err(e),
+        };
+        let dataset = vector_check.finish(result);
+        let dataset = self.finish_quota_write(quota_write, dataset)?;
+        let version = dataset.version().version;
+        // The appended fragments are the last ones, in the order they were given
+        let fragments = dataset.manifest().fragments.clone();
+        let new_fragments = fragments.len().saturating_sub(keys.len());
+        let recorded = fragments[new_fragments..]
+            .iter()
+            .zip(keys)
+            .collect::<Vec<_>>();
+        if !recorded.is_empty() {
+            if let Err(e) = self.record_partitions(&recorded).await {
+                // The rows of these fragments are deleted row by row
+                log::warn!("Failed to record the partitions of {}: {}", self.name, e);
+            }
+        }
+        self.dataset.set_latest(dataset).await?;
+        Ok((version, added_stats, rows_written))
+    }
+
+    /// Delete the rows matching `predicate` by removing whole fragments
+    ///
+    /// Returns None, without deleting anything, unless the filter selects keys
+    /// of the partition column and the key of every fragment is known.
+    pub(super) async fn delete_partitions(
+        &self,
+        filter: &Filter,
+        predicate: &str,
+    ) -> Result<Option<DeleteResult>> {
+        let schema = self.schema().await?;
+        let Some(column) = partition_column(&schema) else {
+            return Ok(None);
+        };
+        let data_type = schema.field_with_name(&column)?.data_type().clone();
+        let Some(values) = filter.equality_values(&column) else {
+            return Ok(None);
+        };
+        let Some(keys) = values
+            .iter()
+            .map(|value| filter_key_sql(value, &data_type))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(None);
+        };
+        let partitions = self.load_partitions().await?;
+        let dataset = self.dataset.get().await?.clone();
+        let mut removed = Vec::new();
+        let mut num_deleted_rows = 0;
+        for fragment in dataset.get_fragments() {
+            let metadata = fragment.metadata();
+            let Some((file, key)) = partitions.get(&metadata.id) else {
+                return Ok(None);
+            };
+            if data_file(metadata) != Some(file.as_str()) {
+                return Ok(None);
+            }
+            if keys.contains(key) {
+                removed.push(metadata.id);
+                num_deleted_rows += fragment.count_rows().await? as u64;
+            }
+        }
+        if removed.is_empty() {
+            return Ok(Some(DeleteResult {
+                num_deleted_rows: 0,
+                version: dataset.version().version,
+            }));
+        }
+        let params = self.sidecar_write_params(WriteMode::Append)?;
+        let dataset = Dataset::commit(
+            &self.uri,
+            Operation::Delete {
+                updated_fragments: Vec::new(),
+                deleted_fragment_ids: removed,
+                predicate: predicate.to_string(),
+            },
+            Some(dataset.version().version),
+            params.store_params,
+            self.commit_handler.clone(),
+        )
+        .await?;
+        let version = dataset.version().version;
+        self.dataset.set_latest(dataset).await?;
+        Ok(Some(DeleteResult {
+            num_deleted_rows,
+            version,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, StringArray};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    fn batch(schema: &SchemaRef, tenants: &[&str]) -> RecordBatch {
+        RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(tenants.to_vec())),
+                Arc::new(Int32Array::from_iter_values(0..tenants.len() as i32)),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_partition_by() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("tenant", DataType::Utf8, false),
+            Field::new("value", DataType::Int32, false),
+        ]));
+        let table = db
+            .create_table(
+                "events",
+                RecordBatchIterator::new(
+                    vec![Ok(batch(&schema, &["a", "b", "a", "c", "b", "a"]))],
+                    schema.clone(),
+                ),
+            )
+            .partition_by("tenant")
+            .execute()
+            .await
+            .unwrap();
+        table
+            .add(RecordBatchIterator::new(
+                vec![Ok(batch(&schema, &["c", "a"]))],
+                schema.clone(),
+            ))
+            .execute()
+            .await
+            .unwrap();
+        let native = table.as_native().unwrap();
+        let dataset = native.dataset.get().await.unwrap().clone();
+        // One fragment for each key of each add
+        assert_eq!(dataset.get_fragments().len(), 5);
+        for fragment in dataset.get_fragments() {
+            let mut scanner = dataset.scan();
+            scanner.with_fragments(vec![fragment.metadata().clone()]);
+            scanner.project(&["tenant"]).unwrap();
+            let batch = scanner.try_into_batch().await.unwrap();
+            let tenants = batch["tenant"].as_string::<i32>();
+            assert!(tenants
+                .iter()
+                .all(|tenant| tenant == tenants.iter().next().unwrap()));
+        }
+
+        // Deleting a tenant drops its fragments
+        let result = table.delete("tenant IN ('a', 'z')").await.unwrap();
+        assert_eq!(result.num_deleted_rows, 4);
+        let dataset = native.dataset.get().await.unwrap().clone();
+        assert_eq!(dataset.get_fragments().len(), 3);
+        assert!(dataset
+            .get_fragments()
+            .iter()
+            .all(|fragment| fragment.metadata().deletion_file.is_none()));
+        assert_eq!(table.count_rows(None).await.unwrap(), 4);
+
+        // Other deletes delete rows
+        let result = table.delete("tenant = 'b' AND value = 1").await.unwrap();
+        assert_eq!(result.num_deleted_rows, 1);
+        assert_eq!(table.count_rows(None).await.unwrap(), 3);
+
+        // A single add can be partitioned
+        let other = db
+            .create_table(
+                "other",
+                RecordBatchIterator::new(vec![Ok(batch(&schema, &["x"]))], schema.clone()),
+            )
+            .execute()
+            .await
+            .unwrap();
+        other
+            .add(RecordBatchIterator::new(
+                vec![Ok(batch(&schema, &["x", "y", "x"]))],
+                schema.clone(),
+            ))
+            .partition_by("tenant")
+            .execute()
+            .await
+            .unwrap();
+        let dataset = other
+            .as_native()
+            .unwrap()
+            .dataset
+            .get()
+            .await
+            .unwrap()
+            .clone();
+        assert_eq!(dataset.get_fragments().len(), 3);
+        // Only the tables partitioned when they are created drop fragments
+        let result = other.delete("tenant = 'y'").await.unwrap();
+        assert_eq!(result.num_deleted_rows, 1);
+        assert_eq!(other.count_rows(None).await.unwrap(), 3);
+    }
+}
@@ -0,0 +1,331 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Loading the indices and columns of a table before it serves queries
+//!
+//! The partitions of an IVF index are read from storage the first time a search
+//! probes them, so the first searches after a table is opened are much slower
+//! than the following ones.  Prewarming a table searches every partition of its
+//! vector indices once, which loads them into the index cache of the table (see
+//! [`crate::connection::OpenTableBuilder::index_cache_size`], the cache must
+//! hold at least one entry per partition), and looks up a value in each of its
+//! scalar indices.  The hot columns given to [`PrewarmBuilder::columns`] are
+//! scanned, which loads them into the read cache of the connection, see
+//! [`crate::connection::ConnectBuilder::read_cache`].
+//!
+//! [`super::Table::cache_residency`] reports what is in the index cache.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use arrow_array::Float32Array;
+use arrow_schema::DataType;
+use futures::TryStreamExt;
+use lance::dataset::scanner::Scanner;
+use lance::Dataset;
+use lance_index::DatasetIndexExt;
+
+use super::{NativeTable, TableInternal};
+use crate::error::{Error, Result};
+use crate::index::vector::IvfIndexStatistics;
+use crate::index::{IndexConfig, IndexType};
+use crate::query::filter::{Filter, FilterValue};
+
+/// A builder used to prewarm a table
+///
+/// See [`super::Table::prewarm`] for more context
+pub struct PrewarmBuilder {
+    parent: Arc<dyn TableInternal>,
+    pub(crate) indices: Option<Vec<String>>,
+    pub(crate) columns: Vec<String>,
+}
+
+impl PrewarmBuilder {
+    pub(super) fn new(parent: Arc<dyn TableInternal>) -> Self {
+        Self {
+            parent,
+            indices: None,
+            columns: Vec::new(),
+        }
+    }
+
+    /// Only prewarm the indices with these names
+    ///
+    /// By default all of the vector and scalar indices of the table are
+    /// prewarmed.  Full text search indices cannot be prewarmed.
+    pub fn indices(mut self, names: &[impl AsRef<str>]) -> Self {
+        self.indices = Some(names.iter().map(|n| n.as_ref().to_string()).collect());
+        self
+    }
+
+    /// Also scan these columns
+    ///
+    /// No columns are scanned by default.
+    pub fn columns(mut self, columns: &[impl AsRef<str>]) -> Self {
+        self.columns = columns.iter().map(|c| c.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Load the indices and the columns
+    ///
+    /// Fails with [`Error::IndexNotFound`] if one of the indices does not exist.
+    pub async fn execute(self) -> Result<()> {
+        self.parent.clone().prewarm(self).await
+    }
+}
+
+/// What is loaded in the caches of a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheResidency {
+    /// The version of the table
+    pub version: u64,
+    /// The number of entries in the index cache of the table
+    ///
+    /// There is an entry for each loaded partition of an IVF index and for
+    /// each loaded scalar index.
+    pub index_cache_entries: usize,
+    /// The fraction of the lookups in the index cache which found the entry
+    pub index_cache_hit_rate: f32,
+    /// The indices prewarmed since the table moved to this version
+    pub prewarmed_indices: Vec<String>,
+    /// The columns prewarmed since the table moved to this version
+    pub prewarmed_columns: Vec<String>,
+}
+
+/// The indices and columns prewarmed on a version of a table, shared by the
+/// clones of the table
+#[derive(Debug, Default)]
+pub(crate) struct Prewarmed {
+    version: u64,
+    indices: BTreeSet<String>,
+    columns: BTreeSet<String>,
+}
+
+impl Prewarmed {
+    fn at_version(&mut self, version: u64) -> &mut Self {
+        if self.version != version {
+            *self = Self {
+                version,
+                ..Default::default()
+            };
+        }
+        self
+    }
+}
+
+impl NativeTable {
+    pub(super) async fn prewarm_impl(&self, params: PrewarmBuilder) -> Result<()> {
+        let indices = self.list_indices().await?;
+        let indices = match params.indices {
+            None => indices
+                .into_iter()
+                .filter(|index| matches!(index.index_type, IndexType::IvfPq | IndexType::BTree))
+                .collect::<Vec<_>>(),
+            Some(names) => names
+                .into_iter()
+                .map(|name| {
+                    indices
+                        .iter()
+                        .find(|index| index.name == name)
+                        .cloned()
+                        .ok_or(Error::IndexNotFound { name })
+                })
+                .collect::<Result<Vec<_>>>()?,
+        };
+
+        let dataset = self.dataset.get().await?.clone();
+        for index in &indices {
+            match index.index_type {
+                IndexType::IvfPq => Self::prewarm_vector_index(&dataset, index).await?,
+                IndexType::BTree => Self::prewarm_scalar_index(&dataset, index).await?,
+                #[cfg(feature = "fts")]
+                IndexType::Fts => {
+                    return Err(Error::NotSupported {
+                        message: "full text search indices cannot be prewarmed".to_string(),
+                    })
+                }
+            }
+        }
+        if !params.columns.is_empty() {
+            let mut scanner = dataset.scan();
+            scanner.project(&params.columns)?;
+            Self::drain(scanner).await?;
+        }
+
+        let mut prewarmed = self.prewarmed.lock()?;
+        let prewarmed = prewarmed.at_version(dataset.version().version);
+        prewarmed
+            .indices
+            .extend(indices.into_iter().map(|index| index.name));
+        prewarmed.columns.extend(params.columns);
+        Ok(())
+    }
+
+    /// Search all of the partitions of a vector index
+    async fn prewarm_vector_index(dataset: &Dataset, index: &IndexConfig) -> Result<()> {
+        let column = &index.columns[0];
+        let dimension = match dataset.schema().field(column).map(|f| f.data_type()) {
+            Some(DataType::FixedSizeList(_, dimension)) => dimension as usize,
+            _ => {
+                return Err(Error::Schema {
+                    message: format!("the column {} is not a vector column", column),
+                })
+            }
+        };
+        let stats = dataset.index_statistics(&index.name).await?;
+        let stats: IvfIndexStatistics =
+            serde_json::from_str(&stats).map_err(|e| Error::Runtime {
+                message: format!("error deserializing index statistics {}: {}", stats, e),
+            })?;
+        let num_partitions = stats
+            .indices
+            .iter()
+            .filter_map(|segment| segment.num_partitions)
+            .max()
+            .unwrap_or(1);
+
+        let mut scanner = dataset.scan();
+        scanner.nearest(column, &Float32Array::from(vec![0.0; dimension]), 1)?;
+        scanner.nprobs(num_partitions as usize);
+        scanner.project(&[column])?;
+        Self::drain(scanner).await
+    }
+
+    /// Look up a value of the column of a scalar index
+    async fn prewarm_scalar_index(dataset: &Dataset, index: &IndexConfig) -> Result<()> {
+        let column = &index.columns[0];
+        let mut scanner = dataset.scan();
+        scanner.project(&[column])?;
+        scanner.filter(&format!("{} IS NOT NULL", column))?;
+        scanner.limit(Some(1), None)?;
+        let batches = scanner
+            .try_into_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        let Some(value) = batches
+            .iter()
+            .map(|batch| FilterValue::from_array(column, batch.column(0)))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .next()
+        else {
+            return Ok(());
+        };
+
+        let filter = Filter::parse(format!("{} = $value", column))?
+            .bind("value", value)
+            .to_sql()?;
+        let mut scanner = dataset.scan();
+        scanner.project(&[column])?;
+        scanner.filter(&filter)?;
+        scanner.limit(Some(1), None)?;
+        Self::drain(scanner).await
+    }
+
+    async fn drain(scanner: Scanner) -> Result<()> {
+        let mut stream = scanner.try_into_stream().await?;
+        while stream.try_next().await?.is_some() {}
+        Ok(())
+    }
+
+    pub(super) async fn cache_residency_impl(&self) -> Result<CacheResidency> {
+        let dataset = self.dataset.get().await?;
+        let version = dataset.version().version;
+        let mut prewarmed = self.prewarmed.lock()?;
+        let prewarmed = prewarmed.at_version(version);
+        Ok(CacheResidency {
+            version,
+            index_cache_entries: dataset.index_cache_entry_count(),
+            index_cache_hit_rate: dataset.index_cache_hit_rate(),
+            prewarmed_indices: prewarmed.indices.iter().cloned().collect(),
+            prewarmed_columns: prewarmed.columns.iter().cloned().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{
+        FixedSizeListArray, Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader,
+    };
+    use arrow_schema::{Field, Schema};
+    use lance::arrow::FixedSizeListArrayExt;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::index::Index;
+
+    fn batch(num_rows: i32) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 8),
+                true,
+            ),
+        ]));
+        let values =
+            Float32Array::from_iter_values((0..num_rows * 8).map(|v| ((v * 7919) % 101) as f32));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..num_rows)),
+                Arc::new(FixedSizeListArray::try_new_from_values(values, 8).unwrap()),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_prewarm() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("test", batch(512)).execute().await.unwrap();
+        table
+            .create_index(&["id"], Index::Auto)
+            .execute()
+            .await
+            .unwrap();
+        table
+            .create_index(&["vector"], Index::Auto)
+            .execute()
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            table.prewarm_index("missing").await,
+            Err(Error::IndexNotFound { .. })
+        ));
+        table.prewarm().columns(&["id"]).execute().await.unwrap();
+        let residency = table.cache_residency().await.unwrap();
+        assert!(residency.index_cache_entries > 0);
+        assert_eq!(residency.prewarmed_indices, vec!["id_idx", "vector_idx"]);
+        assert_eq!(residency.prewarmed_columns, vec!["id"]);
+
+        // The caches are reported per version
+        table.add(batch(10)).execute().await.unwrap();
+        let residency = table.cache_residency().await.unwrap();
+        assert!(residency.prewarmed_indices.is_empty());
+        table.prewarm_index("vector_idx").await.unwrap();
+        let residency = table.cache_residency().await.unwrap();
+        assert_eq!(residency.prewarmed_indices, vec!["vector_idx"]);
+    }
+}
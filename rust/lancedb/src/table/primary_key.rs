@@ -0,0 +1,411 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Primary keys
+//!
+//! A table can declare a primary key column when it is created, see
+//! [`crate::connection::CreateTableBuilder::primary_key`].  The column is
+//! recorded in the schema of the table and must be an integer or a string
+//! column.  The keys of the initial data must be unique and not null.
+//!
+//! Adds with [`super::AddDataMode::OverwriteOnConflict`] replace the rows whose
+//! key is already in the table and adds with
+//! [`super::AddDataMode::ErrorOnConflict`] fail if a key is already in the
+//! table.  Plain appends, with [`super::AddDataMode::Append`], do not check
+//! the keys.  [`super::Table::get`] looks up a row by its key.
+//!
+//! The data of an add checking its keys is read into memory before it is
+//! written, and the keys are checked against the latest version of the table,
+//! so two concurrent adds of the same key can both succeed.  A scalar index on
+//! the key column makes the checks and the lookups faster.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use arrow::compute::filter_record_batch;
+use arrow_array::cast::AsArray;
+use arrow_array::types::Int64Type;
+use arrow_array::{Array, BooleanArray, RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_cast::cast;
+use arrow_schema::{ArrowError, DataType, Schema};
+use futures::TryStreamExt;
+
+use super::merge::MergeInsertBuilder;
+use super::{AddDataBuilder, AddDataMode, TableInternal};
+use crate::connection::NoData;
+use crate::error::{Error, Result};
+use crate::query::filter::{Filter, FilterValue};
+use crate::query::{ExecutableQuery, Query, QueryBase, Select};
+
+/// The schema metadata key which records the primary key column of a table
+pub const PRIMARY_KEY_METADATA_KEY: &str = "lancedb::primary_key";
+
+/// The keys of the rows already in the table are looked up this many at a time
+const LOOKUP_BATCH_SIZE: usize = 1024;
+
+/// The value of a primary key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Int(i64),
+    String(String),
+}
+
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int(value) => write!(f, "{}", value),
+            Self::String(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+impl From<&Key> for FilterValue {
+    fn from(key: &Key) -> Self {
+        match key {
+            Key::Int(value) => Self::Int(*value),
+            Key::String(value) => Self::String(value.clone()),
+        }
+    }
+}
+
+/// The primary key column of a table with the schema `schema`, if it has one
+pub fn primary_key(schema: &Schema) -> Option<&str> {
+    schema
+        .metadata()
+        .get(PRIMARY_KEY_METADATA_KEY)
+        .map(String::as_str)
+}
+
+/// The keys of the rows of `batch`
+fn keys(column: &str, batch: &RecordBatch) -> Result<Vec<Key>> {
+    let array = batch
+        .column_by_name(column)
+        .ok_or_else(|| Error::InvalidInput {
+            message: format!("the data has no primary key column '{}'", column),
+        })?;
+    let (values, data_type) = if array.data_type().is_integer() {
+        (cast(array, &DataType::Int64)?, DataType::Int64)
+    } else {
+        (cast(array, &DataType::Utf8)?, DataType::Utf8)
+    };
+    // Integers too large for an i64 are cast to nulls
+    if values.null_count() > 0 {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "the primary key column '{}' contains nulls or integers larger than {}",
+                column,
+                i64::MAX
+            ),
+        });
+    }
+    Ok(match data_type {
+        DataType::Int64 => values
+            .as_primitive::<Int64Type>()
+            .values()
+            .iter()
+            .map(|value| Key::Int(*value))
+            .collect(),
+        _ => values
+            .as_string::<i32>()
+            .iter()
+            .map(|value| Key::String(value.unwrap_or_default().to_string()))
+            .collect(),
+    })
+}
+
+/// Make `column` the primary key of the table created from `data`
+///
+/// Reading the data fails once a key is null or repeated.
+pub fn with_primary_key(
+    data: Box<dyn RecordBatchReader + Send>,
+    column: &str,
+) -> Result<Box<dyn RecordBatchReader + Send>> {
+    let schema = data.schema();
+    let field = schema
+        .field_with_name(column)
+        .map_err(|_| Error::InvalidInput {
+            message: format!("the primary key column '{}' is not in the data", column),
+        })?;
+    if !(field.data_type().is_integer()
+        || matches!(field.data_type(), DataType::Utf8 | DataType::LargeUtf8))
+    {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "the primary key column '{}' must be an integer or a string column, not {}",
+                column,
+                field.data_type()
+            ),
+        });
+    }
+    let mut metadata = schema.metadata().clone();
+    metadata.insert(PRIMARY_KEY_METADATA_KEY.to_string(), column.to_string());
+    let schema = Arc::new(schema.as_ref().clone().with_metadata(metadata));
+    let batch_schema = schema.clone();
+    let column = column.to_string();
+    let mut seen = HashSet::new();
+    Ok(Box::new(RecordBatchIterator::new(
+        data.map(move |batch| {
+            let batch = batch?.with_schema(batch_schema.clone())?;
+            let batch_keys = keys(&column, &batch)
+                .map_err(|e| ArrowError::InvalidArgumentError(e.to_string()))?;
+            for key in batch_keys {
+                if !seen.insert(key.clone()) {
+                    return Err(ArrowError::InvalidArgumentError(format!(
+                        "the primary key {} appears more than once in the column '{}'",
+                        key, column
+                    )));
+                }
+            }
+            Ok(batch)
+        }),
+        schema,
+    )))
+}
+
+/// The primary key column of `table`, an error if it has none
+async fn key_column(table: &Arc<dyn TableInternal>) -> Result<String> {
+    let schema = table.schema().await?;
+    primary_key(&schema)
+        .map(str::to_string)
+        .ok_or_else(|| Error::InvalidInput {
+            message: format!("the table '{}' has no primary key", table.name()),
+        })
+}
+
+/// The filter matching the rows whose key is one of `keys`
+fn key_filter<'a>(column: &str, keys: impl ExactSizeIterator<Item = &'a Key>) -> Result<String> {
+    let params = (0..keys.len())
+        .map(|i| format!("$key{}", i))
+        .collect::<Vec<_>>();
+    keys.enumerate()
+        .fold(
+            Filter::parse(format!("`{}` IN ({})", column, params.join(", ")))?,
+            |filter, (i, key)| filter.bind(format!("key{}", i), key),
+        )
+        .to_sql()
+}
+
+/// The rows of `table` whose key is one of `keys`, at most `limit` of them
+async fn find(
+    table: &Arc<dyn TableInternal>,
+    column: &str,
+    keys: &[&Key],
+    select: Select,
+    limit: usize,
+) -> Result<Vec<RecordBatch>> {
+    let filter = key_filter(column, keys.iter().copied())?;
+    Query::new(table.clone())
+        .only_if(filter)
+        .select(select)
+        .limit(limit)
+        .execute()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await
+}
+
+fn conflict(table: &Arc<dyn TableInternal>, column: &str, key: &Key) -> Error {
+    Error::PrimaryKeyConflict {
+        table: table.name().to_string(),
+        column: column.to_string(),
+        key: key.to_string(),
+    }
+}
+
+/// Add `data` to `table`, checking the primary keys according to `add.mode`
+pub(super) async fn add_with_keys(
+    table: Arc<dyn TableInternal>,
+    add: AddDataBuilder<NoData>,
+    data: Box<dyn RecordBatchReader + Send>,
+) -> Result<()> {
+    let column = key_column(&table).await?;
+    let schema = data.schema();
+    let batches = data.collect::<std::result::Result<Vec<_>, ArrowError>>()?;
+    let batch_keys = batches
+        .iter()
+        .map(|batch| keys(&column, batch))
+        .collect::<Result<Vec<_>>>()?;
+
+    match add.mode {
+        AddDataMode::OverwriteOnConflict => {
+            // The last row of each key is kept
+            let mut last = HashMap::new();
+            for (i, row_keys) in batch_keys.iter().enumerate() {
+                for (row, key) in row_keys.iter().enumerate() {
+                    last.insert(key, (i, row));
+                }
+            }
+            let batches = batches
+                .iter()
+                .zip(&batch_keys)
+                .enumerate()
+                .map(|(i, (batch, row_keys))| {
+                    let keep = row_keys
+                        .iter()
+                        .enumerate()
+                        .map(|(row, key)| Some(last[key] == (i, row)))
+                        .collect::<BooleanArray>();
+                    filter_record_batch(batch, &keep)
+                })
+                .collect::<std::result::Result<Vec<_>, ArrowError>>()?;
+
+            let mut merge = MergeInsertBuilder::new(table.clone(), vec![column]);
+            merge
+                .when_matched_update_all(None)
+                .when_not_matched_insert_all();
+            if let Some(key) = add.idempotency_key {
+                merge.idempotency_key(key);
+            }
+            merge.schema_policy = add.schema_policy;
+            let data = RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+            table.merge_insert(merge, Box::new(data)).await
+        }
+        AddDataMode::ErrorOnConflict => {
+            let mut seen = HashSet::new();
+            for key in batch_keys.iter().flatten() {
+                if !seen.insert(key) {
+                    return Err(conflict(&table, &column, key));
+                }
+            }
+            let unique = seen.into_iter().collect::<Vec<_>>();
+            for chunk in unique.chunks(LOOKUP_BATCH_SIZE) {
+                let existing = find(&table, &column, chunk, Select::columns(&[&column]), 1).await?;
+                if let Some(batch) = existing.iter().find(|batch| batch.num_rows() > 0) {
+                    let key = keys(&column, batch)?.swap_remove(0);
+                    return Err(conflict(&table, &column, &key));
+                }
+            }
+
+            let append = AddDataBuilder {
+                mode: AddDataMode::Append,
+                ..add
+            };
+            let data = RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+            table.add(append, Box::new(data)).await
+        }
+        AddDataMode::Append | AddDataMode::Overwrite | AddDataMode::BulkIngest(_) => {
+            let data = RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+            table.add(add, Box::new(data)).await
+        }
+    }
+}
+
+/// The row of `table` whose primary key is `key`
+pub(super) async fn get(
+    table: Arc<dyn TableInternal>,
+    key: FilterValue,
+) -> Result<Option<RecordBatch>> {
+    let column = key_column(&table).await?;
+    let key = match key {
+        FilterValue::Int(value) => Key::Int(value),
+        FilterValue::String(value) => Key::String(value),
+        other => {
+            return Err(Error::InvalidInput {
+                message: format!("a primary key is an integer or a string, not {:?}", other),
+            })
+        }
+    };
+    let batches = find(&table, &column, &[&key], Select::All, 1).await?;
+    Ok(batches.into_iter().find(|batch| batch.num_rows() > 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, StringArray};
+    use arrow_schema::Field;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    fn batch(ids: Vec<i32>, names: Vec<&str>) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(ids)),
+                Arc::new(StringArray::from(names)),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    fn name(batch: &RecordBatch) -> &str {
+        batch
+            .column_by_name("name")
+            .unwrap()
+            .as_string::<i32>()
+            .value(0)
+    }
+
+    #[tokio::test]
+    async fn test_primary_key() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+
+        assert!(db
+            .create_table("dup", batch(vec![1, 1], vec!["a", "b"]))
+            .primary_key("id")
+            .execute()
+            .await
+            .is_err());
+        assert!(matches!(
+            db.create_table("bad", batch(vec![1], vec!["a"]))
+                .primary_key("missing")
+                .execute()
+                .await,
+            Err(Error::InvalidInput { .. })
+        ));
+
+        let table = db
+            .create_table("test", batch(vec![1, 2], vec!["a", "b"]))
+            .primary_key("id")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.primary_key().await.unwrap().as_deref(), Some("id"));
+
+        let result = table
+            .add(batch(vec![3, 2], vec!["c", "x"]))
+            .mode(AddDataMode::ErrorOnConflict)
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::PrimaryKeyConflict { key, .. }) if key == "2"));
+        assert_eq!(table.count_rows(None).await.unwrap(), 2);
+
+        table
+            .add(batch(vec![3, 2, 2], vec!["c", "x", "y"]))
+            .mode(AddDataMode::OverwriteOnConflict)
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 3);
+        assert_eq!(name(&table.get(2).await.unwrap().unwrap()), "y");
+        assert_eq!(name(&table.get(3).await.unwrap().unwrap()), "c");
+        assert!(table.get(4).await.unwrap().is_none());
+
+        table
+            .add(batch(vec![4], vec!["d"]))
+            .mode(AddDataMode::ErrorOnConflict)
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 4);
+    }
+}
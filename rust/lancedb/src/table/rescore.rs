@@ -0,0 +1,270 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Re-scoring of the results of a vector search with exact distances
+//!
+//! A vector index compares the query vector with quantized copies of the
+//! vectors, so the `_distance` of its results is approximate.  With
+//! [`VectorQuery::refine_with_exact`] the raw vectors of the results are read
+//! by row id once the search is done, and their exact distances to the query
+//! vector are added in the [`EXACT_DISTANCE_COLUMN`] column.  The approximate
+//! distances are kept so that the two can be compared, e.g. to evaluate the
+//! error of an index.
+//!
+//! The results keep the order found by the search.  This is unlike
+//! [`VectorQuery::refine_factor`], which reorders more candidates than the
+//! limit by their exact distance, and then returns those as `_distance`.
+
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::AsArray,
+    types::{Float32Type, UInt64Type},
+    Array, Float32Array, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema};
+use futures::TryStreamExt;
+use lance::Dataset;
+use lance_linalg::distance::{cosine_distance, dot_distance, l2_distance};
+
+use super::NativeTable;
+use crate::arrow::{SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+use crate::query::{QueryExecutionOptions, VectorQuery};
+use crate::DistanceType;
+
+/// The column of the exact distances added by [`VectorQuery::refine_with_exact`]
+pub const EXACT_DISTANCE_COLUMN: &str = "_exact_distance";
+const ROW_ID_COLUMN: &str = "_rowid";
+
+fn exact_distance(query: &[f32], vector: &[f32], distance_type: DistanceType) -> f32 {
+    match distance_type {
+        DistanceType::Cosine => cosine_distance(query, vector),
+        DistanceType::Dot => dot_distance(query, vector),
+        _ => l2_distance(query, vector),
+    }
+}
+
+/// The exact distances of the vectors of `column`, at `row_ids`, to `query`
+async fn exact_distances(
+    dataset: &Dataset,
+    row_ids: &[u64],
+    column: &str,
+    query: &[f32],
+    distance_type: DistanceType,
+) -> Result<Float32Array> {
+    if row_ids.is_empty() {
+        return Ok(Float32Array::from(Vec::<f32>::new()));
+    }
+    let projection = dataset.schema().project(&[column])?;
+    let batch = dataset.take_rows(row_ids, &projection).await?;
+    let vectors = batch
+        .column(0)
+        .as_fixed_size_list_opt()
+        .ok_or_else(|| Error::InvalidInput {
+            message: format!("the column '{}' is not a vector column", column),
+        })?;
+    let dim = vectors.value_length() as usize;
+    let values = arrow_cast::cast(vectors.values(), &DataType::Float32)?;
+    let values = values.as_primitive::<Float32Type>().values();
+    Ok((0..vectors.len())
+        .map(|i| {
+            vectors
+                .is_valid(i)
+                .then(|| exact_distance(query, &values[i * dim..(i + 1) * dim], distance_type))
+        })
+        .collect())
+}
+
+/// Add the exact distances to a batch of results, and drop its row ids unless
+/// `with_row_id`
+async fn with_exact_distances(
+    dataset: &Dataset,
+    batch: RecordBatch,
+    column: &str,
+    query: &[f32],
+    distance_type: DistanceType,
+    schema: Arc<Schema>,
+    with_row_id: bool,
+) -> Result<RecordBatch> {
+    let row_ids = batch[ROW_ID_COLUMN].as_primitive::<UInt64Type>();
+    let distances =
+        exact_distances(dataset, row_ids.values(), column, query, distance_type).await?;
+    let mut batch = batch;
+    if !with_row_id {
+        let (index, _) = batch.schema().column_with_name(ROW_ID_COLUMN).unwrap();
+        batch.remove_column(index);
+    }
+    let mut columns = batch.columns().to_vec();
+    columns.push(Arc::new(distances));
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+impl NativeTable {
+    /// Run the vector search `query` and add the exact distances of its results
+    pub(super) async fn rescored_search(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        let distance_type = query.distance_type.unwrap_or(DistanceType::L2);
+        if Self::is_flat_query(query) {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "the {} distances of a flat search are exact and cannot be re-scored",
+                    distance_type
+                ),
+            });
+        }
+        let dataset = self.dataset.get().await?.clone();
+        let Some((column, query_vector)) = self.resolve_query_vector(&dataset, query)? else {
+            return Err(Error::InvalidInput {
+                message: "re-scoring with exact distances requires a query vector".to_string(),
+            });
+        };
+        let exact_query = arrow_cast::cast(&query_vector, &DataType::Float32)?;
+        let exact_query = Arc::new(exact_query.as_primitive::<Float32Type>().values().to_vec());
+
+        // The query is already embedded, if needed
+        let mut search = query.clone();
+        search.column = Some(column.clone());
+        search.query_vector = Some(query_vector);
+        search.refine_with_exact = false;
+        search.base.with_row_id = true;
+        let stream = self.search(&search, options).await?;
+
+        let with_row_id = query.base.with_row_id;
+        let mut fields = stream
+            .schema()
+            .fields()
+            .iter()
+            .filter(|field| with_row_id || field.name() != ROW_ID_COLUMN)
+            .cloned()
+            .collect::<Vec<_>>();
+        fields.push(Arc::new(Field::new(
+            EXACT_DISTANCE_COLUMN,
+            DataType::Float32,
+            true,
+        )));
+        let schema = Arc::new(Schema::new_with_metadata(
+            fields,
+            stream.schema().metadata().clone(),
+        ));
+        let batch_schema = schema.clone();
+        Ok(Box::pin(SimpleRecordBatchStream {
+            schema,
+            stream: stream.and_then(move |batch| {
+                let dataset = dataset.clone();
+                let column = column.clone();
+                let exact_query = exact_query.clone();
+                let schema = batch_schema.clone();
+                async move {
+                    with_exact_distances(
+                        &dataset,
+                        batch,
+                        &column,
+                        &exact_query,
+                        distance_type,
+                        schema,
+                        with_row_id,
+                    )
+                    .await
+                }
+            }),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{FixedSizeListArray, RecordBatchIterator};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::index::{vector::IvfPqIndexBuilder, Index};
+    use crate::query::{ExecutableQuery, QueryBase};
+
+    #[tokio::test]
+    async fn test_refine_with_exact() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let dim = 16;
+        let vectors = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            (0..512).map(|i| Some((0..dim).map(move |j| Some(((i * j) % 97) as f32)))),
+            dim,
+        );
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "vector",
+            vectors.data_type().clone(),
+            true,
+        )]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(vectors)]).unwrap();
+        let table = db
+            .create_table("t", RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await
+            .unwrap();
+        table
+            .create_index(
+                &["vector"],
+                Index::IvfPq(
+                    IvfPqIndexBuilder::default()
+                        .num_partitions(2)
+                        .num_sub_vectors(2),
+                ),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        let query = vec![1.0; dim as usize];
+        let batches = table
+            .query()
+            .nearest_to(query.as_slice())
+            .unwrap()
+            .refine_with_exact(true)
+            .limit(5)
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = &batches[0];
+        assert!(batch.column_by_name(ROW_ID_COLUMN).is_none());
+        assert!(batch.column_by_name("_distance").is_some());
+        let exact = batch[EXACT_DISTANCE_COLUMN].as_primitive::<Float32Type>();
+        let vectors = batch["vector"].as_fixed_size_list();
+        for i in 0..batch.num_rows() {
+            let vector = vectors.value(i);
+            let expected = l2_distance(&query, vector.as_primitive::<Float32Type>().values());
+            assert_eq!(exact.value(i), expected);
+        }
+
+        let flat = table
+            .query()
+            .nearest_to(query.as_slice())
+            .unwrap()
+            .distance_type(DistanceType::Hamming)
+            .column("vector")
+            .refine_with_exact(true)
+            .execute()
+            .await;
+        assert!(matches!(flat, Err(Error::InvalidInput { .. })));
+    }
+}
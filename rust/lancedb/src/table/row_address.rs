@@ -0,0 +1,162 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The physical location of the rows returned by a query
+//!
+//! The address of a row is the id of the fragment holding it in the upper 32
+//! bits and the offset of the row in the fragment in the lower 32 bits.  In this
+//! version of the table format the row id of a row is its address, so the
+//! address is read from the row id.  Unlike the row id in future versions of the
+//! format the address changes when the row is moved by a compaction.
+
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use futures::TryStreamExt;
+
+use super::NativeTable;
+use crate::arrow::{SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::Result;
+
+const ROW_ID_COLUMN: &str = "_rowid";
+
+/// The name of the column of the row addresses, see
+/// [`crate::query::QueryBase::with_row_address`]
+pub const ROW_ADDRESS_COLUMN: &str = "_rowaddr";
+
+/// The schema of the results with the row address column, and the row id
+/// column unless `with_row_id`
+fn with_row_address_schema(schema: &Schema, with_row_id: bool) -> SchemaRef {
+    let mut fields = schema
+        .fields()
+        .iter()
+        .filter(|field| with_row_id || field.name() != ROW_ID_COLUMN)
+        .cloned()
+        .collect::<Vec<_>>();
+    fields.push(Arc::new(Field::new(
+        ROW_ADDRESS_COLUMN,
+        DataType::UInt64,
+        false,
+    )));
+    Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+fn add_row_address(
+    batch: RecordBatch,
+    schema: SchemaRef,
+    with_row_id: bool,
+) -> Result<RecordBatch> {
+    let row_ids = batch[ROW_ID_COLUMN].clone();
+    let mut columns = batch
+        .schema()
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .filter(|(field, _)| with_row_id || field.name() != ROW_ID_COLUMN)
+        .map(|(_, column)| column.clone())
+        .collect::<Vec<ArrayRef>>();
+    columns.push(row_ids);
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+impl NativeTable {
+    /// Add the row address column to `stream`, the results of a query reading
+    /// the row ids, and drop the row id column unless `with_row_id`
+    pub(super) fn with_row_address(
+        stream: SendableRecordBatchStream,
+        with_row_id: bool,
+    ) -> SendableRecordBatchStream {
+        let schema = with_row_address_schema(&stream.schema(), with_row_id);
+        Box::pin(SimpleRecordBatchStream {
+            schema: schema.clone(),
+            stream: stream.and_then(move |batch| {
+                std::future::ready(add_row_address(batch, schema.clone(), with_row_id))
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{cast::AsArray, types::UInt64Type};
+    use arrow_array::{Int32Array, RecordBatchIterator, RecordBatchReader};
+    use futures::TryStreamExt;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+
+    fn batch(start: i32) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(start..start + 10))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_row_address() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("t", batch(0)).execute().await.unwrap();
+        table.add(batch(10)).execute().await.unwrap();
+
+        let batches = table
+            .query()
+            .only_if("i = 12")
+            .with_row_address()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batches[0].schema().fields().len(), 2);
+        assert!(batches[0].column_by_name(ROW_ID_COLUMN).is_none());
+        // The third row of the second fragment
+        let addresses = batches[0][ROW_ADDRESS_COLUMN].as_primitive::<UInt64Type>();
+        assert_eq!(addresses.values(), &[(1 << 32) + 2]);
+
+        let batches = table
+            .query()
+            .only_if("i = 3")
+            .with_row_id()
+            .with_row_address()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            batches[0][ROW_ID_COLUMN]
+                .as_primitive::<UInt64Type>()
+                .values(),
+            &[3]
+        );
+        assert_eq!(
+            batches[0][ROW_ADDRESS_COLUMN]
+                .as_primitive::<UInt64Type>()
+                .values(),
+            &[3]
+        );
+    }
+}
@@ -0,0 +1,293 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Random samples of the rows of a table, see [`crate::query::Query::sample`]
+//!
+//! Without a filter the offsets of the sampled rows are drawn from a random
+//! generator seeded with the seed of the sample, and only those rows are read:
+//! the offsets are grouped by fragment and each fragment only reads the pages
+//! holding its sampled rows.
+//!
+//! With a filter the matching rows are not known until they are scanned, so the
+//! table is scanned and each row is given a pseudo-random key, a hash of the seed
+//! and of its row id.  A fraction keeps the rows with a key below the fraction,
+//! and a number of rows keeps the rows with the smallest keys, holding only the
+//! sample in memory.  In both cases the sample only depends on the seed and on
+//! the version of the table.
+
+use std::sync::Arc;
+
+use arrow::compute::{concat_batches, filter_record_batch, sort_to_indices};
+use arrow_array::{cast::AsArray, types::UInt64Type, BooleanArray, RecordBatch, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use futures::{stream, TryStreamExt};
+use rand::{rngs::SmallRng, SeedableRng};
+
+use super::NativeTable;
+use crate::arrow::{take_record_batch, SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+use crate::query::{Query, QueryExecutionOptions, Sample, Select};
+
+const ROW_ID_COLUMN: &str = "_rowid";
+const KEY_COLUMN: &str = "_sample_key";
+
+/// The pseudo-random key of a row in the sample with the given seed
+fn sample_key(seed: u64, row_id: u64) -> u64 {
+    // The finalizer of splitmix64
+    let mut z = seed.wrapping_add(row_id.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// `batch` with the keys of its rows
+fn with_keys(batch: &RecordBatch, seed: u64) -> Result<RecordBatch> {
+    let keys = batch[ROW_ID_COLUMN]
+        .as_primitive::<UInt64Type>()
+        .values()
+        .iter()
+        .map(|row_id| sample_key(seed, *row_id))
+        .collect::<UInt64Array>();
+    let mut fields = batch.schema().fields().to_vec();
+    fields.push(Arc::new(Field::new(KEY_COLUMN, DataType::UInt64, false)));
+    let mut columns = batch.columns().to_vec();
+    columns.push(Arc::new(keys));
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+/// The rows of `batch` in the order of their row ids
+fn sort_by_row_id(batch: &RecordBatch) -> Result<RecordBatch> {
+    let indices = sort_to_indices(&batch[ROW_ID_COLUMN], None, None)?;
+    Ok(take_record_batch(batch, &indices)?)
+}
+
+fn validate(sample: Sample) -> Result<()> {
+    match sample {
+        Sample::Fraction(fraction) if !(0.0..=1.0).contains(&fraction) => {
+            Err(Error::InvalidInput {
+                message: format!(
+                    "the fraction of a sample must be between 0 and 1, not {}",
+                    fraction
+                ),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+impl NativeTable {
+    /// Scan a random sample of the rows matching `query`
+    pub(super) async fn sampled_query(
+        &self,
+        query: &Query,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        let (sample, seed) = query.sample.expect("the query has a sample");
+        validate(sample)?;
+        if query.offset.is_some() || query.after.is_some() || !query.order_by.is_empty() {
+            return Err(Error::InvalidInput {
+                message: "a sample cannot be paginated or ordered".to_string(),
+            });
+        }
+
+        let dataset = self.dataset.get().await?;
+        let schema = Schema::from(dataset.schema());
+        let batch_size = options.max_batch_length as usize;
+        let sampled = if query.resolved_filter(&schema)?.is_none()
+            && !query.only_deleted
+            && !query.with_row_id
+            && query.hints.is_empty()
+            && !matches!(query.resolved_select(&schema), Select::Dynamic(_))
+        {
+            // Only read the sampled rows
+            let projection = match &query.select {
+                Select::Columns(columns) => dataset.schema().project(columns)?,
+                _ => dataset.schema().clone(),
+            };
+            let num_rows = dataset.count_rows(None).await?;
+            let sample_size = match sample {
+                Sample::Fraction(fraction) => (fraction * num_rows as f64).round() as usize,
+                Sample::Rows(rows) => rows,
+            };
+            let sample_size = sample_size
+                .min(num_rows)
+                .min(query.limit.unwrap_or(usize::MAX));
+            let mut offsets =
+                rand::seq::index::sample(&mut SmallRng::seed_from_u64(seed), num_rows, sample_size)
+                    .into_iter()
+                    .map(|offset| offset as u64)
+                    .collect::<Vec<_>>();
+            offsets.sort_unstable();
+            dataset.take(&offsets, &projection).await?
+        } else {
+            drop(dataset);
+            self.scan_sample(query, sample, seed, options).await?
+        };
+
+        let batches = (0..sampled.num_rows())
+            .step_by(batch_size.max(1))
+            .map(|offset| Ok(sampled.slice(offset, batch_size.min(sampled.num_rows() - offset))))
+            .collect::<Vec<_>>();
+        Ok(Box::pin(SimpleRecordBatchStream {
+            schema: sampled.schema(),
+            stream: stream::iter(batches),
+        }))
+    }
+
+    /// Scan the rows matching `query` and keep a sample of them, see the
+    /// [module docs](self)
+    async fn scan_sample(
+        &self,
+        query: &Query,
+        sample: Sample,
+        seed: u64,
+        options: QueryExecutionOptions,
+    ) -> Result<RecordBatch> {
+        let mut scan = query.clone();
+        scan.sample = None;
+        scan.limit = None;
+        scan.with_row_id = true;
+        let mut stream: SendableRecordBatchStream = self
+            .generic_query(&scan.into_vector(), options)
+            .await?
+            .into();
+
+        let empty = with_keys(&RecordBatch::new_empty(stream.schema()), seed)?;
+        let schema = empty.schema();
+        let sampled = match sample {
+            Sample::Fraction(fraction) => {
+                // The kept rows are concatenated once, at the end of the scan
+                let threshold = (fraction * u64::MAX as f64) as u64;
+                let mut kept = Vec::new();
+                while let Some(batch) = stream.try_next().await? {
+                    let batch = with_keys(&batch, seed)?;
+                    let keep = batch[KEY_COLUMN]
+                        .as_primitive::<UInt64Type>()
+                        .values()
+                        .iter()
+                        .map(|key| Some(*key < threshold))
+                        .collect::<BooleanArray>();
+                    kept.push(filter_record_batch(&batch, &keep)?);
+                }
+                concat_batches(&schema, &kept)?
+            }
+            Sample::Rows(rows) => {
+                // Keep the rows with the smallest keys
+                let mut sampled = empty;
+                while let Some(batch) = stream.try_next().await? {
+                    let batch = with_keys(&batch, seed)?;
+                    let batch = concat_batches(&schema, [&sampled, &batch])?;
+                    let indices = sort_to_indices(&batch[KEY_COLUMN], None, Some(rows))?;
+                    sampled = take_record_batch(&batch, &indices)?;
+                }
+                sampled
+            }
+        };
+
+        let mut sampled = sort_by_row_id(&sampled)?;
+        if let Some(limit) = query.limit {
+            sampled = sampled.slice(0, limit.min(sampled.num_rows()));
+        }
+        let (index, _) = sampled.schema().column_with_name(KEY_COLUMN).unwrap();
+        sampled.remove_column(index);
+        if !query.with_row_id {
+            let (index, _) = sampled.schema().column_with_name(ROW_ID_COLUMN).unwrap();
+            sampled.remove_column(index);
+        }
+        Ok(sampled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{types::Int32Type, Int32Array, RecordBatchIterator, RecordBatchReader};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::{ExecutableQuery, QueryBase};
+
+    fn batch(start: i32) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(start..start + 100))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    async fn values(query: Query) -> Vec<i32> {
+        query
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap()
+            .iter()
+            .flat_map(|batch| batch["i"].as_primitive::<Int32Type>().values().to_vec())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_sample() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("t", batch(0)).execute().await.unwrap();
+        table.add(batch(100)).execute().await.unwrap();
+
+        // The same seed gives the same sample
+        let sample = values(table.query().sample(10usize, 42)).await;
+        assert_eq!(sample.len(), 10);
+        assert!(sample.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(values(table.query().sample(10usize, 42)).await, sample);
+        assert_ne!(values(table.query().sample(10usize, 7)).await, sample);
+        assert_eq!(values(table.query().sample(0.25, 42)).await.len(), 50);
+        assert_eq!(values(table.query().sample(1000usize, 1)).await.len(), 200);
+
+        // A sample of the rows matching a filter
+        let even = values(table.query().only_if("i % 2 = 0").sample(20usize, 42)).await;
+        assert_eq!(even.len(), 20);
+        assert!(even.iter().all(|i| i % 2 == 0));
+        assert_eq!(
+            values(table.query().only_if("i % 2 = 0").sample(20usize, 42)).await,
+            even
+        );
+        let fraction = values(table.query().only_if("i < 100").sample(0.5, 3)).await;
+        assert!(fraction.iter().all(|i| *i < 100));
+        assert!(!fraction.is_empty() && fraction.len() < 100);
+
+        assert!(matches!(
+            table.query().sample(1.5, 0).execute().await,
+            Err(Error::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            table
+                .query()
+                .sample(0.5, 0)
+                .nearest_to(&[1.0])
+                .unwrap()
+                .execute()
+                .await,
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Snapshots of a table, for backups and migrations
+//!
+//! A snapshot is a copy of one version of a table (its manifest, the data and
+//! deletion files of its fragments and the files of its indices) in another
+//! directory, possibly of another object store, see
+//! [`super::Table::export_snapshot`].  The snapshot is a table with that single
+//! version, which can be copied into a database with
+//! [`crate::Connection::import_snapshot`].
+//!
+//! The files of a version are never modified once written, so a snapshot is
+//! consistent even if the table is written while it is copied, unlike a copy of
+//! the directory of the table which can catch a commit halfway.  The manifest is
+//! copied last, so the destination only becomes a table once all of its files
+//! are there.  The files are copied in parallel, and a copy which failed can be
+//! resumed by running it again: the files already copied are skipped.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use futures::{stream, TryStreamExt};
+use lance::dataset::builder::DatasetBuilder;
+use lance::dataset::ReadParams;
+use lance::io::{ObjectStore, ObjectStoreParams};
+use object_store::path::Path;
+
+use super::pack::{version_files, LATEST_MANIFEST, VERSIONS_DIR};
+use super::NativeTable;
+use crate::error::{Error, Result};
+
+/// The number of files copied at the same time
+const SNAPSHOT_CONCURRENCY: usize = 16;
+
+/// The description of a snapshot, see [`super::Table::export_snapshot`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    /// The version of the table in the snapshot
+    pub version: u64,
+    /// The number of files of the snapshot
+    pub files: usize,
+    /// The total size of the files
+    pub bytes: u64,
+    /// The number of files copied, the others were already copied by a
+    /// previous attempt
+    pub copied_files: usize,
+}
+
+/// The object store of `uri` and the directory of `uri` in the store
+async fn open_store(
+    uri: &str,
+    storage_options: &HashMap<String, String>,
+) -> Result<(ObjectStore, Path)> {
+    let params = ObjectStoreParams {
+        storage_options: Some(storage_options.clone()),
+        ..Default::default()
+    };
+    Ok(ObjectStore::from_uri_and_params(uri, &params).await?)
+}
+
+fn location(dir: &Path, file: &str) -> Path {
+    dir.parts().chain(Path::from(file).parts()).collect()
+}
+
+/// Copy `files`, the files of `version` of the table in `source_dir` starting
+/// with its manifest, to `target_dir`
+async fn copy_version(
+    source: &ObjectStore,
+    source_dir: &Path,
+    target: &ObjectStore,
+    target_dir: &Path,
+    version: u64,
+    files: &[String],
+) -> Result<SnapshotInfo> {
+    let manifest = location(target_dir, &files[0]);
+    let other_version = target
+        .inner
+        .list(Some(&target_dir.child(VERSIONS_DIR)))
+        .try_collect::<Vec<_>>()
+        .await?
+        .into_iter()
+        .any(|meta| meta.location != manifest);
+    if other_version {
+        return Err(Error::InvalidInput {
+            message: format!("{} already holds another version of a table", target_dir),
+        });
+    }
+
+    let bytes = AtomicU64::new(0);
+    let copied_files = AtomicUsize::new(0);
+    stream::iter(files[1..].iter().map(Ok))
+        .try_for_each_concurrent(SNAPSHOT_CONCURRENCY, |file| {
+            let (bytes, copied_files) = (&bytes, &copied_files);
+            async move {
+                let from = location(source_dir, file);
+                let to = location(target_dir, file);
+                let size = source.inner.head(&from).await?.size;
+                bytes.fetch_add(size as u64, Ordering::Relaxed);
+                if matches!(target.inner.head(&to).await, Ok(meta) if meta.size == size) {
+                    return Ok(());
+                }
+                let data = source.inner.get(&from).await?.bytes().await?;
+                target.inner.put(&to, data).await?;
+                copied_files.fetch_add(1, Ordering::Relaxed);
+                Result::Ok(())
+            }
+        })
+        .await?;
+
+    // The manifest is copied last, so the target is not a table until all of
+    // the files of the version are copied
+    let data = source
+        .inner
+        .get(&location(source_dir, &files[0]))
+        .await?
+        .bytes()
+        .await?;
+    bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+    target.inner.put(&manifest, data.clone()).await?;
+    target
+        .inner
+        .put(&location(target_dir, LATEST_MANIFEST), data)
+        .await?;
+    Ok(SnapshotInfo {
+        version,
+        files: files.len(),
+        bytes: bytes.into_inner(),
+        copied_files: copied_files.into_inner() + 1,
+    })
+}
+
+/// Copy the latest version of the table at `source_uri` to `target_dir`
+///
+/// Fails with [`Error::TableAlreadyExists`] if `target_dir` already holds the
+/// table `name`.
+pub(crate) async fn import(
+    name: &str,
+    source_uri: &str,
+    storage_options: &HashMap<String, String>,
+    target: &ObjectStore,
+    target_dir: &Path,
+) -> Result<SnapshotInfo> {
+    if target
+        .inner
+        .head(&location(target_dir, LATEST_MANIFEST))
+        .await
+        .is_ok()
+    {
+        return Err(Error::TableAlreadyExists {
+            name: name.to_string(),
+        });
+    }
+    let (source, source_dir) = open_store(source_uri, storage_options).await?;
+    let params = ReadParams {
+        store_options: Some(ObjectStoreParams {
+            storage_options: Some(storage_options.clone()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let dataset = DatasetBuilder::from_uri(source_uri)
+        .with_read_params(params)
+        .load()
+        .await?;
+    let (version, files) = version_files(&dataset, &source, &source_dir).await?;
+    copy_version(&source, &source_dir, target, target_dir, version, &files).await
+}
+
+impl NativeTable {
+    pub(super) async fn export_snapshot_impl(&self, target_uri: &str) -> Result<SnapshotInfo> {
+        let (source, source_dir) = open_store(&self.uri, &self.storage_options).await?;
+        let (target, target_dir) = open_store(target_uri, &self.storage_options).await?;
+        let dataset = self.dataset.get().await?.clone();
+        let (version, files) = version_files(&dataset, &source, &source_dir).await?;
+        copy_version(&source, &source_dir, &target, &target_dir, version, &files).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use crate::connect;
+    use crate::index::{scalar::BTreeIndexBuilder, Index};
+
+    fn batches(start: i32) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(start..start + 10))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_snapshot() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().join("db").to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("t", batches(0)).execute().await.unwrap();
+        table.add(batches(10)).execute().await.unwrap();
+        table.delete("i < 5").await.unwrap();
+        table
+            .create_index(&["i"], Index::BTree(BTreeIndexBuilder::default()))
+            .execute()
+            .await
+            .unwrap();
+
+        let backup = tmp_dir.path().join("backup").join("t.lance");
+        let backup = backup.to_str().unwrap();
+        let info = table.export_snapshot(backup).await.unwrap();
+        assert_eq!(info.version, table.version().await.unwrap());
+        assert_eq!(info.copied_files, info.files);
+        // Exporting again only copies the manifest
+        let again = table.export_snapshot(backup).await.unwrap();
+        assert_eq!(again.copied_files, 1);
+        assert_eq!(again.bytes, info.bytes);
+
+        // The snapshot is a table
+        let backups = connect(tmp_dir.path().join("backup").to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let copy = backups.open_table("t").execute().await.unwrap();
+        assert_eq!(copy.count_rows(None).await.unwrap(), 15);
+        assert_eq!(copy.list_indices().await.unwrap().len(), 1);
+
+        let restored = db.import_snapshot(backup, "restored").await.unwrap();
+        assert_eq!(restored.count_rows(None).await.unwrap(), 15);
+        assert_eq!(restored.count_rows(Some("i = 3".into())).await.unwrap(), 0);
+
+        // A snapshot does not overwrite another version
+        table.add(batches(20)).execute().await.unwrap();
+        assert!(table.export_snapshot(backup).await.is_err());
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Splitting a table into shards
+//!
+//! The rows of the table are routed to the shards in a single scan and buffered
+//! per shard, each buffer being written to its shard once it holds enough rows
+//! for a data file.  The shards are new tables in the same database as the table,
+//! which is left unchanged.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::compute::filter_record_batch;
+use arrow::row::{RowConverter, SortField};
+use arrow_array::{BooleanArray, RecordBatch, RecordBatchIterator};
+use futures::TryStreamExt;
+use lance::dataset::{WriteMode, WriteParams};
+use lance::io::ObjectStoreParams;
+
+use super::{NativeTable, Table, TableInternal};
+use crate::connection::LANCE_FILE_EXTENSION;
+use crate::error::{Error, Result};
+use crate::index::Index;
+use crate::utils::validate_table_name;
+
+/// The number of rows buffered for a shard before they are written
+const FLUSH_ROWS: usize = 1024 * 1024;
+
+/// How the rows of a table are assigned to the shards
+#[derive(Debug, Clone)]
+pub enum SplitStrategy {
+    /// Split the rows on the values of `column` into up to `num_shards` shards
+    ///
+    /// All of the rows with the same value are in the same shard.  The values are
+    /// assigned to the shards so that the shards have as close to the same number
+    /// of rows as possible.  There are fewer shards if the column has fewer than
+    /// `num_shards` distinct values.
+    ByColumn { column: String, num_shards: usize },
+    /// Split the rows, in the order of the table, into shards of `max_rows` rows
+    ///
+    /// The last shard holds the remaining rows.
+    BySize { max_rows: usize },
+}
+
+/// A builder used to split a table into shards
+///
+/// See [`super::Table::split`] for more context
+pub struct SplitBuilder {
+    parent: Arc<dyn TableInternal>,
+    pub(crate) strategy: SplitStrategy,
+    pub(crate) name_prefix: Option<String>,
+    pub(crate) indices: Vec<(Vec<String>, Index)>,
+}
+
+impl SplitBuilder {
+    pub(super) fn new(parent: Arc<dyn TableInternal>, strategy: SplitStrategy) -> Self {
+        Self {
+            parent,
+            strategy,
+            name_prefix: None,
+            indices: Vec::new(),
+        }
+    }
+
+    /// The prefix of the names of the shards, the default is `<table>_shard_`
+    ///
+    /// The shard `i` is named `<prefix><i>`, starting from 0.
+    pub fn name_prefix(mut self, name_prefix: impl Into<String>) -> Self {
+        self.name_prefix = Some(name_prefix.into());
+        self
+    }
+
+    /// Create an index on each of the shards
+    ///
+    /// This can be called several times to create several indices.  The indices
+    /// are built once all of the shards have been written.
+    pub fn index(mut self, columns: &[impl AsRef<str>], index: Index) -> Self {
+        let columns = columns.iter().map(|c| c.as_ref().to_string()).collect();
+        self.indices.push((columns, index));
+        self
+    }
+
+    /// Write the shards
+    ///
+    /// Returns the shards, in order.  Fails with [`Error::TableAlreadyExists`] if
+    /// one of the shards already exists.
+    pub async fn execute(self) -> Result<Vec<Table>> {
+        self.parent.clone().split(self).await
+    }
+}
+
+/// Assigns the rows of a table to the shards
+enum Router {
+    ByColumn {
+        column: String,
+        converter: RowConverter,
+        shards: HashMap<Vec<u8>, usize>,
+    },
+    BySize {
+        max_rows: usize,
+        routed: usize,
+    },
+}
+
+impl Router {
+    /// The shard of each row of `batch`
+    fn route(&mut self, batch: &RecordBatch) -> Result<Vec<usize>> {
+        match self {
+            Self::ByColumn {
+                column,
+                converter,
+                shards,
+            } => {
+                let rows = converter.convert_columns(&[batch[column.as_str()].clone()])?;
+                Ok(rows
+                    .iter()
+                    .map(|row| shards.get(row.as_ref()).copied().unwrap_or_default())
+                    .collect())
+            }
+            Self::BySize { max_rows, routed } => {
+                let shards = (*routed..*routed + batch.num_rows())
+                    .map(|row| row / *max_rows)
+                    .collect();
+                *routed += batch.num_rows();
+                Ok(shards)
+            }
+        }
+    }
+}
+
+/// The uri of the table `name` in the same database as the table at `uri`
+fn sibling_uri(uri: &str, name: &str) -> String {
+    let (path, query) = match uri.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (uri, None),
+    };
+    let dir = path
+        .trim_end_matches('/')
+        .rsplit_once('/')
+        .map(|(dir, _)| dir)
+        .unwrap_or(".");
+    let mut uri = format!("{}/{}.{}", dir, name, LANCE_FILE_EXTENSION);
+    if let Some(query) = query {
+        uri.push('?');
+        uri.push_str(query);
+    }
+    uri
+}
+
+/// A shard being written
+#[derive(Default)]
+struct Shard {
+    table: Option<Table>,
+    buffer: Vec<RecordBatch>,
+    buffered_rows: usize,
+}
+
+impl NativeTable {
+    /// Assign the values of `column` to `num_shards` shards, balancing their rows
+    async fn balance_values(
+        &self,
+        column: &str,
+        num_shards: usize,
+    ) -> Result<(RowConverter, HashMap<Vec<u8>, usize>)> {
+        let dataset = self.dataset.get().await?.clone();
+        let field = dataset
+            .schema()
+            .field(column)
+            .ok_or_else(|| Error::Schema {
+                message: format!("The column {} does not exist", column),
+            })?;
+        let converter = RowConverter::new(vec![SortField::new(field.data_type())])?;
+        let mut scanner = dataset.scan();
+        scanner.project(&[column])?;
+        let mut stream = scanner.try_into_stream().await?;
+        let mut counts: HashMap<Vec<u8>, usize> = HashMap::new();
+        while let Some(batch) = stream.try_next().await? {
+            let rows = converter.convert_columns(&[batch[column].clone()])?;
+            for row in rows.iter() {
+                *counts.entry(row.as_ref().to_vec()).or_default() += 1;
+            }
+        }
+
+        // Largest values first, each to the shard with the fewest rows so far
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_by(|(a_value, a_count), (b_value, b_count)| {
+            b_count.cmp(a_count).then_with(|| a_value.cmp(b_value))
+        });
+        let mut shard_rows = vec![0; num_shards];
+        let mut shards = HashMap::with_capacity(counts.len());
+        for (value, count) in counts {
+            let (shard, _) = shard_rows
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, rows)| **rows)
+                .unwrap();
+            shard_rows[shard] += count;
+            shards.insert(value, shard);
+        }
+        Ok((converter, shards))
+    }
+
+    /// Write the buffered rows of `shard`, creating it on the first write
+    async fn flush_shard(&self, shard: &mut Shard, name: String) -> Result<()> {
+        if shard.buffer.is_empty() {
+            return Ok(());
+        }
+        let batches = std::mem::take(&mut shard.buffer);
+        shard.buffered_rows = 0;
+        let schema = batches[0].schema();
+        let data = RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+        match &shard.table {
+            Some(table) => table.add(data).execute().await?,
+            None => {
+                let params = WriteParams {
+                    mode: WriteMode::Create,
+                    store_params: Some(ObjectStoreParams {
+                        storage_options: Some(self.storage_options.clone()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                };
+                let table = Self::create(
+                    &sibling_uri(&self.uri, &name),
+                    &name,
+                    data,
+                    self.store_wrapper.clone(),
+                    Some(params),
+                    self.read_consistency_interval,
+                )
+                .await?;
+                shard.table = Some(table.into());
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) async fn split_impl(&self, params: SplitBuilder) -> Result<Vec<Table>> {
+        let prefix = params
+            .name_prefix
+            .unwrap_or_else(|| format!("{}_shard_", self.name));
+        validate_table_name(&format!("{}0", prefix))?;
+        let mut router = match params.strategy {
+            SplitStrategy::ByColumn { num_shards: 0, .. }
+            | SplitStrategy::BySize { max_rows: 0 } => {
+                return Err(Error::InvalidInput {
+                    message: "the number of shards and rows per shard must be positive".to_string(),
+                });
+            }
+            SplitStrategy::ByColumn { column, num_shards } => {
+                let (converter, shards) = self.balance_values(&column, num_shards).await?;
+                Router::ByColumn {
+                    column,
+                    converter,
+                    shards,
+                }
+            }
+            SplitStrategy::BySize { max_rows } => Router::BySize {
+                max_rows,
+                routed: 0,
+            },
+        };
+
+        let dataset = self.dataset.get().await?.clone();
+        let mut stream = dataset.scan().try_into_stream().await?;
+        let mut shards: Vec<Shard> = Vec::new();
+        while let Some(batch) = stream.try_next().await? {
+            let routes = router.route(&batch)?;
+            let Some(max_shard) = routes.iter().max() else {
+                continue;
+            };
+            if shards.len() <= *max_shard {
+                shards.resize_with(max_shard + 1, Shard::default);
+            }
+            for (id, shard) in shards.iter_mut().enumerate() {
+                let mask = routes
+                    .iter()
+                    .map(|route| Some(*route == id))
+                    .collect::<BooleanArray>();
+                if mask.true_count() == 0 {
+                    continue;
+                }
+                let rows = filter_record_batch(&batch, &mask)?;
+                shard.buffered_rows += rows.num_rows();
+                shard.buffer.push(rows);
+                if shard.buffered_rows >= FLUSH_ROWS {
+                    self.flush_shard(shard, format!("{}{}", prefix, id)).await?;
+                }
+            }
+        }
+
+        let mut tables = Vec::with_capacity(shards.len());
+        for (id, mut shard) in shards.into_iter().enumerate() {
+            self.flush_shard(&mut shard, format!("{}{}", prefix, id))
+                .await?;
+            tables.extend(shard.table);
+        }
+        for table in &tables {
+            for (columns, index) in &params.indices {
+                table
+                    .create_index(columns.as_slice(), index.clone())
+                    .execute()
+                    .await?;
+            }
+        }
+        Ok(tables)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sibling_uri() {
+        assert_eq!(sibling_uri("/db/t.lance", "s0"), "/db/s0.lance");
+        assert_eq!(
+            sibling_uri("s3://bucket/db/t.lance?region=x", "s0"),
+            "s3://bucket/db/s0.lance?region=x"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Queries of versions which are no longer available
+//!
+//! A table handle reads the version it last loaded until it is refreshed, see
+//! [`crate::connection::ConnectBuilder::read_consistency_interval`].  Once
+//! [`super::OptimizeAction::Prune`] removes the files of that version, for
+//! example from another process, the queries of the handle fail with
+//! [`Error::StaleVersion`], until the table is reopened or
+//! [`super::Table::checkout_latest`] is called.
+//!
+//! With [`crate::connection::ConnectBuilder::retry_stale_queries`] a query
+//! failing this way refreshes the handle to the latest version and runs again,
+//! once.  To detect the failure before any result is returned, such queries
+//! read their first batch before [`crate::query::ExecutableQuery::execute`]
+//! returns.  A failure after the first batch, or a query of a version checked
+//! out with [`super::Table::checkout`], is never retried.
+
+use std::future::Future;
+
+use futures::{StreamExt, TryStreamExt};
+
+use super::NativeTable;
+use crate::arrow::{SendableRecordBatchStream, SimpleRecordBatchStream};
+use crate::error::{Error, Result};
+
+/// Whether `error` is caused by a file of the version read having been removed
+fn is_stale(error: &Error) -> bool {
+    match error {
+        Error::ObjectStore {
+            source: object_store::Error::NotFound { .. },
+        } => true,
+        Error::Lance { source } => match source {
+            lance::Error::NotFound { .. } => true,
+            // Lance reports the errors of the object store as IO errors
+            lance::Error::IO { .. } => source.to_string().to_lowercase().contains("not found"),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Start `query` and read its first batch
+async fn start(
+    query: impl Future<Output = Result<SendableRecordBatchStream>>,
+) -> Result<SendableRecordBatchStream> {
+    let mut stream = query.await?;
+    let schema = stream.schema();
+    match stream.next().await {
+        Some(Err(e)) => Err(e),
+        first => Ok(Box::pin(SimpleRecordBatchStream {
+            schema,
+            stream: futures::stream::iter(first).chain(stream),
+        })),
+    }
+}
+
+impl NativeTable {
+    /// Refresh the table and retry the queries which fail because the version
+    /// read is no longer available
+    pub(crate) fn with_retry_stale_queries(mut self, retry_stale_queries: bool) -> Self {
+        self.retry_stale_queries = retry_stale_queries;
+        self
+    }
+
+    /// `error`, as an [`Error::StaleVersion`] if `version` is no longer available
+    fn stale_version_error(name: &str, version: u64, error: Error) -> Error {
+        if is_stale(&error) {
+            Error::StaleVersion {
+                table: name.to_string(),
+                version,
+                message: error.to_string(),
+            }
+        } else {
+            error
+        }
+    }
+
+    fn with_stale_version_errors(
+        &self,
+        stream: SendableRecordBatchStream,
+        version: u64,
+    ) -> SendableRecordBatchStream {
+        let name = self.name.clone();
+        Box::pin(SimpleRecordBatchStream {
+            schema: stream.schema(),
+            stream: stream.map_err(move |e| Self::stale_version_error(&name, version, e)),
+        })
+    }
+
+    /// Run `query` on a copy of the table pinned to its current version
+    ///
+    /// Returns the results and the version read.  See the [module docs](self)
+    /// for when the query is retried.
+    pub(super) async fn query_pinned<F, Fut>(
+        &self,
+        query: F,
+    ) -> Result<(SendableRecordBatchStream, u64)>
+    where
+        F: Fn(Self) -> Fut,
+        Fut: Future<Output = Result<SendableRecordBatchStream>>,
+    {
+        let (table, version) = self.pinned().await?;
+        let retry = self.retry_stale_queries && self.dataset.time_travel_version().await.is_none();
+        let (result, version) = if retry {
+            match start(query(table)).await {
+                Err(e) if is_stale(&e) => {
+                    log::info!(
+                        "Retrying the query of table {} on its latest version: {}",
+                        self.name,
+                        e
+                    );
+                    self.dataset.reload().await?;
+                    let (table, version) = self.pinned().await?;
+                    (query(table).await, version)
+                }
+                result => (result, version),
+            }
+        } else {
+            (query(table).await, version)
+        };
+        match result {
+            Ok(stream) => Ok((self.with_stale_version_errors(stream, version), version)),
+            Err(e) => Err(Self::stale_version_error(&self.name, version, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+    use arrow_schema::{DataType, Field, Schema};
+    use futures::TryStreamExt;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::query::ExecutableQuery;
+    use crate::table::OptimizeAction;
+
+    fn batch(start: i32) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(start..start + 10))],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_retry_stale_queries() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let table = db.create_table("test", batch(0)).execute().await.unwrap();
+        table.add(batch(10)).execute().await.unwrap();
+
+        let stale = connect(uri)
+            .execute()
+            .await
+            .unwrap()
+            .open_table("test")
+            .execute()
+            .await
+            .unwrap();
+        let retrying = connect(uri)
+            .retry_stale_queries(true)
+            .execute()
+            .await
+            .unwrap()
+            .open_table("test")
+            .execute()
+            .await
+            .unwrap();
+
+        // Rewrite the fragments read by the other handles and remove the old files
+        table
+            .optimize(OptimizeAction::Compact {
+                options: Default::default(),
+                remap_options: None,
+            })
+            .await
+            .unwrap();
+        table
+            .optimize(OptimizeAction::Prune {
+                older_than: chrono::Duration::zero(),
+                delete_unverified: Some(true),
+            })
+            .await
+            .unwrap();
+
+        let result = match stale.query().execute().await {
+            Ok(stream) => stream.try_collect::<Vec<_>>().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        assert!(matches!(
+            result,
+            Err(Error::StaleVersion { version: 2, .. })
+        ));
+
+        let batches = retrying
+            .query()
+            .execute()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 20);
+        assert_eq!(
+            retrying.version().await.unwrap(),
+            table.version().await.unwrap()
+        );
+    }
+}
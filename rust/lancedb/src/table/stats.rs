@@ -0,0 +1,588 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Column statistics
+//!
+//! The statistics of a column are computed with a scan the first time they are
+//! requested (see [`super::Table::column_stats`]) and stored, with the version
+//! of the table they describe, in a hidden "stats" dataset in the table's
+//! directory.  From then on the statistics are updated as data is added to the
+//! table, without scanning it again.  Any other change to the table (a delete,
+//! an update, ...) makes the statistics stale and they are recomputed the next
+//! time they are requested.
+//!
+//! All of the statistics can be merged, which is what makes the incremental
+//! updates possible.  The distinct count is estimated with a HyperLogLog sketch
+//! and the histogram is approximate once data outside of its range is added.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use arrow::compute::kernels::aggregate;
+use arrow_array::{
+    cast::AsArray, Array, ArrayRef, RecordBatch, RecordBatchIterator, RecordBatchReader,
+    StringArray, UInt64Array,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use chrono::Duration;
+use futures::TryStreamExt;
+use lance::dataset::WriteMode;
+use lance::Dataset;
+use serde::{Deserialize, Serialize};
+
+use super::NativeTable;
+use crate::error::{Error, Result};
+use crate::query::filter::FilterValue;
+
+const STATS_DIR: &str = "_stats";
+/// The number of bits of the hash used to pick a HyperLogLog register
+const HLL_PRECISION: u32 = 12;
+const HLL_REGISTERS: usize = 1 << HLL_PRECISION;
+const HISTOGRAM_BUCKETS: usize = 16;
+
+/// A bucket of the histogram of a numeric column
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    /// The lower bound of the bucket (inclusive)
+    pub lower: f64,
+    /// The upper bound of the bucket, this is exclusive except for the last bucket
+    pub upper: f64,
+    /// The number of values in the bucket
+    pub count: usize,
+}
+
+/// Statistics describing the values of a column
+///
+/// Temporal values (dates, timestamps, ...) are described by their underlying
+/// integer value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStatistics {
+    /// The number of rows in the table
+    pub num_rows: usize,
+    /// The number of null values in the column
+    pub null_count: usize,
+    /// The smallest value, `None` if all values are null or the column is binary
+    pub min: Option<FilterValue>,
+    /// The largest value, `None` if all values are null or the column is binary
+    pub max: Option<FilterValue>,
+    /// An estimate of the number of distinct (non-null) values, typically within 2%
+    pub approx_distinct: u64,
+    /// An equal width histogram of the values, only for numeric columns
+    pub histogram: Option<Vec<HistogramBucket>>,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+enum Bound {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl From<Bound> for FilterValue {
+    fn from(bound: Bound) -> Self {
+        match bound {
+            Bound::Bool(value) => Self::Bool(value),
+            Bound::Int(value) => Self::Int(value),
+            Bound::Float(value) => Self::Float(value),
+            Bound::String(value) => Self::String(value),
+        }
+    }
+}
+
+/// A stable 64 bit hash (FNV-1a followed by a finalizer to mix the high bits)
+///
+/// The sketches are stored, so the hash must not change between releases.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325_u64;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+/// Cast `array` to one of the few types the statistics are computed on
+fn normalize(array: &dyn Array) -> Result<ArrayRef> {
+    let target = match array.data_type() {
+        DataType::Boolean => DataType::Boolean,
+        data_type if data_type.is_integer() || data_type.is_temporal() => DataType::Int64,
+        data_type if data_type.is_floating() => DataType::Float64,
+        DataType::Utf8 | DataType::LargeUtf8 => DataType::Utf8,
+        DataType::Binary | DataType::LargeBinary => DataType::Binary,
+        DataType::Dictionary(_, value_type) => {
+            return normalize(&arrow_cast::cast(array, value_type)?)
+        }
+        data_type => {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "statistics are not supported for columns of type {}",
+                    data_type
+                ),
+            })
+        }
+    };
+    Ok(arrow_cast::cast(array, &target)?)
+}
+
+/// The mergeable state the statistics of a column are computed from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct StatsAccumulator {
+    num_rows: usize,
+    null_count: usize,
+    min: Option<Bound>,
+    max: Option<Bound>,
+    /// HyperLogLog registers, empty until a value is added
+    registers: Vec<u8>,
+    histogram: Vec<HistogramBucket>,
+}
+
+impl StatsAccumulator {
+    /// The statistics of the values in `array`
+    fn from_array(array: &dyn Array) -> Result<Self> {
+        let mut stats = Self {
+            num_rows: array.len(),
+            null_count: array.null_count(),
+            ..Default::default()
+        };
+        let array = normalize(array)?;
+        match array.data_type() {
+            DataType::Boolean => {
+                let values = array.as_boolean();
+                stats.min = aggregate::min_boolean(values).map(Bound::Bool);
+                stats.max = aggregate::max_boolean(values).map(Bound::Bool);
+                values
+                    .iter()
+                    .flatten()
+                    .for_each(|v| stats.add_hash(&[v as u8]));
+            }
+            DataType::Int64 => {
+                let values = array.as_primitive::<arrow_array::types::Int64Type>();
+                stats.min = aggregate::min(values).map(Bound::Int);
+                stats.max = aggregate::max(values).map(Bound::Int);
+                values
+                    .iter()
+                    .flatten()
+                    .for_each(|v| stats.add_hash(&v.to_le_bytes()));
+                stats.histogram = histogram(
+                    &values
+                        .iter()
+                        .flatten()
+                        .map(|v| v as f64)
+                        .collect::<Vec<_>>(),
+                );
+            }
+            DataType::Float64 => {
+                let values = array.as_primitive::<arrow_array::types::Float64Type>();
+                stats.min = aggregate::min(values).map(Bound::Float);
+                stats.max = aggregate::max(values).map(Bound::Float);
+                values
+                    .iter()
+                    .flatten()
+                    .for_each(|v| stats.add_hash(&v.to_le_bytes()));
+                stats.histogram = histogram(&values.iter().flatten().collect::<Vec<_>>());
+            }
+            DataType::Utf8 => {
+                let values = array.as_string::<i32>();
+                stats.min = aggregate::min_string(values).map(|v| Bound::String(v.to_string()));
+                stats.max = aggregate::max_string(values).map(|v| Bound::String(v.to_string()));
+                values
+                    .iter()
+                    .flatten()
+                    .for_each(|v| stats.add_hash(v.as_bytes()));
+            }
+            _ => {
+                let values = array.as_binary::<i32>();
+                values.iter().flatten().for_each(|v| stats.add_hash(v));
+            }
+        }
+        Ok(stats)
+    }
+
+    fn add_hash(&mut self, bytes: &[u8]) {
+        if self.registers.is_empty() {
+            self.registers = vec![0; HLL_REGISTERS];
+        }
+        let hash = hash(bytes);
+        let register = (hash >> (64 - HLL_PRECISION)) as usize;
+        // The position of the first set bit in the rest of the hash
+        let rank = ((hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1))).leading_zeros() + 1;
+        self.registers[register] = self.registers[register].max(rank as u8);
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.num_rows += other.num_rows;
+        self.null_count += other.null_count;
+        self.min = match (self.min.take(), other.min) {
+            (Some(a), Some(b)) => Some(if b < a { b } else { a }),
+            (a, b) => a.or(b),
+        };
+        self.max = match (self.max.take(), other.max) {
+            (Some(a), Some(b)) => Some(if b > a { b } else { a }),
+            (a, b) => a.or(b),
+        };
+        if self.registers.is_empty() {
+            self.registers = other.registers;
+        } else if !other.registers.is_empty() {
+            for (register, other) in self.registers.iter_mut().zip(other.registers) {
+                *register = (*register).max(other);
+            }
+        }
+        self.histogram = merge_histograms(&self.histogram, &other.histogram);
+    }
+
+    fn approx_distinct(&self) -> u64 {
+        if self.registers.is_empty() {
+            return 0;
+        }
+        let m = HLL_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum = self
+            .registers
+            .iter()
+            .map(|register| 2f64.powi(-(*register as i32)))
+            .sum::<f64>();
+        let estimate = alpha * m * m / sum;
+        let zeros = self
+            .registers
+            .iter()
+            .filter(|register| **register == 0)
+            .count();
+        // Linear counting is more accurate for small cardinalities
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+
+    fn statistics(&self) -> ColumnStatistics {
+        ColumnStatistics {
+            num_rows: self.num_rows,
+            null_count: self.null_count,
+            min: self.min.clone().map(FilterValue::from),
+            max: self.max.clone().map(FilterValue::from),
+            approx_distinct: self.approx_distinct(),
+            histogram: (!self.histogram.is_empty()).then(|| self.histogram.clone()),
+        }
+    }
+}
+
+fn empty_buckets(lower: f64, upper: f64) -> Vec<HistogramBucket> {
+    let num_buckets = if lower < upper { HISTOGRAM_BUCKETS } else { 1 };
+    let width = (upper - lower) / num_buckets as f64;
+    (0..num_buckets)
+        .map(|i| HistogramBucket {
+            lower: lower + width * i as f64,
+            upper: if i + 1 == num_buckets {
+                upper
+            } else {
+                lower + width * (i + 1) as f64
+            },
+            count: 0,
+        })
+        .collect()
+}
+
+fn add_to_buckets(buckets: &mut [HistogramBucket], value: f64, count: usize) {
+    let lower = buckets[0].lower;
+    let upper = buckets[buckets.len() - 1].upper;
+    let index = if upper > lower {
+        (((value - lower) / (upper - lower)) * buckets.len() as f64) as usize
+    } else {
+        0
+    };
+    buckets[index.min(buckets.len() - 1)].count += count;
+}
+
+pub(super) fn histogram(values: &[f64]) -> Vec<HistogramBucket> {
+    let (lower, upper) = values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lower, upper), v| {
+            (lower.min(*v), upper.max(*v))
+        });
+    if lower > upper {
+        return Vec::new();
+    }
+    let mut buckets = empty_buckets(lower, upper);
+    for value in values.iter().filter(|v| v.is_finite()) {
+        add_to_buckets(&mut buckets, *value, 1);
+    }
+    buckets
+}
+
+/// Merge two histograms into one covering both of their ranges
+///
+/// When the ranges differ the counts of each bucket are moved to the bucket
+/// containing its midpoint.
+fn merge_histograms(a: &[HistogramBucket], b: &[HistogramBucket]) -> Vec<HistogramBucket> {
+    let (Some(a_first), Some(b_first)) = (a.first(), b.first()) else {
+        return if a.is_empty() { b.to_vec() } else { a.to_vec() };
+    };
+    let lower = a_first.lower.min(b_first.lower);
+    let upper = a[a.len() - 1].upper.max(b[b.len() - 1].upper);
+    let mut buckets = empty_buckets(lower, upper);
+    for bucket in a.iter().chain(b) {
+        add_to_buckets(
+            &mut buckets,
+            (bucket.lower + bucket.upper) / 2.0,
+            bucket.count,
+        );
+    }
+    buckets
+}
+
+/// The stored statistics of a column
+struct StoredStats {
+    version: u64,
+    stats: StatsAccumulator,
+}
+
+/// Statistics collected from the data written by an add, see [`track_stats`]
+pub(crate) struct AddedStats {
+    /// The version of the table the data was added to
+    version: u64,
+    columns: Arc<Mutex<HashMap<String, StatsAccumulator>>>,
+}
+
+fn stats_schema() -> Arc<Schema> {
+    Arc::new(Schema::new(vec![
+        Field::new("column", DataType::Utf8, false),
+        Field::new("version", DataType::UInt64, false),
+        Field::new("stats", DataType::Utf8, false),
+    ]))
+}
+
+fn json_error(e: serde_json::Error) -> Error {
+    Error::Runtime {
+        message: format!("Invalid column statistics: {}", e),
+    }
+}
+
+impl NativeTable {
+    fn stats_uri(&self) -> String {
+        format!("{}/{}", self.uri.trim_end_matches('/'), STATS_DIR)
+    }
+
+    async fn load_stats(&self) -> Result<HashMap<String, StoredStats>> {
+        let Some(dataset) = self.open_sidecar(&self.stats_uri()).await? else {
+            return Ok(HashMap::new());
+        };
+        let batches = dataset
+            .scan()
+            .try_into_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        let mut stats = HashMap::new();
+        for batch in batches {
+            let columns = batch["column"].as_string::<i32>();
+            let versions = batch["version"].as_primitive::<arrow_array::types::UInt64Type>();
+            let values = batch["stats"].as_string::<i32>();
+            for row in 0..batch.num_rows() {
+                stats.insert(
+                    columns.value(row).to_string(),
+                    StoredStats {
+                        version: versions.value(row),
+                        stats: serde_json::from_str(values.value(row)).map_err(json_error)?,
+                    },
+                );
+            }
+        }
+        Ok(stats)
+    }
+
+    async fn save_stats(&self, stats: &HashMap<String, StoredStats>) -> Result<()> {
+        let mut columns = Vec::with_capacity(stats.len());
+        let mut versions = Vec::with_capacity(stats.len());
+        let mut values = Vec::with_capacity(stats.len());
+        for (column, stored) in stats {
+            columns.push(column.as_str());
+            versions.push(stored.version);
+            values.push(serde_json::to_string(&stored.stats).map_err(json_error)?);
+        }
+        let schema = stats_schema();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(columns)),
+                Arc::new(UInt64Array::from(versions)),
+                Arc::new(StringArray::from(values)),
+            ],
+        )?;
+        let mode = if self.open_sidecar(&self.stats_uri()).await?.is_some() {
+            WriteMode::Overwrite
+        } else {
+            WriteMode::Create
+        };
+        let dataset = Dataset::write(
+            RecordBatchIterator::new(vec![Ok(batch)], schema),
+            &self.stats_uri(),
+            Some(self.sidecar_write_params(mode)?),
+        )
+        .await?;
+        // Only the latest version of the statistics is ever read
+        dataset
+            .cleanup_old_versions(Duration::zero(), Some(true))
+            .await?;
+        Ok(())
+    }
+
+    /// Compute the statistics of `column` with a scan of the table
+    async fn compute_stats(&self, column: &str) -> Result<StatsAccumulator> {
+        let dataset = self.dataset.get().await?.clone();
+        let mut scanner = dataset.scan();
+        scanner.project(&[column])?;
+        let mut stream = scanner.try_into_stream().await?;
+        let mut stats = StatsAccumulator::default();
+        while let Some(batch) = stream.try_next().await? {
+            stats.merge(StatsAccumulator::from_array(batch.column(0))?);
+        }
+        Ok(stats)
+    }
+
+    pub(super) async fn column_stats_impl(&self, column: &str) -> Result<ColumnStatistics> {
+        let version = self.dataset.get().await?.version().version;
+        let schema = self.dataset.get().await?.schema().clone();
+        if schema.field(column).is_none() {
+            return Err(Error::InvalidInput {
+                message: format!("the column '{}' does not exist", column),
+            });
+        }
+        let mut stored = self.load_stats().await?;
+        if let Some(stats) = stored.get(column) {
+            if stats.version == version {
+                return Ok(stats.stats.statistics());
+            }
+        }
+        let stats = self.compute_stats(column).await?;
+        let statistics = stats.statistics();
+        stored.insert(column.to_string(), StoredStats { version, stats });
+        self.save_stats(&stored).await?;
+        Ok(statistics)
+    }
+
+    /// Collect the statistics of the tracked columns from the data being added
+    ///
+    /// Returns the wrapped data, which must be written before calling
+    /// [`Self::update_stats`].
+    pub(super) async fn track_stats(
+        &self,
+        data: Box<dyn RecordBatchReader + Send>,
+    ) -> Result<(Box<dyn RecordBatchReader + Send>, Option<AddedStats>)> {
+        let tracked = self.load_stats().await?;
+        if tracked.is_empty() {
+            return Ok((data, None));
+        }
+        let version = self.dataset.get().await?.version().version;
+        let columns = Arc::new(Mutex::new(
+            tracked
+                .into_keys()
+                .filter(|column| data.schema().column_with_name(column).is_some())
+                .map(|column| (column, StatsAccumulator::default()))
+                .collect::<HashMap<_, _>>(),
+        ));
+        let collected = columns.clone();
+        let schema = data.schema();
+        let data = data.map(move |batch: std::result::Result<RecordBatch, ArrowError>| {
+            let batch = batch?;
+            let mut columns = collected.lock().unwrap();
+            // A column whose statistics can't be computed is left to be recomputed later
+            columns.retain(|column, stats| {
+                match StatsAccumulator::from_array(&batch[column.as_str()]) {
+                    Ok(batch_stats) => {
+                        stats.merge(batch_stats);
+                        true
+                    }
+                    Err(_) => false,
+                }
+            });
+            Ok(batch)
+        });
+        Ok((
+            Box::new(RecordBatchIterator::new(data, schema)),
+            Some(AddedStats { version, columns }),
+        ))
+    }
+
+    /// Merge the statistics of the data added by `mode` into the stored statistics
+    pub(super) async fn update_stats(&self, added: AddedStats, mode: WriteMode) -> Result<()> {
+        let version = self.dataset.get().await?.version().version;
+        let AddedStats {
+            version: previous_version,
+            columns,
+        } = added;
+        let added = std::mem::take(&mut *columns.lock().unwrap());
+        let mut stored = self.load_stats().await?;
+        for (column, stats) in added {
+            match stored.get_mut(&column) {
+                Some(current) if matches!(mode, WriteMode::Overwrite) => {
+                    *current = StoredStats { version, stats };
+                }
+                // Only statistics that were up to date before the add can be updated
+                Some(current) if current.version == previous_version => {
+                    current.version = version;
+                    current.stats.merge(stats);
+                }
+                _ => {}
+            }
+        }
+        self.save_stats(&stored).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Float64Array, Int32Array};
+
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let mut stats =
+            StatsAccumulator::from_array(&Int32Array::from(vec![Some(1), None, Some(3)])).unwrap();
+        stats.merge(StatsAccumulator::from_array(&Int32Array::from(vec![3, 10, -2])).unwrap());
+        let statistics = stats.statistics();
+        assert_eq!(statistics.num_rows, 6);
+        assert_eq!(statistics.null_count, 1);
+        assert_eq!(statistics.min, Some(FilterValue::Int(-2)));
+        assert_eq!(statistics.max, Some(FilterValue::Int(10)));
+        assert_eq!(statistics.approx_distinct, 4);
+        let histogram = statistics.histogram.unwrap();
+        assert_eq!(histogram.first().unwrap().lower, -2.0);
+        assert_eq!(histogram.last().unwrap().upper, 10.0);
+        assert_eq!(histogram.iter().map(|b| b.count).sum::<usize>(), 5);
+
+        let strings = StatsAccumulator::from_array(&StringArray::from(vec!["b", "a", "b"]))
+            .unwrap()
+            .statistics();
+        assert_eq!(strings.min, Some(FilterValue::String("a".to_string())));
+        assert_eq!(strings.approx_distinct, 2);
+        assert_eq!(strings.histogram, None);
+    }
+
+    #[test]
+    fn test_approx_distinct() {
+        let values = Float64Array::from_iter_values((0..100_000).map(|v| (v % 50_000) as f64));
+        let estimate = StatsAccumulator::from_array(&values)
+            .unwrap()
+            .approx_distinct();
+        assert!((estimate as f64 - 50_000.0).abs() < 50_000.0 * 0.05);
+    }
+}
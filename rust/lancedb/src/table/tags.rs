@@ -0,0 +1,228 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Names for versions of a table
+//!
+//! The tags are stored, with the version they name, in a hidden dataset in the
+//! table's directory.  Tagged versions are kept when old versions are pruned
+//! (see [`super::OptimizeAction::Prune`]).
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, types::UInt64Type, RecordBatch, RecordBatchIterator};
+use arrow_array::{StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use chrono::{Duration, Utc};
+use futures::TryStreamExt;
+use lance::dataset::WriteMode;
+use lance::Dataset;
+
+use super::{NativeTable, TableInternal};
+use crate::error::{Error, Result};
+use crate::query::filter::Filter;
+
+const TAGS_DIR: &str = "_tags";
+
+fn tags_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("tag", DataType::Utf8, false),
+        Field::new("version", DataType::UInt64, false),
+    ]))
+}
+
+fn validate_tag(tag: &str) -> Result<()> {
+    if tag.is_empty() {
+        return Err(Error::InvalidInput {
+            message: "tags must not be empty".to_string(),
+        });
+    }
+    if let Some(c) = tag
+        .chars()
+        .find(|c| !c.is_alphanumeric() && !matches!(c, '_' | '-' | '.'))
+    {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "the tag {} contains the character '{}', tags may only contain \
+                 alphanumeric characters, underscores, hyphens, and periods",
+                tag, c
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// The tags of a table, see [`super::Table::tags`]
+///
+/// A tag is a name for a version of the table.  It can be used instead of the
+/// version number, for example to check out the version of a table that a model
+/// was trained on with [`super::Table::checkout_tag`].
+pub struct Tags {
+    parent: Arc<dyn TableInternal>,
+}
+
+impl Tags {
+    pub(super) fn new(parent: Arc<dyn TableInternal>) -> Self {
+        Self { parent }
+    }
+
+    /// Name `version` of the table `tag`
+    ///
+    /// Tags may only contain alphanumeric characters, underscores, hyphens, and
+    /// periods.  Fails if the tag already exists or if the version does not.
+    pub async fn create(&self, tag: &str, version: u64) -> Result<()> {
+        validate_tag(tag)?;
+        self.parent.create_tag(tag, version).await
+    }
+
+    /// Delete the tag `tag`
+    ///
+    /// The version it named is not affected, although it may now be pruned.
+    pub async fn delete(&self, tag: &str) -> Result<()> {
+        self.parent.delete_tag(tag).await
+    }
+
+    /// The tags of the table and the versions they name
+    pub async fn list(&self) -> Result<BTreeMap<String, u64>> {
+        self.parent.list_tags().await
+    }
+
+    /// The version named `tag`
+    pub async fn get_version(&self, tag: &str) -> Result<u64> {
+        self.list()
+            .await?
+            .remove(tag)
+            .ok_or_else(|| Error::InvalidInput {
+                message: format!("the tag {} does not exist", tag),
+            })
+    }
+}
+
+impl NativeTable {
+    fn tags_uri(&self) -> String {
+        format!("{}/{}", self.uri.trim_end_matches('/'), TAGS_DIR)
+    }
+
+    pub(super) async fn list_tags_impl(&self) -> Result<BTreeMap<String, u64>> {
+        let Some(dataset) = self.open_sidecar(&self.tags_uri()).await? else {
+            return Ok(BTreeMap::new());
+        };
+        let batches = dataset
+            .scan()
+            .try_into_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        let mut tags = BTreeMap::new();
+        for batch in batches {
+            let names = batch["tag"].as_string::<i32>();
+            let versions = batch["version"].as_primitive::<UInt64Type>();
+            for (name, version) in names.iter().zip(versions.values().iter()) {
+                tags.insert(name.unwrap_or_default().to_string(), *version);
+            }
+        }
+        Ok(tags)
+    }
+
+    pub(super) async fn create_tag_impl(&self, tag: &str, version: u64) -> Result<()> {
+        let tags = self.list_tags_impl().await?;
+        if let Some(tagged) = tags.get(tag) {
+            return Err(Error::InvalidInput {
+                message: format!("the tag {} already exists, for version {}", tag, tagged),
+            });
+        }
+        let versions = self.dataset.get().await?.versions().await?;
+        if !versions.iter().any(|v| v.version == version) {
+            return Err(Error::InvalidInput {
+                message: format!("the table {} has no version {}", self.name, version),
+            });
+        }
+
+        let schema = tags_schema();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![tag])),
+                Arc::new(UInt64Array::from(vec![version])),
+            ],
+        )?;
+        let mode = if self.open_sidecar(&self.tags_uri()).await?.is_some() {
+            WriteMode::Append
+        } else {
+            WriteMode::Create
+        };
+        Dataset::write(
+            RecordBatchIterator::new(vec![Ok(batch)], schema),
+            &self.tags_uri(),
+            Some(self.sidecar_write_params(mode)?),
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub(super) async fn delete_tag_impl(&self, tag: &str) -> Result<()> {
+        let not_found = || Error::InvalidInput {
+            message: format!("the tag {} does not exist", tag),
+        };
+        let Some(mut dataset) = self.open_sidecar(&self.tags_uri()).await? else {
+            return Err(not_found());
+        };
+        let filter = Filter::parse("tag = $tag")?.bind("tag", tag).to_sql()?;
+        if dataset.count_rows(Some(filter.clone())).await? == 0 {
+            return Err(not_found());
+        }
+        dataset.delete(&filter).await?;
+        Ok(())
+    }
+
+    /// Shorten `older_than` so that a prune keeps the tagged versions
+    ///
+    /// Pruning removes every version older than the cutoff, so the cutoff is
+    /// moved before the oldest tagged version.
+    pub(super) async fn protect_tagged_versions(&self, older_than: Duration) -> Result<Duration> {
+        let tags = self.list_tags_impl().await?;
+        if tags.is_empty() {
+            return Ok(older_than);
+        }
+        let oldest = self
+            .dataset
+            .get()
+            .await?
+            .versions()
+            .await?
+            .into_iter()
+            .filter(|v| tags.values().any(|tagged| *tagged == v.version))
+            .map(|v| v.timestamp)
+            .min();
+        Ok(match oldest {
+            // Keep a margin as the cutoff is computed again, later, by the prune
+            Some(oldest) => older_than.max(Utc::now() - oldest + Duration::seconds(1)),
+            None => older_than,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_tag() {
+        assert!(validate_tag("v1.0").is_ok());
+        assert!(validate_tag("train_2024-01").is_ok());
+        assert!(validate_tag("").is_err());
+        assert!(validate_tag("a/b").is_err());
+        assert!(validate_tag("a b").is_err());
+    }
+}
@@ -0,0 +1,745 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Writes to several tables which are committed together
+//!
+//! A [`Transaction`], started with [`crate::Connection::transaction`], stages
+//! adds and deletes to tables of the database, and commits them all or none
+//! of them.  This keeps tables which describe the same data consistent, for
+//! example documents and their chunks:
+//!
+//! ```no_run
+//! # use arrow_array::RecordBatchReader;
+//! # async fn replace(
+//! #     db: &lancedb::Connection,
+//! #     document: impl RecordBatchReader + Send + 'static,
+//! #     chunks: impl RecordBatchReader + Send + 'static,
+//! # ) -> lancedb::Result<()> {
+//! let mut transaction = db.transaction();
+//! transaction
+//!     .delete("documents", "id = 42")?
+//!     .delete("chunks", "document_id = 42")?
+//!     .add("documents", document)?
+//!     .add("chunks", chunks)?;
+//! transaction.commit().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Nothing is written until [`Transaction::commit`], and dropping the
+//! transaction discards it.  The commit first writes the new data files and
+//! the deletion files of every table, without committing them, so that most
+//! failures (invalid data, a filter on an unknown column, an unreachable
+//! store) leave every table unchanged.  The tables are then committed one
+//! after the other.  If one of these commits fails, e.g. because of a
+//! conflicting concurrent write, the tables already committed are restored to
+//! their version before the transaction, and the error is returned.  A table
+//! with both deletes and adds is committed as two versions, the first one is
+//! recorded before the second one is committed so that it is restored too.
+//!
+//! The deletes of a table are applied before its adds, whatever the order
+//! they were staged in, and all of them are applied to the version of the
+//! table when the commit starts.  The adds must have the schema of the table
+//! (after its embedding columns are computed), and the table must never have
+//! had its columns altered, as for a [`super::AddDataMode::BulkIngest`].  Soft
+//! deletes are not supported.
+//!
+//! The transaction is atomic but not isolated: while it is committed, readers
+//! may see the tables committed first without the others.  A commit
+//! interrupted by a crash leaves a record of the transaction in the database,
+//! and [`crate::Connection::recover_transactions`] then restores the tables it
+//! had committed.  The record is refreshed each time a table is committed,
+//! and a record refreshed less than [`TRANSACTION_LEASE`] ago is taken for a
+//! commit still in progress, which is not rolled back.  A record is marked
+//! committed before it is removed, so that a commit which failed to remove
+//! it is not rolled back either.  Transactions are only supported by LanceDB
+//! OSS.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use arrow_array::RecordBatchReader;
+use arrow_schema::Schema;
+use futures::TryStreamExt;
+use lance::dataset::transaction::Operation;
+use lance::dataset::{Dataset, WriteMode};
+use object_store::path::Path;
+use object_store::ObjectStore;
+use serde::{Deserialize, Serialize};
+
+use super::bulk_ingest::BulkIngestOptions;
+use super::validate;
+use super::NativeTable;
+use crate::arrow::IntoArrow;
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::query::filter::Filter;
+use crate::quota::QuotaWrite;
+
+/// The directory of the records of the transactions being committed
+pub(crate) const TRANSACTIONS_DIR: &str = "_transactions";
+
+/// How long after its last update the record of a transaction is taken for
+/// a commit still in progress, which recovery leaves alone
+pub const TRANSACTION_LEASE: Duration = Duration::from_secs(10 * 60);
+
+/// The record of a transaction being committed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TransactionRecord {
+    tables: Vec<TableRecord>,
+    /// Whether every table is committed, set before the record is removed
+    #[serde(default)]
+    committed: bool,
+    /// When the record was last written, in milliseconds since the epoch
+    #[serde(default)]
+    updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TableRecord {
+    name: String,
+    /// The version before the transaction
+    read_version: u64,
+    /// The last version committed by the transaction, once committed
+    ///
+    /// The writes of a table may be committed as several versions, each one
+    /// is recorded before the next one is committed.
+    committed_version: Option<u64>,
+}
+
+impl TransactionRecord {
+    /// Whether the commit of the transaction may still be in progress
+    fn in_flight(&self) -> bool {
+        let age = chrono::Utc::now().timestamp_millis() - self.updated_at;
+        !self.committed && age < TRANSACTION_LEASE.as_millis() as i64
+    }
+
+    async fn write(&self, store: &dyn ObjectStore, path: &Path) -> Result<()> {
+        let json = serde_json::to_vec(self).map_err(|e| Error::Runtime {
+            message: format!("failed to serialize the transaction record: {}", e),
+        })?;
+        store.put(path, json.into()).await?;
+        Ok(())
+    }
+
+    async fn read(store: &dyn ObjectStore, path: &Path) -> Result<Self> {
+        let bytes = store.get(path).await?.bytes().await?;
+        serde_json::from_slice(&bytes).map_err(|e| Error::Runtime {
+            message: format!("invalid transaction record {}: {}", path, e),
+        })
+    }
+}
+
+/// The record of a transaction being committed, and where it is stored
+struct Journal<'a> {
+    store: &'a dyn ObjectStore,
+    path: &'a Path,
+    record: TransactionRecord,
+}
+
+impl Journal<'_> {
+    /// Record that the transaction committed `version` of its table `index`
+    async fn committed(&mut self, index: usize, version: u64) -> Result<()> {
+        self.record.tables[index].committed_version = Some(version);
+        self.record.updated_at = chrono::Utc::now().timestamp_millis();
+        self.record.write(self.store, self.path).await
+    }
+
+    /// Mark the transaction committed, then remove its record
+    ///
+    /// The transaction is committed whatever happens here, so failures are
+    /// only logged.  Recovery removes a record marked committed without
+    /// rolling it back.
+    async fn finish(mut self) {
+        self.record.committed = true;
+        self.record.updated_at = chrono::Utc::now().timestamp_millis();
+        if let Err(e) = self.record.write(self.store, self.path).await {
+            log::warn!(
+                "Failed to mark the transaction record {} committed: {}",
+                self.path,
+                e
+            );
+        }
+        if let Err(e) = self.store.delete(self.path).await {
+            log::warn!(
+                "Failed to remove the transaction record {}: {}",
+                self.path,
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Fail the commit of a table after its first version is committed
+    static FAIL_BETWEEN_COMMITS: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+#[cfg(test)]
+fn fail_between_commits() -> bool {
+    FAIL_BETWEEN_COMMITS.with(|fail| fail.get())
+}
+
+#[cfg(not(test))]
+fn fail_between_commits() -> bool {
+    false
+}
+
+/// The writes staged for a table
+#[derive(Default)]
+struct StagedWrites {
+    deletes: Vec<String>,
+    adds: Vec<Box<dyn RecordBatchReader + Send>>,
+}
+
+/// The writes of a table, written but not committed
+struct PreparedWrites {
+    read_version: u64,
+    operations: Vec<Operation>,
+    quota_writes: Vec<QuotaWrite>,
+}
+
+/// Adds and deletes to several tables, committed together
+///
+/// See the [module docs](self).
+pub struct Transaction {
+    connection: Connection,
+    tables: Vec<(String, StagedWrites)>,
+}
+
+impl std::fmt::Debug for Transaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Transaction")
+            .field(
+                "tables",
+                &self
+                    .tables
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Transaction {
+    pub(crate) fn new(connection: Connection) -> Self {
+        Self {
+            connection,
+            tables: Vec::new(),
+        }
+    }
+
+    fn staged(&mut self, table: String) -> &mut StagedWrites {
+        let index = match self.tables.iter().position(|(name, _)| *name == table) {
+            Some(index) => index,
+            None => {
+                self.tables.push((table, StagedWrites::default()));
+                self.tables.len() - 1
+            }
+        };
+        &mut self.tables[index].1
+    }
+
+    /// Add `data` to the table `table`
+    pub fn add<T: IntoArrow>(&mut self, table: impl Into<String>, data: T) -> Result<&mut Self> {
+        let data = data.into_arrow()?;
+        self.staged(table.into()).adds.push(data);
+        Ok(self)
+    }
+
+    /// Delete the rows of the table `table` matching `predicate`
+    ///
+    /// The predicate is a [filter](crate::query::filter).
+    pub fn delete(
+        &mut self,
+        table: impl Into<String>,
+        predicate: impl AsRef<str>,
+    ) -> Result<&mut Self> {
+        let predicate = Filter::parse(predicate.as_ref())?.to_sql()?;
+        self.staged(table.into()).deletes.push(predicate);
+        Ok(self)
+    }
+
+    /// Discard the staged writes
+    ///
+    /// This is the same as dropping the transaction.
+    pub fn rollback(self) {}
+
+    /// Commit the staged writes, all of them or none of them
+    ///
+    /// Returns the new version of each table written.  On error the tables
+    /// are left at their version before the transaction, unless their
+    /// restoration failed too, which is reported in the error.
+    pub async fn commit(self) -> Result<HashMap<String, u64>> {
+        let (store, dir) = self.connection.transaction_log()?;
+        let mut prepared = Vec::with_capacity(self.tables.len());
+        for (name, writes) in self.tables {
+            let table = self.connection.open_table(&name).execute().await?;
+            let Some(native) = table.as_native() else {
+                return Err(Error::NotSupported {
+                    message: format!("the table {} can't be written in a transaction", name),
+                });
+            };
+            let writes = native.prepare_writes(writes).await?;
+            prepared.push((table, writes));
+        }
+
+        let path = dir.child(format!(
+            "{}-{:016x}.json",
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u64>()
+        ));
+        let record = TransactionRecord {
+            tables: prepared
+                .iter()
+                .map(|(table, writes)| TableRecord {
+                    name: table.name().to_string(),
+                    read_version: writes.read_version,
+                    committed_version: None,
+                })
+                .collect(),
+            committed: false,
+            updated_at: chrono::Utc::now().timestamp_millis(),
+        };
+        record.write(store.as_ref(), &path).await?;
+        let mut journal = Journal {
+            store: store.as_ref(),
+            path: &path,
+            record,
+        };
+
+        let mut versions = HashMap::with_capacity(prepared.len());
+        for (i, (table, writes)) in prepared.into_iter().enumerate() {
+            let native = table.as_native().expect("checked when prepared");
+            let read_version = writes.read_version;
+            match native.commit_prepared(writes, &mut journal, i).await {
+                Ok(version) => {
+                    versions.insert(table.name().to_string(), version);
+                }
+                Err(e) => {
+                    log::warn!(
+                        "transaction failed to commit the table {} at version {}, \
+                         rolling back: {}",
+                        table.name(),
+                        read_version,
+                        e
+                    );
+                    return Err(roll_back(&self.connection, &journal.record, &path, e).await);
+                }
+            }
+        }
+        journal.finish().await;
+        Ok(versions)
+    }
+}
+
+/// Restore the tables committed by the transaction of `record`, then remove
+/// the record
+///
+/// Returns the error which failed the transaction, or the error of the
+/// restoration if it failed too.
+async fn roll_back(
+    connection: &Connection,
+    record: &TransactionRecord,
+    path: &Path,
+    err: Error,
+) -> Error {
+    match restore_tables(connection, record).await {
+        Ok(()) => {
+            if let Ok((store, _)) = connection.transaction_log() {
+                if let Err(e) = store.delete(path).await {
+                    log::warn!("Failed to remove the transaction record {}: {}", path, e);
+                }
+            }
+            err
+        }
+        Err(e) => Error::Runtime {
+            message: format!(
+                "the transaction failed ({}) and the tables it committed could not be \
+                 restored ({}), see Connection::recover_transactions",
+                err, e
+            ),
+        },
+    }
+}
+
+/// Restore the tables committed by the transaction of `record` to their
+/// version before the transaction
+async fn restore_tables(connection: &Connection, record: &TransactionRecord) -> Result<()> {
+    for table in record.tables.iter().rev() {
+        let Some(committed_version) = table.committed_version else {
+            continue;
+        };
+        let native = connection.open_table(&table.name).execute().await?;
+        let native = native
+            .as_native()
+            .expect("transactions only write native tables");
+        native
+            .restore_prepared(table.read_version, committed_version)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Roll back the transactions of `connection` whose commit was interrupted
+///
+/// The records of committed transactions are removed, and the ones of
+/// commits which may still be in progress are left alone.  Returns the number
+/// of transactions rolled back.
+pub(crate) async fn recover(connection: &Connection) -> Result<usize> {
+    let (store, dir) = connection.transaction_log()?;
+    let paths = store
+        .list(Some(&dir))
+        .map_ok(|meta| meta.location)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let mut rolled_back = 0;
+    for path in &paths {
+        let record = TransactionRecord::read(store.as_ref(), path).await?;
+        if record.committed {
+            store.delete(path).await?;
+            log::info!("Removed the record of the committed transaction {}", path);
+            continue;
+        }
+        if record.in_flight() {
+            log::debug!("Skipped the transaction {}, still being committed", path);
+            continue;
+        }
+        restore_tables(connection, &record).await?;
+        store.delete(path).await?;
+        rolled_back += 1;
+        log::info!("Rolled back the interrupted transaction {}", path);
+    }
+    Ok(rolled_back)
+}
+
+impl NativeTable {
+    /// Write the data and the deletion files of `writes`, without committing
+    /// them
+    async fn prepare_writes(&self, writes: StagedWrites) -> Result<PreparedWrites> {
+        self.dataset.ensure_mutable().await?;
+        if !writes.deletes.is_empty() && self.soft_delete_enabled().await? {
+            return Err(Error::NotSupported {
+                message: format!(
+                    "soft deletes of the table {} can't be part of a transaction",
+                    self.name
+                ),
+            });
+        }
+        let dataset = self.dataset.get().await?.clone();
+        let read_version = dataset.version().version;
+        let mut operations = Vec::new();
+
+        if !writes.deletes.is_empty() {
+            let predicate = writes
+                .deletes
+                .iter()
+                .map(|predicate| format!("({})", predicate))
+                .collect::<Vec<_>>()
+                .join(" OR ");
+            if let Some((operation, _)) = Self::stage_delete(&dataset, &predicate).await? {
+                operations.push(operation);
+            }
+        }
+
+        let mut quota_writes = Vec::new();
+        let params = self.sidecar_write_params(WriteMode::Append)?;
+        let mut fragments = Vec::new();
+        for data in writes.adds {
+            let data = self.with_embeddings(data).await?;
+            Self::check_bulk_ingest_schema(&dataset, &data.schema())?;
+            let (data, vector_check) =
+                validate::check_vectors(&Schema::from(dataset.schema()), data)?;
+            let (data, quota_write) = self.start_quota_write(data, false)?;
+            quota_writes.extend(quota_write);
+            let written = self
+                .write_fragments(data, BulkIngestOptions::default(), &params)
+                .await;
+            fragments.extend(vector_check.finish(written)?);
+        }
+        if !fragments.is_empty() {
+            operations.push(Operation::Append { fragments });
+        }
+        Ok(PreparedWrites {
+            read_version,
+            operations,
+            quota_writes,
+        })
+    }
+
+    /// Commit the operations of `writes`, the writes of the table `index` of
+    /// the transaction of `journal`
+    ///
+    /// Each version committed is recorded in the journal before the next
+    /// operation is committed, so that a failure or a crash after any of them
+    /// restores the table.  Returns the new version.  A conflict with a
+    /// concurrent writer is not retried.
+    async fn commit_prepared(
+        &self,
+        writes: PreparedWrites,
+        journal: &mut Journal<'_>,
+        index: usize,
+    ) -> Result<u64> {
+        self.run_with_hooks("transaction", async move {
+            let params = self.sidecar_write_params(WriteMode::Append)?;
+            let mut version = writes.read_version;
+            let num_operations = writes.operations.len();
+            for (i, operation) in writes.operations.into_iter().enumerate() {
+                let dataset = Dataset::commit(
+                    &self.uri,
+                    operation,
+                    Some(version),
+                    params.store_params.clone(),
+                    self.commit_handler.clone(),
+                )
+                .await
+                .map_err(|e| self.commit_conflict("transaction", 1, e.into()))?;
+                version = dataset.version().version;
+                self.dataset.set_latest(dataset).await?;
+                journal.committed(index, version).await?;
+                if i + 1 < num_operations && fail_between_commits() {
+                    return Err(Error::Runtime {
+                        message: "injected failure between the commits of a table".to_string(),
+                    });
+                }
+            }
+            for write in writes.quota_writes {
+                self.finish_quota_write(Some(write), Ok(()))?;
+            }
+            self.update_quota_usage().await;
+            Ok(version)
+        })
+        .await
+    }
+
+    /// Restore the table to `read_version`, after a transaction committed
+    /// `committed_version`
+    ///
+    /// Fails if the table was written after the transaction.
+    async fn restore_prepared(&self, read_version: u64, committed_version: u64) -> Result<()> {
+        let latest = self.dataset.get().await?.latest_version_id().await?;
+        if latest == read_version {
+            // The transaction never committed this table
+            return Ok(());
+        }
+        if latest != committed_version {
+            return Err(Error::InvalidInput {
+                message: format!(
+                    "the table {} was written after the transaction committed version {}, \
+                     restore version {} by hand",
+                    self.name, committed_version, read_version
+                ),
+            });
+        }
+        let params = self.sidecar_write_params(WriteMode::Append)?;
+        let dataset = Dataset::commit(
+            &self.uri,
+            Operation::Restore {
+                version: read_version,
+            },
+            Some(committed_version),
+            params.store_params,
+            self.commit_handler.clone(),
+        )
+        .await?;
+        self.dataset.set_latest(dataset).await?;
+        self.update_quota_usage().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator};
+    use arrow_schema::SchemaRef;
+    use arrow_schema::{DataType, Field};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    fn ids(schema: &SchemaRef, ids: Vec<i32>) -> impl RecordBatchReader + Send + 'static {
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(ids))]).unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema.clone())
+    }
+
+    #[tokio::test]
+    async fn test_transaction() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let documents = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let chunks = Arc::new(Schema::new(vec![Field::new(
+            "document_id",
+            DataType::Int32,
+            false,
+        )]));
+        let docs = db
+            .create_table("documents", ids(&documents, vec![1, 2]))
+            .execute()
+            .await
+            .unwrap();
+        let chunk_table = db
+            .create_table("chunks", ids(&chunks, vec![1, 1, 2, 2]))
+            .execute()
+            .await
+            .unwrap();
+
+        // Replace the document 2 and its chunks
+        let mut transaction = db.transaction();
+        transaction
+            .delete("documents", "id = 2")
+            .unwrap()
+            .delete("chunks", "document_id = 2")
+            .unwrap()
+            .add("documents", ids(&documents, vec![2]))
+            .unwrap()
+            .add("chunks", ids(&chunks, vec![2, 2, 2]))
+            .unwrap();
+        let versions = transaction.commit().await.unwrap();
+        assert_eq!(versions.len(), 2);
+        // The transaction wrote the tables through handles of its own
+        docs.checkout_latest().await.unwrap();
+        chunk_table.checkout_latest().await.unwrap();
+        assert_eq!(docs.count_rows(None).await.unwrap(), 2);
+        assert_eq!(
+            chunk_table
+                .count_rows(Some("document_id = 2".to_string()))
+                .await
+                .unwrap(),
+            3
+        );
+
+        // The add to the chunks fails, so the delete of the documents is
+        // not applied either
+        let docs_version = docs.version().await.unwrap();
+        let chunks_version = chunk_table.version().await.unwrap();
+        let mut transaction = db.transaction();
+        transaction
+            .delete("documents", "id = 1")
+            .unwrap()
+            .add("chunks", ids(&documents, vec![1]))
+            .unwrap();
+        assert!(transaction.commit().await.is_err());
+        assert_eq!(docs.version().await.unwrap(), docs_version);
+        assert_eq!(chunk_table.version().await.unwrap(), chunks_version);
+        assert_eq!(docs.count_rows(None).await.unwrap(), 2);
+
+        // The delete of the documents is committed but not their add, the
+        // delete is rolled back
+        FAIL_BETWEEN_COMMITS.with(|fail| fail.set(true));
+        let mut transaction = db.transaction();
+        transaction
+            .delete("documents", "id = 1")
+            .unwrap()
+            .add("documents", ids(&documents, vec![3]))
+            .unwrap();
+        let result = transaction.commit().await;
+        FAIL_BETWEEN_COMMITS.with(|fail| fail.set(false));
+        assert!(result.is_err());
+        // The delete was committed, then restored
+        docs.checkout_latest().await.unwrap();
+        assert_eq!(docs.version().await.unwrap(), docs_version + 2);
+        assert_eq!(docs.count_rows(None).await.unwrap(), 2);
+        assert_eq!(
+            docs.count_rows(Some("id = 1".to_string())).await.unwrap(),
+            1
+        );
+        assert_eq!(db.recover_transactions().await.unwrap(), 0);
+        let docs_version = docs.version().await.unwrap();
+
+        // The tables committed by an interrupted commit are restored
+        docs.delete("id = 1").await.unwrap();
+        let expired =
+            chrono::Utc::now().timestamp_millis() - TRANSACTION_LEASE.as_millis() as i64 - 1000;
+        let record = deleted_documents(docs_version, docs.version().await.unwrap(), expired);
+        let (store, dir) = db.transaction_log().unwrap();
+        record
+            .write(store.as_ref(), &dir.child("interrupted.json"))
+            .await
+            .unwrap();
+        assert_eq!(db.recover_transactions().await.unwrap(), 1);
+        docs.checkout_latest().await.unwrap();
+        assert_eq!(docs.count_rows(None).await.unwrap(), 2);
+        assert_eq!(db.recover_transactions().await.unwrap(), 0);
+    }
+
+    fn deleted_documents(
+        read_version: u64,
+        committed_version: u64,
+        updated_at: i64,
+    ) -> TransactionRecord {
+        TransactionRecord {
+            tables: vec![TableRecord {
+                name: "documents".to_string(),
+                read_version,
+                committed_version: Some(committed_version),
+            }],
+            committed: false,
+            updated_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recover_skips_committed_and_in_flight() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let documents = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let docs = db
+            .create_table("documents", ids(&documents, vec![1, 2]))
+            .execute()
+            .await
+            .unwrap();
+        let read_version = docs.version().await.unwrap();
+        docs.delete("id = 1").await.unwrap();
+        let committed_version = docs.version().await.unwrap();
+        let (store, dir) = db.transaction_log().unwrap();
+        let expired =
+            chrono::Utc::now().timestamp_millis() - TRANSACTION_LEASE.as_millis() as i64 - 1000;
+
+        // A commit which failed to remove its record is not rolled back, and
+        // its record is removed
+        let mut record = deleted_documents(read_version, committed_version, expired);
+        record.committed = true;
+        let committed = dir.child("committed.json");
+        record.write(store.as_ref(), &committed).await.unwrap();
+        assert_eq!(db.recover_transactions().await.unwrap(), 0);
+        assert!(store.head(&committed).await.is_err());
+        assert_eq!(docs.count_rows(None).await.unwrap(), 1);
+
+        // A commit still in progress is left alone
+        let record = deleted_documents(
+            read_version,
+            committed_version,
+            chrono::Utc::now().timestamp_millis(),
+        );
+        let in_flight = dir.child("in-flight.json");
+        record.write(store.as_ref(), &in_flight).await.unwrap();
+        assert_eq!(db.recover_transactions().await.unwrap(), 0);
+        assert!(store.head(&in_flight).await.is_ok());
+        docs.checkout_latest().await.unwrap();
+        assert_eq!(docs.version().await.unwrap(), committed_version);
+        assert_eq!(docs.count_rows(None).await.unwrap(), 1);
+    }
+}
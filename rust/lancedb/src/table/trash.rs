@@ -0,0 +1,148 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Soft deletes
+//!
+//! When soft delete is enabled for a table (see
+//! [`crate::connection::CreateTableBuilder::soft_delete`]) the rows removed by
+//! [`super::Table::delete`] are moved into a hidden "trash" dataset, stored in
+//! the table's directory, instead of being discarded.  They can be queried with
+//! [`crate::query::QueryBase::only_deleted`] and restored with
+//! [`super::Table::undelete`] until the table is compacted, which empties the trash.
+
+use std::sync::Arc;
+
+use arrow_array::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_schema::Schema;
+use chrono::Duration;
+use futures::TryStreamExt;
+use lance::dataset::WriteMode;
+use lance::Dataset;
+
+use super::NativeTable;
+use crate::error::{Error, Result};
+
+/// The schema metadata key which marks a table as using soft deletes
+pub const SOFT_DELETE_METADATA_KEY: &str = "lancedb::soft_delete";
+const TRASH_DIR: &str = "_trash";
+
+/// Mark the table created from `data` as using soft deletes
+pub fn with_soft_delete(
+    data: Box<dyn RecordBatchReader + Send>,
+) -> Box<dyn RecordBatchReader + Send> {
+    let mut metadata = data.schema().metadata().clone();
+    metadata.insert(SOFT_DELETE_METADATA_KEY.to_string(), "true".to_string());
+    let schema = Arc::new(data.schema().as_ref().clone().with_metadata(metadata));
+    let batch_schema = schema.clone();
+    Box::new(RecordBatchIterator::new(
+        data.map(move |batch| batch.and_then(|batch| batch.with_schema(batch_schema.clone()))),
+        schema,
+    ))
+}
+
+async fn scan(dataset: &Dataset, filter: &str) -> Result<Vec<RecordBatch>> {
+    let mut scanner = dataset.scan();
+    scanner.filter(filter)?;
+    Ok(scanner.try_into_stream().await?.try_collect().await?)
+}
+
+fn reader(dataset: &Dataset, batches: Vec<RecordBatch>) -> impl RecordBatchReader + Send {
+    RecordBatchIterator::new(
+        batches.into_iter().map(Ok),
+        Arc::new(Schema::from(dataset.schema())),
+    )
+}
+
+impl NativeTable {
+    fn trash_uri(&self) -> String {
+        format!("{}/{}", self.uri.trim_end_matches('/'), TRASH_DIR)
+    }
+
+    /// Returns true if rows deleted from the table are moved to the trash
+    pub(super) async fn soft_delete_enabled(&self) -> Result<bool> {
+        Ok(self
+            .dataset
+            .get()
+            .await?
+            .schema()
+            .metadata
+            .get(SOFT_DELETE_METADATA_KEY)
+            .is_some_and(|value| value == "true"))
+    }
+
+    /// Open the trash, creating it if nothing has been deleted yet
+    pub(super) async fn trash(&self) -> Result<Dataset> {
+        if !self.soft_delete_enabled().await? {
+            return Err(Error::InvalidInput {
+                message: format!("soft delete is not enabled for the table {}", self.name),
+            });
+        }
+        if let Some(trash) = self.open_sidecar(&self.trash_uri()).await? {
+            return Ok(trash);
+        }
+        let dataset = self.dataset.get().await?;
+        Ok(Dataset::write(
+            reader(&dataset, Vec::new()),
+            &self.trash_uri(),
+            Some(self.sidecar_write_params(WriteMode::Create)?),
+        )
+        .await?)
+    }
+
+    /// Copy the rows matching `predicate` to the trash
+    ///
+    /// The rows are deleted from the table by the caller.
+    pub(super) async fn move_to_trash(&self, predicate: &str) -> Result<()> {
+        let mut trash = self.trash().await?;
+        let dataset = self.dataset.get().await?.clone();
+        let batches = scan(&dataset, predicate).await?;
+        if batches.iter().all(|batch| batch.num_rows() == 0) {
+            return Ok(());
+        }
+        // The trash already has the store of the table, lance rejects store params here
+        trash.append(reader(&dataset, batches), None).await?;
+        Ok(())
+    }
+
+    /// Move the rows matching `filter` from the trash back into the table
+    pub(super) async fn restore_from_trash(&self, filter: &str) -> Result<()> {
+        let mut trash = self.trash().await?;
+        let batches = scan(&trash, filter).await?;
+        if batches.iter().all(|batch| batch.num_rows() == 0) {
+            return Ok(());
+        }
+        let mut dataset = self.dataset.get_mut().await?;
+        let data = reader(&dataset, batches);
+        dataset.append(data, None).await?;
+        trash.delete(filter).await?;
+        Ok(())
+    }
+
+    /// Permanently remove the rows in the trash
+    pub(super) async fn empty_trash(&self) -> Result<()> {
+        let Some(trash) = self.open_sidecar(&self.trash_uri()).await? else {
+            return Ok(());
+        };
+        let trash = Dataset::write(
+            reader(&trash, Vec::new()),
+            &self.trash_uri(),
+            Some(self.sidecar_write_params(WriteMode::Overwrite)?),
+        )
+        .await?;
+        trash
+            .cleanup_old_versions(Duration::zero(), Some(true))
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Expiry of old rows
+//!
+//! A table can declare a time to live when it is created, see
+//! [`crate::connection::CreateTableBuilder::ttl`]: a timestamp or date column
+//! and a retention.  The rows whose value in the column is older than the
+//! retention are expired, and are deleted by [`super::OptimizeAction::Expire`]
+//! (which is part of [`super::OptimizeAction::All`]).  Until then they are
+//! still returned by queries.  Rows whose value is null never expire.
+//!
+//! Expired rows are deleted like the rows removed by [`super::Table::delete`]:
+//! they are moved to the trash of tables using soft deletes, and their space
+//! is reclaimed once the table is compacted and its old versions are pruned.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow_array::{RecordBatchIterator, RecordBatchReader};
+use arrow_schema::{DataType, Schema};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{NativeTable, TableInternal};
+use crate::error::{Error, Result};
+
+/// The schema metadata key which records the time to live of the rows
+pub(crate) const TTL_METADATA_KEY: &str = "lancedb::ttl";
+
+/// The time to live of the rows of a table, see the [module docs](self)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ttl {
+    /// The timestamp or date column the age of a row is measured from
+    pub column: String,
+    /// How long the rows are kept
+    pub retention: Duration,
+}
+
+impl Ttl {
+    /// Rows expire `retention` after the time in their `column`
+    pub fn new(column: impl Into<String>, retention: Duration) -> Self {
+        Self {
+            column: column.into(),
+            retention,
+        }
+    }
+
+    /// The filter matching the rows expired at `now`, in a column of type
+    /// `data_type`
+    fn expired_filter(&self, data_type: &DataType, now: DateTime<Utc>) -> Result<String> {
+        let retention =
+            chrono::Duration::from_std(self.retention).map_err(|e| Error::InvalidInput {
+                message: format!("invalid retention {:?}: {}", self.retention, e),
+            })?;
+        let cutoff = now - retention;
+        let literal = match data_type {
+            DataType::Date32 | DataType::Date64 => {
+                format!("DATE '{}'", cutoff.format("%Y-%m-%d"))
+            }
+            _ => format!("TIMESTAMP '{}'", cutoff.format("%Y-%m-%d %H:%M:%S%.6f")),
+        };
+        Ok(format!(
+            "`{}` < {}",
+            self.column.replace('`', "``"),
+            literal
+        ))
+    }
+}
+
+/// The time to live of the rows of a table with the schema `schema`, if it
+/// has one
+pub(crate) fn ttl(schema: &Schema) -> Result<Option<Ttl>> {
+    schema
+        .metadata()
+        .get(TTL_METADATA_KEY)
+        .map(|ttl| {
+            serde_json::from_str(ttl).map_err(|e| Error::Schema {
+                message: format!("invalid time to live {}: {}", ttl, e),
+            })
+        })
+        .transpose()
+}
+
+/// Expire the rows of the table created from `data` according to `ttl`
+pub(crate) fn with_ttl(
+    data: Box<dyn RecordBatchReader + Send>,
+    ttl: &Ttl,
+) -> Result<Box<dyn RecordBatchReader + Send>> {
+    let schema = data.schema();
+    let field = schema
+        .field_with_name(&ttl.column)
+        .map_err(|_| Error::InvalidInput {
+            message: format!(
+                "the time to live column '{}' is not in the data",
+                ttl.column
+            ),
+        })?;
+    if !matches!(
+        field.data_type(),
+        DataType::Timestamp(_, _) | DataType::Date32 | DataType::Date64
+    ) {
+        return Err(Error::InvalidInput {
+            message: format!(
+                "the time to live column '{}' must be a timestamp or a date, not {}",
+                ttl.column,
+                field.data_type()
+            ),
+        });
+    }
+    let mut metadata = schema.metadata().clone();
+    metadata.insert(
+        TTL_METADATA_KEY.to_string(),
+        serde_json::to_string(ttl).map_err(|e| Error::Runtime {
+            message: format!("failed to encode the time to live: {}", e),
+        })?,
+    );
+    let schema = Arc::new(schema.as_ref().clone().with_metadata(metadata));
+    let batch_schema = schema.clone();
+    Ok(Box::new(RecordBatchIterator::new(
+        data.map(move |batch| batch.and_then(|batch| batch.with_schema(batch_schema.clone()))),
+        schema,
+    )))
+}
+
+impl NativeTable {
+    /// Delete the rows whose time to live expired
+    ///
+    /// Returns the number of rows deleted, or None if the table has no time to
+    /// live.
+    pub(super) async fn expire(&self) -> Result<Option<u64>> {
+        let Some(filter) = self.expired_rows_filter().await? else {
+            return Ok(None);
+        };
+        Ok(Some(self.delete(&filter).await?.num_deleted_rows))
+    }
+
+    /// The filter of the rows whose time to live expired, or None if the table
+    /// has no time to live
+    pub(super) async fn expired_rows_filter(&self) -> Result<Option<String>> {
+        let schema = self.schema().await?;
+        let Some(ttl) = ttl(&schema)? else {
+            return Ok(None);
+        };
+        let field = schema.field_with_name(&ttl.column)?;
+        Ok(Some(ttl.expired_filter(field.data_type(), Utc::now())?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int32Array, RecordBatch, TimestampMicrosecondArray};
+    use arrow_schema::{Field, TimeUnit};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+    use crate::table::OptimizeAction;
+
+    #[tokio::test]
+    async fn test_expire() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(
+                "created_at",
+                DataType::Timestamp(TimeUnit::Microsecond, None),
+                true,
+            ),
+        ]));
+        let now = Utc::now().timestamp_micros();
+        let hour = 3600 * 1_000_000;
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2, 3])),
+                // Not a null, which lance reads back as zero, the epoch
+                Arc::new(TimestampMicrosecondArray::from(vec![
+                    Some(now - 2 * hour),
+                    Some(now),
+                    Some(now + hour),
+                ])),
+            ],
+        )
+        .unwrap();
+        let table = db
+            .create_table(
+                "cache",
+                RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+            )
+            .ttl("created_at", Duration::from_secs(3600))
+            .execute()
+            .await
+            .unwrap();
+
+        let stats = table.optimize(OptimizeAction::Expire).await.unwrap();
+        assert_eq!(stats.expired_rows, Some(1));
+        assert_eq!(table.count_rows(None).await.unwrap(), 2);
+        assert_eq!(
+            table.count_rows(Some("id = 1".to_string())).await.unwrap(),
+            0
+        );
+
+        let result = db
+            .create_empty_table("invalid", schema)
+            .ttl("id", Duration::from_secs(3600))
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+    }
+}
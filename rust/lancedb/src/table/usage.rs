@@ -0,0 +1,254 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Table statistics
+//!
+//! The statistics of a table (see [`super::Table::stats`]) describe its rows,
+//! its fragments and the storage used by its data files and indices.  They
+//! are read from the manifest of the table and a listing of its directory, the
+//! data itself is not scanned.
+
+use std::collections::{BTreeMap, HashMap};
+
+use futures::TryStreamExt;
+use lance::datatypes::Field;
+use lance::io::{ObjectStore, ObjectStoreParams};
+use lance_index::DatasetIndexExt;
+
+use super::pack::{DATA_DIR, INDICES_DIR};
+use super::NativeTable;
+use crate::error::Result;
+use crate::query::budget::value_bytes;
+
+/// The storage used by an index
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSize {
+    /// The name of the index
+    pub name: String,
+    /// The columns covered by the index
+    pub columns: Vec<String>,
+    /// The size of the files of the index, in bytes
+    pub bytes: u64,
+}
+
+/// Statistics describing the rows and the storage of a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStatistics {
+    /// The number of rows of the table
+    pub num_rows: usize,
+    /// The number of rows deleted but still stored in the data files
+    ///
+    /// These rows are removed when the table is compacted, see
+    /// [`super::OptimizeAction::Compact`].
+    pub num_deleted_rows: usize,
+    /// The number of fragments of the table
+    pub num_fragments: usize,
+    /// The size of the data files of the current version, in bytes
+    pub data_bytes: u64,
+    /// The size of all of the files in the directory of the table, in bytes
+    ///
+    /// This includes the files of the older versions of the table, which are
+    /// removed when the versions are pruned, see [`super::OptimizeAction::Prune`].
+    pub total_bytes: u64,
+    /// The storage used by each index of the table
+    pub indices: Vec<IndexSize>,
+    /// The estimated compressed size of each column, in bytes
+    ///
+    /// The data files do not record the size of each column, so the size of a
+    /// data file is divided between its columns in proportion to the expected
+    /// width of their values.  The sizes add up to [`Self::data_bytes`].
+    pub columns: BTreeMap<String, u64>,
+}
+
+/// Map the ids of `field` and its children to the name of the top level column
+fn collect_field_ids<'a>(field: &Field, column: &'a str, columns: &mut HashMap<i32, &'a str>) {
+    columns.insert(field.id, column);
+    for child in &field.children {
+        collect_field_ids(child, column, columns);
+    }
+}
+
+impl NativeTable {
+    pub(super) async fn stats_impl(&self) -> Result<TableStatistics> {
+        let dataset = self.dataset.get().await?.clone();
+        let params = ObjectStoreParams {
+            storage_options: Some(self.storage_options.clone()),
+            ..Default::default()
+        };
+        let (store, dir) = ObjectStore::from_uri_and_params(&self.uri, &params).await?;
+        let sizes = store
+            .inner
+            .list(Some(&dir))
+            .map_ok(|meta| {
+                let relative = meta
+                    .location
+                    .prefix_match(&dir)
+                    .expect("listed files are under the table directory")
+                    .map(|part| part.as_ref().to_string())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                (relative, meta.size as u64)
+            })
+            .try_collect::<HashMap<_, _>>()
+            .await?;
+
+        let schema = dataset.schema();
+        let mut column_of = HashMap::new();
+        for field in &schema.fields {
+            collect_field_ids(field, &field.name, &mut column_of);
+        }
+        let widths = schema
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), value_bytes(&field.data_type()) as u64))
+            .collect::<HashMap<_, _>>();
+        let mut columns = schema
+            .fields
+            .iter()
+            .map(|field| (field.name.clone(), 0))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut data_bytes = 0;
+        for fragment in dataset.get_fragments() {
+            for file in &fragment.metadata().files {
+                let size = sizes
+                    .get(&format!("{}/{}", DATA_DIR, file.path))
+                    .copied()
+                    .unwrap_or_default();
+                data_bytes += size;
+
+                let mut file_columns = file
+                    .fields
+                    .iter()
+                    .filter_map(|id| column_of.get(id).copied())
+                    .collect::<Vec<_>>();
+                file_columns.sort_unstable();
+                file_columns.dedup();
+                let total_width = file_columns
+                    .iter()
+                    .map(|column| widths[column])
+                    .sum::<u64>()
+                    .max(1);
+                // The remainder of the division goes to the last column, so that
+                // the sizes of the columns add up to the size of the file
+                let mut remaining = size;
+                for (i, column) in file_columns.iter().enumerate() {
+                    let bytes = if i + 1 == file_columns.len() {
+                        remaining
+                    } else {
+                        size * widths[column] / total_width
+                    };
+                    remaining -= bytes;
+                    *columns.get_mut(*column).expect("columns of the schema") += bytes;
+                }
+            }
+        }
+
+        let indices = dataset
+            .load_indices()
+            .await?
+            .iter()
+            .map(|index| {
+                let prefix = format!("{}/{}/", INDICES_DIR, index.uuid);
+                IndexSize {
+                    name: index.name.clone(),
+                    columns: index
+                        .fields
+                        .iter()
+                        .filter_map(|id| column_of.get(id).map(|column| column.to_string()))
+                        .collect(),
+                    bytes: sizes
+                        .iter()
+                        .filter(|(path, _)| path.starts_with(&prefix))
+                        .map(|(_, size)| size)
+                        .sum(),
+                }
+            })
+            .collect();
+
+        Ok(TableStatistics {
+            num_rows: dataset.count_rows(None).await?,
+            num_deleted_rows: dataset.count_deleted_rows().await?,
+            num_fragments: dataset.count_fragments(),
+            data_bytes,
+            total_bytes: sizes.values().sum(),
+            indices,
+            columns,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{
+        Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader, StringArray,
+    };
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use crate::connect;
+    use crate::index::Index;
+
+    fn batch(start: i32) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(start..start + 100)),
+                Arc::new(StringArray::from_iter_values(
+                    (start..start + 100).map(|i| format!("name {}", i)),
+                )),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db.create_table("t", batch(0)).execute().await.unwrap();
+        table.add(batch(100)).execute().await.unwrap();
+        table.delete("id < 10").await.unwrap();
+        table
+            .create_index(&["id"], Index::Auto)
+            .execute()
+            .await
+            .unwrap();
+
+        let stats = table.stats().await.unwrap();
+        assert_eq!(stats.num_rows, 190);
+        assert_eq!(stats.num_deleted_rows, 10);
+        assert_eq!(stats.num_fragments, 2);
+        assert!(stats.data_bytes > 0);
+        assert!(stats.total_bytes > stats.data_bytes);
+        assert_eq!(stats.columns.keys().collect::<Vec<_>>(), vec!["id", "name"]);
+        assert_eq!(stats.columns.values().sum::<u64>(), stats.data_bytes);
+        assert!(stats.columns["name"] > stats.columns["id"]);
+
+        assert_eq!(stats.indices.len(), 1);
+        assert_eq!(stats.indices[0].columns, vec!["id"]);
+        assert!(stats.indices[0].bytes > 0);
+        assert!(stats.total_bytes >= stats.data_bytes + stats.indices[0].bytes);
+    }
+}
@@ -0,0 +1,346 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Validation of the vectors added to a table
+//!
+//! The vector columns of a table are its `FixedSizeList` columns.  The vectors
+//! added to them must have the dimension and the value type of the column.
+//! Otherwise the write fails with an error about the schema of the dataset, or
+//! the vectors are only rejected later, when an index is built.  The added
+//! batches are checked as they are written and the first mismatch is reported
+//! with [`Error::VectorMismatch`], naming the column and the batch.  Vectors
+//! added as lists of the right length are converted to the fixed size lists of
+//! the column.
+
+use std::sync::{Arc, Mutex};
+
+use arrow::compute::cast;
+use arrow_array::{Array, GenericListArray, OffsetSizeTrait, RecordBatch, RecordBatchReader};
+use arrow_schema::{ArrowError, DataType, Schema};
+
+use crate::error::{Error, Result};
+
+/// Vectors of the added data which do not match their column
+#[derive(Debug, Clone)]
+struct Mismatch {
+    column: String,
+    batch: usize,
+    expected_dim: i32,
+    actual_dim: i32,
+    expected_type: DataType,
+    actual_type: DataType,
+}
+
+impl Mismatch {
+    fn into_error(self) -> Error {
+        Error::VectorMismatch {
+            column: self.column,
+            batch: self.batch,
+            expected_dim: self.expected_dim,
+            actual_dim: self.actual_dim,
+            expected_type: self.expected_type.to_string(),
+            actual_type: self.actual_type.to_string(),
+        }
+    }
+}
+
+/// A vector column of a table
+struct VectorColumn {
+    name: String,
+    dim: i32,
+    value_type: DataType,
+}
+
+impl VectorColumn {
+    fn mismatch(&self, batch: usize, actual_dim: i32, actual_type: &DataType) -> Mismatch {
+        Mismatch {
+            column: self.name.clone(),
+            batch,
+            expected_dim: self.dim,
+            actual_dim,
+            expected_type: self.value_type.clone(),
+            actual_type: actual_type.clone(),
+        }
+    }
+
+    /// Check the declared type of the column in the added data
+    ///
+    /// The dimension of variable size lists is only known from their values.
+    fn check_type(&self, batch: usize, data_type: &DataType) -> Option<Mismatch> {
+        match data_type {
+            DataType::FixedSizeList(item, dim) => (*dim != self.dim
+                || item.data_type() != &self.value_type)
+                .then(|| self.mismatch(batch, *dim, item.data_type())),
+            DataType::List(item) | DataType::LargeList(item) => (item.data_type()
+                != &self.value_type)
+                .then(|| self.mismatch(batch, self.dim, item.data_type())),
+            // Columns of other types are rejected by the write
+            _ => None,
+        }
+    }
+
+    fn check_list<O: OffsetSizeTrait>(
+        &self,
+        batch: usize,
+        list: &GenericListArray<O>,
+    ) -> Option<Mismatch> {
+        (0..list.len())
+            .filter(|&i| list.is_valid(i))
+            .map(|i| list.value_length(i).as_usize() as i32)
+            .find(|&dim| dim != self.dim)
+            .map(|dim| self.mismatch(batch, dim, &list.value_type()))
+    }
+
+    fn check_array(&self, batch: usize, array: &dyn Array) -> Option<Mismatch> {
+        self.check_type(batch, array.data_type())
+            .or_else(|| match array.data_type() {
+                DataType::List(_) => self.check_list::<i32>(batch, array.as_any().downcast_ref()?),
+                DataType::LargeList(_) => {
+                    self.check_list::<i64>(batch, array.as_any().downcast_ref()?)
+                }
+                _ => None,
+            })
+    }
+}
+
+/// The mismatch found by the check of [`check_vectors`], if any
+#[derive(Default)]
+pub(super) struct VectorCheck {
+    mismatch: Arc<Mutex<Option<Mismatch>>>,
+}
+
+impl VectorCheck {
+    /// Replace the error of the write of the checked data with the mismatch
+    /// which caused it
+    pub(super) fn finish<T>(self, result: Result<T>) -> Result<T> {
+        match result {
+            Ok(value) => Ok(value),
+            Err(err) => match self.mismatch.lock()?.take() {
+                Some(mismatch) => Err(mismatch.into_error()),
+                None => Err(err),
+            },
+        }
+    }
+}
+
+/// Check the vectors of `data`, added to a table of `schema`
+///
+/// The declared schema of `data` is checked right away, the vectors of each
+/// batch as the batch is read.  A mismatch in a batch fails the read, the
+/// write should then be finished with [`VectorCheck::finish`].
+pub(super) fn check_vectors(
+    schema: &Schema,
+    data: Box<dyn RecordBatchReader + Send>,
+) -> Result<(Box<dyn RecordBatchReader + Send>, VectorCheck)> {
+    let data_schema = data.schema();
+    let columns = schema
+        .fields()
+        .iter()
+        .filter_map(|field| match field.data_type() {
+            DataType::FixedSizeList(item, dim) => Some(VectorColumn {
+                name: field.name().clone(),
+                dim: *dim,
+                value_type: item.data_type().clone(),
+            }),
+            _ => None,
+        })
+        .filter(|column| data_schema.column_with_name(&column.name).is_some())
+        .collect::<Vec<_>>();
+    for column in &columns {
+        let (_, field) = data_schema.column_with_name(&column.name).unwrap();
+        if let Some(mismatch) = column.check_type(0, field.data_type()) {
+            return Err(mismatch.into_error());
+        }
+    }
+    let check = VectorCheck::default();
+    if columns.is_empty() {
+        return Ok((data, check));
+    }
+
+    // The vectors added as lists are written as the fixed size lists of the table
+    let output_schema = Arc::new(Schema::new_with_metadata(
+        data_schema
+            .fields()
+            .iter()
+            .map(
+                |field| match (field.data_type(), schema.field_with_name(field.name())) {
+                    (DataType::List(_) | DataType::LargeList(_), Ok(table_field))
+                        if matches!(table_field.data_type(), DataType::FixedSizeList(..)) =>
+                    {
+                        Arc::new(
+                            field
+                                .as_ref()
+                                .clone()
+                                .with_data_type(table_field.data_type().clone()),
+                        )
+                    }
+                    _ => field.clone(),
+                },
+            )
+            .collect::<Vec<_>>(),
+        data_schema.metadata().clone(),
+    ));
+
+    let found = check.mismatch.clone();
+    let batch_schema = output_schema.clone();
+    let data = data.enumerate().map(
+        move |(i, batch): (usize, std::result::Result<RecordBatch, ArrowError>)| {
+            let batch = batch?;
+            for column in &columns {
+                let Some(array) = batch.column_by_name(&column.name) else {
+                    continue;
+                };
+                if let Some(mismatch) = column.check_array(i, array) {
+                    let message = mismatch.clone().into_error().to_string();
+                    *found.lock().unwrap() = Some(mismatch);
+                    return Err(ArrowError::InvalidArgumentError(message));
+                }
+            }
+            let arrays = batch
+                .columns()
+                .iter()
+                .zip(batch_schema.fields())
+                .map(|(array, field)| cast(array, field.data_type()))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            RecordBatch::try_new(batch_schema.clone(), arrays)
+        },
+    );
+    Ok((
+        Box::new(arrow_array::RecordBatchIterator::new(data, output_schema)),
+        check,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::buffer::OffsetBuffer;
+    use arrow_array::types::Float32Type;
+    use arrow_array::{FixedSizeListArray, Float64Array, ListArray, RecordBatchIterator};
+    use arrow_schema::Field;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    fn vectors(dim: i32, rows: usize) -> FixedSizeListArray {
+        FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            (0..rows).map(|_| Some(vec![Some(1.0); dim as usize])),
+            dim,
+        )
+    }
+
+    fn reader(batches: Vec<Arc<dyn Array>>) -> Box<dyn RecordBatchReader + Send> {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "vector",
+            batches[0].data_type().clone(),
+            true,
+        )]));
+        let batches = batches
+            .into_iter()
+            .map(|array| RecordBatch::try_new(schema.clone(), vec![array]))
+            .collect::<Vec<_>>();
+        Box::new(RecordBatchIterator::new(batches, schema))
+    }
+
+    #[tokio::test]
+    async fn test_vector_mismatch() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let table = db
+            .create_table("t", reader(vec![Arc::new(vectors(4, 10))]))
+            .execute()
+            .await
+            .unwrap();
+
+        // A fixed size list of the wrong dimension
+        let err = table
+            .add(reader(vec![Arc::new(vectors(3, 10))]))
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                Error::VectorMismatch {
+                    column,
+                    batch: 0,
+                    expected_dim: 4,
+                    actual_dim: 3,
+                    ..
+                } if column == "vector"
+            ),
+            "{}",
+            err
+        );
+
+        // A list of the wrong value type
+        let values = Arc::new(Float64Array::from(vec![1.0; 4]));
+        let list = ListArray::new(
+            Arc::new(Field::new("item", DataType::Float64, true)),
+            OffsetBuffer::from_lengths([4]),
+            values,
+            None,
+        );
+        let err = table
+            .add(reader(vec![Arc::new(list)]))
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::VectorMismatch { actual_type, .. } if actual_type == "Float64"),
+            "{}",
+            err
+        );
+
+        // A list whose second batch has a vector of the wrong length
+        let list = |lengths: Vec<usize>| -> Arc<dyn Array> {
+            let total = lengths.iter().sum::<usize>();
+            Arc::new(ListArray::new(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                OffsetBuffer::from_lengths(lengths),
+                Arc::new(arrow_array::Float32Array::from(vec![1.0; total])),
+                None,
+            ))
+        };
+        let err = table
+            .add(reader(vec![list(vec![4, 4]), list(vec![4, 5])]))
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                Error::VectorMismatch {
+                    batch: 1,
+                    actual_dim: 5,
+                    ..
+                }
+            ),
+            "{}",
+            err
+        );
+        assert_eq!(table.count_rows(None).await.unwrap(), 10);
+
+        // Lists of the right length are added as vectors
+        table
+            .add(reader(vec![list(vec![4, 4])]))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 12);
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Statistics of a vector column
+//!
+//! The statistics describe the distribution of the embeddings in a column: the
+//! norms of the vectors and their centroid.  Unlike [`super::stats`] they are
+//! not stored, every request scans the column.  Comparing the statistics taken
+//! when a vector index was trained with the current ones shows whether the
+//! embeddings drifted (for example because the embedding model changed, or
+//! because new data is about other topics), in which case the IVF centroids of
+//! the index no longer fit the data and the index should be retrained.  The
+//! recall of the index, estimated with [`crate::eval::RecallEvaluation`],
+//! confirms it.
+
+use arrow_array::{cast::AsArray, types::Float32Type, Array};
+use arrow_schema::DataType;
+use futures::TryStreamExt;
+
+use super::stats::{histogram, HistogramBucket};
+use super::Table;
+use crate::error::{Error, Result};
+use crate::eval::RecallEvaluation;
+use crate::index::IndexType;
+use crate::query::{ExecutableQuery, QueryBase, Select};
+
+/// Statistics describing the vectors of a column
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorStatistics {
+    /// The number of dimensions of the vectors
+    pub dimension: usize,
+    /// The number of rows in the table
+    pub num_rows: usize,
+    /// The number of null vectors
+    pub null_count: usize,
+    /// The smallest L2 norm of a vector, `None` if all vectors are null
+    pub min_norm: Option<f64>,
+    /// The largest L2 norm of a vector, `None` if all vectors are null
+    pub max_norm: Option<f64>,
+    /// The mean L2 norm of the vectors, `None` if all vectors are null
+    pub mean_norm: Option<f64>,
+    /// An equal width histogram of the L2 norms of the vectors
+    pub norm_histogram: Vec<HistogramBucket>,
+    /// The mean of the vectors, `None` if all vectors are null
+    pub centroid: Option<Vec<f32>>,
+    /// The estimated recall@10 of the vector index of the column with the
+    /// default search parameters, `None` if the column has no vector index
+    /// or the estimate was disabled
+    pub approx_recall: Option<f64>,
+}
+
+impl VectorStatistics {
+    /// How far the centroid moved from the centroid of `baseline`
+    ///
+    /// This is the L2 distance between the two centroids divided by the mean
+    /// norm of `baseline`, so that it does not depend on the scale of the
+    /// embeddings.  `None` if either column has no vectors or their dimensions
+    /// differ.
+    pub fn drift(&self, baseline: &Self) -> Option<f64> {
+        let (Some(centroid), Some(base), Some(norm)) =
+            (&self.centroid, &baseline.centroid, baseline.mean_norm)
+        else {
+            return None;
+        };
+        if centroid.len() != base.len() || norm == 0.0 {
+            return None;
+        }
+        let distance = centroid
+            .iter()
+            .zip(base)
+            .map(|(a, b)| (*a as f64 - *b as f64).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        Some(distance / norm)
+    }
+}
+
+/// A builder used to compute the statistics of a vector column
+///
+/// See [`super::Table::vector_stats`] for more context
+pub struct VectorStatsBuilder {
+    table: Table,
+    column: String,
+    recall_sample_size: usize,
+}
+
+impl VectorStatsBuilder {
+    pub(super) fn new(table: Table, column: String) -> Self {
+        Self {
+            table,
+            column,
+            recall_sample_size: 20,
+        }
+    }
+
+    /// The number of query vectors used to estimate the recall, 20 by default
+    ///
+    /// Each query vector is searched with and without the index.  0 disables
+    /// the estimate.  Use [`RecallEvaluation`] for a complete evaluation.
+    pub fn recall_sample_size(mut self, sample_size: usize) -> Self {
+        self.recall_sample_size = sample_size;
+        self
+    }
+
+    /// Scan the column and compute its statistics
+    pub async fn execute(self) -> Result<VectorStatistics> {
+        let schema = self.table.schema().await?;
+        let field = schema.field_with_name(&self.column)?;
+        let dimension = match field.data_type() {
+            DataType::FixedSizeList(item, dimension) if item.data_type().is_floating() => {
+                *dimension as usize
+            }
+            _ => {
+                return Err(Error::InvalidInput {
+                    message: format!("the column {} is not a vector column", self.column),
+                })
+            }
+        };
+
+        let mut stream = self
+            .table
+            .query()
+            .select(Select::columns(&[&self.column]))
+            .execute()
+            .await?;
+        let mut num_rows = 0;
+        let mut null_count = 0;
+        let mut sum = vec![0_f64; dimension];
+        let mut norms = Vec::new();
+        while let Some(batch) = stream.try_next().await? {
+            let vectors = batch.column(0).as_fixed_size_list();
+            num_rows += vectors.len();
+            null_count += vectors.null_count();
+            let values = arrow_cast::cast(vectors.values(), &DataType::Float32)?;
+            let values = values.as_primitive::<Float32Type>().values();
+            for row in 0..vectors.len() {
+                if vectors.is_null(row) {
+                    continue;
+                }
+                let offset = vectors.value_offset(row) as usize;
+                let vector = &values[offset..offset + dimension];
+                let mut norm = 0.0;
+                for (total, value) in sum.iter_mut().zip(vector) {
+                    *total += *value as f64;
+                    norm += (*value as f64).powi(2);
+                }
+                norms.push(norm.sqrt());
+            }
+        }
+
+        let count = norms.len() as f64;
+        let (min_norm, max_norm, mean_norm, centroid) = if norms.is_empty() {
+            (None, None, None, None)
+        } else {
+            (
+                Some(norms.iter().copied().fold(f64::INFINITY, f64::min)),
+                Some(norms.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+                Some(norms.iter().sum::<f64>() / count),
+                Some(sum.iter().map(|total| (total / count) as f32).collect()),
+            )
+        };
+        let approx_recall = if self.recall_sample_size > 0 && !norms.is_empty() {
+            self.approx_recall().await?
+        } else {
+            None
+        };
+        Ok(VectorStatistics {
+            dimension,
+            num_rows,
+            null_count,
+            min_norm,
+            max_norm,
+            mean_norm,
+            norm_histogram: histogram(&norms),
+            centroid,
+            approx_recall,
+        })
+    }
+
+    /// The recall of the vector index of the column, if it has one
+    async fn approx_recall(&self) -> Result<Option<f64>> {
+        let indexed = self.table.list_indices().await?.iter().any(|index| {
+            index.index_type == IndexType::IvfPq && index.columns == [self.column.as_str()]
+        });
+        if !indexed {
+            return Ok(None);
+        }
+        let report = RecallEvaluation::new(&self.table)
+            .column(&self.column)
+            .sample_size(self.recall_sample_size)
+            .execute()
+            .await?;
+        Ok(report.results.first().map(|result| result.recall))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{FixedSizeListArray, Float32Array, RecordBatch, RecordBatchIterator};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use crate::connect;
+
+    #[tokio::test]
+    async fn test_vector_stats() {
+        let tmp_dir = tempdir().unwrap();
+        let db = connect(tmp_dir.path().to_str().unwrap())
+            .execute()
+            .await
+            .unwrap();
+        let field = Arc::new(Field::new("item", DataType::Float32, true));
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "vector",
+            DataType::FixedSizeList(field.clone(), 2),
+            true,
+        )]));
+        // (3, 4), (0, 0) and (6, 8), without nulls, which lance reads back as
+        // vectors of zeros
+        let values = Float32Array::from(vec![3.0, 4.0, 0.0, 0.0, 6.0, 8.0]);
+        let vectors = FixedSizeListArray::try_new(field, 2, Arc::new(values), None).unwrap();
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(vectors)]).unwrap();
+        let table = db
+            .create_table("test", RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await
+            .unwrap();
+
+        let stats = table.vector_stats("vector").execute().await.unwrap();
+        assert_eq!(stats.dimension, 2);
+        assert_eq!(stats.num_rows, 3);
+        assert_eq!(stats.null_count, 0);
+        assert_eq!(stats.min_norm, Some(0.0));
+        assert_eq!(stats.max_norm, Some(10.0));
+        assert_eq!(stats.mean_norm, Some(5.0));
+        assert_eq!(stats.centroid, Some(vec![3.0, 4.0]));
+        assert_eq!(
+            stats.norm_histogram.iter().map(|b| b.count).sum::<usize>(),
+            3
+        );
+        // There is no index to estimate the recall of
+        assert_eq!(stats.approx_recall, None);
+
+        let mut shifted = stats.clone();
+        shifted.centroid = Some(vec![3.0, 9.0]);
+        assert_eq!(shifted.drift(&stats), Some(1.0));
+        assert_eq!(stats.drift(&stats), Some(0.0));
+
+        assert!(table.vector_stats("missing").execute().await.is_err());
+    }
+}
@@ -0,0 +1,464 @@
+// Copyright 2024 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Views over a base table
+//!
+//! A view is a filter and/or projection over a base table.  Queries against a
+//! view are rewritten into queries against the base table.  A materialized view
+//! stores the rows of the view in a table of its own, which is rewritten the next
+//! time the view is read after a commit to the base table.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use arrow_array::{FixedSizeListArray, RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_schema::SchemaRef;
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use lance::dataset::{ColumnAlteration, NewColumnTransform};
+use object_store::path::Path;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use super::{
+    changes::{ChangeStream, ChangesBuilder},
+    cluster::ClusterBuilder,
+    dry_run::{DryRunOperation, DryRunReport},
+    merge::MergeInsertBuilder,
+    migrate::MigrateVectorDimBuilder,
+    pack::PackageInfo,
+    prewarm::{CacheResidency, PrewarmBuilder},
+    snapshot::SnapshotInfo,
+    split::SplitBuilder,
+    stats::ColumnStatistics,
+    usage::TableStatistics,
+    AddDataBuilder, AddDataMode, DeleteResult, NativeTable, OptimizeAction, OptimizeIndexOptions,
+    OptimizeStats, RowVersion, Table, TableInternal, UpdateBuilder, Version,
+};
+use crate::arrow::SendableRecordBatchStream;
+use crate::connection::NoData;
+use crate::error::{Error, Result};
+use crate::index::vector::Accelerator;
+use crate::index::{IndexBuilder, IndexConfig, IndexStatistics};
+use crate::io::checksum::ChecksumReport;
+use crate::query::metrics::ScanStats;
+use crate::query::{Query, QueryExecutionOptions, Select, VectorQuery};
+
+/// The definition of a view, stored as JSON alongside the tables of the database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewDefinition {
+    pub base_table: String,
+    pub filter: Option<String>,
+    pub columns: Option<Vec<String>>,
+    pub materialized: bool,
+    /// The version of the base table the materialized rows were computed from
+    pub base_version: Option<u64>,
+}
+
+impl ViewDefinition {
+    /// Rewrite a query against the view into a query against the base table
+    fn apply(&self, query: &Query) -> Result<Query> {
+        let mut query = query.clone();
+        if let Some(filter) = &self.filter {
+            query.filter = Some(match query.filter.take() {
+                Some(query_filter) => format!("({}) AND ({})", filter, query_filter),
+                None => filter.clone(),
+            });
+        }
+        if let Some(columns) = &self.columns {
+            match &query.select {
+                Select::All => query.select = Select::Columns(columns.clone()),
+                Select::Columns(selected) => {
+                    if let Some(column) = selected.iter().find(|c| !columns.contains(*c)) {
+                        return Err(Error::InvalidInput {
+                            message: format!("the column '{}' is not part of the view", column),
+                        });
+                    }
+                }
+                Select::Dynamic(_) => {}
+            }
+        }
+        Ok(query)
+    }
+
+    pub(crate) async fn write(
+        &self,
+        store: &dyn object_store::ObjectStore,
+        path: &Path,
+    ) -> Result<()> {
+        let json = serde_json::to_vec(self).map_err(|e| Error::Runtime {
+            message: format!("failed to serialize the view definition: {}", e),
+        })?;
+        store.put(path, json.into()).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn read(
+        store: &dyn object_store::ObjectStore,
+        path: &Path,
+        name: &str,
+    ) -> Result<Self> {
+        let bytes = match store.get(path).await {
+            Ok(result) => result.bytes().await?,
+            Err(object_store::Error::NotFound { .. }) => {
+                return Err(Error::TableNotFound {
+                    name: name.to_string(),
+                })
+            }
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&bytes).map_err(|e| Error::Runtime {
+            message: format!("failed to read the definition of the view {}: {}", name, e),
+        })
+    }
+}
+
+/// The table holding the rows of a materialized view
+#[derive(Debug)]
+pub struct Materialized {
+    pub table: Arc<dyn TableInternal>,
+    pub store: Arc<dyn object_store::ObjectStore>,
+    pub definition_path: Path,
+    /// The definition as last written, guarding refreshes
+    pub definition: Mutex<ViewDefinition>,
+}
+
+/// A read only table backed by a view over another table
+#[derive(Debug)]
+pub struct ViewTable {
+    name: String,
+    definition: ViewDefinition,
+    base: Arc<dyn TableInternal>,
+    materialized: Option<Materialized>,
+}
+
+impl std::fmt::Display for ViewTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "View(name={}, base_table={}, materialized={})",
+            self.name, self.definition.base_table, self.definition.materialized
+        )
+    }
+}
+
+impl ViewTable {
+    pub(crate) fn new(
+        name: String,
+        definition: ViewDefinition,
+        base: Arc<dyn TableInternal>,
+        materialized: Option<Materialized>,
+    ) -> Self {
+        Self {
+            name,
+            definition,
+            base,
+            materialized,
+        }
+    }
+
+    /// The rows of the view, read from the base table
+    pub(crate) async fn read_base(
+        definition: &ViewDefinition,
+        base: &Arc<dyn TableInternal>,
+    ) -> Result<Box<dyn RecordBatchReader + Send>> {
+        let query = definition.apply(&Query::new(base.clone()))?;
+        let stream = base
+            .plain_query(&query, QueryExecutionOptions::default())
+            .await?;
+        let schema = stream.schema();
+        let batches = stream.try_collect::<Vec<_>>().await?;
+        Ok(Box::new(RecordBatchIterator::new(
+            batches.into_iter().map(Ok),
+            schema,
+        )))
+    }
+
+    /// The table queries are sent to, refreshing the materialized rows if the
+    /// base table has changed since they were computed
+    async fn target(&self) -> Result<&Arc<dyn TableInternal>> {
+        let Some(materialized) = &self.materialized else {
+            return Ok(&self.base);
+        };
+        let mut definition = materialized.definition.lock().await;
+        self.base.checkout_latest().await?;
+        let version = self.base.version().await?;
+        if definition.base_version != Some(version) {
+            let data = Self::read_base(&definition, &self.base).await?;
+            Table::new(materialized.table.clone())
+                .add(data)
+                .mode(AddDataMode::Overwrite)
+                .execute()
+                .await?;
+            definition.base_version = Some(version);
+            definition
+                .write(materialized.store.as_ref(), &materialized.definition_path)
+                .await?;
+        }
+        Ok(&materialized.table)
+    }
+
+    fn read_only(&self) -> Error {
+        Error::NotSupported {
+            message: format!("the view {} is read only", self.name),
+        }
+    }
+}
+
+#[async_trait]
+impl TableInternal for ViewTable {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_native(&self) -> Option<&NativeTable> {
+        None
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn version(&self) -> Result<u64> {
+        self.target().await?.version().await
+    }
+    async fn checkout(&self, _version: u64) -> Result<()> {
+        Err(Error::NotSupported {
+            message: "views cannot be checked out at a version".to_string(),
+        })
+    }
+    async fn checkout_latest(&self) -> Result<()> {
+        self.base.checkout_latest().await
+    }
+    async fn list_versions(&self) -> Result<Vec<Version>> {
+        self.target().await?.list_versions().await
+    }
+    async fn list_tags(&self) -> Result<BTreeMap<String, u64>> {
+        self.target().await?.list_tags().await
+    }
+    async fn create_tag(&self, _tag: &str, _version: u64) -> Result<()> {
+        Err(self.read_only())
+    }
+    async fn delete_tag(&self, _tag: &str) -> Result<()> {
+        Err(self.read_only())
+    }
+    async fn restore(&self) -> Result<()> {
+        Err(self.read_only())
+    }
+    async fn schema(&self) -> Result<SchemaRef> {
+        if self.materialized.is_some() {
+            return self.target().await?.schema().await;
+        }
+        let schema = self.base.schema().await?;
+        match &self.definition.columns {
+            Some(columns) => {
+                let indices = columns
+                    .iter()
+                    .map(|column| schema.index_of(column))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                Ok(Arc::new(schema.project(&indices)?))
+            }
+            None => Ok(schema),
+        }
+    }
+    async fn count_rows(&self, filter: Option<String>) -> Result<usize> {
+        if self.materialized.is_some() {
+            return self.target().await?.count_rows(filter).await;
+        }
+        let filter = match (&self.definition.filter, filter) {
+            (Some(view_filter), Some(filter)) => {
+                Some(format!("({}) AND ({})", view_filter, filter))
+            }
+            (view_filter, filter) => filter.or_else(|| view_filter.clone()),
+        };
+        self.base.count_rows(filter).await
+    }
+    async fn add(
+        &self,
+        _add: AddDataBuilder<NoData>,
+        _data: Box<dyn RecordBatchReader + Send>,
+    ) -> Result<()> {
+        Err(self.read_only())
+    }
+    async fn plain_query(
+        &self,
+        query: &Query,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        if self.materialized.is_some() {
+            return self.target().await?.plain_query(query, options).await;
+        }
+        let query = self.definition.apply(query)?;
+        self.base.plain_query(&query, options).await
+    }
+    async fn vector_query(
+        &self,
+        query: &VectorQuery,
+        options: QueryExecutionOptions,
+    ) -> Result<SendableRecordBatchStream> {
+        if self.materialized.is_some() {
+            return self.target().await?.vector_query(query, options).await;
+        }
+        let mut query = query.clone();
+        query.base = self.definition.apply(&query.base)?;
+        self.base.vector_query(&query, options).await
+    }
+    async fn explain_plan(&self, query: &VectorQuery, verbose: bool) -> Result<String> {
+        if self.materialized.is_some() {
+            return self.target().await?.explain_plan(query, verbose).await;
+        }
+        let mut query = query.clone();
+        query.base = self.definition.apply(&query.base)?;
+        self.base.explain_plan(&query, verbose).await
+    }
+    async fn scan_stats(&self, query: &VectorQuery) -> Result<Option<ScanStats>> {
+        if self.materialized.is_some() {
+            return self.target().await?.scan_stats(query).await;
+        }
+        let mut query = query.clone();
+        query.base = self.definition.apply(&query.base)?;
+        self.base.scan_stats(&query).await
+    }
+    async fn update(&self, _update: UpdateBuilder) -> Result<()> {
+        Err(self.read_only())
+    }
+    async fn delete(&self, _predicate: &str) -> Result<DeleteResult> {
+        Err(self.read_only())
+    }
+    async fn undelete(&self, _filter: &str) -> Result<()> {
+        Err(self.read_only())
+    }
+    async fn create_index(&self, _index: IndexBuilder) -> Result<()> {
+        Err(Error::NotSupported {
+            message: format!(
+                "indices cannot be created on the view {}, create them on the table {} instead",
+                self.name, self.definition.base_table
+            ),
+        })
+    }
+    async fn merge_insert(
+        &self,
+        _params: MergeInsertBuilder,
+        _new_data: Box<dyn RecordBatchReader + Send>,
+    ) -> Result<()> {
+        Err(self.read_only())
+    }
+    async fn optimize(&self, _action: OptimizeAction) -> Result<OptimizeStats> {
+        Err(self.read_only())
+    }
+    async fn add_columns(
+        &self,
+        _transforms: NewColumnTransform,
+        _read_columns: Option<Vec<String>>,
+    ) -> Result<()> {
+        Err(self.read_only())
+    }
+    async fn alter_columns(&self, _alterations: &[ColumnAlteration]) -> Result<()> {
+        Err(self.read_only())
+    }
+    async fn drop_columns(&self, _columns: &[&str]) -> Result<()> {
+        Err(self.read_only())
+    }
+    async fn update_metadata(
+        &self,
+        _column: Option<&str>,
+        _metadata: HashMap<String, String>,
+    ) -> Result<()> {
+        Err(self.read_only())
+    }
+    async fn list_indices(&self) -> Result<Vec<IndexConfig>> {
+        self.target().await?.list_indices().await
+    }
+    async fn row_history(&self, _key_filter: &str) -> Result<Vec<RowVersion>> {
+        Err(Error::NotSupported {
+            message: "row history is not supported for views".to_string(),
+        })
+    }
+    async fn cluster(&self, _params: ClusterBuilder) -> Result<FixedSizeListArray> {
+        Err(self.read_only())
+    }
+    async fn ivf_centroids(&self, _column: &str) -> Result<FixedSizeListArray> {
+        Err(Error::NotSupported {
+            message: "views have no indices".to_string(),
+        })
+    }
+    async fn column_stats(&self, _column: &str) -> Result<ColumnStatistics> {
+        Err(Error::NotSupported {
+            message: "column statistics are not supported for views".to_string(),
+        })
+    }
+    async fn stats(&self) -> Result<TableStatistics> {
+        Err(Error::NotSupported {
+            message: "table statistics are not supported for views".to_string(),
+        })
+    }
+    async fn take(&self, row_ids: &[u64], select: Select) -> Result<RecordBatch> {
+        if self.materialized.is_none() {
+            // The row ids would be those of the base table, which has other columns
+            return Err(Error::NotSupported {
+                message: "taking rows by id is only supported for materialized views".to_string(),
+            });
+        }
+        self.target().await?.take(row_ids, select).await
+    }
+    async fn verify_checksums(&self) -> Result<ChecksumReport> {
+        self.target().await?.verify_checksums().await
+    }
+    async fn rebuild_missing_indices(&self) -> Result<Vec<String>> {
+        Err(self.read_only())
+    }
+    async fn optimize_indices(&self, _options: OptimizeIndexOptions) -> Result<Vec<String>> {
+        Err(self.read_only())
+    }
+    async fn split(&self, _params: SplitBuilder) -> Result<Vec<Table>> {
+        Err(Error::NotSupported {
+            message: "views cannot be split".to_string(),
+        })
+    }
+    async fn prewarm(&self, params: PrewarmBuilder) -> Result<()> {
+        self.target().await?.prewarm(params).await
+    }
+    async fn cache_residency(&self) -> Result<CacheResidency> {
+        self.target().await?.cache_residency().await
+    }
+    async fn changes(&self, _params: ChangesBuilder) -> Result<ChangeStream> {
+        Err(Error::NotSupported {
+            message: "changes are not supported for views".to_string(),
+        })
+    }
+    async fn gpu_search(&self, _column: &str, _accelerator: Accelerator) -> Result<()> {
+        Err(Error::NotSupported {
+            message: "views cannot be searched on a GPU".to_string(),
+        })
+    }
+    async fn migrate_vector_dim(&self, _params: MigrateVectorDimBuilder) -> Result<()> {
+        Err(self.read_only())
+    }
+    async fn pack(&self, _path: &std::path::Path) -> Result<PackageInfo> {
+        Err(Error::NotSupported {
+            message: "views cannot be packed, pack the base table instead".to_string(),
+        })
+    }
+    async fn export_snapshot(&self, _target_uri: &str) -> Result<SnapshotInfo> {
+        Err(Error::NotSupported {
+            message: "views cannot be exported, export the base table instead".to_string(),
+        })
+    }
+    async fn dry_run(&self, _operation: DryRunOperation) -> Result<DryRunReport> {
+        Err(self.read_only())
+    }
+    async fn index_stats(&self, name: &str) -> Result<Option<IndexStatistics>> {
+        self.target().await?.index_stats(name).await
+    }
+    async fn drop_index(&self, _name: &str) -> Result<()> {
+        Err(self.read_only())
+    }
+}
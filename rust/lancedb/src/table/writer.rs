@@ -0,0 +1,314 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Buffered writes of streams of rows
+//!
+//! Each [`super::Table::add`] commits a new version with at least one new
+//! fragment, so adding the events of a stream one at a time creates thousands
+//! of tiny fragments and versions.  A [`TableWriter`], created with
+//! [`super::Table::writer`], buffers the rows written to it and adds them to
+//! the table in one batch once enough rows or bytes are buffered, or once the
+//! oldest buffered row has waited for the flush interval.  The rows of each
+//! flush are written to as few fragments as possible and committed as one
+//! version.
+//!
+//! ```no_run
+//! # use arrow_array::RecordBatch;
+//! # async fn ingest(table: &lancedb::Table, events: Vec<RecordBatch>) -> lancedb::Result<()> {
+//! let mut writer = table
+//!     .writer()
+//!     .max_buffered_rows(100_000)
+//!     .flush_interval(Some(std::time::Duration::from_secs(5)));
+//! for event in events {
+//!     writer.write(event).await?;
+//! }
+//! writer.close().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The buffered rows are only in memory: the rows not flushed when a writer is
+//! dropped without [`TableWriter::close`] are lost.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use arrow::compute::concat_batches;
+use arrow_array::{RecordBatch, RecordBatchIterator};
+use arrow_schema::SchemaRef;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use super::Table;
+use crate::error::{Error, Result};
+
+/// The rows buffered by a writer
+#[derive(Default)]
+struct Buffer {
+    schema: Option<SchemaRef>,
+    batches: Vec<RecordBatch>,
+    rows: usize,
+    bytes: usize,
+    /// When the oldest buffered row was written
+    since: Option<Instant>,
+    /// The error of the last flush made in the background, returned by the
+    /// next call of the writer
+    error: Option<Error>,
+}
+
+impl Buffer {
+    /// Take the buffered rows, as one batch
+    fn take(&mut self) -> Result<Option<RecordBatch>> {
+        let Some(schema) = self.schema.clone() else {
+            return Ok(None);
+        };
+        if self.batches.is_empty() {
+            return Ok(None);
+        }
+        let batch = concat_batches(&schema, &self.batches)?;
+        self.batches.clear();
+        self.rows = 0;
+        self.bytes = 0;
+        self.since = None;
+        Ok(Some(batch))
+    }
+}
+
+/// Add the buffered rows to the table
+async fn flush(table: &Table, buffer: &mut Buffer) -> Result<()> {
+    if let Some(batch) = buffer.take()? {
+        let schema = batch.schema();
+        table
+            .add(RecordBatchIterator::new(vec![Ok(batch)], schema))
+            .execute()
+            .await?;
+    }
+    Ok(())
+}
+
+/// A writer buffering rows and adding them to a table in large batches
+///
+/// See the [module docs](self).
+pub struct TableWriter {
+    table: Table,
+    max_buffered_rows: usize,
+    max_buffered_bytes: usize,
+    flush_interval: Option<Duration>,
+    buffer: Arc<Mutex<Buffer>>,
+    flusher: Option<JoinHandle<()>>,
+}
+
+impl TableWriter {
+    pub(super) fn new(table: Table) -> Self {
+        Self {
+            table,
+            max_buffered_rows: 1024 * 1024,
+            max_buffered_bytes: 256 * 1024 * 1024,
+            flush_interval: Some(Duration::from_secs(10)),
+            buffer: Arc::new(Mutex::new(Buffer::default())),
+            flusher: None,
+        }
+    }
+
+    /// Flush once this many rows are buffered
+    ///
+    /// This is the target number of rows of the fragments written.  The
+    /// default is 1024 * 1024 rows.
+    pub fn max_buffered_rows(mut self, max_buffered_rows: usize) -> Self {
+        self.max_buffered_rows = max_buffered_rows.max(1);
+        self
+    }
+
+    /// Flush once the buffered rows take this many bytes of memory
+    ///
+    /// The default is 256MiB.
+    pub fn max_buffered_bytes(mut self, max_buffered_bytes: usize) -> Self {
+        self.max_buffered_bytes = max_buffered_bytes.max(1);
+        self
+    }
+
+    /// Flush once the oldest buffered row has been buffered this long
+    ///
+    /// The flushes are made by a background task, so a row is never buffered
+    /// much longer than this even if nothing else is written.  `None` only
+    /// flushes on size, on [`Self::flush`] and on [`Self::close`].  The default
+    /// is 10 seconds.
+    pub fn flush_interval(mut self, flush_interval: Option<Duration>) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    /// Start the background task flushing the rows buffered for too long
+    fn start_flusher(&mut self) {
+        let Some(interval) = self.flush_interval else {
+            return;
+        };
+        if self.flusher.is_some() {
+            return;
+        }
+        let table = self.table.clone();
+        let buffer = Arc::downgrade(&self.buffer);
+        // Check often enough that rows wait at most about 1.1 intervals
+        let period = (interval / 10).max(Duration::from_millis(10));
+        self.flusher = Some(tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(period);
+            loop {
+                ticks.tick().await;
+                // The writer was dropped
+                let Some(shared) = buffer.upgrade() else {
+                    return;
+                };
+                let mut buffer = shared.lock().await;
+                if buffer.error.is_some()
+                    || !buffer
+                        .since
+                        .is_some_and(|since| since.elapsed() >= interval)
+                {
+                    continue;
+                }
+                if let Err(e) = flush(&table, &mut buffer).await {
+                    log::warn!("Failed to flush the rows written to {}: {}", table, e);
+                    buffer.error = Some(e);
+                }
+            }
+        }));
+    }
+
+    /// Buffer the rows of `batch`, flushing if a threshold is reached
+    ///
+    /// Batches of any size, including single rows, can be written.  All of the
+    /// batches must have the same schema.  If a flush made in the background
+    /// failed, its error is returned and the rows of `batch` are not buffered;
+    /// the rows of the failed flush are lost.
+    pub async fn write(&mut self, batch: RecordBatch) -> Result<()> {
+        self.start_flusher();
+        let mut buffer = self.buffer.lock().await;
+        if let Some(e) = buffer.error.take() {
+            return Err(e);
+        }
+        match &buffer.schema {
+            Some(schema) if schema != &batch.schema() => {
+                return Err(Error::InvalidInput {
+                    message: format!(
+                        "the schema of the batch does not match the schema of the rows \
+                         already written: {:?} != {:?}",
+                        batch.schema(),
+                        schema
+                    ),
+                });
+            }
+            Some(_) => {}
+            None => buffer.schema = Some(batch.schema()),
+        }
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+        buffer.rows += batch.num_rows();
+        buffer.bytes += batch.get_array_memory_size();
+        buffer.since.get_or_insert_with(Instant::now);
+        buffer.batches.push(batch);
+        if buffer.rows >= self.max_buffered_rows || buffer.bytes >= self.max_buffered_bytes {
+            flush(&self.table, &mut buffer).await?;
+        }
+        Ok(())
+    }
+
+    /// The number of rows buffered and not added to the table yet
+    pub async fn buffered_rows(&self) -> usize {
+        self.buffer.lock().await.rows
+    }
+
+    /// Add the buffered rows to the table now
+    pub async fn flush(&mut self) -> Result<()> {
+        let mut buffer = self.buffer.lock().await;
+        if let Some(e) = buffer.error.take() {
+            return Err(e);
+        }
+        flush(&self.table, &mut buffer).await
+    }
+
+    /// Flush the buffered rows and stop the writer
+    pub async fn close(mut self) -> Result<()> {
+        if let Some(flusher) = self.flusher.take() {
+            flusher.abort();
+        }
+        self.flush().await
+    }
+}
+
+impl Drop for TableWriter {
+    fn drop(&mut self) {
+        if let Some(flusher) = self.flusher.take() {
+            flusher.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::Int32Array;
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::connect;
+
+    fn batch(schema: &SchemaRef, start: i32, rows: i32) -> RecordBatch {
+        RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(start..start + rows))],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_writer() {
+        let tmp_dir = tempdir().unwrap();
+        let uri = tmp_dir.path().to_str().unwrap();
+        let db = connect(uri).execute().await.unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let table = db
+            .create_empty_table("test", schema.clone())
+            .execute()
+            .await
+            .unwrap();
+        let version = table.version().await.unwrap();
+
+        let mut writer = table.writer().max_buffered_rows(100).flush_interval(None);
+        for i in 0..250 {
+            writer.write(batch(&schema, i, 1)).await.unwrap();
+        }
+        // Two flushes of 100 rows
+        assert_eq!(table.count_rows(None).await.unwrap(), 200);
+        assert_eq!(table.version().await.unwrap(), version + 2);
+        assert_eq!(writer.buffered_rows().await, 50);
+
+        let other = Arc::new(Schema::new(vec![Field::new("j", DataType::Int32, false)]));
+        assert!(matches!(
+            writer.write(batch(&other, 0, 1)).await,
+            Err(Error::InvalidInput { .. })
+        ));
+        writer.close().await.unwrap();
+        assert_eq!(table.count_rows(None).await.unwrap(), 250);
+
+        // Rows are flushed in the background once they waited long enough
+        let mut writer = table
+            .writer()
+            .flush_interval(Some(Duration::from_millis(50)));
+        writer.write(batch(&schema, 0, 10)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(writer.buffered_rows().await, 0);
+        assert_eq!(table.count_rows(None).await.unwrap(), 260);
+    }
+}
@@ -0,0 +1,329 @@
+// Copyright 2024 LanceDB Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Spans of the operations of LanceDB
+//!
+//! With the `tracing` feature the operations are run in [`tracing`] spans,
+//! which can be exported to OpenTelemetry with `tracing-opentelemetry`:
+//!
+//! * `lancedb.connect`, with the field `uri`
+//! * `lancedb.create_table` and `lancedb.open_table`, with the field `table`
+//! * `lancedb.write`, for each write to a table of LanceDB OSS, including the
+//!   index builds, with the fields `table`, `operation` (such as `add` or
+//!   `create_index`) and `version`, the version written
+//! * `lancedb.query`, for each query of a table of LanceDB OSS, with the fields
+//!   `table`, `kind` (`plain` or `vector`), `version`, the version read, and
+//!   `rows`, the rows returned
+//!
+//! All of the spans have the fields `latency_ms` and, if the operation failed,
+//! `error`.  The span of a query ends once its results have all been read.
+//! Without the feature no spans are created.
+
+use std::future::Future;
+
+use crate::arrow::SendableRecordBatchStream;
+use crate::error::Result;
+
+#[cfg(feature = "tracing")]
+mod imp {
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use futures::Stream;
+    use tracing::field::Empty;
+
+    use crate::arrow::{RecordBatchStream, SendableRecordBatchStream};
+    use crate::error::{Error, Result};
+
+    /// The span of an operation, and when it started
+    #[derive(Debug, Clone)]
+    pub struct OperationSpan {
+        pub(super) span: tracing::Span,
+        start: Instant,
+    }
+
+    impl OperationSpan {
+        fn new(span: tracing::Span) -> Self {
+            Self {
+                span,
+                start: Instant::now(),
+            }
+        }
+
+        pub(crate) fn connect(uri: &str) -> Self {
+            // The query string of the uri can hold credentials
+            let uri = uri.split('?').next().unwrap_or_default();
+            Self::new(tracing::info_span!(
+                "lancedb.connect",
+                uri,
+                latency_ms = Empty,
+                error = Empty
+            ))
+        }
+
+        pub(crate) fn create_table(table: &str) -> Self {
+            Self::new(tracing::info_span!(
+                "lancedb.create_table",
+                table,
+                latency_ms = Empty,
+                error = Empty
+            ))
+        }
+
+        pub(crate) fn open_table(table: &str) -> Self {
+            Self::new(tracing::info_span!(
+                "lancedb.open_table",
+                table,
+                latency_ms = Empty,
+                error = Empty
+            ))
+        }
+
+        pub(crate) fn write(table: &str, operation: &str) -> Self {
+            Self::new(tracing::info_span!(
+                "lancedb.write",
+                table,
+                operation,
+                version = Empty,
+                latency_ms = Empty,
+                error = Empty
+            ))
+        }
+
+        pub(crate) fn query(table: &str, kind: &'static str) -> Self {
+            Self::new(tracing::info_span!(
+                "lancedb.query",
+                table,
+                kind,
+                version = Empty,
+                rows = Empty,
+                latency_ms = Empty,
+                error = Empty
+            ))
+        }
+
+        pub(crate) fn is_recording(&self) -> bool {
+            !self.span.is_disabled()
+        }
+
+        pub(crate) fn record_version(&self, version: u64) {
+            self.span.record("version", version);
+        }
+
+        fn end(&self, error: Option<&Error>) {
+            self.span
+                .record("latency_ms", self.start.elapsed().as_millis() as u64);
+            if let Some(e) = error {
+                self.span.record("error", tracing::field::display(e));
+            }
+        }
+
+        pub(crate) fn finish<T>(&self, result: &Result<T>) {
+            self.end(result.as_ref().err());
+        }
+
+        pub(crate) fn follow(self, stream: SendableRecordBatchStream) -> SendableRecordBatchStream {
+            if !self.is_recording() {
+                return stream;
+            }
+            Box::pin(TracedRecordBatchStream {
+                stream,
+                span: self,
+                rows: 0,
+                finished: false,
+            })
+        }
+    }
+
+    /// A RecordBatchStream which ends the span of its query once exhausted
+    #[pin_project::pin_project(PinnedDrop)]
+    struct TracedRecordBatchStream {
+        #[pin]
+        stream: SendableRecordBatchStream,
+        span: OperationSpan,
+        rows: u64,
+        finished: bool,
+    }
+
+    impl Stream for TracedRecordBatchStream {
+        type Item = Result<arrow_array::RecordBatch>;
+
+        fn poll_next(
+            self: Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Option<Self::Item>> {
+            let this = self.project();
+            let _entered = this.span.span.enter();
+            let next = this.stream.poll_next(cx);
+            let error = match &next {
+                std::task::Poll::Ready(Some(Ok(batch))) => {
+                    *this.rows += batch.num_rows() as u64;
+                    return next;
+                }
+                std::task::Poll::Ready(Some(Err(e))) => Some(e),
+                std::task::Poll::Ready(None) => None,
+                std::task::Poll::Pending => return next,
+            };
+            if !*this.finished {
+                *this.finished = true;
+                this.span.span.record("rows", *this.rows);
+                this.span.end(error);
+            }
+            next
+        }
+    }
+
+    #[pin_project::pinned_drop]
+    impl PinnedDrop for TracedRecordBatchStream {
+        fn drop(self: Pin<&mut Self>) {
+            // The results were not all read
+            let this = self.project();
+            if !*this.finished {
+                this.span.span.record("rows", *this.rows);
+                this.span.end(None);
+            }
+        }
+    }
+
+    impl RecordBatchStream for TracedRecordBatchStream {
+        fn schema(&self) -> Arc<arrow_schema::Schema> {
+            self.stream.schema()
+        }
+
+        fn version(&self) -> Option<u64> {
+            self.stream.version()
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod imp {
+    use crate::arrow::SendableRecordBatchStream;
+    use crate::error::Result;
+
+    /// Without the `tracing` feature the operations have no span
+    #[derive(Debug, Clone)]
+    pub struct OperationSpan;
+
+    impl OperationSpan {
+        pub(crate) fn connect(_uri: &str) -> Self {
+            Self
+        }
+
+        pub(crate) fn create_table(_table: &str) -> Self {
+            Self
+        }
+
+        pub(crate) fn open_table(_table: &str) -> Self {
+            Self
+        }
+
+        pub(crate) fn write(_table: &str, _operation: &str) -> Self {
+            Self
+        }
+
+        pub(crate) fn query(_table: &str, _kind: &'static str) -> Self {
+            Self
+        }
+
+        pub(crate) fn is_recording(&self) -> bool {
+            false
+        }
+
+        pub(crate) fn record_version(&self, _version: u64) {}
+
+        pub(crate) fn finish<T>(&self, _result: &Result<T>) {}
+
+        pub(crate) fn follow(self, stream: SendableRecordBatchStream) -> SendableRecordBatchStream {
+            stream
+        }
+    }
+}
+
+pub(crate) use self::imp::OperationSpan;
+
+impl OperationSpan {
+    /// Run `operation` in the span
+    pub(crate) async fn run<T>(self, operation: impl Future<Output = Result<T>>) -> Result<T> {
+        #[cfg(feature = "tracing")]
+        let result = tracing::Instrument::instrument(operation, self.span.clone()).await;
+        #[cfg(not(feature = "tracing"))]
+        let result = operation.await;
+        self.finish(&result);
+        result
+    }
+
+    /// Run the query started by `query` in the span, which ends once its
+    /// results have all been read
+    pub(crate) async fn run_query(
+        self,
+        query: impl Future<Output = Result<SendableRecordBatchStream>>,
+    ) -> Result<SendableRecordBatchStream> {
+        #[cfg(feature = "tracing")]
+        let result = tracing::Instrument::instrument(query, self.span.clone()).await;
+        #[cfg(not(feature = "tracing"))]
+        let result = query.await;
+        match result {
+            Ok(stream) => Ok(self.follow(stream)),
+            Err(e) => {
+                let result = Err(e);
+                self.finish(&result);
+                result
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int32Array, RecordBatch};
+    use arrow_schema::{DataType, Field, Schema};
+    use futures::{stream, TryStreamExt};
+
+    use super::*;
+    use crate::arrow::SimpleRecordBatchStream;
+    use crate::error::Error;
+
+    #[tokio::test]
+    async fn test_spans_pass_results_through() {
+        let result = OperationSpan::write("t", "add")
+            .run(async {
+                Result::<()>::Err(Error::InvalidInput {
+                    message: "bad".to_string(),
+                })
+            })
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(vec![1, 2]))])
+                .unwrap();
+        let stream = OperationSpan::query("t", "plain")
+            .run_query(async {
+                let stream: SendableRecordBatchStream = Box::pin(SimpleRecordBatchStream {
+                    schema,
+                    stream: stream::iter(vec![Ok(batch.clone()), Ok(batch)]),
+                });
+                Ok(stream)
+            })
+            .await
+            .unwrap();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(batches.len(), 2);
+    }
+}
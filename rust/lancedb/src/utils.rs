@@ -12,12 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use arrow_schema::Schema;
 use lance::dataset::{ReadParams, WriteParams};
 use lance::io::{ObjectStoreParams, WrappingObjectStore};
 use lazy_static::lazy_static;
+use object_store::local::LocalFileSystem;
 
 use crate::error::{Error, Result};
 
@@ -82,6 +84,53 @@ impl PatchReadParam for ReadParams {
     }
 }
 
+/// The scheme of the object store that local tables are read through
+const LOCAL_OBJECT_STORE_SCHEME: &str = "file-object-store";
+
+/// Read the local table at `uri` through the wrapper of its object store
+///
+/// Lance reads local files directly, bypassing the wrapper (and so the
+/// decryption and checksums of the wrapper), unless the table is opened with
+/// an object store of another scheme.  Returns whether `params` were changed,
+/// which is only the case for an existing local table with a wrapper.
+pub fn read_local_through_wrapper(uri: &str, params: &mut ReadParams) -> Result<bool> {
+    let Some(store_params) = params.store_options.as_mut() else {
+        return Ok(false);
+    };
+    if store_params.object_store_wrapper.is_none() || store_params.object_store.is_some() {
+        return Ok(false);
+    }
+    let path = match url::Url::parse(uri) {
+        Ok(url) if url.scheme() == "file" => {
+            url.to_file_path().map_err(|_| Error::InvalidInput {
+                message: format!("{} is not a valid path", uri),
+            })?
+        }
+        Ok(url) if url.scheme().len() != 1 || cfg!(not(windows)) => return Ok(false),
+        _ => PathBuf::from(uri),
+    };
+    let path = match std::fs::canonicalize(&path) {
+        Ok(path) => path,
+        // Opening the table reports that it does not exist
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => {
+            return Err(Error::Runtime {
+                message: format!("failed to resolve the path {}: {}", uri, e),
+            })
+        }
+    };
+    let location = url::Url::from_directory_path(&path)
+        .and_then(|url| {
+            url::Url::parse(&format!("{}://{}", LOCAL_OBJECT_STORE_SCHEME, url.path()))
+                .map_err(|_| ())
+        })
+        .map_err(|_| Error::InvalidInput {
+            message: format!("{} is not a valid path", uri),
+        })?;
+    store_params.object_store = Some((Arc::new(LocalFileSystem::new()), location));
+    Ok(true)
+}
+
 /// Validate table name.
 pub fn validate_table_name(name: &str) -> Result<()> {
     if name.is_empty() {
@@ -101,6 +150,61 @@ pub fn validate_table_name(name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Validate the name of a namespace, see [`crate::Connection::namespace`].
+///
+/// The names of namespaces follow the rules of table names, but they cannot end
+/// with `.lance`, like the directories of tables, or start with `_` or `.`, like
+/// the directories of the views and other metadata of the database.
+pub fn validate_namespace_name(name: &str) -> Result<()> {
+    let reason = if name.is_empty() {
+        "Namespace names cannot be empty strings"
+    } else if !TABLE_NAME_REGEX.is_match(name) {
+        "Namespace names can only contain alphanumeric characters, underscores, hyphens, and periods"
+    } else if name.starts_with(['_', '.']) {
+        "Namespace names cannot start with an underscore or a period"
+    } else if name.ends_with(".lance") {
+        "Namespace names cannot end with .lance"
+    } else {
+        return Ok(());
+    };
+    Err(Error::InvalidInput {
+        message: format!("invalid namespace name \"{}\": {}", name, reason),
+    })
+}
+
+/// Whether `name` matches the glob `pattern`
+///
+/// `*` matches any number of characters and `?` matches a single character.
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    // The position of the last `*` and of the name when it was reached
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the last `*` match one more character
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
 /// Find one default column to create index.
 pub(crate) fn default_vector_column(schema: &Schema, dim: Option<i32>) -> Result<String> {
     // Try to find one fixed size list array column.
@@ -183,6 +287,21 @@ mod tests {
             .contains("More than one"));
     }
 
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "table"));
+        assert!(glob_match("tenant_*", "tenant_1"));
+        assert!(glob_match("tenant_*_docs", "tenant_1_old_docs"));
+        assert!(glob_match("t?ble", "table"));
+        assert!(glob_match("table", "table"));
+
+        assert!(!glob_match("tenant_*", "tenant"));
+        assert!(!glob_match("tenant_*_docs", "tenant_1_docs_v2"));
+        assert!(!glob_match("t?ble", "tble"));
+        assert!(!glob_match("table", "tables"));
+    }
+
     #[test]
     fn test_validate_table_name() {
         assert!(validate_table_name("my_table").is_ok());